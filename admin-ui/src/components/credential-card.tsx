@@ -162,6 +162,11 @@ export function CredentialCard({
                 {credential.disabled && credential.disabledReason && (
                   <Badge variant="outline">{credential.disabledReason}</Badge>
                 )}
+                {!credential.disabled && !credential.inSchedule && (
+                  <Badge variant="outline" title={credential.schedule.join('\n')}>
+                    时段外
+                  </Badge>
+                )}
                 {credential.authMethod && (
                   <Badge variant="secondary">
                     {credential.authMethod === 'api_key' ? 'API Key' :
@@ -27,6 +27,8 @@ export interface CredentialStatusItem {
   refreshFailureCount: number
   disabledReason?: string
   endpoint: string
+  schedule: string[]
+  inSchedule: boolean
 }
 
 // 余额响应
@@ -0,0 +1,454 @@
+//! Admin 操作审计日志
+//!
+//! 记录所有 Admin API 变更操作（凭据增删、禁用、优先级、配置修改等）与登录成功/失败，
+//! 追加写入凭据目录下的 `kiro_audit.jsonl`，启动时加载最近的记录，
+//! 通过 `GET /api/admin/audit` 按条件分页查询。
+
+use std::collections::{HashMap, VecDeque};
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::path::PathBuf;
+
+use chrono::{DateTime, Utc};
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::common::atomic_file;
+
+/// 内存中保留的最大记录数（文件超过两倍时压缩为最近的记录）
+const MAX_ENTRIES: usize = 10_000;
+
+/// 同一来源的登录成功记录间隔（秒）
+///
+/// Admin API 每个请求都携带密钥，逐个记录会淹没变更操作
+const LOGIN_SUCCESS_INTERVAL_SECS: i64 = 3600;
+
+/// 查询默认返回条数
+const DEFAULT_QUERY_LIMIT: usize = 50;
+
+/// 查询最大返回条数
+const MAX_QUERY_LIMIT: usize = 500;
+
+/// 一条审计记录
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AuditEntry {
+    /// 递增序号
+    pub id: u64,
+    pub time: DateTime<Utc>,
+    /// 操作（如 `credential.delete`、`config.model_routes`、`login.failure`）
+    pub action: String,
+    pub method: String,
+    pub path: String,
+    /// 操作对象（凭据 ID 等路径参数）
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub target: Option<String>,
+    /// 请求参数（仅记录不含密钥的操作）
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub detail: Option<Value>,
+    /// TCP 对端地址
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ip: Option<String>,
+    /// `X-Forwarded-For` 原始值（可被伪造，仅供参考）
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub forwarded_for: Option<String>,
+    /// 响应状态码
+    pub status: u16,
+    pub success: bool,
+}
+
+/// 待记录的操作（序号与时间由审计日志填充）
+#[derive(Debug, Clone, Default)]
+pub struct AuditEvent {
+    pub action: String,
+    pub method: String,
+    pub path: String,
+    pub target: Option<String>,
+    pub detail: Option<Value>,
+    pub ip: Option<String>,
+    pub forwarded_for: Option<String>,
+    pub status: u16,
+}
+
+/// 审计日志查询条件
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AuditQuery {
+    /// 操作前缀（如 `credential.` 匹配所有凭据操作）
+    pub action: Option<String>,
+    /// 来源 IP（匹配对端地址或 `X-Forwarded-For`）
+    pub ip: Option<String>,
+    pub target: Option<String>,
+    pub success: Option<bool>,
+    /// 起始时间（RFC 3339，含）
+    pub since: Option<DateTime<Utc>>,
+    /// 截止时间（RFC 3339，不含）
+    pub until: Option<DateTime<Utc>>,
+    /// 返回条数（默认 50，最大 500）
+    pub limit: Option<usize>,
+    /// 跳过的条数
+    #[serde(default)]
+    pub offset: usize,
+}
+
+/// 审计日志查询结果（按时间倒序）
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AuditPage {
+    /// 满足条件的总条数
+    pub total: usize,
+    pub entries: Vec<AuditEntry>,
+}
+
+#[derive(Debug, Default)]
+struct Inner {
+    entries: VecDeque<AuditEntry>,
+    next_id: u64,
+    /// 文件中的记录行数
+    file_lines: usize,
+    /// 各来源最近一次记录登录成功的时间
+    login_seen: HashMap<String, i64>,
+}
+
+/// 审计日志
+#[derive(Debug)]
+pub struct AuditLog {
+    path: Option<PathBuf>,
+    inner: Mutex<Inner>,
+}
+
+impl AuditLog {
+    /// 创建审计日志并加载已有记录，`path` 为 None 时只保存在内存中
+    pub fn new(path: Option<PathBuf>) -> Self {
+        let mut inner = Inner {
+            next_id: 1,
+            ..Default::default()
+        };
+        if let Some(content) = path.as_ref().and_then(|p| std::fs::read_to_string(p).ok()) {
+            for line in content.lines().filter(|l| !l.trim().is_empty()) {
+                inner.file_lines += 1;
+                match serde_json::from_str::<AuditEntry>(line) {
+                    Ok(entry) => {
+                        inner.next_id = inner.next_id.max(entry.id + 1);
+                        inner.entries.push_back(entry);
+                        if inner.entries.len() > MAX_ENTRIES {
+                            inner.entries.pop_front();
+                        }
+                    }
+                    Err(e) => tracing::warn!("解析审计日志记录失败，将忽略: {}", e),
+                }
+            }
+            tracing::info!("已加载 {} 条审计日志", inner.entries.len());
+        }
+        Self {
+            path,
+            inner: Mutex::new(inner),
+        }
+    }
+
+    /// 记录一次操作
+    pub fn record(&self, event: AuditEvent) {
+        let mut inner = self.inner.lock();
+        let entry = AuditEntry {
+            id: inner.next_id,
+            time: Utc::now(),
+            action: event.action,
+            method: event.method,
+            path: event.path,
+            target: event.target,
+            detail: event.detail,
+            ip: event.ip,
+            forwarded_for: event.forwarded_for,
+            status: event.status,
+            success: (200..400).contains(&event.status),
+        };
+        inner.next_id += 1;
+        tracing::info!(
+            action = %entry.action,
+            target = ?entry.target,
+            ip = ?entry.ip,
+            status = entry.status,
+            "Admin 审计"
+        );
+        self.persist(&mut inner, &entry);
+        inner.entries.push_back(entry);
+        if inner.entries.len() > MAX_ENTRIES {
+            inner.entries.pop_front();
+        }
+    }
+
+    /// 记录登录结果
+    ///
+    /// 失败每次都记录；成功时同一来源在 [`LOGIN_SUCCESS_INTERVAL_SECS`] 内只记录一次
+    pub fn record_login(&self, mut event: AuditEvent, success: bool) {
+        if success {
+            let source = format!(
+                "{}|{}",
+                event.ip.as_deref().unwrap_or_default(),
+                event.forwarded_for.as_deref().unwrap_or_default()
+            );
+            let now = Utc::now().timestamp();
+            let mut inner = self.inner.lock();
+            if inner
+                .login_seen
+                .get(&source)
+                .is_some_and(|at| now - at < LOGIN_SUCCESS_INTERVAL_SECS)
+            {
+                return;
+            }
+            inner
+                .login_seen
+                .retain(|_, at| now - *at < LOGIN_SUCCESS_INTERVAL_SECS);
+            inner.login_seen.insert(source, now);
+        }
+        event.action = if success {
+            "login.success"
+        } else {
+            "login.failure"
+        }
+        .to_string();
+        self.record(event);
+    }
+
+    /// 按条件分页查询（按时间倒序）
+    pub fn query(&self, query: &AuditQuery) -> AuditPage {
+        let limit = query
+            .limit
+            .unwrap_or(DEFAULT_QUERY_LIMIT)
+            .clamp(1, MAX_QUERY_LIMIT);
+        let inner = self.inner.lock();
+        let matched: Vec<&AuditEntry> = inner
+            .entries
+            .iter()
+            .rev()
+            .filter(|e| matches(e, query))
+            .collect();
+        AuditPage {
+            total: matched.len(),
+            entries: matched
+                .into_iter()
+                .skip(query.offset)
+                .take(limit)
+                .cloned()
+                .collect(),
+        }
+    }
+
+    /// 追加写入文件，行数超过上限两倍时重写为内存中的记录
+    fn persist(&self, inner: &mut Inner, entry: &AuditEntry) {
+        let Some(path) = &self.path else {
+            return;
+        };
+        let line = match serde_json::to_string(entry) {
+            Ok(line) => line,
+            Err(e) => {
+                tracing::warn!("序列化审计日志失败: {}", e);
+                return;
+            }
+        };
+
+        if inner.file_lines >= MAX_ENTRIES * 2 {
+            let mut content = String::new();
+            // 与写入后内存中保留的记录一致
+            let skip = (inner.entries.len() + 1).saturating_sub(MAX_ENTRIES);
+            for e in inner.entries.iter().skip(skip) {
+                if let Ok(l) = serde_json::to_string(e) {
+                    content.push_str(&l);
+                    content.push('\n');
+                }
+            }
+            content.push_str(&line);
+            content.push('\n');
+            // 压缩时整体重写文件，中途崩溃不能截断已有记录
+            match atomic_file::write(path, content.as_bytes()) {
+                Ok(()) => inner.file_lines = inner.entries.len(),
+                Err(e) => tracing::warn!("压缩审计日志失败: {}", e),
+            }
+            return;
+        }
+
+        let result = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .and_then(|mut file| writeln!(file, "{}", line));
+        match result {
+            Ok(()) => inner.file_lines += 1,
+            Err(e) => tracing::warn!("写入审计日志失败: {}", e),
+        }
+    }
+}
+
+fn matches(entry: &AuditEntry, query: &AuditQuery) -> bool {
+    query
+        .action
+        .as_ref()
+        .is_none_or(|a| entry.action.starts_with(a.as_str()))
+        && query.ip.as_ref().is_none_or(|ip| {
+            entry.ip.as_deref() == Some(ip.as_str())
+                || entry
+                    .forwarded_for
+                    .as_deref()
+                    .is_some_and(|f| f.split(',').any(|part| part.trim() == ip))
+        })
+        && query
+            .target
+            .as_ref()
+            .is_none_or(|t| entry.target.as_deref() == Some(t.as_str()))
+        && query.success.is_none_or(|s| entry.success == s)
+        && query.since.is_none_or(|since| entry.time >= since)
+        && query.until.is_none_or(|until| entry.time < until)
+}
+
+/// 由请求方法与路由模板得到操作名，返回 None 表示不审计（只读请求）
+///
+/// 未列出的变更路由按 `METHOD 路由模板` 记录
+pub fn action_for(method: &str, route: &str) -> Option<String> {
+    let action = match (method, route) {
+        ("GET" | "HEAD" | "OPTIONS", _) => return None,
+        ("POST", "/credentials") => "credential.add",
+        ("POST", "/credentials/import") => "credential.import",
+        ("POST", "/credentials/export") => "credential.export",
+        ("POST", "/credentials/import-bundle") => "credential.import_bundle",
+        ("POST", "/credentials/device-login") => "credential.device_login",
+        ("POST", "/credentials/device-login/{session}/poll") => "credential.device_login_poll",
+        ("DELETE", "/credentials/{id}") => "credential.delete",
+        ("POST", "/credentials/{id}/disabled") => "credential.set_disabled",
+        ("POST", "/credentials/{id}/priority") => "credential.set_priority",
+        ("POST", "/credentials/{id}/weight") => "credential.set_weight",
+        ("POST", "/credentials/{id}/schedule") => "credential.set_schedule",
+        ("POST", "/credentials/{id}/group") => "credential.set_group",
+        ("POST", "/credentials/{id}/reset") => "credential.reset",
+        ("POST", "/credentials/{id}/refresh") => "credential.refresh",
+        ("PUT", "/config/load-balancing") => "config.load_balancing",
+        ("PUT", "/config/model-routes") => "config.model_routes",
+        ("PUT", "/config/system-prompts") => "config.system_prompts",
+        ("POST", "/share-links") => "share_link.create",
+        ("POST", "/debug/replay") => "debug.replay",
+        _ => return Some(format!("{} {}", method, route)),
+    };
+    Some(action.to_string())
+}
+
+/// 请求体可以原样记录到审计日志的操作（不含凭据与密钥）
+pub fn records_body(action: &str) -> bool {
+    matches!(
+        action,
+        "credential.set_disabled"
+            | "credential.set_priority"
+            | "credential.set_weight"
+            | "credential.set_schedule"
+            | "credential.set_group"
+            | "config.load_balancing"
+            | "config.model_routes"
+            | "config.system_prompts"
+            | "share_link.create"
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn event(action: &str, ip: &str, status: u16) -> AuditEvent {
+        AuditEvent {
+            action: action.to_string(),
+            method: "POST".to_string(),
+            path: "/credentials/1/priority".to_string(),
+            target: Some("1".to_string()),
+            ip: Some(ip.to_string()),
+            status,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_query_filters_and_pagination() {
+        let log = AuditLog::new(None);
+        log.record(event("credential.set_priority", "10.0.0.1", 200));
+        log.record(event("credential.delete", "10.0.0.2", 404));
+        log.record(event("config.model_routes", "10.0.0.1", 200));
+
+        let page = log.query(&AuditQuery::default());
+        assert_eq!(page.total, 3);
+        assert_eq!(page.entries[0].action, "config.model_routes");
+
+        let page = log.query(&AuditQuery {
+            action: Some("credential.".to_string()),
+            ..Default::default()
+        });
+        assert_eq!(page.total, 2);
+
+        let page = log.query(&AuditQuery {
+            success: Some(false),
+            ..Default::default()
+        });
+        assert_eq!(page.total, 1);
+        assert_eq!(page.entries[0].ip.as_deref(), Some("10.0.0.2"));
+
+        let page = log.query(&AuditQuery {
+            ip: Some("10.0.0.1".to_string()),
+            limit: Some(1),
+            offset: 1,
+            ..Default::default()
+        });
+        assert_eq!(page.total, 2);
+        assert_eq!(page.entries.len(), 1);
+        assert_eq!(page.entries[0].action, "credential.set_priority");
+    }
+
+    #[test]
+    fn test_login_success_deduplicated_per_source() {
+        let log = AuditLog::new(None);
+        log.record_login(event("", "10.0.0.1", 200), true);
+        log.record_login(event("", "10.0.0.1", 200), true);
+        log.record_login(event("", "10.0.0.2", 200), true);
+        log.record_login(event("", "10.0.0.1", 401), false);
+        log.record_login(event("", "10.0.0.1", 401), false);
+
+        let page = log.query(&AuditQuery {
+            action: Some("login.".to_string()),
+            ..Default::default()
+        });
+        assert_eq!(page.total, 4);
+        assert_eq!(
+            page.entries
+                .iter()
+                .filter(|e| e.action == "login.failure")
+                .count(),
+            2
+        );
+    }
+
+    #[test]
+    fn test_persisted_entries_reloaded() {
+        let path =
+            std::env::temp_dir().join(format!("kiro-rs-audit-{}.jsonl", uuid::Uuid::new_v4()));
+        let log = AuditLog::new(Some(path.clone()));
+        log.record(event("credential.delete", "10.0.0.1", 200));
+        log.record(event("credential.add", "10.0.0.1", 200));
+        drop(log);
+
+        let log = AuditLog::new(Some(path.clone()));
+        log.record(event("config.load_balancing", "10.0.0.1", 200));
+        let page = log.query(&AuditQuery::default());
+        assert_eq!(page.total, 3);
+        assert_eq!(page.entries[0].id, 3);
+        assert_eq!(page.entries[2].action, "credential.delete");
+        let _ = std::fs::remove_file(path);
+    }
+
+    #[test]
+    fn test_action_for() {
+        assert_eq!(action_for("GET", "/credentials"), None);
+        assert_eq!(
+            action_for("DELETE", "/credentials/{id}").as_deref(),
+            Some("credential.delete")
+        );
+        assert_eq!(
+            action_for("POST", "/something/new").as_deref(),
+            Some("POST /something/new")
+        );
+    }
+}
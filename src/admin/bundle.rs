@@ -0,0 +1,204 @@
+//! 加密凭据包
+//!
+//! 用于在实例之间迁移凭据：导出时把全部凭据（含优先级、Region、代理、可用时段等）
+//! 序列化为 JSON，使用口令派生的密钥加密；导入时解密后走批量导入流程。
+//!
+//! 加密方案：PBKDF2-HMAC-SHA256 派生 256 位密钥，AES-256-GCM 加密，
+//! salt / nonce / 密文均以 base64 编码存放在包内。
+
+use std::num::NonZeroU32;
+
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use ring::aead::{AES_256_GCM, Aad, LessSafeKey, Nonce, UnboundKey};
+use ring::pbkdf2;
+use ring::rand::{SecureRandom, SystemRandom};
+use serde::{Deserialize, Serialize};
+
+use crate::kiro::model::credentials::KiroCredentials;
+
+/// 包格式标识
+pub const BUNDLE_FORMAT: &str = "kiro-rs-credentials";
+
+/// 当前包版本
+pub const BUNDLE_VERSION: u32 = 1;
+
+/// 口令最短长度
+pub const MIN_PASSPHRASE_LEN: usize = 8;
+
+/// 导出时使用的 PBKDF2 迭代次数
+const PBKDF2_ITERATIONS: u32 = 600_000;
+
+/// 导入时允许的最大迭代次数（导出默认值的 4 倍，防止构造的包长时间占用 CPU）
+const MAX_PBKDF2_ITERATIONS: u32 = PBKDF2_ITERATIONS * 4;
+
+const SALT_LEN: usize = 16;
+
+/// 加密凭据包
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct EncryptedBundle {
+    /// 固定为 `kiro-rs-credentials`
+    pub format: String,
+    pub version: u32,
+    /// 密钥派生算法，目前仅支持 `pbkdf2-sha256`
+    pub kdf: String,
+    pub iterations: u32,
+    pub salt: String,
+    pub nonce: String,
+    /// AES-256-GCM 密文（含认证标签）
+    pub ciphertext: String,
+    /// 导出时间（RFC3339）
+    pub exported_at: String,
+    /// 包内凭据数量（明文，便于导入前确认）
+    pub count: usize,
+}
+
+fn derive_key(passphrase: &str, salt: &[u8], iterations: NonZeroU32) -> LessSafeKey {
+    let mut key = [0u8; 32];
+    pbkdf2::derive(
+        pbkdf2::PBKDF2_HMAC_SHA256,
+        iterations,
+        salt,
+        passphrase.as_bytes(),
+        &mut key,
+    );
+    LessSafeKey::new(UnboundKey::new(&AES_256_GCM, &key).expect("AES-256 密钥长度固定为 32 字节"))
+}
+
+/// 关联数据：绑定包格式与版本，防止篡改头部后解密
+fn aad() -> Aad<Vec<u8>> {
+    Aad::from(format!("{}/v{}", BUNDLE_FORMAT, BUNDLE_VERSION).into_bytes())
+}
+
+fn validate_passphrase(passphrase: &str) -> Result<(), String> {
+    if passphrase.chars().count() < MIN_PASSPHRASE_LEN {
+        return Err(format!("口令长度至少 {} 个字符", MIN_PASSPHRASE_LEN));
+    }
+    Ok(())
+}
+
+/// 加密导出凭据
+pub fn seal(credentials: &[KiroCredentials], passphrase: &str) -> Result<EncryptedBundle, String> {
+    seal_with_iterations(credentials, passphrase, PBKDF2_ITERATIONS)
+}
+
+fn seal_with_iterations(
+    credentials: &[KiroCredentials],
+    passphrase: &str,
+    iterations: u32,
+) -> Result<EncryptedBundle, String> {
+    validate_passphrase(passphrase)?;
+    let iterations = NonZeroU32::new(iterations).ok_or("迭代次数必须大于 0")?;
+
+    let rng = SystemRandom::new();
+    let mut salt = [0u8; SALT_LEN];
+    let mut nonce = [0u8; ring::aead::NONCE_LEN];
+    rng.fill(&mut salt)
+        .and_then(|_| rng.fill(&mut nonce))
+        .map_err(|_| "生成随机数失败".to_string())?;
+
+    let mut data = serde_json::to_vec(credentials).map_err(|e| format!("序列化凭据失败: {}", e))?;
+    derive_key(passphrase, &salt, iterations)
+        .seal_in_place_append_tag(Nonce::assume_unique_for_key(nonce), aad(), &mut data)
+        .map_err(|_| "加密失败".to_string())?;
+
+    Ok(EncryptedBundle {
+        format: BUNDLE_FORMAT.to_string(),
+        version: BUNDLE_VERSION,
+        kdf: "pbkdf2-sha256".to_string(),
+        iterations: iterations.get(),
+        salt: BASE64.encode(salt),
+        nonce: BASE64.encode(nonce),
+        ciphertext: BASE64.encode(data),
+        exported_at: chrono::Utc::now().to_rfc3339(),
+        count: credentials.len(),
+    })
+}
+
+/// 解密凭据包
+pub fn open(bundle: &EncryptedBundle, passphrase: &str) -> Result<Vec<KiroCredentials>, String> {
+    if bundle.format != BUNDLE_FORMAT {
+        return Err(format!("不是凭据包（format: {}）", bundle.format));
+    }
+    if bundle.version != BUNDLE_VERSION {
+        return Err(format!("不支持的凭据包版本: {}", bundle.version));
+    }
+    if bundle.kdf != "pbkdf2-sha256" {
+        return Err(format!("不支持的密钥派生算法: {}", bundle.kdf));
+    }
+    let iterations = NonZeroU32::new(bundle.iterations)
+        .filter(|n| n.get() <= MAX_PBKDF2_ITERATIONS)
+        .ok_or_else(|| format!("迭代次数无效: {}", bundle.iterations))?;
+
+    let decode = |field: &str, value: &str| {
+        BASE64
+            .decode(value)
+            .map_err(|e| format!("{} 不是有效的 base64: {}", field, e))
+    };
+    let salt = decode("salt", &bundle.salt)?;
+    let nonce: [u8; ring::aead::NONCE_LEN] = decode("nonce", &bundle.nonce)?
+        .try_into()
+        .map_err(|_| "nonce 长度无效".to_string())?;
+    let mut data = decode("ciphertext", &bundle.ciphertext)?;
+
+    let plaintext = derive_key(passphrase, &salt, iterations)
+        .open_in_place(Nonce::assume_unique_for_key(nonce), aad(), &mut data)
+        .map_err(|_| "解密失败：口令错误或凭据包已损坏".to_string())?;
+    serde_json::from_slice(plaintext).map_err(|e| format!("解析凭据失败: {}", e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn credentials() -> Vec<KiroCredentials> {
+        vec![KiroCredentials {
+            refresh_token: Some("refresh-token".to_string()),
+            priority: 3,
+            region: Some("eu-west-1".to_string()),
+            proxy_url: Some("socks5://127.0.0.1:1080".to_string()),
+            schedule: vec!["Mon-Fri 09:00-18:00".to_string()],
+            disabled: true,
+            ..Default::default()
+        }]
+    }
+
+    #[test]
+    fn test_seal_and_open_roundtrip() {
+        let bundle = seal_with_iterations(&credentials(), "correct horse", 1_000).unwrap();
+        assert_eq!(bundle.count, 1);
+        assert!(!bundle.ciphertext.contains("refresh-token"));
+
+        let opened = open(&bundle, "correct horse").unwrap();
+        assert_eq!(opened[0].refresh_token.as_deref(), Some("refresh-token"));
+        assert_eq!(opened[0].priority, 3);
+        assert_eq!(opened[0].schedule, ["Mon-Fri 09:00-18:00"]);
+        assert!(opened[0].disabled);
+    }
+
+    #[test]
+    fn test_open_rejects_wrong_passphrase_and_tampering() {
+        let bundle = seal_with_iterations(&credentials(), "correct horse", 1_000).unwrap();
+        assert!(open(&bundle, "wrong horse").is_err());
+
+        let mut tampered = bundle.clone();
+        tampered.version = 2;
+        assert!(open(&tampered, "correct horse").is_err());
+
+        let mut tampered = bundle.clone();
+        tampered.iterations = 999;
+        assert!(open(&tampered, "correct horse").is_err());
+
+        // 超出上限的迭代次数在派生密钥前即被拒绝
+        let mut tampered = bundle;
+        tampered.iterations = MAX_PBKDF2_ITERATIONS + 1;
+        let err = open(&tampered, "correct horse").unwrap_err();
+        assert!(err.contains("迭代次数无效"), "{}", err);
+    }
+
+    #[test]
+    fn test_seal_rejects_short_passphrase() {
+        assert!(seal(&credentials(), "short").is_err());
+    }
+}
@@ -0,0 +1,159 @@
+//! IdC 设备授权登录会话
+//!
+//! `POST /credentials/device-login` 发起授权后，会话保存 OIDC 客户端与设备码；
+//! 管理面板按返回的间隔调用 `POST /credentials/device-login/{session}/poll`，
+//! 用户在浏览器中确认授权后，以得到的 refreshToken 添加 IdC 凭据。
+//! 会话仅保存在内存中，重启后需重新发起。
+
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+use parking_lot::Mutex;
+
+use crate::kiro::device_auth::{DeviceAuthorization, SLOW_DOWN_INCREMENT_SECS};
+
+use super::types::StartDeviceLoginRequest;
+
+/// 同时进行中的设备登录会话上限
+pub const MAX_PENDING_LOGINS: usize = 16;
+
+/// 进行中的设备登录
+#[derive(Debug, Clone)]
+pub struct PendingLogin {
+    /// OIDC Region
+    pub region: String,
+    pub authorization: DeviceAuthorization,
+    /// 发起登录时指定的凭据属性（优先级、分组、代理等）
+    pub request: StartDeviceLoginRequest,
+    pub expires_at: Instant,
+    pub next_poll_at: Instant,
+}
+
+/// 轮询前的会话检查结果
+#[derive(Debug)]
+pub enum PollTicket {
+    NotFound,
+    /// 设备码已过期（会话已移除）
+    Expired,
+    /// 未到轮询间隔，需再等待的秒数
+    Wait(u64),
+    /// 可以向上游轮询
+    Ready(Box<PendingLogin>),
+}
+
+/// 设备登录会话表
+#[derive(Debug, Default)]
+pub struct DeviceLogins {
+    sessions: Mutex<HashMap<String, PendingLogin>>,
+}
+
+impl DeviceLogins {
+    /// 保存新会话并返回会话 ID，进行中的会话已达上限时返回 None
+    pub fn insert(&self, login: PendingLogin, now: Instant) -> Option<String> {
+        let mut sessions = self.sessions.lock();
+        sessions.retain(|_, s| s.expires_at > now);
+        if sessions.len() >= MAX_PENDING_LOGINS {
+            return None;
+        }
+        let id = uuid::Uuid::new_v4().simple().to_string();
+        sessions.insert(id.clone(), login);
+        Some(id)
+    }
+
+    /// 检查会话是否可以轮询
+    pub fn ticket(&self, id: &str, now: Instant) -> PollTicket {
+        let mut sessions = self.sessions.lock();
+        let Some(login) = sessions.get(id) else {
+            return PollTicket::NotFound;
+        };
+        if login.expires_at <= now {
+            sessions.remove(id);
+            return PollTicket::Expired;
+        }
+        if login.next_poll_at > now {
+            return PollTicket::Wait(wait_secs(login.next_poll_at, now));
+        }
+        PollTicket::Ready(Box::new(login.clone()))
+    }
+
+    /// 上游尚未完成授权：推迟下次轮询（`slow_down` 时同时加大间隔），返回需等待的秒数
+    pub fn defer(&self, id: &str, slow_down: bool, now: Instant) -> u64 {
+        let mut sessions = self.sessions.lock();
+        let Some(login) = sessions.get_mut(id) else {
+            return 0;
+        };
+        if slow_down {
+            login.authorization.interval += SLOW_DOWN_INCREMENT_SECS;
+        }
+        login.next_poll_at = now + Duration::from_secs(login.authorization.interval as u64);
+        wait_secs(login.next_poll_at, now)
+    }
+
+    /// 结束会话（授权完成或失败）
+    pub fn remove(&self, id: &str) -> Option<PendingLogin> {
+        self.sessions.lock().remove(id)
+    }
+}
+
+fn wait_secs(at: Instant, now: Instant) -> u64 {
+    at.saturating_duration_since(now).as_secs_f64().ceil() as u64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn login(now: Instant) -> PendingLogin {
+        PendingLogin {
+            region: "us-east-1".to_string(),
+            authorization: DeviceAuthorization {
+                client_id: "client".to_string(),
+                client_secret: "secret".to_string(),
+                device_code: "device".to_string(),
+                user_code: "ABCD-EFGH".to_string(),
+                verification_uri: "https://device.sso.us-east-1.amazonaws.com/".to_string(),
+                verification_uri_complete: None,
+                expires_in: 600,
+                interval: 5,
+            },
+            request: StartDeviceLoginRequest::default(),
+            expires_at: now + Duration::from_secs(600),
+            next_poll_at: now,
+        }
+    }
+
+    #[test]
+    fn test_poll_interval_and_slow_down() {
+        let logins = DeviceLogins::default();
+        let now = Instant::now();
+        let id = logins.insert(login(now), now).unwrap();
+
+        assert!(matches!(logins.ticket(&id, now), PollTicket::Ready(_)));
+        assert_eq!(logins.defer(&id, false, now), 5);
+        assert!(matches!(logins.ticket(&id, now), PollTicket::Wait(5)));
+
+        let later = now + Duration::from_secs(5);
+        assert!(matches!(logins.ticket(&id, later), PollTicket::Ready(_)));
+        assert_eq!(logins.defer(&id, true, later), 10);
+
+        assert!(matches!(
+            logins.ticket(&id, now + Duration::from_secs(600)),
+            PollTicket::Expired
+        ));
+        assert!(matches!(logins.ticket(&id, now), PollTicket::NotFound));
+    }
+
+    #[test]
+    fn test_pending_limit_prunes_expired() {
+        let logins = DeviceLogins::default();
+        let now = Instant::now();
+        for _ in 0..MAX_PENDING_LOGINS {
+            assert!(logins.insert(login(now), now).is_some());
+        }
+        assert!(logins.insert(login(now), now).is_none());
+
+        // 过期会话在新建时被清理
+        let later = now + Duration::from_secs(600);
+        assert!(logins.insert(login(later), later).is_some());
+    }
+}
@@ -12,6 +12,9 @@ pub enum AdminServiceError {
     /// 凭据不存在
     NotFound { id: u64 },
 
+    /// 其他资源不存在（如调试录制）
+    ResourceNotFound(String),
+
     /// 上游服务调用失败（网络、API 错误等）
     UpstreamError(String),
 
@@ -20,6 +23,9 @@ pub enum AdminServiceError {
 
     /// 凭据无效（验证失败）
     InvalidCredential(String),
+
+    /// 请求参数无效
+    InvalidRequest(String),
 }
 
 impl fmt::Display for AdminServiceError {
@@ -28,9 +34,11 @@ impl fmt::Display for AdminServiceError {
             AdminServiceError::NotFound { id } => {
                 write!(f, "凭据不存在: {}", id)
             }
+            AdminServiceError::ResourceNotFound(msg) => write!(f, "资源不存在: {}", msg),
             AdminServiceError::UpstreamError(msg) => write!(f, "上游服务错误: {}", msg),
             AdminServiceError::InternalError(msg) => write!(f, "内部错误: {}", msg),
             AdminServiceError::InvalidCredential(msg) => write!(f, "凭据无效: {}", msg),
+            AdminServiceError::InvalidRequest(msg) => write!(f, "请求无效: {}", msg),
         }
     }
 }
@@ -42,9 +50,11 @@ impl AdminServiceError {
     pub fn status_code(&self) -> StatusCode {
         match self {
             AdminServiceError::NotFound { .. } => StatusCode::NOT_FOUND,
+            AdminServiceError::ResourceNotFound(_) => StatusCode::NOT_FOUND,
             AdminServiceError::UpstreamError(_) => StatusCode::BAD_GATEWAY,
             AdminServiceError::InternalError(_) => StatusCode::INTERNAL_SERVER_ERROR,
             AdminServiceError::InvalidCredential(_) => StatusCode::BAD_REQUEST,
+            AdminServiceError::InvalidRequest(_) => StatusCode::BAD_REQUEST,
         }
     }
 
@@ -52,6 +62,9 @@ impl AdminServiceError {
     pub fn into_response(self) -> AdminErrorResponse {
         match &self {
             AdminServiceError::NotFound { .. } => AdminErrorResponse::not_found(self.to_string()),
+            AdminServiceError::ResourceNotFound(_) => {
+                AdminErrorResponse::not_found(self.to_string())
+            }
             AdminServiceError::UpstreamError(_) => AdminErrorResponse::api_error(self.to_string()),
             AdminServiceError::InternalError(_) => {
                 AdminErrorResponse::internal_error(self.to_string())
@@ -59,6 +72,9 @@ impl AdminServiceError {
             AdminServiceError::InvalidCredential(_) => {
                 AdminErrorResponse::invalid_request(self.to_string())
             }
+            AdminServiceError::InvalidRequest(_) => {
+                AdminErrorResponse::invalid_request(self.to_string())
+            }
         }
     }
 }
@@ -1,16 +1,24 @@
 //! Admin API HTTP 处理器
 
 use axum::{
-    Json,
-    extract::{Path, State},
+    Extension, Json,
+    extract::{Path, Query, State},
+    http::{StatusCode, header},
     response::IntoResponse,
 };
 
+use crate::anthropic::usage_ledger::UsageQuery;
+
 use super::{
+    audit::AuditQuery,
     middleware::AdminState,
+    share::ShareScope,
     types::{
-        AddCredentialRequest, SetDisabledRequest, SetLoadBalancingModeRequest, SetPriorityRequest,
-        SuccessResponse,
+        AddCredentialRequest, AdminErrorResponse, CreateShareLinkRequest, ExportCredentialsRequest,
+        ImportCredentialBundleRequest, ImportCredentialsRequest, ModelRoutesPayload, ReplayRequest,
+        SessionMemoryPayload, SetDisabledRequest, SetGroupRequest, SetLoadBalancingModeRequest,
+        SetPriorityRequest, SetScheduleRequest, SetWeightRequest, StartDeviceLoginRequest,
+        SuccessResponse, SystemPromptsPayload, ValidationReportQuery,
     },
 };
 
@@ -54,6 +62,62 @@ pub async fn set_credential_priority(
     }
 }
 
+/// POST /api/admin/credentials/:id/weight
+/// 设置凭据在 balanced 模式下的权重
+pub async fn set_credential_weight(
+    State(state): State<AdminState>,
+    Path(id): Path<u64>,
+    Json(payload): Json<SetWeightRequest>,
+) -> impl IntoResponse {
+    match state.service.set_weight(id, payload.weight) {
+        Ok(_) => Json(SuccessResponse::new(format!(
+            "凭据 #{} 权重已设置为 {}",
+            id, payload.weight
+        )))
+        .into_response(),
+        Err(e) => (e.status_code(), Json(e.into_response())).into_response(),
+    }
+}
+
+/// POST /api/admin/credentials/:id/schedule
+/// 设置凭据可用时段（空列表表示始终可用）
+pub async fn set_credential_schedule(
+    State(state): State<AdminState>,
+    Path(id): Path<u64>,
+    Json(payload): Json<SetScheduleRequest>,
+) -> impl IntoResponse {
+    let cleared = payload.schedule.is_empty();
+    match state.service.set_schedule(id, payload.schedule) {
+        Ok(_) => Json(SuccessResponse::new(if cleared {
+            format!("凭据 #{} 已清除可用时段", id)
+        } else {
+            format!("凭据 #{} 可用时段已更新", id)
+        }))
+        .into_response(),
+        Err(e) => (e.status_code(), Json(e.into_response())).into_response(),
+    }
+}
+
+/// POST /api/admin/credentials/:id/group
+/// 设置凭据分组（空值表示取消分组）
+pub async fn set_credential_group(
+    State(state): State<AdminState>,
+    Path(id): Path<u64>,
+    Json(payload): Json<SetGroupRequest>,
+) -> impl IntoResponse {
+    match state.service.set_group(id, payload.group) {
+        Ok(Some(group)) => Json(SuccessResponse::new(format!(
+            "凭据 #{} 已加入分组 {}",
+            id, group
+        )))
+        .into_response(),
+        Ok(None) => {
+            Json(SuccessResponse::new(format!("凭据 #{} 已取消分组", id))).into_response()
+        }
+        Err(e) => (e.status_code(), Json(e.into_response())).into_response(),
+    }
+}
+
 /// POST /api/admin/credentials/:id/reset
 /// 重置失败计数并重新启用
 pub async fn reset_failure_count(
@@ -70,6 +134,15 @@ pub async fn reset_failure_count(
     }
 }
 
+/// GET /api/admin/credentials/validation-report
+/// 获取凭据验证报告（`?refresh=true` 重新验证）
+pub async fn get_validation_report(
+    State(state): State<AdminState>,
+    Query(query): Query<ValidationReportQuery>,
+) -> impl IntoResponse {
+    Json(state.service.get_validation_report(query.refresh).await)
+}
+
 /// GET /api/admin/credentials/:id/balance
 /// 获取指定凭据的余额
 pub async fn get_credential_balance(
@@ -94,6 +167,30 @@ pub async fn add_credential(
     }
 }
 
+/// POST /api/admin/credentials/device-login
+/// 发起 IdC 设备授权登录
+pub async fn start_device_login(
+    State(state): State<AdminState>,
+    Json(payload): Json<StartDeviceLoginRequest>,
+) -> impl IntoResponse {
+    match state.service.start_device_login(payload).await {
+        Ok(response) => Json(response).into_response(),
+        Err(e) => (e.status_code(), Json(e.into_response())).into_response(),
+    }
+}
+
+/// POST /api/admin/credentials/device-login/:session/poll
+/// 轮询 IdC 设备授权登录，授权完成后添加凭据
+pub async fn poll_device_login(
+    State(state): State<AdminState>,
+    Path(session): Path<String>,
+) -> impl IntoResponse {
+    match state.service.poll_device_login(&session).await {
+        Ok(response) => Json(response).into_response(),
+        Err(e) => (e.status_code(), Json(e.into_response())).into_response(),
+    }
+}
+
 /// DELETE /api/admin/credentials/:id
 /// 删除凭据
 pub async fn delete_credential(
@@ -140,3 +237,314 @@ pub async fn set_load_balancing_mode(
         Err(e) => (e.status_code(), Json(e.into_response())).into_response(),
     }
 }
+
+/// GET /api/admin/config/model-routes
+/// 获取模型路由规则
+pub async fn get_model_routes(State(state): State<AdminState>) -> impl IntoResponse {
+    Json(state.service.get_model_routes())
+}
+
+/// PUT /api/admin/config/model-routes
+/// 设置模型路由规则（整体替换）
+pub async fn set_model_routes(
+    State(state): State<AdminState>,
+    Json(payload): Json<ModelRoutesPayload>,
+) -> impl IntoResponse {
+    match state.service.set_model_routes(payload) {
+        Ok(response) => Json(response).into_response(),
+        Err(e) => (e.status_code(), Json(e.into_response())).into_response(),
+    }
+}
+
+/// GET /api/admin/config/system-prompts
+/// 获取 system 提示注入规则
+pub async fn get_system_prompts(State(state): State<AdminState>) -> impl IntoResponse {
+    Json(state.service.get_system_prompts())
+}
+
+/// PUT /api/admin/config/system-prompts
+/// 设置 system 提示注入规则（整体替换）
+pub async fn set_system_prompts(
+    State(state): State<AdminState>,
+    Json(payload): Json<SystemPromptsPayload>,
+) -> impl IntoResponse {
+    match state.service.set_system_prompts(payload) {
+        Ok(response) => Json(response).into_response(),
+        Err(e) => (e.status_code(), Json(e.into_response())).into_response(),
+    }
+}
+
+/// GET /api/admin/sessions/usage
+/// 列出最近活跃会话的用量与上下文占用
+pub async fn list_session_usage(State(state): State<AdminState>) -> impl IntoResponse {
+    Json(state.service.list_session_usage())
+}
+
+/// GET /api/admin/sessions/memory
+/// 列出保存了记忆的会话
+pub async fn list_session_memories(State(state): State<AdminState>) -> impl IntoResponse {
+    match state.service.list_session_memories() {
+        Ok(response) => Json(response).into_response(),
+        Err(e) => (e.status_code(), Json(e.into_response())).into_response(),
+    }
+}
+
+/// GET /api/admin/sessions/:session_id/memory
+/// 获取会话记忆
+pub async fn get_session_memory(
+    State(state): State<AdminState>,
+    Path(session_id): Path<String>,
+) -> impl IntoResponse {
+    match state.service.get_session_memory(&session_id) {
+        Ok(response) => Json(response).into_response(),
+        Err(e) => (e.status_code(), Json(e.into_response())).into_response(),
+    }
+}
+
+/// PUT /api/admin/sessions/:session_id/memory
+/// 替换会话的全部笔记
+pub async fn set_session_memory(
+    State(state): State<AdminState>,
+    Path(session_id): Path<String>,
+    Json(payload): Json<SessionMemoryPayload>,
+) -> impl IntoResponse {
+    match state.service.set_session_memory(&session_id, payload) {
+        Ok(response) => Json(response).into_response(),
+        Err(e) => (e.status_code(), Json(e.into_response())).into_response(),
+    }
+}
+
+/// DELETE /api/admin/sessions/:session_id/memory
+/// 删除会话记忆
+pub async fn delete_session_memory(
+    State(state): State<AdminState>,
+    Path(session_id): Path<String>,
+) -> impl IntoResponse {
+    match state.service.delete_session_memory(&session_id) {
+        Ok(_) => Json(SuccessResponse::new(format!(
+            "会话 {} 的记忆已删除",
+            session_id
+        )))
+        .into_response(),
+        Err(e) => (e.status_code(), Json(e.into_response())).into_response(),
+    }
+}
+
+/// GET /api/admin/config/schema
+/// 获取 config.json 的 JSON Schema（供设置表单生成）
+pub async fn get_config_schema(State(state): State<AdminState>) -> impl IntoResponse {
+    Json(state.service.get_config_schema())
+}
+
+/// POST /api/admin/share-links
+/// 签发只读分享链接
+pub async fn create_share_link(
+    State(state): State<AdminState>,
+    Json(payload): Json<CreateShareLinkRequest>,
+) -> impl IntoResponse {
+    match state
+        .service
+        .create_share_link(&state.admin_api_key, payload)
+    {
+        Ok(response) => Json(response).into_response(),
+        Err(e) => (e.status_code(), Json(e.into_response())).into_response(),
+    }
+}
+
+/// GET /api/admin/share/credentials?token=...
+/// 通过分享链接获取凭据可用性（只读、已脱敏）
+pub async fn get_shared_credentials(
+    State(state): State<AdminState>,
+    Extension(scope): Extension<ShareScope>,
+) -> impl IntoResponse {
+    if scope != ShareScope::Credentials {
+        let error = AdminErrorResponse::share_token_error("Share token does not grant this view");
+        return (StatusCode::FORBIDDEN, Json(error)).into_response();
+    }
+    Json(state.service.get_shared_credentials()).into_response()
+}
+
+/// POST /api/admin/credentials/import
+/// 批量导入凭据（并发验活、按订阅等级设置优先级，返回汇总报告）
+pub async fn import_credentials(
+    State(state): State<AdminState>,
+    Json(payload): Json<ImportCredentialsRequest>,
+) -> impl IntoResponse {
+    match state.service.import_credentials(payload).await {
+        Ok(response) => Json(response).into_response(),
+        Err(e) => (e.status_code(), Json(e.into_response())).into_response(),
+    }
+}
+
+/// POST /api/admin/credentials/export
+/// 加密导出全部凭据（口令放在请求体中，避免出现在 URL 与访问日志里）
+pub async fn export_credentials(
+    State(state): State<AdminState>,
+    Json(payload): Json<ExportCredentialsRequest>,
+) -> impl IntoResponse {
+    match state.service.export_credentials(payload).await {
+        Ok(bundle) => Json(bundle).into_response(),
+        Err(e) => (e.status_code(), Json(e.into_response())).into_response(),
+    }
+}
+
+/// POST /api/admin/credentials/import-bundle
+/// 导入加密凭据包（解密后按批量导入流程验活）
+pub async fn import_credential_bundle(
+    State(state): State<AdminState>,
+    Json(payload): Json<ImportCredentialBundleRequest>,
+) -> impl IntoResponse {
+    match state.service.import_credential_bundle(payload).await {
+        Ok(response) => Json(response).into_response(),
+        Err(e) => (e.status_code(), Json(e.into_response())).into_response(),
+    }
+}
+
+/// GET /api/admin/malformed-requests
+/// 获取最近被上游判定为格式错误的请求及可疑字段
+pub async fn get_malformed_requests(State(state): State<AdminState>) -> impl IntoResponse {
+    Json(state.service.get_malformed_requests())
+}
+
+/// GET /api/admin/model-prices
+/// 获取可用模型的生效价格（用于费用估算）
+pub async fn get_model_prices(State(state): State<AdminState>) -> impl IntoResponse {
+    Json(state.service.get_model_prices())
+}
+
+/// GET /api/admin/shadow
+/// 获取影子流量计数与最近的响应差异
+pub async fn get_shadow(State(state): State<AdminState>) -> impl IntoResponse {
+    Json(state.service.get_shadow_report())
+}
+
+/// GET /api/admin/audit
+/// 按条件分页查询操作审计日志
+pub async fn get_audit(
+    State(state): State<AdminState>,
+    Query(query): Query<AuditQuery>,
+) -> impl IntoResponse {
+    Json(state.service.get_audit(&query))
+}
+
+/// GET /api/admin/debug/frames
+/// 列出最近录制的上游事件流
+pub async fn list_frame_dumps(State(state): State<AdminState>) -> impl IntoResponse {
+    Json(state.service.list_frame_dumps())
+}
+
+/// GET /api/admin/debug/frames/:id
+/// 导出指定请求的事件流 dump
+pub async fn get_frame_dump(
+    State(state): State<AdminState>,
+    Path(id): Path<String>,
+) -> impl IntoResponse {
+    match state.service.get_frame_dump(&id) {
+        Ok(dump) => Json(dump).into_response(),
+        Err(e) => (e.status_code(), Json(e.into_response())).into_response(),
+    }
+}
+
+/// POST /api/admin/debug/replay
+/// 用流转换器重新处理录制的事件流
+pub async fn replay_frames(
+    State(state): State<AdminState>,
+    Json(payload): Json<ReplayRequest>,
+) -> impl IntoResponse {
+    match state.service.replay_frames(payload) {
+        Ok(result) => Json(result).into_response(),
+        Err(e) => (e.status_code(), Json(e.into_response())).into_response(),
+    }
+}
+
+/// GET /api/admin/upstream-fields
+/// 获取上游事件中出现过的未识别字段
+pub async fn get_unknown_upstream_fields(State(state): State<AdminState>) -> impl IntoResponse {
+    Json(state.service.get_unknown_upstream_fields())
+}
+
+/// GET /api/admin/upstream-events
+/// 获取上游出现过的未识别事件类型
+pub async fn get_unknown_upstream_events(State(state): State<AdminState>) -> impl IntoResponse {
+    Json(state.service.get_unknown_upstream_events())
+}
+
+/// GET /api/admin/request-sizes
+/// 获取发往上游的请求体积分布（字节数与估算 tokens）
+pub async fn get_request_sizes(State(state): State<AdminState>) -> impl IntoResponse {
+    Json(state.service.get_request_sizes())
+}
+
+/// GET /api/admin/concurrency
+/// 获取各凭据的并发上限、在途与排队请求数
+pub async fn get_concurrency(State(state): State<AdminState>) -> impl IntoResponse {
+    Json(state.service.get_concurrency())
+}
+
+/// GET /api/admin/admission
+/// 获取全局准入控制的在途、排队与拒绝请求数
+pub async fn get_admission(State(state): State<AdminState>) -> impl IntoResponse {
+    Json(state.service.get_admission())
+}
+
+/// GET /api/admin/usage
+/// 按日/按月聚合 token 用量
+pub async fn get_usage(
+    State(state): State<AdminState>,
+    Query(query): Query<UsageQuery>,
+) -> impl IntoResponse {
+    Json(state.service.get_usage(&query))
+}
+
+/// GET /api/admin/usage/export
+/// 导出聚合后的 token 用量（CSV）
+pub async fn export_usage(
+    State(state): State<AdminState>,
+    Query(query): Query<UsageQuery>,
+) -> impl IntoResponse {
+    match state.service.export_usage(&query) {
+        Ok(csv) => {
+            let filename = format!(
+                "kiro-rs-usage-{}.csv",
+                chrono::Utc::now().format("%Y%m%d-%H%M%S")
+            );
+            (
+                [
+                    (header::CONTENT_TYPE, "text/csv; charset=utf-8".to_string()),
+                    (
+                        header::CONTENT_DISPOSITION,
+                        format!("attachment; filename=\"{}\"", filename),
+                    ),
+                ],
+                csv,
+            )
+                .into_response()
+        }
+        Err(e) => (e.status_code(), Json(e.into_response())).into_response(),
+    }
+}
+
+/// GET /api/admin/support-bundle
+/// 下载诊断包（zip，已脱敏）
+pub async fn get_support_bundle(State(state): State<AdminState>) -> impl IntoResponse {
+    match state.service.build_support_bundle() {
+        Ok(bytes) => {
+            let filename = format!(
+                "kiro-rs-support-{}.zip",
+                chrono::Utc::now().format("%Y%m%d-%H%M%S")
+            );
+            (
+                [
+                    (header::CONTENT_TYPE, "application/zip".to_string()),
+                    (
+                        header::CONTENT_DISPOSITION,
+                        format!("attachment; filename=\"{}\"", filename),
+                    ),
+                ],
+                bytes,
+            )
+                .into_response()
+        }
+        Err(e) => (e.status_code(), Json(e.into_response())).into_response(),
+    }
+}
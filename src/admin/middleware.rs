@@ -1,16 +1,19 @@
 //! Admin API 中间件
 
+use std::net::SocketAddr;
 use std::sync::Arc;
 
 use axum::{
     body::Body,
-    extract::State,
+    extract::{ConnectInfo, MatchedPath, State},
     http::{Request, StatusCode},
     middleware::Next,
     response::{IntoResponse, Json, Response},
 };
 
+use super::audit::{self, AuditEvent};
 use super::service::AdminService;
+use super::share::{self, ShareTokenError};
 use super::types::AdminErrorResponse;
 use crate::common::auth;
 
@@ -40,11 +43,159 @@ pub async fn admin_auth_middleware(
 ) -> Response {
     let api_key = auth::extract_api_key(&request);
 
+    let event = audit_event(&request);
     match api_key {
-        Some(key) if auth::constant_time_eq(&key, &state.admin_api_key) => next.run(request).await,
+        Some(key) if auth::constant_time_eq(&key, &state.admin_api_key) => {
+            state.service.audit().record_login(event, true);
+            next.run(request).await
+        }
         _ => {
+            state.service.audit().record_login(
+                AuditEvent {
+                    status: StatusCode::UNAUTHORIZED.as_u16(),
+                    ..event
+                },
+                false,
+            );
             let error = AdminErrorResponse::authentication_error();
             (StatusCode::UNAUTHORIZED, Json(error)).into_response()
         }
     }
 }
+
+/// 审计请求体的最大字节数
+const MAX_AUDIT_BODY_BYTES: usize = 64 * 1024;
+
+/// Admin 操作审计中间件
+///
+/// 在认证之后运行，记录变更请求的操作、来源与响应状态；只读请求不记录
+pub async fn audit_middleware(
+    State(state): State<AdminState>,
+    request: Request<Body>,
+    next: Next,
+) -> Response {
+    let path = request.uri().path().to_string();
+    let route = request
+        .extensions()
+        .get::<MatchedPath>()
+        .map(|m| route_template(m.as_str(), &path));
+    let Some(route) = route else {
+        return next.run(request).await;
+    };
+    let Some(action) = audit::action_for(request.method().as_str(), &route) else {
+        return next.run(request).await;
+    };
+
+    let mut event = audit_event(&request);
+    event.action = action;
+    event.target = route
+        .split('/')
+        .position(|segment| segment == "{id}")
+        .and_then(|index| path.split('/').nth(index))
+        .map(str::to_string);
+
+    let request = if audit::records_body(&event.action) {
+        let (parts, body) = request.into_parts();
+        let bytes = match axum::body::to_bytes(body, MAX_AUDIT_BODY_BYTES).await {
+            Ok(bytes) => bytes,
+            Err(_) => {
+                event.status = StatusCode::PAYLOAD_TOO_LARGE.as_u16();
+                state.service.audit().record(event);
+                let error = AdminErrorResponse::invalid_request("Request body too large");
+                return (StatusCode::PAYLOAD_TOO_LARGE, Json(error)).into_response();
+            }
+        };
+        event.detail = serde_json::from_slice(&bytes).ok();
+        Request::from_parts(parts, Body::from(bytes))
+    } else {
+        request
+    };
+
+    let response = next.run(request).await;
+    event.status = response.status().as_u16();
+    state.service.audit().record(event);
+    response
+}
+
+/// 从请求中提取审计所需的公共信息
+fn audit_event(request: &Request<Body>) -> AuditEvent {
+    AuditEvent {
+        method: request.method().to_string(),
+        path: request.uri().path().to_string(),
+        ip: request
+            .extensions()
+            .get::<ConnectInfo<SocketAddr>>()
+            .map(|info| info.0.ip().to_string()),
+        forwarded_for: request
+            .headers()
+            .get("x-forwarded-for")
+            .and_then(|v| v.to_str().ok())
+            .map(str::to_string),
+        ..Default::default()
+    }
+}
+
+/// 去掉路由模板中的嵌套前缀（如 `/api/admin`），与 Admin 路由中的写法保持一致
+///
+/// 嵌套路由的 `MatchedPath` 含前缀，而请求 URI 已去掉前缀，按 URI 的段数截取模板末尾
+fn route_template(matched: &str, path: &str) -> String {
+    let depth = path.split('/').count().saturating_sub(1);
+    let segments: Vec<&str> = matched.split('/').collect();
+    let start = segments.len().saturating_sub(depth);
+    format!("/{}", segments[start..].join("/"))
+}
+
+/// 只读分享链接认证中间件
+///
+/// 从查询参数 `token` 中读取分享令牌，校验签名与有效期后
+/// 将授权的视图（`ShareScope`）写入请求扩展，由处理器判断是否允许访问
+pub async fn share_auth_middleware(
+    State(state): State<AdminState>,
+    mut request: Request<Body>,
+    next: Next,
+) -> Response {
+    let token = request.uri().query().and_then(|q| {
+        q.split('&')
+            .filter_map(|pair| pair.split_once('='))
+            .find(|(k, _)| *k == "token")
+            .and_then(|(_, v)| urlencoding::decode(v).ok())
+            .map(|v| v.into_owned())
+    });
+
+    let Some(token) = token else {
+        let error = AdminErrorResponse::share_token_error("Missing share token");
+        return (StatusCode::UNAUTHORIZED, Json(error)).into_response();
+    };
+
+    match share::verify_token(&state.admin_api_key, &token, chrono::Utc::now().timestamp()) {
+        Ok(scope) => {
+            request.extensions_mut().insert(scope);
+            next.run(request).await
+        }
+        Err(e) => {
+            let message = match e {
+                ShareTokenError::Invalid => "Invalid share token",
+                ShareTokenError::Expired => "Share token expired",
+            };
+            let error = AdminErrorResponse::share_token_error(message);
+            (StatusCode::UNAUTHORIZED, Json(error)).into_response()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_route_template_strips_nest_prefix() {
+        assert_eq!(
+            route_template(
+                "/api/admin/credentials/{id}/priority",
+                "/credentials/3/priority"
+            ),
+            "/credentials/{id}/priority"
+        );
+        assert_eq!(route_template("/api/admin/audit", "/audit"), "/audit");
+    }
+}
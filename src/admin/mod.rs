@@ -8,6 +8,10 @@
 //! - 修改凭据优先级
 //! - 重置失败计数
 //! - 查询凭据余额
+//! - 签发只读分享链接
+//! - 加密导出/导入全部凭据
+//! - 通过 IdC 设备授权登录添加凭据
+//! - 记录并查询操作审计日志
 //!
 //! # 使用
 //! ```ignore
@@ -16,11 +20,15 @@
 //! let admin_router = create_admin_router(admin_state);
 //! ```
 
+mod audit;
+mod bundle;
+mod device_login;
 mod error;
 mod handlers;
 mod middleware;
 mod router;
 mod service;
+mod share;
 pub mod types;
 
 pub use middleware::AdminState;
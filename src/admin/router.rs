@@ -7,11 +7,20 @@ use axum::{
 
 use super::{
     handlers::{
-        add_credential, delete_credential, force_refresh_token, get_all_credentials,
-        get_credential_balance, get_load_balancing_mode, reset_failure_count,
-        set_credential_disabled, set_credential_priority, set_load_balancing_mode,
+        add_credential, create_share_link, delete_credential, delete_session_memory,
+        export_credentials, export_usage, force_refresh_token, get_admission, get_all_credentials,
+        get_audit, get_concurrency, get_config_schema, get_credential_balance, get_frame_dump,
+        get_load_balancing_mode, get_malformed_requests, get_model_prices, get_model_routes,
+        get_request_sizes, get_session_memory, get_shadow, get_shared_credentials,
+        get_support_bundle, get_system_prompts, get_unknown_upstream_events,
+        get_unknown_upstream_fields, get_usage, get_validation_report, import_credential_bundle,
+        import_credentials, list_frame_dumps, list_session_memories, list_session_usage,
+        poll_device_login, replay_frames, reset_failure_count, set_credential_disabled,
+        set_credential_group, set_credential_priority, set_credential_schedule,
+        set_credential_weight, set_load_balancing_mode, set_model_routes, set_session_memory,
+        set_system_prompts, start_device_login,
     },
-    middleware::{AdminState, admin_auth_middleware},
+    middleware::{AdminState, admin_auth_middleware, audit_middleware, share_auth_middleware},
 };
 
 /// 创建 Admin API 路由
@@ -19,28 +28,87 @@ use super::{
 /// # 端点
 /// - `GET /credentials` - 获取所有凭据状态
 /// - `POST /credentials` - 添加新凭据
+/// - `POST /credentials/import` - 批量导入凭据（并发验活）
+/// - `POST /credentials/export` - 加密导出全部凭据
+/// - `POST /credentials/import-bundle` - 导入加密凭据包
+/// - `POST /credentials/device-login` - 发起 IdC 设备授权登录
+/// - `POST /credentials/device-login/:session/poll` - 轮询设备授权，完成后添加凭据
+/// - `GET /credentials/validation-report` - 获取凭据验证报告
 /// - `DELETE /credentials/:id` - 删除凭据
 /// - `POST /credentials/:id/disabled` - 设置凭据禁用状态
 /// - `POST /credentials/:id/priority` - 设置凭据优先级
+/// - `POST /credentials/:id/weight` - 设置凭据权重（balanced 模式）
+/// - `POST /credentials/:id/schedule` - 设置凭据可用时段
+/// - `POST /credentials/:id/group` - 设置凭据分组
 /// - `POST /credentials/:id/reset` - 重置失败计数
 /// - `POST /credentials/:id/refresh` - 强制刷新 Token
 /// - `GET /credentials/:id/balance` - 获取凭据余额
 /// - `GET /config/load-balancing` - 获取负载均衡模式
 /// - `PUT /config/load-balancing` - 设置负载均衡模式
+/// - `GET /config/model-routes` - 获取模型路由规则
+/// - `PUT /config/model-routes` - 设置模型路由规则
+/// - `GET /config/system-prompts` - 获取 system 提示注入规则
+/// - `PUT /config/system-prompts` - 设置 system 提示注入规则
+/// - `GET /config/schema` - 获取 config.json 的 JSON Schema
+/// - `GET /sessions/usage` - 列出最近活跃会话的用量与上下文占用
+/// - `GET /sessions/memory` - 列出保存了记忆的会话
+/// - `GET /sessions/:session_id/memory` - 获取会话记忆
+/// - `PUT /sessions/:session_id/memory` - 替换会话的全部笔记
+/// - `DELETE /sessions/:session_id/memory` - 删除会话记忆
+/// - `POST /share-links` - 签发只读分享链接
+/// - `GET /support-bundle` - 下载诊断包（zip）
+/// - `GET /malformed-requests` - 查看最近被上游判定为格式错误的请求
+/// - `GET /request-sizes` - 查看发往上游的请求体积分布
+/// - `GET /shadow` - 查看影子流量计数与最近的响应差异
+/// - `GET /concurrency` - 查看各凭据的并发状态
+/// - `GET /admission` - 查看全局准入控制状态
+/// - `GET /usage` - 按日/按月聚合 token 用量
+/// - `GET /usage/export` - 导出 token 用量（CSV）
+/// - `GET /model-prices` - 查看可用模型的生效价格
+/// - `GET /upstream-fields` - 查看上游事件中出现过的未识别字段
+/// - `GET /upstream-events` - 查看上游出现过的未识别事件类型
+/// - `GET /debug/frames` - 列出最近录制的上游事件流
+/// - `GET /debug/frames/:id` - 导出指定请求的事件流 dump
+/// - `POST /debug/replay` - 用流转换器回放事件流
+/// - `GET /audit` - 分页查询操作审计日志
+/// - `GET /share/credentials?token=...` - 通过分享链接查看凭据可用性
 ///
 /// # 认证
 /// 需要 Admin API Key 认证，支持：
 /// - `x-api-key` header
 /// - `Authorization: Bearer <token>` header
+///
+/// 所有非只读请求与登录结果记录到审计日志
+///
+/// `/share/*` 例外：仅校验分享令牌，不接受也不需要 Admin API Key
 pub fn create_admin_router(state: AdminState) -> Router {
+    let share_router = Router::new()
+        .route("/share/credentials", get(get_shared_credentials))
+        .layer(middleware::from_fn_with_state(
+            state.clone(),
+            share_auth_middleware,
+        ));
+
     Router::new()
         .route(
             "/credentials",
             get(get_all_credentials).post(add_credential),
         )
+        .route("/credentials/import", post(import_credentials))
+        .route("/credentials/export", post(export_credentials))
+        .route("/credentials/import-bundle", post(import_credential_bundle))
+        .route("/credentials/device-login", post(start_device_login))
+        .route(
+            "/credentials/device-login/{session}/poll",
+            post(poll_device_login),
+        )
+        .route("/credentials/validation-report", get(get_validation_report))
         .route("/credentials/{id}", delete(delete_credential))
         .route("/credentials/{id}/disabled", post(set_credential_disabled))
         .route("/credentials/{id}/priority", post(set_credential_priority))
+        .route("/credentials/{id}/weight", post(set_credential_weight))
+        .route("/credentials/{id}/schedule", post(set_credential_schedule))
+        .route("/credentials/{id}/group", post(set_credential_group))
         .route("/credentials/{id}/reset", post(reset_failure_count))
         .route("/credentials/{id}/refresh", post(force_refresh_token))
         .route("/credentials/{id}/balance", get(get_credential_balance))
@@ -48,9 +116,47 @@ pub fn create_admin_router(state: AdminState) -> Router {
             "/config/load-balancing",
             get(get_load_balancing_mode).put(set_load_balancing_mode),
         )
+        .route(
+            "/config/model-routes",
+            get(get_model_routes).put(set_model_routes),
+        )
+        .route(
+            "/config/system-prompts",
+            get(get_system_prompts).put(set_system_prompts),
+        )
+        .route("/config/schema", get(get_config_schema))
+        .route("/sessions/usage", get(list_session_usage))
+        .route("/sessions/memory", get(list_session_memories))
+        .route(
+            "/sessions/{session_id}/memory",
+            get(get_session_memory)
+                .put(set_session_memory)
+                .delete(delete_session_memory),
+        )
+        .route("/share-links", post(create_share_link))
+        .route("/support-bundle", get(get_support_bundle))
+        .route("/malformed-requests", get(get_malformed_requests))
+        .route("/request-sizes", get(get_request_sizes))
+        .route("/shadow", get(get_shadow))
+        .route("/concurrency", get(get_concurrency))
+        .route("/admission", get(get_admission))
+        .route("/usage", get(get_usage))
+        .route("/usage/export", get(export_usage))
+        .route("/model-prices", get(get_model_prices))
+        .route("/upstream-fields", get(get_unknown_upstream_fields))
+        .route("/upstream-events", get(get_unknown_upstream_events))
+        .route("/debug/frames", get(list_frame_dumps))
+        .route("/debug/frames/{id}", get(get_frame_dump))
+        .route("/debug/replay", post(replay_frames))
+        .route("/audit", get(get_audit))
+        .layer(middleware::from_fn_with_state(
+            state.clone(),
+            audit_middleware,
+        ))
         .layer(middleware::from_fn_with_state(
             state.clone(),
             admin_auth_middleware,
         ))
+        .merge(share_router)
         .with_state(state)
 }
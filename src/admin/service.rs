@@ -1,25 +1,73 @@
 //! Admin API 业务逻辑服务
 
-use std::collections::{HashMap, HashSet};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::io::{Cursor, Write};
 use std::path::PathBuf;
 use std::sync::Arc;
+use std::time::{Duration, Instant};
 
 use chrono::Utc;
+use futures::StreamExt;
 use parking_lot::Mutex;
 use serde::{Deserialize, Serialize};
 
+use crate::anthropic::admission::{self, AdmissionReport};
+use crate::anthropic::replay::{self, FrameDump, FrameDumpSummary, ReplayResult};
+use crate::anthropic::session_cost::{self, ModelPriceEntry, SessionUsage};
+use crate::anthropic::session_memory::{
+    self, MemoryError, SessionMemorySummary, SessionMemoryView,
+};
+use crate::anthropic::shadow::{self, ShadowReport};
+use crate::anthropic::system_prompt;
+use crate::anthropic::usage_ledger::{self, UsageQuery, UsageReport};
+use crate::common::log_buffer;
+use crate::http_client::ProxyConfig;
+use crate::kiro::concurrency::{self, ConcurrencyReport};
+use crate::kiro::device_auth::{self, DevicePoll};
+use crate::kiro::malformed::{self, MalformedCapture};
 use crate::kiro::model::credentials::KiroCredentials;
+use crate::kiro::model::events::unknown_events::{self, UnknownEventReport};
+use crate::kiro::model::events::unknown_fields::{self, UnknownFieldsReport};
+use crate::kiro::model_registry;
+use crate::kiro::request_size::{self, RequestSizeStats};
 use crate::kiro::token_manager::MultiTokenManager;
+use crate::kiro::validation::{self, ValidationReport};
 
+use super::audit::{AuditLog, AuditPage, AuditQuery};
+use super::bundle::{self, EncryptedBundle};
+use super::device_login::{DeviceLogins, PendingLogin, PollTicket};
 use super::error::AdminServiceError;
+use super::share::{self, DEFAULT_SHARE_TTL_SECS, MAX_SHARE_TTL_SECS, ShareScope};
 use super::types::{
-    AddCredentialRequest, AddCredentialResponse, BalanceResponse, CredentialStatusItem,
-    CredentialsStatusResponse, LoadBalancingModeResponse, SetLoadBalancingModeRequest,
+    AddCredentialRequest, AddCredentialResponse, BalanceResponse, CreateShareLinkRequest,
+    CredentialStatusItem, CredentialsStatusResponse, DeviceLoginPollResponse, DeviceLoginResponse,
+    ExportCredentialsRequest,
+    ImportCredentialBundleRequest, ImportCredentialResult, ImportCredentialsRequest, ImportCredentialsResponse, LoadBalancingModeResponse, ModelRoutesPayload, ReplayRequest,
+    SessionMemoryPayload, SetLoadBalancingModeRequest, ShareLinkResponse, SharedCredentialItem,
+    SharedCredentialsResponse, StartDeviceLoginRequest, SystemPromptsPayload,
 };
 
 /// 余额缓存过期时间（秒），5 分钟
 const BALANCE_CACHE_TTL_SECS: i64 = 300;
 
+/// 批量导入默认验活并发数
+const DEFAULT_IMPORT_CONCURRENCY: usize = 4;
+
+/// 批量导入最大验活并发数
+const MAX_IMPORT_CONCURRENCY: usize = 16;
+
+/// 诊断包中需要脱敏的配置键后缀（不区分大小写，作用于任意嵌套层级）
+const REDACTED_KEY_SUFFIXES: &[&str] = &[
+    "apikey",
+    "password",
+    "secret",
+    "token",
+    "headers",
+    "webhookurl",
+    "username",
+    "machineid",
+];
+
 /// 缓存的余额条目（含时间戳）
 #[derive(Debug, Clone, Serialize, Deserialize)]
 struct CachedBalance {
@@ -38,6 +86,10 @@ pub struct AdminService {
     cache_path: Option<PathBuf>,
     /// 已注册的端点名称集合（用于 add_credential 校验）
     known_endpoints: HashSet<String>,
+    /// 操作审计日志
+    audit: AuditLog,
+    /// 进行中的 IdC 设备授权登录
+    device_logins: DeviceLogins,
 }
 
 impl AdminService {
@@ -50,15 +102,32 @@ impl AdminService {
             .map(|d| d.join("kiro_balance_cache.json"));
 
         let balance_cache = Self::load_balance_cache_from(&cache_path);
+        let audit = AuditLog::new(
+            token_manager
+                .cache_dir()
+                .map(|d| d.join("kiro_audit.jsonl")),
+        );
 
         Self {
             token_manager,
             balance_cache: Mutex::new(balance_cache),
             cache_path,
             known_endpoints: known_endpoints.into_iter().collect(),
+            audit,
+            device_logins: DeviceLogins::default(),
         }
     }
 
+    /// 操作审计日志
+    pub fn audit(&self) -> &AuditLog {
+        &self.audit
+    }
+
+    /// 按条件分页查询审计日志
+    pub fn get_audit(&self, query: &AuditQuery) -> AuditPage {
+        self.audit.query(query)
+    }
+
     /// 获取所有凭据状态
     pub fn get_all_credentials(&self) -> CredentialsStatusResponse {
         let snapshot = self.token_manager.snapshot();
@@ -84,9 +153,18 @@ impl AdminService {
                 last_used_at: entry.last_used_at.clone(),
                 has_proxy: entry.has_proxy,
                 proxy_url: entry.proxy_url,
+                proxy_status: entry.proxy_status,
                 refresh_failure_count: entry.refresh_failure_count,
                 disabled_reason: entry.disabled_reason,
                 endpoint: entry.endpoint.unwrap_or_else(|| default_endpoint.clone()),
+                schedule: entry.schedule,
+                in_schedule: entry.in_schedule,
+                group: entry.group,
+                weight: entry.weight,
+                circuit_breaker: entry.circuit_breaker,
+                cooldown_until: entry.cooldown_until,
+                pinned_sessions: entry.pinned_sessions,
+                last_refresh: entry.last_refresh,
             })
             .collect();
 
@@ -101,6 +179,225 @@ impl AdminService {
         }
     }
 
+    /// 获取最近被上游判定为格式错误的请求（已脱敏，新的在前）
+    pub fn get_malformed_requests(&self) -> Vec<MalformedCapture> {
+        malformed::recent_captures()
+    }
+
+    /// 可用模型的生效价格（用于费用估算）
+    pub fn get_model_prices(&self) -> Vec<ModelPriceEntry> {
+        session_cost::price_table(model_registry::list_models().into_iter().map(|m| m.id))
+    }
+
+    /// 影子流量计数与最近的响应差异
+    pub fn get_shadow_report(&self) -> ShadowReport {
+        shadow::report()
+    }
+
+    /// 上游事件中出现过的未识别字段
+    pub fn get_unknown_upstream_fields(&self) -> Vec<UnknownFieldsReport> {
+        unknown_fields::report()
+    }
+
+    /// 上游出现过的未识别事件类型
+    pub fn get_unknown_upstream_events(&self) -> Vec<UnknownEventReport> {
+        unknown_events::report()
+    }
+
+    /// 获取 config.json 的 JSON Schema
+    pub fn get_config_schema(&self) -> serde_json::Value {
+        crate::model::config_schema::config_schema()
+    }
+
+    /// 获取发往上游的请求体积分布
+    pub fn get_request_sizes(&self) -> RequestSizeStats {
+        request_size::stats()
+    }
+
+    /// 获取各凭据的并发状态
+    pub fn get_concurrency(&self) -> ConcurrencyReport {
+        concurrency::report()
+    }
+
+    /// 获取全局准入控制状态
+    pub fn get_admission(&self) -> AdmissionReport {
+        admission::report()
+    }
+
+    /// 按日/按月聚合 token 用量
+    pub fn get_usage(&self, query: &UsageQuery) -> UsageReport {
+        usage_ledger::query(query)
+    }
+
+    /// 导出聚合后的 token 用量（CSV）
+    pub fn export_usage(&self, query: &UsageQuery) -> Result<String, AdminServiceError> {
+        if !usage_ledger::is_enabled() {
+            return Err(AdminServiceError::InvalidRequest(
+                "未配置 usageLedger".into(),
+            ));
+        }
+        Ok(usage_ledger::to_csv(&usage_ledger::query(query).rows))
+    }
+
+    /// 列出最近录制的上游事件流
+    pub fn list_frame_dumps(&self) -> Vec<FrameDumpSummary> {
+        replay::list_dumps()
+    }
+
+    /// 导出指定录制
+    pub fn get_frame_dump(&self, id: &str) -> Result<FrameDump, AdminServiceError> {
+        replay::get_dump(id)
+            .ok_or_else(|| AdminServiceError::ResourceNotFound(format!("录制 {}", id)))
+    }
+
+    /// 用流转换器回放录制的事件流
+    pub fn replay_frames(&self, req: ReplayRequest) -> Result<ReplayResult, AdminServiceError> {
+        let dump = match (req.capture_id, req.dump) {
+            (_, Some(dump)) => dump,
+            (Some(id), None) => self.get_frame_dump(&id)?,
+            (None, None) => {
+                return Err(AdminServiceError::InvalidRequest(
+                    "需要提供 captureId 或 dump".to_string(),
+                ));
+            }
+        };
+        replay::replay(&dump).map_err(AdminServiceError::InvalidRequest)
+    }
+
+    /// 获取凭据可用性（分享视图，已脱敏）
+    pub fn get_shared_credentials(&self) -> SharedCredentialsResponse {
+        let status = self.get_all_credentials();
+        SharedCredentialsResponse {
+            total: status.total,
+            available: status.available,
+            credentials: status
+                .credentials
+                .into_iter()
+                .map(|c| SharedCredentialItem {
+                    id: c.id,
+                    priority: c.priority,
+                    disabled: c.disabled,
+                    disabled_reason: c.disabled_reason,
+                    is_current: c.is_current,
+                    failure_count: c.failure_count,
+                    success_count: c.success_count,
+                    last_used_at: c.last_used_at,
+                })
+                .collect(),
+        }
+    }
+
+    /// 签发只读分享链接
+    pub fn create_share_link(
+        &self,
+        secret: &str,
+        req: CreateShareLinkRequest,
+    ) -> Result<ShareLinkResponse, AdminServiceError> {
+        let scope = ShareScope::parse(&req.scope).ok_or_else(|| {
+            AdminServiceError::InvalidRequest(format!(
+                "不支持的分享视图: {}（可选: credentials）",
+                req.scope
+            ))
+        })?;
+
+        let ttl = req.ttl_secs.unwrap_or(DEFAULT_SHARE_TTL_SECS);
+        if ttl <= 0 || ttl > MAX_SHARE_TTL_SECS {
+            return Err(AdminServiceError::InvalidRequest(format!(
+                "ttlSecs 必须在 1 到 {} 之间",
+                MAX_SHARE_TTL_SECS
+            )));
+        }
+
+        let expires_at = Utc::now() + chrono::Duration::seconds(ttl);
+        let token = share::issue_token(secret, scope, expires_at.timestamp());
+
+        Ok(ShareLinkResponse {
+            path: format!(
+                "/api/admin/share/{}?token={}",
+                scope.as_str(),
+                urlencoding::encode(&token)
+            ),
+            token,
+            scope: scope.as_str().to_string(),
+            expires_at: expires_at.to_rfc3339(),
+        })
+    }
+
+    /// 生成诊断包（zip）
+    ///
+    /// 包含脱敏后的配置、版本信息、最近日志和凭据诊断计数，
+    /// 不包含任何 Token、API Key、邮箱或代理认证信息
+    pub fn build_support_bundle(&self) -> Result<Vec<u8>, AdminServiceError> {
+        let version = serde_json::json!({
+            "name": env!("CARGO_PKG_NAME"),
+            "version": env!("CARGO_PKG_VERSION"),
+            "os": std::env::consts::OS,
+            "arch": std::env::consts::ARCH,
+            "generatedAt": Utc::now().to_rfc3339(),
+        });
+
+        let snapshot = self.token_manager.snapshot();
+        let diagnostics = serde_json::json!({
+            "total": snapshot.total,
+            "available": snapshot.available,
+            "currentId": snapshot.current_id,
+            "loadBalancingMode": self.token_manager.get_load_balancing_mode(),
+            "credentials": snapshot.entries.iter().map(|e| serde_json::json!({
+                "id": e.id,
+                "priority": e.priority,
+                "disabled": e.disabled,
+                "disabledReason": e.disabled_reason,
+                "authMethod": e.auth_method,
+                "endpoint": e.endpoint,
+                "group": e.group,
+                "weight": e.weight,
+                "hasProfileArn": e.has_profile_arn,
+                "hasProxy": e.has_proxy,
+                "expiresAt": e.expires_at,
+                "failureCount": e.failure_count,
+                "refreshFailureCount": e.refresh_failure_count,
+                "successCount": e.success_count,
+                "lastUsedAt": e.last_used_at,
+            })).collect::<Vec<_>>(),
+            "unknownUpstreamFields": unknown_fields::report(),
+            "unknownUpstreamEvents": unknown_events::report(),
+        });
+
+        let logs = log_buffer::recent_lines().join("\n");
+
+        let to_json = |v: &serde_json::Value| {
+            serde_json::to_vec_pretty(v)
+                .map_err(|e| AdminServiceError::InternalError(format!("序列化失败: {}", e)))
+        };
+        let files: Vec<(&str, Vec<u8>)> = vec![
+            ("version.json", to_json(&version)?),
+            ("config.json", to_json(&self.redacted_config())?),
+            ("diagnostics.json", to_json(&diagnostics)?),
+            ("recent.log", logs.into_bytes()),
+        ];
+
+        let mut zip = zip::ZipWriter::new(Cursor::new(Vec::new()));
+        let options = zip::write::SimpleFileOptions::default();
+        for (name, content) in files {
+            zip.start_file(name, options)
+                .and_then(|_| zip.write_all(&content).map_err(Into::into))
+                .map_err(|e| AdminServiceError::InternalError(format!("写入诊断包失败: {}", e)))?;
+        }
+        let cursor = zip
+            .finish()
+            .map_err(|e| AdminServiceError::InternalError(format!("写入诊断包失败: {}", e)))?;
+
+        Ok(cursor.into_inner())
+    }
+
+    /// 脱敏后的配置（见 [`redact_config`]）
+    fn redacted_config(&self) -> serde_json::Value {
+        let mut value = serde_json::to_value(self.token_manager.config())
+            .unwrap_or_else(|_| serde_json::json!({}));
+        redact_config(&mut value);
+        value
+    }
+
     /// 设置凭据禁用状态
     pub fn set_disabled(&self, id: u64, disabled: bool) -> Result<(), AdminServiceError> {
         // 先获取当前凭据 ID，用于判断是否需要切换
@@ -125,6 +422,42 @@ impl AdminService {
             .map_err(|e| self.classify_error(e, id))
     }
 
+    /// 设置凭据可用时段
+    pub fn set_schedule(&self, id: u64, schedule: Vec<String>) -> Result<(), AdminServiceError> {
+        self.token_manager
+            .set_schedule(id, schedule)
+            .map_err(|e| {
+                if e.to_string().contains("可用时段无效") {
+                    AdminServiceError::InvalidRequest(e.to_string())
+                } else {
+                    self.classify_error(e, id)
+                }
+            })
+    }
+
+    /// 设置凭据在 balanced 模式下的权重
+    pub fn set_weight(&self, id: u64, weight: u32) -> Result<(), AdminServiceError> {
+        if weight == 0 {
+            return Err(AdminServiceError::InvalidRequest(
+                "weight 必须大于 0".to_string(),
+            ));
+        }
+        self.token_manager
+            .set_weight(id, weight)
+            .map_err(|e| self.classify_error(e, id))
+    }
+
+    /// 设置凭据分组，返回生效的分组名（空白分组名视为取消分组）
+    pub fn set_group(
+        &self,
+        id: u64,
+        group: Option<String>,
+    ) -> Result<Option<String>, AdminServiceError> {
+        self.token_manager
+            .set_group(id, group)
+            .map_err(|e| self.classify_error(e, id))
+    }
+
     /// 重置失败计数并重新启用
     pub fn reset_and_enable(&self, id: u64) -> Result<(), AdminServiceError> {
         self.token_manager
@@ -132,6 +465,14 @@ impl AdminService {
             .map_err(|e| self.classify_error(e, id))
     }
 
+    /// 获取凭据验证报告（`refresh` 为 true 或尚无报告时重新验证所有凭据）
+    pub async fn get_validation_report(&self, refresh: bool) -> ValidationReport {
+        match validation::latest() {
+            Some(report) if !refresh => report,
+            _ => validation::run(&self.token_manager).await,
+        }
+    }
+
     /// 获取凭据余额（带缓存）
     pub async fn get_balance(&self, id: u64) -> Result<BalanceResponse, AdminServiceError> {
         // 先查缓存
@@ -198,8 +539,384 @@ impl AdminService {
         &self,
         req: AddCredentialRequest,
     ) -> Result<AddCredentialResponse, AdminServiceError> {
-        // 校验端点名：未指定则默认合法，指定则必须已注册
-        if let Some(ref name) = req.endpoint {
+        let email = req.email.clone();
+        let credential_id = self.insert_credential(req).await?;
+
+        // 主动获取订阅等级，避免首次请求时 Free 账号绕过 Opus 模型过滤
+        if let Err(e) = self.token_manager.get_usage_limits_for(credential_id).await {
+            tracing::warn!("添加凭据后获取订阅等级失败（不影响凭据添加）: {}", e);
+        }
+
+        Ok(AddCredentialResponse {
+            success: true,
+            message: format!("凭据添加成功，ID: {}", credential_id),
+            credential_id,
+            email,
+        })
+    }
+
+    /// 发起 IdC 设备授权登录
+    ///
+    /// 注册 OIDC 客户端并获取 user code，用户在验证页面确认后通过
+    /// [`Self::poll_device_login`] 完成凭据添加
+    pub async fn start_device_login(
+        &self,
+        req: StartDeviceLoginRequest,
+    ) -> Result<DeviceLoginResponse, AdminServiceError> {
+        self.check_endpoint(req.endpoint.as_deref())?;
+
+        let config = self.token_manager.config();
+        let region = req
+            .region
+            .clone()
+            .unwrap_or_else(|| config.effective_auth_region().to_string());
+        let start_url = req
+            .start_url
+            .clone()
+            .unwrap_or_else(|| device_auth::BUILDER_ID_START_URL.to_string());
+        let proxy = self.device_login_proxy(&req);
+
+        let authorization = device_auth::start(config, &region, &start_url, proxy.as_ref())
+            .await
+            .map_err(|e| AdminServiceError::UpstreamError(e.to_string()))?;
+
+        let now = Instant::now();
+        let response = DeviceLoginResponse {
+            session_id: String::new(),
+            user_code: authorization.user_code.clone(),
+            verification_uri: authorization.verification_uri.clone(),
+            verification_uri_complete: authorization.verification_uri_complete.clone(),
+            expires_in: authorization.expires_in,
+            interval: authorization.interval,
+        };
+        let login = PendingLogin {
+            region,
+            expires_at: now + Duration::from_secs(authorization.expires_in.max(0) as u64),
+            next_poll_at: now,
+            authorization,
+            request: req,
+        };
+        let session_id = self.device_logins.insert(login, now).ok_or_else(|| {
+            AdminServiceError::InvalidRequest("进行中的设备登录过多，请稍后再试".to_string())
+        })?;
+
+        tracing::info!("已发起 IdC 设备授权登录（会话 {}）", session_id);
+        Ok(DeviceLoginResponse {
+            session_id,
+            ..response
+        })
+    }
+
+    /// 轮询 IdC 设备授权登录，用户确认授权后以 IdC 凭据添加
+    ///
+    /// 未到轮询间隔时直接返回 pending，不请求上游
+    pub async fn poll_device_login(
+        &self,
+        session_id: &str,
+    ) -> Result<DeviceLoginPollResponse, AdminServiceError> {
+        let now = Instant::now();
+        let login = match self.device_logins.ticket(session_id, now) {
+            PollTicket::NotFound => {
+                return Err(AdminServiceError::ResourceNotFound(format!(
+                    "设备登录会话 {}",
+                    session_id
+                )));
+            }
+            PollTicket::Expired => {
+                return Err(AdminServiceError::InvalidRequest(
+                    "设备码已过期，请重新发起登录".to_string(),
+                ));
+            }
+            PollTicket::Wait(secs) => return Ok(DeviceLoginPollResponse::pending(secs)),
+            PollTicket::Ready(login) => login,
+        };
+
+        let config = self.token_manager.config();
+        let proxy = self.device_login_proxy(&login.request);
+        let poll = device_auth::poll(config, &login.region, &login.authorization, proxy.as_ref())
+            .await
+            .map_err(|e| AdminServiceError::UpstreamError(e.to_string()))?;
+
+        let token = match poll {
+            DevicePoll::Pending => {
+                let wait = self.device_logins.defer(session_id, false, now);
+                return Ok(DeviceLoginPollResponse::pending(wait));
+            }
+            DevicePoll::SlowDown => {
+                let wait = self.device_logins.defer(session_id, true, now);
+                return Ok(DeviceLoginPollResponse::pending(wait));
+            }
+            DevicePoll::Failed(message) => {
+                self.device_logins.remove(session_id);
+                return Err(AdminServiceError::InvalidCredential(message));
+            }
+            DevicePoll::Complete(token) => token,
+        };
+        self.device_logins.remove(session_id);
+
+        let PendingLogin {
+            region,
+            authorization,
+            request,
+            ..
+        } = *login;
+        let credential = self
+            .add_credential(AddCredentialRequest {
+                refresh_token: Some(token.refresh_token),
+                auth_method: "idc".to_string(),
+                client_id: Some(authorization.client_id),
+                client_secret: Some(authorization.client_secret),
+                priority: Some(request.priority),
+                region: Some(region),
+                auth_region: None,
+                api_region: None,
+                machine_id: None,
+                email: None,
+                proxy_url: request.proxy_url,
+                proxy_username: request.proxy_username,
+                proxy_password: request.proxy_password,
+                fallback_proxy_urls: request.fallback_proxy_urls,
+                kiro_api_key: None,
+                endpoint: request.endpoint,
+                schedule: Vec::new(),
+                group: request.group,
+                weight: None,
+                client_profile: None,
+            })
+            .await?;
+
+        tracing::info!(
+            "IdC 设备授权登录完成（会话 {}），已添加凭据 #{}",
+            session_id,
+            credential.credential_id
+        );
+        Ok(DeviceLoginPollResponse::completed(credential))
+    }
+
+    /// 设备授权请求使用的代理：与授权完成后添加的凭据一致
+    fn device_login_proxy(&self, req: &StartDeviceLoginRequest) -> Option<ProxyConfig> {
+        let credentials = KiroCredentials {
+            proxy_url: req.proxy_url.clone(),
+            proxy_username: req.proxy_username.clone(),
+            proxy_password: req.proxy_password.clone(),
+            fallback_proxy_urls: req.fallback_proxy_urls.clone(),
+            ..Default::default()
+        };
+        credentials.effective_proxy(self.token_manager.global_proxy().as_ref())
+    }
+
+    /// 批量导入凭据
+    ///
+    /// 以有限并发逐个添加并验活（获取使用额度），按订阅等级设置初始优先级，
+    /// 验活失败的凭据默认回滚（禁用并删除），最后返回汇总报告
+    pub async fn import_credentials(
+        &self,
+        req: ImportCredentialsRequest,
+    ) -> Result<ImportCredentialsResponse, AdminServiceError> {
+        if req.credentials.is_empty() {
+            return Err(AdminServiceError::InvalidRequest(
+                "credentials 不能为空".to_string(),
+            ));
+        }
+        let concurrency = req
+            .concurrency
+            .unwrap_or(DEFAULT_IMPORT_CONCURRENCY)
+            .clamp(1, MAX_IMPORT_CONCURRENCY);
+        let total = req.credentials.len();
+        let priority_by_tier = req.priority_by_tier;
+        let rollback_on_failure = req.rollback_on_failure;
+
+        // 批次内去重（与已有凭据的重复由 token_manager 检测）
+        let mut seen = HashSet::new();
+        let mut results = Vec::with_capacity(total);
+        let mut pending = Vec::new();
+        for (index, cred) in req.credentials.into_iter().enumerate() {
+            let secret = cred
+                .kiro_api_key
+                .as_deref()
+                .or(cred.refresh_token.as_deref())
+                .map(|s| s.trim().to_string())
+                .unwrap_or_default();
+            if !secret.is_empty() && !seen.insert(secret) {
+                results.push(ImportCredentialResult::duplicate(
+                    index,
+                    "与批次内前面的凭据重复",
+                ));
+            } else {
+                pending.push((index, cred));
+            }
+        }
+
+        tracing::info!(
+            "开始批量导入 {} 个凭据（并发 {}，批次内重复 {}）",
+            total,
+            concurrency,
+            results.len()
+        );
+
+        let imported: Vec<ImportCredentialResult> = futures::stream::iter(pending)
+            .map(|(index, cred)| {
+                self.import_one(index, cred, priority_by_tier, rollback_on_failure)
+            })
+            .buffer_unordered(concurrency)
+            .collect()
+            .await;
+        results.extend(imported);
+        results.sort_by_key(|r| r.index);
+
+        let mut by_subscription = BTreeMap::new();
+        for r in results.iter().filter(|r| r.status == "verified") {
+            let title = r
+                .subscription_title
+                .clone()
+                .unwrap_or_else(|| "UNKNOWN".to_string());
+            *by_subscription.entry(title).or_insert(0) += 1;
+        }
+        let count = |status: &str| results.iter().filter(|r| r.status == status).count();
+        let response = ImportCredentialsResponse {
+            total,
+            verified: count("verified"),
+            duplicate: count("duplicate"),
+            failed: count("failed"),
+            by_subscription,
+            results,
+        };
+
+        tracing::info!(
+            "批量导入完成：成功 {}，重复 {}，失败 {}",
+            response.verified,
+            response.duplicate,
+            response.failed
+        );
+        Ok(response)
+    }
+
+    /// 加密导出全部凭据
+    ///
+    /// PBKDF2 密钥派生耗时较长，在阻塞线程池中执行，避免占用 Tokio worker
+    pub async fn export_credentials(
+        &self,
+        req: ExportCredentialsRequest,
+    ) -> Result<EncryptedBundle, AdminServiceError> {
+        let credentials = self.token_manager.export_credentials();
+        let bundle =
+            tokio::task::spawn_blocking(move || bundle::seal(&credentials, &req.passphrase))
+                .await
+                .map_err(|e| AdminServiceError::InternalError(format!("加密凭据失败: {}", e)))?
+                .map_err(AdminServiceError::InvalidRequest)?;
+        tracing::info!("已导出 {} 个凭据（加密凭据包）", bundle.count);
+        Ok(bundle)
+    }
+
+    /// 导入加密凭据包
+    ///
+    /// 解密后走批量导入流程（验活、去重、失败回滚），保留原优先级，
+    /// 导出时处于禁用状态的凭据导入后同样禁用
+    pub async fn import_credential_bundle(
+        &self,
+        req: ImportCredentialBundleRequest,
+    ) -> Result<ImportCredentialsResponse, AdminServiceError> {
+        let (encrypted, passphrase) = (req.bundle, req.passphrase);
+        let credentials =
+            tokio::task::spawn_blocking(move || bundle::open(&encrypted, &passphrase))
+                .await
+                .map_err(|e| AdminServiceError::InternalError(format!("解密凭据包失败: {}", e)))?
+                .map_err(AdminServiceError::InvalidRequest)?;
+        let disabled: Vec<bool> = credentials.iter().map(|c| c.disabled).collect();
+
+        let response = self
+            .import_credentials(ImportCredentialsRequest {
+                credentials: credentials.into_iter().map(add_request_from).collect(),
+                concurrency: req.concurrency,
+                priority_by_tier: false,
+                rollback_on_failure: req.rollback_on_failure,
+            })
+            .await?;
+
+        for result in response.results.iter().filter(|r| r.status == "verified") {
+            if let (true, Some(id)) = (disabled[result.index], result.credential_id)
+                && let Err(e) = self.set_disabled(id, true)
+            {
+                tracing::warn!("恢复凭据 #{} 的禁用状态失败: {}", id, e);
+            }
+        }
+        Ok(response)
+    }
+
+    /// 导入并验活单个凭据
+    async fn import_one(
+        &self,
+        index: usize,
+        req: AddCredentialRequest,
+        priority_by_tier: bool,
+        rollback_on_failure: bool,
+    ) -> ImportCredentialResult {
+        let explicit_priority = req.priority;
+        let id = match self.insert_credential(req).await {
+            Ok(id) => id,
+            Err(e) => {
+                let message = e.to_string();
+                return if message.contains("凭据已存在") {
+                    ImportCredentialResult::duplicate(index, message)
+                } else {
+                    ImportCredentialResult::failed(index, None, message, None)
+                };
+            }
+        };
+
+        // 验活：获取使用额度（同时写入余额缓存）
+        let balance = match self.get_balance(id).await {
+            Ok(balance) => balance,
+            Err(e) => {
+                let rolled_back = rollback_on_failure.then(|| self.rollback_credential(id));
+                let credential_id = (rolled_back != Some(true)).then_some(id);
+                return ImportCredentialResult::failed(
+                    index,
+                    credential_id,
+                    e.to_string(),
+                    rolled_back,
+                );
+            }
+        };
+
+        let mut priority = explicit_priority.unwrap_or_default();
+        if let Some(tier) = initial_tier_priority(
+            explicit_priority,
+            priority_by_tier,
+            balance.subscription_title.as_deref(),
+        ) {
+            match self.set_priority(id, tier) {
+                Ok(()) => priority = tier,
+                Err(e) => tracing::warn!("凭据 #{} 设置初始优先级失败: {}", id, e),
+            }
+        }
+
+        ImportCredentialResult {
+            index,
+            status: "verified".to_string(),
+            credential_id: Some(id),
+            subscription_title: balance.subscription_title,
+            priority: Some(priority),
+            remaining: Some(balance.remaining),
+            error: None,
+            rolled_back: None,
+        }
+    }
+
+    /// 回滚导入失败的凭据（先禁用再删除），返回是否成功
+    fn rollback_credential(&self, id: u64) -> bool {
+        let result = self
+            .set_disabled(id, true)
+            .and_then(|_| self.delete_credential(id));
+        if let Err(e) = &result {
+            tracing::warn!("回滚导入失败的凭据 #{} 失败: {}", id, e);
+        }
+        result.is_ok()
+    }
+
+    /// 校验端点名：未指定则默认合法，指定则必须已注册
+    fn check_endpoint(&self, endpoint: Option<&str>) -> Result<(), AdminServiceError> {
+        if let Some(name) = endpoint {
             if !self.known_endpoints.contains(name) {
                 let mut known: Vec<&str> =
                     self.known_endpoints.iter().map(|s| s.as_str()).collect();
@@ -210,9 +927,19 @@ impl AdminService {
                 )));
             }
         }
+        Ok(())
+    }
+
+    /// 校验并添加凭据，返回新凭据 ID
+    async fn insert_credential(&self, req: AddCredentialRequest) -> Result<u64, AdminServiceError> {
+        self.check_endpoint(req.endpoint.as_deref())?;
+        if req.weight == Some(0) {
+            return Err(AdminServiceError::InvalidRequest(
+                "weight 必须大于 0".to_string(),
+            ));
+        }
 
         // 构建凭据对象
-        let email = req.email.clone();
         let new_cred = KiroCredentials {
             id: None,
             access_token: None,
@@ -222,7 +949,7 @@ impl AdminService {
             auth_method: Some(req.auth_method),
             client_id: req.client_id,
             client_secret: req.client_secret,
-            priority: req.priority,
+            priority: req.priority.unwrap_or_default(),
             region: req.region,
             auth_region: req.auth_region,
             api_region: req.api_region,
@@ -232,29 +959,21 @@ impl AdminService {
             proxy_url: req.proxy_url,
             proxy_username: req.proxy_username,
             proxy_password: req.proxy_password,
+            fallback_proxy_urls: req.fallback_proxy_urls,
             disabled: false, // 新添加的凭据默认启用
             kiro_api_key: req.kiro_api_key,
             endpoint: req.endpoint,
+            schedule: req.schedule,
+            group: req.group,
+            weight: req.weight.filter(|&w| w != 1),
+            client_profile: req.client_profile,
         };
 
         // 调用 token_manager 添加凭据
-        let credential_id = self
-            .token_manager
+        self.token_manager
             .add_credential(new_cred)
             .await
-            .map_err(|e| self.classify_add_error(e))?;
-
-        // 主动获取订阅等级，避免首次请求时 Free 账号绕过 Opus 模型过滤
-        if let Err(e) = self.token_manager.get_usage_limits_for(credential_id).await {
-            tracing::warn!("添加凭据后获取订阅等级失败（不影响凭据添加）: {}", e);
-        }
-
-        Ok(AddCredentialResponse {
-            success: true,
-            message: format!("凭据添加成功，ID: {}", credential_id),
-            credential_id,
-            email,
-        })
+            .map_err(|e| self.classify_add_error(e))
     }
 
     /// 删除凭据
@@ -299,6 +1018,95 @@ impl AdminService {
         Ok(LoadBalancingModeResponse { mode: req.mode })
     }
 
+    /// 获取模型路由规则
+    pub fn get_model_routes(&self) -> ModelRoutesPayload {
+        ModelRoutesPayload {
+            routes: self.token_manager.get_model_routes(),
+        }
+    }
+
+    /// 设置模型路由规则
+    pub fn set_model_routes(
+        &self,
+        req: ModelRoutesPayload,
+    ) -> Result<ModelRoutesPayload, AdminServiceError> {
+        if let Some(index) = req.routes.iter().position(|r| r.groups.is_empty()) {
+            return Err(AdminServiceError::InvalidRequest(format!(
+                "第 {} 条规则的 groups 不能为空",
+                index + 1
+            )));
+        }
+
+        self.token_manager
+            .set_model_routes(req.routes)
+            .map_err(|e| AdminServiceError::InternalError(e.to_string()))?;
+
+        Ok(self.get_model_routes())
+    }
+
+    /// 获取 system 提示注入规则
+    pub fn get_system_prompts(&self) -> SystemPromptsPayload {
+        SystemPromptsPayload {
+            rules: system_prompt::rules(),
+        }
+    }
+
+    /// 设置 system 提示注入规则
+    pub fn set_system_prompts(
+        &self,
+        req: SystemPromptsPayload,
+    ) -> Result<SystemPromptsPayload, AdminServiceError> {
+        if let Some(index) = req
+            .rules
+            .iter()
+            .position(|r| r.prefix.is_none() && r.suffix.is_none())
+        {
+            return Err(AdminServiceError::InvalidRequest(format!(
+                "第 {} 条规则的 prefix 与 suffix 不能都为空",
+                index + 1
+            )));
+        }
+
+        system_prompt::set_rules(req.rules, self.token_manager.config().config_path())
+            .map_err(|e| AdminServiceError::InternalError(e.to_string()))?;
+
+        Ok(self.get_system_prompts())
+    }
+
+    /// 列出最近活跃会话的用量与上下文占用
+    pub fn list_session_usage(&self) -> Vec<SessionUsage> {
+        session_cost::list_usage()
+    }
+
+    // ============ 会话记忆 ============
+
+    /// 列出保存了记忆的会话
+    pub fn list_session_memories(&self) -> Result<Vec<SessionMemorySummary>, AdminServiceError> {
+        session_memory::list().map_err(memory_error)
+    }
+
+    /// 获取会话记忆
+    pub fn get_session_memory(
+        &self,
+        session_id: &str,
+    ) -> Result<SessionMemoryView, AdminServiceError> {
+        session_memory::get(session_id).map_err(memory_error)
+    }
+
+    /// 替换会话的全部笔记
+    pub fn set_session_memory(
+        &self,
+        session_id: &str,
+        req: SessionMemoryPayload,
+    ) -> Result<SessionMemoryView, AdminServiceError> {
+        session_memory::replace(session_id, req.notes).map_err(memory_error)
+    }
+
+    /// 删除会话记忆
+    pub fn delete_session_memory(&self, session_id: &str) -> Result<(), AdminServiceError> {
+        session_memory::delete(session_id).map_err(memory_error)
+    }
+
     /// 强制刷新指定凭据的 Token
     pub async fn force_refresh_token(&self, id: u64) -> Result<(), AdminServiceError> {
         self.token_manager
@@ -429,7 +1237,8 @@ impl AdminService {
             || msg.contains("kiroApiKey 为空")
             || msg.contains("凭证已过期或无效")
             || msg.contains("权限不足")
-            || msg.contains("已被限流");
+            || msg.contains("已被限流")
+            || msg.contains("可用时段无效");
 
         if is_invalid_credential {
             AdminServiceError::InvalidCredential(msg)
@@ -455,3 +1264,188 @@ impl AdminService {
         }
     }
 }
+
+fn memory_error(e: MemoryError) -> AdminServiceError {
+    match e {
+        MemoryError::Disabled => AdminServiceError::InvalidRequest("未配置 sessionMemory".into()),
+        MemoryError::NotFound(msg) => AdminServiceError::ResourceNotFound(msg),
+        MemoryError::Invalid(msg) => AdminServiceError::InvalidRequest(msg),
+        MemoryError::Persist(_) => AdminServiceError::InternalError(e.to_string()),
+    }
+}
+
+/// 把导出的凭据转换为添加请求（Token 与订阅信息在导入验活时重新获取）
+fn add_request_from(cred: KiroCredentials) -> AddCredentialRequest {
+    AddCredentialRequest {
+        refresh_token: cred.refresh_token,
+        auth_method: cred.auth_method.unwrap_or_else(|| "social".to_string()),
+        client_id: cred.client_id,
+        client_secret: cred.client_secret,
+        priority: Some(cred.priority),
+        region: cred.region,
+        auth_region: cred.auth_region,
+        api_region: cred.api_region,
+        machine_id: cred.machine_id,
+        email: cred.email,
+        proxy_url: cred.proxy_url,
+        proxy_username: cred.proxy_username,
+        proxy_password: cred.proxy_password,
+        fallback_proxy_urls: cred.fallback_proxy_urls,
+        kiro_api_key: cred.kiro_api_key,
+        endpoint: cred.endpoint,
+        schedule: cred.schedule,
+        group: cred.group,
+        weight: cred.weight,
+        client_profile: cred.client_profile,
+    }
+}
+
+/// 递归脱敏配置 JSON
+///
+/// - 键名匹配 [`REDACTED_KEY_SUFFIXES`] 时，字符串值替换为 "***"；对象和数组保留结构
+///   （如请求头名），其中的值全部替换为 "***"；Webhook URL 的路径中通常嵌有密钥，只保留协议和主机
+/// - 其他以 `url` 结尾的键移除 URL 中的认证信息（`user:pass@`）
+fn redact_config(value: &mut serde_json::Value) {
+    match value {
+        serde_json::Value::Object(obj) => {
+            for (key, v) in obj.iter_mut() {
+                let key = key.to_ascii_lowercase();
+                if REDACTED_KEY_SUFFIXES.iter().any(|s| key.ends_with(s)) {
+                    match v {
+                        serde_json::Value::String(url) if key.ends_with("webhookurl") => {
+                            *url = mask_url_path(url);
+                        }
+                        _ => mask_values(v),
+                    }
+                } else if key.ends_with("url") {
+                    if let serde_json::Value::String(url) = v
+                        && let Some((scheme, rest)) = url.split_once("://")
+                        && let Some((_, host)) = rest.rsplit_once('@')
+                    {
+                        *url = format!("{}://***@{}", scheme, host);
+                    }
+                } else {
+                    redact_config(v);
+                }
+            }
+        }
+        serde_json::Value::Array(items) => items.iter_mut().for_each(redact_config),
+        _ => {}
+    }
+}
+
+/// 将所有非空值替换为 "***"（保留对象键名与数组结构）
+fn mask_values(value: &mut serde_json::Value) {
+    match value {
+        serde_json::Value::Null => {}
+        serde_json::Value::Object(obj) => obj.values_mut().for_each(mask_values),
+        serde_json::Value::Array(items) => items.iter_mut().for_each(mask_values),
+        _ => *value = serde_json::Value::String("***".to_string()),
+    }
+}
+
+/// 只保留 URL 的协议和主机（`https://hooks.example.com/***`）
+fn mask_url_path(url: &str) -> String {
+    match url.split_once("://") {
+        Some((scheme, rest)) => {
+            let host = rest.split(['/', '?', '#']).next().unwrap_or_default();
+            let host = host.rsplit_once('@').map_or(host, |(_, h)| h);
+            format!("{}://{}/***", scheme, host)
+        }
+        None => "***".to_string(),
+    }
+}
+
+/// 批量导入时按订阅等级设置的初始优先级
+///
+/// 仅对未指定优先级的凭据生效：显式指定的优先级（包括 0）保持不变，返回 None
+fn initial_tier_priority(
+    explicit_priority: Option<u32>,
+    priority_by_tier: bool,
+    subscription_title: Option<&str>,
+) -> Option<u32> {
+    (priority_by_tier && explicit_priority.is_none()).then(|| tier_priority(subscription_title))
+}
+
+/// 按订阅等级映射初始优先级（数字越小越优先）
+///
+/// 付费等级越高越优先使用，FREE 放在最后；无法识别的等级排在 PRO 与 FREE 之间
+fn tier_priority(subscription_title: Option<&str>) -> u32 {
+    let Some(title) = subscription_title else {
+        return 3;
+    };
+    let title = title.to_uppercase();
+    if title.contains("POWER") {
+        0
+    } else if title.contains("PRO+") || title.contains("PRO PLUS") {
+        1
+    } else if title.contains("PRO") {
+        2
+    } else if title.contains("FREE") {
+        4
+    } else {
+        3
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_redact_config_masks_all_secrets() {
+        let config = crate::model::config_schema::populated_config();
+        let mut value = serde_json::to_value(&config).unwrap();
+        redact_config(&mut value);
+        let redacted = value.to_string();
+
+        // 填充配置中的敏感值均以 "secret" 开头或包含 "secret"
+        assert!(!redacted.contains("secret"), "{}", redacted);
+        // 非敏感字段与结构保留
+        assert_eq!(value["otlp"]["headers"]["authorization"], "***");
+        assert_eq!(
+            value["quotaAlerts"]["webhookUrl"],
+            "https://hooks.example.com/***"
+        );
+        assert_eq!(value["proxyUrl"], "http://***@proxy.example.com:8080");
+        assert_eq!(value["countTokensApiUrl"], "https://count.example.com/v1");
+        assert_eq!(value["embeddings"]["url"], config.embeddings.unwrap().url);
+        assert_eq!(value["port"], config.port);
+        assert_eq!(value["tokenQuotas"][0]["windowSecs"], 60);
+    }
+
+    #[test]
+    fn test_tier_priority() {
+        assert_eq!(tier_priority(Some("KIRO POWER")), 0);
+        assert_eq!(tier_priority(Some("KIRO PRO+")), 1);
+        assert_eq!(tier_priority(Some("Kiro Pro")), 2);
+        assert_eq!(tier_priority(Some("KIRO FREE")), 4);
+        assert_eq!(tier_priority(Some("SOMETHING NEW")), 3);
+        assert_eq!(tier_priority(None), 3);
+    }
+
+    #[test]
+    fn test_initial_tier_priority_keeps_explicit_zero() {
+        let explicit: AddCredentialRequest =
+            serde_json::from_value(serde_json::json!({"refreshToken": "t", "priority": 0}))
+                .unwrap();
+        let unset: AddCredentialRequest =
+            serde_json::from_value(serde_json::json!({"refreshToken": "t"})).unwrap();
+        assert_eq!(explicit.priority, Some(0));
+        assert_eq!(unset.priority, None);
+
+        // 显式指定 0（最高优先级）不被订阅等级覆盖
+        assert_eq!(
+            initial_tier_priority(explicit.priority, true, Some("KIRO FREE")),
+            None
+        );
+        assert_eq!(
+            initial_tier_priority(unset.priority, true, Some("KIRO FREE")),
+            Some(4)
+        );
+        assert_eq!(
+            initial_tier_priority(unset.priority, false, Some("KIRO FREE")),
+            None
+        );
+    }
+}
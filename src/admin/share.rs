@@ -0,0 +1,144 @@
+//! 只读分享链接
+//!
+//! 由 Admin API 签发带有效期的分享令牌，持有者无需 Admin API Key
+//! 即可访问指定的只读视图（已脱敏）。
+//!
+//! 令牌格式：`<scope>.<expires_at>.<signature>`
+//! - `scope`: 可访问的视图名称
+//! - `expires_at`: 过期时间（Unix 秒）
+//! - `signature`: 以 Admin API Key 为密钥的 HMAC-SHA256（十六进制）
+
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+
+use crate::common::auth;
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// 默认有效期（秒），24 小时
+pub const DEFAULT_SHARE_TTL_SECS: i64 = 24 * 3600;
+
+/// 最长有效期（秒），30 天
+pub const MAX_SHARE_TTL_SECS: i64 = 30 * 24 * 3600;
+
+/// 分享链接可访问的视图
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ShareScope {
+    /// 凭据可用性（不含邮箱、Token 哈希、代理等敏感信息）
+    Credentials,
+}
+
+impl ShareScope {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ShareScope::Credentials => "credentials",
+        }
+    }
+
+    pub fn parse(s: &str) -> Option<Self> {
+        match s {
+            "credentials" => Some(ShareScope::Credentials),
+            _ => None,
+        }
+    }
+}
+
+/// 分享令牌校验失败原因
+#[derive(Debug, PartialEq, Eq)]
+pub enum ShareTokenError {
+    /// 格式错误或签名不匹配
+    Invalid,
+    /// 已过期
+    Expired,
+}
+
+fn sign(secret: &str, payload: &str) -> String {
+    let mut mac = HmacSha256::new_from_slice(secret.as_bytes()).expect("HMAC 可接受任意长度的密钥");
+    mac.update(payload.as_bytes());
+    hex::encode(mac.finalize().into_bytes())
+}
+
+/// 签发分享令牌
+pub fn issue_token(secret: &str, scope: ShareScope, expires_at: i64) -> String {
+    let payload = format!("{}.{}", scope.as_str(), expires_at);
+    let signature = sign(secret, &payload);
+    format!("{}.{}", payload, signature)
+}
+
+/// 校验分享令牌，成功时返回其授权的视图
+pub fn verify_token(secret: &str, token: &str, now: i64) -> Result<ShareScope, ShareTokenError> {
+    let mut parts = token.splitn(3, '.');
+    let (Some(scope), Some(expires_at), Some(signature)) =
+        (parts.next(), parts.next(), parts.next())
+    else {
+        return Err(ShareTokenError::Invalid);
+    };
+
+    let expected = sign(secret, &format!("{}.{}", scope, expires_at));
+    if !auth::constant_time_eq(signature, &expected) {
+        return Err(ShareTokenError::Invalid);
+    }
+
+    let scope = ShareScope::parse(scope).ok_or(ShareTokenError::Invalid)?;
+    let expires_at: i64 = expires_at.parse().map_err(|_| ShareTokenError::Invalid)?;
+    if now >= expires_at {
+        return Err(ShareTokenError::Expired);
+    }
+
+    Ok(scope)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_issue_and_verify_roundtrip() {
+        let token = issue_token("secret", ShareScope::Credentials, 2000);
+        assert!(token.starts_with("credentials.2000."));
+        assert_eq!(
+            verify_token("secret", &token, 1000),
+            Ok(ShareScope::Credentials)
+        );
+    }
+
+    #[test]
+    fn test_verify_rejects_expired() {
+        let token = issue_token("secret", ShareScope::Credentials, 2000);
+        assert_eq!(
+            verify_token("secret", &token, 2000),
+            Err(ShareTokenError::Expired)
+        );
+    }
+
+    #[test]
+    fn test_verify_rejects_wrong_secret() {
+        let token = issue_token("secret", ShareScope::Credentials, 2000);
+        assert_eq!(
+            verify_token("other", &token, 1000),
+            Err(ShareTokenError::Invalid)
+        );
+    }
+
+    #[test]
+    fn test_verify_rejects_tampered_expiry() {
+        let token = issue_token("secret", ShareScope::Credentials, 2000);
+        let tampered = token.replacen("2000", "9999", 1);
+        assert_eq!(
+            verify_token("secret", &tampered, 1000),
+            Err(ShareTokenError::Invalid)
+        );
+    }
+
+    #[test]
+    fn test_verify_rejects_malformed() {
+        assert_eq!(
+            verify_token("secret", "garbage", 0),
+            Err(ShareTokenError::Invalid)
+        );
+        assert_eq!(
+            verify_token("secret", "credentials.2000.zz", 0),
+            Err(ShareTokenError::Invalid)
+        );
+    }
+}
@@ -1,7 +1,15 @@
 //! Admin API 类型定义
 
+use std::collections::BTreeMap;
+
 use serde::{Deserialize, Serialize};
 
+use crate::anthropic::replay::FrameDump;
+use crate::kiro::circuit_breaker::BreakerSnapshot;
+use crate::kiro::proxy_health::ProxyStatus;
+use crate::kiro::warm_refresh::RefreshStatus;
+use crate::model::config::{ClientProfile, ModelRoute, SystemPromptRule};
+
 // ============ 凭据状态 ============
 
 /// 所有凭据状态响应
@@ -55,6 +63,9 @@ pub struct CredentialStatusItem {
     /// 代理 URL（用于前端展示）
     #[serde(skip_serializing_if = "Option::is_none")]
     pub proxy_url: Option<String>,
+    /// 凭据级代理的健康状态与当前生效的代理（未配置凭据级代理时省略）
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub proxy_status: Option<ProxyStatus>,
     /// Token 刷新连续失败次数
     pub refresh_failure_count: u32,
     /// 禁用原因
@@ -62,6 +73,27 @@ pub struct CredentialStatusItem {
     pub disabled_reason: Option<String>,
     /// 端点名称（决定该凭据走哪套 Kiro API，已回退到默认端点）
     pub endpoint: String,
+    /// 可用时段规则（为空表示始终可用）
+    pub schedule: Vec<String>,
+    /// 当前是否处于可用时段内
+    pub in_schedule: bool,
+    /// 凭据分组（未分组时省略）
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub group: Option<String>,
+    /// balanced 模式下的流量权重
+    pub weight: u32,
+    /// 熔断器状态（未配置熔断时省略）
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub circuit_breaker: Option<BreakerSnapshot>,
+    /// 上游 429 限流冷却的截止时间（RFC3339 格式，未在冷却中时省略）
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cooldown_until: Option<String>,
+    /// 绑定到该凭据的会话数（未配置会话亲和时省略）
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pinned_sessions: Option<usize>,
+    /// 最近一次 Token 刷新的结果（尚未刷新过时省略）
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub last_refresh: Option<RefreshStatus>,
 }
 
 // ============ 操作请求 ============
@@ -82,6 +114,42 @@ pub struct SetPriorityRequest {
     pub priority: u32,
 }
 
+/// 设置可用时段请求
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SetScheduleRequest {
+    /// 时段规则列表（如 `Sat,Sun 00:00-24:00`），为空表示始终可用
+    #[serde(default)]
+    pub schedule: Vec<String>,
+}
+
+/// 设置凭据权重请求
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SetWeightRequest {
+    /// balanced 模式下的流量权重（>= 1）
+    pub weight: u32,
+}
+
+/// 设置凭据分组请求
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SetGroupRequest {
+    /// 分组名，为空或省略表示取消分组
+    #[serde(default)]
+    pub group: Option<String>,
+}
+
+/// 事件流回放请求（`captureId` 与 `dump` 二选一）
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ReplayRequest {
+    /// 内存中的录制 ID
+    pub capture_id: Option<String>,
+    /// 之前导出的 dump
+    pub dump: Option<FrameDump>,
+}
+
 /// 添加凭据请求
 #[derive(Debug, Deserialize)]
 #[serde(rename_all = "camelCase")]
@@ -99,9 +167,8 @@ pub struct AddCredentialRequest {
     /// OIDC Client Secret（IdC 认证需要）
     pub client_secret: Option<String>,
 
-    /// 优先级（可选，默认 0）
-    #[serde(default)]
-    pub priority: u32,
+    /// 优先级（可选，默认 0；批量导入时未指定则可按订阅等级设置）
+    pub priority: Option<u32>,
 
     /// 凭据级 Region 配置（用于 OIDC token 刷新）
     /// 未配置时回退到 config.json 的全局 region
@@ -129,6 +196,10 @@ pub struct AddCredentialRequest {
     /// 凭据级代理认证密码（可选）
     pub proxy_password: Option<String>,
 
+    /// 备用代理 URL 列表（可选，主代理不可用时按顺序切换）
+    #[serde(default)]
+    pub fallback_proxy_urls: Vec<String>,
+
     /// Kiro API Key（API Key 凭据必填，格式: ksk_xxxxxxxx）
     /// 设置后直接作为 Bearer Token 使用，无需 refreshToken
     #[serde(skip_serializing_if = "Option::is_none")]
@@ -137,6 +208,19 @@ pub struct AddCredentialRequest {
     /// 端点名称（可选，未配置时使用 config.defaultEndpoint）
     #[serde(skip_serializing_if = "Option::is_none")]
     pub endpoint: Option<String>,
+
+    /// 可用时段规则（可选，为空表示始终可用）
+    #[serde(default)]
+    pub schedule: Vec<String>,
+
+    /// 凭据分组（可选，用于按模型路由）
+    pub group: Option<String>,
+
+    /// balanced 模式下的流量权重（可选，默认 1）
+    pub weight: Option<u32>,
+
+    /// 凭据级客户端画像（可选，未配置时从 config.clientProfiles 轮换分配）
+    pub client_profile: Option<ClientProfile>,
 }
 
 fn default_auth_method() -> String {
@@ -156,6 +240,232 @@ pub struct AddCredentialResponse {
     pub email: Option<String>,
 }
 
+// ============ 批量导入 ============
+
+/// 批量导入凭据请求
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ImportCredentialsRequest {
+    /// 待导入的凭据列表
+    pub credentials: Vec<AddCredentialRequest>,
+
+    /// 验活并发数（默认 4，最大 16）
+    pub concurrency: Option<usize>,
+
+    /// 是否按订阅等级设置初始优先级（仅对未指定优先级的凭据生效，默认 true）
+    #[serde(default = "default_true")]
+    pub priority_by_tier: bool,
+
+    /// 验活失败时是否自动禁用并删除已添加的凭据（默认 true）
+    #[serde(default = "default_true")]
+    pub rollback_on_failure: bool,
+}
+
+fn default_true() -> bool {
+    true
+}
+
+// ============ 加密导出/导入 ============
+
+/// 导出凭据请求
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ExportCredentialsRequest {
+    /// 加密口令（至少 8 个字符）
+    pub passphrase: String,
+}
+
+/// 导入加密凭据包请求
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ImportCredentialBundleRequest {
+    /// 导出接口返回的加密凭据包
+    pub bundle: super::bundle::EncryptedBundle,
+
+    /// 导出时使用的口令
+    pub passphrase: String,
+
+    /// 验活并发数（默认 4，最大 16）
+    pub concurrency: Option<usize>,
+
+    /// 验活失败时是否自动禁用并删除已添加的凭据（默认 true）
+    #[serde(default = "default_true")]
+    pub rollback_on_failure: bool,
+}
+
+/// 单个凭据的导入结果
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ImportCredentialResult {
+    /// 在请求列表中的位置（从 0 开始）
+    pub index: usize,
+    /// 结果状态："verified" / "duplicate" / "failed"
+    pub status: String,
+    /// 新凭据 ID（验活失败且已回滚时为 None）
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub credential_id: Option<u64>,
+    /// 订阅类型
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub subscription_title: Option<String>,
+    /// 最终优先级
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub priority: Option<u32>,
+    /// 剩余额度
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub remaining: Option<f64>,
+    /// 失败原因
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    /// 失败后是否已回滚（禁用并删除）
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub rolled_back: Option<bool>,
+}
+
+impl ImportCredentialResult {
+    /// 重复凭据（未添加）
+    pub fn duplicate(index: usize, error: impl Into<String>) -> Self {
+        Self::failed(index, None, error, None).with_status("duplicate")
+    }
+
+    /// 添加或验活失败
+    pub fn failed(
+        index: usize,
+        credential_id: Option<u64>,
+        error: impl Into<String>,
+        rolled_back: Option<bool>,
+    ) -> Self {
+        Self {
+            index,
+            status: "failed".to_string(),
+            credential_id,
+            subscription_title: None,
+            priority: None,
+            remaining: None,
+            error: Some(error.into()),
+            rolled_back,
+        }
+    }
+
+    fn with_status(mut self, status: &str) -> Self {
+        self.status = status.to_string();
+        self
+    }
+}
+
+/// 批量导入汇总报告
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ImportCredentialsResponse {
+    pub total: usize,
+    pub verified: usize,
+    pub duplicate: usize,
+    pub failed: usize,
+    /// 按订阅类型统计的验活成功数量
+    pub by_subscription: std::collections::BTreeMap<String, usize>,
+    /// 各凭据结果（按请求顺序）
+    pub results: Vec<ImportCredentialResult>,
+}
+
+// ============ IdC 设备授权登录 ============
+
+/// 发起 IdC 设备授权登录请求
+///
+/// 除 `region` / `startUrl` 外的字段用于授权完成后添加凭据
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StartDeviceLoginRequest {
+    /// OIDC Region（可选，未配置时使用 config.json 的 authRegion / region）
+    pub region: Option<String>,
+
+    /// IAM Identity Center 登录地址（可选，默认使用 AWS Builder ID）
+    pub start_url: Option<String>,
+
+    /// 优先级（可选，默认 0）
+    #[serde(default)]
+    pub priority: u32,
+
+    /// 凭据级代理 URL（可选，同时用于授权请求）
+    pub proxy_url: Option<String>,
+
+    /// 凭据级代理认证用户名（可选）
+    pub proxy_username: Option<String>,
+
+    /// 凭据级代理认证密码（可选）
+    pub proxy_password: Option<String>,
+
+    /// 备用代理 URL 列表（可选）
+    #[serde(default)]
+    pub fallback_proxy_urls: Vec<String>,
+
+    /// 端点名称（可选，未配置时使用 config.defaultEndpoint）
+    pub endpoint: Option<String>,
+
+    /// 凭据分组（可选）
+    pub group: Option<String>,
+}
+
+/// 发起 IdC 设备授权登录响应
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DeviceLoginResponse {
+    /// 轮询使用的会话 ID
+    pub session_id: String,
+    /// 用户需要在验证页面输入的代码
+    pub user_code: String,
+    /// 验证页面地址
+    pub verification_uri: String,
+    /// 已填入 user code 的验证页面地址
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub verification_uri_complete: Option<String>,
+    /// 设备码有效期（秒）
+    pub expires_in: i64,
+    /// 轮询间隔（秒）
+    pub interval: i64,
+}
+
+/// 设备授权登录轮询响应
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DeviceLoginPollResponse {
+    /// "pending"：用户尚未完成授权；"completed"：凭据已添加
+    pub status: String,
+    /// 下次轮询前应等待的秒数（仅 pending）
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub retry_after_secs: Option<u64>,
+    /// 添加的凭据（仅 completed）
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub credential: Option<AddCredentialResponse>,
+}
+
+impl DeviceLoginPollResponse {
+    pub fn pending(retry_after_secs: u64) -> Self {
+        Self {
+            status: "pending".to_string(),
+            retry_after_secs: Some(retry_after_secs),
+            credential: None,
+        }
+    }
+
+    pub fn completed(credential: AddCredentialResponse) -> Self {
+        Self {
+            status: "completed".to_string(),
+            retry_after_secs: None,
+            credential: Some(credential),
+        }
+    }
+}
+
+// ============ 凭据验证报告 ============
+
+/// 凭据验证报告查询参数
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ValidationReportQuery {
+    /// 重新验证所有凭据（默认返回最近一次报告，尚无报告时才验证）
+    #[serde(default)]
+    pub refresh: bool,
+}
+
 // ============ 余额查询 ============
 
 /// 余额查询响应
@@ -196,6 +506,83 @@ pub struct SetLoadBalancingModeRequest {
     pub mode: String,
 }
 
+/// 模型路由规则（请求与响应共用）
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ModelRoutesPayload {
+    /// 按顺序匹配的路由规则
+    pub routes: Vec<ModelRoute>,
+}
+
+/// system 提示注入规则（请求与响应共用）
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SystemPromptsPayload {
+    /// 所有匹配的规则都会生效
+    pub rules: Vec<SystemPromptRule>,
+}
+
+/// 会话记忆（整体替换）
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SessionMemoryPayload {
+    /// 笔记（键 → 内容），为空时删除该会话的记忆
+    pub notes: BTreeMap<String, String>,
+}
+
+// ============ 只读分享链接 ============
+
+/// 创建分享链接请求
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CreateShareLinkRequest {
+    /// 可访问的视图（当前支持 "credentials"）
+    pub scope: String,
+    /// 有效期（秒），默认 24 小时，最长 30 天
+    pub ttl_secs: Option<i64>,
+}
+
+/// 创建分享链接响应
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ShareLinkResponse {
+    /// 分享令牌
+    pub token: String,
+    /// 授权的视图
+    pub scope: String,
+    /// 过期时间（RFC3339 格式）
+    pub expires_at: String,
+    /// 访问路径（相对于服务根路径）
+    pub path: String,
+}
+
+/// 分享视图：凭据可用性
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SharedCredentialsResponse {
+    /// 凭据总数
+    pub total: usize,
+    /// 可用凭据数量（未禁用）
+    pub available: usize,
+    /// 各凭据状态列表（已脱敏）
+    pub credentials: Vec<SharedCredentialItem>,
+}
+
+/// 分享视图中的单个凭据（不含邮箱、Token 哈希、代理等敏感信息）
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SharedCredentialItem {
+    pub id: u64,
+    pub priority: u32,
+    pub disabled: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub disabled_reason: Option<String>,
+    pub is_current: bool,
+    pub failure_count: u32,
+    pub success_count: u64,
+    pub last_used_at: Option<String>,
+}
+
 // ============ 通用响应 ============
 
 /// 操作成功响应
@@ -245,6 +632,10 @@ impl AdminErrorResponse {
         Self::new("authentication_error", "Invalid or missing admin API key")
     }
 
+    pub fn share_token_error(message: impl Into<String>) -> Self {
+        Self::new("authentication_error", message)
+    }
+
     pub fn not_found(message: impl Into<String>) -> Self {
         Self::new("not_found", message)
     }
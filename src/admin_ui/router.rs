@@ -1,10 +1,13 @@
 //! Admin UI 路由配置
 
+use std::collections::HashMap;
+use std::sync::Arc;
+
 use axum::{
     Router,
     body::Body,
-    http::{Response, StatusCode, Uri, header},
-    response::IntoResponse,
+    extract::State,
+    http::{HeaderMap, Response, StatusCode, Uri, header},
     routing::get,
 };
 use rust_embed::Embed;
@@ -14,20 +17,84 @@ use rust_embed::Embed;
 #[folder = "admin-ui/dist"]
 struct Asset;
 
+/// 前端构建时使用的访问路径（`admin-ui/vite.config.ts` 中的 `base`）
+const BUILD_BASE_PATH: &str = "/admin";
+
+/// 已被 API 路由占用、不能作为 Admin UI 访问路径的前缀
+const RESERVED_PREFIXES: &[&str] = &["/v1", "/cc", "/api"];
+
+/// 需要改写访问路径的文本文件扩展名
+const REWRITE_EXTENSIONS: &[&str] = &[".html", ".js", ".css"];
+
+/// Admin UI 静态文件
+struct UiAssets {
+    /// 访问路径与构建时不同时，改写过资源引用路径的文本文件
+    rewritten: HashMap<String, Vec<u8>>,
+}
+
+/// 校验并规范化 Admin UI 访问路径（去掉末尾的 `/`）
+pub fn normalize_base_path(path: &str) -> anyhow::Result<String> {
+    let base = path.trim_end_matches('/');
+    if !path.starts_with('/') || base.is_empty() {
+        anyhow::bail!("adminUiPath 必须以 / 开头且不能为根路径: \"{}\"", path);
+    }
+    if let Some(prefix) = RESERVED_PREFIXES
+        .iter()
+        .find(|p| base == **p || base.starts_with(&format!("{}/", p)))
+    {
+        anyhow::bail!("adminUiPath \"{}\" 与 API 路由 {} 冲突", path, prefix);
+    }
+    Ok(base.to_string())
+}
+
 /// 创建 Admin UI 路由
-pub fn create_admin_ui_router() -> Router {
+///
+/// `base_path` 为规范化后的访问路径（见 [`normalize_base_path`]），路由需挂载在该路径下
+pub fn create_admin_ui_router(base_path: &str) -> Router {
+    let rewritten = if base_path == BUILD_BASE_PATH {
+        HashMap::new()
+    } else {
+        Asset::iter()
+            .filter(|path| REWRITE_EXTENSIONS.iter().any(|ext| path.ends_with(ext)))
+            .filter_map(|path| {
+                let content = Asset::get(&path)?;
+                let text = String::from_utf8(content.data.into_owned()).ok()?;
+                Some((path.into_owned(), rebase(&text, base_path).into_bytes()))
+            })
+            .collect()
+    };
+
     Router::new()
         .route("/", get(index_handler))
         .route("/{*file}", get(static_handler))
+        .with_state(Arc::new(UiAssets { rewritten }))
+}
+
+/// 把资源引用中构建时的访问路径替换为实际访问路径
+///
+/// 只替换紧跟在引号或 `(` 之后的路径，避免误改 `/api/admin` 等 API 地址
+fn rebase(text: &str, base_path: &str) -> String {
+    ["\"", "'", "`", "("]
+        .iter()
+        .fold(text.to_string(), |text, quote| {
+            text.replace(
+                &format!("{}{}/", quote, BUILD_BASE_PATH),
+                &format!("{}{}/", quote, base_path),
+            )
+        })
 }
 
 /// 处理首页请求
-async fn index_handler() -> impl IntoResponse {
-    serve_index()
+async fn index_handler(State(assets): State<Arc<UiAssets>>, headers: HeaderMap) -> Response<Body> {
+    serve_index(&assets, &headers)
 }
 
 /// 处理静态文件请求
-async fn static_handler(uri: Uri) -> impl IntoResponse {
+async fn static_handler(
+    State(assets): State<Arc<UiAssets>>,
+    headers: HeaderMap,
+    uri: Uri,
+) -> Response<Body> {
     let path = uri.path().trim_start_matches('/');
 
     // 安全检查：拒绝包含 .. 的路径
@@ -39,25 +106,13 @@ async fn static_handler(uri: Uri) -> impl IntoResponse {
     }
 
     // 尝试获取请求的文件
-    if let Some(content) = Asset::get(path) {
-        let mime = mime_guess::from_path(path)
-            .first_or_octet_stream()
-            .to_string();
-
-        // 根据文件类型设置不同的缓存策略
-        let cache_control = get_cache_control(path);
-
-        return Response::builder()
-            .status(StatusCode::OK)
-            .header(header::CONTENT_TYPE, mime)
-            .header(header::CACHE_CONTROL, cache_control)
-            .body(Body::from(content.data.into_owned()))
-            .expect("Failed to build response");
+    if let Some(response) = serve_file(&assets, &headers, path) {
+        return response;
     }
 
     // SPA fallback: 如果文件不存在且不是资源文件，返回 index.html
     if !is_asset_path(path) {
-        return serve_index();
+        return serve_index(&assets, &headers);
     }
 
     // 404
@@ -68,21 +123,63 @@ async fn static_handler(uri: Uri) -> impl IntoResponse {
 }
 
 /// 提供 index.html
-fn serve_index() -> Response<Body> {
-    match Asset::get("index.html") {
-        Some(content) => Response::builder()
-            .status(StatusCode::OK)
-            .header(header::CONTENT_TYPE, "text/html; charset=utf-8")
-            .header(header::CACHE_CONTROL, "no-cache")
-            .body(Body::from(content.data.into_owned()))
-            .expect("Failed to build response"),
-        None => Response::builder()
+fn serve_index(assets: &UiAssets, headers: &HeaderMap) -> Response<Body> {
+    serve_file(assets, headers, "index.html").unwrap_or_else(|| {
+        Response::builder()
             .status(StatusCode::NOT_FOUND)
             .body(Body::from(
                 "Admin UI not built. Run 'pnpm build' in admin-ui directory.",
             ))
-            .expect("Failed to build response"),
+            .expect("Failed to build response")
+    })
+}
+
+/// 提供嵌入的文件，文件不存在时返回 None
+///
+/// 响应带有基于内容哈希的 ETag，请求的 `If-None-Match` 匹配时返回 304
+fn serve_file(assets: &UiAssets, headers: &HeaderMap, path: &str) -> Option<Response<Body>> {
+    let content = Asset::get(path)?;
+    let etag = format!("\"{}\"", hex::encode(&content.metadata.sha256_hash()[..16]));
+    // 根据文件类型设置不同的缓存策略
+    let cache_control = get_cache_control(path);
+
+    let not_modified = headers
+        .get(header::IF_NONE_MATCH)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|v| {
+            v.split(',')
+                .any(|tag| tag.trim() == etag || tag.trim() == "*")
+        });
+    let builder = Response::builder()
+        .header(header::ETAG, &etag)
+        .header(header::CACHE_CONTROL, cache_control);
+    if not_modified {
+        return Some(
+            builder
+                .status(StatusCode::NOT_MODIFIED)
+                .body(Body::empty())
+                .expect("Failed to build response"),
+        );
     }
+
+    let mime = if path.ends_with(".html") {
+        "text/html; charset=utf-8".to_string()
+    } else {
+        mime_guess::from_path(path)
+            .first_or_octet_stream()
+            .to_string()
+    };
+    let data = match assets.rewritten.get(path) {
+        Some(data) => data.clone(),
+        None => content.data.into_owned(),
+    };
+    Some(
+        builder
+            .status(StatusCode::OK)
+            .header(header::CONTENT_TYPE, mime)
+            .body(Body::from(data))
+            .expect("Failed to build response"),
+    )
 }
 
 /// 根据文件类型返回合适的缓存策略
@@ -107,3 +204,34 @@ fn is_asset_path(path: &str) -> bool {
         .map(|filename| filename.contains('.'))
         .unwrap_or(false)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_normalize_base_path() {
+        assert_eq!(normalize_base_path("/admin").unwrap(), "/admin");
+        assert_eq!(normalize_base_path("/ops/kiro/").unwrap(), "/ops/kiro");
+        assert_eq!(normalize_base_path("/v1x").unwrap(), "/v1x");
+        assert!(normalize_base_path("admin").is_err());
+        assert!(normalize_base_path("/").is_err());
+        assert!(normalize_base_path("/api/ui").is_err());
+        assert!(normalize_base_path("/v1").is_err());
+    }
+
+    #[test]
+    fn test_rebase_keeps_api_paths() {
+        let html =
+            r#"<script src="/admin/assets/index.js"></script><link href='/admin/assets/a.css'>"#;
+        assert_eq!(
+            rebase(html, "/ops/kiro"),
+            r#"<script src="/ops/kiro/assets/index.js"></script><link href='/ops/kiro/assets/a.css'>"#
+        );
+        let js = r#"const api = "/api/admin/credentials"; url(/admin/assets/font.woff2)"#;
+        assert_eq!(
+            rebase(js, "/ui"),
+            r#"const api = "/api/admin/credentials"; url(/ui/assets/font.woff2)"#
+        );
+    }
+}
@@ -0,0 +1,385 @@
+//! 全局准入控制
+//!
+//! 按凭据的并发控制只约束单个凭据；整个凭据池过载时，请求仍会同时涌向上游并大量失败。
+//! 配置 `admission` 后，所有 Messages 请求先经过全局准入：
+//! - 在途请求数未达 `maxInFlight` 时直接放行
+//! - 否则进入优先级队列，名额释放时优先放行高优先级请求，同级先到先得：
+//!   交互式流式请求 > 其他请求 > 模型匹配 `backgroundModels` 的后台请求（如 haiku）
+//! - 队列已满时，新请求若高于队列中最低优先级的请求则将其挤出，否则直接拒绝
+//! - 被拒绝、被挤出或等待超时的请求返回 503 `overloaded_error` 并附带 `retry-after`
+//!
+//! 准入许可随响应体一起释放（流式响应在事件流结束后释放）。
+
+use std::cmp::Reverse;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::sync::OnceLock;
+use std::time::Duration;
+
+use axum::{body::Body, response::Response};
+use futures::StreamExt;
+use parking_lot::Mutex;
+use serde::Serialize;
+use tokio::sync::oneshot;
+
+use crate::model::config::AdmissionConfig;
+
+static CONTROLLER: OnceLock<AdmissionController> = OnceLock::new();
+
+/// 请求优先级（越大越优先）
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Priority {
+    /// 后台模型请求
+    Background,
+    /// 非流式请求
+    Standard,
+    /// 交互式流式请求
+    Interactive,
+}
+
+/// 准入失败
+#[derive(Debug)]
+pub enum AdmissionError {
+    /// 队列已满
+    QueueFull,
+    /// 排队时被更高优先级的请求挤出队列
+    Evicted,
+    /// 排队超时
+    QueueTimeout { waited: Duration },
+}
+
+impl fmt::Display for AdmissionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::QueueFull => write!(f, "全局在途请求已满且准入队列已满"),
+            Self::Evicted => write!(f, "被更高优先级的请求挤出准入队列"),
+            Self::QueueTimeout { waited } => {
+                write!(f, "等待准入超时（{}s）", waited.as_secs())
+            }
+        }
+    }
+}
+
+impl std::error::Error for AdmissionError {}
+
+/// 初始化准入控制（未配置时不做任何事）
+pub fn init(config: Option<&AdmissionConfig>) {
+    if let Some(config) = config {
+        let _ = CONTROLLER.set(AdmissionController::new(config));
+    }
+}
+
+/// 为请求获取准入许可（未启用时返回 `Ok(None)`）
+pub async fn acquire(model: &str, stream: bool) -> Result<Option<AdmissionPermit>, AdmissionError> {
+    match CONTROLLER.get() {
+        Some(controller) => {
+            let priority = controller.classify(model, stream);
+            controller.acquire(priority).await.map(Some)
+        }
+        None => Ok(None),
+    }
+}
+
+/// 拒绝请求时 `retry-after` 响应头的秒数
+pub fn retry_after_secs() -> u64 {
+    CONTROLLER
+        .get()
+        .map(|c| c.config.retry_after_secs.max(1))
+        .unwrap_or(1)
+}
+
+/// 让准入许可随响应体一起释放
+pub fn hold(response: Response, permit: Option<AdmissionPermit>) -> Response {
+    let Some(permit) = permit else {
+        return response;
+    };
+    response.map(|body| {
+        Body::from_stream(body.into_data_stream().map(move |chunk| {
+            let _ = &permit;
+            chunk
+        }))
+    })
+}
+
+/// 当前准入状态
+pub fn report() -> AdmissionReport {
+    match CONTROLLER.get() {
+        Some(controller) => controller.report(),
+        None => AdmissionReport::default(),
+    }
+}
+
+/// 准入状态汇总
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AdmissionReport {
+    pub enabled: bool,
+    pub max_in_flight: usize,
+    pub queue_size: usize,
+    pub in_flight: usize,
+    /// 各优先级正在排队的请求数
+    pub waiting: BTreeMap<Priority, usize>,
+    /// 累计放行的请求数
+    pub admitted: u64,
+    /// 累计因队列已满或被挤出而拒绝的请求数
+    pub rejected: u64,
+    /// 累计排队超时的请求数
+    pub timed_out: u64,
+}
+
+/// 队列键：优先级 + 入队序号（同级时序号小的排在后面，先被放行）
+type QueueKey = (Priority, Reverse<u64>);
+
+#[derive(Debug, Default)]
+struct AdmissionState {
+    in_flight: usize,
+    next_seq: u64,
+    /// 等待者的放行通知：`true` 表示获得名额，`false` 表示被挤出
+    queue: BTreeMap<QueueKey, oneshot::Sender<bool>>,
+    admitted: u64,
+    rejected: u64,
+    timed_out: u64,
+}
+
+/// 全局准入控制器
+pub struct AdmissionController {
+    config: AdmissionConfig,
+    queue_timeout: Duration,
+    state: Mutex<AdmissionState>,
+}
+
+impl AdmissionController {
+    fn new(config: &AdmissionConfig) -> Self {
+        let mut config = config.clone();
+        config.max_in_flight = config.max_in_flight.max(1);
+        config.background_models = config
+            .background_models
+            .iter()
+            .map(|m| m.to_lowercase())
+            .collect();
+        Self {
+            queue_timeout: Duration::from_secs(config.queue_timeout_secs),
+            config,
+            state: Mutex::new(AdmissionState::default()),
+        }
+    }
+
+    fn classify(&self, model: &str, stream: bool) -> Priority {
+        let model = model.to_lowercase();
+        if self
+            .config
+            .background_models
+            .iter()
+            .any(|keyword| model.contains(keyword.as_str()))
+        {
+            Priority::Background
+        } else if stream {
+            Priority::Interactive
+        } else {
+            Priority::Standard
+        }
+    }
+
+    async fn acquire(&'static self, priority: Priority) -> Result<AdmissionPermit, AdmissionError> {
+        let mut waiter = {
+            let mut state = self.state.lock();
+            if state.in_flight < self.config.max_in_flight {
+                state.in_flight += 1;
+                state.admitted += 1;
+                return Ok(AdmissionPermit { controller: self });
+            }
+            if state.queue.len() >= self.config.queue_size {
+                match state.queue.first_key_value() {
+                    Some((&(lowest, _), _)) if lowest < priority => {
+                        if let Some((_, evicted)) = state.queue.pop_first() {
+                            let _ = evicted.send(false);
+                        }
+                        state.rejected += 1;
+                    }
+                    _ => {
+                        state.rejected += 1;
+                        return Err(AdmissionError::QueueFull);
+                    }
+                }
+            }
+            let key = (priority, Reverse(state.next_seq));
+            state.next_seq += 1;
+            let (tx, rx) = oneshot::channel();
+            state.queue.insert(key, tx);
+            Waiter {
+                controller: self,
+                key,
+                rx,
+            }
+        };
+
+        let started = tokio::time::Instant::now();
+        let granted = match tokio::time::timeout(self.queue_timeout, &mut waiter.rx).await {
+            Ok(result) => result.unwrap_or(false),
+            // 超时与放行可能同时发生：仍在队列中才算超时
+            Err(_) => {
+                let mut state = self.state.lock();
+                if state.queue.remove(&waiter.key).is_some() {
+                    state.timed_out += 1;
+                    return Err(AdmissionError::QueueTimeout {
+                        waited: started.elapsed(),
+                    });
+                }
+                drop(state);
+                waiter.rx.try_recv().unwrap_or(false)
+            }
+        };
+        if !granted {
+            return Err(AdmissionError::Evicted);
+        }
+        Ok(AdmissionPermit { controller: self })
+    }
+
+    /// 释放名额：有等待者时直接转交给优先级最高的等待者
+    fn release(&self) {
+        let mut state = self.state.lock();
+        while let Some((_, waiter)) = state.queue.pop_last() {
+            if waiter.send(true).is_ok() {
+                state.admitted += 1;
+                return;
+            }
+        }
+        state.in_flight = state.in_flight.saturating_sub(1);
+    }
+
+    fn report(&self) -> AdmissionReport {
+        let state = self.state.lock();
+        let mut waiting = BTreeMap::new();
+        for (priority, _) in state.queue.keys() {
+            *waiting.entry(*priority).or_insert(0) += 1;
+        }
+        AdmissionReport {
+            enabled: true,
+            max_in_flight: self.config.max_in_flight,
+            queue_size: self.config.queue_size,
+            in_flight: state.in_flight,
+            waiting,
+            admitted: state.admitted,
+            rejected: state.rejected,
+            timed_out: state.timed_out,
+        }
+    }
+}
+
+/// 队列中的等待者（请求被取消时离开队列，已转交的名额归还）
+struct Waiter {
+    controller: &'static AdmissionController,
+    key: QueueKey,
+    rx: oneshot::Receiver<bool>,
+}
+
+impl Drop for Waiter {
+    fn drop(&mut self) {
+        let mut state = self.controller.state.lock();
+        if state.queue.remove(&self.key).is_some() {
+            return;
+        }
+        drop(state);
+        // 已出队：名额已转交给本请求但未被取走（请求在放行瞬间被取消）时归还
+        if self.rx.try_recv() == Ok(true) {
+            self.controller.release();
+        }
+    }
+}
+
+/// 准入许可（丢弃时释放名额）
+pub struct AdmissionPermit {
+    controller: &'static AdmissionController,
+}
+
+impl Drop for AdmissionPermit {
+    fn drop(&mut self) {
+        self.controller.release();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn controller(max_in_flight: usize, queue_size: usize) -> &'static AdmissionController {
+        let mut controller = AdmissionController::new(&AdmissionConfig {
+            max_in_flight,
+            queue_size,
+            queue_timeout_secs: 1,
+            retry_after_secs: 5,
+            background_models: vec!["Haiku".to_string()],
+        });
+        controller.queue_timeout = Duration::from_millis(50);
+        Box::leak(Box::new(controller))
+    }
+
+    #[test]
+    fn test_classify_priority() {
+        let c = controller(1, 1);
+        assert_eq!(c.classify("claude-sonnet-4", true), Priority::Interactive);
+        assert_eq!(c.classify("claude-sonnet-4", false), Priority::Standard);
+        assert_eq!(c.classify("claude-haiku-4.5", true), Priority::Background);
+    }
+
+    #[tokio::test]
+    async fn test_release_prefers_higher_priority() {
+        let c = controller(1, 4);
+        let first = c.acquire(Priority::Standard).await.unwrap();
+
+        let background = tokio::spawn(c.acquire(Priority::Background));
+        tokio::task::yield_now().await;
+        let interactive = tokio::spawn(c.acquire(Priority::Interactive));
+        tokio::task::yield_now().await;
+        assert_eq!(c.report().waiting.values().sum::<usize>(), 2);
+
+        drop(first);
+        let interactive = interactive.await.unwrap().unwrap();
+        assert!(!background.is_finished());
+        drop(interactive);
+        drop(background.await.unwrap().unwrap());
+
+        let report = c.report();
+        assert_eq!((report.in_flight, report.admitted), (0, 3));
+        assert!(report.waiting.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_queue_full_evicts_lower_priority() {
+        let c = controller(1, 1);
+        let _first = c.acquire(Priority::Interactive).await.unwrap();
+
+        let background = tokio::spawn(c.acquire(Priority::Background));
+        tokio::task::yield_now().await;
+        assert!(matches!(
+            c.acquire(Priority::Background).await,
+            Err(AdmissionError::QueueFull)
+        ));
+
+        let interactive = tokio::spawn(c.acquire(Priority::Interactive));
+        tokio::task::yield_now().await;
+        assert!(matches!(
+            background.await.unwrap(),
+            Err(AdmissionError::Evicted)
+        ));
+        interactive.abort();
+        let _ = interactive.await;
+
+        let report = c.report();
+        assert_eq!((report.in_flight, report.rejected), (1, 2));
+        assert!(report.waiting.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_queue_timeout() {
+        let c = controller(1, 1);
+        let _first = c.acquire(Priority::Standard).await.unwrap();
+        assert!(matches!(
+            c.acquire(Priority::Interactive).await,
+            Err(AdmissionError::QueueTimeout { .. })
+        ));
+        let report = c.report();
+        assert_eq!((report.timed_out, report.waiting.len()), (1, 0));
+    }
+}
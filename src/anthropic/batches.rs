@@ -0,0 +1,584 @@
+//! Message Batches API（/v1/messages/batches）
+//!
+//! 批次中的每个请求在后台以有限并发走与 /v1/messages 相同的处理流程（强制非流式），
+//! 结果按 Anthropic 的格式以 JSONL 返回。
+//!
+//! 批次状态保存在内存中（最多保留最近 [`MAX_RETAINED_BATCHES`] 个），服务重启后丢失。
+
+use std::sync::Arc;
+
+use axum::response::Response;
+use chrono::{DateTime, SecondsFormat, Utc};
+use futures::StreamExt;
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use serde_json::{Value, json};
+use tokio::sync::Semaphore;
+
+use super::types::MessagesRequest;
+
+/// 单个批次的最大请求数
+pub const MAX_BATCH_REQUESTS: usize = 10_000;
+
+/// 内存中保留的批次数量（超出时淘汰最早结束的批次）
+const MAX_RETAINED_BATCHES: usize = 100;
+
+/// 批次过期时间（秒），超时仍未执行的请求标记为 expired
+const BATCH_EXPIRY_SECS: i64 = 24 * 3600;
+
+/// 列表接口默认/最大返回数量
+const DEFAULT_LIST_LIMIT: usize = 20;
+const MAX_LIST_LIMIT: usize = 1000;
+
+/// 创建批次请求体
+#[derive(Debug, Deserialize)]
+pub struct CreateBatchRequest {
+    pub requests: Vec<BatchRequestItem>,
+}
+
+/// 批次中的单个请求
+#[derive(Debug, Deserialize)]
+pub struct BatchRequestItem {
+    pub custom_id: String,
+    pub params: Value,
+}
+
+/// 列表查询参数
+#[derive(Debug, Default, Deserialize)]
+pub struct ListBatchesQuery {
+    pub limit: Option<usize>,
+    pub before_id: Option<String>,
+    pub after_id: Option<String>,
+}
+
+/// 各状态的请求数量
+#[derive(Debug, Clone, Default, Serialize, PartialEq, Eq)]
+pub struct RequestCounts {
+    pub processing: usize,
+    pub succeeded: usize,
+    pub errored: usize,
+    pub canceled: usize,
+    pub expired: usize,
+}
+
+/// Message Batch 对象
+#[derive(Debug, Clone, Serialize)]
+pub struct MessageBatch {
+    pub id: String,
+    #[serde(rename = "type")]
+    pub object_type: &'static str,
+    pub processing_status: &'static str,
+    pub request_counts: RequestCounts,
+    pub ended_at: Option<String>,
+    pub created_at: String,
+    pub expires_at: String,
+    pub archived_at: Option<String>,
+    pub cancel_initiated_at: Option<String>,
+    pub results_url: Option<String>,
+}
+
+/// 列表响应
+#[derive(Debug, Serialize)]
+pub struct ListBatchesResponse {
+    pub data: Vec<MessageBatch>,
+    pub has_more: bool,
+    pub first_id: Option<String>,
+    pub last_id: Option<String>,
+}
+
+struct BatchEntry {
+    custom_id: String,
+    /// 待执行的请求参数（开始执行时取走）
+    params: Option<Value>,
+    /// 执行结果（`result` 字段的内容）
+    result: Option<Value>,
+}
+
+struct Batch {
+    id: String,
+    created_at: DateTime<Utc>,
+    expires_at: DateTime<Utc>,
+    ended_at: Option<DateTime<Utc>>,
+    cancel_initiated_at: Option<DateTime<Utc>>,
+    entries: Vec<BatchEntry>,
+}
+
+impl Batch {
+    fn to_object(&self) -> MessageBatch {
+        let mut counts = RequestCounts::default();
+        for entry in &self.entries {
+            match entry.result.as_ref().and_then(|r| r["type"].as_str()) {
+                Some("succeeded") => counts.succeeded += 1,
+                Some("errored") => counts.errored += 1,
+                Some("canceled") => counts.canceled += 1,
+                Some("expired") => counts.expired += 1,
+                _ => counts.processing += 1,
+            }
+        }
+        let processing_status = match (self.ended_at, self.cancel_initiated_at) {
+            (Some(_), _) => "ended",
+            (None, Some(_)) => "canceling",
+            (None, None) => "in_progress",
+        };
+        MessageBatch {
+            id: self.id.clone(),
+            object_type: "message_batch",
+            processing_status,
+            request_counts: counts,
+            ended_at: self.ended_at.map(format_time),
+            created_at: format_time(self.created_at),
+            expires_at: format_time(self.expires_at),
+            archived_at: None,
+            cancel_initiated_at: self.cancel_initiated_at.map(format_time),
+            results_url: self
+                .ended_at
+                .map(|_| format!("/v1/messages/batches/{}/results", self.id)),
+        }
+    }
+}
+
+fn format_time(time: DateTime<Utc>) -> String {
+    time.to_rfc3339_opts(SecondsFormat::Micros, true)
+}
+
+/// 批次存储与执行
+pub struct BatchStore {
+    /// 按创建时间排序（新的在后）
+    batches: Mutex<Vec<Batch>>,
+    concurrency: usize,
+    /// 所有批次共享的执行并发上限
+    permits: Arc<Semaphore>,
+}
+
+impl BatchStore {
+    pub fn new(concurrency: usize) -> Self {
+        let concurrency = concurrency.max(1);
+        Self {
+            batches: Mutex::new(Vec::new()),
+            concurrency,
+            permits: Arc::new(Semaphore::new(concurrency)),
+        }
+    }
+
+    /// 校验并创建批次，返回批次对象（执行由调用方通过 [`BatchStore::run`] 启动）
+    pub fn create(&self, id: String, request: CreateBatchRequest) -> Result<MessageBatch, String> {
+        if request.requests.is_empty() {
+            return Err("requests: 至少需要一个请求".to_string());
+        }
+        if request.requests.len() > MAX_BATCH_REQUESTS {
+            return Err(format!(
+                "requests: 单个批次最多 {} 个请求",
+                MAX_BATCH_REQUESTS
+            ));
+        }
+
+        let mut seen = std::collections::HashSet::new();
+        for (i, item) in request.requests.iter().enumerate() {
+            if item.custom_id.is_empty() || item.custom_id.len() > 64 {
+                return Err(format!("requests.{}.custom_id: 长度应为 1-64", i));
+            }
+            if !seen.insert(item.custom_id.as_str()) {
+                return Err(format!(
+                    "requests.{}.custom_id: 重复的 custom_id \"{}\"",
+                    i, item.custom_id
+                ));
+            }
+            if let Err(e) = serde_json::from_value::<MessagesRequest>(item.params.clone()) {
+                return Err(format!("requests.{}.params: {}", i, e));
+            }
+        }
+
+        let now = Utc::now();
+        let batch = Batch {
+            id,
+            created_at: now,
+            expires_at: now + chrono::Duration::seconds(BATCH_EXPIRY_SECS),
+            ended_at: None,
+            cancel_initiated_at: None,
+            entries: request
+                .requests
+                .into_iter()
+                .map(|item| BatchEntry {
+                    custom_id: item.custom_id,
+                    params: Some(item.params),
+                    result: None,
+                })
+                .collect(),
+        };
+        let object = batch.to_object();
+
+        let mut batches = self.batches.lock();
+        if batches.len() >= MAX_RETAINED_BATCHES {
+            let Some(oldest) = batches.iter().position(|b| b.ended_at.is_some()) else {
+                return Err(format!(
+                    "进行中的批次已达上限 {}，请等待已有批次结束",
+                    MAX_RETAINED_BATCHES
+                ));
+            };
+            batches.remove(oldest);
+        }
+        batches.push(batch);
+        Ok(object)
+    }
+
+    /// 获取批次
+    pub fn get(&self, id: &str) -> Option<MessageBatch> {
+        self.batches
+            .lock()
+            .iter()
+            .find(|b| b.id == id)
+            .map(Batch::to_object)
+    }
+
+    /// 列出批次（新的在前，支持 before_id/after_id 分页）
+    pub fn list(&self, query: &ListBatchesQuery) -> ListBatchesResponse {
+        let limit = query
+            .limit
+            .unwrap_or(DEFAULT_LIST_LIMIT)
+            .clamp(1, MAX_LIST_LIMIT);
+        let batches = self.batches.lock();
+        let newest_first: Vec<&Batch> = batches.iter().rev().collect();
+
+        let position = |id: &str| newest_first.iter().position(|b| b.id == id);
+        let (start, end) = match (&query.after_id, &query.before_id) {
+            // after_id：该 ID 之后（更早）的批次
+            (Some(after), _) => {
+                let start = position(after).map_or(newest_first.len(), |p| p + 1);
+                (start, (start + limit).min(newest_first.len()))
+            }
+            // before_id：该 ID 之前（更新）的批次
+            (None, Some(before)) => {
+                let end = position(before).unwrap_or(0);
+                (end.saturating_sub(limit), end)
+            }
+            (None, None) => (0, limit.min(newest_first.len())),
+        };
+
+        let data: Vec<MessageBatch> = newest_first[start..end]
+            .iter()
+            .map(|b| b.to_object())
+            .collect();
+        let has_more = if query.before_id.is_some() && query.after_id.is_none() {
+            start > 0
+        } else {
+            end < newest_first.len()
+        };
+        ListBatchesResponse {
+            has_more,
+            first_id: data.first().map(|b| b.id.clone()),
+            last_id: data.last().map(|b| b.id.clone()),
+            data,
+        }
+    }
+
+    /// 取消批次：尚未开始执行的请求标记为 canceled，执行中的请求继续完成
+    pub fn cancel(&self, id: &str) -> Option<MessageBatch> {
+        let mut batches = self.batches.lock();
+        let batch = batches.iter_mut().find(|b| b.id == id)?;
+        if batch.ended_at.is_none() && batch.cancel_initiated_at.is_none() {
+            batch.cancel_initiated_at = Some(Utc::now());
+        }
+        Some(batch.to_object())
+    }
+
+    /// 批次结果（JSONL，按请求顺序）；批次不存在返回 None，未结束返回 Some(Err)
+    pub fn results(&self, id: &str) -> Option<Result<String, String>> {
+        let batches = self.batches.lock();
+        let batch = batches.iter().find(|b| b.id == id)?;
+        if batch.ended_at.is_none() {
+            return Some(Err(format!("批次 {} 尚未结束", id)));
+        }
+        let mut jsonl = String::new();
+        for entry in &batch.entries {
+            let line = json!({
+                "custom_id": entry.custom_id,
+                "result": entry.result.clone().unwrap_or_else(|| json!({ "type": "canceled" })),
+            });
+            jsonl.push_str(&line.to_string());
+            jsonl.push('\n');
+        }
+        Some(Ok(jsonl))
+    }
+
+    /// 取出下一个待执行请求的参数；已取消或过期时直接记录结果并返回 None
+    fn take_params(&self, id: &str, index: usize) -> Option<Value> {
+        let mut batches = self.batches.lock();
+        let batch = batches.iter_mut().find(|b| b.id == id)?;
+        let skipped = if batch.cancel_initiated_at.is_some() {
+            Some("canceled")
+        } else if Utc::now() >= batch.expires_at {
+            Some("expired")
+        } else {
+            None
+        };
+        let entry = batch.entries.get_mut(index)?;
+        if let Some(kind) = skipped {
+            entry.params = None;
+            entry.result = Some(json!({ "type": kind }));
+            return None;
+        }
+        entry.params.take()
+    }
+
+    fn set_result(&self, id: &str, index: usize, result: Value) {
+        let mut batches = self.batches.lock();
+        if let Some(entry) = batches
+            .iter_mut()
+            .find(|b| b.id == id)
+            .and_then(|b| b.entries.get_mut(index))
+        {
+            entry.result = Some(result);
+        }
+    }
+
+    fn finish(&self, id: &str) {
+        let mut batches = self.batches.lock();
+        if let Some(batch) = batches.iter_mut().find(|b| b.id == id) {
+            batch.ended_at = Some(Utc::now());
+        }
+    }
+
+    fn len_of(&self, id: &str) -> usize {
+        self.batches
+            .lock()
+            .iter()
+            .find(|b| b.id == id)
+            .map_or(0, |b| b.entries.len())
+    }
+
+    /// 执行批次中的所有请求
+    ///
+    /// `execute` 负责实际处理单个请求并返回 HTTP 响应（与 /v1/messages 相同）
+    pub async fn run<F, Fut>(self: Arc<Self>, id: String, execute: F)
+    where
+        F: Fn(MessagesRequest) -> Fut,
+        Fut: Future<Output = Response>,
+    {
+        let total = self.len_of(&id);
+        tracing::info!(batch_id = %id, requests = total, "开始执行消息批次");
+
+        futures::stream::iter(0..total)
+            .for_each_concurrent(self.concurrency, |index| {
+                let store = self.clone();
+                let id = id.clone();
+                let execute = &execute;
+                async move {
+                    let Ok(_permit) = store.permits.clone().acquire_owned().await else {
+                        return;
+                    };
+                    let Some(params) = store.take_params(&id, index) else {
+                        return;
+                    };
+                    let result = match serde_json::from_value::<MessagesRequest>(params) {
+                        Ok(mut payload) => {
+                            payload.stream = false;
+                            response_to_result(execute(payload).await).await
+                        }
+                        Err(e) => errored("invalid_request_error", &e.to_string()),
+                    };
+                    store.set_result(&id, index, result);
+                }
+            })
+            .await;
+
+        self.finish(&id);
+        if let Some(batch) = self.get(&id) {
+            let counts = batch.request_counts;
+            tracing::info!(
+                batch_id = %id,
+                succeeded = counts.succeeded,
+                errored = counts.errored,
+                canceled = counts.canceled,
+                expired = counts.expired,
+                "消息批次执行结束"
+            );
+        }
+    }
+}
+
+fn errored(error_type: &str, message: &str) -> Value {
+    json!({
+        "type": "errored",
+        "error": {
+            "type": "error",
+            "error": { "type": error_type, "message": message }
+        }
+    })
+}
+
+/// 将单个请求的 HTTP 响应转换为批次结果
+async fn response_to_result(response: Response) -> Value {
+    let status = response.status();
+    let body = match axum::body::to_bytes(response.into_body(), usize::MAX).await {
+        Ok(body) => body,
+        Err(e) => return errored("api_error", &format!("读取响应失败: {}", e)),
+    };
+    let value: Value = match serde_json::from_slice(&body) {
+        Ok(value) => value,
+        Err(_) => {
+            return errored(
+                "api_error",
+                &format!("上游返回了无法解析的响应（HTTP {}）", status.as_u16()),
+            );
+        }
+    };
+    if status.is_success() {
+        json!({ "type": "succeeded", "message": value })
+    } else {
+        json!({
+            "type": "errored",
+            "error": { "type": "error", "error": value["error"] }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::http::StatusCode;
+    use axum::response::IntoResponse;
+
+    fn params() -> Value {
+        json!({
+            "model": "claude-sonnet-4",
+            "max_tokens": 16,
+            "messages": [{ "role": "user", "content": "hi" }]
+        })
+    }
+
+    fn create_request(ids: &[&str]) -> CreateBatchRequest {
+        CreateBatchRequest {
+            requests: ids
+                .iter()
+                .map(|id| BatchRequestItem {
+                    custom_id: id.to_string(),
+                    params: params(),
+                })
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn test_create_validates_requests() {
+        let store = BatchStore::new(2);
+        assert!(store.create("b0".into(), create_request(&[])).is_err());
+        assert!(
+            store
+                .create("b1".into(), create_request(&["a", "a"]))
+                .is_err()
+        );
+
+        let mut bad = create_request(&["a"]);
+        bad.requests[0].params = json!({ "model": "x" });
+        assert!(store.create("b2".into(), bad).is_err());
+
+        let batch = store
+            .create("b3".into(), create_request(&["a", "b"]))
+            .unwrap();
+        assert_eq!(batch.processing_status, "in_progress");
+        assert_eq!(batch.request_counts.processing, 2);
+        assert!(batch.results_url.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_run_collects_results_in_order() {
+        let store = Arc::new(BatchStore::new(2));
+        let mut request = create_request(&["ok", "fail"]);
+        request.requests[1].params["max_tokens"] = json!(32);
+        store.create("b".into(), request).unwrap();
+
+        store
+            .clone()
+            .run("b".into(), |payload: MessagesRequest| async move {
+                assert!(!payload.stream);
+                if payload.max_tokens == 16 {
+                    (StatusCode::OK, axum::Json(json!({ "id": "msg_1" }))).into_response()
+                } else {
+                    let error = json!({ "error": { "type": "api_error", "message": "boom" } });
+                    (StatusCode::INTERNAL_SERVER_ERROR, axum::Json(error)).into_response()
+                }
+            })
+            .await;
+
+        let batch = store.get("b").unwrap();
+        assert_eq!(batch.processing_status, "ended");
+        assert_eq!(batch.request_counts.succeeded, 1);
+        assert_eq!(batch.request_counts.errored, 1);
+        assert!(batch.results_url.is_some());
+
+        let results = store.results("b").unwrap().unwrap();
+        let lines: Vec<Value> = results
+            .lines()
+            .map(|l| serde_json::from_str(l).unwrap())
+            .collect();
+        assert_eq!(lines[0]["custom_id"], "ok");
+        assert_eq!(lines[0]["result"]["message"]["id"], "msg_1");
+        assert_eq!(lines[1]["custom_id"], "fail");
+        assert_eq!(lines[1]["result"]["type"], "errored");
+    }
+
+    #[tokio::test]
+    async fn test_error_response_becomes_errored_result() {
+        let response = (
+            StatusCode::TOO_MANY_REQUESTS,
+            axum::Json(json!({ "error": { "type": "rate_limit_error", "message": "slow" } })),
+        )
+            .into_response();
+        let result = response_to_result(response).await;
+        assert_eq!(result["type"], "errored");
+        assert_eq!(result["error"]["error"]["type"], "rate_limit_error");
+    }
+
+    #[tokio::test]
+    async fn test_cancel_skips_pending_requests() {
+        let store = Arc::new(BatchStore::new(1));
+        store
+            .create("b".into(), create_request(&["a", "b"]))
+            .unwrap();
+        assert_eq!(store.cancel("b").unwrap().processing_status, "canceling");
+        assert!(store.results("b").unwrap().is_err());
+
+        store
+            .clone()
+            .run("b".into(), |_| async { StatusCode::OK.into_response() })
+            .await;
+
+        let batch = store.get("b").unwrap();
+        assert_eq!(batch.processing_status, "ended");
+        assert_eq!(batch.request_counts.canceled, 2);
+    }
+
+    #[test]
+    fn test_list_pagination() {
+        let store = BatchStore::new(1);
+        for id in ["b1", "b2", "b3"] {
+            store.create(id.into(), create_request(&["a"])).unwrap();
+        }
+
+        let page = store.list(&ListBatchesQuery {
+            limit: Some(2),
+            ..Default::default()
+        });
+        let ids: Vec<_> = page.data.iter().map(|b| b.id.as_str()).collect();
+        assert_eq!(ids, ["b3", "b2"]);
+        assert!(page.has_more);
+
+        let next = store.list(&ListBatchesQuery {
+            limit: Some(2),
+            after_id: page.last_id.clone(),
+            ..Default::default()
+        });
+        let ids: Vec<_> = next.data.iter().map(|b| b.id.as_str()).collect();
+        assert_eq!(ids, ["b1"]);
+        assert!(!next.has_more);
+
+        let prev = store.list(&ListBatchesQuery {
+            limit: Some(2),
+            before_id: Some("b1".to_string()),
+            ..Default::default()
+        });
+        let ids: Vec<_> = prev.data.iter().map(|b| b.id.as_str()).collect();
+        assert_eq!(ids, ["b3", "b2"]);
+        assert!(!prev.has_more);
+    }
+}
@@ -0,0 +1,243 @@
+//! 请求体读取与 JSON 解析
+//!
+//! 替代 axum 的 `Json` 提取器：
+//! - 请求体超过 `maxRequestBodyMb` 时返回 Anthropic 格式的 413 `request_too_large`，
+//!   声明了 Content-Length 的请求在读取前即被拒绝
+//! - 超过 [`STREAM_PARSE_THRESHOLD`] 或未声明长度的请求体边接收边解析，
+//!   不在内存中同时保留完整原始请求体与解析结果，降低大图片 base64 请求的峰值内存
+//! - 读取或解析失败时返回 Anthropic 格式的 400 `invalid_request_error`
+
+use std::io::{self, BufReader, Read};
+
+use axum::{
+    body::Body,
+    extract::{FromRequest, Request},
+    http::{StatusCode, header},
+    response::{IntoResponse, Json, Response},
+};
+use bytes::{Buf, Bytes};
+use futures::StreamExt;
+use serde::de::DeserializeOwned;
+use tokio::sync::mpsc;
+
+use super::middleware::AppState;
+use super::types::ErrorResponse;
+
+/// 请求体不超过该大小时整体读取后解析，否则流式解析
+pub const STREAM_PARSE_THRESHOLD: usize = 1024 * 1024;
+
+/// 流式解析时在途的请求体分片数
+const STREAM_CHANNEL_CAPACITY: usize = 8;
+
+/// 流式解析的读缓冲区大小
+const READ_BUFFER_SIZE: usize = 64 * 1024;
+
+/// JSON 请求体提取器（大小上限取自 [`AppState::max_body_bytes`]）
+pub struct JsonBody<T>(pub T);
+
+impl<T> FromRequest<AppState> for JsonBody<T>
+where
+    T: DeserializeOwned + Send + 'static,
+{
+    type Rejection = Response;
+
+    async fn from_request(req: Request, state: &AppState) -> Result<Self, Self::Rejection> {
+        let limit = state.max_body_bytes;
+        let content_length = req
+            .headers()
+            .get(header::CONTENT_LENGTH)
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.parse::<usize>().ok());
+
+        let result = match content_length {
+            Some(len) if len > limit => Err(BodyError::TooLarge(limit)),
+            Some(len) if len <= STREAM_PARSE_THRESHOLD => {
+                parse_buffered(req.into_body(), len, limit).await
+            }
+            _ => parse_streaming(req.into_body(), limit).await,
+        };
+        result.map(JsonBody).map_err(IntoResponse::into_response)
+    }
+}
+
+#[derive(Debug)]
+enum BodyError {
+    /// 请求体超过上限（字节）
+    TooLarge(usize),
+    /// 读取请求体失败（如客户端断开）
+    Read(String),
+    /// JSON 格式或字段错误
+    Invalid(String),
+}
+
+impl IntoResponse for BodyError {
+    fn into_response(self) -> Response {
+        let (status, error_type, message) = match self {
+            BodyError::TooLarge(limit) => (
+                StatusCode::PAYLOAD_TOO_LARGE,
+                "request_too_large",
+                format!(
+                    "Request exceeds the maximum allowed number of bytes ({}).",
+                    limit
+                ),
+            ),
+            BodyError::Read(e) => (
+                StatusCode::BAD_REQUEST,
+                "invalid_request_error",
+                format!("Failed to read request body: {}", e),
+            ),
+            BodyError::Invalid(e) => (
+                StatusCode::BAD_REQUEST,
+                "invalid_request_error",
+                format!("Invalid request body: {}", e),
+            ),
+        };
+        (status, Json(ErrorResponse::new(error_type, message))).into_response()
+    }
+}
+
+/// 整体读取请求体（按 Content-Length 预分配）后解析
+async fn parse_buffered<T: DeserializeOwned>(
+    body: Body,
+    content_length: usize,
+    limit: usize,
+) -> Result<T, BodyError> {
+    let mut buffer = Vec::with_capacity(content_length);
+    let mut stream = body.into_data_stream();
+    while let Some(chunk) = stream.next().await {
+        let chunk = chunk.map_err(|e| BodyError::Read(e.to_string()))?;
+        if buffer.len() + chunk.len() > limit {
+            return Err(BodyError::TooLarge(limit));
+        }
+        buffer.extend_from_slice(&chunk);
+    }
+    serde_json::from_slice(&buffer).map_err(|e| BodyError::Invalid(e.to_string()))
+}
+
+/// 边接收边解析：分片经有界通道交给阻塞线程中的 `serde_json::from_reader`
+async fn parse_streaming<T>(body: Body, limit: usize) -> Result<T, BodyError>
+where
+    T: DeserializeOwned + Send + 'static,
+{
+    let (tx, rx) = mpsc::channel(STREAM_CHANNEL_CAPACITY);
+    let parser = tokio::task::spawn_blocking(move || {
+        let reader = BufReader::with_capacity(READ_BUFFER_SIZE, ChannelReader::new(rx));
+        serde_json::from_reader::<_, T>(reader)
+    });
+
+    let mut stream = body.into_data_stream();
+    let mut received = 0usize;
+    let mut failure = None;
+    while let Some(chunk) = stream.next().await {
+        let chunk = match chunk {
+            Ok(chunk) => chunk,
+            Err(e) => {
+                failure = Some(BodyError::Read(e.to_string()));
+                break;
+            }
+        };
+        received += chunk.len();
+        if received > limit {
+            failure = Some(BodyError::TooLarge(limit));
+            break;
+        }
+        // 解析线程已提前结束（JSON 语法错误），停止接收
+        if tx.send(chunk).await.is_err() {
+            break;
+        }
+    }
+    // 关闭通道：解析线程读到 EOF 后结束
+    drop(tx);
+
+    let parsed = parser.await.map_err(|e| BodyError::Read(e.to_string()))?;
+    if let Some(failure) = failure {
+        return Err(failure);
+    }
+    parsed.map_err(|e| BodyError::Invalid(e.to_string()))
+}
+
+/// 从通道读取请求体分片的同步 Reader（仅在阻塞线程中使用）
+struct ChannelReader {
+    rx: mpsc::Receiver<Bytes>,
+    chunk: Bytes,
+}
+
+impl ChannelReader {
+    fn new(rx: mpsc::Receiver<Bytes>) -> Self {
+        Self {
+            rx,
+            chunk: Bytes::new(),
+        }
+    }
+}
+
+impl Read for ChannelReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while !self.chunk.has_remaining() {
+            match self.rx.blocking_recv() {
+                Some(chunk) => self.chunk = chunk,
+                None => return Ok(0),
+            }
+        }
+        let n = buf.len().min(self.chunk.len());
+        buf[..n].copy_from_slice(&self.chunk[..n]);
+        self.chunk.advance(n);
+        Ok(n)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chunked_body(json: &str, chunk_size: usize) -> Body {
+        let chunks: Vec<Result<Bytes, io::Error>> = json
+            .as_bytes()
+            .chunks(chunk_size)
+            .map(|c| Ok(Bytes::copy_from_slice(c)))
+            .collect();
+        Body::from_stream(futures::stream::iter(chunks))
+    }
+
+    #[tokio::test]
+    async fn test_streaming_parse_matches_buffered() {
+        let json = serde_json::json!({
+            "model": "claude-sonnet-4",
+            "data": "A".repeat(200_000),
+            "items": [1, 2, 3]
+        })
+        .to_string();
+
+        let streamed: serde_json::Value = parse_streaming(chunked_body(&json, 4096), usize::MAX)
+            .await
+            .unwrap();
+        let buffered: serde_json::Value =
+            parse_buffered(Body::from(json.clone()), json.len(), usize::MAX)
+                .await
+                .unwrap();
+        assert_eq!(streamed, buffered);
+        assert_eq!(streamed["data"].as_str().unwrap().len(), 200_000);
+    }
+
+    #[tokio::test]
+    async fn test_body_limit_and_invalid_json() {
+        let json = serde_json::json!({ "data": "A".repeat(10_000) }).to_string();
+
+        let err = parse_streaming::<serde_json::Value>(chunked_body(&json, 1024), 4096)
+            .await
+            .unwrap_err();
+        assert!(matches!(err, BodyError::TooLarge(4096)));
+        assert_eq!(err.into_response().status(), StatusCode::PAYLOAD_TOO_LARGE);
+
+        let err = parse_buffered::<serde_json::Value>(Body::from(json.clone()), json.len(), 4096)
+            .await
+            .unwrap_err();
+        assert!(matches!(err, BodyError::TooLarge(4096)));
+
+        let err = parse_streaming::<serde_json::Value>(chunked_body("{\"a\": [1, 2", 4), 4096)
+            .await
+            .unwrap_err();
+        assert!(matches!(err, BodyError::Invalid(_)));
+        assert_eq!(err.into_response().status(), StatusCode::BAD_REQUEST);
+    }
+}
@@ -0,0 +1,161 @@
+//! 相同请求合并（request coalescing）
+//!
+//! 客户端在原请求仍在进行时重试完全相同的非流式请求，会对上游重复发起一次 Kiro 调用。
+//! 开启 `coalesceRequests` 后，以请求内容哈希为键记录进行中的请求：相同请求到达时
+//! 不调用上游，而是等待进行中的请求完成并复用其响应。
+//!
+//! 只有成功的响应会被复用；进行中的请求失败、超时或被客户端取消时，等待者各自照常请求上游。
+
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use parking_lot::Mutex;
+use serde_json::{Value, json};
+use sha2::{Digest, Sha256};
+use tokio::sync::watch;
+
+use super::response_cache::ResponseCache;
+use super::types::MessagesRequest;
+
+/// 标记响应复用了进行中的相同请求的响应头
+pub const COALESCED_HEADER: &str = "x-kiro-coalesced";
+
+type Slot = watch::Receiver<Option<Arc<Value>>>;
+
+/// 进行中请求的登记表
+#[derive(Debug, Default)]
+pub struct RequestCoalescer {
+    inflight: Mutex<HashMap<String, Slot>>,
+}
+
+/// 加入登记表的结果
+pub enum Joined {
+    /// 首个请求：负责调用上游并发布响应
+    Leader(Leader),
+    /// 已有相同请求在进行中：等待其响应
+    Follower(Follower),
+}
+
+impl RequestCoalescer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// 计算请求的合并键
+    ///
+    /// 在响应缓存键的基础上加入 metadata.user_id：只合并同一用户的重复请求
+    pub fn key(payload: &MessagesRequest) -> String {
+        let user_id = payload.metadata.as_ref().and_then(|m| m.user_id.as_deref());
+        let material = json!([ResponseCache::key(payload), user_id]);
+        hex::encode(Sha256::digest(material.to_string().as_bytes()))
+    }
+
+    /// 以合并键加入登记表
+    pub fn join(self: &Arc<Self>, key: String) -> Joined {
+        let mut inflight = self.inflight.lock();
+        if let Some(slot) = inflight.get(&key) {
+            return Joined::Follower(Follower { slot: slot.clone() });
+        }
+        let (tx, rx) = watch::channel(None);
+        inflight.insert(key.clone(), rx);
+        Joined::Leader(Leader {
+            coalescer: self.clone(),
+            key,
+            tx,
+        })
+    }
+}
+
+/// 负责调用上游的首个请求，释放时从登记表移除
+pub struct Leader {
+    coalescer: Arc<RequestCoalescer>,
+    key: String,
+    tx: watch::Sender<Option<Arc<Value>>>,
+}
+
+impl Leader {
+    /// 发布成功的响应体，唤醒所有等待者
+    pub fn complete(self, body: &Value) {
+        self.tx.send_replace(Some(Arc::new(body.clone())));
+    }
+}
+
+impl Drop for Leader {
+    fn drop(&mut self) {
+        self.coalescer.inflight.lock().remove(&self.key);
+    }
+}
+
+/// 等待相同请求完成的后到请求
+pub struct Follower {
+    slot: Slot,
+}
+
+impl Follower {
+    /// 等待首个请求的响应；首个请求未成功完成时返回 None
+    pub async fn wait(mut self) -> Option<Arc<Value>> {
+        self.slot
+            .wait_for(Option::is_some)
+            .await
+            .ok()
+            .and_then(|body| (*body).clone())
+    }
+}
+
+/// 把复用的响应改写为本次请求的响应（使用本次请求的消息 ID）
+pub fn as_coalesced(body: &Value, message_id: &str) -> Value {
+    let mut body = body.clone();
+    body["id"] = json!(message_id);
+    body
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::anthropic::response_cache::test_request;
+
+    #[test]
+    fn test_key_includes_user() {
+        assert_eq!(
+            RequestCoalescer::key(&test_request("hi", "a")),
+            RequestCoalescer::key(&test_request("hi", "a"))
+        );
+        assert_ne!(
+            RequestCoalescer::key(&test_request("hi", "a")),
+            RequestCoalescer::key(&test_request("hi", "b"))
+        );
+    }
+
+    #[tokio::test]
+    async fn test_follower_receives_leader_response() {
+        let coalescer = Arc::new(RequestCoalescer::new());
+        let Joined::Leader(leader) = coalescer.join("k".to_string()) else {
+            panic!("首个请求应成为 leader");
+        };
+        let Joined::Follower(follower) = coalescer.join("k".to_string()) else {
+            panic!("相同请求应等待 leader");
+        };
+        let waiting = tokio::spawn(follower.wait());
+
+        leader.complete(&json!({"id": "msg_leader"}));
+        let body = waiting.await.unwrap().unwrap();
+        assert_eq!(as_coalesced(&body, "msg_follower")["id"], "msg_follower");
+        assert!(coalescer.inflight.lock().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_failed_leader_releases_followers() {
+        let coalescer = Arc::new(RequestCoalescer::new());
+        let Joined::Leader(leader) = coalescer.join("k".to_string()) else {
+            panic!("首个请求应成为 leader");
+        };
+        let Joined::Follower(follower) = coalescer.join("k".to_string()) else {
+            panic!("相同请求应等待 leader");
+        };
+        drop(leader);
+        assert!(follower.wait().await.is_none());
+
+        // 失败的 leader 释放后，新的相同请求重新成为 leader
+        assert!(matches!(coalescer.join("k".to_string()), Joined::Leader(_)));
+    }
+}
@@ -2,7 +2,8 @@
 //!
 //! 负责将 Anthropic API 请求格式转换为 Kiro API 请求格式
 
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+use std::sync::atomic::{AtomicBool, Ordering};
 
 use sha2::{Digest, Sha256};
 use uuid::Uuid;
@@ -14,7 +15,10 @@ use crate::kiro::model::requests::conversation::{
 use crate::kiro::model::requests::tool::{
     InputSchema, Tool, ToolResult, ToolSpecification, ToolUseEntry,
 };
+use crate::kiro::model_registry::{self, ModelResolution};
 
+use super::image_compress;
+use super::tool_choice::ToolChoice;
 use super::types::{ContentBlock, MessagesRequest};
 
 /// 规范化 JSON Schema，修复 MCP 工具定义中常见的类型问题
@@ -105,15 +109,20 @@ pub fn map_model(model: &str) -> Option<String> {
     }
 }
 
+/// 解析请求模型：别名优先，其次内置映射，并按 Kiro 可用模型列表自动替换
+pub fn resolve_model(model: &str) -> Option<ModelResolution> {
+    model_registry::resolve(model, map_model(model))
+}
+
 /// 根据模型名称返回对应的上下文窗口大小
 ///
-/// 复用 `map_model` 的映射逻辑，确保窗口大小判断与模型映射一致。
-/// Kiro 于 2026-03-24 将 Opus 4.6 和 Sonnet 4.6 升级至 1M 上下文。
+/// 复用 `resolve_model` 的映射逻辑，确保窗口大小判断与模型映射一致，
+/// 窗口大小取自模型能力表（见 `kiro::model_registry`）。
 pub fn get_context_window_size(model: &str) -> i32 {
-    match map_model(model) {
-        Some(mapped) if mapped == "claude-sonnet-4.6" || mapped == "claude-opus-4.6" => 1_000_000,
-        _ => 200_000,
-    }
+    resolve_model(model)
+        .map(|r| model_registry::capabilities(&r.model_id))
+        .unwrap_or_default()
+        .context_window as i32
 }
 
 /// 转换结果
@@ -123,6 +132,10 @@ pub struct ConversionResult {
     pub conversation_state: ConversationState,
     /// 工具名称映射（短名称 → 原始名称），仅当存在超长工具名时非空
     pub tool_name_map: HashMap<String, String>,
+    /// 末尾 assistant 消息的 prefill 文本（响应中需去掉模型复述的部分）
+    pub prefill: Option<String>,
+    /// 模型解析结果（含是否被自动替换）
+    pub model: ModelResolution,
 }
 
 /// 转换错误
@@ -150,7 +163,7 @@ impl std::error::Error for ConversionError {}
 /// 2. JSON 格式: {"device_id":"...","account_uuid":"...","session_id":"UUID"}
 ///
 /// 提取 session UUID 作为 conversationId
-fn extract_session_id(user_id: &str) -> Option<String> {
+pub fn extract_session_id(user_id: &str) -> Option<String> {
     // 先尝试 JSON 解析
     if let Ok(json) = serde_json::from_str::<serde_json::Value>(user_id) {
         if let Some(session_id) = json.get("session_id").and_then(|v| v.as_str()) {
@@ -173,6 +186,66 @@ fn extract_session_id(user_id: &str) -> Option<String> {
     None
 }
 
+/// 用户标识的最大长度（字符），超出部分截断
+const MAX_USER_ID_CHARS: usize = 256;
+
+/// 从 metadata.user_id 中提取去掉 session 部分的用户标识
+///
+/// Claude Code 的 user_id 每个会话都不同，去掉 session 后同一用户（设备/账号）的请求
+/// 得到相同的标识，用于日志与用量统计：
+/// 1. 字符串格式: user_xxx_account__session_UUID → user_xxx_account
+/// 2. JSON 格式: {"device_id":"xxx","account_uuid":"","session_id":"UUID"} → user_xxx_account
+///
+/// 其他格式原样使用；空字符串返回 None
+pub fn extract_user_id(user_id: &str) -> Option<String> {
+    let json = serde_json::from_str::<serde_json::Value>(user_id).ok();
+    let identity = match json.as_ref().and_then(|v| v.get("device_id")?.as_str()) {
+        Some(device_id) => {
+            let account = json
+                .as_ref()
+                .and_then(|v| v.get("account_uuid")?.as_str())
+                .unwrap_or_default();
+            format!("user_{}_account_{}", device_id, account)
+                .trim_end_matches('_')
+                .to_string()
+        }
+        None => match user_id.find("_session_") {
+            Some(pos) => user_id[..pos].trim_end_matches('_').to_string(),
+            None => user_id.trim().to_string(),
+        },
+    };
+    if identity.is_empty() {
+        return None;
+    }
+    Some(identity.chars().take(MAX_USER_ID_CHARS).collect())
+}
+
+/// 提取 assistant prefill 文本
+///
+/// 仅支持纯文本内容（字符串或 text 块），包含 tool_use 等其他块时返回 None
+fn extract_prefill_text(msg: &super::types::Message) -> Option<String> {
+    let text = match &msg.content {
+        serde_json::Value::String(s) => s.clone(),
+        serde_json::Value::Array(blocks) => {
+            let mut text = String::new();
+            for block in blocks {
+                if block.get("type")?.as_str()? != "text" {
+                    return None;
+                }
+                text.push_str(block.get("text")?.as_str()?);
+            }
+            text
+        }
+        _ => return None,
+    };
+    let text = text.trim_end();
+    if text.trim().is_empty() {
+        None
+    } else {
+        Some(text.to_string())
+    }
+}
+
 /// 简单验证 UUID 格式（36 字符，包含 4 个连字符）
 fn is_valid_uuid(s: &str) -> bool {
     s.len() == 36 && s.chars().filter(|c| *c == '-').count() == 4
@@ -216,20 +289,45 @@ fn create_placeholder_tool(name: &str) -> Tool {
 }
 
 /// 将 Anthropic 请求转换为 Kiro 请求
+#[tracing::instrument(
+    name = "convert_request",
+    skip_all,
+    fields(model = %req.model, messages = req.messages.len())
+)]
 pub fn convert_request(req: &MessagesRequest) -> Result<ConversionResult, ConversionError> {
     // 1. 映射模型
-    let model_id = map_model(&req.model)
+    let resolution = resolve_model(&req.model)
         .ok_or_else(|| ConversionError::UnsupportedModel(req.model.clone()))?;
+    if let Some(replaced) = &resolution.replaced {
+        tracing::warn!(
+            model = %req.model,
+            "模型 {} 不在 Kiro 可用模型列表中，改用 {}",
+            replaced,
+            resolution.model_id
+        );
+    }
+    let model_id = resolution.model_id.clone();
 
     // 2. 检查消息列表
     if req.messages.is_empty() {
         return Err(ConversionError::EmptyMessages);
     }
 
-    // 2.5. 预处理 prefill：如果末尾是 assistant，静默丢弃并截断到最后一条 user
-    // Claude 4.x 已弃用 assistant prefill，Kiro API 也不支持
+    // 2.5. 预处理 prefill：如果末尾是 assistant，截断到最后一条 user
+    // Kiro API 不支持以 assistant 结尾，纯文本 prefill 改为附加到当前 user 消息的续写指令
+    let mut prefill = None;
     let messages: &[_] = if req.messages.last().is_some_and(|m| m.role != "user") {
-        tracing::info!("检测到末尾 assistant 消息（prefill），静默丢弃");
+        let thinking_enabled = req.thinking.as_ref().is_some_and(|t| t.is_enabled());
+        prefill = req
+            .messages
+            .last()
+            .and_then(extract_prefill_text)
+            .filter(|_| !thinking_enabled);
+        if prefill.is_some() {
+            tracing::info!("检测到末尾 assistant 消息（prefill），转换为续写指令");
+        } else {
+            tracing::info!("检测到末尾 assistant 消息（不支持的 prefill），静默丢弃");
+        }
         let last_user_idx = req
             .messages
             .iter()
@@ -261,17 +359,37 @@ pub fn convert_request(req: &MessagesRequest) -> Result<ConversionResult, Conver
     let mut tool_name_map = HashMap::new();
     let mut tools = convert_tools(&req.tools, &mut tool_name_map);
 
+    // 6.5. Kiro 不支持 tool_choice，通过裁剪工具列表并附加指令模拟
+    let tool_choice = match ToolChoice::parse(req.tool_choice.as_ref()) {
+        ToolChoice::Tool(name) => ToolChoice::Tool(map_tool_name(&name, &mut tool_name_map)),
+        other => other,
+    };
+    let tool_choice_instruction = tool_choice.apply(&mut tools);
+
     // 7. 构建历史消息（需要先构建，以便收集历史中使用的工具）
     let mut history = build_history(req, messages, &model_id, &mut tool_name_map)?;
 
     // 8. 验证并过滤 tool_use/tool_result 配对
+    // 修复模式下先合并重复的 tool_use_id
     // 移除孤立的 tool_result（没有对应的 tool_use）
     // 同时返回孤立的 tool_use_id 集合，用于后续清理
-    let (validated_tool_results, orphaned_tool_use_ids) =
+    let repair = TOOL_HISTORY_REPAIR.load(Ordering::Relaxed);
+    if repair {
+        dedup_tool_history(&mut history);
+    }
+    let (mut validated_tool_results, orphaned_tool_use_ids) =
         validate_tool_pairing(&history, &tool_results);
 
-    // 9. 从历史中移除孤立的 tool_use（Kiro API 要求 tool_use 必须有对应的 tool_result）
-    remove_orphaned_tool_uses(&mut history, &orphaned_tool_use_ids);
+    // 9. 处理孤立的 tool_use（Kiro API 要求 tool_use 必须有对应的 tool_result）
+    // 修复模式下补充占位 tool_result，否则从历史中移除
+    if repair {
+        validated_tool_results.extend(fill_orphaned_tool_results(
+            &mut history,
+            &orphaned_tool_use_ids,
+        ));
+    } else {
+        remove_orphaned_tool_uses(&mut history, &orphaned_tool_use_ids);
+    }
 
     // 10. 收集历史中使用的工具名称，为缺失的工具生成占位符定义
     // Kiro API 要求：历史消息中引用的工具必须在 tools 列表中有定义
@@ -299,7 +417,13 @@ pub fn convert_request(req: &MessagesRequest) -> Result<ConversionResult, Conver
 
     // 12. 构建当前消息
     // 保留文本内容，即使有工具结果也不丢弃用户文本
-    let content = text_content;
+    let mut content = text_content;
+    if let Some(instruction) = tool_choice_instruction {
+        content.push_str(&instruction);
+    }
+    if let Some(ref prefill) = prefill {
+        content.push_str(&super::prefill::continuation_instruction(prefill));
+    }
 
     let mut user_input = UserInputMessage::new(content, &model_id)
         .with_context(context)
@@ -329,6 +453,8 @@ pub fn convert_request(req: &MessagesRequest) -> Result<ConversionResult, Conver
     Ok(ConversionResult {
         conversation_state,
         tool_name_map,
+        prefill,
+        model: resolution,
     })
 }
 
@@ -352,6 +478,14 @@ fn process_message_content(
         }
         serde_json::Value::Array(arr) => {
             for item in arr {
+                // 图片块单独解析：非 base64 来源无法反序列化为 ContentBlock，需降级为占位文本
+                if item.get("type").and_then(|v| v.as_str()) == Some("image") {
+                    match convert_image_block(item) {
+                        Ok(image) => images.push(image),
+                        Err(placeholder) => text_parts.push(placeholder),
+                    }
+                    continue;
+                }
                 if let Ok(block) = serde_json::from_value::<ContentBlock>(item.clone()) {
                     match block.block_type.as_str() {
                         "text" => {
@@ -359,16 +493,12 @@ fn process_message_content(
                                 text_parts.push(text);
                             }
                         }
-                        "image" => {
-                            if let Some(source) = block.source {
-                                if let Some(format) = get_image_format(&source.media_type) {
-                                    images.push(KiroImage::from_base64(format, source.data));
-                                }
-                            }
-                        }
                         "tool_result" => {
                             if let Some(tool_use_id) = block.tool_use_id {
-                                let result_content = extract_tool_result_content(&block.content);
+                                let (result_content, result_images) =
+                                    extract_tool_result_content(&block.content);
+                                // Kiro 的 toolResult 只支持文本，图片随所在 user 消息一起发送
+                                images.extend(result_images);
                                 let is_error = block.is_error.unwrap_or(false);
 
                                 let mut result = if is_error {
@@ -407,24 +537,79 @@ fn get_image_format(media_type: &str) -> Option<String> {
     }
 }
 
-/// 提取工具结果内容
-fn extract_tool_result_content(content: &Option<serde_json::Value>) -> String {
+/// 工具结果中的图片移到所在 user 消息后，在结果文本中留下的说明
+const TOOL_RESULT_IMAGE_NOTE: &str = "[image attached to this message]";
+
+/// 转换 image 块
+///
+/// 仅支持 base64 来源的 jpeg/png/gif/webp，其他情况返回说明原因的占位文本；
+/// 配置 `imageCompression` 时超出上限的图片会被缩小并重新编码
+fn convert_image_block(item: &serde_json::Value) -> Result<KiroImage, String> {
+    let source = item.get("source");
+    let source_type = source
+        .and_then(|s| s.get("type"))
+        .and_then(|v| v.as_str())
+        .unwrap_or("unknown");
+    if source_type != "base64" {
+        tracing::warn!("不支持的图片来源类型 {}，已替换为占位文本", source_type);
+        return Err(format!("[image omitted: {} source is not supported]", source_type));
+    }
+    let media_type = source
+        .and_then(|s| s.get("media_type"))
+        .and_then(|v| v.as_str())
+        .unwrap_or_default();
+    let data = source.and_then(|s| s.get("data")).and_then(|v| v.as_str());
+    match (get_image_format(media_type), data) {
+        (Some(format), Some(data)) => Ok(match image_compress::compress(&format, data) {
+            Some((format, data)) => KiroImage::from_base64(format, data),
+            None => KiroImage::from_base64(format, data),
+        }),
+        _ => {
+            tracing::warn!("不支持的图片格式 {}，已替换为占位文本", media_type);
+            Err(format!("[image omitted: unsupported media type {}]", media_type))
+        }
+    }
+}
+
+/// 提取工具结果内容，返回（文本, 图片）
+///
+/// 图片在文本中以占位说明代替
+fn extract_tool_result_content(content: &Option<serde_json::Value>) -> (String, Vec<KiroImage>) {
     match content {
-        Some(serde_json::Value::String(s)) => s.clone(),
+        Some(serde_json::Value::String(s)) => (s.clone(), Vec::new()),
         Some(serde_json::Value::Array(arr)) => {
             let mut parts = Vec::new();
+            let mut images = Vec::new();
             for item in arr {
-                if let Some(text) = item.get("text").and_then(|v| v.as_str()) {
+                if item.get("type").and_then(|v| v.as_str()) == Some("image") {
+                    match convert_image_block(item) {
+                        Ok(image) => {
+                            images.push(image);
+                            parts.push(TOOL_RESULT_IMAGE_NOTE.to_string());
+                        }
+                        Err(placeholder) => parts.push(placeholder),
+                    }
+                } else if let Some(text) = item.get("text").and_then(|v| v.as_str()) {
                     parts.push(text.to_string());
                 }
             }
-            parts.join("\n")
+            (parts.join("\n"), images)
         }
-        Some(v) => v.to_string(),
-        None => String::new(),
+        Some(v) => (v.to_string(), Vec::new()),
+        None => (String::new(), Vec::new()),
     }
 }
 
+/// 修复模式下为孤立 tool_use 补充的占位结果内容
+const PLACEHOLDER_TOOL_RESULT: &str = "result unavailable";
+
+static TOOL_HISTORY_REPAIR: AtomicBool = AtomicBool::new(false);
+
+/// 设置是否修复工具调用历史（`repairToolHistory`）
+pub fn init_tool_history_repair(enabled: bool) {
+    TOOL_HISTORY_REPAIR.store(enabled, Ordering::Relaxed);
+}
+
 /// 验证并过滤 tool_use/tool_result 配对
 ///
 /// 收集所有 tool_use_id，验证 tool_result 是否匹配
@@ -501,7 +686,7 @@ fn validate_tool_pairing(
     // 5. 检测真正孤立的 tool_use（有 tool_use 但在历史和当前消息中都没有 tool_result）
     for orphaned_id in &unpaired_tool_use_ids {
         tracing::warn!(
-            "检测到孤立的 tool_use：找不到对应的 tool_result，将从历史中移除，tool_use_id={}",
+            "检测到孤立的 tool_use：找不到对应的 tool_result，tool_use_id={}",
             orphaned_id
         );
     }
@@ -545,6 +730,98 @@ fn remove_orphaned_tool_uses(
     }
 }
 
+/// 合并历史中重复的 tool_use_id
+///
+/// 客户端重发或拼接历史时，同一个 tool_use_id 可能出现多次，Kiro 会拒绝整段历史。
+/// 保留首次出现的 tool_use 与 tool_result，移除之后的重复项。
+fn dedup_tool_history(history: &mut [Message]) {
+    let mut seen_uses: HashSet<String> = HashSet::new();
+    let mut seen_results: HashSet<String> = HashSet::new();
+
+    for msg in history.iter_mut() {
+        match msg {
+            Message::Assistant(assistant_msg) => {
+                let tool_uses = &mut assistant_msg.assistant_response_message.tool_uses;
+                if let Some(uses) = tool_uses {
+                    uses.retain(|tu| {
+                        let first = seen_uses.insert(tu.tool_use_id.clone());
+                        if !first {
+                            tracing::warn!("合并重复的 tool_use，tool_use_id={}", tu.tool_use_id);
+                        }
+                        first
+                    });
+                    if uses.is_empty() {
+                        *tool_uses = None;
+                    }
+                }
+            }
+            Message::User(user_msg) => {
+                user_msg
+                    .user_input_message
+                    .user_input_message_context
+                    .tool_results
+                    .retain(|result| {
+                        let first = seen_results.insert(result.tool_use_id.clone());
+                        if !first {
+                            tracing::warn!(
+                                "合并重复的 tool_result，tool_use_id={}",
+                                result.tool_use_id
+                            );
+                        }
+                        first
+                    });
+            }
+        }
+    }
+}
+
+/// 为孤立的 tool_use 补充占位 tool_result
+///
+/// 占位结果写入该 tool_use 所在 assistant 消息之后的 user 消息；
+/// 历史末尾 assistant 消息的孤立 tool_use 由当前消息承接，以返回值交给调用方。
+fn fill_orphaned_tool_results(
+    history: &mut [Message],
+    orphaned_ids: &HashSet<String>,
+) -> Vec<ToolResult> {
+    let mut current = Vec::new();
+    if orphaned_ids.is_empty() {
+        return current;
+    }
+
+    for i in 0..history.len() {
+        let Message::Assistant(assistant_msg) = &history[i] else {
+            continue;
+        };
+        let placeholders: Vec<ToolResult> = assistant_msg
+            .assistant_response_message
+            .tool_uses
+            .iter()
+            .flatten()
+            .filter(|tu| orphaned_ids.contains(&tu.tool_use_id))
+            .map(|tu| {
+                tracing::debug!(
+                    "为孤立的 tool_use 补充占位 tool_result，tool_use_id={}",
+                    tu.tool_use_id
+                );
+                ToolResult::error(&tu.tool_use_id, PLACEHOLDER_TOOL_RESULT)
+            })
+            .collect();
+        if placeholders.is_empty() {
+            continue;
+        }
+        match history.get_mut(i + 1) {
+            Some(Message::User(user_msg)) => user_msg
+                .user_input_message
+                .user_input_message_context
+                .tool_results
+                .extend(placeholders),
+            _ => current.extend(placeholders),
+        }
+    }
+
+    current
+}
+
 /// Kiro API 工具名称最大长度限制
 const TOOL_NAME_MAX_LEN: usize = 63;
 
@@ -796,13 +1073,23 @@ fn convert_assistant_message(
         }
         serde_json::Value::Array(arr) => {
             for item in arr {
+                if item.get("type").and_then(|v| v.as_str()) == Some("image") {
+                    // Kiro 的 assistant 消息不支持图片，保留占位说明以免上下文断裂
+                    text_content.push_str("[image omitted from assistant message]");
+                    continue;
+                }
                 if let Ok(block) = serde_json::from_value::<ContentBlock>(item.clone()) {
                     match block.block_type.as_str() {
+                        // signature 仅对 Anthropic 有意义，思考内容以 <thinking> 标签回放给 Kiro
                         "thinking" => {
                             if let Some(thinking) = block.thinking {
                                 thinking_content.push_str(&thinking);
                             }
                         }
+                        // 加密的思考内容 Kiro 无法解读，固定丢弃
+                        "redacted_thinking" => {
+                            tracing::debug!("丢弃历史中的 redacted_thinking 块");
+                        }
                         "text" => {
                             if let Some(text) = block.text {
                                 text_content.push_str(&text);
@@ -974,6 +1261,7 @@ mod tests {
             thinking: None,
             output_config: None,
             metadata: None,
+            stop_sequences: None,
         };
         assert_eq!(determine_chat_trigger_type(&req), "MANUAL");
     }
@@ -1089,6 +1377,7 @@ mod tests {
             tool_choice: None,
             output_config: None,
             metadata: None,
+            stop_sequences: None,
         };
 
         let result = convert_request(&req).unwrap();
@@ -1107,6 +1396,48 @@ mod tests {
         assert_eq!(tools[0].tool_specification.name, *short);
     }
 
+    #[test]
+    fn test_tool_choice_forces_mapped_tool() {
+        use super::super::types::{Message as AnthropicMessage, Tool as AnthropicTool};
+
+        let long_tool_name = "mcp__plugin_very_long_server_name__extremely_long_tool_name_exceeds_63";
+        let tool = |name: &str| AnthropicTool {
+            name: name.to_string(),
+            description: "A test tool".to_string(),
+            input_schema: std::collections::HashMap::new(),
+            tool_type: None,
+            max_uses: None,
+        };
+
+        let req = MessagesRequest {
+            model: "claude-sonnet-4".to_string(),
+            max_tokens: 1024,
+            messages: vec![AnthropicMessage {
+                role: "user".to_string(),
+                content: serde_json::json!("test"),
+            }],
+            system: None,
+            stream: false,
+            tools: Some(vec![tool("Read"), tool(long_tool_name)]),
+            thinking: None,
+            tool_choice: Some(serde_json::json!({"type": "tool", "name": long_tool_name})),
+            output_config: None,
+            metadata: None,
+            stop_sequences: None,
+        };
+
+        let result = convert_request(&req).unwrap();
+        let short = result.tool_name_map.iter().next().unwrap().0.clone();
+        let message = &result.conversation_state.current_message.user_input_message;
+
+        // 只保留被强制调用的工具，并在当前消息中要求调用它
+        let tools = &message.user_input_message_context.tools;
+        assert_eq!(tools.len(), 1);
+        assert_eq!(tools[0].tool_specification.name, short);
+        assert!(message.content.starts_with("test"));
+        assert!(message.content.contains(&format!("`{}`", short)));
+    }
+
     #[test]
     fn test_tool_name_mapping_in_history() {
         use super::super::types::{Message as AnthropicMessage, Tool as AnthropicTool};
@@ -1152,6 +1483,7 @@ mod tests {
             tool_choice: None,
             output_config: None,
             metadata: None,
+            stop_sequences: None,
         };
 
         let result = convert_request(&req).unwrap();
@@ -1209,6 +1541,7 @@ mod tests {
             thinking: None,
             output_config: None,
             metadata: None,
+            stop_sequences: None,
         };
 
         let result = convert_request(&req).unwrap();
@@ -1274,6 +1607,30 @@ mod tests {
         assert_eq!(session_id, None);
     }
 
+    #[test]
+    fn test_extract_user_id_strips_session() {
+        let device = "0dede55c6dcc4a11a30bbb5e7f22e6fdf86cdeba3820019cc27612af4e1243cd";
+        let expected = Some(format!("user_{}_account", device));
+        let legacy = format!(
+            "user_{}_account__session_8bb5523b-ec7c-4540-a9ca-beb6d79f1552",
+            device
+        );
+        assert_eq!(extract_user_id(&legacy), expected);
+        let json = format!(
+            r#"{{"device_id":"{}","account_uuid":"","session_id":"8bb5523b-ec7c-4540-a9ca-beb6d79f1552"}}"#,
+            device
+        );
+        assert_eq!(extract_user_id(&json), expected);
+
+        let json = r#"{"device_id":"abc","account_uuid":"acc-1","session_id":"x"}"#;
+        assert_eq!(
+            extract_user_id(json),
+            Some("user_abc_account_acc-1".to_string())
+        );
+        assert_eq!(extract_user_id("alice"), Some("alice".to_string()));
+        assert_eq!(extract_user_id(" "), None);
+    }
+
     #[test]
     fn test_convert_request_with_session_metadata() {
         use super::super::types::{Message as AnthropicMessage, Metadata};
@@ -1297,6 +1654,7 @@ mod tests {
                     "user_0dede55c6dcc4a11a30bbb5e7f22e6fdf86cdeba3820019cc27612af4e1243cd_account__session_a0662283-7fd3-4399-a7eb-52b9a717ae88".to_string(),
                 ),
             }),
+            stop_sequences: None,
         };
 
         let result = convert_request(&req).unwrap();
@@ -1325,6 +1683,7 @@ mod tests {
             thinking: None,
             output_config: None,
             metadata: None,
+            stop_sequences: None,
         };
 
         let result = convert_request(&req).unwrap();
@@ -1679,6 +2038,26 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_assistant_thinking_history_with_signature() {
+        use super::super::types::Message as AnthropicMessage;
+
+        let msg = AnthropicMessage {
+            role: "assistant".to_string(),
+            content: serde_json::json!([
+                {"type": "redacted_thinking", "data": "EmwKAhgBEgy..."},
+                {"type": "thinking", "thinking": "Check the file.", "signature": "sig-1"},
+                {"type": "text", "text": "Reading it now."}
+            ]),
+        };
+
+        let result = convert_assistant_message(&msg, &mut HashMap::new()).unwrap();
+        assert_eq!(
+            result.assistant_response_message.content,
+            "<thinking>Check the file.</thinking>\n\nReading it now."
+        );
+    }
+
     #[test]
     fn test_merge_consecutive_assistant_messages() {
         // 测试连续 assistant 消息被正确合并（Issue #79）
@@ -1755,6 +2134,7 @@ mod tests {
             thinking: None,
             output_config: None,
             metadata: None,
+            stop_sequences: None,
         };
 
         let result = convert_request(&req);
@@ -1774,4 +2154,194 @@ mod tests {
         }
         assert!(found_tool_use, "合并后的 assistant 消息应包含 tool_use");
     }
+
+    #[test]
+    fn test_assistant_prefill_becomes_continuation() {
+        use super::super::types::Message as AnthropicMessage;
+
+        let mut req = MessagesRequest {
+            model: "claude-sonnet-4".to_string(),
+            max_tokens: 1024,
+            messages: vec![
+                AnthropicMessage {
+                    role: "user".to_string(),
+                    content: serde_json::json!("Describe Rust as JSON"),
+                },
+                AnthropicMessage {
+                    role: "assistant".to_string(),
+                    content: serde_json::json!([{"type": "text", "text": "{\"name\": "}]),
+                },
+            ],
+            stream: false,
+            system: None,
+            tools: None,
+            tool_choice: None,
+            thinking: None,
+            output_config: None,
+            metadata: None,
+            stop_sequences: None,
+        };
+
+        let result = convert_request(&req).unwrap();
+        assert_eq!(result.prefill.as_deref(), Some("{\"name\":"));
+        let content = &result
+            .conversation_state
+            .current_message
+            .user_input_message
+            .content;
+        assert!(content.starts_with("Describe Rust as JSON"));
+        assert!(content.contains("<assistant_prefill>\n{\"name\":\n</assistant_prefill>"));
+
+        // thinking 模式下不支持 prefill，仍然丢弃
+        req.thinking = Some(super::super::types::Thinking {
+            thinking_type: "enabled".to_string(),
+            budget_tokens: 1024,
+        });
+        assert!(convert_request(&req).unwrap().prefill.is_none());
+
+        // 含 tool_use 的 assistant 消息不作为 prefill
+        req.thinking = None;
+        req.messages[1].content = serde_json::json!([
+            {"type": "tool_use", "id": "toolu_1", "name": "read", "input": {}}
+        ]);
+        assert!(convert_request(&req).unwrap().prefill.is_none());
+    }
+
+    #[test]
+    fn test_tool_result_images_moved_to_user_message() {
+        let content = serde_json::json!([
+            {"type": "tool_result", "tool_use_id": "toolu_1", "content": [
+                {"type": "text", "text": "screenshot taken"},
+                {"type": "image", "source": {"type": "base64", "media_type": "image/png", "data": "AAAA"}}
+            ]}
+        ]);
+
+        let (_, images, tool_results) = process_message_content(&content).unwrap();
+        assert_eq!(images.len(), 1);
+        assert_eq!(images[0].format, "png");
+        assert_eq!(tool_results.len(), 1);
+        let text = tool_results[0].content[0]["text"].as_str().unwrap();
+        assert!(text.contains("screenshot taken"));
+        assert!(text.contains(TOOL_RESULT_IMAGE_NOTE));
+    }
+
+    #[test]
+    fn test_unsupported_image_source_becomes_placeholder() {
+        let content = serde_json::json!([
+            {"type": "image", "source": {"type": "url", "url": "https://example.com/a.png"}},
+            {"type": "image", "source": {"type": "base64", "media_type": "image/bmp", "data": "AAAA"}}
+        ]);
+
+        let (text, images, _) = process_message_content(&content).unwrap();
+        assert!(images.is_empty());
+        assert!(text.contains("url source is not supported"));
+        assert!(text.contains("unsupported media type image/bmp"));
+    }
+
+    #[test]
+    fn test_assistant_image_becomes_placeholder() {
+        let msg = super::super::types::Message {
+            role: "assistant".to_string(),
+            content: serde_json::json!([
+                {"type": "text", "text": "Here is the chart:"},
+                {"type": "image", "source": {"type": "base64", "media_type": "image/png", "data": "AAAA"}}
+            ]),
+        };
+
+        let result = convert_assistant_message(&msg, &mut HashMap::new()).unwrap();
+        let content = &result.assistant_response_message.content;
+        assert!(content.contains("Here is the chart:"));
+        assert!(content.contains("[image omitted from assistant message]"));
+    }
+
+    fn assistant_with_tools(ids: &[&str]) -> Message {
+        use crate::kiro::model::requests::tool::ToolUseEntry;
+
+        let tool_uses = ids
+            .iter()
+            .map(|id| ToolUseEntry::new(*id, "read").with_input(serde_json::json!({})))
+            .collect();
+        Message::Assistant(HistoryAssistantMessage {
+            assistant_response_message: AssistantMessage::new("calling tools")
+                .with_tool_uses(tool_uses),
+        })
+    }
+
+    fn user_with_results(ids: &[&str]) -> Message {
+        let mut msg = HistoryUserMessage::new("results", "claude-sonnet-4.5");
+        msg.user_input_message
+            .user_input_message_context
+            .tool_results = ids
+            .iter()
+            .map(|id| ToolResult::success(*id, "ok"))
+            .collect();
+        Message::User(msg)
+    }
+
+    fn result_ids(msg: &Message) -> Vec<String> {
+        match msg {
+            Message::User(u) => u
+                .user_input_message
+                .user_input_message_context
+                .tool_results
+                .iter()
+                .map(|r| r.tool_use_id.clone())
+                .collect(),
+            Message::Assistant(_) => Vec::new(),
+        }
+    }
+
+    #[test]
+    fn test_fill_orphaned_tool_results_with_placeholders() {
+        let mut history = vec![
+            user_with_results(&[]),
+            assistant_with_tools(&["tool-1", "tool-2"]),
+            user_with_results(&["tool-1"]),
+            assistant_with_tools(&["tool-3"]),
+        ];
+        let (_, orphaned) = validate_tool_pairing(&history, &[]);
+
+        let current = fill_orphaned_tool_results(&mut history, &orphaned);
+
+        // 中间的孤立 tool_use 在下一条 user 消息中补充占位结果
+        assert_eq!(result_ids(&history[2]), vec!["tool-1", "tool-2"]);
+        // 末尾 assistant 的孤立 tool_use 由当前消息承接
+        assert_eq!(current.len(), 1);
+        assert_eq!(current[0].tool_use_id, "tool-3");
+        assert_eq!(current[0].content[0]["text"], PLACEHOLDER_TOOL_RESULT);
+        assert_eq!(current[0].status.as_deref(), Some("error"));
+
+        // 补充后所有 tool_use 均已配对
+        let (_, orphaned) = validate_tool_pairing(&history, &current);
+        assert!(orphaned.is_empty());
+    }
+
+    #[test]
+    fn test_dedup_tool_history_keeps_first_occurrence() {
+        let mut history = vec![
+            user_with_results(&[]),
+            assistant_with_tools(&["tool-1"]),
+            user_with_results(&["tool-1"]),
+            assistant_with_tools(&["tool-1", "tool-2"]),
+            user_with_results(&["tool-1", "tool-2"]),
+            assistant_with_tools(&["tool-2"]),
+        ];
+
+        dedup_tool_history(&mut history);
+
+        let tool_use_ids = |msg: &Message| match msg {
+            Message::Assistant(a) => a
+                .assistant_response_message
+                .tool_uses
+                .as_ref()
+                .map(|uses| uses.iter().map(|tu| tu.tool_use_id.clone()).collect()),
+            Message::User(_) => None,
+        };
+        assert_eq!(tool_use_ids(&history[1]), Some(vec!["tool-1".to_string()]));
+        assert_eq!(tool_use_ids(&history[3]), Some(vec!["tool-2".to_string()]));
+        assert_eq!(tool_use_ids(&history[5]), None);
+        assert_eq!(result_ids(&history[4]), vec!["tool-2"]);
+        let (_, orphaned) = validate_tool_pairing(&history, &[]);
+        assert!(orphaned.is_empty());
+    }
 }
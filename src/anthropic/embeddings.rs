@@ -0,0 +1,82 @@
+//! OpenAI 兼容的 Embeddings 转发
+//!
+//! Kiro 不提供 embeddings，配置 `embeddings` 后 `POST /v1/embeddings` 原样转发到
+//! 外部 OpenAI 兼容接口，客户端只需配置 kiro.rs 一个 base URL。
+
+use std::sync::OnceLock;
+
+use anyhow::Context;
+use serde_json::Value;
+
+use crate::http_client::{ProxyConfig, build_client};
+use crate::model::config::{EmbeddingsConfig, TlsBackend};
+
+static BACKEND: OnceLock<EmbeddingsBackend> = OnceLock::new();
+
+/// 已配置的 embeddings 上游
+pub struct EmbeddingsBackend {
+    config: EmbeddingsConfig,
+    client: reqwest::Client,
+}
+
+/// 初始化 embeddings 上游（未配置时不做任何事）
+///
+/// 应在应用启动时调用一次
+pub fn init(
+    config: Option<&EmbeddingsConfig>,
+    proxy: Option<&ProxyConfig>,
+    tls_backend: TlsBackend,
+) -> anyhow::Result<()> {
+    let Some(config) = config else {
+        return Ok(());
+    };
+    if config.url.trim().is_empty() {
+        anyhow::bail!("embeddings.url 不能为空");
+    }
+    let client = build_client(proxy, config.timeout_secs, tls_backend)?;
+    let _ = BACKEND.set(EmbeddingsBackend {
+        config: config.clone(),
+        client,
+    });
+    Ok(())
+}
+
+/// 获取已配置的 embeddings 上游
+pub fn get() -> Option<&'static EmbeddingsBackend> {
+    BACKEND.get()
+}
+
+impl EmbeddingsBackend {
+    /// 转发 embeddings 请求，返回上游原始响应（非 2xx 状态同样返回，由调用方透传）
+    pub async fn forward(&self, mut body: Value) -> anyhow::Result<reqwest::Response> {
+        apply_model_override(&mut body, self.config.model.as_deref());
+        let mut request = self.client.post(&self.config.url).json(&body);
+        if let Some(api_key) = &self.config.api_key {
+            request = request.bearer_auth(api_key);
+        }
+        request.send().await.context("embeddings 上游请求失败")
+    }
+}
+
+/// 配置了固定模型时覆盖请求中的 model
+fn apply_model_override(body: &mut Value, model: Option<&str>) {
+    if let (Some(model), Some(obj)) = (model, body.as_object_mut()) {
+        obj.insert("model".to_string(), Value::String(model.to_string()));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_model_override() {
+        let mut body = json!({"model": "text-embedding-ada-002", "input": "hi"});
+        apply_model_override(&mut body, None);
+        assert_eq!(body["model"], "text-embedding-ada-002");
+
+        apply_model_override(&mut body, Some("bge-m3"));
+        assert_eq!(body, json!({"model": "bge-m3", "input": "hi"}));
+    }
+}
@@ -0,0 +1,190 @@
+//! 故障注入
+//!
+//! 用于客户端集成测试：配置 `faultInjection` 后，按规则的概率让匹配路径的请求返回模拟的
+//! 上游错误（401 / 429 / 500 / 模型不可用），或让流式响应变慢、中途断开。
+//! 错误类故障在调用上游前直接返回，不占用凭据，也不影响凭据的健康状态；
+//! 流式故障只改写返回给客户端的事件流。注入的响应带有 `x-kiro-fault` 响应头。
+
+use std::time::Duration;
+
+use axum::{
+    body::Body,
+    http::{HeaderValue, StatusCode, header},
+    response::{IntoResponse, Json, Response},
+};
+use futures::StreamExt;
+
+use crate::model::config::{FaultKind, FaultRule};
+
+use super::types::ErrorResponse;
+
+/// 标记注入故障类型的响应头
+pub const FAULT_HEADER: &str = "x-kiro-fault";
+
+/// 按规则注入故障
+#[derive(Debug)]
+pub struct FaultInjector {
+    rules: Vec<FaultRule>,
+}
+
+impl FaultInjector {
+    /// 创建故障注入器，没有规则时返回 None
+    pub fn new(rules: Vec<FaultRule>) -> Option<Self> {
+        (!rules.is_empty()).then_some(Self { rules })
+    }
+
+    /// 按顺序评估规则，返回第一条路径匹配且按概率命中的规则
+    pub fn pick(&self, path: &str) -> Option<&FaultRule> {
+        self.pick_with(path, fastrand::f64)
+    }
+
+    fn pick_with(&self, path: &str, mut roll: impl FnMut() -> f64) -> Option<&FaultRule> {
+        self.rules.iter().find(|rule| {
+            (rule.paths.is_empty() || rule.paths.iter().any(|p| path.starts_with(p.as_str())))
+                && roll() < rule.probability
+        })
+    }
+}
+
+/// 错误类故障的响应（流式故障返回 None，需要在上游响应上施加）
+pub fn error_response(fault: FaultKind) -> Option<Response> {
+    let (status, error_type, message) = match fault {
+        FaultKind::Unauthorized => (
+            StatusCode::UNAUTHORIZED,
+            "authentication_error",
+            "Invalid bearer token (injected fault).",
+        ),
+        FaultKind::RateLimited => (
+            StatusCode::TOO_MANY_REQUESTS,
+            "rate_limit_error",
+            "Rate limit exceeded (injected fault).",
+        ),
+        FaultKind::ServerError => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "api_error",
+            "Internal server error (injected fault).",
+        ),
+        FaultKind::InvalidModel => (
+            StatusCode::BAD_REQUEST,
+            "invalid_request_error",
+            "The requested model is not available upstream.",
+        ),
+        FaultKind::SlowStream | FaultKind::TruncatedStream => return None,
+    };
+    let mut response = (status, Json(ErrorResponse::new(error_type, message))).into_response();
+    if fault == FaultKind::RateLimited {
+        response
+            .headers_mut()
+            .insert(header::RETRY_AFTER, HeaderValue::from(1));
+    }
+    mark(&mut response, fault);
+    Some(response)
+}
+
+/// 对流式响应施加故障（非 SSE 响应原样返回）
+pub fn wrap_stream(response: Response, rule: &FaultRule) -> Response {
+    let is_stream = response
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|v| v.starts_with("text/event-stream"));
+    if !is_stream {
+        return response;
+    }
+
+    let (parts, body) = response.into_parts();
+    let stream = body.into_data_stream();
+    let body = match rule.fault {
+        FaultKind::SlowStream => {
+            let delay = Duration::from_millis(rule.delay_ms);
+            Body::from_stream(stream.then(move |chunk| async move {
+                tokio::time::sleep(delay).await;
+                chunk
+            }))
+        }
+        FaultKind::TruncatedStream => Body::from_stream(stream.take(rule.after_events)),
+        _ => Body::from_stream(stream),
+    };
+    let mut response = Response::from_parts(parts, body);
+    mark(&mut response, rule.fault);
+    response
+}
+
+fn mark(response: &mut Response, fault: FaultKind) {
+    response
+        .headers_mut()
+        .insert(FAULT_HEADER, HeaderValue::from_static(fault_name(fault)));
+}
+
+fn fault_name(fault: FaultKind) -> &'static str {
+    match fault {
+        FaultKind::Unauthorized => "unauthorized",
+        FaultKind::RateLimited => "rateLimited",
+        FaultKind::ServerError => "serverError",
+        FaultKind::InvalidModel => "invalidModel",
+        FaultKind::SlowStream => "slowStream",
+        FaultKind::TruncatedStream => "truncatedStream",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rule(paths: &[&str], fault: FaultKind, probability: f64) -> FaultRule {
+        FaultRule {
+            paths: paths.iter().map(|p| p.to_string()).collect(),
+            fault,
+            probability,
+            delay_ms: 0,
+            after_events: 2,
+        }
+    }
+
+    #[test]
+    fn test_pick_matches_path_and_probability() {
+        let injector = FaultInjector::new(vec![
+            rule(&["/v1/messages"], FaultKind::ServerError, 0.5),
+            rule(&[], FaultKind::Unauthorized, 1.0),
+        ])
+        .unwrap();
+
+        let picked = injector.pick_with("/v1/messages", || 0.2).unwrap();
+        assert_eq!(picked.fault, FaultKind::ServerError);
+        // 第一条未命中概率时继续评估后续规则
+        let picked = injector.pick_with("/v1/messages", || 0.7).unwrap();
+        assert_eq!(picked.fault, FaultKind::Unauthorized);
+        let picked = injector.pick_with("/v1/models", || 0.2).unwrap();
+        assert_eq!(picked.fault, FaultKind::Unauthorized);
+
+        let injector = FaultInjector::new(vec![rule(&[], FaultKind::ServerError, 0.0)]).unwrap();
+        assert!(injector.pick_with("/v1/messages", || 0.0).is_none());
+        assert!(FaultInjector::new(Vec::new()).is_none());
+    }
+
+    #[test]
+    fn test_error_response() {
+        let response = error_response(FaultKind::RateLimited).unwrap();
+        assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(response.headers()[FAULT_HEADER], "rateLimited");
+        assert!(response.headers().contains_key(header::RETRY_AFTER));
+        assert!(error_response(FaultKind::TruncatedStream).is_none());
+    }
+
+    #[tokio::test]
+    async fn test_truncated_stream() {
+        let events = ["event: a\n\n", "event: b\n\n", "event: c\n\n"];
+        let response = Response::builder()
+            .header(header::CONTENT_TYPE, "text/event-stream")
+            .body(Body::from_stream(futures::stream::iter(
+                events.map(Ok::<_, std::io::Error>),
+            )))
+            .unwrap();
+        let response = wrap_stream(response, &rule(&[], FaultKind::TruncatedStream, 1.0));
+        assert_eq!(response.headers()[FAULT_HEADER], "truncatedStream");
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        assert_eq!(&body[..], b"event: a\n\nevent: b\n\n");
+    }
+}
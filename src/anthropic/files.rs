@@ -0,0 +1,622 @@
+//! Files API 模拟
+//!
+//! 实现 Anthropic Files API 的上传、列出、查询与删除（`/v1/files`），文件保存在本地目录
+//! （默认为凭据文件所在目录下的 `kiro_files/`），元数据写入同目录的 `files.json`。
+//!
+//! Kiro 不支持文件引用，Messages 请求中 `source.type` 为 `file` 的 image / document 块
+//! 在转换前按 `file_id` 读取本地文件内联：
+//! - 图片改写为 base64 来源
+//! - 文本类文档（`text/*`、JSON、XML 等）改写为带文件名的 text 块
+//! - 其他文档（如 PDF）Kiro 无法处理，替换为说明原因的占位文本
+
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use chrono::{DateTime, Utc};
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use serde_json::{Value, json};
+use tokio::io::AsyncWriteExt;
+
+use crate::common::atomic_file;
+use crate::model::config::FilesConfig;
+
+use super::types::Message;
+
+/// 元数据索引文件名
+const INDEX_FILE: &str = "files.json";
+
+/// 列出文件时的默认与最大条数
+const DEFAULT_LIST_LIMIT: usize = 20;
+const MAX_LIST_LIMIT: usize = 1000;
+
+static STORE: OnceLock<FileStore> = OnceLock::new();
+
+/// 文件元数据（与 Anthropic 的 file 对象一致）
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FileObject {
+    pub id: String,
+    #[serde(rename = "type")]
+    pub object_type: String,
+    pub filename: String,
+    pub mime_type: String,
+    pub size_bytes: u64,
+    pub created_at: DateTime<Utc>,
+    /// 上传的文件不可下载（与 Anthropic 一致）
+    pub downloadable: bool,
+}
+
+/// 文件列表（与 Anthropic 的分页格式一致）
+#[derive(Debug, Serialize)]
+pub struct FileList {
+    pub data: Vec<FileObject>,
+    pub first_id: Option<String>,
+    pub last_id: Option<String>,
+    pub has_more: bool,
+}
+
+/// 文件列表查询参数
+#[derive(Debug, Default, Deserialize)]
+pub struct ListFilesQuery {
+    pub limit: Option<usize>,
+    /// 返回该文件之后（更早上传）的文件
+    pub after_id: Option<String>,
+    /// 返回该文件之前（更晚上传）的文件
+    pub before_id: Option<String>,
+}
+
+/// 文件操作错误
+#[derive(Debug)]
+pub enum FileError {
+    /// 未配置 `files`
+    Disabled,
+    /// 文件不存在
+    NotFound(String),
+    /// 单个文件超过大小上限（字节）
+    TooLarge(u64),
+    /// 存储总量超过上限（字节）
+    StorageFull(u64),
+    /// 请求不合法
+    Invalid(String),
+    /// 读写本地文件失败
+    Io(String),
+}
+
+impl fmt::Display for FileError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FileError::Disabled => write!(f, "Files API is not enabled"),
+            FileError::NotFound(id) => write!(f, "File not found: {}", id),
+            FileError::TooLarge(limit) => {
+                write!(f, "File exceeds the maximum allowed size ({} bytes)", limit)
+            }
+            FileError::StorageFull(limit) => {
+                write!(f, "File storage limit exceeded ({} bytes)", limit)
+            }
+            FileError::Invalid(msg) => write!(f, "{}", msg),
+            FileError::Io(msg) => write!(f, "File storage error: {}", msg),
+        }
+    }
+}
+
+impl std::error::Error for FileError {}
+
+impl From<std::io::Error> for FileError {
+    fn from(e: std::io::Error) -> Self {
+        FileError::Io(e.to_string())
+    }
+}
+
+struct FileStore {
+    dir: PathBuf,
+    max_file_bytes: u64,
+    max_total_bytes: u64,
+    files: Mutex<BTreeMap<String, FileObject>>,
+}
+
+/// 初始化文件存储（未配置时不做任何事），加载已有文件的元数据
+///
+/// `default_dir` 为未配置 `dir` 时使用的目录。索引无法解析时备份后按目录中的文件重建，
+/// 索引中缺少的文件（如写入索引前进程退出）同样重新登记，使其计入存储用量并可通过 API 删除。
+pub fn init(config: Option<&FilesConfig>, default_dir: Option<PathBuf>) -> anyhow::Result<()> {
+    let Some(config) = config else {
+        return Ok(());
+    };
+    let Some(dir) = config.dir.as_ref().map(PathBuf::from).or(default_dir) else {
+        anyhow::bail!("files.dir 未配置且无法确定凭据文件所在目录");
+    };
+    std::fs::create_dir_all(&dir)?;
+
+    let index = dir.join(INDEX_FILE);
+    let mut files: BTreeMap<String, FileObject> = atomic_file::load_json(&index)
+        .map_err(|e| anyhow::anyhow!("读取 {} 失败: {}", index.display(), e))?
+        .unwrap_or_default();
+    // 丢弃文件内容已不存在的记录
+    files.retain(|id, _| dir.join(id).is_file());
+    let recovered = recover_unindexed(&dir, &mut files)?;
+    if recovered > 0 {
+        tracing::warn!(
+            "{} 中有 {} 个文件不在索引内，已重新登记（原文件名与类型无法恢复）",
+            dir.display(),
+            recovered
+        );
+        write_index(&dir, &files)?;
+    }
+
+    let _ = STORE.set(FileStore {
+        dir,
+        max_file_bytes: config.max_file_mb.saturating_mul(1024 * 1024),
+        max_total_bytes: config.max_total_mb.saturating_mul(1024 * 1024),
+        files: Mutex::new(files),
+    });
+    Ok(())
+}
+
+/// 把目录中不在索引内的文件登记到索引，返回登记的数量
+fn recover_unindexed(
+    dir: &Path,
+    files: &mut BTreeMap<String, FileObject>,
+) -> std::io::Result<usize> {
+    let mut recovered = 0;
+    for entry in std::fs::read_dir(dir)? {
+        let entry = entry?;
+        let id = entry.file_name().to_string_lossy().into_owned();
+        // 上传中的 `.part` 临时文件与索引文件本身不是已提交的文件
+        if !id.starts_with("file_") || id.contains('.') || files.contains_key(&id) {
+            continue;
+        }
+        let metadata = entry.metadata()?;
+        if !metadata.is_file() {
+            continue;
+        }
+        let created_at = metadata
+            .modified()
+            .map(DateTime::<Utc>::from)
+            .unwrap_or_else(|_| Utc::now());
+        files.insert(
+            id.clone(),
+            FileObject {
+                id: id.clone(),
+                object_type: "file".to_string(),
+                filename: id,
+                mime_type: "application/octet-stream".to_string(),
+                size_bytes: metadata.len(),
+                created_at,
+                downloadable: false,
+            },
+        );
+        recovered += 1;
+    }
+    Ok(recovered)
+}
+
+fn write_index(dir: &Path, files: &BTreeMap<String, FileObject>) -> std::io::Result<()> {
+    let json = serde_json::to_string_pretty(files)?;
+    atomic_file::write(&dir.join(INDEX_FILE), json.as_bytes())
+}
+
+fn store() -> Result<&'static FileStore, FileError> {
+    STORE.get().ok_or(FileError::Disabled)
+}
+
+/// 单个文件的大小上限（字节，未启用时为 None）
+pub fn max_file_bytes() -> Option<u64> {
+    STORE.get().map(|s| s.max_file_bytes)
+}
+
+impl FileStore {
+    fn used_bytes(&self, files: &BTreeMap<String, FileObject>) -> u64 {
+        files.values().map(|f| f.size_bytes).sum()
+    }
+
+    fn persist(&self, files: &BTreeMap<String, FileObject>) -> Result<(), FileError> {
+        write_index(&self.dir, files)?;
+        Ok(())
+    }
+
+    fn get(&self, id: &str) -> Result<FileObject, FileError> {
+        self.files
+            .lock()
+            .get(id)
+            .cloned()
+            .ok_or_else(|| FileError::NotFound(id.to_string()))
+    }
+}
+
+/// 上传中的文件（边接收边写入临时文件，未提交时删除）
+pub struct Upload {
+    store: &'static FileStore,
+    id: String,
+    filename: String,
+    mime_type: String,
+    file: tokio::fs::File,
+    size: u64,
+    committed: bool,
+}
+
+/// 开始上传文件
+pub async fn begin_upload(filename: &str, mime_type: Option<&str>) -> Result<Upload, FileError> {
+    let store = store()?;
+    let filename = filename.trim();
+    if filename.is_empty() {
+        return Err(FileError::Invalid(
+            "Uploaded file must have a filename".into(),
+        ));
+    }
+    let id = format!("file_{}", uuid::Uuid::new_v4().simple());
+    let file = tokio::fs::File::create(store.dir.join(format!("{}.part", id))).await?;
+    let mime_type = match mime_type {
+        Some(mime) if mime != "application/octet-stream" => mime.to_string(),
+        _ => guess_mime_type(filename).to_string(),
+    };
+    Ok(Upload {
+        store,
+        id,
+        filename: filename.to_string(),
+        mime_type,
+        file,
+        size: 0,
+        committed: false,
+    })
+}
+
+impl Upload {
+    /// 写入一段文件内容
+    pub async fn write(&mut self, chunk: &[u8]) -> Result<(), FileError> {
+        self.size += chunk.len() as u64;
+        if self.size > self.store.max_file_bytes {
+            return Err(FileError::TooLarge(self.store.max_file_bytes));
+        }
+        self.file.write_all(chunk).await?;
+        Ok(())
+    }
+
+    /// 完成上传并保存元数据
+    pub async fn finish(mut self) -> Result<FileObject, FileError> {
+        self.file.flush().await?;
+        let part = self.store.dir.join(format!("{}.part", self.id));
+        let object = FileObject {
+            id: self.id.clone(),
+            object_type: "file".to_string(),
+            filename: self.filename.clone(),
+            mime_type: self.mime_type.clone(),
+            size_bytes: self.size,
+            created_at: Utc::now(),
+            downloadable: false,
+        };
+
+        let mut files = self.store.files.lock();
+        if self.store.used_bytes(&files) + self.size > self.store.max_total_bytes {
+            return Err(FileError::StorageFull(self.store.max_total_bytes));
+        }
+        std::fs::rename(&part, self.store.dir.join(&self.id))?;
+        self.committed = true;
+        files.insert(self.id.clone(), object.clone());
+        if let Err(e) = self.store.persist(&files) {
+            files.remove(&self.id);
+            let _ = std::fs::remove_file(self.store.dir.join(&self.id));
+            return Err(e);
+        }
+        Ok(object)
+    }
+}
+
+impl Drop for Upload {
+    fn drop(&mut self) {
+        if !self.committed {
+            let _ = std::fs::remove_file(self.store.dir.join(format!("{}.part", self.id)));
+        }
+    }
+}
+
+/// 按扩展名推断 MIME 类型（客户端未提供时使用）
+fn guess_mime_type(filename: &str) -> &'static str {
+    let extension = filename
+        .rsplit_once('.')
+        .map(|(_, ext)| ext.to_ascii_lowercase())
+        .unwrap_or_default();
+    match extension.as_str() {
+        "png" => "image/png",
+        "jpg" | "jpeg" => "image/jpeg",
+        "gif" => "image/gif",
+        "webp" => "image/webp",
+        "pdf" => "application/pdf",
+        "json" => "application/json",
+        "xml" => "application/xml",
+        "csv" => "text/csv",
+        "md" | "markdown" => "text/markdown",
+        "txt" | "log" => "text/plain",
+        _ => "application/octet-stream",
+    }
+}
+
+/// 获取文件元数据
+pub fn get(id: &str) -> Result<FileObject, FileError> {
+    store()?.get(id)
+}
+
+/// 删除文件
+pub fn delete(id: &str) -> Result<(), FileError> {
+    let store = store()?;
+    let mut files = store.files.lock();
+    if files.remove(id).is_none() {
+        return Err(FileError::NotFound(id.to_string()));
+    }
+    let _ = std::fs::remove_file(store.dir.join(id));
+    store.persist(&files)
+}
+
+/// 列出文件（按上传时间倒序）
+pub fn list(query: &ListFilesQuery) -> Result<FileList, FileError> {
+    let mut files: Vec<FileObject> = store()?.files.lock().values().cloned().collect();
+    files.sort_by(|a, b| b.created_at.cmp(&a.created_at).then(b.id.cmp(&a.id)));
+    Ok(paginate(files, query))
+}
+
+fn paginate(files: Vec<FileObject>, query: &ListFilesQuery) -> FileList {
+    let limit = query
+        .limit
+        .unwrap_or(DEFAULT_LIST_LIMIT)
+        .clamp(1, MAX_LIST_LIMIT);
+    let position = |id: &str| files.iter().position(|f| f.id == id);
+    let (start, end) = match (&query.after_id, &query.before_id) {
+        (Some(after), _) => {
+            let start = position(after).map_or(files.len(), |i| i + 1);
+            (start, (start + limit).min(files.len()))
+        }
+        (None, Some(before)) => {
+            let end = position(before).unwrap_or(0);
+            (end.saturating_sub(limit), end)
+        }
+        (None, None) => (0, limit.min(files.len())),
+    };
+    let has_more = if query.before_id.is_some() && query.after_id.is_none() {
+        start > 0
+    } else {
+        end < files.len()
+    };
+    let data: Vec<FileObject> = files[start..end].to_vec();
+    FileList {
+        first_id: data.first().map(|f| f.id.clone()),
+        last_id: data.last().map(|f| f.id.clone()),
+        has_more,
+        data,
+    }
+}
+
+/// 内容块引用的文件 ID（`source.type` 为 `file` 的 image / document 块）
+fn file_reference(block: &Value) -> Option<&str> {
+    let block_type = block.get("type")?.as_str()?;
+    if block_type != "image" && block_type != "document" {
+        return None;
+    }
+    let source = block.get("source")?;
+    if source.get("type")?.as_str()? != "file" {
+        return None;
+    }
+    source.get("file_id")?.as_str()
+}
+
+/// 遍历消息中的所有内容块（含 tool_result 内的内容）
+fn for_each_block(messages: &mut [Message], f: &mut impl FnMut(&mut Value)) {
+    for message in messages {
+        let Value::Array(blocks) = &mut message.content else {
+            continue;
+        };
+        for block in blocks {
+            if let Some(Value::Array(inner)) = block.get_mut("content") {
+                inner.iter_mut().for_each(&mut *f);
+            }
+            f(block);
+        }
+    }
+}
+
+/// 是否为可内联为文本的文档类型
+fn is_text_mime(mime_type: &str) -> bool {
+    mime_type.starts_with("text/")
+        || matches!(
+            mime_type,
+            "application/json" | "application/xml" | "application/x-yaml" | "application/yaml"
+        )
+}
+
+/// 将引用的文件内容转换为可直接发送的内容块
+fn inline_block(block: &Value, file: &FileObject, data: &[u8]) -> Value {
+    let is_image = block.get("type").and_then(Value::as_str) == Some("image");
+    if is_image {
+        let mut block = block.clone();
+        block["source"] = json!({
+            "type": "base64",
+            "media_type": file.mime_type,
+            "data": BASE64.encode(data),
+        });
+        return block;
+    }
+    if !is_text_mime(&file.mime_type) {
+        tracing::warn!(
+            "不支持的文档类型 {}（{}），已替换为占位文本",
+            file.mime_type,
+            file.id
+        );
+        return json!({
+            "type": "text",
+            "text": format!(
+                "[document omitted: {} ({}) is not supported]",
+                file.filename, file.mime_type
+            ),
+        });
+    }
+    let title = block
+        .get("title")
+        .and_then(Value::as_str)
+        .unwrap_or(&file.filename);
+    json!({
+        "type": "text",
+        "text": format!(
+            "<document title=\"{}\">\n{}\n</document>",
+            title,
+            String::from_utf8_lossy(data)
+        ),
+    })
+}
+
+/// 将消息中的文件引用替换为文件内容，返回内联的块数
+pub async fn inline_references(messages: &mut [Message]) -> Result<usize, FileError> {
+    let mut ids = Vec::new();
+    for_each_block(messages, &mut |block| {
+        if let Some(id) = file_reference(block) {
+            ids.push(id.to_string());
+        }
+    });
+    if ids.is_empty() {
+        return Ok(0);
+    }
+
+    let store = store()?;
+    let mut loaded: HashMap<String, (FileObject, Vec<u8>)> = HashMap::new();
+    for id in ids {
+        if loaded.contains_key(&id) {
+            continue;
+        }
+        let file = store.get(&id)?;
+        let data = tokio::fs::read(store.dir.join(&id)).await?;
+        loaded.insert(id, (file, data));
+    }
+
+    let mut inlined = 0;
+    for_each_block(messages, &mut |block| {
+        let Some((file, data)) = file_reference(block).and_then(|id| loaded.get(id)) else {
+            return;
+        };
+        *block = inline_block(block, file, data);
+        inlined += 1;
+    });
+    Ok(inlined)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn file(id: &str, mime_type: &str, created_at: i64) -> FileObject {
+        FileObject {
+            id: id.to_string(),
+            object_type: "file".to_string(),
+            filename: format!("{}.bin", id),
+            mime_type: mime_type.to_string(),
+            size_bytes: 4,
+            created_at: DateTime::from_timestamp(created_at, 0).unwrap(),
+            downloadable: false,
+        }
+    }
+
+    #[test]
+    fn test_inline_block_by_type() {
+        let image = json!({"type": "image", "source": {"type": "file", "file_id": "file_a"}});
+        let inlined = inline_block(&image, &file("file_a", "image/png", 0), b"\x89PNG");
+        assert_eq!(inlined["source"]["type"], "base64");
+        assert_eq!(inlined["source"]["media_type"], "image/png");
+        assert_eq!(inlined["source"]["data"], "iVBORw==");
+
+        let doc = json!({"type": "document", "title": "notes", "source": {"type": "file", "file_id": "file_b"}});
+        let inlined = inline_block(&doc, &file("file_b", "text/markdown", 0), b"# Hi");
+        assert_eq!(inlined["type"], "text");
+        assert_eq!(
+            inlined["text"],
+            "<document title=\"notes\">\n# Hi\n</document>"
+        );
+
+        let pdf = json!({"type": "document", "source": {"type": "file", "file_id": "file_c"}});
+        let inlined = inline_block(&pdf, &file("file_c", "application/pdf", 0), b"%PDF");
+        assert!(inlined["text"].as_str().unwrap().contains("not supported"));
+    }
+
+    #[test]
+    fn test_file_reference_and_walk() {
+        let mut messages = vec![Message {
+            role: "user".to_string(),
+            content: json!([
+                {"type": "text", "text": "look"},
+                {"type": "image", "source": {"type": "file", "file_id": "file_a"}},
+                {"type": "image", "source": {"type": "base64", "media_type": "image/png", "data": "AA"}},
+                {"type": "tool_result", "tool_use_id": "t1", "content": [
+                    {"type": "document", "source": {"type": "file", "file_id": "file_b"}}
+                ]}
+            ]),
+        }];
+        let mut ids = Vec::new();
+        for_each_block(&mut messages, &mut |block| {
+            if let Some(id) = file_reference(block) {
+                ids.push(id.to_string());
+            }
+        });
+        assert_eq!(ids, vec!["file_a", "file_b"]);
+    }
+
+    #[test]
+    fn test_paginate() {
+        let files: Vec<FileObject> = (0..5)
+            .rev()
+            .map(|i| file(&format!("file_{}", i), "text/plain", i))
+            .collect();
+        let ids = |list: &FileList| list.data.iter().map(|f| f.id.clone()).collect::<Vec<_>>();
+
+        let first = paginate(
+            files.clone(),
+            &ListFilesQuery {
+                limit: Some(2),
+                ..Default::default()
+            },
+        );
+        assert_eq!(ids(&first), vec!["file_4", "file_3"]);
+        assert!(first.has_more);
+
+        let next = paginate(
+            files.clone(),
+            &ListFilesQuery {
+                limit: Some(2),
+                after_id: first.last_id.clone(),
+                before_id: None,
+            },
+        );
+        assert_eq!(ids(&next), vec!["file_2", "file_1"]);
+        assert!(next.has_more);
+
+        let previous = paginate(
+            files,
+            &ListFilesQuery {
+                limit: Some(2),
+                after_id: None,
+                before_id: Some("file_1".to_string()),
+            },
+        );
+        assert_eq!(ids(&previous), vec!["file_3", "file_2"]);
+        assert!(previous.has_more);
+
+        assert_eq!(guess_mime_type("a.JPG"), "image/jpeg");
+        assert_eq!(guess_mime_type("README"), "application/octet-stream");
+    }
+
+    #[test]
+    fn test_recover_unindexed_files() {
+        let dir = std::env::temp_dir().join(format!("kiro-files-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        for name in ["file_a", "file_b", "file_c.part", INDEX_FILE] {
+            std::fs::write(dir.join(name), "data").unwrap();
+        }
+
+        let mut files = BTreeMap::from([("file_a".to_string(), file("file_a", "text/plain", 0))]);
+        assert_eq!(recover_unindexed(&dir, &mut files).unwrap(), 1);
+        let ids: Vec<&str> = files.keys().map(String::as_str).collect();
+        assert_eq!(ids, ["file_a", "file_b"]);
+        assert_eq!(files["file_a"].mime_type, "text/plain");
+        assert_eq!(files["file_b"].size_bytes, 4);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
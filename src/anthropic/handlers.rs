@@ -3,168 +3,438 @@
 use std::convert::Infallible;
 
 use anyhow::Error;
+use crate::kiro::concurrency::ConcurrencyLimitError;
+use crate::kiro::malformed::MalformedRequestError;
 use crate::kiro::model::events::Event;
 use crate::kiro::model::requests::kiro::KiroRequest;
+use crate::kiro::model_registry;
 use crate::kiro::parser::decoder::EventStreamDecoder;
+use crate::kiro::provider::{
+    CallOptions, ServedCredential, UpstreamThrottledError, body_stream, parse_retry_after,
+};
+use crate::kiro::token_manager::RouteHints;
+use crate::model::config::SsePingStyle;
 use crate::token;
 use axum::{
-    Json as JsonExtractor,
     body::Body,
-    extract::State,
-    http::{StatusCode, header},
+    extract::{Path, Query, State, multipart::{Field, Multipart, MultipartError}},
+    http::{HeaderMap, HeaderName, HeaderValue, StatusCode, header},
     response::{IntoResponse, Json, Response},
 };
 use bytes::Bytes;
 use futures::{Stream, StreamExt, stream};
 use serde_json::json;
+use std::sync::Arc;
 use std::time::Duration;
-use tokio::time::interval;
-use uuid::Uuid;
-
-use super::converter::{ConversionError, convert_request};
-use super::middleware::AppState;
-use super::stream::{BufferedStreamContext, SseEvent, StreamContext};
-use super::types::{CountTokensRequest, CountTokensResponse, ErrorResponse, MessagesRequest, Model, ModelsResponse, OutputConfig, Thinking};
+use tokio::time::{Instant, Interval, interval};
+use tracing::Instrument;
+
+use super::admission::{self, AdmissionPermit};
+use super::batches::{CreateBatchRequest, ListBatchesQuery};
+use super::body::JsonBody;
+use super::coalesce::{self, COALESCED_HEADER, Joined, Leader, RequestCoalescer};
+use super::converter::{
+    ConversionError, convert_request, extract_session_id, extract_user_id, resolve_model,
+};
+use super::files::{self, FileError, FileObject, ListFilesQuery};
+use super::middleware::{AppState, SsePing};
+use super::output_filter::OutputFilter;
+use super::prefill::{PrefillFilter, strip_prefill};
+use super::quota::QuotaExceeded;
+use super::replay::{FrameRecorder, recorded};
+use super::request_id::{REQUEST_ID_HEADER, RequestId};
+use super::response_cache::{self, RESPONSE_CACHE_HEADER, ResponseCache};
+use super::session_cost::{self, SESSION_COST_HEADER};
+use super::session_memory::{self, MemoryError};
+use super::shadow;
+use super::system_prompt;
+use super::transform;
+use super::stream::{BufferedStreamContext, SseEvent, StreamContext, UsageCallback};
+use super::upstream_error;
+use super::usage_ledger;
+use super::types::{CountTokensRequest, CountTokensResponse, ErrorResponse, MessagesRequest, Model, ModelsResponse, OutputConfig, SetMemoryNoteRequest, Thinking};
 use super::websearch;
 
 /// 将 KiroProvider 错误映射为 HTTP 响应
 fn map_provider_error(err: Error) -> Response {
-    let err_str = err.to_string();
-
-    // 上下文窗口满了（对话历史累积超出模型上下文窗口限制）
-    if err_str.contains("CONTENT_LENGTH_EXCEEDS_THRESHOLD") {
-        tracing::warn!(error = %err, "上游拒绝请求：上下文窗口已满（不应重试）");
+    // 上游判定请求格式错误：请求体已记录，返回可疑字段便于定位转换问题
+    if let Some(malformed) = err.downcast_ref::<MalformedRequestError>() {
+        let fields = malformed
+            .suspects
+            .iter()
+            .map(|s| format!("{} ({})", s.pointer, s.reason))
+            .collect::<Vec<_>>();
+        let hint = if fields.is_empty() {
+            "no suspect field identified".to_string()
+        } else {
+            format!("suspect fields: {}", fields.join("; "))
+        };
+        tracing::warn!(error = %err, capture_id = %malformed.capture_id, "上游拒绝请求：请求格式错误");
         return (
             StatusCode::BAD_REQUEST,
             Json(ErrorResponse::new(
                 "invalid_request_error",
-                "Context window is full. Reduce conversation history, system prompt, or tools.",
+                format!(
+                    "Upstream rejected the request as improperly formed; {} (capture id: {})",
+                    hint, malformed.capture_id
+                ),
             )),
         )
             .into_response();
     }
 
-    // 单次输入太长（请求体本身超出上游限制）
-    if err_str.contains("Input is too long") {
-        tracing::warn!(error = %err, "上游拒绝请求：输入过长（不应重试）");
-        return (
-            StatusCode::BAD_REQUEST,
+    // 上游限流且重试耗尽：返回 429 并转发 Retry-After，便于客户端退避
+    if let Some(throttled) = err.downcast_ref::<UpstreamThrottledError>() {
+        tracing::warn!(error = %err, retry_after = ?throttled.retry_after, "上游限流，重试已耗尽");
+        let mut response = (
+            StatusCode::TOO_MANY_REQUESTS,
             Json(ErrorResponse::new(
-                "invalid_request_error",
-                "Input is too long. Reduce the size of your messages.",
+                "rate_limit_error",
+                "Upstream is rate limiting requests. Please retry later.",
             )),
         )
             .into_response();
+        if let Some(secs) = throttled.retry_after {
+            response
+                .headers_mut()
+                .insert(header::RETRY_AFTER, HeaderValue::from(secs));
+        }
+        return response;
+    }
+
+    // 本地凭据并发已满：排队已满返回 429，排队超时返回 529（overloaded）
+    if let Some(limited) = err.downcast_ref::<ConcurrencyLimitError>() {
+        let (status, error_type, message) = match limited {
+            ConcurrencyLimitError::QueueFull { .. } => (
+                StatusCode::TOO_MANY_REQUESTS,
+                "rate_limit_error",
+                "Too many concurrent requests. Please retry later.",
+            ),
+            ConcurrencyLimitError::QueueTimeout { .. } => (
+                StatusCode::from_u16(529).expect("529 是合法状态码"),
+                "overloaded_error",
+                "Overloaded: timed out waiting for an upstream slot.",
+            ),
+        };
+        tracing::warn!(error = %err, "凭据并发已满，拒绝请求");
+        return (status, Json(ErrorResponse::new(error_type, message))).into_response();
+    }
+
+    // 其余上游错误按 reason 代码与状态码映射为 Anthropic 错误类型
+    let mapped = upstream_error::map(&err.to_string());
+    if mapped.status.is_server_error() {
+        tracing::error!(error = %err, error_type = mapped.error_type, "Kiro API 调用失败");
+    } else {
+        tracing::warn!(error = %err, error_type = mapped.error_type, "上游拒绝请求");
+    }
+    let mut response = (
+        mapped.status,
+        Json(ErrorResponse::new(mapped.error_type, mapped.message)),
+    )
+        .into_response();
+    if let Some(secs) = mapped.retry_after {
+        response
+            .headers_mut()
+            .insert(header::RETRY_AFTER, HeaderValue::from(secs));
+    }
+    response
+}
+
+/// 请求级模型 fallback 链请求头（逗号分隔，按顺序尝试）
+const MODEL_FALLBACK_HEADER: &str = "x-kiro-model-fallback";
+
+/// 实际服务本次请求的模型（仅在配置了 fallback 链时返回）
+const SERVED_MODEL_HEADER: &str = "x-kiro-served-model";
+
+/// 请求模型不可用、已自动改用其他模型时的提示
+const MODEL_WARNING_HEADER: &str = "x-kiro-model-warning";
+
+/// 按请求覆盖上游交互总时限（秒）的请求头，0 表示不限制
+const REQUEST_TIMEOUT_HEADER: &str = "x-kiro-request-timeout";
+
+/// 按请求指定 API Region 的请求头（需在 `allowedApiRegions` 中）
+const REGION_HEADER: &str = "x-kiro-region";
+
+/// 解析请求头 `x-kiro-region` 指定的 API Region
+///
+/// 未提供时返回 None；Region 不在 `allowedApiRegions` 中时返回错误信息
+fn requested_api_region(headers: &HeaderMap, allowed: &[String]) -> Result<Option<String>, String> {
+    let Some(value) = headers.get(REGION_HEADER) else {
+        return Ok(None);
+    };
+    let region = value.to_str().unwrap_or_default().trim();
+    match allowed.iter().find(|r| r.eq_ignore_ascii_case(region)) {
+        Some(region) => {
+            tracing::debug!(region = %region, "请求指定 API Region");
+            Ok(Some(region.clone()))
+        }
+        None => Err(format!(
+            "Region '{}' is not allowed in the {} header.",
+            region, REGION_HEADER
+        )),
+    }
+}
+
+/// 计算本次请求的截止时间
+///
+/// 请求头 `x-kiro-request-timeout` 只能在配置的默认时限内缩短时限：配置了默认时限时，
+/// 请求头为 0、超出默认时限或无法解析都使用默认时限；未配置时请求头的正数值生效
+fn request_deadline(headers: &HeaderMap, default_secs: u64) -> Option<Instant> {
+    let requested = headers.get(REQUEST_TIMEOUT_HEADER).map(|value| {
+        value
+            .to_str()
+            .ok()
+            .and_then(|v| v.trim().parse::<u64>().ok())
+    });
+    let secs = match requested {
+        Some(Some(secs)) if secs > 0 && (default_secs == 0 || secs <= default_secs) => secs,
+        Some(Some(secs)) => {
+            tracing::debug!(
+                "{} 请求头的值 {} 超出允许范围，使用默认时限",
+                REQUEST_TIMEOUT_HEADER,
+                secs
+            );
+            default_secs
+        }
+        Some(None) => {
+            tracing::warn!("无法解析 {} 请求头，使用默认时限", REQUEST_TIMEOUT_HEADER);
+            default_secs
+        }
+        None => default_secs,
+    };
+    (secs > 0).then(|| Instant::now() + Duration::from_secs(secs))
+}
+
+/// 在截止时间前等待 future 完成，超时返回 None（future 被丢弃，上游请求随之中止）
+async fn within_deadline<F: std::future::Future>(
+    deadline: Option<Instant>,
+    future: F,
+) -> Option<F::Output> {
+    match deadline {
+        Some(deadline) => tokio::time::timeout_at(deadline, future).await.ok(),
+        None => Some(future.await),
+    }
+}
+
+/// 等待截止时间到达（未设置截止时间时永不完成）
+async fn deadline_reached(deadline: Option<Instant>) {
+    match deadline {
+        Some(deadline) => tokio::time::sleep_until(deadline).await,
+        None => std::future::pending().await,
     }
-    tracing::error!("Kiro API 调用失败: {}", err);
+}
+
+/// 请求超过时限时的 504 响应
+fn request_timeout_response() -> Response {
+    tracing::warn!("请求超过时限，已中止上游请求");
     (
-        StatusCode::BAD_GATEWAY,
+        StatusCode::GATEWAY_TIMEOUT,
         Json(ErrorResponse::new(
-            "api_error",
-            format!("上游 API 调用失败: {}", err),
+            "timeout_error",
+            "Request timed out before the upstream response completed.",
         )),
     )
         .into_response()
 }
 
+/// 解析本次请求的 fallback 链
+///
+/// 请求头 `x-kiro-model-fallback` 优先，未提供时使用配置中按模型名匹配的规则
+fn resolve_fallback_chain(headers: &HeaderMap, state: &AppState, model: &str) -> Vec<String> {
+    if let Some(value) = headers
+        .get(MODEL_FALLBACK_HEADER)
+        .and_then(|v| v.to_str().ok())
+    {
+        return value
+            .split(',')
+            .map(|m| m.trim())
+            .filter(|m| !m.is_empty() && *m != model)
+            .map(|m| m.to_string())
+            .collect();
+    }
+
+    state.model_fallbacks.get(model).cloned().unwrap_or_default()
+}
+
+/// 判断上游错误是否应沿 fallback 链切换到下一个模型
+///
+/// 仅模型无效和额度类错误会触发 fallback，其余错误（如上下文超限）换模型也无济于事
+fn is_fallback_eligible(err: &Error) -> bool {
+    let err_str = err.to_string();
+    err_str.contains("INVALID_MODEL_ID")
+        || err_str.contains("MONTHLY_REQUEST_COUNT")
+        || err_str.contains("所有凭据已用尽")
+        || err_str.contains("所有凭据均已禁用")
+}
+
+/// 将请求转换错误映射为 HTTP 响应
+fn map_conversion_error(e: ConversionError) -> Response {
+    let (error_type, message) = match &e {
+        ConversionError::UnsupportedModel(model) => {
+            ("invalid_request_error", format!("模型不支持: {}", model))
+        }
+        ConversionError::EmptyMessages => ("invalid_request_error", "消息列表为空".to_string()),
+    };
+    tracing::warn!("请求转换失败: {}", e);
+    (
+        StatusCode::BAD_REQUEST,
+        Json(ErrorResponse::new(error_type, message)),
+    )
+        .into_response()
+}
+
+/// 上游调用成功后的上下文
+struct UpstreamCall {
+    response: reqwest::Response,
+    /// 实际服务的模型（发生 fallback 时与请求模型不同）
+    model: String,
+    /// 实际服务的凭据 ID
+    credential_id: Option<u64>,
+    thinking_enabled: bool,
+    tool_name_map: std::collections::HashMap<String, String>,
+    /// 原始事件流录制器（仅开启 debugCaptureFrames 时存在）
+    frame_recorder: Option<FrameRecorder>,
+    /// 请求末尾的 assistant prefill（响应需去除开头复述的部分）
+    prefill: Option<String>,
+    /// 请求的停止序列（由代理在输出中检测）
+    stop_sequences: Vec<String>,
+    /// 映射的模型不可用而被自动替换时的提示（通过响应头返回）
+    model_warning: Option<String>,
+    /// 请求截止时间，超过后中止读取上游响应
+    deadline: Option<Instant>,
+}
+
+/// 转换请求并调用上游，失败时沿 fallback 链依次尝试
+///
+/// 每个候选模型都会重新转换请求（模型映射、thinking 后缀覆写），
+/// 结束后 `payload.model` 为实际服务的模型
+async fn call_upstream_with_fallback(
+    provider: &crate::kiro::provider::KiroProvider,
+    payload: &mut MessagesRequest,
+    fallbacks: &[String],
+    options: CallOptions<'_>,
+) -> Result<UpstreamCall, Response> {
+    let candidates: Vec<String> = std::iter::once(payload.model.clone())
+        .chain(fallbacks.iter().cloned())
+        .collect();
+
+    for (i, model) in candidates.iter().enumerate() {
+        let has_next = i + 1 < candidates.len();
+        if i > 0 {
+            tracing::warn!(from = %payload.model, to = %model, "模型 fallback");
+            payload.model = model.clone();
+            override_thinking_from_model_name(payload);
+        }
+
+        // 转换请求
+        let conversion_result = match convert_request(payload) {
+            Ok(result) => result,
+            Err(ConversionError::UnsupportedModel(m)) if has_next => {
+                tracing::warn!("模型不支持: {}，尝试 fallback 链中的下一个模型", m);
+                continue;
+            }
+            Err(e) => return Err(map_conversion_error(e)),
+        };
+
+        // 构建 Kiro 请求（profile_arn 由 provider 层根据实际凭据注入）
+        let kiro_request = KiroRequest {
+            conversation_state: conversion_result.conversation_state,
+            profile_arn: None,
+        };
+
+        let request_body = match serde_json::to_string(&kiro_request) {
+            Ok(body) => body,
+            Err(e) => {
+                tracing::error!("序列化请求失败: {}", e);
+                return Err((
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    Json(ErrorResponse::new(
+                        "internal_error",
+                        format!("序列化请求失败: {}", e),
+                    )),
+                )
+                    .into_response());
+            }
+        };
+
+        tracing::debug!("Kiro request body: {}", request_body);
+
+        // 调用 Kiro API（支持多凭据故障转移）
+        let hints = RouteHints {
+            message_count: Some(payload.messages.len()),
+            max_tokens: Some(payload.max_tokens),
+        };
+        let result = if payload.stream {
+            provider
+                .call_api_stream(&request_body, hints, options)
+                .await
+        } else {
+            provider.call_api(&request_body, hints, options).await
+        };
+
+        match result {
+            Ok(response) => {
+                // 检查是否启用了thinking
+                let thinking_enabled = payload
+                    .thinking
+                    .as_ref()
+                    .map(|t| t.is_enabled())
+                    .unwrap_or(false);
+                return Ok(UpstreamCall {
+                    credential_id: response.extensions().get::<ServedCredential>().map(|c| c.0),
+                    response,
+                    model: payload.model.clone(),
+                    thinking_enabled,
+                    tool_name_map: conversion_result.tool_name_map,
+                    frame_recorder: None,
+                    prefill: conversion_result.prefill,
+                    stop_sequences: payload.stop_sequences.clone().unwrap_or_default(),
+                    model_warning: conversion_result.model.replaced.map(|replaced| {
+                        format!(
+                            "model {} is not available, served by {}",
+                            replaced, conversion_result.model.model_id
+                        )
+                    }),
+                    deadline: None,
+                });
+            }
+            Err(e) if has_next && is_fallback_eligible(&e) => {
+                tracing::warn!(model = %payload.model, error = %e, "上游调用失败，尝试 fallback 链中的下一个模型");
+            }
+            Err(e) => return Err(map_provider_error(e)),
+        }
+    }
+
+    // candidates 至少包含请求模型本身，循环内必然返回
+    unreachable!("fallback 候选列表为空")
+}
+
+/// 为响应附加实际服务模型的响应头
+fn with_served_model_header(mut response: Response, model: &str) -> Response {
+    if let Ok(value) = HeaderValue::from_str(model) {
+        response.headers_mut().insert(SERVED_MODEL_HEADER, value);
+    }
+    response
+}
+
 /// GET /v1/models
 ///
 /// 返回可用的模型列表
 pub async fn get_models() -> impl IntoResponse {
     tracing::info!("Received GET /v1/models request");
 
-    let models = vec![
-        Model {
-            id: "claude-opus-4-6".to_string(),
-            object: "model".to_string(),
-            created: 1770163200, // Feb 4, 2026
-            owned_by: "anthropic".to_string(),
-            display_name: "Claude Opus 4.6".to_string(),
-            model_type: "chat".to_string(),
-            max_tokens: 64000,
-        },
-        Model {
-            id: "claude-opus-4-6-thinking".to_string(),
-            object: "model".to_string(),
-            created: 1770163200, // Feb 4, 2026
-            owned_by: "anthropic".to_string(),
-            display_name: "Claude Opus 4.6 (Thinking)".to_string(),
-            model_type: "chat".to_string(),
-            max_tokens: 64000,
-        },
-        Model {
-            id: "claude-sonnet-4-6".to_string(),
-            object: "model".to_string(),
-            created: 1771286400, // Feb 17, 2026
-            owned_by: "anthropic".to_string(),
-            display_name: "Claude Sonnet 4.6".to_string(),
-            model_type: "chat".to_string(),
-            max_tokens: 64000,
-        },
-        Model {
-            id: "claude-sonnet-4-6-thinking".to_string(),
-            object: "model".to_string(),
-            created: 1771286400, // Feb 17, 2026
-            owned_by: "anthropic".to_string(),
-            display_name: "Claude Sonnet 4.6 (Thinking)".to_string(),
-            model_type: "chat".to_string(),
-            max_tokens: 64000,
-        },
-        Model {
-            id: "claude-opus-4-5-20251101".to_string(),
+    let models = model_registry::list_models()
+        .into_iter()
+        .map(|m| Model {
+            id: m.id,
             object: "model".to_string(),
-            created: 1763942400, // Nov 24, 2025
+            created: m.created,
             owned_by: "anthropic".to_string(),
-            display_name: "Claude Opus 4.5".to_string(),
+            display_name: m.display_name,
             model_type: "chat".to_string(),
-            max_tokens: 64000,
-        },
-        Model {
-            id: "claude-opus-4-5-20251101-thinking".to_string(),
-            object: "model".to_string(),
-            created: 1763942400, // Nov 24, 2025
-            owned_by: "anthropic".to_string(),
-            display_name: "Claude Opus 4.5 (Thinking)".to_string(),
-            model_type: "chat".to_string(),
-            max_tokens: 64000,
-        },
-        Model {
-            id: "claude-sonnet-4-5-20250929".to_string(),
-            object: "model".to_string(),
-            created: 1759104000, // Sep 29, 2025
-            owned_by: "anthropic".to_string(),
-            display_name: "Claude Sonnet 4.5".to_string(),
-            model_type: "chat".to_string(),
-            max_tokens: 64000,
-        },
-        Model {
-            id: "claude-sonnet-4-5-20250929-thinking".to_string(),
-            object: "model".to_string(),
-            created: 1759104000, // Sep 29, 2025
-            owned_by: "anthropic".to_string(),
-            display_name: "Claude Sonnet 4.5 (Thinking)".to_string(),
-            model_type: "chat".to_string(),
-            max_tokens: 64000,
-        },
-        Model {
-            id: "claude-haiku-4-5-20251001".to_string(),
-            object: "model".to_string(),
-            created: 1760486400, // Oct 15, 2025
-            owned_by: "anthropic".to_string(),
-            display_name: "Claude Haiku 4.5".to_string(),
-            model_type: "chat".to_string(),
-            max_tokens: 64000,
-        },
-        Model {
-            id: "claude-haiku-4-5-20251001-thinking".to_string(),
-            object: "model".to_string(),
-            created: 1760486400, // Oct 15, 2025
-            owned_by: "anthropic".to_string(),
-            display_name: "Claude Haiku 4.5 (Thinking)".to_string(),
-            model_type: "chat".to_string(),
-            max_tokens: 64000,
-        },
-    ];
+            max_tokens: m.max_tokens as i32,
+        })
+        .collect();
 
     Json(ModelsResponse {
         object: "list".to_string(),
@@ -177,15 +447,185 @@ pub async fn get_models() -> impl IntoResponse {
 /// 创建消息（对话）
 pub async fn post_messages(
     State(state): State<AppState>,
-    JsonExtractor(mut payload): JsonExtractor<MessagesRequest>,
+    headers: HeaderMap,
+    JsonBody(payload): JsonBody<MessagesRequest>,
+) -> Response {
+    handle_messages(state, headers, payload, MessagesEndpoint::Standard).await
+}
+
+/// POST /v1/messages/batches
+///
+/// 创建消息批次，批次中的请求在后台以非流式方式逐个执行
+pub async fn create_message_batch(
+    State(state): State<AppState>,
+    JsonBody(payload): JsonBody<CreateBatchRequest>,
+) -> Response {
+    let id = RequestId::generate().batch_id();
+    let batch = match state.batches.create(id.clone(), payload) {
+        Ok(batch) => batch,
+        Err(message) => {
+            return (
+                StatusCode::BAD_REQUEST,
+                Json(ErrorResponse::new("invalid_request_error", message)),
+            )
+                .into_response();
+        }
+    };
+
+    let store = state.batches.clone();
+    tokio::spawn(store.run(id, move |payload| {
+        handle_messages(
+            state.clone(),
+            HeaderMap::new(),
+            payload,
+            MessagesEndpoint::Standard,
+        )
+    }));
+
+    Json(batch).into_response()
+}
+
+/// GET /v1/messages/batches
+///
+/// 列出消息批次（新的在前）
+pub async fn list_message_batches(
+    State(state): State<AppState>,
+    Query(query): Query<ListBatchesQuery>,
+) -> impl IntoResponse {
+    Json(state.batches.list(&query))
+}
+
+fn batch_not_found(id: &str) -> Response {
+    (
+        StatusCode::NOT_FOUND,
+        Json(ErrorResponse::new(
+            "not_found_error",
+            format!("Message batch not found: {}", id),
+        )),
+    )
+        .into_response()
+}
+
+/// GET /v1/messages/batches/{id}
+///
+/// 获取消息批次状态
+pub async fn get_message_batch(State(state): State<AppState>, Path(id): Path<String>) -> Response {
+    match state.batches.get(&id) {
+        Some(batch) => Json(batch).into_response(),
+        None => batch_not_found(&id),
+    }
+}
+
+/// POST /v1/messages/batches/{id}/cancel
+///
+/// 取消消息批次（尚未执行的请求不再执行）
+pub async fn cancel_message_batch(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+) -> Response {
+    match state.batches.cancel(&id) {
+        Some(batch) => Json(batch).into_response(),
+        None => batch_not_found(&id),
+    }
+}
+
+/// GET /v1/messages/batches/{id}/results
+///
+/// 获取已结束批次的结果（JSONL，每行一个请求）
+pub async fn get_message_batch_results(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+) -> Response {
+    match state.batches.results(&id) {
+        Some(Ok(jsonl)) => (
+            [(header::CONTENT_TYPE, "application/x-jsonl")],
+            jsonl,
+        )
+            .into_response(),
+        Some(Err(message)) => (
+            StatusCode::BAD_REQUEST,
+            Json(ErrorResponse::new("invalid_request_error", message)),
+        )
+            .into_response(),
+        None => batch_not_found(&id),
+    }
+}
+
+/// Messages 请求的入口端点
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum MessagesEndpoint {
+    /// /v1/messages
+    Standard,
+    /// /cc/v1/messages（流式响应缓冲到结束后再发送）
+    ClaudeCode,
+}
+
+impl MessagesEndpoint {
+    fn path(&self) -> &'static str {
+        match self {
+            MessagesEndpoint::Standard => "/v1/messages",
+            MessagesEndpoint::ClaudeCode => "/cc/v1/messages",
+        }
+    }
+}
+
+/// 处理 Messages 请求（/v1 与 /cc/v1 共用）
+///
+/// 为请求生成 Anthropic 风格的 request_id，整个处理过程（含流式响应）
+/// 的日志都挂在携带该 ID 的 span 下，并通过 `request-id` 响应头返回
+async fn handle_messages(
+    state: AppState,
+    headers: HeaderMap,
+    payload: MessagesRequest,
+    endpoint: MessagesEndpoint,
+) -> Response {
+    let request_id = RequestId::generate();
+    let span = tracing::info_span!(
+        "messages",
+        request_id = %request_id.request_id(),
+        model = %payload.model,
+        stream = payload.stream,
+        user_id = tracing::field::Empty,
+    );
+
+    let mut response = handle_messages_inner(state, headers, payload, endpoint, &request_id)
+        .instrument(span)
+        .await;
+
+    if let Ok(value) = HeaderValue::from_str(&request_id.request_id()) {
+        response.headers_mut().insert(REQUEST_ID_HEADER, value);
+    }
+    response
+}
+
+async fn handle_messages_inner(
+    state: AppState,
+    headers: HeaderMap,
+    mut payload: MessagesRequest,
+    endpoint: MessagesEndpoint,
+    request_id: &RequestId,
 ) -> Response {
     tracing::info!(
         model = %payload.model,
         max_tokens = %payload.max_tokens,
         stream = %payload.stream,
         message_count = %payload.messages.len(),
-        "Received POST /v1/messages request"
+        "Received POST {} request",
+        endpoint.path()
     );
+    let deadline = request_deadline(&headers, state.request_timeout_secs);
+    let api_region = match requested_api_region(&headers, &state.allowed_api_regions) {
+        Ok(region) => region,
+        Err(message) => {
+            tracing::warn!("{}", message);
+            return (
+                StatusCode::BAD_REQUEST,
+                Json(ErrorResponse::new("invalid_request_error", message)),
+            )
+                .into_response();
+        }
+    };
+
     // 检查 KiroProvider 是否可用
     let provider = match &state.kiro_provider {
         Some(p) => p.clone(),
@@ -202,9 +642,59 @@ pub async fn post_messages(
         }
     };
 
+    // 滚动窗口 token 配额
+    if let Some(quota) = &state.quota
+        && let Err(exceeded) = quota.check(&state.api_key, chrono::Utc::now().timestamp())
+    {
+        return quota_exceeded_response(exceeded);
+    }
+
+    // 影子流量：复制改写前的原始请求
+    let shadow_request = shadow::sample(&headers, &payload, request_id.request_id());
+
+    // 按配置改写请求（插入 system、移除工具、改写模型、限制 max_tokens）
+    let applied = transform::apply(&state.request_transforms, &headers, &mut payload);
+    if !applied.is_empty() {
+        tracing::info!(rules = ?applied, model = %payload.model, "已应用请求改写规则");
+    }
+    let applied = system_prompt::apply(&headers, &mut payload);
+    if !applied.is_empty() {
+        tracing::debug!(rules = ?applied, model = %payload.model, "已注入 system 提示");
+    }
+
     // 检测模型名是否包含 "thinking" 后缀，若包含则覆写 thinking 配置
     override_thinking_from_model_name(&mut payload);
 
+    let metadata_user_id = payload.metadata.as_ref().and_then(|m| m.user_id.as_deref());
+    let session_id = metadata_user_id.and_then(extract_session_id);
+    let user_id = metadata_user_id.and_then(extract_user_id);
+    if let Some(user_id) = &user_id {
+        tracing::Span::current().record("user_id", user_id.as_str());
+    }
+    if let Some(session_id) = &session_id {
+        let notes = session_memory::inject(session_id, &mut payload);
+        if notes > 0 {
+            tracing::debug!(notes, "已注入会话记忆");
+        }
+    }
+
+    // Files API：file_id 引用内联为文件内容
+    match files::inline_references(&mut payload.messages).await {
+        Ok(0) => {}
+        Ok(inlined) => tracing::debug!(inlined, "已内联文件引用"),
+        Err(e) => return file_error_response(e),
+    }
+
+    // 按模型能力截断 max_tokens / budget_tokens，拒绝模型不支持的功能
+    if let Err(message) = enforce_model_capabilities(&mut payload) {
+        tracing::warn!("{}", message);
+        return (
+            StatusCode::BAD_REQUEST,
+            Json(ErrorResponse::new("invalid_request_error", message)),
+        )
+            .into_response();
+    }
+
     // 检查是否为 WebSearch 请求
     if websearch::has_web_search_tool(&payload) {
         tracing::info!("检测到 WebSearch 工具，路由到 WebSearch 处理");
@@ -217,52 +707,100 @@ pub async fn post_messages(
             payload.tools.clone(),
         ) as i32;
 
-        return websearch::handle_websearch_request(provider, &payload, input_tokens).await;
+        let permit = match admit(&payload).await {
+            Ok(permit) => permit,
+            Err(resp) => return resp,
+        };
+        // 与普通请求一样计入配额、会话费用和用量账本（搜索不占用 Kiro 凭据）
+        let usage_callback = with_session_cost(
+            quota_usage_callback(&state),
+            session_id.clone(),
+            &payload.model,
+        );
+        let usage_callback =
+            with_usage_ledger(usage_callback, &state, user_id, None, &payload.model);
+        let websearch = websearch::handle_websearch_request(
+            provider,
+            &payload,
+            input_tokens,
+            request_id.message_id(),
+            usage_callback,
+        );
+        let response = within_deadline(deadline, websearch)
+            .await
+            .unwrap_or_else(request_timeout_response);
+        let response = match &session_id {
+            Some(session_id) => with_session_cost_header(response, session_id),
+            None => response,
+        };
+        return admission::hold(response, permit);
     }
 
-    // 转换请求
-    let conversion_result = match convert_request(&payload) {
-        Ok(result) => result,
-        Err(e) => {
-            let (error_type, message) = match &e {
-                ConversionError::UnsupportedModel(model) => {
-                    ("invalid_request_error", format!("模型不支持: {}", model))
-                }
-                ConversionError::EmptyMessages => {
-                    ("invalid_request_error", "消息列表为空".to_string())
-                }
-            };
-            tracing::warn!("请求转换失败: {}", e);
-            return (
-                StatusCode::BAD_REQUEST,
-                Json(ErrorResponse::new(error_type, message)),
-            )
-                .into_response();
+    // 非流式请求的响应缓存
+    let cache_slot = match &state.response_cache {
+        Some(cache) if !payload.stream => {
+            let key = ResponseCache::key(&payload);
+            if let Some(body) = cache.get(&key, std::time::Instant::now()) {
+                tracing::info!("响应缓存命中");
+                let body = response_cache::as_cache_hit(body, &request_id.message_id());
+                return (StatusCode::OK, [(RESPONSE_CACHE_HEADER, "hit")], Json(body))
+                    .into_response();
+            }
+            Some((cache.clone(), key))
         }
+        _ => None,
     };
 
-    // 构建 Kiro 请求（profile_arn 由 provider 层根据实际凭据注入）
-    let kiro_request = KiroRequest {
-        conversation_state: conversion_result.conversation_state,
-        profile_arn: None,
-    };
-
-    let request_body = match serde_json::to_string(&kiro_request) {
-        Ok(body) => body,
-        Err(e) => {
-            tracing::error!("序列化请求失败: {}", e);
-            return (
-                StatusCode::INTERNAL_SERVER_ERROR,
-                Json(ErrorResponse::new(
-                    "internal_error",
-                    format!("序列化请求失败: {}", e),
-                )),
-            )
-                .into_response();
+    // 合并相同的并发非流式请求：后到的请求等待进行中的请求并复用其响应
+    let coalesce_leader = match &state.coalescer {
+        Some(coalescer) if !payload.stream => {
+            match coalescer.join(RequestCoalescer::key(&payload)) {
+                Joined::Leader(leader) => Some(leader),
+                Joined::Follower(follower) => {
+                    match within_deadline(deadline, follower.wait()).await {
+                        None => return request_timeout_response(),
+                        Some(Some(body)) => {
+                            tracing::info!("复用进行中的相同请求的响应");
+                            let body = coalesce::as_coalesced(&body, &request_id.message_id());
+                            let response =
+                                (StatusCode::OK, [(COALESCED_HEADER, "hit")], Json(body))
+                                    .into_response();
+                            return match &session_id {
+                                Some(session_id) => with_session_cost_header(response, session_id),
+                                None => response,
+                            };
+                        }
+                        // 进行中的请求未成功完成，照常请求上游
+                        Some(None) => None,
+                    }
+                }
+            }
         }
+        _ => None,
     };
 
-    tracing::debug!("Kiro request body: {}", request_body);
+    // 全局准入：过载时按优先级排队
+    let permit = match admit(&payload).await {
+        Ok(permit) => permit,
+        Err(resp) => return resp,
+    };
+    let shadow_handle = shadow_request.map(shadow::ShadowRequest::dispatch);
+
+    let fallbacks = resolve_fallback_chain(&headers, &state, &payload.model);
+    let session_key = session_affinity_key(&payload, session_id.as_deref());
+    let user_hash = user_id.as_deref().map(user_id_hash);
+    let options = CallOptions {
+        session_key: session_key.as_deref(),
+        user_hash: user_hash.as_deref(),
+        api_region: api_region.as_deref(),
+    };
+    let upstream = call_upstream_with_fallback(&provider, &mut payload, &fallbacks, options);
+    let mut call = match within_deadline(deadline, upstream).await {
+        Some(Ok(call)) => call,
+        Some(Err(resp)) => return resp,
+        None => return request_timeout_response(),
+    };
+    call.deadline = deadline;
 
     // 估算输入 tokens
     let input_tokens = token::count_all_tokens(
@@ -272,56 +810,266 @@ pub async fn post_messages(
         payload.tools,
     ) as i32;
 
-    // 检查是否启用了thinking
-    let thinking_enabled = payload
-        .thinking
-        .as_ref()
-        .map(|t| t.is_enabled())
-        .unwrap_or(false);
-
-    let tool_name_map = conversion_result.tool_name_map;
+    call.frame_recorder = FrameRecorder::start(
+        request_id.request_id(),
+        &call.model,
+        call.thinking_enabled,
+        input_tokens,
+        &call.tool_name_map,
+    );
 
-    if payload.stream {
-        // 流式响应
-        handle_stream_request(
-            provider,
-            &request_body,
-            &payload.model,
+    let served_model = call.model.clone();
+    let model_warning = call.model_warning.clone();
+    let message_id = request_id.message_id();
+    let usage_callback =
+        with_session_cost(quota_usage_callback(&state), session_id.clone(), &served_model);
+    let usage_callback =
+        with_usage_ledger(usage_callback, &state, user_id, call.credential_id, &served_model);
+    let response = if payload.stream {
+        match endpoint {
+            // 流式响应
+            MessagesEndpoint::Standard => {
+                handle_stream_request(call, input_tokens, message_id, usage_callback, state.sse_ping)
+                    .await
+            }
+            // 流式响应（缓冲模式）
+            MessagesEndpoint::ClaudeCode => {
+                handle_stream_request_buffered(
+                    call,
+                    input_tokens,
+                    message_id,
+                    usage_callback,
+                    state.sse_ping,
+                )
+                .await
+            }
+        }
+    } else {
+        // 非流式响应：仅在配置开启时提取 thinking 块
+        let extract_thinking = state.extract_thinking && call.thinking_enabled;
+        handle_non_stream_request(
+            call,
             input_tokens,
-            thinking_enabled,
-            tool_name_map,
+            extract_thinking,
+            message_id,
+            usage_callback,
+            cache_slot,
+            coalesce_leader,
         )
         .await
+    };
+
+    let mut response = match &session_id {
+        Some(session_id) => with_session_cost_header(response, session_id),
+        None => response,
+    };
+    if let Some(warning) = model_warning
+        && let Ok(value) = HeaderValue::from_str(&warning)
+    {
+        response.headers_mut().insert(MODEL_WARNING_HEADER, value);
+    }
+    let response = if fallbacks.is_empty() {
+        response
     } else {
-        // 非流式响应：仅在配置开启时提取 thinking 块
-        let extract_thinking = state.extract_thinking && thinking_enabled;
-        handle_non_stream_request(provider, &request_body, &payload.model, input_tokens, extract_thinking, tool_name_map).await
+        with_served_model_header(response, &served_model)
+    };
+    let response = match shadow_handle {
+        Some(handle) => handle.finish(response).await,
+        None => response,
+    };
+    admission::hold(response, permit)
+}
+
+/// 获取全局准入许可，被拒绝时返回 503 响应
+async fn admit(payload: &MessagesRequest) -> Result<Option<AdmissionPermit>, Response> {
+    admission::acquire(&payload.model, payload.stream)
+        .await
+        .map_err(|err| {
+            tracing::warn!(error = %err, "全局准入拒绝请求");
+            let mut response = (
+                StatusCode::SERVICE_UNAVAILABLE,
+                Json(ErrorResponse::new(
+                    "overloaded_error",
+                    "Overloaded: too many requests in flight. Please retry later.",
+                )),
+            )
+                .into_response();
+            response.headers_mut().insert(
+                header::RETRY_AFTER,
+                HeaderValue::from(admission::retry_after_secs()),
+            );
+            response
+        })
+}
+
+/// 配额超出时的 429 响应
+///
+/// 响应头沿用 Anthropic 的 `anthropic-ratelimit-*` 命名，并附带 `retry-after`
+fn quota_exceeded_response(exceeded: QuotaExceeded) -> Response {
+    tracing::warn!("token 配额已用尽: {}", exceeded);
+    let retry_after = (exceeded.reset_at - chrono::Utc::now().timestamp()).max(1);
+    let reset_at = chrono::DateTime::from_timestamp(exceeded.reset_at, 0)
+        .map(|t| t.to_rfc3339_opts(chrono::SecondsFormat::Secs, true))
+        .unwrap_or_default();
+    let kind = exceeded.kind.as_str();
+
+    let mut response = (
+        StatusCode::TOO_MANY_REQUESTS,
+        Json(ErrorResponse::new("rate_limit_error", exceeded.to_string())),
+    )
+        .into_response();
+    let headers = response.headers_mut();
+    headers.insert(header::RETRY_AFTER, HeaderValue::from(retry_after));
+    headers.insert(
+        HeaderName::try_from(format!("anthropic-ratelimit-{}-tokens-limit", kind)).unwrap(),
+        HeaderValue::from(exceeded.limit),
+    );
+    headers.insert(
+        HeaderName::try_from(format!("anthropic-ratelimit-{}-tokens-remaining", kind)).unwrap(),
+        HeaderValue::from(0),
+    );
+    if let Ok(value) = HeaderValue::from_str(&reset_at) {
+        headers.insert(
+            HeaderName::try_from(format!("anthropic-ratelimit-{}-tokens-reset", kind)).unwrap(),
+            value,
+        );
+    }
+    response
+}
+
+/// 构建把本次请求用量计入配额的回调（未配置配额时为 None）
+fn quota_usage_callback(state: &AppState) -> Option<UsageCallback> {
+    let quota = state.quota.clone()?;
+    let key = state.api_key.clone();
+    Some(Box::new(move |input_tokens, output_tokens| {
+        quota.record(
+            &key,
+            input_tokens.max(0) as u64,
+            output_tokens.max(0) as u64,
+            chrono::Utc::now().timestamp(),
+        );
+    }))
+}
+
+/// 会话亲和路由的键
+///
+/// 优先使用 metadata.user_id 中的 session_id；未携带时使用首条 user 消息内容的哈希，
+/// 同一对话的后续请求会携带相同的首条消息
+fn session_affinity_key(payload: &MessagesRequest, session_id: Option<&str>) -> Option<String> {
+    use sha2::{Digest, Sha256};
+
+    if let Some(session_id) = session_id {
+        return Some(format!("session:{}", session_id));
+    }
+    let first_user = payload.messages.iter().find(|m| m.role == "user")?;
+    let digest = Sha256::digest(first_user.content.to_string().as_bytes());
+    Some(format!("message:{}", hex::encode(&digest[..16])))
+}
+
+/// 转发给上游的用户标识哈希（SHA-256 的前 16 字节，十六进制）
+fn user_id_hash(user_id: &str) -> String {
+    use sha2::{Digest, Sha256};
+
+    let digest = Sha256::digest(user_id.as_bytes());
+    hex::encode(&digest[..16])
+}
+
+/// 在用量回调中追加会话费用记录（请求未携带 session 时原样返回）
+fn with_session_cost(
+    callback: Option<UsageCallback>,
+    session_id: Option<String>,
+    model: &str,
+) -> Option<UsageCallback> {
+    let Some(session_id) = session_id else {
+        return callback;
+    };
+    let model = model.to_string();
+    Some(Box::new(move |input_tokens, output_tokens| {
+        session_cost::record(
+            &session_id,
+            &model,
+            input_tokens.max(0) as u64,
+            output_tokens.max(0) as u64,
+        );
+        if let Some(callback) = callback {
+            callback(input_tokens, output_tokens);
+        }
+    }))
+}
+
+/// 在用量回调中追加用量账本记录（未启用账本时原样返回）
+fn with_usage_ledger(
+    callback: Option<UsageCallback>,
+    state: &AppState,
+    user_id: Option<String>,
+    credential_id: Option<u64>,
+    model: &str,
+) -> Option<UsageCallback> {
+    if !usage_ledger::is_enabled() {
+        return callback;
+    }
+    let key = state.api_key.clone();
+    let model = model.to_string();
+    Some(Box::new(move |input_tokens, output_tokens| {
+        usage_ledger::record(
+            &key,
+            user_id.as_deref(),
+            credential_id,
+            &model,
+            input_tokens.max(0) as u64,
+            output_tokens.max(0) as u64,
+        );
+        if let Some(callback) = callback {
+            callback(input_tokens, output_tokens);
+        }
+    }))
+}
+
+/// 附加会话累计估算费用响应头
+///
+/// 非流式响应已包含本次请求；流式响应在发送响应头时尚未结束，只包含之前的请求
+fn with_session_cost_header(mut response: Response, session_id: &str) -> Response {
+    if let Some(cost) = session_cost::get(session_id)
+        && let Ok(value) = HeaderValue::from_str(&format!("{:.6}", cost.cost_usd))
+    {
+        response.headers_mut().insert(SESSION_COST_HEADER, value);
     }
+    response
 }
 
 /// 处理流式请求
 async fn handle_stream_request(
-    provider: std::sync::Arc<crate::kiro::provider::KiroProvider>,
-    request_body: &str,
-    model: &str,
+    call: UpstreamCall,
     input_tokens: i32,
-    thinking_enabled: bool,
-    tool_name_map: std::collections::HashMap<String, String>,
+    message_id: String,
+    usage_callback: Option<UsageCallback>,
+    ping: SsePing,
 ) -> Response {
-    // 调用 Kiro API（支持多凭据故障转移）
-    let response = match provider.call_api_stream(request_body).await {
-        Ok(resp) => resp,
-        Err(e) => return map_provider_error(e),
-    };
-
     // 创建流处理上下文
-    let mut ctx = StreamContext::new_with_thinking(model, input_tokens, thinking_enabled, tool_name_map);
+    let mut ctx = StreamContext::new_with_thinking(
+        &call.model,
+        input_tokens,
+        call.thinking_enabled,
+        call.tool_name_map,
+    );
+    ctx.message_id = message_id;
+    ctx.usage_callback = usage_callback;
+    ctx.retry_after_hint = parse_retry_after(call.response.headers());
+    ctx.prefill_filter = call.prefill.as_deref().map(PrefillFilter::new);
+    ctx.output_filter = OutputFilter::new(&call.stop_sequences);
 
     // 生成初始事件
     let initial_events = ctx.generate_initial_events();
 
     // 创建 SSE 流
-    let stream = create_sse_stream(response, ctx, initial_events);
+    let stream = create_sse_stream(
+        call.response,
+        call.frame_recorder,
+        ctx,
+        initial_events,
+        ping,
+        call.deadline,
+    );
 
     // 返回 SSE 响应
     Response::builder()
@@ -333,19 +1081,27 @@ async fn handle_stream_request(
         .unwrap()
 }
 
-/// Ping 事件间隔（25秒）
-const PING_INTERVAL_SECS: u64 = 25;
+/// 创建 ping 保活的 SSE 字符串
+fn create_ping_sse(style: SsePingStyle) -> Bytes {
+    match style {
+        SsePingStyle::Event => Bytes::from("event: ping\ndata: {\"type\": \"ping\"}\n\n"),
+        SsePingStyle::Comment => Bytes::from_static(b": ping\n\n"),
+    }
+}
 
-/// 创建 ping 事件的 SSE 字符串
-fn create_ping_sse() -> Bytes {
-    Bytes::from("event: ping\ndata: {\"type\": \"ping\"}\n\n")
+/// 创建 ping 定时器（关闭 ping 时定时器不会被轮询）
+fn ping_interval(ping: SsePing) -> Interval {
+    interval(Duration::from_secs(ping.interval_secs.max(1)))
 }
 
 /// 创建 SSE 事件流
 fn create_sse_stream(
     response: reqwest::Response,
+    frame_recorder: Option<FrameRecorder>,
     ctx: StreamContext,
     initial_events: Vec<SseEvent>,
+    ping: SsePing,
+    deadline: Option<Instant>,
 ) -> impl Stream<Item = Result<Bytes, Infallible>> {
     // 先发送初始事件
     let initial_stream = stream::iter(
@@ -354,12 +1110,15 @@ fn create_sse_stream(
             .map(|e| Ok(Bytes::from(e.to_sse_string()))),
     );
 
-    // 然后处理 Kiro 响应流，同时每25秒发送 ping 保活
-    let body_stream = response.bytes_stream();
+    // 然后处理 Kiro 响应流，同时按配置的间隔发送 ping 保活
+    let body_stream = recorded(body_stream(response), frame_recorder);
+    // 流在 handler 返回后才被消费，显式挂在请求 span 下以保留 request_id；
+    // 该 span 随流一起释放，其时长即为事件流输出时长
+    let span = tracing::info_span!("stream");
 
     let processing_stream = stream::unfold(
-        (body_stream, ctx, EventStreamDecoder::new(), false, interval(Duration::from_secs(PING_INTERVAL_SECS))),
-        |(mut body_stream, mut ctx, mut decoder, finished, mut ping_interval)| async move {
+        (body_stream, ctx, EventStreamDecoder::new(), false, ping_interval(ping)),
+        move |(mut body_stream, mut ctx, mut decoder, finished, mut ping_interval)| async move {
             if finished {
                 return None;
             }
@@ -390,13 +1149,19 @@ fn create_sse_stream(
                                 }
                             }
 
+                            // 命中停止序列：不再读取上游，直接结束
+                            let finished = ctx.stop_sequence_matched();
+                            if finished {
+                                events.extend(ctx.generate_final_events());
+                            }
+
                             // 转换为 SSE 字节流
                             let bytes: Vec<Result<Bytes, Infallible>> = events
                                 .into_iter()
                                 .map(|e| Ok(Bytes::from(e.to_sse_string())))
                                 .collect();
 
-                            Some((stream::iter(bytes), (body_stream, ctx, decoder, false, ping_interval)))
+                            Some((stream::iter(bytes), (body_stream, ctx, decoder, finished, ping_interval)))
                         }
                         Some(Err(e)) => {
                             tracing::error!("读取响应流失败: {}", e);
@@ -419,14 +1184,26 @@ fn create_sse_stream(
                         }
                     }
                 }
+                // 超过请求时限：中止读取上游并以 error 事件结束
+                _ = deadline_reached(deadline) => {
+                    tracing::warn!("请求超过时限，中止上游响应流");
+                    ctx.mark_timed_out();
+                    let final_events = ctx.generate_final_events();
+                    let bytes: Vec<Result<Bytes, Infallible>> = final_events
+                        .into_iter()
+                        .map(|e| Ok(Bytes::from(e.to_sse_string())))
+                        .collect();
+                    Some((stream::iter(bytes), (body_stream, ctx, decoder, true, ping_interval)))
+                }
                 // 发送 ping 保活
-                _ = ping_interval.tick() => {
+                _ = ping_interval.tick(), if ping.interval_secs > 0 => {
                     tracing::trace!("发送 ping 保活事件");
-                    let bytes: Vec<Result<Bytes, Infallible>> = vec![Ok(create_ping_sse())];
+                    let bytes: Vec<Result<Bytes, Infallible>> = vec![Ok(create_ping_sse(ping.style))];
                     Some((stream::iter(bytes), (body_stream, ctx, decoder, false, ping_interval)))
                 }
             }
-        },
+        }
+        .instrument(span.clone()),
     )
     .flatten();
 
@@ -437,23 +1214,31 @@ use super::converter::get_context_window_size;
 
 /// 处理非流式请求
 async fn handle_non_stream_request(
-    provider: std::sync::Arc<crate::kiro::provider::KiroProvider>,
-    request_body: &str,
-    model: &str,
+    call: UpstreamCall,
     input_tokens: i32,
     thinking_enabled: bool,
-    tool_name_map: std::collections::HashMap<String, String>,
+    message_id: String,
+    usage_callback: Option<UsageCallback>,
+    cache_slot: Option<(Arc<ResponseCache>, String)>,
+    coalesce_leader: Option<Leader>,
 ) -> Response {
-    // 调用 Kiro API（支持多凭据故障转移）
-    let response = match provider.call_api(request_body).await {
-        Ok(resp) => resp,
-        Err(e) => return map_provider_error(e),
-    };
+    let UpstreamCall {
+        response,
+        model,
+        tool_name_map,
+        frame_recorder,
+        prefill,
+        stop_sequences,
+        deadline,
+        ..
+    } = call;
+    let model = model.as_str();
 
     // 读取响应体
-    let body_bytes = match response.bytes().await {
-        Ok(bytes) => bytes,
-        Err(e) => {
+    let body_bytes = match within_deadline(deadline, response.bytes()).await {
+        None => return request_timeout_response(),
+        Some(Ok(bytes)) => bytes,
+        Some(Err(e)) => {
             tracing::error!("读取响应体失败: {}", e);
             return (
                 StatusCode::BAD_GATEWAY,
@@ -466,6 +1251,10 @@ async fn handle_non_stream_request(
         }
     };
 
+    if let Some(mut recorder) = frame_recorder {
+        recorder.push(&body_bytes);
+    }
+
     // 解析事件流
     let mut decoder = EventStreamDecoder::new();
     if let Err(e) = decoder.feed(&body_bytes) {
@@ -474,6 +1263,8 @@ async fn handle_non_stream_request(
 
     let mut text_content = String::new();
     let mut tool_uses: Vec<serde_json::Value> = Vec::new();
+    // reasoningContentEvent 产生的 thinking / redacted_thinking 块（按到达顺序）
+    let mut reasoning_blocks: Vec<serde_json::Value> = Vec::new();
     let mut has_tool_use = false;
     let mut stop_reason = "end_turn".to_string();
     // 从 contextUsageEvent 计算的实际输入 tokens
@@ -491,6 +1282,14 @@ async fn handle_non_stream_request(
                         Event::AssistantResponse(resp) => {
                             text_content.push_str(&resp.content);
                         }
+                        Event::Unknown {
+                            text: Some(text), ..
+                        } => {
+                            text_content.push_str(&text);
+                        }
+                        Event::ReasoningContent(reasoning) => {
+                            collect_reasoning_block(&mut reasoning_blocks, reasoning);
+                        }
                         Event::ToolUse(tool_use) => {
                             has_tool_use = true;
 
@@ -566,13 +1365,23 @@ async fn handle_non_stream_request(
         stop_reason = "tool_use".to_string();
     }
 
+    if let Some(prefill) = prefill.as_deref() {
+        text_content = strip_prefill(&text_content, prefill);
+    }
+
     // 构建响应内容
     let mut content: Vec<serde_json::Value> = Vec::new();
+    let mut output_filter = OutputFilter::new(&stop_sequences);
 
     if thinking_enabled {
+        content.append(&mut reasoning_blocks);
+
         // 从完整文本中提取 thinking 块
-        let (thinking, remaining_text) =
+        let (thinking, mut remaining_text) =
             super::stream::extract_thinking_from_complete_text(&text_content);
+        if let Some(filter) = output_filter.as_mut() {
+            remaining_text = filter.apply(&remaining_text);
+        }
 
         if let Some(thinking_text) = thinking {
             content.push(json!({
@@ -587,14 +1396,28 @@ async fn handle_non_stream_request(
                 "text": remaining_text
             }));
         }
-    } else if !text_content.is_empty() {
-        content.push(json!({
-            "type": "text",
-            "text": text_content
-        }));
+    } else {
+        if let Some(filter) = output_filter.as_mut() {
+            text_content = filter.apply(&text_content);
+        }
+        if !text_content.is_empty() {
+            content.push(json!({
+                "type": "text",
+                "text": text_content
+            }));
+        }
     }
 
-    content.extend(tool_uses);
+    // 命中停止序列时丢弃其后的工具调用
+    let stop_sequence = output_filter
+        .as_ref()
+        .and_then(|f| f.matched_stop_sequence())
+        .map(str::to_string);
+    if stop_sequence.is_some() {
+        stop_reason = "stop_sequence".to_string();
+    } else {
+        content.extend(tool_uses);
+    }
 
     // 估算输出 tokens
     let output_tokens = token::estimate_output_tokens(&content);
@@ -602,24 +1425,132 @@ async fn handle_non_stream_request(
     // 使用从 contextUsageEvent 计算的 input_tokens，如果没有则使用估算值
     let final_input_tokens = context_input_tokens.unwrap_or(input_tokens);
 
+    if let Some(callback) = usage_callback {
+        callback(final_input_tokens, output_tokens);
+    }
+
     // 构建 Anthropic 响应
     let response_body = json!({
-        "id": format!("msg_{}", Uuid::new_v4().to_string().replace('-', "")),
+        "id": message_id,
         "type": "message",
         "role": "assistant",
         "content": content,
         "model": model,
         "stop_reason": stop_reason,
-        "stop_sequence": null,
+        "stop_sequence": stop_sequence,
         "usage": {
             "input_tokens": final_input_tokens,
-            "output_tokens": output_tokens
+            "output_tokens": output_tokens,
+            "cache_creation_input_tokens": 0,
+            "cache_read_input_tokens": 0
         }
     });
 
+    if let Some((cache, key)) = cache_slot {
+        cache.insert(key, response_body.clone(), std::time::Instant::now());
+    }
+    if let Some(leader) = coalesce_leader {
+        leader.complete(&response_body);
+    }
+
     (StatusCode::OK, Json(response_body)).into_response()
 }
 
+/// 将推理内容事件合并到非流式响应的 thinking 块中
+///
+/// 连续的 text 片段累积到同一个 thinking 块，signature 结束当前块；
+/// redactedContent 作为独立的 redacted_thinking 块
+fn collect_reasoning_block(
+    blocks: &mut Vec<serde_json::Value>,
+    reasoning: crate::kiro::model::events::ReasoningContentEvent,
+) {
+    if let Some(data) = reasoning.redacted_content {
+        blocks.push(json!({ "type": "redacted_thinking", "data": data }));
+    }
+    if reasoning.text.is_none() && reasoning.signature.is_none() {
+        return;
+    }
+
+    // 最后一个块是尚未签名的 thinking 块时继续追加，否则新建
+    let open = blocks
+        .last()
+        .is_some_and(|b| b["type"] == "thinking" && b.get("signature").is_none());
+    if !open {
+        blocks.push(json!({ "type": "thinking", "thinking": "" }));
+    }
+    let Some(block) = blocks.last_mut() else {
+        return;
+    };
+    if let Some(text) = reasoning.text {
+        let thinking = block["thinking"].as_str().unwrap_or_default().to_string() + &text;
+        block["thinking"] = json!(thinking);
+    }
+    if let Some(signature) = reasoning.signature {
+        block["signature"] = json!(signature);
+    }
+}
+
+/// 按模型能力表检查请求（未知模型跳过，由请求转换报错）
+///
+/// - `max_tokens` 超出模型最大输出时截断；`budget_tokens` 不小于 `max_tokens` 时截断为 `max_tokens - 1`
+/// - 模型不支持 thinking 或图片输入时返回错误信息
+fn enforce_model_capabilities(payload: &mut MessagesRequest) -> Result<(), String> {
+    let Some(resolution) = resolve_model(&payload.model) else {
+        return Ok(());
+    };
+    let capabilities = model_registry::capabilities(&resolution.model_id);
+
+    let thinking_enabled = payload.thinking.as_ref().is_some_and(Thinking::is_enabled);
+    if thinking_enabled && !capabilities.supports_thinking {
+        return Err(format!(
+            "Model {} does not support extended thinking",
+            payload.model
+        ));
+    }
+    if !capabilities.supports_images && payload.messages.iter().any(|m| contains_image(&m.content))
+    {
+        return Err(format!(
+            "Model {} does not support image input",
+            payload.model
+        ));
+    }
+
+    let max_output = capabilities.max_output_tokens.min(i32::MAX as u32) as i32;
+    if payload.max_tokens > max_output {
+        tracing::info!(
+            requested = payload.max_tokens,
+            max_output,
+            "max_tokens 超出模型最大输出，已截断"
+        );
+        payload.max_tokens = max_output;
+    }
+    if let Some(thinking) = payload.thinking.as_mut()
+        && thinking.thinking_type == "enabled"
+        && payload.max_tokens > 1
+        && thinking.budget_tokens >= payload.max_tokens
+    {
+        tracing::debug!(
+            budget_tokens = thinking.budget_tokens,
+            max_tokens = payload.max_tokens,
+            "budget_tokens 不小于 max_tokens，已截断"
+        );
+        thinking.budget_tokens = payload.max_tokens - 1;
+    }
+    Ok(())
+}
+
+/// 消息内容（含 tool_result 嵌套内容）中是否包含图片
+fn contains_image(content: &serde_json::Value) -> bool {
+    match content {
+        serde_json::Value::Array(items) => items.iter().any(contains_image),
+        serde_json::Value::Object(obj) => {
+            obj.get("type").and_then(|v| v.as_str()) == Some("image")
+                || obj.get("content").is_some_and(contains_image)
+        }
+        _ => false,
+    }
+}
+
 /// 检测模型名是否包含 "thinking" 后缀，若包含则覆写 thinking 配置
 ///
 /// - Opus 4.6：覆写为 adaptive 类型
@@ -661,9 +1592,7 @@ fn override_thinking_from_model_name(payload: &mut MessagesRequest) {
 /// POST /v1/messages/count_tokens
 ///
 /// 计算消息的 token 数量
-pub async fn count_tokens(
-    JsonExtractor(payload): JsonExtractor<CountTokensRequest>,
-) -> impl IntoResponse {
+pub async fn count_tokens(JsonBody(payload): JsonBody<CountTokensRequest>) -> impl IntoResponse {
     tracing::info!(
         model = %payload.model,
         message_count = %payload.messages.len(),
@@ -682,159 +1611,279 @@ pub async fn count_tokens(
     })
 }
 
-/// POST /cc/v1/messages
+/// GET /v1/sessions/{session_id}/cost
 ///
-/// Claude Code 兼容端点，与 /v1/messages 的区别在于：
-/// - 流式响应会等待 kiro 端返回 contextUsageEvent 后再发送 message_start
-/// - message_start 中的 input_tokens 是从 contextUsageEvent 计算的准确值
-pub async fn post_messages_cc(
-    State(state): State<AppState>,
-    JsonExtractor(mut payload): JsonExtractor<MessagesRequest>,
-) -> Response {
-    tracing::info!(
-        model = %payload.model,
-        max_tokens = %payload.max_tokens,
-        stream = %payload.stream,
-        message_count = %payload.messages.len(),
-        "Received POST /cc/v1/messages request"
-    );
+/// 查询会话累计用量与按标价估算的费用
+pub async fn get_session_cost(Path(session_id): Path<String>) -> Response {
+    match session_cost::get(&session_id) {
+        Some(cost) => Json(cost).into_response(),
+        None => (
+            StatusCode::NOT_FOUND,
+            Json(ErrorResponse::new(
+                "not_found_error",
+                format!("Session not found: {}", session_id),
+            )),
+        )
+            .into_response(),
+    }
+}
 
-    // 检查 KiroProvider 是否可用
-    let provider = match &state.kiro_provider {
-        Some(p) => p.clone(),
-        None => {
-            tracing::error!("KiroProvider 未配置");
-            return (
-                StatusCode::SERVICE_UNAVAILABLE,
-                Json(ErrorResponse::new(
-                    "service_unavailable",
-                    "Kiro API provider not configured",
-                )),
-            )
-                .into_response();
+/// GET /v1/sessions/{session_id}/usage
+///
+/// 查询会话累计用量与当前上下文占上下文窗口的比例
+pub async fn get_session_usage(Path(session_id): Path<String>) -> Response {
+    match session_cost::usage(&session_id) {
+        Some(usage) => Json(usage).into_response(),
+        None => (
+            StatusCode::NOT_FOUND,
+            Json(ErrorResponse::new(
+                "not_found_error",
+                format!("Session not found: {}", session_id),
+            )),
+        )
+            .into_response(),
+    }
+}
+
+fn memory_error_response(err: MemoryError) -> Response {
+    let (status, error_type) = match &err {
+        MemoryError::Disabled | MemoryError::NotFound(_) => {
+            (StatusCode::NOT_FOUND, "not_found_error")
         }
+        MemoryError::Invalid(_) => (StatusCode::BAD_REQUEST, "invalid_request_error"),
+        MemoryError::Persist(_) => (StatusCode::INTERNAL_SERVER_ERROR, "api_error"),
     };
+    (
+        status,
+        Json(ErrorResponse::new(error_type, err.to_string())),
+    )
+        .into_response()
+}
 
-    // 检测模型名是否包含 "thinking" 后缀，若包含则覆写 thinking 配置
-    override_thinking_from_model_name(&mut payload);
-
-    // 检查是否为 WebSearch 请求
-    if websearch::has_web_search_tool(&payload) {
-        tracing::info!("检测到 WebSearch 工具，路由到 WebSearch 处理");
+/// GET /v1/sessions/{session_id}/memory
+///
+/// 查询会话记忆笔记
+pub async fn get_session_memory(Path(session_id): Path<String>) -> Response {
+    match session_memory::get(&session_id) {
+        Ok(memory) => Json(memory).into_response(),
+        Err(e) => memory_error_response(e),
+    }
+}
 
-        // 估算输入 tokens
-        let input_tokens = token::count_all_tokens(
-            payload.model.clone(),
-            payload.system.clone(),
-            payload.messages.clone(),
-            payload.tools.clone(),
-        ) as i32;
+/// PUT /v1/sessions/{session_id}/memory/{key}
+///
+/// 写入（新增或覆盖）一条会话记忆笔记
+pub async fn put_session_memory_note(
+    Path((session_id, key)): Path<(String, String)>,
+    JsonBody(payload): JsonBody<SetMemoryNoteRequest>,
+) -> Response {
+    match session_memory::set_note(&session_id, &key, &payload.value) {
+        Ok(memory) => Json(memory).into_response(),
+        Err(e) => memory_error_response(e),
+    }
+}
 
-        return websearch::handle_websearch_request(provider, &payload, input_tokens).await;
+/// DELETE /v1/sessions/{session_id}/memory/{key}
+///
+/// 删除一条会话记忆笔记
+pub async fn delete_session_memory_note(
+    Path((session_id, key)): Path<(String, String)>,
+) -> Response {
+    match session_memory::delete_note(&session_id, &key) {
+        Ok(memory) => Json(memory).into_response(),
+        Err(e) => memory_error_response(e),
     }
+}
 
-    // 转换请求
-    let conversion_result = match convert_request(&payload) {
-        Ok(result) => result,
-        Err(e) => {
-            let (error_type, message) = match &e {
-                ConversionError::UnsupportedModel(model) => {
-                    ("invalid_request_error", format!("模型不支持: {}", model))
-                }
-                ConversionError::EmptyMessages => {
-                    ("invalid_request_error", "消息列表为空".to_string())
-                }
-            };
-            tracing::warn!("请求转换失败: {}", e);
-            return (
-                StatusCode::BAD_REQUEST,
-                Json(ErrorResponse::new(error_type, message)),
-            )
-                .into_response();
+fn file_error_response(err: FileError) -> Response {
+    let (status, error_type) = match &err {
+        FileError::Disabled | FileError::NotFound(_) => (StatusCode::NOT_FOUND, "not_found_error"),
+        FileError::TooLarge(_) | FileError::StorageFull(_) => {
+            (StatusCode::PAYLOAD_TOO_LARGE, "request_too_large")
         }
+        FileError::Invalid(_) => (StatusCode::BAD_REQUEST, "invalid_request_error"),
+        FileError::Io(_) => (StatusCode::INTERNAL_SERVER_ERROR, "api_error"),
     };
+    if status.is_server_error() {
+        tracing::error!(error = %err, "文件存储失败");
+    }
+    (
+        status,
+        Json(ErrorResponse::new(error_type, err.to_string())),
+    )
+        .into_response()
+}
 
-    // 构建 Kiro 请求（profile_arn 由 provider 层根据实际凭据注入）
-    let kiro_request = KiroRequest {
-        conversation_state: conversion_result.conversation_state,
-        profile_arn: None,
-    };
-
-    let request_body = match serde_json::to_string(&kiro_request) {
-        Ok(body) => body,
-        Err(e) => {
-            tracing::error!("序列化请求失败: {}", e);
-            return (
-                StatusCode::INTERNAL_SERVER_ERROR,
-                Json(ErrorResponse::new(
-                    "internal_error",
-                    format!("序列化请求失败: {}", e),
-                )),
-            )
-                .into_response();
+/// POST /v1/files
+///
+/// 上传文件（multipart/form-data，文件字段名为 `file`）
+pub async fn upload_file(mut multipart: Multipart) -> Response {
+    loop {
+        let field = match multipart.next_field().await {
+            Ok(Some(field)) => field,
+            Ok(None) => {
+                return file_error_response(FileError::Invalid(
+                    "Missing multipart field: file".to_string(),
+                ));
+            }
+            Err(e) => return file_error_response(multipart_error(e)),
+        };
+        if field.name() != Some("file") {
+            continue;
         }
-    };
+        return match save_upload(field).await {
+            Ok(file) => {
+                tracing::info!(
+                    file_id = %file.id,
+                    size_bytes = file.size_bytes,
+                    mime_type = %file.mime_type,
+                    "已保存上传文件"
+                );
+                Json(file).into_response()
+            }
+            Err(e) => file_error_response(e),
+        };
+    }
+}
 
-    tracing::debug!("Kiro request body: {}", request_body);
+/// 边接收边写入上传的文件
+async fn save_upload(mut field: Field<'_>) -> Result<FileObject, FileError> {
+    let filename = field.file_name().unwrap_or_default().to_string();
+    let mut upload = files::begin_upload(&filename, field.content_type()).await?;
+    while let Some(chunk) = field.chunk().await.map_err(multipart_error)? {
+        upload.write(&chunk).await?;
+    }
+    upload.finish().await
+}
 
-    // 估算输入 tokens
-    let input_tokens = token::count_all_tokens(
-        payload.model.clone(),
-        payload.system,
-        payload.messages,
-        payload.tools,
-    ) as i32;
+/// multipart 解析错误（请求体超过上限时按文件过大处理）
+fn multipart_error(e: MultipartError) -> FileError {
+    if e.status() == StatusCode::PAYLOAD_TOO_LARGE {
+        FileError::TooLarge(files::max_file_bytes().unwrap_or_default())
+    } else {
+        FileError::Invalid(format!("Invalid multipart body: {}", e.body_text()))
+    }
+}
 
-    // 检查是否启用了thinking
-    let thinking_enabled = payload
-        .thinking
-        .as_ref()
-        .map(|t| t.is_enabled())
-        .unwrap_or(false);
+/// GET /v1/files
+///
+/// 列出已上传的文件（按上传时间倒序）
+pub async fn list_files(Query(query): Query<ListFilesQuery>) -> Response {
+    match files::list(&query) {
+        Ok(list) => Json(list).into_response(),
+        Err(e) => file_error_response(e),
+    }
+}
 
-    let tool_name_map = conversion_result.tool_name_map;
+/// GET /v1/files/{file_id}
+///
+/// 获取文件元数据
+pub async fn get_file(Path(file_id): Path<String>) -> Response {
+    match files::get(&file_id) {
+        Ok(file) => Json(file).into_response(),
+        Err(e) => file_error_response(e),
+    }
+}
 
-    if payload.stream {
-        // 流式响应（缓冲模式）
-        handle_stream_request_buffered(
-            provider,
-            &request_body,
-            &payload.model,
-            input_tokens,
-            thinking_enabled,
-            tool_name_map,
+/// DELETE /v1/files/{file_id}
+///
+/// 删除文件
+pub async fn delete_file(Path(file_id): Path<String>) -> Response {
+    match files::delete(&file_id) {
+        Ok(()) => Json(json!({"id": file_id, "type": "file_deleted"})).into_response(),
+        Err(e) => file_error_response(e),
+    }
+}
+
+/// POST /v1/embeddings
+///
+/// 转发到配置的 OpenAI 兼容 embeddings 上游，状态码与响应体原样透传
+pub async fn post_embeddings(JsonBody(body): JsonBody<serde_json::Value>) -> Response {
+    let Some(backend) = super::embeddings::get() else {
+        return (
+            StatusCode::NOT_FOUND,
+            Json(ErrorResponse::new(
+                "not_found_error",
+                "Embeddings upstream is not configured",
+            )),
         )
-        .await
-    } else {
-        // 非流式响应：仅在配置开启时提取 thinking 块
-        let extract_thinking = state.extract_thinking && thinking_enabled;
-        handle_non_stream_request(provider, &request_body, &payload.model, input_tokens, extract_thinking, tool_name_map).await
+            .into_response();
+    };
+
+    match backend.forward(body).await {
+        Ok(upstream) => {
+            let status = StatusCode::from_u16(upstream.status().as_u16())
+                .unwrap_or(StatusCode::BAD_GATEWAY);
+            if !status.is_success() {
+                tracing::warn!("embeddings 上游返回 {}", status);
+            }
+            let content_type = upstream
+                .headers()
+                .get(header::CONTENT_TYPE)
+                .and_then(|v| HeaderValue::from_bytes(v.as_bytes()).ok())
+                .unwrap_or_else(|| HeaderValue::from_static("application/json"));
+            let mut response =
+                Response::new(Body::from_stream(upstream.bytes_stream()));
+            *response.status_mut() = status;
+            response
+                .headers_mut()
+                .insert(header::CONTENT_TYPE, content_type);
+            response
+        }
+        Err(e) => {
+            tracing::error!("embeddings 转发失败: {:#}", e);
+            (
+                StatusCode::BAD_GATEWAY,
+                Json(ErrorResponse::new(
+                    "api_error",
+                    "Embeddings upstream request failed",
+                )),
+            )
+                .into_response()
+        }
     }
 }
 
+/// POST /cc/v1/messages
+///
+/// Claude Code 兼容端点，与 /v1/messages 的区别在于：
+/// - 流式响应会等待 kiro 端返回 contextUsageEvent 后再发送 message_start
+/// - message_start 中的 input_tokens 是从 contextUsageEvent 计算的准确值
+pub async fn post_messages_cc(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    JsonBody(payload): JsonBody<MessagesRequest>,
+) -> Response {
+    handle_messages(state, headers, payload, MessagesEndpoint::ClaudeCode).await
+}
+
 /// 处理流式请求（缓冲版本）
 ///
 /// 与 `handle_stream_request` 不同，此函数会缓冲所有事件直到流结束，
 /// 然后用从 contextUsageEvent 计算的正确 input_tokens 生成 message_start 事件。
 async fn handle_stream_request_buffered(
-    provider: std::sync::Arc<crate::kiro::provider::KiroProvider>,
-    request_body: &str,
-    model: &str,
+    call: UpstreamCall,
     estimated_input_tokens: i32,
-    thinking_enabled: bool,
-    tool_name_map: std::collections::HashMap<String, String>,
+    message_id: String,
+    usage_callback: Option<UsageCallback>,
+    ping: SsePing,
 ) -> Response {
-    // 调用 Kiro API（支持多凭据故障转移）
-    let response = match provider.call_api_stream(request_body).await {
-        Ok(resp) => resp,
-        Err(e) => return map_provider_error(e),
-    };
-
     // 创建缓冲流处理上下文
-    let ctx = BufferedStreamContext::new(model, estimated_input_tokens, thinking_enabled, tool_name_map);
+    let ctx = BufferedStreamContext::new(
+        &call.model,
+        estimated_input_tokens,
+        call.thinking_enabled,
+        call.tool_name_map,
+    )
+    .with_message_id(message_id)
+    .with_usage_callback(usage_callback)
+    .with_retry_after_hint(parse_retry_after(call.response.headers()))
+    .with_prefill(call.prefill.as_deref())
+    .with_stop_sequences(&call.stop_sequences);
 
     // 创建缓冲 SSE 流
-    let stream = create_buffered_sse_stream(response, ctx);
+    let stream =
+        create_buffered_sse_stream(call.response, call.frame_recorder, ctx, ping, call.deadline);
 
     // 返回 SSE 响应
     Response::builder()
@@ -855,9 +1904,15 @@ async fn handle_stream_request_buffered(
 /// 4. 一次性发送所有事件
 fn create_buffered_sse_stream(
     response: reqwest::Response,
+    frame_recorder: Option<FrameRecorder>,
     ctx: BufferedStreamContext,
+    ping: SsePing,
+    deadline: Option<Instant>,
 ) -> impl Stream<Item = Result<Bytes, Infallible>> {
-    let body_stream = response.bytes_stream();
+    let body_stream = recorded(body_stream(response), frame_recorder);
+    // 流在 handler 返回后才被消费，显式挂在请求 span 下以保留 request_id；
+    // 该 span 随流一起释放，其时长即为事件流输出时长
+    let span = tracing::info_span!("stream");
 
     stream::unfold(
         (
@@ -865,9 +1920,9 @@ fn create_buffered_sse_stream(
             ctx,
             EventStreamDecoder::new(),
             false,
-            interval(Duration::from_secs(PING_INTERVAL_SECS)),
+            ping_interval(ping),
         ),
-        |(mut body_stream, mut ctx, mut decoder, finished, mut ping_interval)| async move {
+        move |(mut body_stream, mut ctx, mut decoder, finished, mut ping_interval)| async move {
             if finished {
                 return None;
             }
@@ -879,12 +1934,24 @@ fn create_buffered_sse_stream(
                     biased;
 
                     // 优先检查 ping 保活（等待期间唯一发送的数据）
-                    _ = ping_interval.tick() => {
+                    _ = ping_interval.tick(), if ping.interval_secs > 0 => {
                         tracing::trace!("发送 ping 保活事件（缓冲模式）");
-                        let bytes: Vec<Result<Bytes, Infallible>> = vec![Ok(create_ping_sse())];
+                        let bytes: Vec<Result<Bytes, Infallible>> = vec![Ok(create_ping_sse(ping.style))];
                         return Some((stream::iter(bytes), (body_stream, ctx, decoder, false, ping_interval)));
                     }
 
+                    // 超过请求时限：中止读取上游，返回已缓冲的事件并以 error 事件结束
+                    _ = deadline_reached(deadline) => {
+                        tracing::warn!("请求超过时限，中止上游响应流（缓冲模式）");
+                        ctx.mark_timed_out();
+                        let all_events = ctx.finish_and_get_all_events();
+                        let bytes: Vec<Result<Bytes, Infallible>> = all_events
+                            .into_iter()
+                            .map(|e| Ok(Bytes::from(e.to_sse_string())))
+                            .collect();
+                        return Some((stream::iter(bytes), (body_stream, ctx, decoder, true, ping_interval)));
+                    }
+
                     // 然后处理数据流
                     chunk_result = body_stream.next() => {
                         match chunk_result {
@@ -932,7 +1999,69 @@ fn create_buffered_sse_stream(
                     }
                 }
             }
-        },
+        }
+        .instrument(span.clone()),
     )
     .flatten()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::kiro::model::credentials::KiroCredentials;
+    use crate::kiro::token_manager::MultiTokenManager;
+    use crate::model::config::Config;
+
+    #[test]
+    fn test_request_deadline_header_cannot_extend_timeout() {
+        let secs = |value: Option<&str>, default_secs: u64| {
+            let mut headers = HeaderMap::new();
+            if let Some(value) = value {
+                headers.insert(REQUEST_TIMEOUT_HEADER, value.parse().unwrap());
+            }
+            request_deadline(&headers, default_secs)
+                .map(|deadline| (deadline - Instant::now()).as_secs_f64().round() as u64)
+        };
+
+        assert_eq!(secs(None, 60), Some(60));
+        assert_eq!(secs(Some("30"), 60), Some(30));
+        assert_eq!(secs(Some("60"), 60), Some(60));
+        // 配置了默认时限时，请求头不能取消或延长时限
+        assert_eq!(secs(Some("0"), 60), Some(60));
+        assert_eq!(secs(Some("3600"), 60), Some(60));
+        assert_eq!(secs(Some("abc"), 60), Some(60));
+        // 未配置默认时限时，请求头可以设置时限
+        assert_eq!(secs(Some("30"), 0), Some(30));
+        assert_eq!(secs(Some("0"), 0), None);
+        assert_eq!(secs(None, 0), None);
+    }
+
+    #[tokio::test]
+    async fn test_opus_on_free_credentials_falls_back() {
+        let creds: Vec<KiroCredentials> = (0..2)
+            .map(|priority| KiroCredentials {
+                priority,
+                access_token: Some(format!("token-{}", priority)),
+                expires_at: Some((chrono::Utc::now() + chrono::Duration::hours(1)).to_rfc3339()),
+                subscription_title: Some("KIRO FREE".to_string()),
+                ..Default::default()
+            })
+            .collect();
+        let manager = MultiTokenManager::new(Config::default(), creds, None, None, false).unwrap();
+
+        // 所有凭据都不支持 opus：错误应触发 fallback，而不是报告可用时段
+        let err = match manager.acquire_context(Some("claude-opus-4.5")).await {
+            Ok(ctx) => panic!("unexpected credential #{}", ctx.id),
+            Err(e) => e,
+        };
+        assert!(is_fallback_eligible(&err), "{}", err);
+        assert!(!err.to_string().contains("可用时段"), "{}", err);
+
+        // fallback 链中的下一个模型可以正常分配凭据
+        let ctx = manager
+            .acquire_context(Some("claude-sonnet-4.5"))
+            .await
+            .unwrap();
+        assert_eq!(ctx.id, 1);
+    }
+}
@@ -0,0 +1,186 @@
+//! 图片压缩
+//!
+//! Claude Code 发送的高分辨率截图会显著增大请求体，有时超出 Kiro 的请求大小限制。
+//! 配置 `imageCompression` 后，请求转换时对最长边超过 `maxDimension` 或大小超过 `maxKb`
+//! 的 base64 图片等比缩小并重新编码为 JPEG / WebP。GIF（可能是动图）不处理；
+//! 解码失败或重新编码后没有变小时保留原图。
+
+use std::io::Cursor;
+use std::sync::OnceLock;
+
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD;
+use image::codecs::jpeg::JpegEncoder;
+use image::codecs::webp::WebPEncoder;
+use image::imageops::FilterType;
+use image::{DynamicImage, ExtendedColorType, ImageFormat, ImageReader, Rgb, RgbImage};
+
+use crate::model::config::{ImageCompressionConfig, ImageOutputFormat};
+
+static CONFIG: OnceLock<ImageCompressionConfig> = OnceLock::new();
+
+/// 初始化图片压缩配置（未配置时图片原样转发）
+pub fn init(config: Option<&ImageCompressionConfig>) {
+    if let Some(config) = config {
+        let _ = CONFIG.set(config.clone());
+    }
+}
+
+/// 按配置压缩图片，返回新的（格式, base64 数据）
+///
+/// 未配置、图片无需处理或处理失败时返回 None，调用方使用原图
+pub fn compress(format: &str, data: &str) -> Option<(String, String)> {
+    compress_with(CONFIG.get()?, format, data)
+}
+
+fn compress_with(
+    config: &ImageCompressionConfig,
+    format: &str,
+    data: &str,
+) -> Option<(String, String)> {
+    let input_format = match format {
+        "jpeg" => ImageFormat::Jpeg,
+        "png" => ImageFormat::Png,
+        "webp" => ImageFormat::WebP,
+        _ => return None,
+    };
+    let bytes = STANDARD.decode(data).ok()?;
+    let (width, height) = ImageReader::with_format(Cursor::new(&bytes), input_format)
+        .into_dimensions()
+        .ok()?;
+    let oversized = width.max(height) > config.max_dimension;
+    if !oversized && bytes.len() <= config.max_kb.saturating_mul(1024) {
+        return None;
+    }
+
+    let mut image = match ImageReader::with_format(Cursor::new(&bytes), input_format).decode() {
+        Ok(image) => image,
+        Err(e) => {
+            tracing::warn!("图片解码失败，保留原图: {}", e);
+            return None;
+        }
+    };
+    if oversized {
+        image = image.resize(
+            config.max_dimension,
+            config.max_dimension,
+            FilterType::Triangle,
+        );
+    }
+    let encoded = match encode(&image, config) {
+        Ok(encoded) => encoded,
+        Err(e) => {
+            tracing::warn!("图片重新编码失败，保留原图: {}", e);
+            return None;
+        }
+    };
+    // 尺寸未变且重新编码后没有变小时保留原图
+    if !oversized && encoded.len() >= bytes.len() {
+        return None;
+    }
+
+    let output_format = match config.format {
+        ImageOutputFormat::Jpeg => "jpeg",
+        ImageOutputFormat::Webp => "webp",
+    };
+    tracing::debug!(
+        "图片已压缩: {} {}x{} {} 字节 -> {} {}x{} {} 字节",
+        format,
+        width,
+        height,
+        bytes.len(),
+        output_format,
+        image.width(),
+        image.height(),
+        encoded.len()
+    );
+    Some((output_format.to_string(), STANDARD.encode(encoded)))
+}
+
+fn encode(image: &DynamicImage, config: &ImageCompressionConfig) -> image::ImageResult<Vec<u8>> {
+    let mut out = Vec::new();
+    match config.format {
+        ImageOutputFormat::Jpeg => {
+            JpegEncoder::new_with_quality(&mut out, config.jpeg_quality.clamp(1, 100))
+                .encode_image(&flatten(image))?;
+        }
+        ImageOutputFormat::Webp => {
+            let rgba = image.to_rgba8();
+            WebPEncoder::new_lossless(&mut out).encode(
+                &rgba,
+                rgba.width(),
+                rgba.height(),
+                ExtendedColorType::Rgba8,
+            )?;
+        }
+    }
+    Ok(out)
+}
+
+/// JPEG 不支持透明通道，透明区域以白色填充
+fn flatten(image: &DynamicImage) -> RgbImage {
+    if !image.color().has_alpha() {
+        return image.to_rgb8();
+    }
+    let rgba = image.to_rgba8();
+    RgbImage::from_fn(rgba.width(), rgba.height(), |x, y| {
+        let [r, g, b, a] = rgba.get_pixel(x, y).0;
+        let blend = |c: u8| ((c as u32 * a as u32 + 255 * (255 - a as u32)) / 255) as u8;
+        Rgb([blend(r), blend(g), blend(b)])
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::RgbaImage;
+
+    fn png(width: u32, height: u32) -> String {
+        let image = RgbaImage::from_fn(width, height, |x, y| {
+            image::Rgba([(x % 256) as u8, (y % 256) as u8, ((x * y) % 256) as u8, 255])
+        });
+        let mut out = Vec::new();
+        DynamicImage::ImageRgba8(image)
+            .write_to(&mut Cursor::new(&mut out), ImageFormat::Png)
+            .unwrap();
+        STANDARD.encode(out)
+    }
+
+    fn dimensions(format: ImageFormat, data: &str) -> (u32, u32) {
+        let bytes = STANDARD.decode(data).unwrap();
+        ImageReader::with_format(Cursor::new(bytes), format)
+            .into_dimensions()
+            .unwrap()
+    }
+
+    #[test]
+    fn test_oversized_image_is_downscaled_to_jpeg() {
+        let config = ImageCompressionConfig {
+            max_dimension: 400,
+            ..Default::default()
+        };
+        let (format, data) = compress_with(&config, "png", &png(1200, 600)).unwrap();
+        assert_eq!(format, "jpeg");
+        assert_eq!(dimensions(ImageFormat::Jpeg, &data), (400, 200));
+    }
+
+    #[test]
+    fn test_webp_output() {
+        let config = ImageCompressionConfig {
+            max_dimension: 300,
+            format: ImageOutputFormat::Webp,
+            ..Default::default()
+        };
+        let (format, data) = compress_with(&config, "png", &png(600, 600)).unwrap();
+        assert_eq!(format, "webp");
+        assert_eq!(dimensions(ImageFormat::WebP, &data), (300, 300));
+    }
+
+    #[test]
+    fn test_small_or_unsupported_image_is_kept() {
+        let config = ImageCompressionConfig::default();
+        assert!(compress_with(&config, "png", &png(64, 64)).is_none());
+        assert!(compress_with(&config, "gif", &png(64, 64)).is_none());
+        assert!(compress_with(&config, "png", "not base64!").is_none());
+    }
+}
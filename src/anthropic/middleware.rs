@@ -1,20 +1,49 @@
 //! Anthropic API 中间件
 
+use std::collections::HashMap;
+use std::net::{IpAddr, SocketAddr};
 use std::sync::Arc;
+use std::time::Instant;
 
 use axum::{
     body::Body,
-    extract::State,
-    http::{Request, StatusCode},
+    extract::{ConnectInfo, OriginalUri, State},
+    http::{HeaderValue, Request, StatusCode, header},
     middleware::Next,
     response::{IntoResponse, Json, Response},
 };
 
 use crate::common::auth;
 use crate::kiro::provider::KiroProvider;
+use crate::model::config::{
+    FaultRule, RateLimitConfig, RequestTransform, ResponseCacheConfig, SsePingStyle, TokenQuota,
+};
 
+use super::batches::BatchStore;
+use super::coalesce::RequestCoalescer;
+use super::fault::{self, FaultInjector};
+use super::quota::QuotaTracker;
+use super::rate_limit::{RateLimitScope, RateLimiter};
+use super::response_cache::ResponseCache;
 use super::types::ErrorResponse;
 
+/// 流式响应的 ping 保活设置
+#[derive(Debug, Clone, Copy)]
+pub struct SsePing {
+    /// 发送间隔（秒），0 表示不发送
+    pub interval_secs: u64,
+    pub style: SsePingStyle,
+}
+
+impl Default for SsePing {
+    fn default() -> Self {
+        Self {
+            interval_secs: 25,
+            style: SsePingStyle::Event,
+        }
+    }
+}
+
 /// 应用共享状态
 #[derive(Clone)]
 pub struct AppState {
@@ -25,6 +54,30 @@ pub struct AppState {
     pub kiro_provider: Option<Arc<KiroProvider>>,
     /// 是否开启非流式响应的 thinking 块提取
     pub extract_thinking: bool,
+    /// 模型 fallback 规则（请求模型 → 按顺序尝试的备用模型）
+    pub model_fallbacks: Arc<HashMap<String, Vec<String>>>,
+    /// 请求改写规则
+    pub request_transforms: Arc<Vec<RequestTransform>>,
+    /// 滚动窗口 token 配额（未配置时为 None）
+    pub quota: Option<Arc<QuotaTracker>>,
+    /// Message Batches 存储与执行
+    pub batches: Arc<BatchStore>,
+    /// 公共 API 限流（未配置时为 None）
+    pub rate_limiter: Option<Arc<RateLimiter>>,
+    /// 流式响应的 ping 保活设置
+    pub sse_ping: SsePing,
+    /// 请求与上游交互的默认总时限（秒），0 表示不限制
+    pub request_timeout_secs: u64,
+    /// 非流式请求的响应缓存（未配置时为 None）
+    pub response_cache: Option<Arc<ResponseCache>>,
+    /// 相同并发请求合并（未开启时为 None）
+    pub coalescer: Option<Arc<RequestCoalescer>>,
+    /// 请求体大小上限（字节）
+    pub max_body_bytes: usize,
+    /// 故障注入（未配置时为 None）
+    pub fault_injector: Option<Arc<FaultInjector>>,
+    /// 允许通过 `X-Kiro-Region` 请求头指定的 API Region
+    pub allowed_api_regions: Arc<Vec<String>>,
 }
 
 impl AppState {
@@ -34,6 +87,18 @@ impl AppState {
             api_key: api_key.into(),
             kiro_provider: None,
             extract_thinking,
+            model_fallbacks: Arc::new(HashMap::new()),
+            request_transforms: Arc::new(Vec::new()),
+            quota: None,
+            batches: Arc::new(BatchStore::new(1)),
+            rate_limiter: None,
+            sse_ping: SsePing::default(),
+            request_timeout_secs: 0,
+            response_cache: None,
+            coalescer: None,
+            max_body_bytes: 50 * 1024 * 1024,
+            fault_injector: None,
+            allowed_api_regions: Arc::new(Vec::new()),
         }
     }
 
@@ -42,6 +107,81 @@ impl AppState {
         self.kiro_provider = Some(Arc::new(provider));
         self
     }
+
+    /// 设置模型 fallback 规则
+    pub fn with_model_fallbacks(mut self, model_fallbacks: HashMap<String, Vec<String>>) -> Self {
+        self.model_fallbacks = Arc::new(model_fallbacks);
+        self
+    }
+
+    /// 设置请求改写规则
+    pub fn with_request_transforms(mut self, request_transforms: Vec<RequestTransform>) -> Self {
+        self.request_transforms = Arc::new(request_transforms);
+        self
+    }
+
+    /// 设置 Message Batches 执行并发数
+    pub fn with_batch_concurrency(mut self, concurrency: usize) -> Self {
+        self.batches = Arc::new(BatchStore::new(concurrency));
+        self
+    }
+
+    /// 设置滚动窗口 token 配额
+    pub fn with_token_quotas(mut self, token_quotas: Vec<TokenQuota>) -> Self {
+        self.quota = QuotaTracker::new(token_quotas).map(Arc::new);
+        self
+    }
+
+    /// 设置流式响应的 ping 保活
+    pub fn with_sse_ping(mut self, interval_secs: u64, style: SsePingStyle) -> Self {
+        self.sse_ping = SsePing {
+            interval_secs,
+            style,
+        };
+        self
+    }
+
+    /// 设置请求与上游交互的默认总时限（秒）
+    pub fn with_request_timeout(mut self, secs: u64) -> Self {
+        self.request_timeout_secs = secs;
+        self
+    }
+
+    /// 设置非流式请求的响应缓存
+    pub fn with_response_cache(mut self, response_cache: Option<ResponseCacheConfig>) -> Self {
+        self.response_cache = response_cache.and_then(ResponseCache::new).map(Arc::new);
+        self
+    }
+
+    /// 设置是否合并相同的并发非流式请求
+    pub fn with_request_coalescing(mut self, enabled: bool) -> Self {
+        self.coalescer = enabled.then(|| Arc::new(RequestCoalescer::new()));
+        self
+    }
+
+    /// 设置请求体大小上限（MB）
+    pub fn with_max_body_size(mut self, mb: usize) -> Self {
+        self.max_body_bytes = mb.saturating_mul(1024 * 1024);
+        self
+    }
+
+    /// 设置公共 API 限流
+    pub fn with_rate_limit(mut self, rate_limit: Option<RateLimitConfig>) -> Self {
+        self.rate_limiter = rate_limit.and_then(RateLimiter::new).map(Arc::new);
+        self
+    }
+
+    /// 设置允许通过请求头指定的 API Region
+    pub fn with_allowed_api_regions(mut self, regions: Vec<String>) -> Self {
+        self.allowed_api_regions = Arc::new(regions);
+        self
+    }
+
+    /// 设置故障注入规则
+    pub fn with_fault_injection(mut self, rules: Vec<FaultRule>) -> Self {
+        self.fault_injector = FaultInjector::new(rules).map(Arc::new);
+        self
+    }
 }
 
 /// API Key 认证中间件
@@ -59,6 +199,103 @@ pub async fn auth_middleware(
     }
 }
 
+/// 限流中间件
+///
+/// 位于认证之后，按 API Key 与客户端 IP 消耗令牌，桶空时返回 429 并附带 Retry-After
+pub async fn rate_limit_middleware(
+    State(state): State<AppState>,
+    request: Request<Body>,
+    next: Next,
+) -> Response {
+    let Some(limiter) = state.rate_limiter.as_ref() else {
+        return next.run(request).await;
+    };
+
+    let api_key = auth::extract_api_key(&request);
+    let ip = client_ip(&request, limiter.trust_forwarded_for());
+    match limiter.check(api_key.as_deref(), ip, Instant::now()) {
+        Ok(()) => next.run(request).await,
+        Err(limited) => {
+            let scope = match limited.scope {
+                RateLimitScope::ApiKey => "API key",
+                RateLimitScope::Ip => "client IP",
+            };
+            tracing::warn!(
+                ip = ?ip,
+                retry_after = limited.retry_after,
+                "请求被限流（{}）",
+                scope
+            );
+            let mut response = (
+                StatusCode::TOO_MANY_REQUESTS,
+                Json(ErrorResponse::new(
+                    "rate_limit_error",
+                    format!(
+                        "Rate limit exceeded for this {}. Please retry after {} seconds.",
+                        scope, limited.retry_after
+                    ),
+                )),
+            )
+                .into_response();
+            response
+                .headers_mut()
+                .insert(header::RETRY_AFTER, HeaderValue::from(limited.retry_after));
+            response
+        }
+    }
+}
+
+/// 故障注入中间件
+///
+/// 位于认证与限流之后：错误类故障直接返回，不调用处理器；流式故障改写处理器返回的事件流
+pub async fn fault_injection_middleware(
+    State(state): State<AppState>,
+    request: Request<Body>,
+    next: Next,
+) -> Response {
+    let Some(injector) = state.fault_injector.as_ref() else {
+        return next.run(request).await;
+    };
+
+    // nest 后的路由只能看到去掉前缀的路径，按原始路径匹配规则
+    let path = request
+        .extensions()
+        .get::<OriginalUri>()
+        .map_or_else(|| request.uri().path(), |uri| uri.path())
+        .to_string();
+    let Some(rule) = injector.pick(&path).cloned() else {
+        return next.run(request).await;
+    };
+    tracing::info!(path = %path, fault = ?rule.fault, "注入故障");
+    if let Some(response) = fault::error_response(rule.fault) {
+        return response;
+    }
+    fault::wrap_stream(next.run(request).await, &rule)
+}
+
+/// 获取客户端 IP
+///
+/// 开启 `trust_forwarded_for` 时优先取 `X-Forwarded-For` 的最后一个地址（由可信反向代理追加；
+/// 前面的地址由客户端提供，可以任意伪造），否则使用 TCP 对端地址
+fn client_ip(request: &Request<Body>, trust_forwarded_for: bool) -> Option<IpAddr> {
+    if trust_forwarded_for
+        && let Some(ip) = request
+            .headers()
+            .get_all("x-forwarded-for")
+            .iter()
+            .next_back()
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.rsplit(',').next())
+            .and_then(|v| v.trim().parse().ok())
+    {
+        return Some(ip);
+    }
+    request
+        .extensions()
+        .get::<ConnectInfo<SocketAddr>>()
+        .map(|info| info.0.ip())
+}
+
 /// CORS 中间件层
 ///
 /// **安全说明**：当前配置允许所有来源（Any），这是为了支持公开 API 服务。
@@ -76,3 +313,33 @@ pub fn cors_layer() -> tower_http::cors::CorsLayer {
         .allow_methods(Any)
         .allow_headers(Any)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request_with_forwarded_for(value: &str) -> Request<Body> {
+        let mut request = Request::builder()
+            .header("x-forwarded-for", value)
+            .body(Body::empty())
+            .unwrap();
+        request
+            .extensions_mut()
+            .insert(ConnectInfo(SocketAddr::from(([10, 0, 0, 1], 8080))));
+        request
+    }
+
+    #[test]
+    fn test_client_ip_uses_proxy_appended_forwarded_for() {
+        // 客户端伪造的第一个地址不可信，取反向代理追加的最后一个地址
+        let request = request_with_forwarded_for("1.1.1.1, 203.0.113.7");
+        assert_eq!(
+            client_ip(&request, true),
+            Some(IpAddr::from([203, 0, 113, 7]))
+        );
+        assert_eq!(
+            client_ip(&request, false),
+            Some(IpAddr::from([10, 0, 0, 1]))
+        );
+    }
+}
@@ -8,6 +8,11 @@
 //! - `GET /v1/models` - 获取可用模型列表
 //! - `POST /v1/messages` - 创建消息（对话）
 //! - `POST /v1/messages/count_tokens` - 计算 token 数量
+//! - `POST /v1/messages/batches` 等 - Message Batches API（后台执行，结果为 JSONL）
+//! - `GET /v1/sessions/{session_id}/cost` - 查询会话累计估算费用
+//! - `GET /v1/sessions/{session_id}/memory` 等 - 读写会话记忆笔记
+//! - `POST /v1/files` 等 - Files API（文件保存在本地，消息中的 file_id 引用内联为文件内容）
+//! - `POST /v1/embeddings` - OpenAI 兼容 embeddings（转发到配置的外部上游）
 //!
 //! ## Claude Code 兼容端点 (/cc/v1)
 //! - `POST /cc/v1/messages` - 创建消息（流式响应会等待 contextUsageEvent 后再发送 message_start，确保 input_tokens 准确）
@@ -22,12 +27,37 @@
 //! axum::serve(listener, app).await?;
 //! ```
 
+pub mod admission;
+mod batches;
+mod body;
+mod coalesce;
 mod converter;
+pub mod embeddings;
+mod fault;
+pub mod files;
 mod handlers;
+pub mod image_compress;
 mod middleware;
+pub mod output_filter;
+mod prefill;
+mod quota;
+mod rate_limit;
+pub mod replay;
+mod request_id;
+mod response_cache;
 mod router;
+pub mod search_provider;
+pub mod session_cost;
+pub mod session_memory;
+pub mod shadow;
 mod stream;
+pub mod system_prompt;
+mod tool_choice;
+mod transform;
 pub mod types;
+mod upstream_error;
+pub mod usage_ledger;
 mod websearch;
 
+pub use converter::init_tool_history_repair;
 pub use router::create_router_with_provider;
@@ -0,0 +1,284 @@
+//! 响应输出过滤
+//!
+//! Kiro 不支持请求中的 `stop_sequences`，由代理在输出的 text 内容中检测：命中后截断
+//! 停止序列及其后的内容，`stop_reason` 为 `stop_sequence` 并回传命中的序列。
+//! `outputRedactions` 配置的违禁字符串/正则在发送给客户端前被替换。
+//!
+//! 流式输出中匹配可能跨越多个 chunk，过滤器会暂扣文本末尾可能属于匹配的部分，
+//! 确认不构成匹配（或流结束）后再放行。
+
+use anyhow::Context;
+use parking_lot::RwLock;
+use regex::Regex;
+
+use crate::model::config::OutputRedaction;
+
+/// 正则规则跨 chunk 匹配时暂扣的字节数
+///
+/// 正则匹配的长度不固定，跨 chunk 且长于该值的匹配无法识别
+const REGEX_HOLD_BYTES: usize = 64;
+
+static REDACTIONS: RwLock<Vec<Redaction>> = RwLock::new(Vec::new());
+
+/// 编译后的脱敏规则
+#[derive(Debug, Clone)]
+struct Redaction {
+    regex: Regex,
+    replacement: String,
+    /// 跨 chunk 匹配时需要暂扣的字节数
+    hold_bytes: usize,
+}
+
+impl Redaction {
+    fn compile(rule: &OutputRedaction) -> anyhow::Result<Self> {
+        if rule.pattern.is_empty() {
+            anyhow::bail!("脱敏规则的 pattern 不能为空");
+        }
+        let (pattern, hold_bytes) = if rule.regex {
+            (rule.pattern.clone(), REGEX_HOLD_BYTES)
+        } else {
+            (regex::escape(&rule.pattern), rule.pattern.len() - 1)
+        };
+        let regex =
+            Regex::new(&pattern).with_context(|| format!("无效的脱敏正则: {}", rule.pattern))?;
+        Ok(Self {
+            regex,
+            replacement: rule.replacement.clone(),
+            hold_bytes,
+        })
+    }
+}
+
+/// 编译并替换当前生效的脱敏规则（启动与配置热重载时调用），任一规则无效时保留原规则
+pub fn init(rules: &[OutputRedaction]) -> anyhow::Result<()> {
+    let compiled = rules
+        .iter()
+        .map(Redaction::compile)
+        .collect::<anyhow::Result<Vec<_>>>()?;
+    *REDACTIONS.write() = compiled;
+    Ok(())
+}
+
+/// 依次应用所有脱敏规则
+fn redact(redactions: &[Redaction], text: &str) -> String {
+    redactions.iter().fold(text.to_string(), |text, r| {
+        r.regex
+            .replace_all(&text, regex::NoExpand(&r.replacement))
+            .into_owned()
+    })
+}
+
+/// 单个响应的输出过滤器
+#[derive(Debug, Clone)]
+pub struct OutputFilter {
+    stop_sequences: Vec<String>,
+    redactions: Vec<Redaction>,
+    /// 脱敏规则暂扣字节数的最大值
+    hold_bytes: usize,
+    /// 尚未确认是否为停止序列开头的文本
+    pending_stop: String,
+    /// 尚未确认是否命中脱敏规则的文本
+    pending_redact: String,
+    /// 命中的停止序列
+    matched: Option<String>,
+}
+
+impl OutputFilter {
+    /// 按请求的停止序列与当前生效的脱敏规则创建过滤器，两者都为空时返回 None
+    pub fn new(stop_sequences: &[String]) -> Option<Self> {
+        Self::with_redactions(stop_sequences, REDACTIONS.read().clone())
+    }
+
+    fn with_redactions(stop_sequences: &[String], redactions: Vec<Redaction>) -> Option<Self> {
+        let stop_sequences: Vec<String> = stop_sequences
+            .iter()
+            .filter(|s| !s.is_empty())
+            .cloned()
+            .collect();
+        if stop_sequences.is_empty() && redactions.is_empty() {
+            return None;
+        }
+        Some(Self {
+            stop_sequences,
+            hold_bytes: redactions.iter().map(|r| r.hold_bytes).max().unwrap_or(0),
+            redactions,
+            pending_stop: String::new(),
+            pending_redact: String::new(),
+            matched: None,
+        })
+    }
+
+    /// 命中的停止序列
+    pub fn matched_stop_sequence(&self) -> Option<&str> {
+        self.matched.as_deref()
+    }
+
+    /// 输入一段输出文本，返回可以发送给客户端的部分（命中停止序列后总是返回空）
+    pub fn push(&mut self, text: &str) -> String {
+        if self.matched.is_some() {
+            return String::new();
+        }
+        self.pending_stop.push_str(text);
+
+        if let Some((pos, sequence)) = self.find_stop_sequence() {
+            self.pending_stop.truncate(pos);
+            let ready = std::mem::take(&mut self.pending_stop);
+            self.pending_redact.push_str(&ready);
+            self.matched = Some(sequence);
+            return self.take_all();
+        }
+
+        let cut = self.pending_stop.len() - self.partial_stop_len();
+        let ready: String = self.pending_stop.drain(..cut).collect();
+        self.pending_redact.push_str(&ready);
+        self.take_ready()
+    }
+
+    /// 结束过滤，返回仍在暂扣中的文本
+    pub fn finish(&mut self) -> String {
+        let rest = std::mem::take(&mut self.pending_stop);
+        self.pending_redact.push_str(&rest);
+        self.take_all()
+    }
+
+    /// 过滤完整的响应文本（非流式响应使用）
+    pub fn apply(&mut self, text: &str) -> String {
+        let mut out = self.push(text);
+        out.push_str(&self.finish());
+        out
+    }
+
+    /// 查找最早出现的停止序列
+    fn find_stop_sequence(&self) -> Option<(usize, String)> {
+        self.stop_sequences
+            .iter()
+            .filter_map(|s| self.pending_stop.find(s.as_str()).map(|pos| (pos, s)))
+            .min_by_key(|(pos, _)| *pos)
+            .map(|(pos, s)| (pos, s.clone()))
+    }
+
+    /// 缓冲区末尾可能是停止序列开头的最长长度
+    fn partial_stop_len(&self) -> usize {
+        self.stop_sequences
+            .iter()
+            .filter_map(|s| {
+                (1..s.len())
+                    .rev()
+                    .filter(|&k| s.is_char_boundary(k))
+                    .find(|&k| self.pending_stop.ends_with(&s[..k]))
+            })
+            .max()
+            .unwrap_or(0)
+    }
+
+    /// 放行不会再与后续文本组成匹配的部分
+    fn take_ready(&mut self) -> String {
+        if self.redactions.is_empty() {
+            return std::mem::take(&mut self.pending_redact);
+        }
+        let text = &self.pending_redact;
+        let mut cut = text.floor_char_boundary(text.len().saturating_sub(self.hold_bytes));
+        // 跨越切分点的匹配可能随后续文本变化，整体暂扣
+        while let Some(start) = self
+            .redactions
+            .iter()
+            .flat_map(|r| r.regex.find_iter(text))
+            .filter(|m| m.start() < cut && m.end() > cut)
+            .map(|m| m.start())
+            .min()
+        {
+            cut = start;
+        }
+        let ready: String = self.pending_redact.drain(..cut).collect();
+        redact(&self.redactions, &ready)
+    }
+
+    fn take_all(&mut self) -> String {
+        let text = std::mem::take(&mut self.pending_redact);
+        redact(&self.redactions, &text)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rule(pattern: &str, regex: bool) -> Redaction {
+        Redaction::compile(&OutputRedaction {
+            pattern: pattern.to_string(),
+            regex,
+            replacement: "[REDACTED]".to_string(),
+        })
+        .unwrap()
+    }
+
+    fn filter(stop_sequences: &[&str], redactions: Vec<Redaction>) -> OutputFilter {
+        let stop_sequences: Vec<String> = stop_sequences.iter().map(|s| s.to_string()).collect();
+        OutputFilter::with_redactions(&stop_sequences, redactions).unwrap()
+    }
+
+    #[test]
+    fn test_stop_sequence_across_chunks() {
+        let mut f = filter(&["\n\nHuman:"], Vec::new());
+        assert_eq!(f.push("Hello"), "Hello");
+        assert_eq!(f.push(" world\n"), " world");
+        assert_eq!(f.push("\nHum"), "");
+        assert_eq!(f.push("an: ignored"), "");
+        assert_eq!(f.matched_stop_sequence(), Some("\n\nHuman:"));
+        assert_eq!(f.push("more"), "");
+        assert_eq!(f.finish(), "");
+    }
+
+    #[test]
+    fn test_partial_stop_sequence_released() {
+        let mut f = filter(&["END", "STOP"], Vec::new());
+        assert_eq!(f.push("the EN"), "the ");
+        assert_eq!(f.push("D-less"), "");
+        assert_eq!(f.matched_stop_sequence(), Some("END"));
+
+        let mut f = filter(&["END"], Vec::new());
+        assert_eq!(f.push("EN"), "");
+        assert_eq!(f.push("TRY"), "ENTRY");
+        assert_eq!(f.push(" E"), " ");
+        assert_eq!(f.finish(), "E");
+        assert_eq!(f.matched_stop_sequence(), None);
+    }
+
+    #[test]
+    fn test_earliest_stop_sequence_wins() {
+        let mut f = filter(&["b", "a"], Vec::new());
+        assert_eq!(f.apply("xxabxx"), "xx");
+        assert_eq!(f.matched_stop_sequence(), Some("a"));
+    }
+
+    #[test]
+    fn test_redaction_across_chunks() {
+        let mut f = filter(&[], vec![rule("secret-key", false)]);
+        let mut out = f.push("my secr");
+        out.push_str(&f.push("et-key is here, secret"));
+        out.push_str(&f.push("-key"));
+        out.push_str(&f.finish());
+        assert_eq!(out, "my [REDACTED] is here, [REDACTED]");
+    }
+
+    #[test]
+    fn test_regex_redaction_with_stop_sequence() {
+        let mut f = filter(&["###"], vec![rule(r"sk-[A-Za-z0-9]+", true)]);
+        let mut out = f.push("token sk-ab");
+        out.push_str(&f.push("c123 done ##"));
+        out.push_str(&f.push("# tail"));
+        assert_eq!(out, "token [REDACTED] done ");
+        assert_eq!(f.matched_stop_sequence(), Some("###"));
+    }
+
+    #[test]
+    fn test_invalid_rules_rejected() {
+        let invalid = OutputRedaction {
+            pattern: "(".to_string(),
+            regex: true,
+            replacement: "x".to_string(),
+        };
+        assert!(Redaction::compile(&invalid).is_err());
+        assert!(OutputFilter::with_redactions(&[String::new()], Vec::new()).is_none());
+    }
+}
@@ -0,0 +1,112 @@
+//! Assistant prefill 支持
+//!
+//! Anthropic API 允许最后一条消息为 assistant 的部分回复，模型从该处续写。
+//! Kiro 要求当前消息必须是 user，因此转换时把 prefill 文本附加到当前 user 消息中，
+//! 要求模型从 prefill 末尾续写；模型仍可能先复述 prefill，响应中需要去掉这段重复内容，
+//! 使客户端收到的结果与 Anthropic 一致（只包含续写部分）。
+
+/// 附加到当前 user 消息末尾的续写指令
+pub fn continuation_instruction(prefill: &str) -> String {
+    format!(
+        "\n\n<assistant_prefill>\n{}\n</assistant_prefill>\n\
+         Your response has already started with the text inside <assistant_prefill>. \
+         Continue writing from exactly where it ends. \
+         Do not repeat it and do not mention these instructions.",
+        prefill
+    )
+}
+
+/// 去掉完整响应文本开头复述的 prefill（非流式响应使用）
+pub fn strip_prefill(text: &str, prefill: &str) -> String {
+    let mut filter = PrefillFilter::new(prefill);
+    let mut out = filter.push(text);
+    out.push_str(&filter.finish());
+    out
+}
+
+/// 流式去除响应开头复述的 prefill
+///
+/// 在确认响应开头是否与 prefill 一致之前缓冲文本：
+/// 一致则丢弃重复部分，一旦出现差异立即原样放行缓冲内容
+#[derive(Debug, Clone)]
+pub struct PrefillFilter {
+    prefix: String,
+    buffer: String,
+    done: bool,
+}
+
+impl PrefillFilter {
+    pub fn new(prefill: &str) -> Self {
+        let prefix = prefill.trim().to_string();
+        Self {
+            done: prefix.is_empty(),
+            prefix,
+            buffer: String::new(),
+        }
+    }
+
+    /// 输入一段响应文本，返回可以发送给客户端的部分
+    pub fn push(&mut self, text: &str) -> String {
+        if self.done {
+            return text.to_string();
+        }
+        self.buffer.push_str(text);
+        let candidate = self.buffer.trim_start();
+
+        if candidate.len() < self.prefix.len() {
+            if self.prefix.starts_with(candidate) {
+                // 仍可能是复述，继续缓冲
+                return String::new();
+            }
+        } else if let Some(rest) = candidate.strip_prefix(self.prefix.as_str()) {
+            let rest = rest.to_string();
+            self.done = true;
+            self.buffer.clear();
+            tracing::debug!("已去除响应开头复述的 prefill");
+            return rest;
+        }
+
+        self.done = true;
+        std::mem::take(&mut self.buffer)
+    }
+
+    /// 结束过滤，返回仍在缓冲中的文本
+    pub fn finish(&mut self) -> String {
+        self.done = true;
+        std::mem::take(&mut self.buffer)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_filter_strips_repeated_prefill_across_chunks() {
+        let mut filter = PrefillFilter::new("{\"name\":");
+        assert_eq!(filter.push("{\"na"), "");
+        assert_eq!(filter.push("me\": \"kiro\"}"), " \"kiro\"}");
+        assert_eq!(filter.push(" more"), " more");
+        assert_eq!(filter.finish(), "");
+    }
+
+    #[test]
+    fn test_filter_passes_through_on_mismatch() {
+        let mut filter = PrefillFilter::new("Hello");
+        assert_eq!(filter.push("He"), "");
+        assert_eq!(filter.push("y there"), "Hey there");
+    }
+
+    #[test]
+    fn test_filter_flushes_short_response() {
+        let mut filter = PrefillFilter::new("Once upon a time");
+        assert_eq!(filter.push("Once"), "");
+        assert_eq!(filter.finish(), "Once");
+    }
+
+    #[test]
+    fn test_strip_prefill() {
+        assert_eq!(strip_prefill("\nThe answer is 42", "The answer is"), " 42");
+        assert_eq!(strip_prefill("42", "The answer is"), "42");
+    }
+}
@@ -0,0 +1,251 @@
+//! 滚动窗口 token 配额
+//!
+//! 按 API Key 记录每次请求的输入/输出 tokens，请求进入时检查最近窗口内的累计用量，
+//! 超出任一配置上限时返回 `rate_limit_error`，并给出配额恢复时间。
+
+use std::collections::{HashMap, VecDeque};
+use std::fmt;
+
+use parking_lot::Mutex;
+
+use crate::model::config::TokenQuota;
+
+/// 配额计量的 token 类型
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum QuotaKind {
+    Input,
+    Output,
+}
+
+impl QuotaKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            QuotaKind::Input => "input",
+            QuotaKind::Output => "output",
+        }
+    }
+}
+
+/// 配额超出详情
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct QuotaExceeded {
+    pub kind: QuotaKind,
+    pub window_secs: u64,
+    pub limit: u64,
+    pub used: u64,
+    /// 用量回落到上限以下的时间（Unix 秒）
+    pub reset_at: i64,
+}
+
+impl fmt::Display for QuotaExceeded {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let reset_at = chrono::DateTime::from_timestamp(self.reset_at, 0)
+            .map(|t| t.to_rfc3339())
+            .unwrap_or_else(|| self.reset_at.to_string());
+        write!(
+            f,
+            "{} token quota exceeded: {}/{} tokens used in the last {} window; resets at {}",
+            match self.kind {
+                QuotaKind::Input => "Input",
+                QuotaKind::Output => "Output",
+            },
+            self.used,
+            self.limit,
+            format_window(self.window_secs),
+            reset_at
+        )
+    }
+}
+
+/// 窗口长度的可读形式（整小时/整分钟时简写）
+fn format_window(secs: u64) -> String {
+    if secs.is_multiple_of(3600) {
+        format!("{}h", secs / 3600)
+    } else if secs.is_multiple_of(60) {
+        format!("{}m", secs / 60)
+    } else {
+        format!("{}s", secs)
+    }
+}
+
+/// 单次请求的用量记录
+#[derive(Debug, Clone, Copy)]
+struct UsageEntry {
+    at: i64,
+    input: u64,
+    output: u64,
+}
+
+impl UsageEntry {
+    fn get(&self, kind: QuotaKind) -> u64 {
+        match kind {
+            QuotaKind::Input => self.input,
+            QuotaKind::Output => self.output,
+        }
+    }
+}
+
+/// 按 API Key 的滚动窗口配额跟踪器（进程内存储）
+pub struct QuotaTracker {
+    rules: Vec<TokenQuota>,
+    /// 最长窗口，超出该时长的记录会被清理
+    max_window_secs: i64,
+    usage: Mutex<HashMap<String, VecDeque<UsageEntry>>>,
+}
+
+impl QuotaTracker {
+    /// 创建跟踪器，无有效规则时返回 None
+    pub fn new(rules: Vec<TokenQuota>) -> Option<Self> {
+        let rules: Vec<TokenQuota> = rules
+            .into_iter()
+            .filter(|r| {
+                r.window_secs > 0 && (r.max_input_tokens.is_some() || r.max_output_tokens.is_some())
+            })
+            .collect();
+        let max_window_secs = rules.iter().map(|r| r.window_secs).max()? as i64;
+        Some(Self {
+            rules,
+            max_window_secs,
+            usage: Mutex::new(HashMap::new()),
+        })
+    }
+
+    /// 检查 API Key 当前是否仍有配额
+    pub fn check(&self, key: &str, now: i64) -> Result<(), QuotaExceeded> {
+        let mut usage = self.usage.lock();
+        let Some(entries) = usage.get_mut(key) else {
+            return Ok(());
+        };
+        Self::prune(entries, now - self.max_window_secs);
+
+        for rule in &self.rules {
+            let since = now - rule.window_secs as i64;
+            for (kind, limit) in [
+                (QuotaKind::Input, rule.max_input_tokens),
+                (QuotaKind::Output, rule.max_output_tokens),
+            ] {
+                let Some(limit) = limit else { continue };
+                let in_window = entries.iter().filter(|e| e.at > since);
+                let used: u64 = in_window.clone().map(|e| e.get(kind)).sum();
+                if used < limit {
+                    continue;
+                }
+
+                // 从最早的记录开始移出窗口，直到用量回落到上限以下
+                let mut remaining = used;
+                let mut reset_at = now;
+                for entry in in_window {
+                    remaining -= entry.get(kind);
+                    reset_at = entry.at + rule.window_secs as i64;
+                    if remaining < limit {
+                        break;
+                    }
+                }
+
+                return Err(QuotaExceeded {
+                    kind,
+                    window_secs: rule.window_secs,
+                    limit,
+                    used,
+                    reset_at,
+                });
+            }
+        }
+        Ok(())
+    }
+
+    /// 记录一次请求的用量
+    pub fn record(&self, key: &str, input_tokens: u64, output_tokens: u64, now: i64) {
+        let mut usage = self.usage.lock();
+        let entries = usage.entry(key.to_string()).or_default();
+        Self::prune(entries, now - self.max_window_secs);
+        entries.push_back(UsageEntry {
+            at: now,
+            input: input_tokens,
+            output: output_tokens,
+        });
+    }
+
+    fn prune(entries: &mut VecDeque<UsageEntry>, cutoff: i64) {
+        while entries.front().is_some_and(|e| e.at <= cutoff) {
+            entries.pop_front();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn output_rule(window_secs: u64, max: u64) -> TokenQuota {
+        TokenQuota {
+            window_secs,
+            max_input_tokens: None,
+            max_output_tokens: Some(max),
+        }
+    }
+
+    #[test]
+    fn test_new_ignores_empty_rules() {
+        assert!(QuotaTracker::new(vec![]).is_none());
+        assert!(
+            QuotaTracker::new(vec![TokenQuota {
+                window_secs: 60,
+                max_input_tokens: None,
+                max_output_tokens: None,
+            }])
+            .is_none()
+        );
+    }
+
+    #[test]
+    fn test_quota_exceeded_with_reset_time() {
+        let tracker = QuotaTracker::new(vec![output_rule(100, 1000)]).unwrap();
+        tracker.record("k", 10, 600, 1000);
+        tracker.record("k", 10, 500, 1050);
+
+        let err = tracker.check("k", 1060).unwrap_err();
+        assert_eq!(err.kind, QuotaKind::Output);
+        assert_eq!(err.used, 1100);
+        // 第一条记录在 1100 移出窗口后用量降为 500
+        assert_eq!(err.reset_at, 1100);
+
+        assert!(tracker.check("k", 1100).is_ok());
+        assert!(tracker.check("other", 1060).is_ok());
+    }
+
+    #[test]
+    fn test_multiple_windows() {
+        let tracker = QuotaTracker::new(vec![
+            output_rule(60, 1000),
+            TokenQuota {
+                window_secs: 3600,
+                max_input_tokens: Some(5000),
+                max_output_tokens: None,
+            },
+        ])
+        .unwrap();
+        tracker.record("k", 3000, 100, 0);
+        tracker.record("k", 3000, 100, 120);
+
+        let err = tracker.check("k", 130).unwrap_err();
+        assert_eq!(err.kind, QuotaKind::Input);
+        assert_eq!(err.window_secs, 3600);
+        assert_eq!(err.reset_at, 3600);
+    }
+
+    #[test]
+    fn test_display() {
+        let err = QuotaExceeded {
+            kind: QuotaKind::Output,
+            window_secs: 18000,
+            limit: 100_000,
+            used: 100_500,
+            reset_at: 0,
+        };
+        assert_eq!(
+            err.to_string(),
+            "Output token quota exceeded: 100500/100000 tokens used in the last 5h window; resets at 1970-01-01T00:00:00+00:00"
+        );
+    }
+}
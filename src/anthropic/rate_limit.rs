@@ -0,0 +1,242 @@
+//! 公共 API 限流
+//!
+//! 令牌桶算法：每个 API Key / 客户端 IP 各有一个桶，按每分钟请求数匀速补充，
+//! 桶容量即允许的突发请求数。桶空时拒绝请求并给出下一个令牌到达的等待时间。
+
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::time::{Duration, Instant};
+
+use parking_lot::Mutex;
+
+use crate::model::config::RateLimitConfig;
+
+/// 最多跟踪的桶数量，超出时先清理已回满的桶，仍然超出时淘汰最久未使用的桶
+const MAX_TRACKED_BUCKETS: usize = 10_000;
+
+#[derive(Debug, Clone, Copy)]
+struct Bucket {
+    tokens: f64,
+    updated_at: Instant,
+}
+
+/// 一组共享速率的令牌桶
+#[derive(Debug)]
+struct Buckets {
+    capacity: f64,
+    /// 每秒补充的令牌数
+    refill_per_sec: f64,
+    buckets: Mutex<HashMap<String, Bucket>>,
+}
+
+impl Buckets {
+    fn new(rpm: u32, burst: Option<u32>) -> Self {
+        Self {
+            capacity: f64::from(burst.unwrap_or(rpm).max(1)),
+            refill_per_sec: f64::from(rpm) / 60.0,
+            buckets: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// 取一个令牌，桶空时返回需要等待的时间
+    fn acquire(&self, key: &str, now: Instant) -> Result<(), Duration> {
+        let mut buckets = self.buckets.lock();
+        if !buckets.contains_key(key) && buckets.len() >= MAX_TRACKED_BUCKETS {
+            // 已回满的桶与新建的桶等价，可以安全丢弃
+            buckets.retain(|_, b| self.refilled(b, now) < self.capacity);
+            if buckets.len() >= MAX_TRACKED_BUCKETS {
+                // 一次淘汰 1/10 最久未使用的桶，摊薄排序开销
+                let mut by_age: Vec<_> = buckets
+                    .iter()
+                    .map(|(key, b)| (b.updated_at, key.clone()))
+                    .collect();
+                by_age.sort_unstable_by_key(|(updated_at, _)| *updated_at);
+                for (_, key) in by_age.into_iter().take(MAX_TRACKED_BUCKETS / 10) {
+                    buckets.remove(&key);
+                }
+            }
+        }
+        let bucket = buckets.entry(key.to_string()).or_insert(Bucket {
+            tokens: self.capacity,
+            updated_at: now,
+        });
+        bucket.tokens = self.refilled(bucket, now);
+        bucket.updated_at = now;
+
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            Ok(())
+        } else {
+            let wait = (1.0 - bucket.tokens) / self.refill_per_sec;
+            Err(Duration::from_secs_f64(wait))
+        }
+    }
+
+    fn refilled(&self, bucket: &Bucket, now: Instant) -> f64 {
+        let elapsed = now.duration_since(bucket.updated_at).as_secs_f64();
+        (bucket.tokens + elapsed * self.refill_per_sec).min(self.capacity)
+    }
+}
+
+/// 请求被限流的原因
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RateLimitScope {
+    ApiKey,
+    Ip,
+}
+
+/// 限流拒绝详情
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RateLimited {
+    pub scope: RateLimitScope,
+    /// 建议客户端等待的秒数（向上取整，至少 1）
+    pub retry_after: u64,
+}
+
+/// 按 API Key 与客户端 IP 的限流器
+#[derive(Debug)]
+pub struct RateLimiter {
+    per_key: Option<Buckets>,
+    per_ip: Option<Buckets>,
+    trust_forwarded_for: bool,
+}
+
+impl RateLimiter {
+    /// 创建限流器，两个维度都未开启时返回 None
+    pub fn new(config: RateLimitConfig) -> Option<Self> {
+        let per_key =
+            (config.per_key_rpm > 0).then(|| Buckets::new(config.per_key_rpm, config.burst));
+        let per_ip = (config.per_ip_rpm > 0).then(|| Buckets::new(config.per_ip_rpm, config.burst));
+        if per_key.is_none() && per_ip.is_none() {
+            return None;
+        }
+        Some(Self {
+            per_key,
+            per_ip,
+            trust_forwarded_for: config.trust_forwarded_for,
+        })
+    }
+
+    /// 是否从 `X-Forwarded-For` 读取客户端 IP
+    pub fn trust_forwarded_for(&self) -> bool {
+        self.trust_forwarded_for
+    }
+
+    /// 检查并消耗一次请求配额
+    ///
+    /// 先检查 IP 再检查 API Key；IP 维度被拒绝时不消耗 API Key 的令牌
+    pub fn check(
+        &self,
+        api_key: Option<&str>,
+        ip: Option<IpAddr>,
+        now: Instant,
+    ) -> Result<(), RateLimited> {
+        if let (Some(buckets), Some(ip)) = (&self.per_ip, ip) {
+            buckets
+                .acquire(&ip.to_string(), now)
+                .map_err(|wait| RateLimited::new(RateLimitScope::Ip, wait))?;
+        }
+        if let (Some(buckets), Some(key)) = (&self.per_key, api_key) {
+            buckets
+                .acquire(key, now)
+                .map_err(|wait| RateLimited::new(RateLimitScope::ApiKey, wait))?;
+        }
+        Ok(())
+    }
+}
+
+impl RateLimited {
+    fn new(scope: RateLimitScope, wait: Duration) -> Self {
+        Self {
+            scope,
+            retry_after: wait.as_secs_f64().ceil().max(1.0) as u64,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn limiter(per_key_rpm: u32, per_ip_rpm: u32, burst: Option<u32>) -> RateLimiter {
+        RateLimiter::new(RateLimitConfig {
+            per_key_rpm,
+            per_ip_rpm,
+            burst,
+            trust_forwarded_for: false,
+        })
+        .unwrap()
+    }
+
+    #[test]
+    fn test_disabled_when_no_limits() {
+        assert!(
+            RateLimiter::new(RateLimitConfig {
+                per_key_rpm: 0,
+                per_ip_rpm: 0,
+                burst: Some(5),
+                trust_forwarded_for: false,
+            })
+            .is_none()
+        );
+    }
+
+    #[test]
+    fn test_burst_then_refill() {
+        let limiter = limiter(60, 0, Some(2));
+        let t0 = Instant::now();
+        assert!(limiter.check(Some("k"), None, t0).is_ok());
+        assert!(limiter.check(Some("k"), None, t0).is_ok());
+        assert_eq!(
+            limiter.check(Some("k"), None, t0),
+            Err(RateLimited {
+                scope: RateLimitScope::ApiKey,
+                retry_after: 1
+            })
+        );
+        // 其他 key 不受影响
+        assert!(limiter.check(Some("other"), None, t0).is_ok());
+        // 60 rpm 每秒补充一个令牌
+        assert!(
+            limiter
+                .check(Some("k"), None, t0 + Duration::from_secs(1))
+                .is_ok()
+        );
+    }
+
+    #[test]
+    fn test_ip_limit_checked_first() {
+        let limiter = limiter(1, 1, None);
+        let ip: IpAddr = "10.0.0.1".parse().unwrap();
+        let t0 = Instant::now();
+        assert!(limiter.check(Some("k"), Some(ip), t0).is_ok());
+
+        let err = limiter.check(Some("k"), Some(ip), t0).unwrap_err();
+        assert_eq!(err.scope, RateLimitScope::Ip);
+        assert_eq!(err.retry_after, 60);
+
+        // 另一个 IP 使用同一 key 时受 key 维度限制
+        let other: IpAddr = "10.0.0.2".parse().unwrap();
+        let err = limiter.check(Some("k"), Some(other), t0).unwrap_err();
+        assert_eq!(err.scope, RateLimitScope::ApiKey);
+    }
+
+    #[test]
+    fn test_evicts_oldest_buckets_when_full() {
+        let limiter = limiter(60, 0, None);
+        let t0 = Instant::now();
+        // 所有桶都未回满，清理不掉任何桶
+        for i in 0..MAX_TRACKED_BUCKETS {
+            let now = t0 + Duration::from_millis(i as u64);
+            assert!(limiter.check(Some(&format!("k{}", i)), None, now).is_ok());
+        }
+        let now = t0 + Duration::from_millis(MAX_TRACKED_BUCKETS as u64);
+        assert!(limiter.check(Some("new"), None, now).is_ok());
+
+        let buckets = limiter.per_key.as_ref().unwrap().buckets.lock();
+        assert!(buckets.len() <= MAX_TRACKED_BUCKETS);
+        assert!(!buckets.contains_key("k0"));
+        assert!(buckets.contains_key(&format!("k{}", MAX_TRACKED_BUCKETS - 1)));
+        assert!(buckets.contains_key("new"));
+    }
+}
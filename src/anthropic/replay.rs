@@ -0,0 +1,356 @@
+//! Kiro 事件流录制与回放（调试用）
+//!
+//! 开启 `debugCaptureFrames` 后，每次 /v1/messages 请求收到的原始 Kiro event-stream
+//! 字节会连同转换所需的上下文（模型、thinking、工具名映射）保存在内存中，
+//! Admin API 可导出 dump，或把 dump 重新喂给流转换器，离线复现转换问题。
+
+use std::collections::{HashMap, VecDeque};
+use std::sync::LazyLock;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use bytes::Bytes;
+use futures::{Stream, StreamExt};
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+
+use crate::kiro::model::events::Event;
+use crate::kiro::parser::decoder::EventStreamDecoder;
+
+use super::stream::StreamContext;
+
+/// 保留的最近录制数量
+const MAX_DUMPS: usize = 10;
+
+/// 单次录制的最大字节数，超出部分丢弃并标记 truncated
+const MAX_DUMP_BYTES: usize = 4 * 1024 * 1024;
+
+/// 回放时每次喂给解码器的字节数（避免超过解码器缓冲区上限）
+const REPLAY_CHUNK_SIZE: usize = 64 * 1024;
+
+/// 回放结果中单帧 payload 的最大展示长度（字符）
+const MAX_PAYLOAD_PREVIEW_CHARS: usize = 2000;
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+
+static DUMPS: LazyLock<Mutex<VecDeque<FrameDump>>> =
+    LazyLock::new(|| Mutex::new(VecDeque::with_capacity(MAX_DUMPS)));
+
+/// 设置是否录制事件流
+pub fn init(enabled: bool) {
+    ENABLED.store(enabled, Ordering::Relaxed);
+}
+
+/// 一次请求的原始事件流录制
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FrameDump {
+    /// 录制 ID（与 request-id 响应头一致）
+    pub id: String,
+    /// 录制时间（RFC3339）
+    pub captured_at: String,
+    /// 实际服务的模型
+    pub model: String,
+    #[serde(default)]
+    pub thinking_enabled: bool,
+    /// 估算的输入 tokens
+    #[serde(default)]
+    pub input_tokens: i32,
+    /// 工具名称反向映射（短名称 → 原始名称）
+    #[serde(default)]
+    pub tool_name_map: HashMap<String, String>,
+    /// 是否因超出大小上限被截断
+    #[serde(default)]
+    pub truncated: bool,
+    /// 原始 event-stream 字节（十六进制）
+    pub frames_hex: String,
+}
+
+/// 录制列表项
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FrameDumpSummary {
+    pub id: String,
+    pub captured_at: String,
+    pub model: String,
+    pub bytes: usize,
+    pub truncated: bool,
+}
+
+/// 回放时解码出的单帧
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ReplayFrame {
+    pub message_type: Option<String>,
+    pub event_type: Option<String>,
+    pub payload: String,
+}
+
+/// 回放输出的 SSE 事件
+#[derive(Debug, Clone, Serialize)]
+pub struct ReplayEvent {
+    pub event: String,
+    pub data: serde_json::Value,
+}
+
+/// 回放结果
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ReplayResult {
+    pub frames: Vec<ReplayFrame>,
+    /// 转换器输出的 Anthropic SSE 事件
+    pub events: Vec<ReplayEvent>,
+    /// 解码/解析过程中的错误
+    pub errors: Vec<String>,
+}
+
+/// 单次请求的录制器，drop 时保存（流正常结束或客户端断开都会保留已收到的部分）
+pub struct FrameRecorder {
+    dump: FrameDump,
+    bytes: Vec<u8>,
+}
+
+impl FrameRecorder {
+    /// 开始录制（未开启录制时返回 None）
+    pub fn start(
+        id: String,
+        model: &str,
+        thinking_enabled: bool,
+        input_tokens: i32,
+        tool_name_map: &HashMap<String, String>,
+    ) -> Option<Self> {
+        if !ENABLED.load(Ordering::Relaxed) {
+            return None;
+        }
+        Some(Self {
+            dump: FrameDump {
+                id,
+                captured_at: chrono::Utc::now().to_rfc3339(),
+                model: model.to_string(),
+                thinking_enabled,
+                input_tokens,
+                tool_name_map: tool_name_map.clone(),
+                truncated: false,
+                frames_hex: String::new(),
+            },
+            bytes: Vec::new(),
+        })
+    }
+
+    /// 追加收到的原始字节
+    pub fn push(&mut self, chunk: &[u8]) {
+        let room = MAX_DUMP_BYTES.saturating_sub(self.bytes.len());
+        if chunk.len() > room {
+            self.dump.truncated = true;
+        }
+        self.bytes
+            .extend_from_slice(&chunk[..chunk.len().min(room)]);
+    }
+}
+
+impl Drop for FrameRecorder {
+    fn drop(&mut self) {
+        let mut dump = std::mem::take(&mut self.dump);
+        dump.frames_hex = hex::encode(&self.bytes);
+        let mut dumps = DUMPS.lock();
+        if dumps.len() >= MAX_DUMPS {
+            dumps.pop_front();
+        }
+        dumps.push_back(dump);
+    }
+}
+
+/// 包装上游字节流，边转发边录制
+pub fn recorded<S>(stream: S, recorder: Option<FrameRecorder>) -> impl Stream<Item = S::Item>
+where
+    S: Stream<Item = reqwest::Result<Bytes>>,
+{
+    let mut recorder = recorder;
+    stream.inspect(move |chunk| {
+        if let (Some(recorder), Ok(chunk)) = (recorder.as_mut(), chunk) {
+            recorder.push(chunk);
+        }
+    })
+}
+
+/// 最近的录制列表（新的在前）
+pub fn list_dumps() -> Vec<FrameDumpSummary> {
+    DUMPS
+        .lock()
+        .iter()
+        .rev()
+        .map(|d| FrameDumpSummary {
+            id: d.id.clone(),
+            captured_at: d.captured_at.clone(),
+            model: d.model.clone(),
+            bytes: d.frames_hex.len() / 2,
+            truncated: d.truncated,
+        })
+        .collect()
+}
+
+/// 按 ID 获取录制
+pub fn get_dump(id: &str) -> Option<FrameDump> {
+    DUMPS.lock().iter().find(|d| d.id == id).cloned()
+}
+
+/// 用流转换器重新处理录制的事件流
+pub fn replay(dump: &FrameDump) -> Result<ReplayResult, String> {
+    let bytes =
+        hex::decode(dump.frames_hex.trim()).map_err(|e| format!("framesHex 无效: {}", e))?;
+
+    let mut ctx = StreamContext::new_with_thinking(
+        &dump.model,
+        dump.input_tokens,
+        dump.thinking_enabled,
+        dump.tool_name_map.clone(),
+    );
+    let mut events = ctx.generate_initial_events();
+    let mut frames = Vec::new();
+    let mut errors = Vec::new();
+    let mut decoder = EventStreamDecoder::new();
+
+    for chunk in bytes.chunks(REPLAY_CHUNK_SIZE) {
+        if let Err(e) = decoder.feed(chunk) {
+            errors.push(format!("缓冲区溢出: {}", e));
+            break;
+        }
+        for result in decoder.decode_iter() {
+            let frame = match result {
+                Ok(frame) => frame,
+                Err(e) => {
+                    errors.push(format!("解码帧失败: {}", e));
+                    continue;
+                }
+            };
+            frames.push(ReplayFrame {
+                message_type: frame.message_type().map(str::to_string),
+                event_type: frame.event_type().map(str::to_string),
+                payload: frame
+                    .payload_as_str()
+                    .chars()
+                    .take(MAX_PAYLOAD_PREVIEW_CHARS)
+                    .collect(),
+            });
+            match Event::from_frame(frame) {
+                Ok(event) => events.extend(ctx.process_kiro_event(&event)),
+                Err(e) => errors.push(format!("解析事件失败: {}", e)),
+            }
+        }
+    }
+    events.extend(ctx.generate_final_events());
+
+    Ok(ReplayResult {
+        frames,
+        events: events
+            .into_iter()
+            .map(|e| ReplayEvent {
+                event: e.event,
+                data: e.data,
+            })
+            .collect(),
+        errors,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::kiro::parser::crc::crc32;
+
+    /// 构造一个 AWS event-stream 帧（仅字符串类型头部）
+    fn encode_frame(headers: &[(&str, &str)], payload: &[u8]) -> Vec<u8> {
+        let mut header_bytes = Vec::new();
+        for (name, value) in headers {
+            header_bytes.push(name.len() as u8);
+            header_bytes.extend_from_slice(name.as_bytes());
+            header_bytes.push(7);
+            header_bytes.extend_from_slice(&(value.len() as u16).to_be_bytes());
+            header_bytes.extend_from_slice(value.as_bytes());
+        }
+        let total = 12 + header_bytes.len() + payload.len() + 4;
+        let mut frame = Vec::with_capacity(total);
+        frame.extend_from_slice(&(total as u32).to_be_bytes());
+        frame.extend_from_slice(&(header_bytes.len() as u32).to_be_bytes());
+        let prelude_crc = crc32(&frame);
+        frame.extend_from_slice(&prelude_crc.to_be_bytes());
+        frame.extend_from_slice(&header_bytes);
+        frame.extend_from_slice(payload);
+        let message_crc = crc32(&frame);
+        frame.extend_from_slice(&message_crc.to_be_bytes());
+        frame
+    }
+
+    fn assistant_frame(content: &str) -> Vec<u8> {
+        encode_frame(
+            &[
+                (":message-type", "event"),
+                (":event-type", "assistantResponseEvent"),
+            ],
+            serde_json::json!({ "content": content })
+                .to_string()
+                .as_bytes(),
+        )
+    }
+
+    fn dump_of(bytes: &[u8]) -> FrameDump {
+        FrameDump {
+            id: "req_test".to_string(),
+            captured_at: String::new(),
+            model: "claude-sonnet-4".to_string(),
+            thinking_enabled: false,
+            input_tokens: 10,
+            tool_name_map: HashMap::new(),
+            truncated: false,
+            frames_hex: hex::encode(bytes),
+        }
+    }
+
+    #[test]
+    fn test_replay_assistant_text() {
+        let mut bytes = assistant_frame("Hello");
+        bytes.extend(assistant_frame(", world"));
+
+        let result = replay(&dump_of(&bytes)).unwrap();
+        assert!(result.errors.is_empty(), "{:?}", result.errors);
+        assert_eq!(result.frames.len(), 2);
+        assert_eq!(
+            result.frames[0].event_type.as_deref(),
+            Some("assistantResponseEvent")
+        );
+
+        let text: String = result
+            .events
+            .iter()
+            .filter(|e| e.event == "content_block_delta")
+            .filter_map(|e| e.data["delta"]["text"].as_str())
+            .collect();
+        assert_eq!(text, "Hello, world");
+        assert_eq!(result.events.last().unwrap().event, "message_stop");
+    }
+
+    #[test]
+    fn test_replay_rejects_invalid_hex() {
+        let mut dump = dump_of(&[]);
+        dump.frames_hex = "zz".to_string();
+        assert!(replay(&dump).is_err());
+    }
+
+    #[test]
+    fn test_recorder_truncates_and_saves_on_drop() {
+        let mut dump = dump_of(&[]);
+        dump.id = "req_truncate_test".to_string();
+        let mut recorder = FrameRecorder {
+            dump,
+            bytes: Vec::new(),
+        };
+        recorder.push(&vec![0u8; MAX_DUMP_BYTES - 1]);
+        recorder.push(&[1, 2, 3]);
+        assert!(recorder.dump.truncated);
+        assert_eq!(recorder.bytes.len(), MAX_DUMP_BYTES);
+
+        drop(recorder);
+        let saved = get_dump("req_truncate_test").unwrap();
+        assert!(saved.truncated);
+        assert_eq!(saved.frames_hex.len(), MAX_DUMP_BYTES * 2);
+    }
+}
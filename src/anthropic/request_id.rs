@@ -0,0 +1,71 @@
+//! Anthropic 风格的请求 ID
+//!
+//! 每个 /v1/messages 请求生成一个随机后缀，
+//! 同时用于 `request-id` 响应头（`req_...`）和消息 ID（`msg_...`），
+//! 客户端上报任意一个 ID 都能在日志中定位到同一次请求。
+
+/// 请求 ID 响应头（与 Anthropic API 一致）
+pub const REQUEST_ID_HEADER: &str = "request-id";
+
+/// 后缀长度（不含固定前缀 "01"）
+const SUFFIX_RANDOM_LEN: usize = 22;
+
+/// 单次请求的关联 ID
+#[derive(Debug, Clone)]
+pub struct RequestId {
+    suffix: String,
+}
+
+impl RequestId {
+    /// 生成新的请求 ID
+    pub fn generate() -> Self {
+        const CHARSET: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789";
+        let random: String = (0..SUFFIX_RANDOM_LEN)
+            .map(|_| CHARSET[fastrand::usize(..CHARSET.len())] as char)
+            .collect();
+        Self {
+            suffix: format!("01{}", random),
+        }
+    }
+
+    /// 请求 ID，格式: req_01xxxxxxxxxxxxxxxxxxxxxx
+    pub fn request_id(&self) -> String {
+        format!("req_{}", self.suffix)
+    }
+
+    /// 消息 ID，格式: msg_01xxxxxxxxxxxxxxxxxxxxxx
+    pub fn message_id(&self) -> String {
+        format!("msg_{}", self.suffix)
+    }
+
+    /// 消息批次 ID，格式: msgbatch_01xxxxxxxxxxxxxxxxxxxxxx
+    pub fn batch_id(&self) -> String {
+        format!("msgbatch_{}", self.suffix)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_request_id_format() {
+        let id = RequestId::generate();
+        let req = id.request_id();
+        let msg = id.message_id();
+
+        assert!(req.starts_with("req_01"));
+        assert!(msg.starts_with("msg_01"));
+        assert_eq!(req.len(), 4 + 2 + SUFFIX_RANDOM_LEN);
+        assert_eq!(req[4..], msg[4..]);
+        assert!(req[4..].chars().all(|c| c.is_ascii_alphanumeric()));
+    }
+
+    #[test]
+    fn test_request_id_unique() {
+        assert_ne!(
+            RequestId::generate().request_id(),
+            RequestId::generate().request_id()
+        );
+    }
+}
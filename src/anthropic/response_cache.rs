@@ -0,0 +1,209 @@
+//! 非流式请求的响应缓存
+//!
+//! 以请求内容（模型、system、messages、工具、thinking 等，不含 metadata）的哈希为键，
+//! 在 TTL 内对完全相同的非流式请求直接返回缓存的响应，不调用上游。
+//! 命中的响应把输入 tokens 计为 `cache_read_input_tokens`，并通过响应头标记。
+
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+use parking_lot::Mutex;
+use serde_json::{Value, json};
+use sha2::{Digest, Sha256};
+
+use crate::model::config::ResponseCacheConfig;
+
+use super::types::MessagesRequest;
+
+/// 标记响应来自缓存的响应头
+pub const RESPONSE_CACHE_HEADER: &str = "x-kiro-response-cache";
+
+#[derive(Debug)]
+struct CachedResponse {
+    body: Value,
+    stored_at: Instant,
+}
+
+/// 精确匹配的响应缓存
+#[derive(Debug)]
+pub struct ResponseCache {
+    ttl: Duration,
+    max_entries: usize,
+    entries: Mutex<HashMap<String, CachedResponse>>,
+}
+
+impl ResponseCache {
+    /// 创建响应缓存，TTL 或容量为 0 时返回 None
+    pub fn new(config: ResponseCacheConfig) -> Option<Self> {
+        if config.ttl_secs == 0 || config.max_entries == 0 {
+            return None;
+        }
+        Some(Self {
+            ttl: Duration::from_secs(config.ttl_secs),
+            max_entries: config.max_entries,
+            entries: Mutex::new(HashMap::new()),
+        })
+    }
+
+    /// 计算请求的缓存键
+    ///
+    /// metadata 与 stream 不参与计算：同一提示在不同会话中重复发送时同样命中
+    pub fn key(payload: &MessagesRequest) -> String {
+        let material = json!([
+            payload.model,
+            payload.max_tokens,
+            payload.system,
+            payload.messages,
+            payload.tools,
+            payload.tool_choice,
+            payload
+                .thinking
+                .as_ref()
+                .map(|t| (t.thinking_type.as_str(), t.budget_tokens)),
+            payload.output_config.as_ref().map(|c| c.effort.as_str()),
+            payload.stop_sequences,
+        ]);
+        let digest = Sha256::digest(material.to_string().as_bytes());
+        hex::encode(digest)
+    }
+
+    /// 查找未过期的缓存响应
+    pub fn get(&self, key: &str, now: Instant) -> Option<Value> {
+        let mut entries = self.entries.lock();
+        match entries.get(key) {
+            Some(entry) if now.duration_since(entry.stored_at) < self.ttl => {
+                Some(entry.body.clone())
+            }
+            Some(_) => {
+                entries.remove(key);
+                None
+            }
+            None => None,
+        }
+    }
+
+    /// 写入缓存，容量已满时先清理过期条目，仍满则淘汰最早写入的条目
+    pub fn insert(&self, key: String, body: Value, now: Instant) {
+        let mut entries = self.entries.lock();
+        if !entries.contains_key(&key) && entries.len() >= self.max_entries {
+            entries.retain(|_, e| now.duration_since(e.stored_at) < self.ttl);
+            if entries.len() >= self.max_entries
+                && let Some(oldest) = entries
+                    .iter()
+                    .min_by_key(|(_, e)| e.stored_at)
+                    .map(|(k, _)| k.clone())
+            {
+                entries.remove(&oldest);
+            }
+        }
+        entries.insert(
+            key,
+            CachedResponse {
+                body,
+                stored_at: now,
+            },
+        );
+    }
+}
+
+/// 把缓存的响应改写为本次请求的命中响应
+///
+/// 使用本次请求的消息 ID；输入 tokens 全部计为 `cache_read_input_tokens`
+pub fn as_cache_hit(mut body: Value, message_id: &str) -> Value {
+    body["id"] = json!(message_id);
+    if let Some(usage) = body.get_mut("usage").and_then(Value::as_object_mut) {
+        let input_tokens = usage
+            .get("input_tokens")
+            .and_then(Value::as_i64)
+            .unwrap_or(0);
+        let cache_read = usage
+            .get("cache_read_input_tokens")
+            .and_then(Value::as_i64)
+            .unwrap_or(0);
+        usage.insert("input_tokens".to_string(), json!(0));
+        usage.insert(
+            "cache_read_input_tokens".to_string(),
+            json!(input_tokens + cache_read),
+        );
+    }
+    body
+}
+
+/// 测试用的单条用户消息请求（响应缓存与请求合并的测试共用）
+#[cfg(test)]
+pub(crate) fn test_request(content: &str, user_id: &str) -> MessagesRequest {
+    serde_json::from_value(json!({
+        "model": "claude-sonnet-4-5",
+        "max_tokens": 1024,
+        "messages": [{"role": "user", "content": content}],
+        "metadata": {"user_id": user_id}
+    }))
+    .unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cache(max_entries: usize) -> ResponseCache {
+        ResponseCache::new(ResponseCacheConfig {
+            ttl_secs: 60,
+            max_entries,
+        })
+        .unwrap()
+    }
+
+    #[test]
+    fn test_key_ignores_metadata() {
+        assert_eq!(
+            ResponseCache::key(&test_request("hi", "a")),
+            ResponseCache::key(&test_request("hi", "b"))
+        );
+        assert_ne!(
+            ResponseCache::key(&test_request("hi", "a")),
+            ResponseCache::key(&test_request("hello", "a"))
+        );
+    }
+
+    #[test]
+    fn test_ttl_and_eviction() {
+        let cache = cache(2);
+        let t0 = Instant::now();
+        cache.insert("a".to_string(), json!(1), t0);
+        cache.insert("b".to_string(), json!(2), t0 + Duration::from_secs(1));
+        assert_eq!(cache.get("a", t0 + Duration::from_secs(59)), Some(json!(1)));
+        assert_eq!(cache.get("a", t0 + Duration::from_secs(60)), None);
+
+        // 容量已满时淘汰最早写入的条目
+        cache.insert("a".to_string(), json!(1), t0 + Duration::from_secs(2));
+        cache.insert("c".to_string(), json!(3), t0 + Duration::from_secs(3));
+        let now = t0 + Duration::from_secs(4);
+        assert_eq!(cache.get("b", now), None);
+        assert_eq!(cache.get("a", now), Some(json!(1)));
+        assert_eq!(cache.get("c", now), Some(json!(3)));
+    }
+
+    #[test]
+    fn test_as_cache_hit() {
+        let body = json!({
+            "id": "msg_old",
+            "usage": {"input_tokens": 100, "output_tokens": 20, "cache_read_input_tokens": 0}
+        });
+        let hit = as_cache_hit(body, "msg_new");
+        assert_eq!(hit["id"], "msg_new");
+        assert_eq!(hit["usage"]["input_tokens"], 0);
+        assert_eq!(hit["usage"]["cache_read_input_tokens"], 100);
+        assert_eq!(hit["usage"]["output_tokens"], 20);
+    }
+
+    #[test]
+    fn test_disabled_config() {
+        assert!(
+            ResponseCache::new(ResponseCacheConfig {
+                ttl_secs: 0,
+                max_entries: 10,
+            })
+            .is_none()
+        );
+    }
+}
@@ -4,18 +4,27 @@ use axum::{
     Router,
     extract::DefaultBodyLimit,
     middleware,
-    routing::{get, post},
+    routing::{get, post, put},
 };
 
 use crate::kiro::provider::KiroProvider;
+use crate::model::config::Config;
 
 use super::{
-    handlers::{count_tokens, get_models, post_messages, post_messages_cc},
-    middleware::{AppState, auth_middleware, cors_layer},
+    handlers::{
+        cancel_message_batch, count_tokens, create_message_batch, delete_file,
+        delete_session_memory_note, get_file, get_message_batch, get_message_batch_results,
+        get_models, get_session_cost, get_session_memory, get_session_usage, list_files,
+        list_message_batches, post_embeddings, post_messages, post_messages_cc,
+        put_session_memory_note, upload_file,
+    },
+    middleware::{
+        AppState, auth_middleware, cors_layer, fault_injection_middleware, rate_limit_middleware,
+    },
 };
 
-/// 请求体最大大小限制 (50MB)
-const MAX_BODY_SIZE: usize = 50 * 1024 * 1024;
+/// multipart 上传中文件内容以外的部分（边界、字段头）预留的请求体大小
+const MULTIPART_OVERHEAD_BYTES: usize = 64 * 1024;
 
 /// 创建 Anthropic API 路由
 ///
@@ -23,6 +32,21 @@ const MAX_BODY_SIZE: usize = 50 * 1024 * 1024;
 /// - `GET /v1/models` - 获取可用模型列表
 /// - `POST /v1/messages` - 创建消息（对话）
 /// - `POST /v1/messages/count_tokens` - 计算 token 数量
+/// - `POST /v1/messages/batches` - 创建消息批次
+/// - `GET /v1/messages/batches` - 列出消息批次
+/// - `GET /v1/messages/batches/{id}` - 获取消息批次
+/// - `POST /v1/messages/batches/{id}/cancel` - 取消消息批次
+/// - `GET /v1/messages/batches/{id}/results` - 获取批次结果（JSONL）
+/// - `GET /v1/sessions/{session_id}/cost` - 查询会话累计估算费用
+/// - `GET /v1/sessions/{session_id}/usage` - 查询会话用量与上下文占用
+/// - `GET /v1/sessions/{session_id}/memory` - 查询会话记忆笔记
+/// - `PUT /v1/sessions/{session_id}/memory/{key}` - 写入会话记忆笔记
+/// - `DELETE /v1/sessions/{session_id}/memory/{key}` - 删除会话记忆笔记
+/// - `POST /v1/files` - 上传文件
+/// - `GET /v1/files` - 列出已上传的文件
+/// - `GET /v1/files/{file_id}` - 获取文件元数据
+/// - `DELETE /v1/files/{file_id}` - 删除文件
+/// - `POST /v1/embeddings` - OpenAI 兼容 embeddings（转发到外部上游）
 ///
 /// # 认证
 /// 所有 `/v1` 路径需要 API Key 认证，支持：
@@ -32,23 +56,76 @@ const MAX_BODY_SIZE: usize = 50 * 1024 * 1024;
 /// # 参数
 /// - `api_key`: API 密钥，用于验证客户端请求
 /// - `kiro_provider`: 可选的 KiroProvider，用于调用上游 API
+/// - `config`: 应用配置，读取 thinking 提取、模型 fallback、请求改写、token 配额、
+///   Message Batches 并发数、限流与请求体大小上限等选项
 
 /// 创建带有 KiroProvider 的 Anthropic API 路由
 pub fn create_router_with_provider(
     api_key: impl Into<String>,
     kiro_provider: Option<KiroProvider>,
-    extract_thinking: bool,
+    config: &Config,
 ) -> Router {
-    let mut state = AppState::new(api_key, extract_thinking);
+    let mut state = AppState::new(api_key, config.extract_thinking)
+        .with_model_fallbacks(config.model_fallbacks.clone())
+        .with_request_transforms(config.request_transforms.clone())
+        .with_token_quotas(config.token_quotas.clone())
+        .with_batch_concurrency(config.batch_concurrency)
+        .with_rate_limit(config.rate_limit)
+        .with_sse_ping(config.sse_ping_interval_secs, config.sse_ping_style)
+        .with_request_timeout(config.request_timeout_secs)
+        .with_response_cache(config.response_cache)
+        .with_request_coalescing(config.coalesce_requests)
+        .with_max_body_size(config.max_request_body_mb)
+        .with_fault_injection(config.fault_injection.clone())
+        .with_allowed_api_regions(config.allowed_api_regions.clone());
     if let Some(provider) = kiro_provider {
         state = state.with_kiro_provider(provider);
     }
+    // 上传文件的大小由 files.maxFileMb 限制，请求体上限额外预留 multipart 头部的空间
+    let upload_body_limit = config.files.as_ref().map_or(state.max_body_bytes, |files| {
+        (files.max_file_mb as usize)
+            .saturating_mul(1024 * 1024)
+            .saturating_add(MULTIPART_OVERHEAD_BYTES)
+    });
 
     // 需要认证的 /v1 路由
     let v1_routes = Router::new()
         .route("/models", get(get_models))
         .route("/messages", post(post_messages))
         .route("/messages/count_tokens", post(count_tokens))
+        .route(
+            "/messages/batches",
+            get(list_message_batches).post(create_message_batch),
+        )
+        .route("/messages/batches/{id}", get(get_message_batch))
+        .route("/messages/batches/{id}/cancel", post(cancel_message_batch))
+        .route(
+            "/messages/batches/{id}/results",
+            get(get_message_batch_results),
+        )
+        .route("/sessions/{session_id}/cost", get(get_session_cost))
+        .route("/sessions/{session_id}/usage", get(get_session_usage))
+        .route("/sessions/{session_id}/memory", get(get_session_memory))
+        .route(
+            "/sessions/{session_id}/memory/{key}",
+            put(put_session_memory_note).delete(delete_session_memory_note),
+        )
+        .route(
+            "/files",
+            get(list_files)
+                .post(upload_file)
+                .layer(DefaultBodyLimit::max(upload_body_limit)),
+        )
+        .route("/files/{file_id}", get(get_file).delete(delete_file))
+        .route("/embeddings", post(post_embeddings))
+        .layer(middleware::from_fn_with_state(
+            state.clone(),
+            fault_injection_middleware,
+        ))
+        .layer(middleware::from_fn_with_state(
+            state.clone(),
+            rate_limit_middleware,
+        ))
         .layer(middleware::from_fn_with_state(
             state.clone(),
             auth_middleware,
@@ -59,6 +136,14 @@ pub fn create_router_with_provider(
     let cc_v1_routes = Router::new()
         .route("/messages", post(post_messages_cc))
         .route("/messages/count_tokens", post(count_tokens))
+        .layer(middleware::from_fn_with_state(
+            state.clone(),
+            fault_injection_middleware,
+        ))
+        .layer(middleware::from_fn_with_state(
+            state.clone(),
+            rate_limit_middleware,
+        ))
         .layer(middleware::from_fn_with_state(
             state.clone(),
             auth_middleware,
@@ -68,6 +153,6 @@ pub fn create_router_with_provider(
         .nest("/v1", v1_routes)
         .nest("/cc/v1", cc_v1_routes)
         .layer(cors_layer())
-        .layer(DefaultBodyLimit::max(MAX_BODY_SIZE))
+        .layer(DefaultBodyLimit::max(state.max_body_bytes))
         .with_state(state)
 }
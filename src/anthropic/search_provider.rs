@@ -0,0 +1,248 @@
+//! 本地 WebSearch 后端
+//!
+//! 配置 `webSearch` 后，web_search 工具请求由代理直接调用 SearXNG / Brave / Bing，
+//! 结果转换为与 Kiro MCP 相同的 [`WebSearchResults`]，复用同一套 SSE 事件生成逻辑。
+
+use std::sync::OnceLock;
+
+use anyhow::Context;
+use serde_json::Value;
+
+use crate::http_client::{ProxyConfig, build_client};
+use crate::model::config::{TlsBackend, WebSearchConfig, WebSearchProvider};
+
+use super::websearch::{WebSearchResult, WebSearchResults};
+
+const BRAVE_DEFAULT_URL: &str = "https://api.search.brave.com/res/v1/web/search";
+const BING_DEFAULT_URL: &str = "https://api.bing.microsoft.com/v7.0/search";
+
+/// 搜索请求超时（秒）
+const SEARCH_TIMEOUT_SECS: u64 = 30;
+
+static BACKEND: OnceLock<SearchBackend> = OnceLock::new();
+
+/// 已配置的搜索后端
+pub struct SearchBackend {
+    config: WebSearchConfig,
+    client: reqwest::Client,
+}
+
+/// 初始化本地搜索后端（未配置时不做任何事）
+///
+/// 应在应用启动时调用一次，配置不完整时返回错误
+pub fn init(
+    config: Option<&WebSearchConfig>,
+    proxy: Option<&ProxyConfig>,
+    tls_backend: TlsBackend,
+) -> anyhow::Result<()> {
+    let Some(config) = config else {
+        return Ok(());
+    };
+    match config.provider {
+        WebSearchProvider::Searxng if config.url.is_none() => {
+            anyhow::bail!("webSearch.provider 为 searxng 时必须配置 webSearch.url")
+        }
+        WebSearchProvider::Brave | WebSearchProvider::Bing if config.api_key.is_none() => {
+            anyhow::bail!("webSearch.provider 为 brave / bing 时必须配置 webSearch.apiKey")
+        }
+        _ => {}
+    }
+    let client = build_client(proxy, SEARCH_TIMEOUT_SECS, tls_backend)?;
+    let _ = BACKEND.set(SearchBackend {
+        config: config.clone(),
+        client,
+    });
+    Ok(())
+}
+
+/// 获取已配置的搜索后端
+pub fn get() -> Option<&'static SearchBackend> {
+    BACKEND.get()
+}
+
+impl SearchBackend {
+    /// 执行搜索
+    pub async fn search(&self, query: &str) -> anyhow::Result<WebSearchResults> {
+        let max = self.config.max_results.max(1);
+        let count = max.to_string();
+        let request = match self.config.provider {
+            WebSearchProvider::Searxng => {
+                let base = self.config.url.as_deref().unwrap_or_default();
+                self.client
+                    .get(format!("{}/search", base.trim_end_matches('/')))
+                    .query(&[("q", query), ("format", "json")])
+            }
+            WebSearchProvider::Brave => self
+                .client
+                .get(self.config.url.as_deref().unwrap_or(BRAVE_DEFAULT_URL))
+                .query(&[("q", query), ("count", count.as_str())])
+                .header("X-Subscription-Token", self.api_key()),
+            WebSearchProvider::Bing => self
+                .client
+                .get(self.config.url.as_deref().unwrap_or(BING_DEFAULT_URL))
+                .query(&[("q", query), ("count", count.as_str())])
+                .header("Ocp-Apim-Subscription-Key", self.api_key()),
+        };
+
+        let response = request.send().await.context("搜索请求失败")?;
+        let status = response.status();
+        if !status.is_success() {
+            let body = response.text().await.unwrap_or_default();
+            anyhow::bail!("搜索后端返回 {}: {}", status, body);
+        }
+        let body: Value = response.json().await.context("解析搜索结果失败")?;
+
+        let mut results = match self.config.provider {
+            WebSearchProvider::Searxng => parse_searxng(&body),
+            WebSearchProvider::Brave => parse_brave(&body),
+            WebSearchProvider::Bing => parse_bing(&body),
+        };
+        results.truncate(max);
+        tracing::debug!(count = results.len(), "本地 WebSearch 完成");
+
+        Ok(WebSearchResults {
+            total_results: Some(results.len() as i32),
+            results,
+            query: Some(query.to_string()),
+            error: None,
+        })
+    }
+
+    fn api_key(&self) -> &str {
+        self.config.api_key.as_deref().unwrap_or_default()
+    }
+}
+
+/// 解析发布时间（RFC3339 或不带时区的 ISO 8601，视为 UTC），返回毫秒时间戳
+fn parse_date(value: Option<&Value>) -> Option<i64> {
+    let s = value?.as_str()?;
+    if let Ok(dt) = chrono::DateTime::parse_from_rfc3339(s) {
+        return Some(dt.timestamp_millis());
+    }
+    chrono::NaiveDateTime::parse_from_str(s, "%Y-%m-%dT%H:%M:%S%.f")
+        .ok()
+        .map(|dt| dt.and_utc().timestamp_millis())
+}
+
+fn result(item: &Value, title: &str, snippet: &str, date: &str) -> Option<WebSearchResult> {
+    let url = item.get("url")?.as_str()?.to_string();
+    let domain = reqwest::Url::parse(&url)
+        .ok()
+        .and_then(|u| u.host_str().map(str::to_string));
+    Some(WebSearchResult {
+        title: item.get(title)?.as_str()?.to_string(),
+        snippet: item
+            .get(snippet)
+            .and_then(Value::as_str)
+            .map(str::to_string),
+        published_date: parse_date(item.get(date)),
+        id: None,
+        domain,
+        max_verbatim_word_limit: None,
+        public_domain: None,
+        url,
+    })
+}
+
+fn collect(items: Option<&Value>, title: &str, snippet: &str, date: &str) -> Vec<WebSearchResult> {
+    items
+        .and_then(Value::as_array)
+        .map(|items| {
+            items
+                .iter()
+                .filter_map(|item| result(item, title, snippet, date))
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// SearXNG: `{"results": [{"title", "url", "content", "publishedDate"}]}`
+fn parse_searxng(body: &Value) -> Vec<WebSearchResult> {
+    collect(body.get("results"), "title", "content", "publishedDate")
+}
+
+/// Brave: `{"web": {"results": [{"title", "url", "description", "page_age"}]}}`
+fn parse_brave(body: &Value) -> Vec<WebSearchResult> {
+    collect(
+        body.pointer("/web/results"),
+        "title",
+        "description",
+        "page_age",
+    )
+}
+
+/// Bing: `{"webPages": {"value": [{"name", "url", "snippet", "datePublished"}]}}`
+fn parse_bing(body: &Value) -> Vec<WebSearchResult> {
+    collect(
+        body.pointer("/webPages/value"),
+        "name",
+        "snippet",
+        "datePublished",
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_parse_searxng() {
+        let body = json!({
+            "results": [
+                {
+                    "title": "Rust",
+                    "url": "https://www.rust-lang.org/",
+                    "content": "A language empowering everyone",
+                    "publishedDate": "2025-01-02T03:04:05"
+                },
+                { "title": "no url" }
+            ]
+        });
+        let results = parse_searxng(&body);
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].domain.as_deref(), Some("www.rust-lang.org"));
+        assert_eq!(
+            results[0].snippet.as_deref(),
+            Some("A language empowering everyone")
+        );
+        assert_eq!(results[0].published_date, Some(1_735_787_045_000));
+    }
+
+    #[test]
+    fn test_parse_brave_and_bing() {
+        let brave = json!({
+            "web": { "results": [
+                { "title": "A", "url": "https://a.example/", "description": "a" }
+            ]}
+        });
+        assert_eq!(parse_brave(&brave)[0].title, "A");
+
+        let bing = json!({
+            "webPages": { "value": [
+                {
+                    "name": "B",
+                    "url": "https://b.example/x",
+                    "snippet": "b",
+                    "datePublished": "2025-01-02T03:04:05Z"
+                }
+            ]}
+        });
+        let results = parse_bing(&bing);
+        assert_eq!(results[0].title, "B");
+        assert_eq!(results[0].published_date, Some(1_735_787_045_000));
+        assert!(parse_bing(&json!({})).is_empty());
+    }
+
+    #[test]
+    fn test_init_requires_provider_settings() {
+        let config = WebSearchConfig {
+            provider: WebSearchProvider::Brave,
+            url: None,
+            api_key: None,
+            max_results: 10,
+        };
+        assert!(init(Some(&config), None, TlsBackend::Rustls).is_err());
+        assert!(init(None, None, TlsBackend::Rustls).is_ok());
+    }
+}
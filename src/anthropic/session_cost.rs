@@ -0,0 +1,387 @@
+//! 会话级费用估算
+//!
+//! 以 metadata.user_id 中的 session UUID 为键，按模型公开标价累计每个会话的
+//! 输入/输出 tokens 与估算费用（美元），通过响应头和 `GET /v1/sessions/{id}/cost`
+//! 提供给客户端展示。费用仅为按 Anthropic 标价换算的参考值，与 Kiro 实际计费无关。
+//!
+//! `GET /v1/sessions/{id}/usage` 另外返回当前上下文占模型上下文窗口的比例，以及按
+//! 输入 tokens 骤降推断的 `/compact` 次数，便于用户判断距离上下文上限还有多远。
+//!
+//! 价格优先取 `modelPrices` 中第一条匹配的规则，未匹配时使用内置标价；用量账本共用同一价格表。
+
+use std::collections::HashMap;
+use std::sync::{LazyLock, OnceLock};
+
+use parking_lot::Mutex;
+use serde::Serialize;
+
+use crate::model::config::ModelPriceRule;
+
+use super::converter::get_context_window_size;
+
+/// 携带会话累计估算费用的响应头
+pub const SESSION_COST_HEADER: &str = "x-kiro-session-cost-usd";
+
+/// 最多保留的会话数，超出时淘汰最久未更新的会话
+const MAX_SESSIONS: usize = 10_000;
+
+/// Admin API 最多列出的会话数
+const MAX_LISTED_SESSIONS: usize = 200;
+
+/// 判定 `/compact` 的最小上下文：同一模型的输入 tokens 从不低于该值骤降到一半以下
+const COMPACT_MIN_CONTEXT: u64 = 20_000;
+
+/// 缓存读取相对输入价格的默认比例
+const CACHE_READ_RATIO: f64 = 0.1;
+
+static PRICE_RULES: OnceLock<Vec<ModelPriceRule>> = OnceLock::new();
+
+/// 每百万 tokens 的价格（美元）
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ModelPrice {
+    pub input: f64,
+    pub output: f64,
+    pub cache_read: f64,
+}
+
+/// 价格来源
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum PriceSource {
+    /// `modelPrices` 配置
+    Config,
+    /// 内置的 Anthropic 标价
+    Builtin,
+}
+
+/// 模型的生效价格（用于 Admin API）
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ModelPriceEntry {
+    pub model: String,
+    /// 未知模型为 None（不计费用）
+    pub price: Option<ModelPrice>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub source: Option<PriceSource>,
+}
+
+/// 设置配置的价格规则
+///
+/// 应在应用启动时调用一次
+pub fn init_prices(rules: Vec<ModelPriceRule>) {
+    let _ = PRICE_RULES.set(rules);
+}
+
+/// 按模型名查找价格（未知模型返回 None，不计费用）
+pub fn price_for(model: &str) -> Option<ModelPrice> {
+    lookup(PRICE_RULES.get().map_or(&[][..], Vec::as_slice), model).map(|(price, _)| price)
+}
+
+/// 列出模型的生效价格及来源
+pub fn price_table(models: impl IntoIterator<Item = String>) -> Vec<ModelPriceEntry> {
+    let rules = PRICE_RULES.get().map_or(&[][..], Vec::as_slice);
+    models
+        .into_iter()
+        .map(|model| {
+            let found = lookup(rules, &model);
+            ModelPriceEntry {
+                price: found.map(|(price, _)| price),
+                source: found.map(|(_, source)| source),
+                model,
+            }
+        })
+        .collect()
+}
+
+fn lookup(rules: &[ModelPriceRule], model: &str) -> Option<(ModelPrice, PriceSource)> {
+    let lowered = model.to_lowercase();
+    let configured = rules.iter().find(|rule| {
+        rule.models.iter().any(|pattern| {
+            let pattern = pattern.to_lowercase();
+            match pattern.strip_suffix('*') {
+                Some(prefix) => lowered.starts_with(prefix),
+                None => lowered == pattern,
+            }
+        })
+    });
+    match configured {
+        Some(rule) => Some((
+            ModelPrice {
+                input: rule.input_per_mtok,
+                output: rule.output_per_mtok,
+                cache_read: rule
+                    .cache_read_per_mtok
+                    .unwrap_or(rule.input_per_mtok * CACHE_READ_RATIO),
+            },
+            PriceSource::Config,
+        )),
+        None => builtin_price(model).map(|price| (price, PriceSource::Builtin)),
+    }
+}
+
+/// 内置的 Anthropic 公开标价
+fn builtin_price(model: &str) -> Option<ModelPrice> {
+    let model = model.to_lowercase();
+    let is_45_or_later = ["4-5", "4.5", "4-6", "4.6"]
+        .iter()
+        .any(|v| model.contains(v));
+    let (input, output) = if model.contains("opus") {
+        if is_45_or_later {
+            (5.0, 25.0)
+        } else {
+            (15.0, 75.0)
+        }
+    } else if model.contains("sonnet") {
+        (3.0, 15.0)
+    } else if model.contains("haiku") {
+        if is_45_or_later {
+            (1.0, 5.0)
+        } else {
+            (0.8, 4.0)
+        }
+    } else {
+        return None;
+    };
+    Some(ModelPrice {
+        input,
+        output,
+        cache_read: input * CACHE_READ_RATIO,
+    })
+}
+
+/// 按标价估算一次请求的费用（美元）
+pub fn estimate_cost(model: &str, input_tokens: u64, output_tokens: u64) -> f64 {
+    price_for(model)
+        .map(|p| (input_tokens as f64 * p.input + output_tokens as f64 * p.output) / 1_000_000.0)
+        .unwrap_or(0.0)
+}
+
+/// 单个会话的累计用量
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SessionCost {
+    pub session_id: String,
+    pub requests: u64,
+    pub input_tokens: u64,
+    pub output_tokens: u64,
+    pub cost_usd: f64,
+    pub currency: &'static str,
+    /// 当前上下文所属的模型（各模型最近一次输入 tokens 最大者）
+    pub model: String,
+    /// 当前上下文的输入 tokens（该模型最近一次请求的输入）
+    pub context_tokens: u64,
+    /// 推断的 `/compact` 次数
+    pub compactions: u32,
+    /// 最近一次推断为 `/compact` 的时间（RFC3339）
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub last_compacted_at: Option<String>,
+    /// 首次记录时间（RFC3339）
+    pub created_at: String,
+    /// 最近一次记录时间（RFC3339）
+    pub updated_at: String,
+    #[serde(skip)]
+    updated_ts: i64,
+    /// 各模型最近一次请求的输入 tokens（Claude Code 会用小模型生成标题等，需分开统计）
+    #[serde(skip)]
+    contexts: HashMap<String, u64>,
+}
+
+/// 会话的上下文用量（用于 `/v1/sessions/{id}/usage` 与 Admin API）
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SessionUsage {
+    #[serde(flatten)]
+    pub session: SessionCost,
+    /// 当前上下文所属模型的上下文窗口（tokens）
+    pub context_window: u64,
+    /// 当前上下文占上下文窗口的百分比
+    pub context_usage_percent: f64,
+}
+
+impl From<SessionCost> for SessionUsage {
+    fn from(session: SessionCost) -> Self {
+        let context_window = get_context_window_size(&session.model).max(0) as u64;
+        let context_usage_percent = if context_window == 0 {
+            0.0
+        } else {
+            session.context_tokens as f64 * 100.0 / context_window as f64
+        };
+        Self {
+            session,
+            context_window,
+            context_usage_percent,
+        }
+    }
+}
+
+static LEDGER: LazyLock<Mutex<HashMap<String, SessionCost>>> =
+    LazyLock::new(|| Mutex::new(HashMap::new()));
+
+/// 记录一次请求的用量，返回更新后的会话累计值
+pub fn record(session_id: &str, model: &str, input_tokens: u64, output_tokens: u64) -> SessionCost {
+    record_in(
+        &mut LEDGER.lock(),
+        session_id,
+        model,
+        input_tokens,
+        output_tokens,
+        chrono::Utc::now(),
+    )
+}
+
+/// 查询会话累计值
+pub fn get(session_id: &str) -> Option<SessionCost> {
+    LEDGER.lock().get(session_id).cloned()
+}
+
+/// 查询会话的上下文用量
+pub fn usage(session_id: &str) -> Option<SessionUsage> {
+    get(session_id).map(SessionUsage::from)
+}
+
+/// 列出最近更新的会话（按更新时间倒序，最多 200 个）
+pub fn list_usage() -> Vec<SessionUsage> {
+    let mut sessions: Vec<SessionCost> = LEDGER.lock().values().cloned().collect();
+    sessions.sort_by_key(|s| std::cmp::Reverse(s.updated_ts));
+    sessions.truncate(MAX_LISTED_SESSIONS);
+    sessions.into_iter().map(SessionUsage::from).collect()
+}
+
+fn record_in(
+    ledger: &mut HashMap<String, SessionCost>,
+    session_id: &str,
+    model: &str,
+    input_tokens: u64,
+    output_tokens: u64,
+    now: chrono::DateTime<chrono::Utc>,
+) -> SessionCost {
+    if !ledger.contains_key(session_id)
+        && ledger.len() >= MAX_SESSIONS
+        && let Some(oldest) = ledger
+            .values()
+            .min_by_key(|s| s.updated_ts)
+            .map(|s| s.session_id.clone())
+    {
+        ledger.remove(&oldest);
+    }
+
+    let timestamp = now.to_rfc3339();
+    let entry = ledger
+        .entry(session_id.to_string())
+        .or_insert_with(|| SessionCost {
+            session_id: session_id.to_string(),
+            requests: 0,
+            input_tokens: 0,
+            output_tokens: 0,
+            cost_usd: 0.0,
+            currency: "USD",
+            model: model.to_string(),
+            context_tokens: 0,
+            compactions: 0,
+            last_compacted_at: None,
+            created_at: timestamp.clone(),
+            updated_at: timestamp.clone(),
+            updated_ts: now.timestamp(),
+            contexts: HashMap::new(),
+        });
+    entry.requests += 1;
+    entry.input_tokens += input_tokens;
+    entry.output_tokens += output_tokens;
+    entry.cost_usd += estimate_cost(model, input_tokens, output_tokens);
+
+    if let Some(previous) = entry.contexts.insert(model.to_string(), input_tokens)
+        && previous >= COMPACT_MIN_CONTEXT
+        && input_tokens.saturating_mul(2) < previous
+    {
+        entry.compactions += 1;
+        entry.last_compacted_at = Some(timestamp.clone());
+    }
+    if let Some((model, tokens)) = entry.contexts.iter().max_by_key(|(_, tokens)| **tokens) {
+        entry.model = model.clone();
+        entry.context_tokens = *tokens;
+    }
+    entry.updated_at = timestamp;
+    entry.updated_ts = now.timestamp();
+    entry.clone()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_price_for_model_families() {
+        assert_eq!(price_for("claude-sonnet-4-5-20250929").unwrap().input, 3.0);
+        assert_eq!(price_for("claude-opus-4-5-20251101").unwrap().output, 25.0);
+        assert_eq!(price_for("claude-opus-4-1-20250805").unwrap().output, 75.0);
+        assert_eq!(price_for("claude-haiku-4-5-20251001").unwrap().input, 1.0);
+        assert!(price_for("gpt-4o").is_none());
+    }
+
+    #[test]
+    fn test_configured_prices_override_builtin() {
+        let rules = vec![
+            ModelPriceRule {
+                models: vec!["claude-sonnet-4-5*".to_string()],
+                input_per_mtok: 2.0,
+                output_per_mtok: 10.0,
+                cache_read_per_mtok: Some(0.3),
+            },
+            ModelPriceRule {
+                models: vec!["DeepSeek-V3".to_string()],
+                input_per_mtok: 0.5,
+                output_per_mtok: 1.5,
+                cache_read_per_mtok: None,
+            },
+        ];
+        let (price, source) = lookup(&rules, "claude-sonnet-4-5-20250929").unwrap();
+        assert_eq!(source, PriceSource::Config);
+        assert_eq!(price.cache_read, 0.3);
+
+        let (price, source) = lookup(&rules, "deepseek-v3").unwrap();
+        assert_eq!(source, PriceSource::Config);
+        assert_eq!(price.cache_read, 0.05);
+
+        let (price, source) = lookup(&rules, "claude-sonnet-4-20250514").unwrap();
+        assert_eq!(source, PriceSource::Builtin);
+        assert_eq!(price.input, 3.0);
+        assert!(lookup(&rules, "gpt-4o").is_none());
+    }
+
+    #[test]
+    fn test_record_accumulates_per_session() {
+        let mut ledger = HashMap::new();
+        let now = chrono::Utc::now();
+        record_in(&mut ledger, "s1", "claude-sonnet-4-5", 1_000_000, 0, now);
+        let cost = record_in(&mut ledger, "s1", "claude-sonnet-4-5", 0, 100_000, now);
+        assert_eq!(cost.requests, 2);
+        assert_eq!(cost.input_tokens, 1_000_000);
+        assert!((cost.cost_usd - 4.5).abs() < 1e-9);
+
+        let other = record_in(&mut ledger, "s2", "unknown-model", 10, 10, now);
+        assert_eq!(other.requests, 1);
+        assert_eq!(other.cost_usd, 0.0);
+    }
+
+    #[test]
+    fn test_context_tracks_main_model_and_compactions() {
+        let mut ledger = HashMap::new();
+        let now = chrono::Utc::now();
+        record_in(&mut ledger, "s", "claude-opus-4-6", 120_000, 500, now);
+        // 小模型的标题请求不影响主模型的上下文
+        let session = record_in(&mut ledger, "s", "claude-haiku-4-5", 300, 20, now);
+        assert_eq!(session.model, "claude-opus-4-6");
+        assert_eq!(session.context_tokens, 120_000);
+        assert_eq!(session.compactions, 0);
+
+        let session = record_in(&mut ledger, "s", "claude-opus-4-6", 150_000, 500, now);
+        assert_eq!(session.context_tokens, 150_000);
+        assert_eq!(session.compactions, 0);
+
+        let session = record_in(&mut ledger, "s", "claude-opus-4-6", 15_000, 500, now);
+        assert_eq!(session.context_tokens, 15_000);
+        assert_eq!(session.compactions, 1);
+        assert!(session.last_compacted_at.is_some());
+    }
+}
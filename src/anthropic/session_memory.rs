@@ -0,0 +1,403 @@
+//! 会话记忆
+//!
+//! 以 metadata.user_id 中的 session UUID 为键保存键值笔记（项目约定、关键路径等），
+//! 每次请求时以紧凑的 system 块追加到 system 提示末尾，长会话在 `/compact` 压缩
+//! 上下文后仍能保留这些事实。笔记通过 `/v1/sessions/{id}/memory` 与 Admin API 读写，
+//! 持久化到凭据文件所在目录的 `kiro_session_memory.json`。
+
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::path::PathBuf;
+use std::sync::OnceLock;
+
+use chrono::{DateTime, Utc};
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+
+use crate::common::atomic_file;
+use crate::model::config::SessionMemoryConfig;
+
+use super::types::{MessagesRequest, SystemMessage};
+
+/// 笔记键的最大字符数
+const MAX_KEY_CHARS: usize = 64;
+
+/// 笔记内容的最大字符数
+const MAX_VALUE_CHARS: usize = 2000;
+
+static STORE: OnceLock<MemoryStore> = OnceLock::new();
+
+struct MemoryStore {
+    config: SessionMemoryConfig,
+    sessions: Mutex<HashMap<String, SessionMemory>>,
+    path: Option<PathBuf>,
+}
+
+/// 单个会话的记忆
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SessionMemory {
+    pub notes: BTreeMap<String, String>,
+    pub updated_at: Option<DateTime<Utc>>,
+}
+
+/// 会话记忆（含会话 ID，用于接口响应）
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SessionMemoryView {
+    pub session_id: String,
+    #[serde(flatten)]
+    pub memory: SessionMemory,
+}
+
+/// 会话记忆摘要（Admin 列表）
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SessionMemorySummary {
+    pub session_id: String,
+    pub notes: usize,
+    pub updated_at: Option<DateTime<Utc>>,
+}
+
+/// 会话记忆操作错误
+#[derive(Debug)]
+pub enum MemoryError {
+    /// 未配置 `sessionMemory`
+    Disabled,
+    /// 会话或笔记不存在
+    NotFound(String),
+    /// 笔记键或内容不合法
+    Invalid(String),
+    /// 持久化失败
+    Persist(String),
+}
+
+impl fmt::Display for MemoryError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MemoryError::Disabled => write!(f, "Session memory is not enabled"),
+            MemoryError::NotFound(msg) => write!(f, "{}", msg),
+            MemoryError::Invalid(msg) => write!(f, "{}", msg),
+            MemoryError::Persist(msg) => write!(f, "Failed to persist session memory: {}", msg),
+        }
+    }
+}
+
+impl std::error::Error for MemoryError {}
+
+/// 初始化会话记忆（未配置时不做任何事），从持久化文件加载已有笔记
+///
+/// 文件无法解析时备份后以空记忆启动；无法读取时不再写入该文件，避免覆盖已有笔记
+pub fn init(config: Option<&SessionMemoryConfig>, path: Option<PathBuf>) {
+    let Some(config) = config else {
+        return;
+    };
+    let (sessions, path) = match path {
+        Some(path) => match atomic_file::load_json(&path) {
+            Ok(sessions) => (sessions.unwrap_or_default(), Some(path)),
+            Err(e) => {
+                tracing::error!(
+                    "读取会话记忆 {} 失败，本次运行不会写入该文件: {}",
+                    path.display(),
+                    e
+                );
+                (HashMap::new(), None)
+            }
+        },
+        None => (HashMap::new(), None),
+    };
+    let _ = STORE.set(MemoryStore {
+        config: *config,
+        sessions: Mutex::new(sessions),
+        path,
+    });
+}
+
+fn store() -> Result<&'static MemoryStore, MemoryError> {
+    STORE.get().ok_or(MemoryError::Disabled)
+}
+
+fn session_not_found(session_id: &str) -> MemoryError {
+    MemoryError::NotFound(format!("Session memory not found: {}", session_id))
+}
+
+fn validate_note(key: &str, value: &str) -> Result<(), MemoryError> {
+    if key.trim().is_empty() || key.chars().count() > MAX_KEY_CHARS || key.contains('\n') {
+        return Err(MemoryError::Invalid(format!(
+            "Memory key must be a single line of 1-{} characters",
+            MAX_KEY_CHARS
+        )));
+    }
+    if value.chars().count() > MAX_VALUE_CHARS {
+        return Err(MemoryError::Invalid(format!(
+            "Memory value exceeds {} characters",
+            MAX_VALUE_CHARS
+        )));
+    }
+    Ok(())
+}
+
+impl MemoryStore {
+    /// 在锁内修改会话记忆并持久化（空会话会被移除），返回修改后的记忆
+    ///
+    /// 修改在副本上进行，写入文件成功后才替换内存中的数据
+    fn update<F>(&self, session_id: &str, f: F) -> Result<SessionMemory, MemoryError>
+    where
+        F: FnOnce(&mut SessionMemory) -> Result<(), MemoryError>,
+    {
+        let mut sessions = self.sessions.lock();
+        let mut memory = sessions.get(session_id).cloned().unwrap_or_default();
+        f(&mut memory)?;
+        if memory.notes.len() > self.config.max_notes {
+            return Err(MemoryError::Invalid(format!(
+                "A session can hold at most {} notes",
+                self.config.max_notes
+            )));
+        }
+        memory.updated_at = Some(Utc::now());
+
+        let mut updated = sessions.clone();
+        if memory.notes.is_empty() {
+            updated.remove(session_id);
+        } else {
+            if !updated.contains_key(session_id) {
+                evict_oldest(&mut updated, self.config.max_sessions);
+            }
+            updated.insert(session_id.to_string(), memory.clone());
+        }
+        self.persist(&updated)?;
+        *sessions = updated;
+        Ok(memory)
+    }
+
+    fn persist(&self, sessions: &HashMap<String, SessionMemory>) -> Result<(), MemoryError> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+        let json = serde_json::to_string_pretty(sessions)
+            .map_err(|e| MemoryError::Persist(e.to_string()))?;
+        atomic_file::write(path, json.as_bytes()).map_err(|e| MemoryError::Persist(e.to_string()))
+    }
+}
+
+/// 会话数达到上限时淘汰最久未更新的会话，为新会话腾出位置
+fn evict_oldest(sessions: &mut HashMap<String, SessionMemory>, max_sessions: usize) {
+    while sessions.len() >= max_sessions.max(1) {
+        let Some(oldest) = sessions
+            .iter()
+            .min_by_key(|(_, m)| m.updated_at)
+            .map(|(id, _)| id.clone())
+        else {
+            break;
+        };
+        sessions.remove(&oldest);
+    }
+}
+
+/// 获取会话记忆
+pub fn get(session_id: &str) -> Result<SessionMemoryView, MemoryError> {
+    let memory = store()?
+        .sessions
+        .lock()
+        .get(session_id)
+        .cloned()
+        .ok_or_else(|| session_not_found(session_id))?;
+    Ok(SessionMemoryView {
+        session_id: session_id.to_string(),
+        memory,
+    })
+}
+
+/// 写入（新增或覆盖）一条笔记
+pub fn set_note(
+    session_id: &str,
+    key: &str,
+    value: &str,
+) -> Result<SessionMemoryView, MemoryError> {
+    validate_note(key, value)?;
+    let memory = store()?.update(session_id, |memory| {
+        memory.notes.insert(key.to_string(), value.to_string());
+        Ok(())
+    })?;
+    Ok(SessionMemoryView {
+        session_id: session_id.to_string(),
+        memory,
+    })
+}
+
+/// 删除一条笔记
+pub fn delete_note(session_id: &str, key: &str) -> Result<SessionMemoryView, MemoryError> {
+    let memory = store()?.update(session_id, |memory| {
+        memory
+            .notes
+            .remove(key)
+            .map(|_| ())
+            .ok_or_else(|| MemoryError::NotFound(format!("Memory note not found: {}", key)))
+    })?;
+    Ok(SessionMemoryView {
+        session_id: session_id.to_string(),
+        memory,
+    })
+}
+
+/// 替换会话的全部笔记（为空时删除该会话的记忆）
+pub fn replace(
+    session_id: &str,
+    notes: BTreeMap<String, String>,
+) -> Result<SessionMemoryView, MemoryError> {
+    for (key, value) in &notes {
+        validate_note(key, value)?;
+    }
+    let memory = store()?.update(session_id, |memory| {
+        memory.notes = notes;
+        Ok(())
+    })?;
+    Ok(SessionMemoryView {
+        session_id: session_id.to_string(),
+        memory,
+    })
+}
+
+/// 删除会话的全部笔记
+pub fn delete(session_id: &str) -> Result<(), MemoryError> {
+    let store = store()?;
+    let mut sessions = store.sessions.lock();
+    if sessions.remove(session_id).is_none() {
+        return Err(session_not_found(session_id));
+    }
+    store.persist(&sessions)
+}
+
+/// 列出所有保存了记忆的会话（按更新时间倒序）
+pub fn list() -> Result<Vec<SessionMemorySummary>, MemoryError> {
+    let mut summaries: Vec<SessionMemorySummary> = store()?
+        .sessions
+        .lock()
+        .iter()
+        .map(|(session_id, memory)| SessionMemorySummary {
+            session_id: session_id.clone(),
+            notes: memory.notes.len(),
+            updated_at: memory.updated_at,
+        })
+        .collect();
+    summaries.sort_by_key(|s| std::cmp::Reverse(s.updated_at));
+    Ok(summaries)
+}
+
+/// 将会话记忆渲染为 system 块
+fn render(notes: &BTreeMap<String, String>) -> String {
+    let mut block = String::from("<session_memory>\nNotes saved for this session:\n");
+    for (key, value) in notes {
+        block.push_str(&format!("- {}: {}\n", key, value));
+    }
+    block.push_str("</session_memory>");
+    block
+}
+
+/// 将会话记忆追加到请求的 system 提示末尾，返回注入的笔记数
+pub fn inject(session_id: &str, payload: &mut MessagesRequest) -> usize {
+    let Some(store) = STORE.get() else {
+        return 0;
+    };
+    let sessions = store.sessions.lock();
+    let Some(memory) = sessions.get(session_id).filter(|m| !m.notes.is_empty()) else {
+        return 0;
+    };
+    payload
+        .system
+        .get_or_insert_with(Vec::new)
+        .push(SystemMessage {
+            text: render(&memory.notes),
+        });
+    memory.notes.len()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn memory(notes: &[(&str, &str)], updated_at: i64) -> SessionMemory {
+        SessionMemory {
+            notes: notes
+                .iter()
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .collect(),
+            updated_at: DateTime::from_timestamp(updated_at, 0),
+        }
+    }
+
+    #[test]
+    fn test_render_and_validate() {
+        let rendered = render(&memory(&[("build", "cargo build"), ("db", "postgres")], 0).notes);
+        assert_eq!(
+            rendered,
+            "<session_memory>\nNotes saved for this session:\n- build: cargo build\n- db: postgres\n</session_memory>"
+        );
+
+        assert!(validate_note("build", "cargo build").is_ok());
+        assert!(matches!(
+            validate_note(" ", "x"),
+            Err(MemoryError::Invalid(_))
+        ));
+        assert!(matches!(
+            validate_note("a\nb", "x"),
+            Err(MemoryError::Invalid(_))
+        ));
+        let long = "x".repeat(MAX_VALUE_CHARS + 1);
+        assert!(matches!(
+            validate_note("k", &long),
+            Err(MemoryError::Invalid(_))
+        ));
+    }
+
+    #[test]
+    fn test_evict_oldest_session() {
+        let mut sessions = HashMap::from([
+            ("a".to_string(), memory(&[("k", "v")], 30)),
+            ("b".to_string(), memory(&[("k", "v")], 10)),
+            ("c".to_string(), memory(&[("k", "v")], 20)),
+        ]);
+        evict_oldest(&mut sessions, 3);
+        assert_eq!(sessions.len(), 2);
+        assert!(!sessions.contains_key("b"));
+    }
+
+    #[test]
+    fn test_failed_persist_keeps_memory_unchanged() {
+        let dir = std::env::temp_dir().join(format!("kiro-memory-{}", uuid::Uuid::new_v4()));
+        let store = MemoryStore {
+            config: SessionMemoryConfig {
+                max_notes: 10,
+                max_sessions: 10,
+            },
+            sessions: Mutex::new(HashMap::from([(
+                "s".to_string(),
+                memory(&[("build", "cargo build")], 0),
+            )])),
+            // 目录不存在，写入失败
+            path: Some(dir.join("kiro_session_memory.json")),
+        };
+
+        let result = store.update("s", |memory| {
+            memory
+                .notes
+                .insert("db".to_string(), "postgres".to_string());
+            Ok(())
+        });
+        assert!(matches!(result, Err(MemoryError::Persist(_))));
+        assert_eq!(store.sessions.lock()["s"].notes.len(), 1);
+
+        std::fs::create_dir_all(&dir).unwrap();
+        store
+            .update("s", |memory| {
+                memory
+                    .notes
+                    .insert("db".to_string(), "postgres".to_string());
+                Ok(())
+            })
+            .unwrap();
+        assert_eq!(store.sessions.lock()["s"].notes.len(), 2);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
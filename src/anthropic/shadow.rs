@@ -0,0 +1,458 @@
+//! 影子流量
+//!
+//! 配置 `shadow` 后，按 `sampleRatio` 把非流式 Messages 请求（改写前的原始内容）复制一份，
+//! 与主请求并行发往另一个 kiro.rs 实例。两边都完成后比较响应的状态码、stop_reason、
+//! 内容块类型与工具调用（可选比较文本），不一致时记录日志并保留差异，供 Admin API 查看。
+//! 影子请求不影响主请求的响应，失败只计数。
+
+use std::collections::VecDeque;
+use std::sync::OnceLock;
+
+use axum::{body::Body, http::HeaderMap, response::Response};
+use parking_lot::Mutex;
+use serde::Serialize;
+use serde_json::{Value, json};
+use tokio::sync::oneshot;
+
+use crate::http_client::build_client;
+use crate::model::config::{ShadowConfig, TlsBackend};
+
+use super::types::MessagesRequest;
+use super::websearch;
+
+/// 标记影子请求的请求头，影子实例收到后不再继续复制
+pub const SHADOW_HEADER: &str = "x-kiro-shadow";
+
+static SHADOW: OnceLock<Shadow> = OnceLock::new();
+
+/// 已配置的影子实例
+struct Shadow {
+    config: ShadowConfig,
+    client: reqwest::Client,
+    state: Mutex<ShadowState>,
+}
+
+#[derive(Default)]
+struct ShadowState {
+    stats: ShadowStats,
+    diffs: VecDeque<ShadowDiff>,
+}
+
+/// 影子流量计数
+#[derive(Debug, Clone, Copy, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ShadowStats {
+    /// 已复制的请求数
+    pub sampled: u64,
+    /// 两边响应一致的次数
+    pub matched: u64,
+    /// 两边响应不一致的次数
+    pub mismatched: u64,
+    /// 影子请求失败（网络错误、超时、响应无法解析）的次数
+    pub failed: u64,
+}
+
+/// 一次响应差异记录
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ShadowDiff {
+    pub request_id: String,
+    /// 记录时间（RFC3339）
+    pub at: String,
+    pub model: String,
+    /// 不一致的字段说明
+    pub differences: Vec<String>,
+    /// 主实例响应摘要
+    pub primary: Value,
+    /// 影子实例响应摘要
+    pub shadow: Value,
+}
+
+/// 影子流量报告（用于 Admin API）
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ShadowReport {
+    pub enabled: bool,
+    pub stats: ShadowStats,
+    /// 最近的差异记录（新的在前）
+    pub diffs: Vec<ShadowDiff>,
+}
+
+/// 初始化影子实例（未配置时不做任何事）
+///
+/// 应在应用启动时调用一次
+pub fn init(config: Option<&ShadowConfig>, tls_backend: TlsBackend) -> anyhow::Result<()> {
+    let Some(config) = config else {
+        return Ok(());
+    };
+    if config.url.trim().is_empty() {
+        anyhow::bail!("shadow.url 不能为空");
+    }
+    // 影子实例通常在内网，不走全局代理
+    let client = build_client(None, config.timeout_secs, tls_backend)?;
+    let _ = SHADOW.set(Shadow {
+        config: config.clone(),
+        client,
+        state: Mutex::default(),
+    });
+    Ok(())
+}
+
+/// 抽中的影子请求（尚未发出）
+pub struct ShadowRequest {
+    shadow: &'static Shadow,
+    body: Value,
+    model: String,
+    request_id: String,
+}
+
+/// 进行中的影子请求，主请求完成后通过 [`ShadowHandle::finish`] 交付主响应
+pub struct ShadowHandle {
+    primary: oneshot::Sender<(u16, Option<Value>)>,
+}
+
+/// 按比例决定是否复制请求
+///
+/// 须在请求改写之前调用，影子实例收到的是客户端的原始请求；
+/// 流式请求、WebSearch 请求与来自其他实例的影子请求不复制
+pub fn sample(
+    headers: &HeaderMap,
+    payload: &MessagesRequest,
+    request_id: String,
+) -> Option<ShadowRequest> {
+    let shadow = SHADOW.get()?;
+    if payload.stream
+        || headers.contains_key(SHADOW_HEADER)
+        || websearch::has_web_search_tool(payload)
+        || fastrand::f64() >= shadow.config.sample_ratio
+    {
+        return None;
+    }
+    Some(ShadowRequest {
+        shadow,
+        body: shadow_body(payload),
+        model: payload.model.clone(),
+        request_id,
+    })
+}
+
+impl ShadowRequest {
+    /// 发出影子请求，与主请求并行执行
+    ///
+    /// 在确定主请求会调用上游后调用，响应缓存命中与复用进行中请求的响应不复制
+    pub fn dispatch(self) -> ShadowHandle {
+        let ShadowRequest {
+            shadow,
+            body,
+            model,
+            request_id,
+        } = self;
+        shadow.state.lock().stats.sampled += 1;
+        let (tx, rx) = oneshot::channel::<(u16, Option<Value>)>();
+        tokio::spawn(async move {
+            let shadow_result = shadow.send(&body).await;
+            // 主请求未走到响应阶段（如被拒绝或超时）时放弃比较
+            let Ok((primary_status, primary_body)) = rx.await else {
+                return;
+            };
+            let (shadow_status, shadow_body) = match shadow_result {
+                Ok(result) => result,
+                Err(e) => {
+                    tracing::warn!(request_id = %request_id, "影子请求失败: {:#}", e);
+                    shadow.state.lock().stats.failed += 1;
+                    return;
+                }
+            };
+            let primary = summarize(
+                primary_status,
+                primary_body.as_ref(),
+                shadow.config.compare_text,
+            );
+            let shadow_summary = summarize(
+                shadow_status,
+                shadow_body.as_ref(),
+                shadow.config.compare_text,
+            );
+            shadow.record(request_id, model, primary, shadow_summary);
+        });
+        ShadowHandle { primary: tx }
+    }
+}
+
+impl ShadowHandle {
+    /// 交付主响应用于比较，返回原样的响应
+    ///
+    /// 非流式响应体已在内存中，这里读出后重新封装，不改变响应内容
+    pub async fn finish(self, response: Response) -> Response {
+        let (parts, body) = response.into_parts();
+        let bytes = match axum::body::to_bytes(body, usize::MAX).await {
+            Ok(bytes) => bytes,
+            Err(e) => {
+                tracing::warn!("读取主响应失败，跳过影子比较: {}", e);
+                return Response::from_parts(parts, Body::empty());
+            }
+        };
+        let value = serde_json::from_slice(&bytes).ok();
+        let _ = self.primary.send((parts.status.as_u16(), value));
+        Response::from_parts(parts, Body::from(bytes))
+    }
+}
+
+/// 获取影子流量报告
+pub fn report() -> ShadowReport {
+    match SHADOW.get() {
+        Some(shadow) => {
+            let state = shadow.state.lock();
+            ShadowReport {
+                enabled: true,
+                stats: state.stats,
+                diffs: state.diffs.iter().cloned().collect(),
+            }
+        }
+        None => ShadowReport {
+            enabled: false,
+            stats: ShadowStats::default(),
+            diffs: Vec::new(),
+        },
+    }
+}
+
+impl Shadow {
+    /// 发送影子请求，返回状态码与响应体（无法解析为 JSON 时为 None）
+    async fn send(&self, body: &Value) -> anyhow::Result<(u16, Option<Value>)> {
+        let url = format!("{}/v1/messages", self.config.url.trim_end_matches('/'));
+        let mut request = self
+            .client
+            .post(url)
+            .header(SHADOW_HEADER, "1")
+            .header("anthropic-version", "2023-06-01")
+            .json(body);
+        if let Some(api_key) = &self.config.api_key {
+            request = request.header("x-api-key", api_key);
+        }
+        let response = request.send().await?;
+        let status = response.status().as_u16();
+        let bytes = response.bytes().await?;
+        Ok((status, serde_json::from_slice(&bytes).ok()))
+    }
+
+    fn record(&self, request_id: String, model: String, primary: Value, shadow: Value) {
+        let differences = diff(&primary, &shadow);
+        let mut state = self.state.lock();
+        if differences.is_empty() {
+            state.stats.matched += 1;
+            return;
+        }
+        state.stats.mismatched += 1;
+        tracing::warn!(
+            request_id = %request_id,
+            differences = ?differences,
+            "影子实例响应与主实例不一致"
+        );
+        if state.diffs.len() >= self.config.max_records.max(1) {
+            state.diffs.pop_back();
+        }
+        state.diffs.push_front(ShadowDiff {
+            request_id,
+            at: chrono::Utc::now().to_rfc3339(),
+            model,
+            differences,
+            primary,
+            shadow,
+        });
+    }
+}
+
+/// 由解析后的请求重建影子请求体（非流式）
+fn shadow_body(payload: &MessagesRequest) -> Value {
+    let mut body = json!({
+        "model": payload.model,
+        "max_tokens": payload.max_tokens,
+        "messages": payload.messages,
+        "stream": false,
+    });
+    let optional = [
+        (
+            "system",
+            json!(payload.system.as_ref().map(|system| {
+                system
+                    .iter()
+                    .map(|m| json!({ "type": "text", "text": m.text }))
+                    .collect::<Vec<_>>()
+            })),
+        ),
+        ("tools", json!(payload.tools)),
+        ("tool_choice", json!(payload.tool_choice)),
+        (
+            "thinking",
+            json!(payload.thinking.as_ref().map(|t| json!({
+                "type": t.thinking_type,
+                "budget_tokens": t.budget_tokens,
+            }))),
+        ),
+        (
+            "output_config",
+            json!(
+                payload
+                    .output_config
+                    .as_ref()
+                    .map(|c| json!({ "effort": c.effort }))
+            ),
+        ),
+        (
+            "metadata",
+            json!(
+                payload
+                    .metadata
+                    .as_ref()
+                    .map(|m| json!({ "user_id": m.user_id }))
+            ),
+        ),
+        ("stop_sequences", json!(payload.stop_sequences)),
+    ];
+    for (key, value) in optional {
+        if !value.is_null() {
+            body[key] = value;
+        }
+    }
+    body
+}
+
+/// 提取响应中参与比较的部分
+///
+/// 错误响应只保留状态码与错误类型；成功响应保留 stop_reason、内容块类型与工具调用，
+/// `compare_text` 时另外保留文本内容
+fn summarize(status: u16, body: Option<&Value>, compare_text: bool) -> Value {
+    let Some(body) = body else {
+        return json!({ "status": status });
+    };
+    if !(200..300).contains(&status) {
+        return json!({ "status": status, "error": body.pointer("/error/type") });
+    }
+    let blocks = body["content"]
+        .as_array()
+        .map(Vec::as_slice)
+        .unwrap_or_default();
+    let mut summary = json!({
+        "status": status,
+        "stopReason": body["stop_reason"],
+        "blocks": blocks.iter().map(|b| b["type"].clone()).collect::<Vec<_>>(),
+        "toolUses": blocks
+            .iter()
+            .filter(|b| b["type"] == "tool_use")
+            .map(|b| json!({ "name": b["name"], "input": b["input"] }))
+            .collect::<Vec<_>>(),
+    });
+    if compare_text {
+        summary["text"] = blocks
+            .iter()
+            .filter(|b| b["type"] == "text")
+            .filter_map(|b| b["text"].as_str())
+            .collect::<String>()
+            .into();
+    }
+    summary
+}
+
+/// 逐字段比较两份响应摘要
+fn diff(primary: &Value, shadow: &Value) -> Vec<String> {
+    let (Some(primary), Some(shadow)) = (primary.as_object(), shadow.as_object()) else {
+        return Vec::new();
+    };
+    let mut keys: Vec<&String> = primary.keys().chain(shadow.keys()).collect();
+    keys.sort();
+    keys.dedup();
+    keys.into_iter()
+        .filter_map(|key| {
+            let (a, b) = (
+                primary.get(key).unwrap_or(&Value::Null),
+                shadow.get(key).unwrap_or(&Value::Null),
+            );
+            (a != b).then(|| format!("{}: {} != {}", key, a, b))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_shadow_body_is_non_stream_original_request() {
+        let payload: MessagesRequest = serde_json::from_value(json!({
+            "model": "claude-sonnet-4-5",
+            "max_tokens": 1024,
+            "stream": true,
+            "system": "be brief",
+            "messages": [{"role": "user", "content": "hi"}],
+            "metadata": {"user_id": "user_1"}
+        }))
+        .unwrap();
+        let body = shadow_body(&payload);
+        assert_eq!(body["stream"], false);
+        assert_eq!(
+            body["system"],
+            json!([{"type": "text", "text": "be brief"}])
+        );
+        assert_eq!(body["metadata"]["user_id"], "user_1");
+        assert!(body.get("tools").is_none());
+        assert!(body.get("thinking").is_none());
+    }
+
+    #[test]
+    fn test_summarize_and_diff() {
+        let primary = json!({
+            "stop_reason": "tool_use",
+            "content": [
+                {"type": "text", "text": "checking"},
+                {"type": "tool_use", "id": "a", "name": "Read", "input": {"path": "x"}}
+            ]
+        });
+        let shadow = json!({
+            "stop_reason": "end_turn",
+            "content": [{"type": "text", "text": "done"}]
+        });
+
+        let same = summarize(200, Some(&primary), false);
+        assert!(diff(&same, &summarize(200, Some(&primary), false)).is_empty());
+
+        let differences = diff(&same, &summarize(200, Some(&shadow), false));
+        assert_eq!(differences.len(), 3);
+        assert!(differences[0].starts_with("blocks:"));
+        assert!(differences[1].starts_with("stopReason:"));
+        assert!(differences[2].starts_with("toolUses:"));
+
+        // 文本只在 compareText 时参与比较
+        let text_only = json!({
+            "stop_reason": "tool_use",
+            "content": [
+                {"type": "text", "text": "looking"},
+                {"type": "tool_use", "id": "b", "name": "Read", "input": {"path": "x"}}
+            ]
+        });
+        assert!(diff(&same, &summarize(200, Some(&text_only), false)).is_empty());
+        assert_eq!(
+            diff(
+                &summarize(200, Some(&primary), true),
+                &summarize(200, Some(&text_only), true)
+            ),
+            ["text: \"checking\" != \"looking\""]
+        );
+
+        let error = summarize(
+            529,
+            Some(&json!({"type": "error", "error": {"type": "overloaded_error"}})),
+            false,
+        );
+        assert_eq!(
+            diff(&summarize(200, Some(&shadow), false), &error),
+            [
+                "blocks: [\"text\"] != null",
+                "error: null != \"overloaded_error\"",
+                "status: 200 != 529",
+                "stopReason: \"end_turn\" != null",
+                "toolUses: [] != null"
+            ]
+        );
+    }
+}
@@ -7,7 +7,10 @@ use std::collections::HashMap;
 use serde_json::json;
 use uuid::Uuid;
 
-use crate::kiro::model::events::Event;
+use crate::kiro::model::events::{Event, ReasoningContentEvent};
+
+use super::output_filter::OutputFilter;
+use super::prefill::PrefillFilter;
 
 /// 找到小于等于目标位置的最近有效UTF-8字符边界
 ///
@@ -289,6 +292,8 @@ pub struct SseStateManager {
     next_block_index: i32,
     /// 当前 stop_reason
     stop_reason: Option<String>,
+    /// 命中的停止序列
+    stop_sequence: Option<String>,
     /// 是否有工具调用
     has_tool_use: bool,
 }
@@ -308,6 +313,7 @@ impl SseStateManager {
             message_ended: false,
             next_block_index: 0,
             stop_reason: None,
+            stop_sequence: None,
             has_tool_use: false,
         }
     }
@@ -331,16 +337,24 @@ impl SseStateManager {
         self.has_tool_use = has;
     }
 
-    /// 设置 stop_reason
+    /// 设置 stop_reason（命中停止序列后不再改变）
     pub fn set_stop_reason(&mut self, reason: impl Into<String>) {
-        self.stop_reason = Some(reason.into());
+        if self.stop_sequence.is_none() {
+            self.stop_reason = Some(reason.into());
+        }
+    }
+
+    /// 记录命中的停止序列，stop_reason 固定为 `stop_sequence`
+    pub fn set_stop_sequence(&mut self, sequence: impl Into<String>) {
+        self.stop_reason = Some("stop_sequence".to_string());
+        self.stop_sequence = Some(sequence.into());
     }
 
     /// 检查是否存在非 thinking 类型的内容块（如 text 或 tool_use）
     fn has_non_thinking_blocks(&self) -> bool {
         self.active_blocks
             .values()
-            .any(|b| b.block_type != "thinking")
+            .any(|b| b.block_type != "thinking" && b.block_type != "redacted_thinking")
     }
 
     /// 获取最终的 stop_reason
@@ -461,21 +475,20 @@ impl SseStateManager {
     ) -> Vec<SseEvent> {
         let mut events = Vec::new();
 
-        // 关闭所有未关闭的块
-        for (index, block) in self.active_blocks.iter_mut() {
-            if block.started && !block.stopped {
-                events.push(SseEvent::new(
-                    "content_block_stop",
-                    json!({
-                        "type": "content_block_stop",
-                        "index": index
-                    }),
-                ));
-                block.stopped = true;
-            }
+        // 按索引顺序关闭所有未关闭的块（与官方流一致）
+        let mut open_indices: Vec<i32> = self
+            .active_blocks
+            .iter()
+            .filter(|(_, block)| block.started && !block.stopped)
+            .map(|(&index, _)| index)
+            .collect();
+        open_indices.sort_unstable();
+        for index in open_indices {
+            events.extend(self.handle_content_block_stop(index));
         }
 
         // 发送 message_delta
+        // usage 为累计值，字段与 message_start 保持一致（Kiro 不返回 cache 统计，固定为 0）
         if !self.message_delta_sent {
             self.message_delta_sent = true;
             events.push(SseEvent::new(
@@ -484,10 +497,12 @@ impl SseStateManager {
                     "type": "message_delta",
                     "delta": {
                         "stop_reason": self.get_stop_reason(),
-                        "stop_sequence": null
+                        "stop_sequence": self.stop_sequence
                     },
                     "usage": {
                         "input_tokens": input_tokens,
+                        "cache_creation_input_tokens": 0,
+                        "cache_read_input_tokens": 0,
                         "output_tokens": output_tokens
                     }
                 }),
@@ -509,6 +524,46 @@ impl SseStateManager {
 
 use super::converter::get_context_window_size;
 
+/// 流结束时的用量回调，参数为最终的 (input_tokens, output_tokens)
+pub type UsageCallback = Box<dyn FnOnce(i32, i32) + Send>;
+
+/// 上游未给出 Retry-After 时建议客户端等待的秒数
+const DEFAULT_THROTTLE_RETRY_AFTER_SECS: u64 = 30;
+
+/// 是否为上游限流类异常/错误代码（如 ThrottlingException）
+fn is_throttling(kind: &str) -> bool {
+    kind.contains("Throttling") || kind.contains("TooManyRequests")
+}
+
+/// 创建限流错误 SSE 事件（终止事件，客户端应在 retry_after 秒后重试）
+pub fn create_throttle_error_event(message: &str, retry_after: u64) -> SseEvent {
+    SseEvent::new(
+        "error",
+        json!({
+            "type": "error",
+            "error": {
+                "type": "rate_limit_error",
+                "message": message,
+                "retry_after": retry_after
+            }
+        }),
+    )
+}
+
+/// 创建请求超时错误 SSE 事件（终止事件）
+pub fn create_timeout_error_event() -> SseEvent {
+    SseEvent::new(
+        "error",
+        json!({
+            "type": "error",
+            "error": {
+                "type": "timeout_error",
+                "message": "Request timed out before the upstream response completed."
+            }
+        }),
+    )
+}
+
 /// 流处理上下文
 pub struct StreamContext {
     /// SSE 状态管理器
@@ -539,9 +594,23 @@ pub struct StreamContext {
     pub thinking_block_index: Option<i32>,
     /// 文本块索引（thinking 启用时动态分配）
     pub text_block_index: Option<i32>,
+    /// 由 reasoningContentEvent 打开、尚未收到签名的 thinking 块索引
+    reasoning_block_index: Option<i32>,
     /// 是否需要剥离 thinking 内容开头的换行符
     /// 模型输出 `<thinking>\n` 时，`\n` 可能与标签在同一 chunk 或下一 chunk
     strip_thinking_leading_newline: bool,
+    /// 生成最终事件时调用一次的用量回调（配额统计等）
+    pub usage_callback: Option<UsageCallback>,
+    /// 上游响应头中的 Retry-After 秒数（流中途被限流时转发给客户端）
+    pub retry_after_hint: Option<u64>,
+    /// 流中途收到的上游限流消息，结束时以 error 事件代替 message_stop
+    throttled: Option<String>,
+    /// 是否因超过请求时限而中止，结束时以 error 事件代替 message_stop
+    timed_out: bool,
+    /// 去除响应开头复述的 assistant prefill（请求以 prefill 结尾时存在）
+    pub prefill_filter: Option<PrefillFilter>,
+    /// 停止序列检测与输出脱敏（请求带 stop_sequences 或配置了脱敏规则时存在）
+    pub output_filter: Option<OutputFilter>,
 }
 
 impl StreamContext {
@@ -567,11 +636,21 @@ impl StreamContext {
             thinking_extracted: false,
             thinking_block_index: None,
             text_block_index: None,
+            reasoning_block_index: None,
             strip_thinking_leading_newline: false,
+            usage_callback: None,
+            retry_after_hint: None,
+            throttled: None,
+            timed_out: false,
+            prefill_filter: None,
+            output_filter: None,
         }
     }
 
     /// 生成 message_start 事件
+    ///
+    /// Kiro 不返回 prompt cache 统计，cache 字段固定为 0，
+    /// 但从首个事件起就提供，客户端（如 Claude Code 的费用显示）无需等到流结束
     pub fn create_message_start_event(&self) -> serde_json::Value {
         json!({
             "type": "message_start",
@@ -585,7 +664,9 @@ impl StreamContext {
                 "stop_sequence": null,
                 "usage": {
                     "input_tokens": self.input_tokens,
-                    "output_tokens": 1
+                    "output_tokens": 1,
+                    "cache_creation_input_tokens": 0,
+                    "cache_read_input_tokens": 0
                 }
             }
         })
@@ -632,9 +713,25 @@ impl StreamContext {
 
     /// 处理 Kiro 事件并转换为 Anthropic SSE 事件
     pub fn process_kiro_event(&mut self, event: &Event) -> Vec<SseEvent> {
+        // 命中停止序列后丢弃后续输出内容
+        if self.stop_sequence_matched()
+            && matches!(
+                event,
+                Event::AssistantResponse(_)
+                    | Event::ToolUse(_)
+                    | Event::ReasoningContent(_)
+                    | Event::Unknown { .. }
+            )
+        {
+            return Vec::new();
+        }
         match event {
             Event::AssistantResponse(resp) => self.process_assistant_response(&resp.content),
             Event::ToolUse(tool_use) => self.process_tool_use(tool_use),
+            Event::ReasoningContent(reasoning) => self.process_reasoning(reasoning),
+            Event::Unknown {
+                text: Some(text), ..
+            } => self.process_assistant_response(text),
             Event::ContextUsage(context_usage) => {
                 // 从上下文使用百分比计算实际的 input_tokens
                 let window_size = get_context_window_size(&self.model);
@@ -659,6 +756,9 @@ impl StreamContext {
                 error_message,
             } => {
                 tracing::error!("收到错误事件: {} - {}", error_code, error_message);
+                if is_throttling(error_code) {
+                    self.throttled = Some(error_message.clone());
+                }
                 Vec::new()
             }
             Event::Exception {
@@ -669,6 +769,9 @@ impl StreamContext {
                 if exception_type == "ContentLengthExceededException" {
                     self.state_manager.set_stop_reason("max_tokens");
                 }
+                if is_throttling(exception_type) {
+                    self.throttled = Some(message.clone());
+                }
                 tracing::warn!("收到异常事件: {} - {}", exception_type, message);
                 Vec::new()
             }
@@ -687,7 +790,17 @@ impl StreamContext {
 
         // 如果启用了thinking，需要处理thinking块
         if self.thinking_enabled {
-            return self.process_content_with_thinking(content);
+            let mut events = self.close_reasoning_block();
+            events.extend(self.process_content_with_thinking(content));
+            return events;
+        }
+
+        if let Some(filter) = self.prefill_filter.as_mut() {
+            let text = filter.push(content);
+            if text.is_empty() {
+                return Vec::new();
+            }
+            return self.create_text_delta_events(&text);
         }
 
         // 非 thinking 模式同样复用统一的 text_delta 发送逻辑，
@@ -695,6 +808,14 @@ impl StreamContext {
         self.create_text_delta_events(content)
     }
 
+    /// 发送 prefill 过滤器中仍在缓冲的文本
+    fn flush_prefill(&mut self) -> Vec<SseEvent> {
+        match self.prefill_filter.as_mut().map(PrefillFilter::finish) {
+            Some(text) if !text.is_empty() => self.create_text_delta_events(&text),
+            _ => Vec::new(),
+        }
+    }
+
     /// 处理包含thinking块的内容
     fn process_content_with_thinking(&mut self, content: &str) -> Vec<SseEvent> {
         let mut events = Vec::new();
@@ -841,13 +962,43 @@ impl StreamContext {
         events
     }
 
+    /// 是否已命中停止序列
+    pub fn stop_sequence_matched(&self) -> bool {
+        self.output_filter
+            .as_ref()
+            .is_some_and(|f| f.matched_stop_sequence().is_some())
+    }
+
+    /// 经输出过滤器处理后创建 text_delta 事件
+    fn create_text_delta_events(&mut self, text: &str) -> Vec<SseEvent> {
+        let Some(filter) = self.output_filter.as_mut() else {
+            return self.emit_text_delta_events(text);
+        };
+        let text = filter.push(text);
+        if let Some(sequence) = filter.matched_stop_sequence() {
+            self.state_manager.set_stop_sequence(sequence);
+        }
+        if text.is_empty() {
+            return Vec::new();
+        }
+        self.emit_text_delta_events(&text)
+    }
+
+    /// 发送输出过滤器中仍在暂扣的文本
+    fn flush_output_filter(&mut self) -> Vec<SseEvent> {
+        match self.output_filter.as_mut().map(OutputFilter::finish) {
+            Some(text) if !text.is_empty() => self.emit_text_delta_events(&text),
+            _ => Vec::new(),
+        }
+    }
+
     /// 创建 text_delta 事件
     ///
     /// 如果文本块尚未创建，会先创建文本块。
     /// 当发生 tool_use 时，状态机会自动关闭当前文本块；后续文本会自动创建新的文本块继续输出。
     ///
     /// 返回值包含可能的 content_block_start 事件和 content_block_delta 事件。
-    fn create_text_delta_events(&mut self, text: &str) -> Vec<SseEvent> {
+    fn emit_text_delta_events(&mut self, text: &str) -> Vec<SseEvent> {
         let mut events = Vec::new();
 
         // 如果当前 text_block_index 指向的块已经被关闭（例如 tool_use 开始时自动 stop），
@@ -916,12 +1067,122 @@ impl StreamContext {
         )
     }
 
+    /// 创建 signature_delta 事件
+    fn create_signature_delta_event(&self, index: i32, signature: &str) -> SseEvent {
+        SseEvent::new(
+            "content_block_delta",
+            json!({
+                "type": "content_block_delta",
+                "index": index,
+                "delta": {
+                    "type": "signature_delta",
+                    "signature": signature
+                }
+            }),
+        )
+    }
+
+    /// 处理推理内容事件
+    ///
+    /// 上游原生推理输出直接映射为 thinking 块：text 作为 thinking_delta，
+    /// signature 作为 signature_delta 并结束当前块；redactedContent 输出为独立的
+    /// redacted_thinking 块。客户端未启用 thinking 时丢弃。
+    fn process_reasoning(&mut self, reasoning: &ReasoningContentEvent) -> Vec<SseEvent> {
+        if !self.thinking_enabled {
+            return Vec::new();
+        }
+        let mut events = Vec::new();
+        // 原生推理与 `<thinking>` 标签不会同时出现，之后的文本不再查找标签
+        self.thinking_extracted = true;
+
+        if let Some(data) = &reasoning.redacted_content {
+            events.extend(self.close_reasoning_block());
+            events.extend(self.close_text_block());
+            let index = self.state_manager.next_block_index();
+            self.thinking_block_index = Some(index);
+            events.extend(self.state_manager.handle_content_block_start(
+                index,
+                "redacted_thinking",
+                json!({
+                    "type": "content_block_start",
+                    "index": index,
+                    "content_block": {
+                        "type": "redacted_thinking",
+                        "data": data
+                    }
+                }),
+            ));
+            events.extend(self.state_manager.handle_content_block_stop(index));
+        }
+
+        if let Some(text) = reasoning.text.as_deref().filter(|t| !t.is_empty()) {
+            self.output_tokens += estimate_tokens(text);
+            let index = self.open_reasoning_block(&mut events);
+            events.push(self.create_thinking_delta_event(index, text));
+        }
+
+        if let Some(signature) = &reasoning.signature {
+            let index = self.open_reasoning_block(&mut events);
+            events.push(self.create_signature_delta_event(index, signature));
+            events.extend(self.state_manager.handle_content_block_stop(index));
+            self.reasoning_block_index = None;
+        }
+
+        events
+    }
+
+    /// 获取当前推理 thinking 块索引，不存在时新建（之前打开的文本块先关闭）
+    fn open_reasoning_block(&mut self, events: &mut Vec<SseEvent>) -> i32 {
+        if let Some(index) = self.reasoning_block_index {
+            return index;
+        }
+        events.extend(self.close_text_block());
+        let index = self.state_manager.next_block_index();
+        self.reasoning_block_index = Some(index);
+        self.thinking_block_index = Some(index);
+        events.extend(self.state_manager.handle_content_block_start(
+            index,
+            "thinking",
+            json!({
+                "type": "content_block_start",
+                "index": index,
+                "content_block": {
+                    "type": "thinking",
+                    "thinking": ""
+                }
+            }),
+        ));
+        index
+    }
+
+    /// 关闭未收到签名的推理 thinking 块（后续出现文本、工具调用或流结束时）
+    fn close_reasoning_block(&mut self) -> Vec<SseEvent> {
+        self.reasoning_block_index
+            .take()
+            .and_then(|index| self.state_manager.handle_content_block_stop(index))
+            .into_iter()
+            .collect()
+    }
+
+    /// 关闭当前打开的文本块，保证块按索引顺序依次结束
+    fn close_text_block(&mut self) -> Vec<SseEvent> {
+        match self.text_block_index.take() {
+            Some(index) if self.state_manager.is_block_open_of_type(index, "text") => self
+                .state_manager
+                .handle_content_block_stop(index)
+                .into_iter()
+                .collect(),
+            _ => Vec::new(),
+        }
+    }
+
     /// 处理工具使用事件
     fn process_tool_use(
         &mut self,
         tool_use: &crate::kiro::model::events::ToolUseEvent,
     ) -> Vec<SseEvent> {
-        let mut events = Vec::new();
+        let mut events = self.flush_prefill();
+        events.extend(self.close_reasoning_block());
 
         self.state_manager.set_has_tool_use(true);
 
@@ -976,6 +1237,7 @@ impl StreamContext {
             let buffered = std::mem::take(&mut self.thinking_buffer);
             events.extend(self.create_text_delta_events(&buffered));
         }
+        events.extend(self.flush_output_filter());
 
         // 获取或分配块索引
         let block_index = if let Some(&idx) = self.tool_block_indices.get(&tool_use.tool_use_id) {
@@ -1013,7 +1275,7 @@ impl StreamContext {
 
         // 发送参数增量 (ToolUseEvent.input 是 String 类型)
         if !tool_use.input.is_empty() {
-            self.output_tokens += (tool_use.input.len() as i32 + 3) / 4; // 估算 token
+            self.output_tokens += estimate_tokens(&tool_use.input);
 
             if let Some(delta_event) = self.state_manager.handle_content_block_delta(
                 block_index,
@@ -1040,9 +1302,15 @@ impl StreamContext {
         events
     }
 
+    /// 标记流因超过请求时限而中止
+    pub fn mark_timed_out(&mut self) {
+        self.timed_out = true;
+    }
+
     /// 生成最终事件序列
     pub fn generate_final_events(&mut self) -> Vec<SseEvent> {
-        let mut events = Vec::new();
+        let mut events = self.close_reasoning_block();
+        events.extend(self.flush_prefill());
 
         // Flush thinking_buffer 中的剩余内容
         if self.thinking_enabled && !self.thinking_buffer.is_empty() {
@@ -1105,6 +1373,7 @@ impl StreamContext {
             }
             self.thinking_buffer.clear();
         }
+        events.extend(self.flush_output_filter());
 
         // 如果整个流中只产生了 thinking 块，没有 text 也没有 tool_use，
         // 则设置 stop_reason 为 max_tokens（表示模型耗尽了 token 预算在思考上），
@@ -1114,12 +1383,35 @@ impl StreamContext {
             && !self.state_manager.has_non_thinking_blocks()
         {
             self.state_manager.set_stop_reason("max_tokens");
-            events.extend(self.create_text_delta_events(" "));
+            events.extend(self.emit_text_delta_events(" "));
         }
 
         // 使用从 contextUsageEvent 计算的 input_tokens，如果没有则使用估算值
         let final_input_tokens = self.context_input_tokens.unwrap_or(self.input_tokens);
 
+        if let Some(callback) = self.usage_callback.take() {
+            callback(final_input_tokens, self.output_tokens);
+        }
+
+        // 超过请求时限：以终止性 error 事件结束
+        if self.timed_out {
+            events.push(create_timeout_error_event());
+            return events;
+        }
+
+        // 流中途被上游限流：以终止性 error 事件结束，携带建议的重试等待时间
+        if let Some(message) = self.throttled.take() {
+            let retry_after = self
+                .retry_after_hint
+                .unwrap_or(DEFAULT_THROTTLE_RETRY_AFTER_SECS);
+            tracing::warn!("流被上游限流中断，建议客户端 {} 秒后重试", retry_after);
+            events.push(create_throttle_error_event(
+                &format!("Upstream throttled the request: {}", message),
+                retry_after,
+            ));
+            return events;
+        }
+
         // 生成最终事件
         events.extend(
             self.state_manager
@@ -1168,6 +1460,41 @@ impl BufferedStreamContext {
         }
     }
 
+    /// 指定消息 ID（默认随机生成）
+    pub fn with_message_id(mut self, message_id: String) -> Self {
+        self.inner.message_id = message_id;
+        self
+    }
+
+    /// 指定流结束时的用量回调
+    pub fn with_usage_callback(mut self, callback: Option<UsageCallback>) -> Self {
+        self.inner.usage_callback = callback;
+        self
+    }
+
+    /// 设置上游 Retry-After 提示（流中途被限流时使用）
+    pub fn with_retry_after_hint(mut self, retry_after: Option<u64>) -> Self {
+        self.inner.retry_after_hint = retry_after;
+        self
+    }
+
+    /// 设置请求末尾的 assistant prefill（响应开头复述的部分会被去除）
+    pub fn with_prefill(mut self, prefill: Option<&str>) -> Self {
+        self.inner.prefill_filter = prefill.map(PrefillFilter::new);
+        self
+    }
+
+    /// 设置请求的停止序列（同时应用当前生效的输出脱敏规则）
+    pub fn with_stop_sequences(mut self, stop_sequences: &[String]) -> Self {
+        self.inner.output_filter = OutputFilter::new(stop_sequences);
+        self
+    }
+
+    /// 标记流因超过请求时限而中止
+    pub fn mark_timed_out(&mut self) {
+        self.inner.mark_timed_out();
+    }
+
     /// 处理 Kiro 事件并缓冲结果
     ///
     /// 复用 StreamContext 的事件处理逻辑，但把结果缓存而不是立即发送。
@@ -1223,25 +1550,9 @@ impl BufferedStreamContext {
     }
 }
 
-/// 简单的 token 估算
+/// 估算增量文本的 token 数（与 count_tokens 使用相同的本地估算规则）
 fn estimate_tokens(text: &str) -> i32 {
-    let chars: Vec<char> = text.chars().collect();
-    let mut chinese_count = 0;
-    let mut other_count = 0;
-
-    for c in &chars {
-        if *c >= '\u{4E00}' && *c <= '\u{9FFF}' {
-            chinese_count += 1;
-        } else {
-            other_count += 1;
-        }
-    }
-
-    // 中文约 1.5 字符/token，英文约 4 字符/token
-    let chinese_tokens = (chinese_count * 2 + 2) / 3;
-    let other_tokens = (other_count + 3) / 4;
-
-    (chinese_tokens + other_tokens).max(1)
+    (crate::token::count_tokens(text) as i32).max(1)
 }
 
 #[cfg(test)]
@@ -1271,6 +1582,117 @@ mod tests {
         assert!(event.is_none());
     }
 
+    #[test]
+    fn test_message_start_usage_includes_cache_fields() {
+        let ctx = StreamContext::new_with_thinking("test-model", 42, false, HashMap::new());
+        let event = ctx.create_message_start_event();
+        let usage = &event["message"]["usage"];
+        assert_eq!(usage["input_tokens"], 42);
+        assert_eq!(usage["cache_creation_input_tokens"], 0);
+        assert_eq!(usage["cache_read_input_tokens"], 0);
+    }
+
+    #[test]
+    fn test_throttling_exception_ends_with_error_event() {
+        let mut ctx = StreamContext::new_with_thinking("test-model", 1, false, HashMap::new());
+        ctx.retry_after_hint = Some(12);
+        let _ = ctx.generate_initial_events();
+        ctx.process_kiro_event(&Event::Exception {
+            exception_type: "ThrottlingException".to_string(),
+            message: "Too many requests".to_string(),
+        });
+
+        let events = ctx.generate_final_events();
+        let last = events.last().unwrap();
+        assert_eq!(last.event, "error");
+        assert_eq!(last.data["error"]["type"], "rate_limit_error");
+        assert_eq!(last.data["error"]["retry_after"], 12);
+        assert!(!events.iter().any(|e| e.event == "message_stop"));
+    }
+
+    #[test]
+    fn test_unknown_event_text_becomes_text_delta() {
+        let mut ctx = StreamContext::new_with_thinking("test-model", 1, false, HashMap::new());
+        let _ = ctx.generate_initial_events();
+        let events = ctx.process_kiro_event(&Event::Unknown { text: None });
+        assert!(events.is_empty());
+
+        let events = ctx.process_kiro_event(&Event::Unknown {
+            text: Some("Hello".to_string()),
+        });
+        let delta = events
+            .iter()
+            .find(|e| e.event == "content_block_delta")
+            .unwrap();
+        assert_eq!(delta.data["delta"]["text"], "Hello");
+    }
+
+    #[test]
+    fn test_stop_sequence_truncates_stream() {
+        let mut ctx = StreamContext::new_with_thinking("test-model", 1, false, HashMap::new());
+        ctx.output_filter = OutputFilter::new(&["STOP".to_string()]);
+        let _ = ctx.generate_initial_events();
+
+        let mut events = ctx.process_assistant_response("Hello ST");
+        events.extend(ctx.process_assistant_response("OP ignored"));
+        assert!(ctx.stop_sequence_matched());
+        events.extend(ctx.process_kiro_event(&Event::ToolUse(
+            crate::kiro::model::events::ToolUseEvent {
+                name: "test_tool".to_string(),
+                tool_use_id: "tool_1".to_string(),
+                input: "{}".to_string(),
+                stop: true,
+                ..Default::default()
+            },
+        )));
+        assert_eq!(collect_text_content(&events), "Hello ");
+        assert!(
+            !events
+                .iter()
+                .any(|e| e.data["content_block"]["type"] == "tool_use")
+        );
+
+        let final_events = ctx.generate_final_events();
+        let delta = final_events
+            .iter()
+            .find(|e| e.event == "message_delta")
+            .unwrap();
+        assert_eq!(delta.data["delta"]["stop_reason"], "stop_sequence");
+        assert_eq!(delta.data["delta"]["stop_sequence"], "STOP");
+    }
+
+    #[test]
+    fn test_timeout_ends_with_error_event() {
+        let mut ctx = StreamContext::new_with_thinking("test-model", 1, false, HashMap::new());
+        let _ = ctx.generate_initial_events();
+        let _ = ctx.process_assistant_response("partial");
+        ctx.mark_timed_out();
+
+        let events = ctx.generate_final_events();
+        assert_eq!(events.last().unwrap().event, "error");
+        assert_eq!(
+            events.last().unwrap().data["error"]["type"],
+            "timeout_error"
+        );
+        assert!(!events.iter().any(|e| e.event == "message_stop"));
+    }
+
+    #[test]
+    fn test_throttling_error_uses_default_retry_after() {
+        let mut ctx = StreamContext::new_with_thinking("test-model", 1, false, HashMap::new());
+        let _ = ctx.generate_initial_events();
+        ctx.process_kiro_event(&Event::Error {
+            error_code: "ThrottlingException".to_string(),
+            error_message: "slow down".to_string(),
+        });
+
+        let events = ctx.generate_final_events();
+        assert_eq!(
+            events.last().unwrap().data["error"]["retry_after"],
+            DEFAULT_THROTTLE_RETRY_AFTER_SECS
+        );
+    }
+
     #[test]
     fn test_sse_state_manager_block_lifecycle() {
         let mut manager = SseStateManager::new();
@@ -1308,6 +1730,7 @@ mod tests {
             tool_use_id: "toolu_01".to_string(),
             input: r#"{"key":"value"}"#.to_string(),
             stop: true,
+            ..Default::default()
         });
 
         let events = ctx.process_kiro_event(&tool_event);
@@ -1343,6 +1766,7 @@ mod tests {
             tool_use_id: "tool_1".to_string(),
             input: "{}".to_string(),
             stop: false,
+            ..Default::default()
         });
         assert!(
             tool_events.iter().any(|e| {
@@ -1405,6 +1829,7 @@ mod tests {
             tool_use_id: "tool_1".to_string(),
             input: "{}".to_string(),
             stop: false,
+            ..Default::default()
         });
 
         let text_start_index = events.iter().find_map(|e| {
@@ -1604,6 +2029,7 @@ mod tests {
             tool_use_id: "tool_1".to_string(),
             input: "{}".to_string(),
             stop: false,
+            ..Default::default()
         });
         all_events.extend(tool_events);
 
@@ -1973,6 +2399,7 @@ mod tests {
             tool_use_id: "tool_1".to_string(),
             input: "{}".to_string(),
             stop: true,
+            ..Default::default()
         }));
         all_events.extend(ctx.generate_final_events());
 
@@ -1986,4 +2413,313 @@ mod tests {
             "stop_reason should be tool_use when tool_use is present"
         );
     }
+
+    fn reasoning(text: Option<&str>, signature: Option<&str>) -> ReasoningContentEvent {
+        ReasoningContentEvent {
+            text: text.map(str::to_string),
+            signature: signature.map(str::to_string),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_reasoning_events_emit_thinking_and_signature_deltas() {
+        let mut ctx = StreamContext::new_with_thinking("test-model", 1, true, HashMap::new());
+        let _initial_events = ctx.generate_initial_events();
+
+        let mut all_events = Vec::new();
+        all_events.extend(ctx.process_reasoning(&ReasoningContentEvent {
+            redacted_content: Some("opaque".to_string()),
+            ..Default::default()
+        }));
+        all_events.extend(ctx.process_reasoning(&reasoning(Some("Let me "), None)));
+        all_events.extend(ctx.process_reasoning(&reasoning(Some("think."), None)));
+        all_events.extend(ctx.process_reasoning(&reasoning(None, Some("sig-1"))));
+        all_events.extend(ctx.process_assistant_response("Answer"));
+        all_events.extend(ctx.generate_final_events());
+
+        let blocks: Vec<(i64, String)> = all_events
+            .iter()
+            .filter(|e| e.event == "content_block_start")
+            .map(|e| {
+                (
+                    e.data["index"].as_i64().unwrap(),
+                    e.data["content_block"]["type"].as_str().unwrap().to_string(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            blocks,
+            vec![
+                (0, "redacted_thinking".to_string()),
+                (1, "thinking".to_string()),
+                (2, "text".to_string())
+            ]
+        );
+        assert_eq!(all_events[0].data["content_block"]["data"], "opaque");
+        assert_eq!(collect_thinking_content(&all_events), "Let me think.");
+        assert_eq!(collect_text_content(&all_events), "Answer");
+
+        // signature_delta 紧接在 thinking 块的 content_block_stop 之前
+        let signature_pos = all_events
+            .iter()
+            .position(|e| e.data["delta"]["type"] == "signature_delta")
+            .expect("should emit signature_delta");
+        assert_eq!(all_events[signature_pos].data["index"], 1);
+        assert_eq!(all_events[signature_pos].data["delta"]["signature"], "sig-1");
+        assert_eq!(all_events[signature_pos + 1].event, "content_block_stop");
+        assert_eq!(all_events[signature_pos + 1].data["index"], 1);
+
+        let message_delta = all_events
+            .iter()
+            .find(|e| e.event == "message_delta")
+            .unwrap();
+        assert_eq!(message_delta.data["delta"]["stop_reason"], "end_turn");
+    }
+
+    #[test]
+    fn test_unsigned_reasoning_block_closed_before_tool_use() {
+        let mut ctx = StreamContext::new_with_thinking("test-model", 1, true, HashMap::new());
+        let _initial_events = ctx.generate_initial_events();
+
+        let mut all_events = ctx.process_reasoning(&reasoning(Some("plan"), None));
+        all_events.extend(ctx.process_tool_use(&crate::kiro::model::events::ToolUseEvent {
+            name: "test_tool".to_string(),
+            tool_use_id: "tool_1".to_string(),
+            input: "{}".to_string(),
+            stop: true,
+            ..Default::default()
+        }));
+
+        let stop_pos = all_events
+            .iter()
+            .position(|e| e.event == "content_block_stop" && e.data["index"] == 0)
+            .expect("thinking block should be stopped");
+        let tool_start_pos = all_events
+            .iter()
+            .position(|e| {
+                e.event == "content_block_start" && e.data["content_block"]["type"] == "tool_use"
+            })
+            .unwrap();
+        assert!(stop_pos < tool_start_pos);
+        assert_eq!(all_events[tool_start_pos].data["index"], 1);
+    }
+
+    #[test]
+    fn test_reasoning_dropped_when_thinking_disabled() {
+        let mut ctx = StreamContext::new_with_thinking("test-model", 1, false, HashMap::new());
+        let _initial_events = ctx.generate_initial_events();
+        assert!(
+            ctx.process_reasoning(&reasoning(Some("hidden"), Some("sig")))
+                .is_empty()
+        );
+    }
+
+    // 录制自官方 API 的流式响应（省略 ping 与部分 delta）
+    const OFFICIAL_TEXT_TRACE: &str = r#"event: message_start
+data: {"type":"message_start","message":{"id":"msg_01XFDUDYJgAACzvnptvVoYEL","type":"message","role":"assistant","content":[],"model":"claude-sonnet-4-5-20250929","stop_reason":null,"stop_sequence":null,"usage":{"input_tokens":25,"cache_creation_input_tokens":0,"cache_read_input_tokens":0,"output_tokens":1}}}
+
+event: content_block_start
+data: {"type":"content_block_start","index":0,"content_block":{"type":"text","text":""}}
+
+event: ping
+data: {"type": "ping"}
+
+event: content_block_delta
+data: {"type":"content_block_delta","index":0,"delta":{"type":"text_delta","text":"Hello!"}}
+
+event: content_block_stop
+data: {"type":"content_block_stop","index":0}
+
+event: message_delta
+data: {"type":"message_delta","delta":{"stop_reason":"end_turn","stop_sequence":null},"usage":{"input_tokens":25,"cache_creation_input_tokens":0,"cache_read_input_tokens":0,"output_tokens":15}}
+
+event: message_stop
+data: {"type":"message_stop"}
+
+"#;
+
+    const OFFICIAL_TOOL_USE_TRACE: &str = r#"event: message_start
+data: {"type":"message_start","message":{"id":"msg_014p7gG3wDgGV9EUtLvnow3U","type":"message","role":"assistant","content":[],"model":"claude-sonnet-4-5-20250929","stop_reason":null,"stop_sequence":null,"usage":{"input_tokens":472,"cache_creation_input_tokens":0,"cache_read_input_tokens":0,"output_tokens":2}}}
+
+event: content_block_start
+data: {"type":"content_block_start","index":0,"content_block":{"type":"text","text":""}}
+
+event: content_block_delta
+data: {"type":"content_block_delta","index":0,"delta":{"type":"text_delta","text":"Let me check the weather."}}
+
+event: content_block_stop
+data: {"type":"content_block_stop","index":0}
+
+event: content_block_start
+data: {"type":"content_block_start","index":1,"content_block":{"type":"tool_use","id":"toolu_01T1x1fJ34qAmk2tNTrN7Up6","name":"get_weather","input":{}}}
+
+event: content_block_delta
+data: {"type":"content_block_delta","index":1,"delta":{"type":"input_json_delta","partial_json":"{\"location\": \"San Francisco, CA\"}"}}
+
+event: content_block_stop
+data: {"type":"content_block_stop","index":1}
+
+event: message_delta
+data: {"type":"message_delta","delta":{"stop_reason":"tool_use","stop_sequence":null},"usage":{"input_tokens":472,"cache_creation_input_tokens":0,"cache_read_input_tokens":0,"output_tokens":89}}
+
+event: message_stop
+data: {"type":"message_stop"}
+
+"#;
+
+    const OFFICIAL_MAX_TOKENS_TRACE: &str = r#"event: message_start
+data: {"type":"message_start","message":{"id":"msg_01Aq9w938a90dw8q","type":"message","role":"assistant","content":[],"model":"claude-sonnet-4-5-20250929","stop_reason":null,"stop_sequence":null,"usage":{"input_tokens":12,"cache_creation_input_tokens":0,"cache_read_input_tokens":0,"output_tokens":1}}}
+
+event: content_block_start
+data: {"type":"content_block_start","index":0,"content_block":{"type":"text","text":""}}
+
+event: content_block_delta
+data: {"type":"content_block_delta","index":0,"delta":{"type":"text_delta","text":"Once upon a time"}}
+
+event: content_block_stop
+data: {"type":"content_block_stop","index":0}
+
+event: message_delta
+data: {"type":"message_delta","delta":{"stop_reason":"max_tokens","stop_sequence":null},"usage":{"input_tokens":12,"cache_creation_input_tokens":0,"cache_read_input_tokens":0,"output_tokens":5}}
+
+event: message_stop
+data: {"type":"message_stop"}
+
+"#;
+
+    /// 解析录制的 SSE 文本（忽略 ping）
+    fn parse_trace(trace: &str) -> Vec<SseEvent> {
+        trace
+            .split("\n\n")
+            .filter_map(|chunk| {
+                let event = chunk.lines().find_map(|l| l.strip_prefix("event: "))?;
+                let data = chunk.lines().find_map(|l| l.strip_prefix("data: "))?;
+                (event != "ping").then(|| SseEvent::new(event, serde_json::from_str(data).unwrap()))
+            })
+            .collect()
+    }
+
+    /// 事件序列的结构：(事件名, 块索引, 块/增量类型)
+    fn shape(events: &[SseEvent]) -> Vec<(String, Option<i64>, Option<String>)> {
+        events
+            .iter()
+            .map(|e| {
+                let kind = e.data["content_block"]["type"]
+                    .as_str()
+                    .or(e.data["delta"]["type"].as_str());
+                (
+                    e.event.clone(),
+                    e.data["index"].as_i64(),
+                    kind.map(str::to_string),
+                )
+            })
+            .collect()
+    }
+
+    /// 官方事件中的每个字段都应出现在生成的事件中，且 JSON 类型一致
+    fn assert_fields_match(official: &serde_json::Value, ours: &serde_json::Value, path: &str) {
+        use serde_json::Value;
+        match (official, ours) {
+            (Value::Object(expected), Value::Object(actual)) => {
+                for (key, value) in expected {
+                    let actual = actual
+                        .get(key)
+                        .unwrap_or_else(|| panic!("missing field {}.{}", path, key));
+                    assert_fields_match(value, actual, &format!("{}.{}", path, key));
+                }
+            }
+            (Value::Null, Value::Null)
+            | (Value::Bool(_), Value::Bool(_))
+            | (Value::Number(_), Value::Number(_))
+            | (Value::String(_), Value::String(_))
+            | (Value::Array(_), Value::Array(_)) => {}
+            _ => panic!("field {} type mismatch: {} vs {}", path, official, ours),
+        }
+    }
+
+    /// 对照官方录制：事件序列结构、终止事件字段与 stop_reason 一致
+    fn assert_conforms(trace: &str, ours: &[SseEvent]) {
+        let official = parse_trace(trace);
+        assert_eq!(shape(ours), shape(&official));
+        for (expected, actual) in official.iter().zip(ours) {
+            if matches!(
+                expected.event.as_str(),
+                "message_start" | "message_delta" | "message_stop" | "content_block_stop"
+            ) {
+                assert_fields_match(&expected.data, &actual.data, &expected.event);
+            }
+        }
+        let stop_reason = |events: &[SseEvent]| {
+            events
+                .iter()
+                .find(|e| e.event == "message_delta")
+                .map(|e| e.data["delta"]["stop_reason"].clone())
+        };
+        assert_eq!(stop_reason(ours), stop_reason(&official));
+    }
+
+    #[test]
+    fn test_conformance_text_end_turn() {
+        let mut ctx = StreamContext::new_with_thinking("test-model", 25, false, HashMap::new());
+        let mut events = ctx.generate_initial_events();
+        events.extend(ctx.process_assistant_response("Hello!"));
+        events.extend(ctx.generate_final_events());
+        assert_conforms(OFFICIAL_TEXT_TRACE, &events);
+    }
+
+    #[test]
+    fn test_conformance_tool_use() {
+        let mut ctx = StreamContext::new_with_thinking("test-model", 472, false, HashMap::new());
+        let mut events = ctx.generate_initial_events();
+        events.extend(ctx.process_assistant_response("Let me check the weather."));
+        events.extend(
+            ctx.process_tool_use(&crate::kiro::model::events::ToolUseEvent {
+                name: "get_weather".to_string(),
+                tool_use_id: "toolu_01".to_string(),
+                input: r#"{"location": "San Francisco, CA"}"#.to_string(),
+                stop: true,
+                ..Default::default()
+            }),
+        );
+        events.extend(ctx.generate_final_events());
+        assert_conforms(OFFICIAL_TOOL_USE_TRACE, &events);
+    }
+
+    #[test]
+    fn test_conformance_max_tokens() {
+        let mut ctx = StreamContext::new_with_thinking("test-model", 12, false, HashMap::new());
+        let mut events = ctx.generate_initial_events();
+        events.extend(ctx.process_assistant_response("Once upon a time"));
+        events.extend(ctx.process_kiro_event(&Event::Exception {
+            exception_type: "ContentLengthExceededException".to_string(),
+            message: "Output exceeded the limit".to_string(),
+        }));
+        events.extend(ctx.generate_final_events());
+        assert_conforms(OFFICIAL_MAX_TOKENS_TRACE, &events);
+    }
+
+    #[test]
+    fn test_final_events_close_blocks_in_index_order() {
+        let mut ctx = StreamContext::new_with_thinking("test-model", 1, false, HashMap::new());
+        let _ = ctx.generate_initial_events();
+        for id in ["tool_a", "tool_b", "tool_c"] {
+            let _ = ctx.process_tool_use(&crate::kiro::model::events::ToolUseEvent {
+                name: "test_tool".to_string(),
+                tool_use_id: id.to_string(),
+                input: "{}".to_string(),
+                stop: false,
+                ..Default::default()
+            });
+        }
+        let events = ctx.generate_final_events();
+        let stops: Vec<i64> = events
+            .iter()
+            .filter(|e| e.event == "content_block_stop")
+            .filter_map(|e| e.data["index"].as_i64())
+            .collect();
+        assert_eq!(stops, vec![1, 2, 3]);
+        assert_eq!(events[events.len() - 2].event, "message_delta");
+        assert_eq!(events[events.len() - 1].event, "message_stop");
+    }
 }
@@ -0,0 +1,183 @@
+//! 按模型注入 system 提示
+//!
+//! Kiro 会注入自身的代理行为（如拒绝非编码任务），`systemPrompts` 配置的前缀/后缀
+//! 在请求转换前加入 system 提示，用于抵消这些行为。规则可通过 Admin API 在运行时
+//! 修改，修改结果会持久化到配置文件。
+
+use std::path::Path;
+
+use anyhow::Context;
+use axum::http::{HeaderMap, header};
+use parking_lot::RwLock;
+
+use crate::model::config::{Config, SystemPromptRule};
+
+use super::types::{MessagesRequest, SystemMessage};
+
+static RULES: RwLock<Vec<SystemPromptRule>> = RwLock::new(Vec::new());
+
+/// 替换当前生效的规则（启动与配置热重载时调用）
+pub fn init(rules: Vec<SystemPromptRule>) {
+    *RULES.write() = rules;
+}
+
+/// 获取当前生效的规则
+pub fn rules() -> Vec<SystemPromptRule> {
+    RULES.read().clone()
+}
+
+/// 替换当前生效的规则并持久化到配置文件，持久化失败时恢复原规则
+///
+/// 配置文件路径未知时仅在当前进程生效
+pub fn set_rules(rules: Vec<SystemPromptRule>, config_path: Option<&Path>) -> anyhow::Result<()> {
+    let previous = std::mem::replace(&mut *RULES.write(), rules.clone());
+
+    let Some(config_path) = config_path else {
+        tracing::warn!("配置文件路径未知，system 提示规则仅在当前进程生效");
+        return Ok(());
+    };
+    let persisted = Config::load(config_path)
+        .with_context(|| format!("重新加载配置失败: {}", config_path.display()))
+        .and_then(|mut config| {
+            config.system_prompts = rules;
+            config
+                .save()
+                .with_context(|| format!("持久化 system 提示规则失败: {}", config_path.display()))
+        });
+    if let Err(err) = persisted {
+        *RULES.write() = previous;
+        return Err(err);
+    }
+
+    tracing::info!("system 提示规则已更新");
+    Ok(())
+}
+
+/// 对请求应用当前生效的规则，返回生效的规则序号
+pub fn apply(headers: &HeaderMap, payload: &mut MessagesRequest) -> Vec<usize> {
+    apply_rules(&RULES.read(), headers, payload)
+}
+
+fn apply_rules(
+    rules: &[SystemPromptRule],
+    headers: &HeaderMap,
+    payload: &mut MessagesRequest,
+) -> Vec<usize> {
+    let model = payload.model.to_lowercase();
+    let user_agent = headers
+        .get(header::USER_AGENT)
+        .and_then(|v| v.to_str().ok())
+        .unwrap_or_default()
+        .to_lowercase();
+
+    let mut applied = Vec::new();
+    let mut prefixes = Vec::new();
+    for (index, rule) in rules.iter().enumerate() {
+        if !matches(rule, &model, &user_agent) {
+            continue;
+        }
+        applied.push(index);
+        if let Some(text) = &rule.prefix {
+            prefixes.push(SystemMessage { text: text.clone() });
+        }
+        if let Some(text) = &rule.suffix {
+            payload
+                .system
+                .get_or_insert_with(Vec::new)
+                .push(SystemMessage { text: text.clone() });
+        }
+    }
+    if !prefixes.is_empty() {
+        payload
+            .system
+            .get_or_insert_with(Vec::new)
+            .splice(0..0, prefixes);
+    }
+    applied
+}
+
+fn matches(rule: &SystemPromptRule, model: &str, user_agent: &str) -> bool {
+    let model_matches = rule.models.is_empty()
+        || rule.models.iter().any(|pattern| {
+            let pattern = pattern.to_lowercase();
+            match pattern.strip_suffix('*') {
+                Some(prefix) => model.starts_with(prefix),
+                None => model == pattern,
+            }
+        });
+    let user_agent_matches = rule
+        .user_agent
+        .as_ref()
+        .is_none_or(|ua| user_agent.contains(&ua.to_lowercase()));
+    model_matches && user_agent_matches
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::http::HeaderValue;
+
+    fn request(model: &str) -> MessagesRequest {
+        serde_json::from_value(serde_json::json!({
+            "model": model,
+            "max_tokens": 1024,
+            "system": "original",
+            "messages": [{"role": "user", "content": "hi"}]
+        }))
+        .unwrap()
+    }
+
+    fn texts(payload: &MessagesRequest) -> Vec<&str> {
+        payload
+            .system
+            .iter()
+            .flatten()
+            .map(|m| m.text.as_str())
+            .collect()
+    }
+
+    #[test]
+    fn test_prefix_and_suffix_in_rule_order() {
+        let rules = vec![
+            SystemPromptRule {
+                models: vec!["claude-sonnet-*".to_string()],
+                prefix: Some("p1".to_string()),
+                suffix: Some("s1".to_string()),
+                ..Default::default()
+            },
+            SystemPromptRule {
+                prefix: Some("p2".to_string()),
+                ..Default::default()
+            },
+            SystemPromptRule {
+                models: vec!["claude-opus-4-6".to_string()],
+                suffix: Some("never".to_string()),
+                ..Default::default()
+            },
+        ];
+        let mut payload = request("Claude-Sonnet-4-5");
+        let applied = apply_rules(&rules, &HeaderMap::new(), &mut payload);
+        assert_eq!(applied, vec![0, 1]);
+        assert_eq!(texts(&payload), vec!["p1", "p2", "original", "s1"]);
+    }
+
+    #[test]
+    fn test_user_agent_filter() {
+        let rules = vec![SystemPromptRule {
+            user_agent: Some("claude-cli".to_string()),
+            suffix: Some("s".to_string()),
+            ..Default::default()
+        }];
+        let mut payload = request("claude-sonnet-4-5");
+        assert!(apply_rules(&rules, &HeaderMap::new(), &mut payload).is_empty());
+        assert_eq!(texts(&payload), vec!["original"]);
+
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            header::USER_AGENT,
+            HeaderValue::from_static("Claude-CLI/2.0 (external)"),
+        );
+        assert_eq!(apply_rules(&rules, &headers, &mut payload), vec![0]);
+        assert_eq!(texts(&payload), vec!["original", "s"]);
+    }
+}
@@ -0,0 +1,169 @@
+//! tool_choice 支持
+//!
+//! Kiro 请求没有 tool_choice 字段，转换时通过裁剪工具列表并在当前 user 消息末尾
+//! 附加指令来模拟：
+//! - `auto`（默认）：不做处理
+//! - `any`：要求模型本轮必须调用某个工具
+//! - `tool`：只保留指定的工具，并要求模型调用它
+//! - `none`：移除工具定义，并要求模型不调用工具
+
+use serde_json::Value;
+
+use crate::kiro::model::requests::tool::Tool;
+
+/// 解析后的 tool_choice
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ToolChoice {
+    Auto,
+    Any,
+    Tool(String),
+    None,
+}
+
+impl ToolChoice {
+    /// 解析请求中的 tool_choice，未设置或无法识别时视为 auto
+    pub fn parse(value: Option<&Value>) -> Self {
+        let Some(value) = value else {
+            return Self::Auto;
+        };
+        match value.get("type").and_then(Value::as_str) {
+            Some("any") => Self::Any,
+            Some("none") => Self::None,
+            Some("tool") => match value.get("name").and_then(Value::as_str) {
+                Some(name) if !name.is_empty() => Self::Tool(name.to_string()),
+                _ => {
+                    tracing::warn!("tool_choice 类型为 tool 但缺少 name，按 auto 处理");
+                    Self::Auto
+                }
+            },
+            Some("auto") => Self::Auto,
+            other => {
+                tracing::warn!("无法识别的 tool_choice 类型 {:?}，按 auto 处理", other);
+                Self::Auto
+            }
+        }
+    }
+
+    /// 按 tool_choice 裁剪工具列表，返回需要附加到当前 user 消息末尾的指令
+    ///
+    /// `tool` 模式的名称需为转换后的工具名（超长名称已缩短）；
+    /// 指定的工具不在列表中或没有任何工具时不做处理
+    pub fn apply(&self, tools: &mut Vec<Tool>) -> Option<String> {
+        match self {
+            Self::Auto => None,
+            Self::None => {
+                tools.clear();
+                Some(
+                    "\n\nDo not call any tools in this response. Reply with text only.".to_string(),
+                )
+            }
+            Self::Any => {
+                if tools.is_empty() {
+                    return None;
+                }
+                Some(
+                    "\n\nYou must call at least one of the available tools in this response. \
+                     Do not reply with text only."
+                        .to_string(),
+                )
+            }
+            Self::Tool(name) => {
+                // Kiro 匹配工具名称时忽略大小写
+                let Some(tool) = tools
+                    .iter()
+                    .find(|t| t.tool_specification.name.eq_ignore_ascii_case(name))
+                    .cloned()
+                else {
+                    tracing::warn!(
+                        "tool_choice 指定的工具 {} 不在工具列表中，按 auto 处理",
+                        name
+                    );
+                    return None;
+                };
+                let instruction = format!(
+                    "\n\nYou must call the `{}` tool in this response. \
+                     Do not call any other tool and do not reply with text only.",
+                    tool.tool_specification.name
+                );
+                *tools = vec![tool];
+                Some(instruction)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::kiro::model::requests::tool::{InputSchema, ToolSpecification};
+    use serde_json::json;
+
+    fn tools(names: &[&str]) -> Vec<Tool> {
+        names
+            .iter()
+            .map(|name| Tool {
+                tool_specification: ToolSpecification {
+                    name: name.to_string(),
+                    description: String::new(),
+                    input_schema: InputSchema::from_json(json!({"type": "object"})),
+                },
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(ToolChoice::parse(None), ToolChoice::Auto);
+        assert_eq!(
+            ToolChoice::parse(Some(&json!({"type": "auto"}))),
+            ToolChoice::Auto
+        );
+        assert_eq!(
+            ToolChoice::parse(Some(
+                &json!({"type": "any", "disable_parallel_tool_use": true})
+            )),
+            ToolChoice::Any
+        );
+        assert_eq!(
+            ToolChoice::parse(Some(&json!({"type": "tool", "name": "Bash"}))),
+            ToolChoice::Tool("Bash".to_string())
+        );
+        assert_eq!(
+            ToolChoice::parse(Some(&json!({"type": "tool"}))),
+            ToolChoice::Auto
+        );
+        assert_eq!(
+            ToolChoice::parse(Some(&json!({"type": "none"}))),
+            ToolChoice::None
+        );
+    }
+
+    #[test]
+    fn test_apply_tool_keeps_only_named_tool() {
+        let mut list = tools(&["Read", "Bash"]);
+        let instruction = ToolChoice::Tool("bash".to_string()).apply(&mut list);
+        assert_eq!(list.len(), 1);
+        assert_eq!(list[0].tool_specification.name, "Bash");
+        assert!(instruction.unwrap().contains("`Bash`"));
+
+        // 指定的工具不存在时不做处理
+        let mut list = tools(&["Read"]);
+        assert!(
+            ToolChoice::Tool("Bash".to_string())
+                .apply(&mut list)
+                .is_none()
+        );
+        assert_eq!(list.len(), 1);
+    }
+
+    #[test]
+    fn test_apply_any_and_none() {
+        let mut list = tools(&["Read"]);
+        assert!(ToolChoice::Any.apply(&mut list).is_some());
+        assert_eq!(list.len(), 1);
+        assert!(ToolChoice::Any.apply(&mut Vec::new()).is_none());
+
+        assert!(ToolChoice::None.apply(&mut list).is_some());
+        assert!(list.is_empty());
+    }
+}
@@ -0,0 +1,156 @@
+//! 请求改写
+//!
+//! 在请求转换前按 `requestTransforms` 配置依次评估规则，对匹配的请求
+//! 插入 system 提示、移除工具、改写模型或限制 max_tokens。
+
+use axum::http::{HeaderMap, header};
+
+use crate::model::config::RequestTransform;
+
+use super::types::{MessagesRequest, SystemMessage};
+
+/// 对请求应用所有匹配的改写规则，返回生效的规则序号
+///
+/// 匹配条件以改写前的请求为准，前面规则的模型改写不影响后续规则的匹配
+pub fn apply(
+    rules: &[RequestTransform],
+    headers: &HeaderMap,
+    payload: &mut MessagesRequest,
+) -> Vec<usize> {
+    let model = payload.model.to_lowercase();
+    let user_agent = headers
+        .get(header::USER_AGENT)
+        .and_then(|v| v.to_str().ok())
+        .unwrap_or_default()
+        .to_lowercase();
+
+    let mut applied = Vec::new();
+    for (index, rule) in rules.iter().enumerate() {
+        if matches(rule, &model, &user_agent) {
+            apply_rule(rule, payload);
+            applied.push(index);
+        }
+    }
+    applied
+}
+
+fn matches(rule: &RequestTransform, model: &str, user_agent: &str) -> bool {
+    let model_matches = rule.models.is_empty()
+        || rule.models.iter().any(|pattern| {
+            let pattern = pattern.to_lowercase();
+            match pattern.strip_suffix('*') {
+                Some(prefix) => model.starts_with(prefix),
+                None => model == pattern,
+            }
+        });
+    let user_agent_matches = rule
+        .user_agent
+        .as_ref()
+        .is_none_or(|ua| user_agent.contains(&ua.to_lowercase()));
+    model_matches && user_agent_matches
+}
+
+fn apply_rule(rule: &RequestTransform, payload: &mut MessagesRequest) {
+    if let Some(text) = &rule.prepend_system {
+        payload
+            .system
+            .get_or_insert_with(Vec::new)
+            .insert(0, SystemMessage { text: text.clone() });
+    }
+
+    if !rule.drop_tools.is_empty() {
+        if let Some(tools) = &mut payload.tools {
+            tools.retain(|t| !rule.drop_tools.contains(&t.name));
+        }
+        // tool_choice 指定的工具被移除时回退为默认行为
+        let forced = payload
+            .tool_choice
+            .as_ref()
+            .and_then(|c| c.get("name"))
+            .and_then(|v| v.as_str());
+        if forced.is_some_and(|name| rule.drop_tools.iter().any(|t| t == name)) {
+            payload.tool_choice = None;
+        }
+    }
+
+    if let Some(model) = &rule.rewrite_model {
+        payload.model = model.clone();
+    }
+
+    if let Some(max_tokens) = rule.max_tokens {
+        payload.max_tokens = payload.max_tokens.min(max_tokens);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request() -> MessagesRequest {
+        serde_json::from_value(serde_json::json!({
+            "model": "claude-opus-4-6",
+            "max_tokens": 32000,
+            "system": "You are Claude Code.",
+            "messages": [{"role": "user", "content": "hi"}],
+            "tools": [
+                {"name": "Bash", "description": "", "input_schema": {}},
+                {"name": "Read", "description": "", "input_schema": {}}
+            ],
+            "tool_choice": {"type": "tool", "name": "Bash"}
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_apply_matching_rule() {
+        let rules = vec![RequestTransform {
+            models: vec!["claude-opus-*".to_string()],
+            prepend_system: Some("Be brief.".to_string()),
+            drop_tools: vec!["Bash".to_string()],
+            rewrite_model: Some("claude-sonnet-4-6".to_string()),
+            max_tokens: Some(8192),
+            ..Default::default()
+        }];
+        let mut payload = request();
+
+        assert_eq!(apply(&rules, &HeaderMap::new(), &mut payload), vec![0]);
+        let system = payload.system.unwrap();
+        assert_eq!(system[0].text, "Be brief.");
+        assert_eq!(system[1].text, "You are Claude Code.");
+        let tools = payload.tools.unwrap();
+        assert_eq!(tools.len(), 1);
+        assert_eq!(tools[0].name, "Read");
+        assert!(payload.tool_choice.is_none());
+        assert_eq!(payload.model, "claude-sonnet-4-6");
+        assert_eq!(payload.max_tokens, 8192);
+    }
+
+    #[test]
+    fn test_rules_match_on_original_request() {
+        let rules = vec![
+            RequestTransform {
+                models: vec!["claude-opus-4-6".to_string()],
+                rewrite_model: Some("claude-sonnet-4-6".to_string()),
+                ..Default::default()
+            },
+            RequestTransform {
+                models: vec!["claude-sonnet-*".to_string()],
+                max_tokens: Some(1024),
+                ..Default::default()
+            },
+            RequestTransform {
+                user_agent: Some("claude-cli".to_string()),
+                max_tokens: Some(64000),
+                ..Default::default()
+            },
+        ];
+        let mut headers = HeaderMap::new();
+        headers.insert(header::USER_AGENT, "Claude-CLI/2.0".parse().unwrap());
+        let mut payload = request();
+
+        assert_eq!(apply(&rules, &headers, &mut payload), vec![0, 2]);
+        assert_eq!(payload.model, "claude-sonnet-4-6");
+        // 上限只收紧不放宽
+        assert_eq!(payload.max_tokens, 32000);
+    }
+}
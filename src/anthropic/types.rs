@@ -5,9 +5,11 @@ use std::collections::HashMap;
 
 // === 错误响应 ===
 
-/// API 错误响应
+/// API 错误响应（与 Anthropic 官方错误格式一致：`{"type": "error", "error": {...}}`）
 #[derive(Debug, Serialize)]
 pub struct ErrorResponse {
+    #[serde(rename = "type")]
+    pub response_type: &'static str,
     pub error: ErrorDetail,
 }
 
@@ -23,6 +25,7 @@ impl ErrorResponse {
     /// 创建新的错误响应
     pub fn new(error_type: impl Into<String>, message: impl Into<String>) -> Self {
         Self {
+            response_type: "error",
             error: ErrorDetail {
                 error_type: error_type.into(),
                 message: message.into(),
@@ -128,6 +131,8 @@ pub struct MessagesRequest {
     pub output_config: Option<OutputConfig>,
     /// Claude Code 请求中的 metadata，包含 session 信息
     pub metadata: Option<Metadata>,
+    /// 停止序列（Kiro 不支持，由代理在输出中检测并截断）
+    pub stop_sequences: Option<Vec<String>>,
 }
 
 /// 反序列化 system 字段，支持字符串或数组格式
@@ -276,6 +281,12 @@ pub struct CountTokensRequest {
     pub tools: Option<Vec<Tool>>,
 }
 
+/// 写入会话记忆笔记请求
+#[derive(Debug, Deserialize)]
+pub struct SetMemoryNoteRequest {
+    pub value: String,
+}
+
 /// Token 计数响应
 #[derive(Debug, Serialize, Deserialize)]
 pub struct CountTokensResponse {
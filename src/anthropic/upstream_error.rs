@@ -0,0 +1,208 @@
+//! 上游错误映射
+//!
+//! provider 返回的错误为 `"{api_type} API 请求失败: {status} {body}"` 形式的文本，
+//! 这里按 reason 代码与上游状态码，映射为 Anthropic 官方错误类型与状态码，
+//! 使官方 SDK 的重试逻辑（429 / 5xx / 529 重试，4xx 不重试）按预期工作。
+
+use axum::http::StatusCode;
+use serde_json::Value;
+
+/// 额度用尽时建议客户端等待的秒数
+const QUOTA_RETRY_AFTER_SECS: u64 = 60;
+
+/// 映射后的 Anthropic 错误
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MappedError {
+    pub status: StatusCode,
+    pub error_type: &'static str,
+    pub message: String,
+    /// 需要通过 Retry-After 告知客户端的等待秒数
+    pub retry_after: Option<u64>,
+}
+
+impl MappedError {
+    fn new(status: u16, error_type: &'static str, message: impl Into<String>) -> Self {
+        Self {
+            status: StatusCode::from_u16(status).expect("映射表中的状态码均合法"),
+            error_type,
+            message: message.into(),
+            retry_after: None,
+        }
+    }
+
+    fn with_retry_after(mut self, secs: u64) -> Self {
+        self.retry_after = Some(secs);
+        self
+    }
+}
+
+/// 将 provider 错误文本映射为 Anthropic 错误
+pub fn map(err: &str) -> MappedError {
+    let upstream = parse_upstream(err);
+    // reason 代码（如 `{"reason":"INVALID_MODEL_ID"}`）直接在原始文本中匹配
+    let has = |reason: &str| err.contains(reason);
+
+    if has("INVALID_MODEL_ID") {
+        return MappedError::new(
+            400,
+            "invalid_request_error",
+            "The requested model is not available upstream.",
+        );
+    }
+    if has("CONTENT_LENGTH_EXCEEDS_THRESHOLD") {
+        return MappedError::new(
+            400,
+            "invalid_request_error",
+            "Context window is full. Reduce conversation history, system prompt, or tools.",
+        );
+    }
+    if err.contains("Input is too long") {
+        return MappedError::new(
+            400,
+            "invalid_request_error",
+            "Input is too long. Reduce the size of your messages.",
+        );
+    }
+    if is_content_policy(err) {
+        return MappedError::new(
+            400,
+            "invalid_request_error",
+            "Request blocked by the upstream content filtering policy.",
+        );
+    }
+    if has("MONTHLY_REQUEST_COUNT") || has("DAILY_REQUEST_COUNT") {
+        return MappedError::new(
+            429,
+            "rate_limit_error",
+            "Upstream request quota has been exhausted. Please retry later.",
+        )
+        .with_retry_after(QUOTA_RETRY_AFTER_SECS);
+    }
+    if has("INSUFFICIENT_MODEL_CAPACITY") {
+        return MappedError::new(
+            529,
+            "overloaded_error",
+            "Upstream model capacity is insufficient. Please retry later.",
+        );
+    }
+    if has("TEMPORARILY_SUSPENDED") {
+        return MappedError::new(
+            403,
+            "permission_error",
+            "Upstream account is temporarily suspended.",
+        );
+    }
+
+    // 没有可调度的凭据（全部禁用、熔断或不在可用时段）：稍后可能恢复
+    if upstream.status.is_none() && err.contains("凭据") {
+        return MappedError::new(
+            529,
+            "overloaded_error",
+            "No upstream credentials are currently available. Please retry later.",
+        );
+    }
+
+    let message = upstream
+        .message
+        .unwrap_or_else(|| "Upstream API request failed.".to_string());
+    match upstream.status {
+        Some(400) => MappedError::new(400, "invalid_request_error", message),
+        Some(401) => MappedError::new(401, "authentication_error", message),
+        Some(402 | 403) => MappedError::new(403, "permission_error", message),
+        Some(404) => MappedError::new(404, "not_found_error", message),
+        Some(413) => MappedError::new(413, "request_too_large", message),
+        Some(429) => MappedError::new(429, "rate_limit_error", message),
+        _ => MappedError::new(502, "api_error", message),
+    }
+}
+
+/// 从错误文本中解析出的上游响应信息
+#[derive(Debug, Default)]
+struct Upstream {
+    status: Option<u16>,
+    message: Option<String>,
+}
+
+/// 解析 `...: {status} {reason phrase} {body}` 中的状态码与 JSON 错误体
+fn parse_upstream(err: &str) -> Upstream {
+    let Some((status, rest)) = err.match_indices(": ").find_map(|(idx, sep)| {
+        let rest = &err[idx + sep.len()..];
+        let code = rest.get(..3)?.parse::<u16>().ok()?;
+        ((100..600).contains(&code) && rest[3..].starts_with(' ')).then_some((code, &rest[3..]))
+    }) else {
+        return Upstream::default();
+    };
+
+    let message = rest
+        .find('{')
+        .and_then(|idx| serde_json::from_str::<Value>(&rest[idx..]).ok())
+        .and_then(|body| {
+            body.get("message")
+                .or_else(|| body.get("Message"))
+                .and_then(Value::as_str)
+                .map(str::to_string)
+        });
+    Upstream {
+        status: Some(status),
+        message,
+    }
+}
+
+/// 是否为内容安全策略拒绝
+fn is_content_policy(err: &str) -> bool {
+    let lower = err.to_lowercase();
+    [
+        "content_policy",
+        "content policy",
+        "guardrail",
+        "content filter",
+    ]
+    .iter()
+    .any(|marker| lower.contains(marker))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_maps_reason_codes() {
+        let mapped = map(
+            r#"流式 API 请求失败: 400 Bad Request {"message":"Invalid model","reason":"INVALID_MODEL_ID"}"#,
+        );
+        assert_eq!(mapped.status, StatusCode::BAD_REQUEST);
+        assert_eq!(mapped.error_type, "invalid_request_error");
+
+        let mapped = map(
+            r#"流式 API 请求失败（所有凭据已用尽）: 402 Payment Required {"reason":"MONTHLY_REQUEST_COUNT"}"#,
+        );
+        assert_eq!(mapped.status, StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(mapped.error_type, "rate_limit_error");
+        assert_eq!(mapped.retry_after, Some(QUOTA_RETRY_AFTER_SECS));
+
+        let mapped = map(
+            r#"非流式 API 请求失败: 400 Bad Request {"message":"Blocked by guardrail policy"}"#,
+        );
+        assert_eq!(mapped.error_type, "invalid_request_error");
+        assert!(mapped.message.contains("content filtering"));
+    }
+
+    #[test]
+    fn test_maps_by_upstream_status() {
+        let mapped = map(r#"流式 API 请求失败: 403 Forbidden {"message":"Not allowed"}"#);
+        assert_eq!(mapped.status, StatusCode::FORBIDDEN);
+        assert_eq!(mapped.error_type, "permission_error");
+        assert_eq!(mapped.message, "Not allowed");
+
+        let mapped = map("流式 API 请求失败: 500 Internal Server Error oops");
+        assert_eq!(mapped.status, StatusCode::BAD_GATEWAY);
+        assert_eq!(mapped.error_type, "api_error");
+    }
+
+    #[test]
+    fn test_no_credentials_is_overloaded() {
+        let mapped = map("所有凭据均已禁用（0/2）");
+        assert_eq!(mapped.status.as_u16(), 529);
+        assert_eq!(mapped.error_type, "overloaded_error");
+    }
+}
@@ -0,0 +1,623 @@
+//! Token 用量账本
+//!
+//! 以 (API Key, 用户, 凭据, 模型, UTC 日期) 为键累计请求数与输入/输出 tokens，数据来自流式
+//! 结束事件（非流式响应为响应体）中的 usage。账本持久化到凭据文件所在目录的
+//! `kiro_usage_ledger.json`：请求只在内存中累计，后台任务每 [`SAVE_DEBOUNCE`] 把变更原子地
+//! 写入文件，写入失败时下一轮重试。Admin API 提供按日/按月的聚合查询与 CSV 导出，用于月末对账。
+//!
+//! API Key 只保存掩码形式（前 4 位 + 后 4 位），账本文件与导出结果中不含完整 Key。
+//! 用户为请求 metadata.user_id 去掉 session 部分后的标识，未携带时为空。
+
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::path::PathBuf;
+use std::sync::OnceLock;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+
+use chrono::{Days, NaiveDate, Utc};
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+
+use crate::common::atomic_file;
+use crate::kiro::token_manager::mask_api_key;
+use crate::model::config::UsageLedgerConfig;
+
+use super::session_cost;
+
+/// 两次写入账本文件之间的最小间隔
+const SAVE_DEBOUNCE: Duration = Duration::from_secs(30);
+
+static LEDGER: OnceLock<Ledger> = OnceLock::new();
+
+/// 账本键（按日期排序，便于按时间范围查询）
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+struct LedgerKey {
+    day: NaiveDate,
+    api_key: String,
+    user_id: Option<String>,
+    credential_id: Option<u64>,
+    model: String,
+}
+
+/// 累计用量
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UsageTotals {
+    pub requests: u64,
+    pub input_tokens: u64,
+    pub output_tokens: u64,
+}
+
+impl UsageTotals {
+    fn add(&mut self, other: &UsageTotals) {
+        self.requests += other.requests;
+        self.input_tokens += other.input_tokens;
+        self.output_tokens += other.output_tokens;
+    }
+}
+
+/// 账本文件中的一行
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct LedgerRow {
+    day: NaiveDate,
+    api_key: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    user_id: Option<String>,
+    credential_id: Option<u64>,
+    model: String,
+    #[serde(flatten)]
+    totals: UsageTotals,
+}
+
+/// 聚合粒度
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Granularity {
+    #[default]
+    Daily,
+    Monthly,
+}
+
+/// 聚合维度
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum GroupBy {
+    /// 按 (API Key, 用户, 凭据, 模型) 分组
+    #[default]
+    All,
+    /// 只按凭据分组
+    Credential,
+    /// 只按模型分组
+    Model,
+}
+
+/// 用量查询条件
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UsageQuery {
+    #[serde(default)]
+    pub granularity: Granularity,
+    #[serde(default)]
+    pub group_by: GroupBy,
+    /// 起始日期（YYYY-MM-DD，含）
+    pub from: Option<NaiveDate>,
+    /// 截止日期（YYYY-MM-DD，含）
+    pub to: Option<NaiveDate>,
+    pub user_id: Option<String>,
+    pub credential_id: Option<u64>,
+    pub model: Option<String>,
+}
+
+/// 某个周期内 (API Key, 用户, 凭据, 模型) 的聚合用量
+///
+/// 按凭据或模型分组时，不参与分组的维度为 None
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UsageAggregate {
+    /// 日期（YYYY-MM-DD）或月份（YYYY-MM）
+    pub period: String,
+    pub api_key: Option<String>,
+    pub user_id: Option<String>,
+    pub credential_id: Option<u64>,
+    pub model: Option<String>,
+    #[serde(flatten)]
+    pub totals: UsageTotals,
+    /// 按价格表（modelPrices 或内置的 Anthropic 标价）估算的费用（美元），仅供参考
+    pub cost_usd: f64,
+}
+
+/// 用量查询结果
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UsageReport {
+    pub enabled: bool,
+    pub granularity: Granularity,
+    pub group_by: GroupBy,
+    pub rows: Vec<UsageAggregate>,
+    pub total: UsageTotals,
+    pub total_cost_usd: f64,
+}
+
+struct Ledger {
+    config: UsageLedgerConfig,
+    rows: Mutex<BTreeMap<LedgerKey, UsageTotals>>,
+    path: Option<PathBuf>,
+    dirty: AtomicBool,
+}
+
+/// 初始化用量账本（未配置时不做任何事），从持久化文件加载已有记录并启动后台写入任务
+///
+/// 账本文件无法解析时备份后以空账本启动；无法读取时不再写入该文件，避免覆盖已有记录
+pub fn init(config: Option<&UsageLedgerConfig>, path: Option<PathBuf>) {
+    let Some(config) = config else {
+        return;
+    };
+    let (rows, path): (Vec<LedgerRow>, _) = match path {
+        Some(path) => match atomic_file::load_json(&path) {
+            Ok(rows) => (rows.unwrap_or_default(), Some(path)),
+            Err(e) => {
+                tracing::error!(
+                    "读取用量账本 {} 失败，本次运行不会写入账本文件: {}",
+                    path.display(),
+                    e
+                );
+                (Vec::new(), None)
+            }
+        },
+        None => (Vec::new(), None),
+    };
+    let ledger = Ledger {
+        config: *config,
+        rows: Mutex::new(rows.into_iter().map(LedgerRow::into_entry).collect()),
+        path,
+        dirty: AtomicBool::new(false),
+    };
+    if LEDGER.set(ledger).is_ok() {
+        tokio::spawn(async {
+            let mut interval = tokio::time::interval(SAVE_DEBOUNCE);
+            loop {
+                interval.tick().await;
+                if let Some(ledger) = LEDGER.get()
+                    && ledger.dirty.load(Ordering::Relaxed)
+                {
+                    // 序列化与写入整个账本是阻塞操作，不占用 runtime 工作线程
+                    let _ = tokio::task::spawn_blocking(|| ledger.save()).await;
+                }
+            }
+        });
+    }
+}
+
+/// 是否启用了用量账本
+pub fn is_enabled() -> bool {
+    LEDGER.get().is_some()
+}
+
+/// 记录一次请求的用量（未启用时不做任何事）
+pub fn record(
+    api_key: &str,
+    user_id: Option<&str>,
+    credential_id: Option<u64>,
+    model: &str,
+    input_tokens: u64,
+    output_tokens: u64,
+) {
+    let Some(ledger) = LEDGER.get() else {
+        return;
+    };
+    let key = LedgerKey {
+        day: Utc::now().date_naive(),
+        api_key: mask_api_key(api_key),
+        user_id: user_id.map(str::to_string),
+        credential_id,
+        model: model.to_string(),
+    };
+    add(
+        &mut ledger.rows.lock(),
+        key,
+        &UsageTotals {
+            requests: 1,
+            input_tokens,
+            output_tokens,
+        },
+    );
+    ledger.dirty.store(true, Ordering::Relaxed);
+}
+
+fn add(rows: &mut BTreeMap<LedgerKey, UsageTotals>, key: LedgerKey, totals: &UsageTotals) {
+    rows.entry(key).or_default().add(totals);
+}
+
+impl LedgerRow {
+    fn into_entry(self) -> (LedgerKey, UsageTotals) {
+        let key = LedgerKey {
+            day: self.day,
+            api_key: self.api_key,
+            user_id: self.user_id,
+            credential_id: self.credential_id,
+            model: self.model,
+        };
+        (key, self.totals)
+    }
+}
+
+impl Ledger {
+    /// 清理超出保留期的记录并写入账本文件，写入失败时保留变更标记以便下一轮重试
+    fn save(&self) {
+        let rows: Vec<LedgerRow> = {
+            let mut rows = self.rows.lock();
+            // 在锁内取快照时清除标记：之后的记录会重新标记，写入失败时再恢复
+            self.dirty.store(false, Ordering::Relaxed);
+            prune(
+                &mut rows,
+                Utc::now().date_naive(),
+                self.config.retention_days,
+            );
+            rows.iter()
+                .map(|(key, totals)| LedgerRow {
+                    day: key.day,
+                    api_key: key.api_key.clone(),
+                    user_id: key.user_id.clone(),
+                    credential_id: key.credential_id,
+                    model: key.model.clone(),
+                    totals: *totals,
+                })
+                .collect()
+        };
+        let Some(path) = &self.path else {
+            return;
+        };
+        match serde_json::to_string_pretty(&rows) {
+            Ok(json) => {
+                if let Err(e) = atomic_file::write(path, json.as_bytes()) {
+                    tracing::warn!("保存用量账本失败: {}", e);
+                    self.dirty.store(true, Ordering::Relaxed);
+                }
+            }
+            Err(e) => {
+                tracing::warn!("序列化用量账本失败: {}", e);
+                self.dirty.store(true, Ordering::Relaxed);
+            }
+        }
+    }
+}
+
+/// 移除早于保留期的记录
+fn prune(rows: &mut BTreeMap<LedgerKey, UsageTotals>, today: NaiveDate, retention_days: u32) {
+    let Some(cutoff) = today.checked_sub_days(Days::new(u64::from(retention_days.max(1)) - 1))
+    else {
+        return;
+    };
+    rows.retain(|key, _| key.day >= cutoff);
+}
+
+/// 按条件聚合账本
+///
+/// 费用按账本中每一行的模型分别估算后累加，按凭据分组时也能正确计入不同模型的价格
+fn aggregate(rows: &BTreeMap<LedgerKey, UsageTotals>, query: &UsageQuery) -> Vec<UsageAggregate> {
+    type GroupKey = (
+        String,
+        Option<String>,
+        Option<String>,
+        Option<u64>,
+        Option<String>,
+    );
+    let mut grouped: BTreeMap<GroupKey, (UsageTotals, f64)> = BTreeMap::new();
+    for (key, totals) in rows {
+        if query.from.is_some_and(|from| key.day < from)
+            || query.to.is_some_and(|to| key.day > to)
+            || query
+                .user_id
+                .as_ref()
+                .is_some_and(|user_id| key.user_id.as_ref() != Some(user_id))
+            || query
+                .credential_id
+                .is_some_and(|id| key.credential_id != Some(id))
+            || query
+                .model
+                .as_ref()
+                .is_some_and(|model| key.model != *model)
+        {
+            continue;
+        }
+        let period = match query.granularity {
+            Granularity::Daily => key.day.format("%Y-%m-%d").to_string(),
+            Granularity::Monthly => key.day.format("%Y-%m").to_string(),
+        };
+        let group = match query.group_by {
+            GroupBy::All => (
+                period,
+                Some(key.api_key.clone()),
+                key.user_id.clone(),
+                key.credential_id,
+                Some(key.model.clone()),
+            ),
+            GroupBy::Credential => (period, None, None, key.credential_id, None),
+            GroupBy::Model => (period, None, None, None, Some(key.model.clone())),
+        };
+        let (group_totals, cost) = grouped.entry(group).or_default();
+        group_totals.add(totals);
+        *cost += session_cost::estimate_cost(&key.model, totals.input_tokens, totals.output_tokens);
+    }
+    grouped
+        .into_iter()
+        .map(
+            |((period, api_key, user_id, credential_id, model), (totals, cost_usd))| {
+                UsageAggregate {
+                    period,
+                    api_key,
+                    user_id,
+                    credential_id,
+                    model,
+                    totals,
+                    cost_usd,
+                }
+            },
+        )
+        .collect()
+}
+
+/// 查询聚合用量（未启用时返回空结果）
+pub fn query(query: &UsageQuery) -> UsageReport {
+    let rows = LEDGER
+        .get()
+        .map(|ledger| aggregate(&ledger.rows.lock(), query))
+        .unwrap_or_default();
+    let mut total = UsageTotals::default();
+    for row in &rows {
+        total.add(&row.totals);
+    }
+    UsageReport {
+        enabled: is_enabled(),
+        granularity: query.granularity,
+        group_by: query.group_by,
+        total_cost_usd: rows.iter().map(|r| r.cost_usd).sum(),
+        rows,
+        total,
+    }
+}
+
+/// 将聚合结果渲染为 CSV
+pub fn to_csv(rows: &[UsageAggregate]) -> String {
+    let mut csv = String::from(
+        "period,api_key,user_id,credential_id,model,requests,input_tokens,output_tokens,cost_usd\n",
+    );
+    for row in rows {
+        let credential_id = row
+            .credential_id
+            .map(|id| id.to_string())
+            .unwrap_or_default();
+        let _ = writeln!(
+            csv,
+            "{},{},{},{},{},{},{},{},{:.6}",
+            row.period,
+            csv_field(row.api_key.as_deref().unwrap_or_default()),
+            csv_field(row.user_id.as_deref().unwrap_or_default()),
+            credential_id,
+            csv_field(row.model.as_deref().unwrap_or_default()),
+            row.totals.requests,
+            row.totals.input_tokens,
+            row.totals.output_tokens,
+            row.cost_usd
+        );
+    }
+    csv
+}
+
+/// 转义 CSV 字段（含逗号、引号或换行时加引号）
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn day(s: &str) -> NaiveDate {
+        NaiveDate::parse_from_str(s, "%Y-%m-%d").unwrap()
+    }
+
+    fn key(d: &str, credential_id: u64, model: &str) -> LedgerKey {
+        LedgerKey {
+            day: day(d),
+            api_key: "sk-a...wxyz".to_string(),
+            user_id: None,
+            credential_id: Some(credential_id),
+            model: model.to_string(),
+        }
+    }
+
+    fn usage(requests: u64, input_tokens: u64, output_tokens: u64) -> UsageTotals {
+        UsageTotals {
+            requests,
+            input_tokens,
+            output_tokens,
+        }
+    }
+
+    fn sample_rows() -> BTreeMap<LedgerKey, UsageTotals> {
+        let mut rows = BTreeMap::new();
+        add(
+            &mut rows,
+            key("2026-09-30", 1, "claude-sonnet-4"),
+            &usage(1, 100, 10),
+        );
+        add(
+            &mut rows,
+            key("2026-10-01", 1, "claude-sonnet-4"),
+            &usage(1, 200, 20),
+        );
+        add(
+            &mut rows,
+            key("2026-10-01", 1, "claude-sonnet-4"),
+            &usage(1, 300, 30),
+        );
+        add(
+            &mut rows,
+            key("2026-10-02", 1, "claude-sonnet-4"),
+            &usage(1, 1000, 100),
+        );
+        add(
+            &mut rows,
+            key("2026-10-02", 2, "claude-sonnet-4"),
+            &usage(2, 50, 5),
+        );
+        rows
+    }
+
+    #[test]
+    fn test_aggregate_daily_and_monthly() {
+        let rows = sample_rows();
+
+        let daily = aggregate(
+            &rows,
+            &UsageQuery {
+                from: Some(day("2026-10-01")),
+                credential_id: Some(1),
+                ..Default::default()
+            },
+        );
+        let periods: Vec<&str> = daily.iter().map(|r| r.period.as_str()).collect();
+        assert_eq!(periods, ["2026-10-01", "2026-10-02"]);
+        assert_eq!(daily[0].totals, usage(2, 500, 50));
+
+        let monthly = aggregate(
+            &rows,
+            &UsageQuery {
+                granularity: Granularity::Monthly,
+                ..Default::default()
+            },
+        );
+        let summary: Vec<(&str, Option<u64>, UsageTotals)> = monthly
+            .iter()
+            .map(|r| (r.period.as_str(), r.credential_id, r.totals))
+            .collect();
+        assert_eq!(
+            summary,
+            [
+                ("2026-09", Some(1), usage(1, 100, 10)),
+                ("2026-10", Some(1), usage(3, 1500, 150)),
+                ("2026-10", Some(2), usage(2, 50, 5)),
+            ]
+        );
+        // sonnet 标价：输入 $3 / 输出 $15 每百万 tokens
+        assert!((monthly[1].cost_usd - 0.00675).abs() < 1e-9);
+
+        let mut rows = rows;
+        let mut alice = key("2026-10-02", 1, "claude-sonnet-4");
+        alice.user_id = Some("alice".to_string());
+        add(&mut rows, alice, &usage(1, 7, 7));
+        let by_user = aggregate(
+            &rows,
+            &UsageQuery {
+                user_id: Some("alice".to_string()),
+                ..Default::default()
+            },
+        );
+        assert_eq!(by_user.len(), 1);
+        assert_eq!(by_user[0].user_id.as_deref(), Some("alice"));
+        assert_eq!(by_user[0].totals, usage(1, 7, 7));
+    }
+
+    #[test]
+    fn test_aggregate_by_credential_prices_each_model() {
+        let mut rows = sample_rows();
+        // opus 4.1 标价：输入 $15 / 输出 $75 每百万 tokens
+        add(
+            &mut rows,
+            key("2026-10-02", 1, "claude-opus-4-1"),
+            &usage(1, 1000, 100),
+        );
+        let by_credential = aggregate(
+            &rows,
+            &UsageQuery {
+                from: Some(day("2026-10-02")),
+                group_by: GroupBy::Credential,
+                ..Default::default()
+            },
+        );
+        let summary: Vec<(Option<u64>, UsageTotals)> = by_credential
+            .iter()
+            .map(|r| (r.credential_id, r.totals))
+            .collect();
+        assert_eq!(
+            summary,
+            [(Some(1), usage(2, 2000, 200)), (Some(2), usage(2, 50, 5))]
+        );
+        assert!(by_credential[0].model.is_none() && by_credential[0].api_key.is_none());
+        assert!((by_credential[0].cost_usd - (0.0045 + 0.0225)).abs() < 1e-9);
+
+        let by_model = aggregate(
+            &rows,
+            &UsageQuery {
+                from: Some(day("2026-10-02")),
+                group_by: GroupBy::Model,
+                ..Default::default()
+            },
+        );
+        let models: Vec<Option<&str>> = by_model.iter().map(|r| r.model.as_deref()).collect();
+        assert_eq!(models, [Some("claude-opus-4-1"), Some("claude-sonnet-4")]);
+        assert_eq!(by_model[1].totals, usage(3, 1050, 105));
+    }
+
+    #[test]
+    fn test_prune_expired_days() {
+        let mut rows = sample_rows();
+        prune(&mut rows, day("2026-10-02"), 2);
+        assert!(rows.keys().all(|k| k.day >= day("2026-10-01")));
+        assert_eq!(rows.len(), 3);
+    }
+
+    #[test]
+    fn test_save_keeps_dirty_until_written() {
+        let dir = std::env::temp_dir().join(format!("kiro-ledger-{}", uuid::Uuid::new_v4()));
+        let path = dir.join("kiro_usage_ledger.json");
+        let ledger = Ledger {
+            config: UsageLedgerConfig { retention_days: 30 },
+            rows: Mutex::new(BTreeMap::new()),
+            path: Some(path.clone()),
+            dirty: AtomicBool::new(true),
+        };
+        add(
+            &mut ledger.rows.lock(),
+            key(&Utc::now().date_naive().to_string(), 1, "claude-sonnet-4"),
+            &usage(1, 100, 10),
+        );
+
+        // 目录不存在，写入失败后保留变更标记
+        ledger.save();
+        assert!(ledger.dirty.load(Ordering::Relaxed));
+
+        std::fs::create_dir_all(&dir).unwrap();
+        ledger.save();
+        assert!(!ledger.dirty.load(Ordering::Relaxed));
+        let saved: Vec<LedgerRow> = atomic_file::load_json(&path).unwrap().unwrap();
+        assert_eq!(saved.len(), 1);
+        assert_eq!(saved[0].totals, usage(1, 100, 10));
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_csv_export() {
+        let rows = aggregate(
+            &sample_rows(),
+            &UsageQuery {
+                to: Some(day("2026-09-30")),
+                ..Default::default()
+            },
+        );
+        assert_eq!(
+            to_csv(&rows),
+            "period,api_key,user_id,credential_id,model,requests,input_tokens,output_tokens,cost_usd\n\
+             2026-09-30,sk-a...wxyz,,1,claude-sonnet-4,1,100,10,0.000450\n"
+        );
+        assert_eq!(csv_field("a,\"b\""), "\"a,\"\"b\"\"\"");
+    }
+}
@@ -1,6 +1,9 @@
 //! WebSearch 工具处理模块
 //!
 //! 实现 Anthropic WebSearch 请求到 Kiro MCP 的转换和响应生成
+//!
+//! 配置了本地搜索后端（`webSearch`）时改用 [`search_provider`](super::search_provider)，
+//! 不再调用 Kiro MCP
 
 use std::convert::Infallible;
 
@@ -15,7 +18,8 @@ use serde::{Deserialize, Serialize};
 use serde_json::json;
 use uuid::Uuid;
 
-use super::stream::SseEvent;
+use super::search_provider;
+use super::stream::{SseEvent, UsageCallback};
 use super::types::{ErrorResponse, MessagesRequest};
 
 /// MCP 请求
@@ -214,15 +218,28 @@ pub fn parse_search_results(mcp_response: &McpResponse) -> Option<WebSearchResul
 }
 
 /// 生成 WebSearch SSE 响应流
+///
+/// 事件在返回前已全部生成，用量回调随即以本次的输入/输出 tokens 调用
 pub fn create_websearch_sse_stream(
     model: String,
     query: String,
     tool_use_id: String,
     search_results: Option<WebSearchResults>,
     input_tokens: i32,
+    message_id: String,
+    usage_callback: Option<UsageCallback>,
 ) -> impl Stream<Item = Result<Bytes, Infallible>> {
-    let events =
-        generate_websearch_events(&model, &query, &tool_use_id, search_results, input_tokens);
+    let (events, output_tokens) = generate_websearch_events(
+        &model,
+        &query,
+        &tool_use_id,
+        search_results,
+        input_tokens,
+        &message_id,
+    );
+    if let Some(callback) = usage_callback {
+        callback(input_tokens, output_tokens);
+    }
 
     stream::iter(
         events
@@ -231,19 +248,16 @@ pub fn create_websearch_sse_stream(
     )
 }
 
-/// 生成 WebSearch SSE 事件序列
+/// 生成 WebSearch SSE 事件序列，同时返回输出 tokens
 fn generate_websearch_events(
     model: &str,
     query: &str,
     tool_use_id: &str,
     search_results: Option<WebSearchResults>,
     input_tokens: i32,
-) -> Vec<SseEvent> {
+    message_id: &str,
+) -> (Vec<SseEvent>, i32) {
     let mut events = Vec::new();
-    let message_id = format!(
-        "msg_{}",
-        Uuid::new_v4().to_string().replace('-', "")[..24].to_string()
-    );
 
     // 1. message_start
     events.push(SseEvent::new(
@@ -416,7 +430,7 @@ fn generate_websearch_events(
 
     // 10. message_delta
     // 官方 API 的 message_delta.delta 中没有 stop_sequence 字段
-    let output_tokens = (summary.len() as i32 + 3) / 4; // 简单估算
+    let output_tokens = crate::token::count_tokens(&summary) as i32;
     events.push(SseEvent::new(
         "message_delta",
         json!({
@@ -441,7 +455,7 @@ fn generate_websearch_events(
         }),
     ));
 
-    events
+    (events, output_tokens)
 }
 
 /// 生成搜索结果摘要
@@ -475,6 +489,8 @@ pub async fn handle_websearch_request(
     provider: std::sync::Arc<crate::kiro::provider::KiroProvider>,
     payload: &MessagesRequest,
     input_tokens: i32,
+    message_id: String,
+    usage_callback: Option<UsageCallback>,
 ) -> Response {
     // 1. 提取搜索查询
     let query = match extract_search_query(payload) {
@@ -496,19 +512,36 @@ pub async fn handle_websearch_request(
     // 2. 创建 MCP 请求
     let (tool_use_id, mcp_request) = create_mcp_request(&query);
 
-    // 3. 调用 Kiro MCP API
-    let search_results = match call_mcp_api(&provider, &mcp_request).await {
-        Ok(response) => parse_search_results(&response),
-        Err(e) => {
-            tracing::warn!("MCP API 调用失败: {}", e);
-            None
+    // 3. 调用本地搜索后端或 Kiro MCP API
+    let search_results = if let Some(backend) = search_provider::get() {
+        match backend.search(&query).await {
+            Ok(results) => Some(results),
+            Err(e) => {
+                tracing::warn!("本地 WebSearch 失败: {:#}", e);
+                None
+            }
+        }
+    } else {
+        match call_mcp_api(&provider, &mcp_request).await {
+            Ok(response) => parse_search_results(&response),
+            Err(e) => {
+                tracing::warn!("MCP API 调用失败: {}", e);
+                None
+            }
         }
     };
 
     // 4. 生成 SSE 响应
     let model = payload.model.clone();
-    let stream =
-        create_websearch_sse_stream(model, query, tool_use_id, search_results, input_tokens);
+    let stream = create_websearch_sse_stream(
+        model,
+        query,
+        tool_use_id,
+        search_results,
+        input_tokens,
+        message_id,
+        usage_callback,
+    );
 
     Response::builder()
         .status(StatusCode::OK)
@@ -574,6 +607,7 @@ mod tests {
             thinking: None,
             output_config: None,
             metadata: None,
+            stop_sequences: None,
         };
 
         assert!(has_web_search_tool(&req));
@@ -612,6 +646,7 @@ mod tests {
             thinking: None,
             output_config: None,
             metadata: None,
+            stop_sequences: None,
         };
 
         // 多个工具时不应该被识别为纯 websearch 请求
@@ -639,6 +674,7 @@ mod tests {
             thinking: None,
             output_config: None,
             metadata: None,
+            stop_sequences: None,
         };
 
         let query = extract_search_query(&req);
@@ -664,6 +700,7 @@ mod tests {
             thinking: None,
             output_config: None,
             metadata: None,
+            stop_sequences: None,
         };
 
         let query = extract_search_query(&req);
@@ -758,4 +795,27 @@ mod tests {
         assert!(summary.contains("https://example.com"));
         assert!(summary.contains("This is a test snippet"));
     }
+
+    #[test]
+    fn test_websearch_stream_records_usage() {
+        let usage = std::sync::Arc::new(parking_lot::Mutex::new(None));
+        let recorded = usage.clone();
+        let callback: UsageCallback = Box::new(move |input_tokens, output_tokens| {
+            *recorded.lock() = Some((input_tokens, output_tokens));
+        });
+
+        let _stream = create_websearch_sse_stream(
+            "claude-sonnet-4".to_string(),
+            "test".to_string(),
+            "srvtoolu_test".to_string(),
+            None,
+            42,
+            "msg_test".to_string(),
+            Some(callback),
+        );
+
+        let (input_tokens, output_tokens) = usage.lock().unwrap();
+        assert_eq!(input_tokens, 42);
+        assert!(output_tokens > 0);
+    }
 }
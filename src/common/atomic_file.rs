@@ -0,0 +1,160 @@
+//! 原子文件写入
+//!
+//! 先写入同目录下的临时文件并 fsync，再 rename 覆盖目标文件：进程在写入中途崩溃时
+//! 目标文件保持旧内容，不会出现截断或半写的 JSON。临时文件沿用目标文件的权限。
+//!
+//! 读取时内容无法解析的文件先改名备份，避免随后的写入把唯一一份数据覆盖为空。
+
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+use serde::de::DeserializeOwned;
+
+/// 原子地写入文件
+pub fn write(path: &Path, contents: &[u8]) -> io::Result<()> {
+    let tmp = tmp_path(path);
+    let result = write_tmp(&tmp, path, contents).and_then(|()| fs::rename(&tmp, path));
+    if result.is_err() {
+        let _ = fs::remove_file(&tmp);
+        return result;
+    }
+    #[cfg(unix)]
+    sync_dir(path);
+    Ok(())
+}
+
+/// 读取 JSON 文件
+///
+/// - 文件不存在时返回 `Ok(None)`
+/// - 内容无法解析时把文件改名为 `<文件名>.corrupt-<时间戳>` 备份，记录错误后返回 `Ok(None)`
+/// - 读取或备份失败时返回错误，调用方不应再写入该文件
+pub fn load_json<T: DeserializeOwned>(path: &Path) -> io::Result<Option<T>> {
+    let content = match fs::read_to_string(path) {
+        Ok(content) => content,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e),
+    };
+    match serde_json::from_str(&content) {
+        Ok(value) => Ok(Some(value)),
+        Err(e) => {
+            let backup = corrupt_path(path);
+            fs::rename(path, &backup)?;
+            tracing::error!(
+                "解析 {} 失败，已备份为 {} 并以空数据启动: {}",
+                path.display(),
+                backup.display(),
+                e
+            );
+            Ok(None)
+        }
+    }
+}
+
+/// 损坏文件的备份路径：`<文件名>.corrupt-<UTC 时间戳>`
+fn corrupt_path(path: &Path) -> PathBuf {
+    let name = path
+        .file_name()
+        .map(|n| n.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    path.with_file_name(format!(
+        "{}.corrupt-{}",
+        name,
+        chrono::Utc::now().format("%Y%m%dT%H%M%S%.3fZ")
+    ))
+}
+
+/// 临时文件路径：`<目录>/.<文件名>.tmp`
+fn tmp_path(path: &Path) -> PathBuf {
+    let name = path
+        .file_name()
+        .map(|n| n.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    path.with_file_name(format!(".{}.tmp", name))
+}
+
+fn write_tmp(tmp: &Path, target: &Path, contents: &[u8]) -> io::Result<()> {
+    let mut file = OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .open(tmp)?;
+    if let Ok(metadata) = fs::metadata(target) {
+        file.set_permissions(metadata.permissions())?;
+    }
+    file.write_all(contents)?;
+    file.sync_all()
+}
+
+/// rename 后同步目录项，确保掉电后新文件名可见
+#[cfg(unix)]
+fn sync_dir(path: &Path) {
+    let dir = match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+    if let Ok(dir) = fs::File::open(dir) {
+        let _ = dir.sync_all();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_write_replaces_content_and_removes_tmp() {
+        let dir = std::env::temp_dir().join(format!("kiro-atomic-{}", uuid::Uuid::new_v4()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("credentials.json");
+
+        write(&path, b"[1]").unwrap();
+        write(&path, b"[1, 2]").unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "[1, 2]");
+        assert!(!tmp_path(&path).exists());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_load_json_backs_up_corrupt_file() {
+        let dir = std::env::temp_dir().join(format!("kiro-atomic-{}", uuid::Uuid::new_v4()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("ledger.json");
+
+        assert!(load_json::<Vec<u32>>(&path).unwrap().is_none());
+        write(&path, b"[1, 2]").unwrap();
+        assert_eq!(load_json::<Vec<u32>>(&path).unwrap(), Some(vec![1, 2]));
+
+        write(&path, b"[1, 2").unwrap();
+        assert!(load_json::<Vec<u32>>(&path).unwrap().is_none());
+        assert!(!path.exists());
+        let backups: Vec<String> = fs::read_dir(&dir)
+            .unwrap()
+            .map(|e| e.unwrap().file_name().to_string_lossy().into_owned())
+            .collect();
+        assert_eq!(backups.len(), 1);
+        assert!(backups[0].starts_with("ledger.json.corrupt-"));
+        assert_eq!(fs::read_to_string(dir.join(&backups[0])).unwrap(), "[1, 2");
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_write_keeps_permissions() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = std::env::temp_dir().join(format!("kiro-atomic-{}", uuid::Uuid::new_v4()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("credentials.json");
+        fs::write(&path, "[]").unwrap();
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o600)).unwrap();
+
+        write(&path, b"[1]").unwrap();
+        let mode = fs::metadata(&path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
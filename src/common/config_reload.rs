@@ -0,0 +1,191 @@
+//! config.json 热加载
+//!
+//! 按固定间隔检查配置文件的修改时间，变化后重新加载（含 profile 叠加），
+//! 把可热更新的字段应用到运行中的组件；其余字段的变化只输出日志，提示需要重启。
+
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Weak};
+use std::time::{Duration, SystemTime};
+
+use crate::anthropic::{self, output_filter, system_prompt};
+use crate::http_client::ProxyConfig;
+use crate::kiro::model::events::unknown_events;
+use crate::kiro::request_size;
+use crate::kiro::token_manager::MultiTokenManager;
+use crate::model::config::Config;
+
+/// 可在运行时生效的字段（JSON 键名）
+const HOT_RELOADABLE: &[&str] = &[
+    "proxyUrl",
+    "proxyUsername",
+    "proxyPassword",
+    "loadBalancingMode",
+    "modelRoutes",
+    "systemPrompts",
+    "outputRedactions",
+    "requestSizeAlertTokens",
+    "repairToolHistory",
+    "mapUnknownTextEvents",
+    "configReloadIntervalSecs",
+];
+
+/// 未配置时每次加载随机生成默认值的字段（比较时忽略，否则每次重新加载都会误报变化）
+const RANDOM_DEFAULT_FIELDS: &[&str] = &["systemVersion"];
+
+/// 比较两份配置，返回值发生变化的顶层字段（JSON 键名，已排序）
+pub fn changed_keys(old: &Config, new: &Config) -> Vec<String> {
+    let old = serde_json::to_value(old).unwrap_or_default();
+    let new = serde_json::to_value(new).unwrap_or_default();
+    let (Some(old), Some(new)) = (old.as_object(), new.as_object()) else {
+        return Vec::new();
+    };
+    let mut keys: Vec<String> = old
+        .keys()
+        .chain(new.keys())
+        .filter(|k| !RANDOM_DEFAULT_FIELDS.contains(&k.as_str()))
+        .filter(|k| old.get(*k) != new.get(*k))
+        .cloned()
+        .collect();
+    keys.sort();
+    keys.dedup();
+    keys
+}
+
+/// 把变化的字段分为（可热更新, 需重启）两组
+fn partition(changed: &[String]) -> (Vec<&str>, Vec<&str>) {
+    changed
+        .iter()
+        .map(String::as_str)
+        .partition(|k| HOT_RELOADABLE.contains(k))
+}
+
+/// 把新配置中可热更新的字段应用到运行中的组件
+fn apply(token_manager: &MultiTokenManager, config: &Config, changed: &[&str]) {
+    if changed.iter().any(|k| k.starts_with("proxy")) {
+        let proxy = ProxyConfig::from_config(config);
+        tracing::info!(
+            "全局代理已更新: {}",
+            proxy.as_ref().map(|p| p.url.as_str()).unwrap_or("无")
+        );
+        token_manager.set_global_proxy(proxy);
+    }
+    if changed.contains(&"loadBalancingMode") {
+        match token_manager.apply_load_balancing_mode(&config.load_balancing_mode) {
+            Ok(()) => tracing::info!("负载均衡模式已更新: {}", config.load_balancing_mode),
+            Err(e) => tracing::warn!("忽略无效的负载均衡模式: {}", e),
+        }
+    }
+    if changed.contains(&"modelRoutes") {
+        token_manager.apply_model_routes(config.model_routes.clone());
+        tracing::info!("模型路由规则已更新: {} 条", config.model_routes.len());
+    }
+    if changed.contains(&"systemPrompts") {
+        system_prompt::init(config.system_prompts.clone());
+        tracing::info!("system 提示规则已更新: {} 条", config.system_prompts.len());
+    }
+    if changed.contains(&"outputRedactions") {
+        match output_filter::init(&config.output_redactions) {
+            Ok(()) => tracing::info!("输出脱敏规则已更新: {} 条", config.output_redactions.len()),
+            Err(e) => tracing::warn!("忽略无效的输出脱敏规则: {:#}", e),
+        }
+    }
+    if changed.contains(&"requestSizeAlertTokens") {
+        request_size::init_alert_threshold(config.request_size_alert_tokens);
+        tracing::info!(
+            "请求体积告警阈值已更新: {}",
+            config.request_size_alert_tokens
+        );
+    }
+    if changed.contains(&"repairToolHistory") {
+        anthropic::init_tool_history_repair(config.repair_tool_history);
+        tracing::info!("工具调用历史修复已更新: {}", config.repair_tool_history);
+    }
+    if changed.contains(&"mapUnknownTextEvents") {
+        unknown_events::init(config.map_unknown_text_events);
+        tracing::info!(
+            "未识别事件文本映射已更新: {}",
+            config.map_unknown_text_events
+        );
+    }
+}
+
+fn modified_at(path: &Path) -> Option<SystemTime> {
+    std::fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+/// 启动配置热加载任务（`config.configReloadIntervalSecs` 为 0 时不启动）
+///
+/// 仅监视主配置文件；修改 `config.d/` 下的 profile 后需 touch 主配置文件触发重新加载
+pub fn spawn(
+    path: PathBuf,
+    profiles: Vec<String>,
+    initial: Config,
+    token_manager: &Arc<MultiTokenManager>,
+) {
+    if initial.config_reload_interval_secs == 0 {
+        return;
+    }
+    let manager: Weak<MultiTokenManager> = Arc::downgrade(token_manager);
+    tokio::spawn(async move {
+        let mut current = initial;
+        let mut last_modified = modified_at(&path);
+        loop {
+            let interval = Duration::from_secs(current.config_reload_interval_secs.max(1));
+            tokio::time::sleep(interval).await;
+            let Some(manager) = manager.upgrade() else {
+                break;
+            };
+
+            let modified = modified_at(&path);
+            if modified == last_modified {
+                continue;
+            }
+            last_modified = modified;
+
+            let config = match Config::load_with_profiles(&path, &profiles) {
+                Ok(config) => config,
+                Err(e) => {
+                    tracing::warn!("配置文件已修改但重新加载失败，继续使用当前配置: {:#}", e);
+                    continue;
+                }
+            };
+            let changed = changed_keys(&current, &config);
+            if changed.is_empty() {
+                continue;
+            }
+
+            let (hot, restart) = partition(&changed);
+            apply(&manager, &config, &hot);
+            if !restart.is_empty() {
+                tracing::warn!("以下配置项已修改，需重启后生效: {}", restart.join(", "));
+            }
+            if config.config_reload_interval_secs == 0 {
+                tracing::info!("configReloadIntervalSecs 已设为 0，停止配置热加载");
+                break;
+            }
+            current = config;
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_changed_keys_and_partition() {
+        let old = Config::default();
+        let mut new = Config::default();
+        new.proxy_url = Some("http://127.0.0.1:7890".to_string());
+        new.load_balancing_mode = "balanced".to_string();
+        new.port = 9090;
+
+        let changed = changed_keys(&old, &new);
+        assert_eq!(changed, ["loadBalancingMode", "port", "proxyUrl"]);
+
+        let (hot, restart) = partition(&changed);
+        assert_eq!(hot, ["loadBalancingMode", "proxyUrl"]);
+        assert_eq!(restart, ["port"]);
+        assert!(changed_keys(&old, &Config::default()).is_empty());
+    }
+}
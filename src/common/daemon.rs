@@ -0,0 +1,124 @@
+//! 后台运行（`--daemon`）与 PID 文件
+//!
+//! Unix 下以两次 fork + setsid 脱离终端，标准输入输出重定向到 /dev/null，
+//! 日志需通过 `logFile` 写入文件。必须在创建 tokio 运行时之前调用。
+
+use std::fs;
+use std::io;
+use std::path::Path;
+
+/// 脱离终端转入后台运行
+#[cfg(unix)]
+pub fn daemonize() -> io::Result<()> {
+    // SAFETY: 调用时进程仍为单线程（tokio 运行时尚未创建），fork 后子进程只调用
+    // async-signal-safe 的系统调用
+    unsafe {
+        match libc::fork() {
+            -1 => return Err(io::Error::last_os_error()),
+            0 => {}
+            _ => libc::_exit(0),
+        }
+        if libc::setsid() == -1 {
+            return Err(io::Error::last_os_error());
+        }
+        // 第二次 fork：会话首进程退出，确保不会重新获得控制终端
+        match libc::fork() {
+            -1 => return Err(io::Error::last_os_error()),
+            0 => {}
+            _ => libc::_exit(0),
+        }
+        libc::umask(0o022);
+
+        let dev_null = libc::open(c"/dev/null".as_ptr(), libc::O_RDWR);
+        if dev_null == -1 {
+            return Err(io::Error::last_os_error());
+        }
+        for fd in [libc::STDIN_FILENO, libc::STDOUT_FILENO, libc::STDERR_FILENO] {
+            libc::dup2(dev_null, fd);
+        }
+        if dev_null > libc::STDERR_FILENO {
+            libc::close(dev_null);
+        }
+    }
+    Ok(())
+}
+
+/// 非 Unix 平台不支持 fork，请使用系统服务管理器
+#[cfg(not(unix))]
+pub fn daemonize() -> io::Result<()> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "当前平台不支持 --daemon，请使用系统服务管理器（如 Windows 的 sc.exe / NSSM）托管",
+    ))
+}
+
+/// 写入 PID 文件
+///
+/// 文件已存在且记录的进程仍在运行时拒绝启动，避免同一 PID 文件被两个实例使用；
+/// 进程已退出留下的旧文件直接覆盖
+pub fn write_pid_file(path: &Path) -> io::Result<()> {
+    if let Some(pid) = fs::read_to_string(path)
+        .ok()
+        .and_then(|s| s.trim().parse::<u32>().ok())
+        && pid != std::process::id()
+        && is_running(pid)
+    {
+        return Err(io::Error::new(
+            io::ErrorKind::AlreadyExists,
+            format!("PID 文件 {} 对应的进程 {} 仍在运行", path.display(), pid),
+        ));
+    }
+    if let Some(parent) = path.parent().filter(|p| !p.as_os_str().is_empty()) {
+        fs::create_dir_all(parent)?;
+    }
+    fs::write(path, format!("{}\n", std::process::id()))
+}
+
+#[cfg(unix)]
+fn is_running(pid: u32) -> bool {
+    let Ok(pid) = libc::pid_t::try_from(pid) else {
+        return false;
+    };
+    // SAFETY: 信号 0 只检查进程是否存在，不会实际发送信号
+    unsafe {
+        libc::kill(pid, 0) == 0 || io::Error::last_os_error().raw_os_error() == Some(libc::EPERM)
+    }
+}
+
+#[cfg(not(unix))]
+fn is_running(_pid: u32) -> bool {
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_write_pid_file_replaces_stale_file() {
+        let path = std::env::temp_dir().join(format!("kiro-rs-{}.pid", uuid::Uuid::new_v4()));
+
+        // 不存在的进程留下的旧文件可以覆盖
+        fs::write(&path, "999999999\n").unwrap();
+        write_pid_file(&path).unwrap();
+        assert_eq!(
+            fs::read_to_string(&path).unwrap().trim(),
+            std::process::id().to_string()
+        );
+
+        // 自身 PID 视为同一实例重复写入
+        write_pid_file(&path).unwrap();
+        fs::remove_file(path).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_write_pid_file_rejects_running_process() {
+        let path = std::env::temp_dir().join(format!("kiro-rs-{}.pid", uuid::Uuid::new_v4()));
+        // PID 1（init）始终存在
+        fs::write(&path, "1\n").unwrap();
+        let err = write_pid_file(&path).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::AlreadyExists);
+        fs::remove_file(path).unwrap();
+    }
+}
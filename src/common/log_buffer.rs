@@ -0,0 +1,106 @@
+//! 最近日志环形缓冲区
+//!
+//! 作为 tracing 的输出 writer，在写入 stdout 的同时保留最近若干行日志，
+//! 供诊断包（support bundle）导出使用；配置了 `logFile` 时同时写入日志文件。
+
+use std::collections::VecDeque;
+use std::io::{self, Write};
+use std::sync::LazyLock;
+
+use parking_lot::Mutex;
+use tracing_subscriber::fmt::MakeWriter;
+
+/// 保留的最大日志行数
+const MAX_LOG_LINES: usize = 1000;
+
+static RECENT_LOGS: LazyLock<Mutex<VecDeque<String>>> =
+    LazyLock::new(|| Mutex::new(VecDeque::with_capacity(MAX_LOG_LINES)));
+
+/// 获取最近的日志行（按时间顺序）
+pub fn recent_lines() -> Vec<String> {
+    RECENT_LOGS.lock().iter().cloned().collect()
+}
+
+fn push_line(line: String) {
+    let mut logs = RECENT_LOGS.lock();
+    if logs.len() >= MAX_LOG_LINES {
+        logs.pop_front();
+    }
+    logs.push_back(line);
+}
+
+/// 去除 ANSI 颜色控制序列（`ESC [ ... m`）
+fn strip_ansi(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    let mut chars = s.chars();
+    while let Some(c) = chars.next() {
+        if c == '\x1b' {
+            // 跳过直到终止字母
+            for c in chars.by_ref() {
+                if c.is_ascii_alphabetic() {
+                    break;
+                }
+            }
+        } else {
+            out.push(c);
+        }
+    }
+    out
+}
+
+/// 同时写入 stdout 和环形缓冲区的 writer
+#[derive(Clone, Copy, Default)]
+pub struct TeeMakeWriter;
+
+impl<'a> MakeWriter<'a> for TeeMakeWriter {
+    type Writer = TeeWriter;
+
+    fn make_writer(&'a self) -> Self::Writer {
+        TeeWriter { buf: Vec::new() }
+    }
+}
+
+/// 单条日志事件的 writer，drop 时提交到缓冲区
+pub struct TeeWriter {
+    buf: Vec<u8>,
+}
+
+impl Write for TeeWriter {
+    fn write(&mut self, data: &[u8]) -> io::Result<usize> {
+        io::stdout().write_all(data)?;
+        self.buf.extend_from_slice(data);
+        Ok(data.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        io::stdout().flush()
+    }
+}
+
+impl Drop for TeeWriter {
+    fn drop(&mut self) {
+        if self.buf.is_empty() {
+            return;
+        }
+        let text = String::from_utf8_lossy(&self.buf);
+        for line in text.lines().filter(|l| !l.is_empty()) {
+            let line = strip_ansi(line);
+            super::log_file::append(&line);
+            push_line(line);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_strip_ansi() {
+        assert_eq!(
+            strip_ansi("\x1b[2m2026-01-01\x1b[0m \x1b[32m INFO\x1b[0m hello"),
+            "2026-01-01  INFO hello"
+        );
+        assert_eq!(strip_ansi("plain"), "plain");
+    }
+}
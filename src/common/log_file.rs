@@ -0,0 +1,207 @@
+//! 滚动日志文件
+//!
+//! 日志在写入 stdout 的同时追加到 `logFile.path`。按天（跨日后首次写入时）
+//! 或按大小轮转：当前文件重命名为 `<path>.<YYYYmmdd-HHMMSS>`，
+//! 并只保留最近 `maxFiles` 个已轮转的文件。
+
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+
+use chrono::{DateTime, Local, NaiveDate};
+use parking_lot::Mutex;
+
+use crate::model::config::LogFileConfig;
+
+static LOG_FILE: OnceLock<Mutex<RollingFile>> = OnceLock::new();
+
+/// 打开日志文件，之后的日志同时写入该文件
+pub fn init(config: &LogFileConfig) -> io::Result<()> {
+    let file = RollingFile::open(config, Local::now())?;
+    let _ = LOG_FILE.set(Mutex::new(file));
+    Ok(())
+}
+
+/// 追加一行日志（未配置日志文件时忽略）
+pub fn append(line: &str) {
+    if let Some(file) = LOG_FILE.get() {
+        // 写日志失败时无处可报，只能丢弃
+        let _ = file.lock().write_line(line, Local::now());
+    }
+}
+
+struct RollingFile {
+    path: PathBuf,
+    /// 单个文件大小上限（字节），0 表示不按大小轮转
+    max_size: u64,
+    daily: bool,
+    max_files: usize,
+    file: File,
+    size: u64,
+    /// 当前文件开始写入的日期
+    opened_on: NaiveDate,
+}
+
+impl RollingFile {
+    fn open(config: &LogFileConfig, now: DateTime<Local>) -> io::Result<Self> {
+        let path = PathBuf::from(&config.path);
+        if let Some(parent) = path.parent().filter(|p| !p.as_os_str().is_empty()) {
+            fs::create_dir_all(parent)?;
+        }
+        let file = OpenOptions::new().create(true).append(true).open(&path)?;
+        let metadata = file.metadata()?;
+        // 沿用已有文件时以其最后修改日期为准，跨日重启后首次写入即轮转
+        let opened_on = metadata
+            .modified()
+            .map(|t| DateTime::<Local>::from(t).date_naive())
+            .unwrap_or_else(|_| now.date_naive());
+        Ok(Self {
+            path,
+            max_size: config.max_size_mb.saturating_mul(1024 * 1024),
+            daily: config.daily,
+            max_files: config.max_files,
+            file,
+            size: metadata.len(),
+            opened_on,
+        })
+    }
+
+    fn write_line(&mut self, line: &str, now: DateTime<Local>) -> io::Result<()> {
+        let len = line.len() as u64 + 1;
+        let day_changed = self.daily && now.date_naive() != self.opened_on;
+        let too_large = self.max_size > 0 && self.size > 0 && self.size + len > self.max_size;
+        if day_changed || too_large {
+            self.rotate(now)?;
+        }
+        self.file.write_all(line.as_bytes())?;
+        self.file.write_all(b"\n")?;
+        self.size += len;
+        Ok(())
+    }
+
+    fn rotate(&mut self, now: DateTime<Local>) -> io::Result<()> {
+        self.file.flush()?;
+        let stamp = now.format("%Y%m%d-%H%M%S").to_string();
+        let mut target = suffixed(&self.path, &stamp);
+        let mut n = 1;
+        while target.exists() {
+            target = suffixed(&self.path, &format!("{}.{}", stamp, n));
+            n += 1;
+        }
+        fs::rename(&self.path, &target)?;
+        self.file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)?;
+        self.size = 0;
+        self.opened_on = now.date_naive();
+        self.prune()
+    }
+
+    /// 删除超出保留数量的最旧的已轮转文件
+    fn prune(&self) -> io::Result<()> {
+        let Some(name) = self.path.file_name().and_then(|n| n.to_str()) else {
+            return Ok(());
+        };
+        let prefix = format!("{}.", name);
+        let dir = match self.path.parent() {
+            Some(p) if !p.as_os_str().is_empty() => p,
+            _ => Path::new("."),
+        };
+        let mut rotated: Vec<PathBuf> = fs::read_dir(dir)?
+            .filter_map(|e| e.ok())
+            .filter(|e| e.file_name().to_string_lossy().starts_with(&prefix))
+            .map(|e| e.path())
+            .collect();
+        // 时间戳后缀按字典序即按时间排序
+        rotated.sort();
+        let excess = rotated.len().saturating_sub(self.max_files);
+        for path in &rotated[..excess] {
+            fs::remove_file(path)?;
+        }
+        Ok(())
+    }
+}
+
+fn suffixed(path: &Path, suffix: &str) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(".");
+    name.push(suffix);
+    PathBuf::from(name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{Duration, TimeZone};
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir =
+            std::env::temp_dir().join(format!("kiro-rs-log-{}-{}", name, uuid::Uuid::new_v4()));
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn rotated_count(dir: &Path) -> usize {
+        fs::read_dir(dir)
+            .unwrap()
+            .filter(|e| {
+                e.as_ref()
+                    .unwrap()
+                    .file_name()
+                    .to_string_lossy()
+                    .starts_with("kiro.log.")
+            })
+            .count()
+    }
+
+    #[test]
+    fn test_rotates_by_size_and_prunes() {
+        let dir = temp_dir("size");
+        let config = LogFileConfig {
+            path: dir.join("kiro.log").to_string_lossy().into_owned(),
+            max_size_mb: 1,
+            daily: false,
+            max_files: 2,
+        };
+        let now = Local::now();
+        let mut file = RollingFile::open(&config, now).unwrap();
+        let line = "x".repeat(400 * 1024);
+        for i in 0..8 {
+            file.write_line(&line, now + Duration::seconds(i)).unwrap();
+        }
+
+        // 每个文件最多容纳 2 行，8 行产生 3 次轮转，只保留最近 2 个
+        assert_eq!(rotated_count(&dir), 2);
+        assert!(fs::metadata(dir.join("kiro.log")).unwrap().len() <= 1024 * 1024);
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_rotates_daily() {
+        let dir = temp_dir("daily");
+        let config = LogFileConfig {
+            path: dir.join("kiro.log").to_string_lossy().into_owned(),
+            max_size_mb: 0,
+            daily: true,
+            max_files: 7,
+        };
+        let day1 = Local.with_ymd_and_hms(2026, 10, 14, 23, 59, 0).unwrap();
+        let mut file = RollingFile::open(&config, day1).unwrap();
+        file.opened_on = day1.date_naive();
+        file.write_line("first", day1).unwrap();
+        file.write_line("second", day1).unwrap();
+        assert_eq!(rotated_count(&dir), 0);
+
+        file.write_line("third", day1 + Duration::minutes(2))
+            .unwrap();
+        assert_eq!(rotated_count(&dir), 1);
+        assert_eq!(
+            fs::read_to_string(dir.join("kiro.log.20261015-000100")).unwrap(),
+            "first\nsecond\n"
+        );
+        assert_eq!(fs::read_to_string(dir.join("kiro.log")).unwrap(), "third\n");
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -1,3 +1,10 @@
 //! 公共工具模块
 
+pub mod atomic_file;
 pub mod auth;
+pub mod config_reload;
+pub mod daemon;
+pub mod log_buffer;
+pub mod log_file;
+pub mod net;
+pub mod telemetry;
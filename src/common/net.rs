@@ -0,0 +1,117 @@
+//! 网络工具：监听 socket 构建与启动时连通性自检
+
+use std::net::{IpAddr, SocketAddr};
+use std::time::Duration;
+
+use socket2::{Domain, Protocol, Socket, Type};
+use tokio::net::{TcpListener, TcpStream};
+
+/// 自检单个地址的连接超时
+const SELF_TEST_CONNECT_TIMEOUT: Duration = Duration::from_secs(3);
+
+/// 监听 backlog
+const LISTEN_BACKLOG: i32 = 1024;
+
+/// 构建监听 socket
+///
+/// - `host` 支持 IPv4 / IPv6 字面量和主机名（IPv6 无需加方括号）
+/// - `dual_stack` 为 true 且解析结果为 IPv6 地址时关闭 IPV6_V6ONLY，
+///   使同一 socket 同时接受 IPv4 连接（如 `host: "::"`）
+pub async fn bind_listener(host: &str, port: u16, dual_stack: bool) -> anyhow::Result<TcpListener> {
+    let addr = tokio::net::lookup_host((host, port))
+        .await?
+        .next()
+        .ok_or_else(|| anyhow::anyhow!("无法解析监听地址: {}", host))?;
+
+    let socket = Socket::new(Domain::for_address(addr), Type::STREAM, Some(Protocol::TCP))?;
+    socket.set_reuse_address(true)?;
+    if addr.is_ipv6() {
+        socket.set_only_v6(!dual_stack)?;
+    } else if dual_stack {
+        tracing::warn!("dualStack 仅在 host 为 IPv6 地址时生效（当前: {}）", addr);
+    }
+    socket.set_nonblocking(true)?;
+    socket.bind(&addr.into())?;
+    socket.listen(LISTEN_BACKLOG)?;
+
+    Ok(TcpListener::from_std(socket.into())?)
+}
+
+/// 单个协议族的连通性结果
+#[derive(Debug, Default)]
+struct FamilyResult {
+    resolved: usize,
+    reachable: Option<SocketAddr>,
+    last_error: Option<String>,
+}
+
+/// 启动时的上游连通性自检
+///
+/// 分别解析并尝试 TCP 连接上游主机的 IPv4 / IPv6 地址，仅输出日志，不影响启动
+pub async fn connectivity_self_test(host: String, port: u16) {
+    let addrs: Vec<SocketAddr> = match tokio::net::lookup_host((host.as_str(), port)).await {
+        Ok(addrs) => addrs.collect(),
+        Err(e) => {
+            tracing::warn!("连通性自检：解析 {} 失败: {}", host, e);
+            return;
+        }
+    };
+
+    let mut v4 = FamilyResult::default();
+    let mut v6 = FamilyResult::default();
+
+    for addr in addrs {
+        let result = match addr.ip() {
+            IpAddr::V4(_) => &mut v4,
+            IpAddr::V6(_) => &mut v6,
+        };
+        result.resolved += 1;
+        if result.reachable.is_some() {
+            continue;
+        }
+        match tokio::time::timeout(SELF_TEST_CONNECT_TIMEOUT, TcpStream::connect(addr)).await {
+            Ok(Ok(_)) => result.reachable = Some(addr),
+            Ok(Err(e)) => result.last_error = Some(e.to_string()),
+            Err(_) => result.last_error = Some("连接超时".to_string()),
+        }
+    }
+
+    for (family, result) in [("IPv4", &v4), ("IPv6", &v6)] {
+        match (&result.reachable, result.resolved) {
+            (Some(addr), _) => tracing::info!("连通性自检：{} {} 可达（{}）", host, family, addr),
+            (None, 0) => tracing::info!("连通性自检：{} 无 {} 地址", host, family),
+            (None, n) => tracing::warn!(
+                "连通性自检：{} {} 不可达（{} 个地址，最后错误: {}）",
+                host,
+                family,
+                n,
+                result.last_error.as_deref().unwrap_or("未知")
+            ),
+        }
+    }
+
+    if v4.reachable.is_none() && v6.reachable.is_none() {
+        tracing::error!(
+            "连通性自检：{} 所有地址均不可达，请检查网络或代理配置",
+            host
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_bind_listener_ipv4() {
+        let listener = bind_listener("127.0.0.1", 0, false).await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        assert!(addr.is_ipv4());
+        assert_ne!(addr.port(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_bind_listener_rejects_unresolvable_host() {
+        assert!(bind_listener("not a host", 0, false).await.is_err());
+    }
+}
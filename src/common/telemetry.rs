@@ -0,0 +1,74 @@
+//! OTLP 链路追踪导出
+//!
+//! 日志订阅器在加载配置前就已初始化，因此预先叠加一个可替换的空 layer；
+//! 配置 `otlp` 后由 [`init`] 装入 OpenTelemetry layer，之后的 span 批量导出到
+//! OTLP/HTTP 后端（Jaeger / Tempo 等）。一次 `/v1/messages` 请求的 span 层级：
+//!
+//! ```text
+//! messages                  整个请求（流式响应持续到事件流结束）
+//! ├── convert_request       Anthropic → Kiro 请求转换
+//! ├── kiro.call_api         上游调用（含重试，记录最终凭据与尝试次数）
+//! │   ├── acquire_credential  选择凭据，必要时刷新 Token
+//! │   │   └── refresh_token
+//! │   ├── queue             等待凭据并发名额
+//! │   └── upstream_ttfb     发送请求到收到响应头
+//! └── stream                向客户端输出事件流
+//! ```
+
+use std::sync::OnceLock;
+use std::time::Duration;
+
+use opentelemetry::trace::TracerProvider as _;
+use opentelemetry_otlp::{WithExportConfig, WithHttpConfig};
+use opentelemetry_sdk::Resource;
+use opentelemetry_sdk::trace::{Sampler, SdkTracer, SdkTracerProvider};
+use tracing_subscriber::{Registry, reload};
+
+use crate::model::config::OtlpConfig;
+
+/// 单次导出请求超时（秒）
+const EXPORT_TIMEOUT_SECS: u64 = 10;
+
+type OtelLayer = tracing_opentelemetry::OpenTelemetryLayer<Registry, SdkTracer>;
+
+static HANDLE: OnceLock<reload::Handle<Option<OtelLayer>, Registry>> = OnceLock::new();
+
+/// 导出器需要在进程生命周期内保持存活
+static PROVIDER: OnceLock<SdkTracerProvider> = OnceLock::new();
+
+/// 创建占位的 OpenTelemetry layer（须直接叠加在 `Registry` 上）
+pub fn layer() -> reload::Layer<Option<OtelLayer>, Registry> {
+    let (layer, handle) = reload::Layer::new(None);
+    let _ = HANDLE.set(handle);
+    layer
+}
+
+/// 按配置启用 OTLP 导出
+pub fn init(config: &OtlpConfig) -> anyhow::Result<()> {
+    let Some(handle) = HANDLE.get() else {
+        anyhow::bail!("日志订阅器未预留 OpenTelemetry layer");
+    };
+
+    let exporter = opentelemetry_otlp::SpanExporter::builder()
+        .with_http()
+        .with_endpoint(&config.endpoint)
+        .with_headers(config.headers.clone())
+        .with_timeout(Duration::from_secs(EXPORT_TIMEOUT_SECS))
+        .build()?;
+    let provider = SdkTracerProvider::builder()
+        .with_batch_exporter(exporter)
+        .with_sampler(Sampler::TraceIdRatioBased(
+            config.sample_ratio.clamp(0.0, 1.0),
+        ))
+        .with_resource(
+            Resource::builder()
+                .with_service_name(config.service_name.clone())
+                .build(),
+        )
+        .build();
+
+    let tracer = provider.tracer("kiro-rs");
+    handle.reload(Some(tracing_opentelemetry::layer().with_tracer(tracer)))?;
+    let _ = PROVIDER.set(provider);
+    Ok(())
+}
@@ -3,9 +3,21 @@
 //! 提供统一的 HTTP Client 构建功能，支持代理配置
 
 use reqwest::{Client, Proxy};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use std::sync::OnceLock;
 use std::time::Duration;
 
-use crate::model::config::TlsBackend;
+use crate::model::config::{Config, IpFamily, TlsBackend};
+
+/// 上游连接的 IP 协议族（进程级，启动时设置一次）
+static IP_FAMILY: OnceLock<IpFamily> = OnceLock::new();
+
+/// 设置上游连接使用的 IP 协议族
+///
+/// 应在构建任何 Client 之前调用；未调用时为 `Auto`
+pub fn init_ip_family(family: IpFamily) {
+    let _ = IP_FAMILY.set(family);
+}
 
 /// 代理配置
 #[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
@@ -34,6 +46,17 @@ impl ProxyConfig {
         self.password = Some(password.into());
         self
     }
+
+    /// 从应用配置构建全局代理配置（未配置 proxyUrl 时返回 None）
+    pub fn from_config(config: &Config) -> Option<Self> {
+        config.proxy_url.as_ref().map(|url| {
+            let proxy = Self::new(url);
+            match (&config.proxy_username, &config.proxy_password) {
+                (Some(username), Some(password)) => proxy.with_auth(username, password),
+                _ => proxy,
+            }
+        })
+    }
 }
 
 /// 构建 HTTP Client
@@ -42,6 +65,10 @@ impl ProxyConfig {
 /// * `proxy` - 可选的代理配置
 /// * `timeout_secs` - 超时时间（秒）
 ///
+/// IP 协议族由 [`init_ip_family`] 决定：
+/// - `Auto`：reqwest 默认的 happy eyeballs，同时解析 A/AAAA 并优先使用先连上的地址
+/// - `Ipv4` / `Ipv6`：绑定对应协议族的本地地址，只连接该协议族的远端地址
+///
 /// # Returns
 /// 配置好的 reqwest::Client
 pub fn build_client(
@@ -51,6 +78,16 @@ pub fn build_client(
 ) -> anyhow::Result<Client> {
     let mut builder = Client::builder().timeout(Duration::from_secs(timeout_secs));
 
+    match IP_FAMILY.get().copied().unwrap_or_default() {
+        IpFamily::Auto => {}
+        IpFamily::Ipv4 => {
+            builder = builder.local_address(IpAddr::V4(Ipv4Addr::UNSPECIFIED));
+        }
+        IpFamily::Ipv6 => {
+            builder = builder.local_address(IpAddr::V6(Ipv6Addr::UNSPECIFIED));
+        }
+    }
+
     match tls_backend {
         TlsBackend::Rustls => {
             builder = builder.use_rustls_tls();
@@ -0,0 +1,244 @@
+//! 按凭据的熔断器
+//!
+//! 状态流转：
+//! - Closed：正常调度，记录滑动窗口内的上游 5xx / 超时次数，达到阈值后转为 Open
+//! - Open：冷却期内不调度该凭据，流量由其他凭据承接
+//! - HalfOpen：冷却结束后放行一个探测请求，成功则回到 Closed，失败则重新进入 Open
+
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+use serde::Serialize;
+
+use crate::model::config::CircuitBreakerConfig;
+
+/// 熔断状态
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum BreakerState {
+    Closed,
+    Open,
+    HalfOpen,
+}
+
+/// 熔断器状态快照（用于 Admin API）
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BreakerSnapshot {
+    pub state: BreakerState,
+    /// 当前窗口内的错误次数
+    pub recent_failures: usize,
+    /// 距冷却结束的秒数（仅 Open 状态）
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cooldown_remaining_secs: Option<u64>,
+    /// 累计熔断次数
+    pub trips: u64,
+}
+
+/// 单个凭据的熔断器（未配置时始终放行）
+#[derive(Debug, Clone, Default)]
+pub struct CircuitBreaker {
+    config: Option<CircuitBreakerConfig>,
+    failures: VecDeque<Instant>,
+    opened_at: Option<Instant>,
+    /// 半开状态下探测请求的发出时间
+    probe_started: Option<Instant>,
+    trips: u64,
+}
+
+impl CircuitBreaker {
+    pub fn new(config: Option<CircuitBreakerConfig>) -> Self {
+        Self {
+            config,
+            ..Default::default()
+        }
+    }
+
+    fn cooldown(config: &CircuitBreakerConfig) -> Duration {
+        Duration::from_secs(config.cooldown_secs)
+    }
+
+    /// 当前状态
+    pub fn state(&self, now: Instant) -> BreakerState {
+        let (Some(config), Some(opened_at)) = (&self.config, self.opened_at) else {
+            return BreakerState::Closed;
+        };
+        if now.duration_since(opened_at) < Self::cooldown(config) {
+            BreakerState::Open
+        } else {
+            BreakerState::HalfOpen
+        }
+    }
+
+    /// 是否允许调度新请求
+    ///
+    /// 半开状态只放行一个探测请求；探测超过冷却时间仍无结果（如客户端中途断开）时允许重新探测
+    pub fn allows(&self, now: Instant) -> bool {
+        match self.state(now) {
+            BreakerState::Closed => true,
+            BreakerState::Open => false,
+            BreakerState::HalfOpen => match (&self.config, self.probe_started) {
+                (Some(config), Some(started)) => {
+                    now.duration_since(started) >= Self::cooldown(config)
+                }
+                _ => true,
+            },
+        }
+    }
+
+    /// 请求已分配给该凭据（半开状态下记为探测请求）
+    pub fn on_dispatch(&mut self, now: Instant) {
+        if self.state(now) == BreakerState::HalfOpen {
+            self.probe_started = Some(now);
+        }
+    }
+
+    /// 记录成功，返回是否由半开恢复为关闭
+    pub fn record_success(&mut self, now: Instant) -> bool {
+        match self.state(now) {
+            BreakerState::Closed => {
+                self.failures.clear();
+                false
+            }
+            // 熔断前发出的请求晚到的成功结果不作为恢复依据
+            BreakerState::Open => false,
+            BreakerState::HalfOpen => {
+                self.opened_at = None;
+                self.probe_started = None;
+                self.failures.clear();
+                true
+            }
+        }
+    }
+
+    /// 记录一次上游 5xx / 超时错误，返回是否因此进入熔断
+    pub fn record_failure(&mut self, now: Instant) -> bool {
+        let Some(config) = self.config else {
+            return false;
+        };
+        match self.state(now) {
+            BreakerState::Open => false,
+            BreakerState::HalfOpen => {
+                self.trip(now);
+                true
+            }
+            BreakerState::Closed => {
+                let window = Duration::from_secs(config.window_secs);
+                self.failures.push_back(now);
+                while self
+                    .failures
+                    .front()
+                    .is_some_and(|t| now.duration_since(*t) > window)
+                {
+                    self.failures.pop_front();
+                }
+                if self.failures.len() >= config.failure_threshold.max(1) as usize {
+                    self.trip(now);
+                    true
+                } else {
+                    false
+                }
+            }
+        }
+    }
+
+    fn trip(&mut self, now: Instant) {
+        self.opened_at = Some(now);
+        self.probe_started = None;
+        self.failures.clear();
+        self.trips += 1;
+    }
+
+    /// 状态快照（未配置熔断时返回 None）
+    pub fn snapshot(&self, now: Instant) -> Option<BreakerSnapshot> {
+        let config = self.config.as_ref()?;
+        let state = self.state(now);
+        let cooldown_remaining_secs = match (state, self.opened_at) {
+            (BreakerState::Open, Some(opened_at)) => Some(
+                Self::cooldown(config)
+                    .saturating_sub(now.duration_since(opened_at))
+                    .as_secs(),
+            ),
+            _ => None,
+        };
+        Some(BreakerSnapshot {
+            state,
+            recent_failures: self.failures.len(),
+            cooldown_remaining_secs,
+            trips: self.trips,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn breaker() -> CircuitBreaker {
+        CircuitBreaker::new(Some(CircuitBreakerConfig {
+            failure_threshold: 3,
+            window_secs: 60,
+            cooldown_secs: 30,
+        }))
+    }
+
+    #[test]
+    fn test_opens_after_threshold_and_recovers_via_probe() {
+        let mut breaker = breaker();
+        let t0 = Instant::now();
+        assert!(!breaker.record_failure(t0));
+        assert!(!breaker.record_failure(t0));
+        assert!(breaker.record_failure(t0));
+        assert!(!breaker.allows(t0 + Duration::from_secs(10)));
+
+        // 冷却结束：只放行一个探测请求
+        let t1 = t0 + Duration::from_secs(31);
+        assert_eq!(breaker.state(t1), BreakerState::HalfOpen);
+        assert!(breaker.allows(t1));
+        breaker.on_dispatch(t1);
+        assert!(!breaker.allows(t1));
+
+        assert!(breaker.record_success(t1));
+        assert_eq!(breaker.state(t1), BreakerState::Closed);
+        assert_eq!(breaker.snapshot(t1).unwrap().trips, 1);
+    }
+
+    #[test]
+    fn test_failed_probe_reopens_and_old_failures_expire() {
+        let mut breaker = breaker();
+        let t0 = Instant::now();
+        breaker.record_failure(t0);
+        breaker.record_failure(t0);
+        // 超出窗口的错误不计入
+        assert!(!breaker.record_failure(t0 + Duration::from_secs(61)));
+        assert_eq!(
+            breaker.state(t0 + Duration::from_secs(61)),
+            BreakerState::Closed
+        );
+
+        let t1 = t0 + Duration::from_secs(62);
+        breaker.record_failure(t1);
+        breaker.record_failure(t1);
+        assert_eq!(breaker.state(t1), BreakerState::Open);
+
+        let t2 = t1 + Duration::from_secs(30);
+        breaker.on_dispatch(t2);
+        assert!(breaker.record_failure(t2));
+        assert_eq!(breaker.state(t2), BreakerState::Open);
+        assert_eq!(
+            breaker.snapshot(t2).unwrap().cooldown_remaining_secs,
+            Some(30)
+        );
+    }
+
+    #[test]
+    fn test_disabled_breaker_always_allows() {
+        let mut breaker = CircuitBreaker::new(None);
+        let now = Instant::now();
+        for _ in 0..10 {
+            assert!(!breaker.record_failure(now));
+        }
+        assert!(breaker.allows(now));
+        assert!(breaker.snapshot(now).is_none());
+    }
+}
@@ -0,0 +1,525 @@
+//! 按凭据的并发控制
+//!
+//! 每个凭据的在途请求数受两类上限约束（取较小者）：
+//! - 静态上限 `maxInFlightPerCredential`
+//! - 自适应上限（AIMD，配置 `adaptiveConcurrency` 后启用）：
+//!   - 请求成功且首字节延迟正常：加性增加（每完成约 `limit` 个请求上限 +1）
+//!   - 上游返回 429：乘性减少（减半，1 秒内多次限流只减一次）
+//!   - 首字节延迟超过基线的 `latencyTolerance` 倍：小幅下调（×0.9，限频）
+//!
+//! 在途请求达到上限时，新请求进入该凭据的等待队列（有界）；
+//! 队列已满或等待超时返回 [`ConcurrencyLimitError`]。
+//! 在途许可随响应体一起释放（流式响应在事件流读取结束后释放）。
+
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::sync::{Arc, OnceLock};
+use std::time::{Duration, Instant};
+
+use parking_lot::Mutex;
+use serde::Serialize;
+use tokio::sync::Notify;
+
+use crate::model::config::{AdaptiveConcurrencyConfig, Config};
+
+/// 两次 429 减半之间的最小间隔
+const THROTTLE_DECREASE_INTERVAL: Duration = Duration::from_secs(1);
+
+/// 两次延迟下调之间的最小间隔
+const LATENCY_DECREASE_INTERVAL: Duration = Duration::from_secs(5);
+
+/// 延迟下调系数
+const LATENCY_DECREASE_FACTOR: f64 = 0.9;
+
+/// 延迟 EWMA 平滑系数
+const LATENCY_EWMA_ALPHA: f64 = 0.2;
+
+static CONTROLLER: OnceLock<ConcurrencyController> = OnceLock::new();
+
+/// 等待并发名额失败
+#[derive(Debug)]
+pub enum ConcurrencyLimitError {
+    /// 等待队列已满
+    QueueFull { credential_id: u64 },
+    /// 等待超时
+    QueueTimeout {
+        credential_id: u64,
+        waited: Duration,
+    },
+}
+
+impl fmt::Display for ConcurrencyLimitError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::QueueFull { credential_id } => {
+                write!(f, "凭据 #{} 并发已满且等待队列已满", credential_id)
+            }
+            Self::QueueTimeout {
+                credential_id,
+                waited,
+            } => write!(
+                f,
+                "凭据 #{} 等待并发名额超时（{}s）",
+                credential_id,
+                waited.as_secs()
+            ),
+        }
+    }
+}
+
+impl std::error::Error for ConcurrencyLimitError {}
+
+/// 静态上限与等待队列配置
+#[derive(Debug, Clone)]
+struct QueueLimits {
+    /// 每个凭据的最大在途请求数，0 表示不限制
+    max_in_flight: usize,
+    /// 每个凭据的最大等待请求数
+    queue_size: usize,
+    queue_timeout: Duration,
+}
+
+/// 初始化并发控制（未配置静态上限与自适应控制时不做任何事）
+pub fn init(config: &Config) {
+    if config.max_in_flight_per_credential == 0 && config.adaptive_concurrency.is_none() {
+        return;
+    }
+    let limits = QueueLimits {
+        max_in_flight: config.max_in_flight_per_credential,
+        queue_size: config.concurrency_queue_size,
+        queue_timeout: Duration::from_secs(config.concurrency_queue_timeout_secs),
+    };
+    let _ = CONTROLLER.set(ConcurrencyController::new(
+        config.adaptive_concurrency.as_ref(),
+        limits,
+    ));
+}
+
+/// 为凭据获取在途许可（未启用时返回 `Ok(None)`）
+pub async fn acquire(credential_id: u64) -> Result<Option<InFlightPermit>, ConcurrencyLimitError> {
+    match CONTROLLER.get() {
+        Some(controller) => controller.acquire(credential_id).await.map(Some),
+        None => Ok(None),
+    }
+}
+
+/// 当前各凭据的并发状态
+pub fn report() -> ConcurrencyReport {
+    match CONTROLLER.get() {
+        Some(controller) => ConcurrencyReport {
+            enabled: true,
+            adaptive: controller.adaptive.is_some(),
+            max_in_flight: (controller.limits.max_in_flight > 0)
+                .then_some(controller.limits.max_in_flight),
+            queue_size: controller.limits.queue_size,
+            queue_timeout_secs: controller.limits.queue_timeout.as_secs(),
+            credentials: controller.snapshot(),
+        },
+        None => ConcurrencyReport {
+            enabled: false,
+            adaptive: false,
+            max_in_flight: None,
+            queue_size: 0,
+            queue_timeout_secs: 0,
+            credentials: Vec::new(),
+        },
+    }
+}
+
+/// 并发状态汇总
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ConcurrencyReport {
+    pub enabled: bool,
+    /// 是否启用自适应上限
+    pub adaptive: bool,
+    /// 静态上限（未配置时为 null）
+    pub max_in_flight: Option<usize>,
+    pub queue_size: usize,
+    pub queue_timeout_secs: u64,
+    pub credentials: Vec<CredentialConcurrency>,
+}
+
+/// 单个凭据的并发状态
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CredentialConcurrency {
+    pub id: u64,
+    /// 自适应上限（AIMD 计算值，未启用时为 null）
+    pub limit: Option<f64>,
+    /// 实际生效的上限（自适应上限向下取整与静态上限中的较小者）
+    pub effective_limit: usize,
+    pub in_flight: usize,
+    /// 正在等待名额的请求数
+    pub waiting: usize,
+    /// 累计收到的上游限流次数
+    pub throttled: u64,
+    /// 首字节延迟 EWMA（毫秒）
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub latency_ms: Option<u64>,
+    /// 首字节延迟基线（毫秒）
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub baseline_latency_ms: Option<u64>,
+}
+
+#[derive(Debug)]
+struct LimiterState {
+    limit: f64,
+    in_flight: usize,
+    waiting: usize,
+    throttled: u64,
+    latency_ewma_ms: Option<f64>,
+    baseline_ms: Option<f64>,
+    last_decrease: Option<Instant>,
+}
+
+/// 按凭据的并发控制器
+pub struct ConcurrencyController {
+    /// 自适应上限配置（None 表示仅使用静态上限）
+    adaptive: Option<AdaptiveConcurrencyConfig>,
+    limits: QueueLimits,
+    states: Mutex<HashMap<u64, LimiterState>>,
+    released: Notify,
+}
+
+impl ConcurrencyController {
+    fn new(adaptive: Option<&AdaptiveConcurrencyConfig>, limits: QueueLimits) -> Self {
+        let adaptive = adaptive.map(|config| {
+            let mut config = config.clone();
+            config.min_limit = config.min_limit.max(1);
+            config.max_limit = config.max_limit.max(config.min_limit);
+            config.initial_limit = config
+                .initial_limit
+                .clamp(config.min_limit, config.max_limit);
+            config.latency_tolerance = config.latency_tolerance.max(1.0);
+            config
+        });
+        Self {
+            adaptive,
+            limits,
+            states: Mutex::new(HashMap::new()),
+            released: Notify::new(),
+        }
+    }
+
+    fn with_state<R>(&self, id: u64, f: impl FnOnce(&mut LimiterState) -> R) -> R {
+        let initial = self
+            .adaptive
+            .as_ref()
+            .map(|c| c.initial_limit as f64)
+            .unwrap_or_default();
+        let mut states = self.states.lock();
+        let state = states.entry(id).or_insert_with(|| LimiterState {
+            limit: initial,
+            in_flight: 0,
+            waiting: 0,
+            throttled: 0,
+            latency_ewma_ms: None,
+            baseline_ms: None,
+            last_decrease: None,
+        });
+        f(state)
+    }
+
+    /// 实际生效的上限
+    fn effective_limit(&self, state: &LimiterState) -> usize {
+        let adaptive = match self.adaptive {
+            Some(_) => (state.limit.floor() as usize).max(1),
+            None => usize::MAX,
+        };
+        match self.limits.max_in_flight {
+            0 => adaptive,
+            max => adaptive.min(max),
+        }
+    }
+
+    /// 未达上限时占用一个名额
+    fn try_acquire(&self, id: u64) -> bool {
+        self.with_state(id, |state| {
+            if state.in_flight < self.effective_limit(state) {
+                state.in_flight += 1;
+                true
+            } else {
+                false
+            }
+        })
+    }
+
+    async fn acquire(&'static self, id: u64) -> Result<InFlightPermit, ConcurrencyLimitError> {
+        if !self.try_acquire(id) {
+            let queued = self.with_state(id, |state| {
+                if state.waiting >= self.limits.queue_size {
+                    return false;
+                }
+                state.waiting += 1;
+                true
+            });
+            if !queued {
+                return Err(ConcurrencyLimitError::QueueFull { credential_id: id });
+            }
+            // 请求被取消（客户端断开）时同样需要离开队列
+            let _slot = QueueSlot {
+                controller: self,
+                id,
+            };
+            self.wait(id).await?;
+        }
+        Ok(InFlightPermit(Arc::new(PermitInner {
+            controller: self,
+            id,
+        })))
+    }
+
+    /// 在队列中等待空闲名额
+    async fn wait(&self, id: u64) -> Result<(), ConcurrencyLimitError> {
+        let started = tokio::time::Instant::now();
+        let deadline = started + self.limits.queue_timeout;
+        loop {
+            // 先注册通知再检查，避免检查与等待之间的释放被错过
+            let notified = self.released.notified();
+            tokio::pin!(notified);
+            notified.as_mut().enable();
+
+            if self.try_acquire(id) {
+                return Ok(());
+            }
+            if tokio::time::timeout_at(deadline, notified).await.is_err() {
+                return Err(ConcurrencyLimitError::QueueTimeout {
+                    credential_id: id,
+                    waited: started.elapsed(),
+                });
+            }
+        }
+    }
+
+    fn release(&self, id: u64) {
+        self.with_state(id, |state| {
+            state.in_flight = state.in_flight.saturating_sub(1)
+        });
+        self.released.notify_waiters();
+    }
+
+    /// 请求成功：根据首字节延迟加性增加或小幅下调
+    fn on_success(&self, id: u64, latency: Duration) {
+        let Some(config) = &self.adaptive else {
+            return;
+        };
+        let latency_ms = latency.as_secs_f64() * 1000.0;
+        let (min, max, tolerance) = (
+            config.min_limit as f64,
+            config.max_limit as f64,
+            config.latency_tolerance,
+        );
+        let grew = self.with_state(id, |state| {
+            state.latency_ewma_ms = Some(match state.latency_ewma_ms {
+                Some(ewma) => ewma + LATENCY_EWMA_ALPHA * (latency_ms - ewma),
+                None => latency_ms,
+            });
+            // 基线取近期最小延迟，并缓慢向上漂移以适应长期变化
+            let baseline = match state.baseline_ms {
+                Some(b) if latency_ms < b => latency_ms,
+                Some(b) => b + 0.01 * (latency_ms - b),
+                None => latency_ms,
+            };
+            state.baseline_ms = Some(baseline);
+
+            if latency_ms > baseline * tolerance {
+                let due = state
+                    .last_decrease
+                    .is_none_or(|t| t.elapsed() >= LATENCY_DECREASE_INTERVAL);
+                if due {
+                    state.limit = (state.limit * LATENCY_DECREASE_FACTOR).max(min);
+                    state.last_decrease = Some(Instant::now());
+                }
+                false
+            } else {
+                let before = state.limit.floor();
+                state.limit = (state.limit + 1.0 / state.limit).min(max);
+                state.limit.floor() > before
+            }
+        });
+        if grew {
+            // 上限增加后唤醒等待者
+            self.released.notify_waiters();
+        }
+    }
+
+    /// 上游限流：乘性减少
+    fn on_throttled(&self, id: u64) {
+        let min = self.adaptive.as_ref().map(|c| c.min_limit as f64);
+        self.with_state(id, |state| {
+            state.throttled += 1;
+            let Some(min) = min else {
+                return;
+            };
+            let due = state
+                .last_decrease
+                .is_none_or(|t| t.elapsed() >= THROTTLE_DECREASE_INTERVAL);
+            if due {
+                state.limit = (state.limit / 2.0).max(min);
+                state.last_decrease = Some(Instant::now());
+                tracing::info!(
+                    "凭据 #{} 收到上游限流，并发上限下调至 {:.1}",
+                    id,
+                    state.limit
+                );
+            }
+        });
+    }
+
+    fn snapshot(&self) -> Vec<CredentialConcurrency> {
+        let states = self.states.lock();
+        let sorted: BTreeMap<_, _> = states.iter().collect();
+        sorted
+            .into_iter()
+            .map(|(&id, s)| CredentialConcurrency {
+                id,
+                limit: self
+                    .adaptive
+                    .as_ref()
+                    .map(|_| (s.limit * 100.0).round() / 100.0),
+                effective_limit: self.effective_limit(s),
+                in_flight: s.in_flight,
+                waiting: s.waiting,
+                throttled: s.throttled,
+                latency_ms: s.latency_ewma_ms.map(|v| v.round() as u64),
+                baseline_latency_ms: s.baseline_ms.map(|v| v.round() as u64),
+            })
+            .collect()
+    }
+}
+
+/// 等待队列中的位置（离开队列时释放）
+struct QueueSlot {
+    controller: &'static ConcurrencyController,
+    id: u64,
+}
+
+impl Drop for QueueSlot {
+    fn drop(&mut self) {
+        self.controller.with_state(self.id, |state| {
+            state.waiting = state.waiting.saturating_sub(1)
+        });
+    }
+}
+
+struct PermitInner {
+    controller: &'static ConcurrencyController,
+    id: u64,
+}
+
+impl Drop for PermitInner {
+    fn drop(&mut self) {
+        self.controller.release(self.id);
+    }
+}
+
+/// 在途请求许可（最后一个克隆被丢弃时释放名额）
+///
+/// 可存入 `reqwest::Response` 的 extensions，随响应体一起释放
+#[derive(Clone)]
+pub struct InFlightPermit(Arc<PermitInner>);
+
+impl InFlightPermit {
+    /// 报告请求成功及首字节延迟
+    pub fn record_success(&self, latency: Duration) {
+        self.0.controller.on_success(self.0.id, latency);
+    }
+
+    /// 报告上游限流
+    pub fn record_throttled(&self) {
+        self.0.controller.on_throttled(self.0.id);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn limits(max_in_flight: usize, queue_size: usize) -> QueueLimits {
+        QueueLimits {
+            max_in_flight,
+            queue_size,
+            queue_timeout: Duration::from_millis(50),
+        }
+    }
+
+    fn adaptive(initial: u32, min: u32, max: u32) -> &'static ConcurrencyController {
+        let config = AdaptiveConcurrencyConfig {
+            initial_limit: initial,
+            min_limit: min,
+            max_limit: max,
+            latency_tolerance: 2.0,
+        };
+        Box::leak(Box::new(ConcurrencyController::new(
+            Some(&config),
+            limits(0, 8),
+        )))
+    }
+
+    #[test]
+    fn test_additive_increase_and_multiplicative_decrease() {
+        let c = adaptive(2, 1, 4);
+        for _ in 0..20 {
+            c.on_success(1, Duration::from_millis(100));
+        }
+        assert_eq!(c.snapshot()[0].limit, Some(4.0));
+
+        c.on_throttled(1);
+        assert_eq!(c.snapshot()[0].limit, Some(2.0));
+        // 1 秒内的连续限流只减一次
+        c.on_throttled(1);
+        let state = &c.snapshot()[0];
+        assert_eq!((state.limit, state.throttled), (Some(2.0), 2));
+    }
+
+    #[test]
+    fn test_high_latency_decreases_limit() {
+        let c = adaptive(4, 1, 8);
+        c.on_success(1, Duration::from_millis(100));
+        c.on_success(1, Duration::from_millis(1000));
+        assert!(c.snapshot()[0].limit.unwrap() < 4.0);
+    }
+
+    #[tokio::test]
+    async fn test_acquire_waits_for_release() {
+        let c = adaptive(1, 1, 1);
+        let first = c.acquire(7).await.unwrap();
+        assert!(!c.try_acquire(7));
+
+        let waiter = tokio::spawn(c.acquire(7));
+        tokio::task::yield_now().await;
+        drop(first);
+        let second = tokio::time::timeout(Duration::from_secs(1), waiter)
+            .await
+            .unwrap()
+            .unwrap()
+            .unwrap();
+        assert_eq!(c.snapshot()[0].in_flight, 1);
+        drop(second);
+        assert_eq!((c.snapshot()[0].in_flight, c.snapshot()[0].waiting), (0, 0));
+    }
+
+    #[tokio::test]
+    async fn test_static_limit_queue_full_and_timeout() {
+        let c: &'static ConcurrencyController =
+            Box::leak(Box::new(ConcurrencyController::new(None, limits(1, 1))));
+        let _first = c.acquire(1).await.unwrap();
+
+        let queued = tokio::spawn(c.acquire(1));
+        tokio::task::yield_now().await;
+        assert!(matches!(
+            c.acquire(1).await,
+            Err(ConcurrencyLimitError::QueueFull { credential_id: 1 })
+        ));
+        assert!(matches!(
+            queued.await.unwrap(),
+            Err(ConcurrencyLimitError::QueueTimeout { .. })
+        ));
+        let state = &c.snapshot()[0];
+        assert_eq!(
+            (state.limit, state.effective_limit, state.waiting),
+            (None, 1, 0)
+        );
+    }
+}
@@ -0,0 +1,427 @@
+//! 交互式添加凭据（`kiro-rs credentials add`）
+//!
+//! 手动编辑 credentials.json 时常出现 refreshToken 被截断、JSON 格式错误等问题。
+//! 向导逐步询问认证方式与凭据字段，可选地刷新 Token 并查询额度以验证凭据，
+//! 确认后先备份原文件，再以数组格式写入临时文件并原子替换。
+
+use std::io::{BufRead, Write};
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, bail};
+use serde_json::Value;
+
+use crate::http_client::{self, ProxyConfig};
+use crate::kiro::model::credentials::KiroCredentials;
+use crate::kiro::token_manager;
+use crate::model::config::Config;
+
+/// 执行向导，返回进程退出码
+pub fn run(config: &Config, credentials_path: &Path) -> i32 {
+    let stdin = std::io::stdin();
+    let mut prompt = Prompt::new(stdin.lock(), std::io::stdout());
+    match wizard(&mut prompt, config, credentials_path) {
+        Ok(true) => 0,
+        Ok(false) => {
+            println!("已取消，未修改 {}", credentials_path.display());
+            1
+        }
+        Err(e) => {
+            eprintln!("添加凭据失败: {:#}", e);
+            1
+        }
+    }
+}
+
+/// 终端问答
+struct Prompt<R, W> {
+    input: R,
+    output: W,
+}
+
+impl<R: BufRead, W: Write> Prompt<R, W> {
+    fn new(input: R, output: W) -> Self {
+        Self { input, output }
+    }
+
+    fn say(&mut self, message: &str) -> anyhow::Result<()> {
+        writeln!(self.output, "{}", message)?;
+        Ok(())
+    }
+
+    /// 提问并读取一行（去除首尾空白），输入结束时返回错误
+    fn ask(&mut self, question: &str) -> anyhow::Result<String> {
+        write!(self.output, "{}", question)?;
+        self.output.flush()?;
+        let mut line = String::new();
+        if self.input.read_line(&mut line)? == 0 {
+            bail!("输入已结束");
+        }
+        Ok(line.trim().to_string())
+    }
+
+    /// 提问可选值，直接回车时返回 None
+    fn ask_optional(&mut self, question: &str) -> anyhow::Result<Option<String>> {
+        let answer = self.ask(question)?;
+        Ok((!answer.is_empty()).then_some(answer))
+    }
+
+    /// 是/否确认，直接回车时取默认值
+    fn confirm(&mut self, question: &str, default: bool) -> anyhow::Result<bool> {
+        let hint = if default { "Y/n" } else { "y/N" };
+        loop {
+            let answer = self.ask(&format!("{} [{}]: ", question, hint))?;
+            match answer.to_lowercase().as_str() {
+                "" => return Ok(default),
+                "y" | "yes" => return Ok(true),
+                "n" | "no" => return Ok(false),
+                _ => self.say("请输入 y 或 n")?,
+            }
+        }
+    }
+
+    /// 读取密钥类输入，无效时重新询问
+    fn ask_secret(
+        &mut self,
+        question: &str,
+        check: impl Fn(&str) -> anyhow::Result<()>,
+    ) -> anyhow::Result<String> {
+        loop {
+            let value = clean_secret(&self.ask(question)?);
+            match check(&value) {
+                Ok(()) => return Ok(value),
+                Err(e) => self.say(&format!("无效: {:#}，请重新粘贴", e))?,
+            }
+        }
+    }
+}
+
+/// 清理粘贴的密钥：去掉两侧引号（含末尾逗号）与所有空白（终端折行可能带入换行或空格）
+fn clean_secret(raw: &str) -> String {
+    raw.trim()
+        .trim_end_matches(',')
+        .trim_matches(|c| c == '"' || c == '\'')
+        .chars()
+        .filter(|c| !c.is_whitespace())
+        .collect()
+}
+
+/// 向导主流程，返回是否已写入
+fn wizard<R: BufRead, W: Write>(
+    prompt: &mut Prompt<R, W>,
+    config: &Config,
+    credentials_path: &Path,
+) -> anyhow::Result<bool> {
+    let existing = read_existing(credentials_path)?;
+    prompt.say(&format!("添加凭据到 {}", credentials_path.display()))?;
+
+    let mut credentials = collect(prompt)?;
+    // 尽早发现重复凭据，避免白白验证
+    append_credential(&existing, &credentials)?;
+
+    if prompt.confirm("是否刷新 Token 并查询额度以验证凭据？", true)? {
+        prompt.say("正在验证...")?;
+        match verify(&credentials, config) {
+            Ok((verified, summary)) => {
+                credentials = verified;
+                prompt.say(&format!("验证通过: {}", summary))?;
+            }
+            Err(e) => {
+                prompt.say(&format!("验证失败: {:#}", e))?;
+                if !prompt.confirm("仍要写入该凭据？", false)? {
+                    return Ok(false);
+                }
+            }
+        }
+    }
+
+    if !prompt.confirm(&format!("写入 {}？", credentials_path.display()), true)? {
+        return Ok(false);
+    }
+    // 重新读取，避免覆盖向导运行期间对文件的修改
+    let existing = read_existing(credentials_path)?;
+    let (content, id) = append_credential(&existing, &credentials)?;
+    let backup = write_credentials(credentials_path, &content)?;
+
+    prompt.say(&format!(
+        "已添加凭据 #{} 到 {}",
+        id,
+        credentials_path.display()
+    ))?;
+    if let Some(backup) = backup {
+        prompt.say(&format!("原文件已备份到 {}", backup.display()))?;
+    }
+    prompt.say("服务运行中时请重启服务以加载新凭据（运行中的服务回写凭据时可能覆盖本次修改，也可改用 Admin API 添加）")?;
+    Ok(true)
+}
+
+/// 询问认证方式与凭据字段
+fn collect<R: BufRead, W: Write>(prompt: &mut Prompt<R, W>) -> anyhow::Result<KiroCredentials> {
+    prompt.say("认证方式:")?;
+    prompt.say("  1) social  - Kiro IDE 使用 Google / GitHub 登录")?;
+    prompt.say("  2) idc     - AWS Builder ID / IAM Identity Center")?;
+    prompt.say("  3) api_key - Kiro API Key")?;
+    let auth_method = loop {
+        match prompt.ask("请选择 [1]: ")?.as_str() {
+            "" | "1" | "social" => break "social",
+            "2" | "idc" => break "idc",
+            "3" | "api_key" => break "api_key",
+            _ => prompt.say("请输入 1、2 或 3")?,
+        }
+    };
+
+    let mut credentials = KiroCredentials {
+        auth_method: Some(auth_method.to_string()),
+        ..Default::default()
+    };
+    if auth_method == "api_key" {
+        let key = prompt.ask_secret("kiroApiKey: ", |key| {
+            if key.is_empty() {
+                bail!("kiroApiKey 为空");
+            }
+            Ok(())
+        })?;
+        credentials.kiro_api_key = Some(key);
+    } else {
+        let token = prompt.ask_secret("refreshToken（完整粘贴）: ", |token| {
+            token_manager::validate_refresh_token(&KiroCredentials {
+                refresh_token: Some(token.to_string()),
+                ..Default::default()
+            })
+        })?;
+        credentials.refresh_token = Some(token);
+    }
+
+    if auth_method == "idc" {
+        credentials.client_id = Some(prompt.ask_secret("clientId: ", non_empty)?);
+        credentials.client_secret = Some(prompt.ask_secret("clientSecret: ", non_empty)?);
+        credentials.region = prompt.ask_optional("region（回车使用 config.json 的 region）: ")?;
+    }
+
+    credentials.priority = loop {
+        let answer = prompt.ask("priority（数字越小越优先）[0]: ")?;
+        if answer.is_empty() {
+            break 0;
+        }
+        match answer.parse() {
+            Ok(priority) => break priority,
+            Err(_) => prompt.say("请输入非负整数")?,
+        }
+    };
+    Ok(credentials)
+}
+
+fn non_empty(value: &str) -> anyhow::Result<()> {
+    if value.is_empty() {
+        bail!("不能为空");
+    }
+    Ok(())
+}
+
+/// 刷新 Token（API Key 凭据跳过）并查询额度，返回刷新后的凭据与额度摘要
+fn verify(
+    credentials: &KiroCredentials,
+    config: &Config,
+) -> anyhow::Result<(KiroCredentials, String)> {
+    http_client::init_ip_family(config.upstream_ip_family);
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .context("创建 tokio 运行时失败")?;
+    runtime.block_on(async {
+        let global_proxy = ProxyConfig::from_config(config);
+        let proxy = credentials.effective_proxy(global_proxy.as_ref());
+
+        let (mut credentials, token) = if credentials.is_api_key_credential() {
+            let token = credentials.kiro_api_key.clone().unwrap_or_default();
+            (credentials.clone(), token)
+        } else {
+            // 刷新后的凭据保留用户填写的字段，并可能带有轮换后的 refreshToken
+            let refreshed =
+                token_manager::refresh_token(credentials, config, proxy.as_ref()).await?;
+            let token = refreshed
+                .access_token
+                .clone()
+                .context("刷新响应缺少 accessToken")?;
+            (refreshed, token)
+        };
+
+        let usage =
+            token_manager::get_usage_limits(&credentials, config, &token, proxy.as_ref()).await?;
+        credentials.subscription_title = usage.subscription_title().map(str::to_string);
+
+        let mut summary = format!(
+            "{}，已用 {:.2} / {:.2}",
+            usage.subscription_title().unwrap_or("未知订阅"),
+            usage.current_usage(),
+            usage.usage_limit()
+        );
+        if let Some(reset) = usage
+            .next_date_reset
+            .and_then(|ts| chrono::DateTime::from_timestamp(ts as i64, 0))
+        {
+            summary.push_str(&format!("，{} 重置", reset.format("%Y-%m-%d")));
+        }
+        Ok((credentials, summary))
+    })
+}
+
+/// 读取凭据文件（不存在时视为空）
+fn read_existing(path: &Path) -> anyhow::Result<String> {
+    match std::fs::read_to_string(path) {
+        Ok(text) => Ok(text),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(String::new()),
+        Err(e) => Err(e).with_context(|| format!("读取 {} 失败", path.display())),
+    }
+}
+
+/// 将凭据追加到凭据文件内容，返回新的文件内容（数组格式）与分配的 ID
+///
+/// 已有条目原样保留（包括未知字段）；refreshToken 或 kiroApiKey 重复时拒绝。
+fn append_credential(
+    existing: &str,
+    credentials: &KiroCredentials,
+) -> anyhow::Result<(String, u64)> {
+    let mut entries = if existing.trim().is_empty() {
+        Vec::new()
+    } else {
+        match serde_json::from_str(existing).context("现有凭据文件不是有效的 JSON")? {
+            Value::Array(entries) => entries,
+            entry @ Value::Object(_) => vec![entry],
+            _ => bail!("现有凭据文件应为对象或数组"),
+        }
+    };
+
+    for (field, value) in [
+        ("refreshToken", &credentials.refresh_token),
+        ("kiroApiKey", &credentials.kiro_api_key),
+    ] {
+        let Some(value) = value else { continue };
+        if let Some(index) = entries
+            .iter()
+            .position(|e| e.get(field).and_then(Value::as_str) == Some(value.as_str()))
+        {
+            bail!("凭据文件第 {} 个条目已包含相同的 {}", index + 1, field);
+        }
+    }
+
+    let id = entries
+        .iter()
+        .filter_map(|e| e.get("id").and_then(Value::as_u64))
+        .max()
+        .map_or(1, |max| max + 1);
+    let mut credentials = credentials.clone();
+    credentials.id = Some(id);
+    entries.push(serde_json::to_value(&credentials)?);
+
+    let mut content = serde_json::to_string_pretty(&entries)?;
+    content.push('\n');
+    Ok((content, id))
+}
+
+/// 备份原文件后原子写入，返回备份路径
+fn write_credentials(path: &Path, content: &str) -> anyhow::Result<Option<PathBuf>> {
+    let file_name = path
+        .file_name()
+        .and_then(|n| n.to_str())
+        .context("无效的凭据文件路径")?;
+    let original = std::fs::metadata(path).ok();
+
+    let backup = match &original {
+        Some(_) => {
+            let backup = path.with_file_name(format!(
+                "{}.{}.bak",
+                file_name,
+                chrono::Local::now().format("%Y%m%d-%H%M%S")
+            ));
+            std::fs::copy(path, &backup)
+                .with_context(|| format!("备份到 {} 失败", backup.display()))?;
+            Some(backup)
+        }
+        None => None,
+    };
+
+    let tmp = path.with_file_name(format!("{}.tmp", file_name));
+    std::fs::write(&tmp, content).with_context(|| format!("写入 {} 失败", tmp.display()))?;
+    // 保持原文件权限（凭据文件通常仅所有者可读）
+    if let Some(original) = original {
+        std::fs::set_permissions(&tmp, original.permissions())?;
+    }
+    std::fs::rename(&tmp, path).with_context(|| format!("替换 {} 失败", path.display()))?;
+    Ok(backup)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn token(c: char) -> String {
+        c.to_string().repeat(120)
+    }
+
+    fn temp_dir() -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("kiro-rs-wizard-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn test_clean_secret() {
+        assert_eq!(clean_secret("  \"abc\",\n"), "abc");
+        assert_eq!(clean_secret("'ab c\td'"), "abcd");
+    }
+
+    #[test]
+    fn test_append_credential() {
+        let cred = KiroCredentials {
+            refresh_token: Some(token('a')),
+            ..Default::default()
+        };
+        let (content, id) = append_credential("", &cred).unwrap();
+        assert_eq!(id, 1);
+        assert!(append_credential(&content, &cred).is_err());
+
+        // 单对象格式转为数组，保留未知字段，ID 接在已有最大 ID 之后
+        let single = r#"{"id": 7, "refreshToken": "x", "note": "keep"}"#;
+        let (content, id) = append_credential(single, &cred).unwrap();
+        assert_eq!(id, 8);
+        let entries: Vec<Value> = serde_json::from_str(&content).unwrap();
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0]["note"], "keep");
+        assert_eq!(entries[1]["id"], 8);
+
+        assert!(append_credential("42", &cred).is_err());
+    }
+
+    #[test]
+    fn test_wizard_writes_with_backup() {
+        let dir = temp_dir();
+        let path = dir.join("credentials.json");
+        std::fs::write(&path, r#"{"refreshToken": "old"}"#).unwrap();
+
+        // 截断的 token 会被拒绝并重新询问；跳过验证后写入
+        let input = format!("2\nabc...\n\"{}\"\nclient\nsecret\n\n5\nn\n\n", token('b'));
+        let mut output = Vec::new();
+        let mut prompt = Prompt::new(input.as_bytes(), &mut output);
+        assert!(wizard(&mut prompt, &Config::default(), &path).unwrap());
+
+        let entries: Vec<KiroCredentials> =
+            serde_json::from_str(&std::fs::read_to_string(&path).unwrap()).unwrap();
+        assert_eq!(entries.len(), 2);
+        let added = &entries[1];
+        assert_eq!(added.id, Some(1));
+        assert_eq!(added.auth_method.as_deref(), Some("idc"));
+        assert_eq!(added.refresh_token, Some(token('b')));
+        assert_eq!(added.client_secret.as_deref(), Some("secret"));
+        assert_eq!(added.region, None);
+        assert_eq!(added.priority, 5);
+
+        let backups = std::fs::read_dir(&dir)
+            .unwrap()
+            .filter_map(|e| e.ok())
+            .filter(|e| e.file_name().to_string_lossy().ends_with(".bak"))
+            .count();
+        assert_eq!(backups, 1);
+        assert!(String::from_utf8(output).unwrap().contains("已被截断"));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,217 @@
+//! IdC 设备授权登录
+//!
+//! 通过 AWS SSO OIDC 的 Device Authorization Grant 获取 IdC 凭据：
+//! 1. 注册公共 OIDC 客户端（`/client/register`），得到 clientId / clientSecret
+//! 2. 发起设备授权（`/device_authorization`），用户在浏览器中打开验证地址并输入 user code
+//! 3. 按建议间隔轮询 `/token`，用户确认后得到 refreshToken
+
+use anyhow::bail;
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+
+use crate::http_client::{ProxyConfig, build_client};
+use crate::kiro::model::device_auth::{
+    DeviceTokenRequest, DeviceTokenResponse, OidcErrorResponse, RegisterClientRequest,
+    RegisterClientResponse, StartDeviceAuthorizationRequest, StartDeviceAuthorizationResponse,
+};
+use crate::model::config::Config;
+
+/// AWS Builder ID 的登录地址（未指定 startUrl 时使用）
+pub const BUILDER_ID_START_URL: &str = "https://view.awsapps.com/start";
+
+/// 默认轮询间隔（秒），上游未返回 interval 时使用
+pub const DEFAULT_POLL_INTERVAL_SECS: i64 = 5;
+
+/// 收到 `slow_down` 时追加的轮询间隔（秒）
+pub const SLOW_DOWN_INCREMENT_SECS: i64 = 5;
+
+const DEVICE_CODE_GRANT_TYPE: &str = "urn:ietf:params:oauth:grant-type:device_code";
+
+/// Kiro 使用的 CodeWhisperer 权限范围
+const SCOPES: &[&str] = &[
+    "codewhisperer:completions",
+    "codewhisperer:analysis",
+    "codewhisperer:conversations",
+    "codewhisperer:transformations",
+    "codewhisperer:taskassist",
+];
+
+/// 已发起的设备授权
+#[derive(Debug, Clone)]
+pub struct DeviceAuthorization {
+    pub client_id: String,
+    pub client_secret: String,
+    pub device_code: String,
+    pub user_code: String,
+    pub verification_uri: String,
+    pub verification_uri_complete: Option<String>,
+    /// 设备码有效期（秒）
+    pub expires_in: i64,
+    /// 轮询间隔（秒）
+    pub interval: i64,
+}
+
+/// 单次轮询结果
+#[derive(Debug)]
+pub enum DevicePoll {
+    /// 用户尚未完成授权
+    Pending,
+    /// 轮询过快，需要加大间隔
+    SlowDown,
+    /// 授权完成
+    Complete(DeviceTokenResponse),
+    /// 授权被拒绝或设备码已过期，不应继续轮询
+    Failed(String),
+}
+
+async fn oidc_post<B: Serialize, R: DeserializeOwned>(
+    config: &Config,
+    region: &str,
+    path: &str,
+    body: &B,
+    proxy: Option<&ProxyConfig>,
+) -> anyhow::Result<Result<R, OidcErrorResponse>> {
+    let url = format!("https://oidc.{}.amazonaws.com{}", region, path);
+    let user_agent = format!(
+        "aws-sdk-js/3.980.0 ua/2.1 os/{} lang/js md/nodejs#{} api/sso-oidc#3.980.0 m/E KiroIDE",
+        config.system_version, config.node_version
+    );
+
+    let client = build_client(proxy, 60, config.tls_backend)?;
+    let response = client
+        .post(&url)
+        .header("content-type", "application/json")
+        .header("x-amz-user-agent", "aws-sdk-js/3.980.0 KiroIDE")
+        .header("user-agent", &user_agent)
+        .header("amz-sdk-invocation-id", uuid::Uuid::new_v4().to_string())
+        .header("amz-sdk-request", "attempt=1; max=1")
+        .header("Connection", "close")
+        .json(body)
+        .send()
+        .await?;
+
+    let status = response.status();
+    let body_text = response.text().await.unwrap_or_default();
+    if status.is_success() {
+        return Ok(Ok(serde_json::from_str(&body_text)?));
+    }
+    match serde_json::from_str::<OidcErrorResponse>(&body_text) {
+        Ok(error) if status.as_u16() == 400 => Ok(Err(error)),
+        _ => bail!("OIDC 请求 {} 失败: {} {}", path, status, body_text),
+    }
+}
+
+/// 注册 OIDC 客户端并发起设备授权
+pub async fn start(
+    config: &Config,
+    region: &str,
+    start_url: &str,
+    proxy: Option<&ProxyConfig>,
+) -> anyhow::Result<DeviceAuthorization> {
+    let register = RegisterClientRequest {
+        client_name: "Kiro IDE".to_string(),
+        client_type: "public".to_string(),
+        scopes: SCOPES.iter().map(|s| s.to_string()).collect(),
+        grant_types: vec![
+            DEVICE_CODE_GRANT_TYPE.to_string(),
+            "refresh_token".to_string(),
+        ],
+        issuer_url: start_url.to_string(),
+    };
+    let client: RegisterClientResponse =
+        match oidc_post(config, region, "/client/register", &register, proxy).await? {
+            Ok(client) => client,
+            Err(e) => bail!("注册 OIDC 客户端失败: {}", describe(&e)),
+        };
+
+    let start = StartDeviceAuthorizationRequest {
+        client_id: client.client_id.clone(),
+        client_secret: client.client_secret.clone(),
+        start_url: start_url.to_string(),
+    };
+    let authorization: StartDeviceAuthorizationResponse =
+        match oidc_post(config, region, "/device_authorization", &start, proxy).await? {
+            Ok(authorization) => authorization,
+            Err(e) => bail!("发起设备授权失败: {}", describe(&e)),
+        };
+
+    Ok(DeviceAuthorization {
+        client_id: client.client_id,
+        client_secret: client.client_secret,
+        device_code: authorization.device_code,
+        user_code: authorization.user_code,
+        verification_uri: authorization.verification_uri,
+        verification_uri_complete: authorization.verification_uri_complete,
+        expires_in: authorization.expires_in,
+        interval: authorization
+            .interval
+            .filter(|&secs| secs > 0)
+            .unwrap_or(DEFAULT_POLL_INTERVAL_SECS),
+    })
+}
+
+/// 以设备码轮询一次 Token
+pub async fn poll(
+    config: &Config,
+    region: &str,
+    authorization: &DeviceAuthorization,
+    proxy: Option<&ProxyConfig>,
+) -> anyhow::Result<DevicePoll> {
+    let body = DeviceTokenRequest {
+        client_id: authorization.client_id.clone(),
+        client_secret: authorization.client_secret.clone(),
+        grant_type: DEVICE_CODE_GRANT_TYPE.to_string(),
+        device_code: authorization.device_code.clone(),
+    };
+    let result = match oidc_post(config, region, "/token", &body, proxy).await? {
+        Ok(token) => DevicePoll::Complete(token),
+        Err(e) => classify(&e),
+    };
+    Ok(result)
+}
+
+fn classify(error: &OidcErrorResponse) -> DevicePoll {
+    match error.error.as_str() {
+        "authorization_pending" => DevicePoll::Pending,
+        "slow_down" => DevicePoll::SlowDown,
+        "expired_token" => DevicePoll::Failed("设备码已过期，请重新发起登录".to_string()),
+        "access_denied" => DevicePoll::Failed("用户拒绝了授权".to_string()),
+        _ => DevicePoll::Failed(describe(error)),
+    }
+}
+
+fn describe(error: &OidcErrorResponse) -> String {
+    match &error.error_description {
+        Some(description) => format!("{} ({})", error.error, description),
+        None => error.error.clone(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn error(code: &str) -> OidcErrorResponse {
+        serde_json::from_value(serde_json::json!({"error": code})).unwrap()
+    }
+
+    #[test]
+    fn test_classify_poll_errors() {
+        assert!(matches!(
+            classify(&error("authorization_pending")),
+            DevicePoll::Pending
+        ));
+        assert!(matches!(
+            classify(&error("slow_down")),
+            DevicePoll::SlowDown
+        ));
+        assert!(matches!(
+            classify(&error("expired_token")),
+            DevicePoll::Failed(_)
+        ));
+        match classify(&error("invalid_grant")) {
+            DevicePoll::Failed(message) => assert_eq!(message, "invalid_grant"),
+            other => panic!("unexpected poll result: {:?}", other),
+        }
+    }
+}
@@ -22,28 +22,23 @@ impl IdeEndpoint {
         Self
     }
 
-    fn api_region<'a>(&self, ctx: &'a RequestContext<'_>) -> &'a str {
-        ctx.credentials.effective_api_region(ctx.config)
-    }
-
     fn host(&self, ctx: &RequestContext<'_>) -> String {
-        format!("q.{}.amazonaws.com", self.api_region(ctx))
+        format!("q.{}.amazonaws.com", ctx.api_region())
     }
 
     fn x_amz_user_agent(&self, ctx: &RequestContext<'_>) -> String {
+        let profile = ctx.credentials.effective_client_profile(ctx.config);
         format!(
             "aws-sdk-js/1.0.34 KiroIDE-{}-{}",
-            ctx.config.kiro_version, ctx.machine_id
+            profile.kiro_version, ctx.machine_id
         )
     }
 
     fn user_agent(&self, ctx: &RequestContext<'_>) -> String {
+        let profile = ctx.credentials.effective_client_profile(ctx.config);
         format!(
             "aws-sdk-js/1.0.34 ua/2.1 os/{} lang/js md/nodejs#{} api/codewhispererstreaming#1.0.34 m/E KiroIDE-{}-{}",
-            ctx.config.system_version,
-            ctx.config.node_version,
-            ctx.config.kiro_version,
-            ctx.machine_id
+            profile.system_version, profile.node_version, profile.kiro_version, ctx.machine_id
         )
     }
 }
@@ -62,12 +57,12 @@ impl KiroEndpoint for IdeEndpoint {
     fn api_url(&self, ctx: &RequestContext<'_>) -> String {
         format!(
             "https://q.{}.amazonaws.com/generateAssistantResponse",
-            self.api_region(ctx)
+            ctx.api_region()
         )
     }
 
     fn mcp_url(&self, ctx: &RequestContext<'_>) -> String {
-        format!("https://q.{}.amazonaws.com/mcp", self.api_region(ctx))
+        format!("https://q.{}.amazonaws.com/mcp", ctx.api_region())
     }
 
     fn decorate_api(&self, req: RequestBuilder, ctx: &RequestContext<'_>) -> RequestBuilder {
@@ -125,9 +120,43 @@ fn inject_profile_arn(request_body: &str, profile_arn: &Option<String>) -> Strin
 
 #[cfg(test)]
 mod tests {
-    use super::inject_profile_arn;
+    use super::{IdeEndpoint, inject_profile_arn};
+    use crate::kiro::endpoint::{KiroEndpoint, RequestContext};
+    use crate::kiro::model::credentials::KiroCredentials;
+    use crate::model::config::Config;
     use serde_json::Value;
 
+    #[test]
+    fn test_request_region_overrides_credential_region() {
+        let config = Config::default();
+        let credentials = KiroCredentials {
+            api_region: Some("us-east-1".to_string()),
+            ..Default::default()
+        };
+        let mut ctx = RequestContext {
+            credentials: &credentials,
+            token: "t",
+            machine_id: "m",
+            config: &config,
+            api_region: None,
+        };
+        let endpoint = IdeEndpoint::new();
+        assert_eq!(
+            endpoint.api_url(&ctx),
+            "https://q.us-east-1.amazonaws.com/generateAssistantResponse"
+        );
+
+        ctx.api_region = Some("eu-central-1");
+        assert_eq!(
+            endpoint.api_url(&ctx),
+            "https://q.eu-central-1.amazonaws.com/generateAssistantResponse"
+        );
+        assert_eq!(
+            endpoint.mcp_url(&ctx),
+            "https://q.eu-central-1.amazonaws.com/mcp"
+        );
+    }
+
     #[test]
     fn test_inject_profile_arn_with_some() {
         let body = r#"{"conversationState":{"conversationId":"c1"}}"#;
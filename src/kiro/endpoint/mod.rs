@@ -68,6 +68,16 @@ pub struct RequestContext<'a> {
     pub machine_id: &'a str,
     /// 全局配置
     pub config: &'a Config,
+    /// 本次请求指定的 API Region（来自 `X-Kiro-Region` 请求头，优先于凭据与全局配置）
+    pub api_region: Option<&'a str>,
+}
+
+impl RequestContext<'_> {
+    /// 本次请求实际使用的 API Region
+    pub fn api_region(&self) -> &str {
+        self.api_region
+            .unwrap_or_else(|| self.credentials.effective_api_region(self.config))
+    }
 }
 
 /// 默认的 MONTHLY_REQUEST_COUNT 判断逻辑
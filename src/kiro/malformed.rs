@@ -0,0 +1,430 @@
+//! "Improperly formed request" 诊断
+//!
+//! 上游以 400 "Improperly formed request" 拒绝请求时，通常不会说明是哪个字段出了问题。
+//! 本模块负责：
+//! - 从上游消息中提取字段路径（如 AWS 校验错误 `Value at 'a.b.c' failed to ...`）
+//! - 对请求体做启发式检查，找出最可能的问题字段（JSON Pointer）
+//! - 保存最近若干次脱敏后的请求体 + 上游响应，供 Admin API 排查
+
+use std::collections::VecDeque;
+use std::fmt;
+use std::sync::LazyLock;
+
+use parking_lot::Mutex;
+use serde::Serialize;
+use serde_json::Value;
+
+/// 保留的最近捕获数量
+const MAX_CAPTURES: usize = 20;
+
+/// 工具名最大长度（与 converter 的缩短阈值一致）
+const MAX_TOOL_NAME_LEN: usize = 64;
+
+static CAPTURES: LazyLock<Mutex<VecDeque<MalformedCapture>>> =
+    LazyLock::new(|| Mutex::new(VecDeque::with_capacity(MAX_CAPTURES)));
+
+/// 可疑字段
+#[derive(Debug, Clone, Serialize)]
+pub struct Suspect {
+    /// JSON Pointer（RFC 6901），相对于发往上游的请求体
+    pub pointer: String,
+    /// 可疑原因
+    pub reason: String,
+}
+
+/// 一次 malformed 请求的捕获记录
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MalformedCapture {
+    pub id: String,
+    /// 捕获时间（RFC3339）
+    pub captured_at: String,
+    /// 上游响应体
+    pub upstream_message: String,
+    /// 可疑字段（按可信度排序，上游明确指出的字段在前）
+    pub suspects: Vec<Suspect>,
+    /// 脱敏后的请求体
+    pub request_body: Value,
+}
+
+/// 上游拒绝 malformed 请求时的错误
+///
+/// 由 provider 返回，handler 通过 downcast 识别并转换为带可疑字段的 400 响应
+#[derive(Debug)]
+pub struct MalformedRequestError {
+    pub status: u16,
+    pub upstream_message: String,
+    pub capture_id: String,
+    pub suspects: Vec<Suspect>,
+}
+
+impl fmt::Display for MalformedRequestError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "API 请求失败: {} {}", self.status, self.upstream_message)
+    }
+}
+
+impl std::error::Error for MalformedRequestError {}
+
+/// 判断上游响应是否为 "Improperly formed request"
+pub fn is_improperly_formed(body: &str) -> bool {
+    body.contains("Improperly formed request")
+}
+
+/// 诊断并记录一次 malformed 请求，返回供调用方包装的错误
+pub fn capture(request_body: &str, status: u16, upstream_message: &str) -> MalformedRequestError {
+    let request: Value = serde_json::from_str(request_body).unwrap_or(Value::Null);
+    let suspects = diagnose(&request, upstream_message);
+    let id = uuid::Uuid::new_v4().to_string();
+
+    tracing::warn!(
+        capture_id = %id,
+        suspects = ?suspects.iter().map(|s| s.pointer.as_str()).collect::<Vec<_>>(),
+        "上游拒绝请求（Improperly formed request），已记录请求体"
+    );
+
+    let record = MalformedCapture {
+        id: id.clone(),
+        captured_at: chrono::Utc::now().to_rfc3339(),
+        upstream_message: upstream_message.to_string(),
+        suspects: suspects.clone(),
+        request_body: redact(request),
+    };
+    {
+        let mut captures = CAPTURES.lock();
+        if captures.len() >= MAX_CAPTURES {
+            captures.pop_front();
+        }
+        captures.push_back(record);
+    }
+
+    MalformedRequestError {
+        status,
+        upstream_message: upstream_message.to_string(),
+        capture_id: id,
+        suspects,
+    }
+}
+
+/// 获取最近的捕获记录（新的在前）
+pub fn recent_captures() -> Vec<MalformedCapture> {
+    CAPTURES.lock().iter().rev().cloned().collect()
+}
+
+/// 找出最可能导致 malformed 的字段
+pub fn diagnose(request: &Value, upstream_message: &str) -> Vec<Suspect> {
+    let mut suspects = Vec::new();
+
+    // 1. 上游消息中明确给出的字段路径
+    if let Some(pointer) = pointer_from_upstream_message(upstream_message) {
+        suspects.push(Suspect {
+            pointer,
+            reason: "上游错误消息指出的字段".to_string(),
+        });
+    }
+
+    // 2. 启发式检查
+    let Some(state) = request.get("conversationState") else {
+        return suspects;
+    };
+
+    let current = "/conversationState/currentMessage/userInputMessage";
+    if let Some(msg) = state.pointer("/currentMessage/userInputMessage") {
+        check_user_message(msg, current, &mut suspects);
+        if let Some(tools) = msg
+            .pointer("/userInputMessageContext/tools")
+            .and_then(|v| v.as_array())
+        {
+            check_tools(
+                tools,
+                &format!("{}/userInputMessageContext/tools", current),
+                &mut suspects,
+            );
+        }
+    }
+
+    if let Some(history) = state.get("history").and_then(|v| v.as_array()) {
+        let mut prev_role: Option<&str> = None;
+        for (i, entry) in history.iter().enumerate() {
+            let base = format!("/conversationState/history/{}", i);
+            let role = if let Some(msg) = entry.get("userInputMessage") {
+                check_user_message(msg, &format!("{}/userInputMessage", base), &mut suspects);
+                "user"
+            } else if let Some(msg) = entry.get("assistantResponseMessage") {
+                let has_tool_uses = msg
+                    .get("toolUses")
+                    .and_then(|v| v.as_array())
+                    .is_some_and(|a| !a.is_empty());
+                if is_blank(msg.get("content")) && !has_tool_uses {
+                    suspects.push(Suspect {
+                        pointer: format!("{}/assistantResponseMessage/content", base),
+                        reason: "assistant 消息内容为空且没有 toolUses".to_string(),
+                    });
+                }
+                "assistant"
+            } else {
+                suspects.push(Suspect {
+                    pointer: base.clone(),
+                    reason: "history 条目既不是 userInputMessage 也不是 assistantResponseMessage"
+                        .to_string(),
+                });
+                continue;
+            };
+
+            if i == 0 && role == "assistant" {
+                suspects.push(Suspect {
+                    pointer: base.clone(),
+                    reason: "history 以 assistant 消息开头".to_string(),
+                });
+            }
+            if prev_role == Some(role) {
+                suspects.push(Suspect {
+                    pointer: base.clone(),
+                    reason: format!("连续两条 {} 消息（user/assistant 未交替）", role),
+                });
+            }
+            prev_role = Some(role);
+        }
+
+        if prev_role == Some("user") {
+            suspects.push(Suspect {
+                pointer: format!("/conversationState/history/{}", history.len() - 1),
+                reason: "history 以 user 消息结尾，与 currentMessage 连续两条 user".to_string(),
+            });
+        }
+    }
+
+    suspects
+}
+
+/// 从 AWS 风格的校验错误中提取字段路径
+///
+/// 形如 `Value at 'conversationState.history.3.userInputMessage.content' failed ...`
+fn pointer_from_upstream_message(message: &str) -> Option<String> {
+    let start = message.find("at '")? + 4;
+    let end = start + message[start..].find('\'')?;
+    let path = &message[start..end];
+    if path.is_empty() {
+        return None;
+    }
+    Some(format!("/{}", path.replace('.', "/")))
+}
+
+fn is_blank(value: Option<&Value>) -> bool {
+    value
+        .and_then(|v| v.as_str())
+        .is_none_or(|s| s.trim().is_empty())
+}
+
+fn check_user_message(msg: &Value, base: &str, suspects: &mut Vec<Suspect>) {
+    let ctx = msg.get("userInputMessageContext");
+    let has_tool_results = ctx
+        .and_then(|c| c.get("toolResults"))
+        .and_then(|v| v.as_array())
+        .is_some_and(|a| !a.is_empty());
+    let has_images = msg
+        .get("images")
+        .and_then(|v| v.as_array())
+        .is_some_and(|a| !a.is_empty());
+
+    if is_blank(msg.get("content")) && !has_tool_results && !has_images {
+        suspects.push(Suspect {
+            pointer: format!("{}/content", base),
+            reason: "user 消息内容为空且没有 toolResults/images".to_string(),
+        });
+    }
+
+    if let Some(results) = ctx
+        .and_then(|c| c.get("toolResults"))
+        .and_then(|v| v.as_array())
+    {
+        for (i, result) in results.iter().enumerate() {
+            let empty = result
+                .get("content")
+                .and_then(|v| v.as_array())
+                .is_none_or(|a| a.is_empty());
+            if empty {
+                suspects.push(Suspect {
+                    pointer: format!("{}/userInputMessageContext/toolResults/{}/content", base, i),
+                    reason: "toolResult 内容为空".to_string(),
+                });
+            }
+        }
+    }
+}
+
+fn check_tools(tools: &[Value], base: &str, suspects: &mut Vec<Suspect>) {
+    for (i, tool) in tools.iter().enumerate() {
+        let Some(spec) = tool.get("toolSpecification") else {
+            continue;
+        };
+        let pointer = format!("{}/{}/toolSpecification", base, i);
+
+        let name = spec.get("name").and_then(|v| v.as_str()).unwrap_or("");
+        if name.is_empty() || name.len() > MAX_TOOL_NAME_LEN {
+            suspects.push(Suspect {
+                pointer: format!("{}/name", pointer),
+                reason: format!("工具名为空或超过 {} 字符", MAX_TOOL_NAME_LEN),
+            });
+        }
+        if is_blank(spec.get("description")) {
+            suspects.push(Suspect {
+                pointer: format!("{}/description", pointer),
+                reason: "工具描述为空".to_string(),
+            });
+        }
+        let schema_type = spec
+            .pointer("/inputSchema/json/type")
+            .and_then(|v| v.as_str());
+        if schema_type != Some("object") {
+            suspects.push(Suspect {
+                pointer: format!("{}/inputSchema/json/type", pointer),
+                reason: "inputSchema 顶层 type 不是 object".to_string(),
+            });
+        }
+    }
+}
+
+/// 脱敏：移除 profileArn，图片数据替换为长度占位
+fn redact(mut value: Value) -> Value {
+    fn walk(value: &mut Value) {
+        match value {
+            Value::Object(map) => {
+                if map.contains_key("profileArn") {
+                    map.insert("profileArn".to_string(), Value::String("***".to_string()));
+                }
+                if let Some(Value::String(bytes)) = map.get_mut("bytes") {
+                    *bytes = format!("<{} bytes base64>", bytes.len());
+                }
+                for v in map.values_mut() {
+                    walk(v);
+                }
+            }
+            Value::Array(items) => items.iter_mut().for_each(walk),
+            _ => {}
+        }
+    }
+    walk(&mut value);
+    value
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn request(history: Value, current_content: &str) -> Value {
+        json!({
+            "profileArn": "arn:aws:codewhisperer:us-east-1:123:profile/ABC",
+            "conversationState": {
+                "conversationId": "c",
+                "currentMessage": {
+                    "userInputMessage": {
+                        "content": current_content,
+                        "modelId": "claude-sonnet-4.5",
+                        "userInputMessageContext": {
+                            "tools": [{
+                                "toolSpecification": {
+                                    "name": "read",
+                                    "description": "Read a file",
+                                    "inputSchema": {"json": {"type": "object"}}
+                                }
+                            }]
+                        }
+                    }
+                },
+                "history": history
+            }
+        })
+    }
+
+    fn pointers(suspects: &[Suspect]) -> Vec<&str> {
+        suspects.iter().map(|s| s.pointer.as_str()).collect()
+    }
+
+    #[test]
+    fn test_is_improperly_formed() {
+        assert!(is_improperly_formed(
+            r#"{"message":"Improperly formed request.","reason":null}"#
+        ));
+        assert!(!is_improperly_formed(r#"{"message":"Input is too long."}"#));
+    }
+
+    #[test]
+    fn test_pointer_from_upstream_message() {
+        let msg = "1 validation error detected: Value at 'conversationState.history.3.userInputMessage.content' failed to satisfy constraint";
+        assert_eq!(
+            pointer_from_upstream_message(msg).as_deref(),
+            Some("/conversationState/history/3/userInputMessage/content")
+        );
+        assert_eq!(
+            pointer_from_upstream_message("Improperly formed request."),
+            None
+        );
+    }
+
+    #[test]
+    fn test_diagnose_clean_request_has_no_suspects() {
+        let req = request(
+            json!([
+                {"userInputMessage": {"content": "hi", "modelId": "m"}},
+                {"assistantResponseMessage": {"content": "hello"}}
+            ]),
+            "next",
+        );
+        assert!(diagnose(&req, "Improperly formed request.").is_empty());
+    }
+
+    #[test]
+    fn test_diagnose_empty_contents_and_role_order() {
+        let req = request(
+            json!([
+                {"userInputMessage": {"content": "hi", "modelId": "m"}},
+                {"assistantResponseMessage": {"content": ""}},
+                {"assistantResponseMessage": {"content": "x"}}
+            ]),
+            "",
+        );
+        let suspects = diagnose(&req, "Improperly formed request.");
+        assert_eq!(
+            pointers(&suspects),
+            vec![
+                "/conversationState/currentMessage/userInputMessage/content",
+                "/conversationState/history/1/assistantResponseMessage/content",
+                "/conversationState/history/2",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_diagnose_bad_tool_schema() {
+        let mut req = request(json!([]), "hi");
+        req["conversationState"]["currentMessage"]["userInputMessage"]["userInputMessageContext"]
+            ["tools"][0]["toolSpecification"]["inputSchema"]["json"] = json!({});
+        let suspects = diagnose(&req, "Improperly formed request.");
+        assert_eq!(
+            pointers(&suspects),
+            vec![
+                "/conversationState/currentMessage/userInputMessage/userInputMessageContext/tools/0/toolSpecification/inputSchema/json/type"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_redact() {
+        let req = json!({
+            "profileArn": "arn:secret",
+            "conversationState": {"currentMessage": {"userInputMessage": {
+                "images": [{"format": "png", "source": {"bytes": "AAAA"}}]
+            }}}
+        });
+        let redacted = redact(req);
+        assert_eq!(redacted["profileArn"], "***");
+        assert_eq!(
+            redacted.pointer(
+                "/conversationState/currentMessage/userInputMessage/images/0/source/bytes"
+            ),
+            Some(&json!("<4 bytes base64>"))
+        );
+    }
+}
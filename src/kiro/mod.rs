@@ -1,8 +1,22 @@
 //! Kiro API 客户端模块
 
+pub mod circuit_breaker;
+pub mod concurrency;
+pub mod credential_wizard;
+pub mod device_auth;
 pub mod endpoint;
 pub mod machine_id;
+pub mod malformed;
 pub mod model;
+pub mod model_registry;
 pub mod parser;
 pub mod provider;
+pub mod proxy_health;
+pub mod quota_alert;
+pub mod refresh_journal;
+pub mod request_size;
+pub mod schedule;
+pub mod session_affinity;
 pub mod token_manager;
+pub mod validation;
+pub mod warm_refresh;
@@ -0,0 +1,26 @@
+//! 可用模型查询数据模型
+//!
+//! 包含 ListAvailableModels API 的响应类型定义
+
+use serde::Deserialize;
+
+/// 可用模型查询响应
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AvailableModelsResponse {
+    /// 当前凭据可用的模型列表
+    #[serde(default)]
+    pub models: Vec<AvailableModel>,
+
+    /// Kiro 推荐的默认模型
+    #[serde(default)]
+    pub default_model: Option<AvailableModel>,
+}
+
+/// 单个可用模型
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AvailableModel {
+    /// 模型 ID（如 claude-sonnet-4.5）
+    pub model_id: String,
+}
@@ -8,7 +8,8 @@ use std::fs;
 use std::path::Path;
 
 use crate::http_client::ProxyConfig;
-use crate::model::config::Config;
+use crate::kiro::proxy_health;
+use crate::model::config::{ClientProfile, Config, TlsBackend};
 
 /// Kiro OAuth 凭证
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
@@ -93,6 +94,13 @@ pub struct KiroCredentials {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub proxy_password: Option<String>,
 
+    /// 备用代理 URL 列表（可选，共用 proxyUsername / proxyPassword）
+    ///
+    /// `proxyUrl` 连接失败时按顺序切换到第一个可用的备用代理，见 `kiro::proxy_health`
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub fallback_proxy_urls: Vec<String>,
+
     /// 凭据是否被禁用（默认为 false）
     #[serde(default)]
     pub disabled: bool,
@@ -109,6 +117,42 @@ pub struct KiroCredentials {
     /// 端点名必须在启动时注册的端点 registry 中存在。
     #[serde(skip_serializing_if = "Option::is_none")]
     pub endpoint: Option<String>,
+
+    /// 可用时段（可选，如 `["Sat,Sun 00:00-24:00", "Mon-Fri 22:00-07:00 +08:00"]`）
+    ///
+    /// 配置后仅在时段内参与轮换，格式见 `kiro::schedule`；未配置表示始终可用
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub schedule: Vec<String>,
+
+    /// 凭据分组（可选，如 `opus-capable`）
+    ///
+    /// 配合 `config.modelRoutes` 按请求模型把流量路由到指定分组；未分组的凭据
+    /// 只服务未命中路由规则的请求
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub group: Option<String>,
+
+    /// balanced 模式下的流量权重（可选，默认 1）
+    ///
+    /// 权重为 3 的凭据承担的请求约为权重 1 的凭据的 3 倍
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub weight: Option<u32>,
+
+    /// 凭据级客户端画像（可选）
+    ///
+    /// 覆盖 `config.clientProfiles` 轮换分配的画像；未配置的字段依次回退到分配的画像与全局配置
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub client_profile: Option<ClientProfile>,
+}
+
+/// 凭据实际生效的客户端画像，见 [`KiroCredentials::effective_client_profile`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EffectiveClientProfile<'a> {
+    pub kiro_version: &'a str,
+    pub system_version: &'a str,
+    pub node_version: &'a str,
+    pub tls_backend: TlsBackend,
 }
 
 /// 判断是否为零（用于跳过序列化）
@@ -215,25 +259,93 @@ impl KiroCredentials {
             .unwrap_or(config.effective_api_region())
     }
 
+    /// profileArn 所在的 Region（`arn:aws:codewhisperer:<region>:<account>:profile/<id>`）
+    pub fn profile_region(&self) -> Option<&str> {
+        self.profile_arn
+            .as_deref()?
+            .split(':')
+            .nth(3)
+            .filter(|region| !region.is_empty())
+    }
+
+    /// 能否向指定 API Region 发送请求
+    ///
+    /// 请求体携带的 profileArn 必须属于该 Region；没有 profileArn（如 API Key 凭据）
+    /// 或无法从中解析出 Region 时不受限制
+    pub fn serves_api_region(&self, region: &str) -> bool {
+        self.profile_region()
+            .is_none_or(|profile_region| profile_region.eq_ignore_ascii_case(region))
+    }
+
+    /// 获取有效的客户端画像（User-Agent 版本信息与 TLS 后端）
+    /// 优先级（逐字段）：凭据.client_profile > config.client_profiles 按 id 轮换分配的画像 > 全局配置
+    pub fn effective_client_profile<'a>(
+        &'a self,
+        config: &'a Config,
+    ) -> EffectiveClientProfile<'a> {
+        let pooled = match config.client_profiles.len() {
+            0 => None,
+            len => {
+                let index = self.id.unwrap_or(0).saturating_sub(1) % len as u64;
+                config.client_profiles.get(index as usize)
+            }
+        };
+        let profiles = [self.client_profile.as_ref(), pooled];
+        let pick = |field: fn(&'a ClientProfile) -> Option<&'a String>, global: &'a String| {
+            profiles
+                .iter()
+                .flatten()
+                .find_map(|p| field(p))
+                .unwrap_or(global)
+                .as_str()
+        };
+
+        EffectiveClientProfile {
+            kiro_version: pick(|p| p.kiro_version.as_ref(), &config.kiro_version),
+            system_version: pick(|p| p.system_version.as_ref(), &config.system_version),
+            node_version: pick(|p| p.node_version.as_ref(), &config.node_version),
+            tls_backend: profiles
+                .iter()
+                .flatten()
+                .find_map(|p| p.tls_backend)
+                .unwrap_or(config.tls_backend),
+        }
+    }
+
     /// 获取有效的代理配置
-    /// 优先级：凭据代理 > 全局代理 > 无代理
+    /// 优先级：凭据代理（不可用时切换到备用代理）> 全局代理 > 无代理
     /// 特殊值 "direct" 表示显式不使用代理（即使全局配置了代理）
     pub fn effective_proxy(&self, global_proxy: Option<&ProxyConfig>) -> Option<ProxyConfig> {
         match self.proxy_url.as_deref() {
             Some(url) if url.eq_ignore_ascii_case(Self::PROXY_DIRECT) => None,
-            Some(url) => {
-                let mut proxy = ProxyConfig::new(url);
-                if let (Some(username), Some(password)) =
-                    (&self.proxy_username, &self.proxy_password)
-                {
-                    proxy = proxy.with_auth(username, password);
-                }
-                Some(proxy)
-            }
+            Some(_) => proxy_health::select(&self.proxy_candidates()).cloned(),
             None => global_proxy.cloned(),
         }
     }
 
+    /// 凭据级代理候选列表（主代理在前，备用代理按顺序在后）
+    ///
+    /// 未配置凭据级代理或为 "direct" 时为空
+    pub fn proxy_candidates(&self) -> Vec<ProxyConfig> {
+        let Some(primary) = self
+            .proxy_url
+            .as_deref()
+            .filter(|url| !url.eq_ignore_ascii_case(Self::PROXY_DIRECT))
+        else {
+            return Vec::new();
+        };
+        std::iter::once(primary)
+            .chain(self.fallback_proxy_urls.iter().map(String::as_str))
+            .map(|url| {
+                let proxy = ProxyConfig::new(url);
+                match (&self.proxy_username, &self.proxy_password) {
+                    (Some(username), Some(password)) => proxy.with_auth(username, password),
+                    _ => proxy,
+                }
+            })
+            .collect()
+    }
+
     pub fn canonicalize_auth_method(&mut self) {
         let auth_method = match &self.auth_method {
             Some(m) => m,
@@ -246,6 +358,11 @@ impl KiroCredentials {
         }
     }
 
+    /// balanced 模式下生效的权重（未配置时为 1）
+    pub fn effective_weight(&self) -> u32 {
+        self.weight.unwrap_or(1).max(1)
+    }
+
     /// 检查凭据是否支持 Opus 模型
     ///
     /// Free 账号不支持 Opus 模型，需要 PRO 或更高等级订阅
@@ -340,9 +457,14 @@ mod tests {
             proxy_url: None,
             proxy_username: None,
             proxy_password: None,
+            fallback_proxy_urls: Vec::new(),
             disabled: false,
             kiro_api_key: None,
             endpoint: None,
+            schedule: Vec::new(),
+            group: None,
+            weight: None,
+            client_profile: None,
         };
 
         let json = creds.to_pretty_json().unwrap();
@@ -458,9 +580,14 @@ mod tests {
             proxy_url: None,
             proxy_username: None,
             proxy_password: None,
+            fallback_proxy_urls: Vec::new(),
             disabled: false,
             kiro_api_key: None,
             endpoint: None,
+            schedule: Vec::new(),
+            group: None,
+            weight: None,
+            client_profile: None,
         };
 
         let json = creds.to_pretty_json().unwrap();
@@ -489,9 +616,14 @@ mod tests {
             proxy_url: None,
             proxy_username: None,
             proxy_password: None,
+            fallback_proxy_urls: Vec::new(),
             disabled: false,
             kiro_api_key: None,
             endpoint: None,
+            schedule: Vec::new(),
+            group: None,
+            weight: None,
+            client_profile: None,
         };
 
         let json = creds.to_pretty_json().unwrap();
@@ -603,9 +735,14 @@ mod tests {
             proxy_url: None,
             proxy_username: None,
             proxy_password: None,
+            fallback_proxy_urls: Vec::new(),
             disabled: false,
             kiro_api_key: None,
             endpoint: None,
+            schedule: Vec::new(),
+            group: None,
+            weight: None,
+            client_profile: None,
         };
 
         let json = original.to_pretty_json().unwrap();
@@ -810,6 +947,66 @@ mod tests {
         assert_eq!(creds.effective_api_region(&config), "api-only");
     }
 
+    #[test]
+    fn test_serves_api_region_matches_profile_arn() {
+        let mut creds = KiroCredentials::default();
+        assert!(creds.serves_api_region("eu-central-1"));
+
+        creds.profile_arn =
+            Some("arn:aws:codewhisperer:us-east-1:699475941385:profile/EHGA3GRVQMUK".to_string());
+        assert_eq!(creds.profile_region(), Some("us-east-1"));
+        assert!(creds.serves_api_region("us-east-1"));
+        assert!(!creds.serves_api_region("eu-central-1"));
+
+        creds.profile_arn = Some("arn:aws:test".to_string());
+        assert_eq!(creds.profile_region(), None);
+        assert!(creds.serves_api_region("eu-central-1"));
+    }
+
+    #[test]
+    fn test_effective_client_profile_rotation_and_override() {
+        let mut config = Config::default();
+        config.kiro_version = "0.1.0".to_string();
+        config.client_profiles = vec![
+            ClientProfile {
+                kiro_version: Some("0.2.0".to_string()),
+                ..Default::default()
+            },
+            ClientProfile {
+                node_version: Some("20.0.0".to_string()),
+                tls_backend: Some(TlsBackend::NativeTls),
+                ..Default::default()
+            },
+        ];
+
+        let mut creds = KiroCredentials {
+            id: Some(1),
+            ..Default::default()
+        };
+        let profile = creds.effective_client_profile(&config);
+        assert_eq!(profile.kiro_version, "0.2.0");
+        assert_eq!(profile.node_version, config.node_version);
+        assert_eq!(profile.tls_backend, TlsBackend::Rustls);
+
+        // id 2 轮换到第二个画像，未配置的字段回退到全局配置
+        creds.id = Some(2);
+        let profile = creds.effective_client_profile(&config);
+        assert_eq!(profile.kiro_version, "0.1.0");
+        assert_eq!(profile.node_version, "20.0.0");
+        assert_eq!(profile.tls_backend, TlsBackend::NativeTls);
+
+        // 凭据级画像逐字段覆盖分配的画像
+        creds.client_profile = Some(ClientProfile {
+            kiro_version: Some("0.3.0".to_string()),
+            tls_backend: Some(TlsBackend::Rustls),
+            ..Default::default()
+        });
+        let profile = creds.effective_client_profile(&config);
+        assert_eq!(profile.kiro_version, "0.3.0");
+        assert_eq!(profile.node_version, "20.0.0");
+        assert_eq!(profile.tls_backend, TlsBackend::Rustls);
+    }
+
     // ============ 凭据级代理优先级测试 ============
 
     #[test]
@@ -835,6 +1032,25 @@ mod tests {
         assert_eq!(result, Some(expected));
     }
 
+    #[test]
+    fn test_effective_proxy_switches_to_fallback() {
+        let creds = KiroCredentials {
+            proxy_url: Some("http://cred-primary:3128".to_string()),
+            proxy_username: Some("user".to_string()),
+            proxy_password: Some("pass".to_string()),
+            fallback_proxy_urls: vec!["socks5://cred-backup:1080".to_string()],
+            ..Default::default()
+        };
+
+        let candidates = creds.proxy_candidates();
+        assert_eq!(candidates.len(), 2);
+        assert_eq!(candidates[1].username.as_deref(), Some("user"));
+        assert_eq!(creds.effective_proxy(None), Some(candidates[0].clone()));
+
+        proxy_health::report_failure("http://cred-primary:3128", "connection refused");
+        assert_eq!(creds.effective_proxy(None), Some(candidates[1].clone()));
+    }
+
     #[test]
     fn test_effective_proxy_direct_bypasses_global() {
         let global = ProxyConfig::new("http://global:8080");
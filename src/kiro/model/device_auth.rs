@@ -0,0 +1,74 @@
+//! IdC 设备授权登录（AWS SSO OIDC Device Authorization Grant）
+
+use serde::{Deserialize, Serialize};
+
+/// 注册 OIDC 客户端的请求体
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RegisterClientRequest {
+    pub client_name: String,
+    pub client_type: String,
+    pub scopes: Vec<String>,
+    pub grant_types: Vec<String>,
+    pub issuer_url: String,
+}
+
+/// 注册 OIDC 客户端的响应体
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RegisterClientResponse {
+    pub client_id: String,
+    pub client_secret: String,
+}
+
+/// 发起设备授权的请求体
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StartDeviceAuthorizationRequest {
+    pub client_id: String,
+    pub client_secret: String,
+    pub start_url: String,
+}
+
+/// 发起设备授权的响应体
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StartDeviceAuthorizationResponse {
+    pub device_code: String,
+    pub user_code: String,
+    pub verification_uri: String,
+    #[serde(default)]
+    pub verification_uri_complete: Option<String>,
+    /// 设备码有效期（秒）
+    pub expires_in: i64,
+    /// 建议的轮询间隔（秒）
+    #[serde(default)]
+    pub interval: Option<i64>,
+}
+
+/// 以设备码换取 Token 的请求体
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DeviceTokenRequest {
+    pub client_id: String,
+    pub client_secret: String,
+    pub grant_type: String,
+    pub device_code: String,
+}
+
+/// 以设备码换取 Token 的响应体
+///
+/// 只保留 refreshToken：添加凭据时会立即刷新得到 accessToken
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DeviceTokenResponse {
+    pub refresh_token: String,
+}
+
+/// OIDC 错误响应体（如 `authorization_pending`）
+#[derive(Debug, Deserialize)]
+pub struct OidcErrorResponse {
+    pub error: String,
+    #[serde(default)]
+    pub error_description: Option<String>,
+}
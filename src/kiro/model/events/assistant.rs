@@ -7,7 +7,8 @@ use serde::{Deserialize, Serialize};
 use crate::kiro::parser::error::ParseResult;
 use crate::kiro::parser::frame::Frame;
 
-use super::base::EventPayload;
+use super::base::{EventPayload, EventType};
+use super::unknown_fields;
 
 /// 助手响应事件
 ///
@@ -16,7 +17,8 @@ use super::base::EventPayload;
 /// # 设计说明
 ///
 /// 此结构体只保留实际使用的 `content` 字段，其他 API 返回的字段
-/// 通过 `#[serde(flatten)]` 捕获到 `extra` 中，确保反序列化不会失败。
+/// 通过 `#[serde(flatten)]` 捕获到 `extra` 中，确保反序列化不会失败，
+/// 其中未识别的字段会交给 [`unknown_fields`](super::unknown_fields) 追踪。
 ///
 /// # 示例
 ///
@@ -37,13 +39,14 @@ pub struct AssistantResponseEvent {
     /// 捕获其他未使用的字段，确保反序列化兼容性
     #[serde(flatten)]
     #[serde(skip_serializing)]
-    #[allow(dead_code)]
-    extra: serde_json::Value,
+    pub(crate) extra: serde_json::Map<String, serde_json::Value>,
 }
 
 impl EventPayload for AssistantResponseEvent {
     fn from_frame(frame: &Frame) -> ParseResult<Self> {
-        frame.payload_as_json()
+        let event: Self = frame.payload_as_json()?;
+        unknown_fields::observe(EventType::AssistantResponse.as_str(), &event.extra);
+        Ok(event)
     }
 }
 
@@ -51,7 +54,7 @@ impl Default for AssistantResponseEvent {
     fn default() -> Self {
         Self {
             content: String::new(),
-            extra: serde_json::Value::Null,
+            extra: serde_json::Map::new(),
         }
     }
 }
@@ -88,6 +91,7 @@ mod tests {
         }"#;
         let event: AssistantResponseEvent = serde_json::from_str(json).unwrap();
         assert_eq!(event.content, "Done");
+        assert_eq!(event.extra["messageStatus"], "COMPLETED");
     }
 
     #[test]
@@ -16,6 +16,8 @@ pub enum EventType {
     Metering,
     /// 上下文使用率事件
     ContextUsage,
+    /// 推理内容事件
+    ReasoningContent,
     /// 未知事件类型
     Unknown,
 }
@@ -28,6 +30,7 @@ impl EventType {
             "toolUseEvent" => Self::ToolUse,
             "meteringEvent" => Self::Metering,
             "contextUsageEvent" => Self::ContextUsage,
+            "reasoningContentEvent" => Self::ReasoningContent,
             _ => Self::Unknown,
         }
     }
@@ -39,6 +42,7 @@ impl EventType {
             Self::ToolUse => "toolUseEvent",
             Self::Metering => "meteringEvent",
             Self::ContextUsage => "contextUsageEvent",
+            Self::ReasoningContent => "reasoningContentEvent",
             Self::Unknown => "unknown",
         }
     }
@@ -71,8 +75,13 @@ pub enum Event {
     Metering(()),
     /// 上下文使用率
     ContextUsage(super::ContextUsageEvent),
-    /// 未知事件 (保留原始帧数据)
-    Unknown {},
+    /// 推理内容
+    ReasoningContent(super::ReasoningContentEvent),
+    /// 未知事件
+    Unknown {
+        /// 从 payload 映射出的助手文本（开启 `mapUnknownTextEvents` 且带文本字段时）
+        text: Option<String>,
+    },
     /// 服务端错误
     Error {
         /// 错误代码
@@ -121,7 +130,13 @@ impl Event {
                 let payload = super::ContextUsageEvent::from_frame(&frame)?;
                 Ok(Self::ContextUsage(payload))
             }
-            EventType::Unknown => Ok(Self::Unknown {}),
+            EventType::ReasoningContent => {
+                let payload = super::ReasoningContentEvent::from_frame(&frame)?;
+                Ok(Self::ReasoningContent(payload))
+            }
+            EventType::Unknown => Ok(Self::Unknown {
+                text: super::unknown_events::observe(event_type_str, &frame.payload),
+            }),
         }
     }
 
@@ -172,6 +187,10 @@ mod tests {
             EventType::from_str("contextUsageEvent"),
             EventType::ContextUsage
         );
+        assert_eq!(
+            EventType::from_str("reasoningContentEvent"),
+            EventType::ReasoningContent
+        );
         assert_eq!(EventType::from_str("unknown_type"), EventType::Unknown);
     }
 
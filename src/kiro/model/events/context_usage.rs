@@ -7,7 +7,8 @@ use serde::Deserialize;
 use crate::kiro::parser::error::ParseResult;
 use crate::kiro::parser::frame::Frame;
 
-use super::base::EventPayload;
+use super::base::{EventPayload, EventType};
+use super::unknown_fields;
 
 /// 上下文使用率事件
 ///
@@ -18,11 +19,16 @@ pub struct ContextUsageEvent {
     /// 上下文使用百分比 (0-100)
     #[serde(default)]
     pub context_usage_percentage: f64,
+    /// 未声明的字段（用于发现上游协议变化）
+    #[serde(flatten)]
+    pub(crate) extra: serde_json::Map<String, serde_json::Value>,
 }
 
 impl EventPayload for ContextUsageEvent {
     fn from_frame(frame: &Frame) -> ParseResult<Self> {
-        frame.payload_as_json()
+        let event: Self = frame.payload_as_json()?;
+        unknown_fields::observe(EventType::ContextUsage.as_str(), &event.extra);
+        Ok(event)
     }
 }
 
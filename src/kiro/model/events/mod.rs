@@ -5,9 +5,13 @@
 mod assistant;
 mod base;
 mod context_usage;
+mod reasoning;
 mod tool_use;
+pub mod unknown_events;
+pub mod unknown_fields;
 
 pub use assistant::AssistantResponseEvent;
 pub use base::Event;
 pub use context_usage::ContextUsageEvent;
+pub use reasoning::ReasoningContentEvent;
 pub use tool_use::ToolUseEvent;
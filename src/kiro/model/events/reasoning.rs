@@ -0,0 +1,58 @@
+//! 推理内容事件
+//!
+//! 处理 reasoningContentEvent 类型的事件
+
+use serde::Deserialize;
+
+use crate::kiro::parser::error::ParseResult;
+use crate::kiro::parser::frame::Frame;
+
+use super::base::{EventPayload, EventType};
+use super::unknown_fields;
+
+/// 推理内容事件
+///
+/// 原生推理输出：`text` 为思考内容片段，`signature` 标志一段思考结束，
+/// `redactedContent` 为被加密的思考内容（对应 Anthropic 的 redacted_thinking 块）
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ReasoningContentEvent {
+    /// 思考内容片段
+    #[serde(default)]
+    pub text: Option<String>,
+    /// 思考块签名
+    #[serde(default)]
+    pub signature: Option<String>,
+    /// 被加密的思考内容
+    #[serde(default)]
+    pub redacted_content: Option<String>,
+    /// 未声明的字段（用于发现上游协议变化）
+    #[serde(flatten)]
+    pub(crate) extra: serde_json::Map<String, serde_json::Value>,
+}
+
+impl EventPayload for ReasoningContentEvent {
+    fn from_frame(frame: &Frame) -> ParseResult<Self> {
+        let event: Self = frame.payload_as_json()?;
+        unknown_fields::observe(EventType::ReasoningContent.as_str(), &event.extra);
+        Ok(event)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_deserialize_reasoning_fields() {
+        let event: ReasoningContentEvent =
+            serde_json::from_str(r#"{"text":"hmm","signature":"sig"}"#).unwrap();
+        assert_eq!(event.text.as_deref(), Some("hmm"));
+        assert_eq!(event.signature.as_deref(), Some("sig"));
+        assert!(event.redacted_content.is_none());
+
+        let event: ReasoningContentEvent =
+            serde_json::from_str(r#"{"redactedContent":"opaque"}"#).unwrap();
+        assert_eq!(event.redacted_content.as_deref(), Some("opaque"));
+    }
+}
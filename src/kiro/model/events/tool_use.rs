@@ -7,17 +7,20 @@ use serde::Deserialize;
 use crate::kiro::parser::error::ParseResult;
 use crate::kiro::parser::frame::Frame;
 
-use super::base::EventPayload;
+use super::base::{EventPayload, EventType};
+use super::unknown_fields;
 
 /// 工具使用事件
 ///
 /// 包含工具调用的流式数据
-#[derive(Debug, Clone, Deserialize)]
+#[derive(Debug, Clone, Default, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct ToolUseEvent {
     /// 工具名称
+    #[serde(default)]
     pub name: String,
     /// 工具调用 ID
+    #[serde(default)]
     pub tool_use_id: String,
     /// 工具输入数据 (JSON 字符串，可能是流式的部分数据)
     #[serde(default)]
@@ -25,11 +28,16 @@ pub struct ToolUseEvent {
     /// 是否是最后一个块
     #[serde(default)]
     pub stop: bool,
+    /// 未声明的字段（用于发现上游协议变化）
+    #[serde(flatten)]
+    pub(crate) extra: serde_json::Map<String, serde_json::Value>,
 }
 
 impl EventPayload for ToolUseEvent {
     fn from_frame(frame: &Frame) -> ParseResult<Self> {
-        frame.payload_as_json()
+        let event: Self = frame.payload_as_json()?;
+        unknown_fields::observe(EventType::ToolUse.as_str(), &event.extra);
+        Ok(event)
     }
 }
 
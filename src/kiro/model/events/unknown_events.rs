@@ -0,0 +1,197 @@
+//! 上游未识别事件类型追踪
+//!
+//! 上游偶尔会新增事件类型，旧版本解析时只能丢弃。每种未识别的事件类型首次出现时
+//! 输出一条带 payload 样本的日志，之后同一类型每 [`LOG_INTERVAL`] 最多再输出一次；
+//! 出现次数在诊断接口中汇总。
+//!
+//! 开启 `mapUnknownTextEvents` 后，payload 顶层带有文本字段（`content` / `text`）
+//! 的未识别事件会按助手文本输出，避免上游协议变化时丢失回复内容。
+
+use std::collections::BTreeMap;
+use std::sync::LazyLock;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+
+use parking_lot::Mutex;
+use serde::Serialize;
+
+/// 同一事件类型两次日志之间的最小间隔
+const LOG_INTERVAL: Duration = Duration::from_secs(600);
+
+/// 日志与诊断接口中 payload 样本的最大字符数
+const SAMPLE_CHARS: usize = 256;
+
+/// 最多追踪的事件类型数（防止异常上游撑爆内存）
+const MAX_EVENT_TYPES: usize = 64;
+
+/// 视为文本内容的 payload 顶层字段（按顺序匹配）
+const TEXT_FIELDS: &[&str] = &["content", "text"];
+
+static MAP_TEXT: AtomicBool = AtomicBool::new(false);
+
+static SEEN: LazyLock<Mutex<BTreeMap<String, Sighting>>> =
+    LazyLock::new(|| Mutex::new(BTreeMap::new()));
+
+#[derive(Debug)]
+struct Sighting {
+    first_seen_at: String,
+    last_seen_at: String,
+    count: u64,
+    mapped_count: u64,
+    sample: String,
+    last_logged: Instant,
+}
+
+/// 某未识别事件类型的汇总
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UnknownEventReport {
+    pub event_type: String,
+    pub count: u64,
+    /// 按助手文本输出的次数
+    pub mapped_count: u64,
+    pub first_seen_at: String,
+    pub last_seen_at: String,
+    /// 最近一次 payload 的样本（截断）
+    pub sample: String,
+}
+
+/// 设置是否将带文本字段的未识别事件映射为助手文本
+pub fn init(map_text: bool) {
+    MAP_TEXT.store(map_text, Ordering::Relaxed);
+}
+
+/// payload 样本（按字符截断）
+fn sample(payload: &[u8]) -> String {
+    let text = String::from_utf8_lossy(payload);
+    match text.char_indices().nth(SAMPLE_CHARS) {
+        Some((end, _)) => format!("{}...", &text[..end]),
+        None => text.into_owned(),
+    }
+}
+
+/// 从 payload 顶层的文本字段提取内容
+fn extract_text(payload: &[u8]) -> Option<String> {
+    let value: serde_json::Value = serde_json::from_slice(payload).ok()?;
+    let object = value.as_object()?;
+    TEXT_FIELDS
+        .iter()
+        .filter_map(|field| object.get(*field)?.as_str())
+        .find(|text| !text.is_empty())
+        .map(str::to_string)
+}
+
+/// 记录一次出现，返回需要输出日志时的累计次数（首次出现为 1）
+fn record(event_type: &str, sample: String, mapped: bool, now: Instant) -> Option<u64> {
+    let mut seen = SEEN.lock();
+    let timestamp = chrono::Utc::now().to_rfc3339();
+    if let Some(sighting) = seen.get_mut(event_type) {
+        sighting.count += 1;
+        sighting.mapped_count += u64::from(mapped);
+        sighting.last_seen_at = timestamp;
+        sighting.sample = sample;
+        if now.duration_since(sighting.last_logged) < LOG_INTERVAL {
+            return None;
+        }
+        sighting.last_logged = now;
+        return Some(sighting.count);
+    }
+    if seen.len() >= MAX_EVENT_TYPES {
+        return None;
+    }
+    seen.insert(
+        event_type.to_string(),
+        Sighting {
+            first_seen_at: timestamp.clone(),
+            last_seen_at: timestamp,
+            count: 1,
+            mapped_count: u64::from(mapped),
+            sample,
+            last_logged: now,
+        },
+    );
+    Some(1)
+}
+
+/// 观察一个未识别事件，返回映射出的助手文本（未开启映射或没有文本字段时为 None）
+pub fn observe(event_type: &str, payload: &[u8]) -> Option<String> {
+    let text = MAP_TEXT
+        .load(Ordering::Relaxed)
+        .then(|| extract_text(payload))
+        .flatten();
+    let sample = sample(payload);
+    match record(event_type, sample.clone(), text.is_some(), Instant::now()) {
+        Some(1) => tracing::warn!(
+            "上游出现未识别事件类型 {}，可能是协议更新{}，payload 样本: {}",
+            event_type,
+            if text.is_some() {
+                "（已按文本输出）"
+            } else {
+                ""
+            },
+            sample
+        ),
+        Some(count) => tracing::warn!(
+            "上游未识别事件类型 {} 累计出现 {} 次，最近 payload 样本: {}",
+            event_type,
+            count,
+            sample
+        ),
+        None => {}
+    }
+    text
+}
+
+/// 所有未识别事件类型的汇总
+pub fn report() -> Vec<UnknownEventReport> {
+    SEEN.lock()
+        .iter()
+        .map(|(event_type, s)| UnknownEventReport {
+            event_type: event_type.clone(),
+            count: s.count,
+            mapped_count: s.mapped_count,
+            first_seen_at: s.first_seen_at.clone(),
+            last_seen_at: s.last_seen_at.clone(),
+            sample: s.sample.clone(),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_record_rate_limits_logs() {
+        let event_type = "testRateLimitedEvent";
+        let start = Instant::now();
+        assert_eq!(record(event_type, "a".into(), false, start), Some(1));
+        assert_eq!(record(event_type, "b".into(), true, start), None);
+        assert_eq!(
+            record(event_type, "c".into(), false, start + LOG_INTERVAL),
+            Some(3)
+        );
+
+        let report = report();
+        let entry = report.iter().find(|r| r.event_type == event_type).unwrap();
+        assert_eq!((entry.count, entry.mapped_count), (3, 1));
+        assert_eq!(entry.sample, "c");
+    }
+
+    #[test]
+    fn test_extract_text_and_sample() {
+        assert_eq!(
+            extract_text(br#"{"content":"Hello","id":1}"#).as_deref(),
+            Some("Hello")
+        );
+        assert_eq!(
+            extract_text(br#"{"content":"","text":"Hi"}"#).as_deref(),
+            Some("Hi")
+        );
+        assert_eq!(extract_text(br#"{"delta":{"text":"x"}}"#), None);
+        assert_eq!(extract_text(b"not json"), None);
+
+        let long = "中".repeat(SAMPLE_CHARS + 10);
+        assert_eq!(sample(long.as_bytes()).chars().count(), SAMPLE_CHARS + 3);
+    }
+}
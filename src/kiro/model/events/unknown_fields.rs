@@ -0,0 +1,187 @@
+//! 上游事件未识别字段追踪
+//!
+//! 事件模型通过 `#[serde(flatten)]` 捕获未声明的字段，反序列化不会因上游新增字段失败。
+//! 每种事件类型首次出现新字段时输出一条日志（字段名摘要），
+//! 并在诊断接口中汇总，便于维护者尽早发现上游协议变化。
+
+use std::collections::BTreeMap;
+use std::sync::LazyLock;
+
+use parking_lot::Mutex;
+use serde::Serialize;
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+
+/// 已知存在但未使用的字段（不视为协议变化）
+const KNOWN_IGNORED: &[(&str, &[&str])] = &[(
+    "assistantResponseEvent",
+    &[
+        "conversationId",
+        "messageId",
+        "messageStatus",
+        "followupPrompt",
+    ],
+)];
+
+/// 每种事件类型最多记录的字段数（防止异常 payload 撑爆内存）
+const MAX_FIELDS_PER_TYPE: usize = 64;
+
+static SEEN: LazyLock<Mutex<BTreeMap<String, BTreeMap<String, FieldSighting>>>> =
+    LazyLock::new(|| Mutex::new(BTreeMap::new()));
+
+#[derive(Debug, Clone)]
+struct FieldSighting {
+    first_seen_at: String,
+    count: u64,
+    value_type: &'static str,
+}
+
+/// 单个未识别字段的汇总
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UnknownField {
+    pub name: String,
+    /// 首次出现时值的 JSON 类型
+    pub value_type: &'static str,
+    pub first_seen_at: String,
+    pub count: u64,
+}
+
+/// 某事件类型的未识别字段
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UnknownFieldsReport {
+    pub event_type: String,
+    /// 字段名集合的摘要（与日志中一致）
+    pub digest: String,
+    pub fields: Vec<UnknownField>,
+}
+
+fn value_type(value: &Value) -> &'static str {
+    match value {
+        Value::Null => "null",
+        Value::Bool(_) => "boolean",
+        Value::Number(_) => "number",
+        Value::String(_) => "string",
+        Value::Array(_) => "array",
+        Value::Object(_) => "object",
+    }
+}
+
+fn is_known_ignored(event_type: &str, field: &str) -> bool {
+    KNOWN_IGNORED
+        .iter()
+        .any(|(t, fields)| *t == event_type && fields.contains(&field))
+}
+
+/// 字段名集合的短摘要（sha256 前 8 位）
+fn digest<'a>(names: impl Iterator<Item = &'a String>) -> String {
+    let mut hasher = Sha256::new();
+    for name in names {
+        hasher.update(name.as_bytes());
+        hasher.update(b"\n");
+    }
+    hex::encode(&hasher.finalize()[..4])
+}
+
+/// 记录事件中捕获到的额外字段，返回本次首次出现的字段名
+fn record(event_type: &str, extra: &serde_json::Map<String, Value>) -> Vec<String> {
+    let mut seen = SEEN.lock();
+    let fields = seen.entry(event_type.to_string()).or_default();
+    let mut new_fields = Vec::new();
+    for (name, value) in extra {
+        if is_known_ignored(event_type, name) {
+            continue;
+        }
+        if let Some(sighting) = fields.get_mut(name) {
+            sighting.count += 1;
+            continue;
+        }
+        if fields.len() >= MAX_FIELDS_PER_TYPE {
+            continue;
+        }
+        fields.insert(
+            name.clone(),
+            FieldSighting {
+                first_seen_at: chrono::Utc::now().to_rfc3339(),
+                count: 1,
+                value_type: value_type(value),
+            },
+        );
+        new_fields.push(name.clone());
+    }
+    new_fields
+}
+
+/// 观察事件的额外字段（新字段首次出现时输出日志）
+pub fn observe(event_type: &str, extra: &serde_json::Map<String, Value>) {
+    if extra.is_empty() {
+        return;
+    }
+    let mut new_fields = record(event_type, extra);
+    if new_fields.is_empty() {
+        return;
+    }
+    new_fields.sort();
+    tracing::warn!(
+        "上游 {} 事件出现未识别字段（digest {}）: {}，可能是协议更新",
+        event_type,
+        digest(new_fields.iter()),
+        new_fields.join(", ")
+    );
+}
+
+/// 所有事件类型的未识别字段汇总
+pub fn report() -> Vec<UnknownFieldsReport> {
+    SEEN.lock()
+        .iter()
+        .filter(|(_, fields)| !fields.is_empty())
+        .map(|(event_type, fields)| UnknownFieldsReport {
+            event_type: event_type.clone(),
+            digest: digest(fields.keys()),
+            fields: fields
+                .iter()
+                .map(|(name, s)| UnknownField {
+                    name: name.clone(),
+                    value_type: s.value_type,
+                    first_seen_at: s.first_seen_at.clone(),
+                    count: s.count,
+                })
+                .collect(),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn extra(value: Value) -> serde_json::Map<String, Value> {
+        value.as_object().unwrap().clone()
+    }
+
+    #[test]
+    fn test_record_reports_new_fields_once() {
+        let event_type = "testRecordEvent";
+        let first = record(event_type, &extra(json!({ "a": 1, "b": "x" })));
+        assert_eq!(first, ["a", "b"]);
+        let second = record(event_type, &extra(json!({ "a": 2, "c": [] })));
+        assert_eq!(second, ["c"]);
+
+        let report = report();
+        let entry = report.iter().find(|r| r.event_type == event_type).unwrap();
+        let a = entry.fields.iter().find(|f| f.name == "a").unwrap();
+        assert_eq!((a.count, a.value_type), (2, "number"));
+        assert_eq!(entry.digest.len(), 8);
+    }
+
+    #[test]
+    fn test_known_ignored_fields_are_skipped() {
+        let new = record(
+            "assistantResponseEvent",
+            &extra(json!({ "conversationId": "c", "messageId": "m" })),
+        );
+        assert!(new.is_empty());
+    }
+}
@@ -0,0 +1,439 @@
+//! 模型别名与可用模型注册表
+//!
+//! 请求模型到 Kiro 模型 ID 的解析顺序：
+//! 1. `modelAliases` 中的别名（不区分大小写，忽略 `-thinking` 后缀）
+//! 2. 内置映射规则（见 `anthropic::converter::map_model`）
+//! 3. 开启 `modelRegistryRefreshSecs` 后，若映射结果不在 Kiro 返回的可用模型列表中，
+//!    自动改用同系列（opus / sonnet / haiku）中版本最新的可用模型，
+//!    无同系列模型时使用 Kiro 的默认模型，并通过响应头提示调用方
+//!
+//! 同时维护各 Kiro 模型的能力表（上下文窗口、最大输出、是否支持 thinking / 图片），
+//! 内置能力可通过 `modelCapabilities` 按字段覆盖，`/v1/models` 的模型列表也由此生成。
+
+use std::collections::HashMap;
+use std::sync::{Arc, LazyLock, Weak};
+use std::time::Duration;
+
+use parking_lot::RwLock;
+
+use crate::kiro::token_manager::MultiTokenManager;
+use crate::model::config::ModelCapabilityOverride;
+
+/// 模型能力
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ModelCapabilities {
+    /// 上下文窗口（tokens）
+    pub context_window: u32,
+    /// 最大输出 tokens
+    pub max_output_tokens: u32,
+    pub supports_thinking: bool,
+    pub supports_images: bool,
+}
+
+impl ModelCapabilities {
+    /// 未知模型使用的默认能力
+    const DEFAULT: Self = Self {
+        context_window: 200_000,
+        max_output_tokens: 64_000,
+        supports_thinking: true,
+        supports_images: true,
+    };
+
+    /// Kiro 于 2026-03-24 将 Opus 4.6 和 Sonnet 4.6 升级至 1M 上下文
+    const LONG_CONTEXT: Self = Self {
+        context_window: 1_000_000,
+        ..Self::DEFAULT
+    };
+
+    fn with_override(self, o: &ModelCapabilityOverride) -> Self {
+        Self {
+            context_window: o.context_window.unwrap_or(self.context_window),
+            max_output_tokens: o.max_output_tokens.unwrap_or(self.max_output_tokens),
+            supports_thinking: o.supports_thinking.unwrap_or(self.supports_thinking),
+            supports_images: o.supports_images.unwrap_or(self.supports_images),
+        }
+    }
+}
+
+impl Default for ModelCapabilities {
+    fn default() -> Self {
+        Self::DEFAULT
+    }
+}
+
+/// 内置模型（对外的模型名与对应的 Kiro 模型）
+struct BuiltinModel {
+    id: &'static str,
+    display_name: &'static str,
+    created: i64,
+    model_id: &'static str,
+    capabilities: ModelCapabilities,
+}
+
+const BUILTIN_MODELS: &[BuiltinModel] = &[
+    BuiltinModel {
+        id: "claude-opus-4-6",
+        display_name: "Claude Opus 4.6",
+        created: 1770163200, // Feb 4, 2026
+        model_id: "claude-opus-4.6",
+        capabilities: ModelCapabilities::LONG_CONTEXT,
+    },
+    BuiltinModel {
+        id: "claude-sonnet-4-6",
+        display_name: "Claude Sonnet 4.6",
+        created: 1771286400, // Feb 17, 2026
+        model_id: "claude-sonnet-4.6",
+        capabilities: ModelCapabilities::LONG_CONTEXT,
+    },
+    BuiltinModel {
+        id: "claude-opus-4-5-20251101",
+        display_name: "Claude Opus 4.5",
+        created: 1763942400, // Nov 24, 2025
+        model_id: "claude-opus-4.5",
+        capabilities: ModelCapabilities::DEFAULT,
+    },
+    BuiltinModel {
+        id: "claude-sonnet-4-5-20250929",
+        display_name: "Claude Sonnet 4.5",
+        created: 1759104000, // Sep 29, 2025
+        model_id: "claude-sonnet-4.5",
+        capabilities: ModelCapabilities::DEFAULT,
+    },
+    BuiltinModel {
+        id: "claude-haiku-4-5-20251001",
+        display_name: "Claude Haiku 4.5",
+        created: 1760486400, // Oct 15, 2025
+        model_id: "claude-haiku-4.5",
+        capabilities: ModelCapabilities::DEFAULT,
+    },
+];
+
+/// 对外列出的模型（`/v1/models`）
+#[derive(Debug, Clone, PartialEq)]
+pub struct ListedModel {
+    pub id: String,
+    pub display_name: String,
+    pub created: i64,
+    pub max_tokens: u32,
+}
+
+/// Kiro 返回的可用模型
+#[derive(Debug, Clone, Default, PartialEq)]
+struct AvailableModels {
+    ids: Vec<String>,
+    default: Option<String>,
+}
+
+#[derive(Debug, Default)]
+struct RegistryState {
+    /// 别名表（键已转为小写）
+    aliases: HashMap<String, String>,
+    /// 模型能力覆盖（键为 Kiro 模型 ID）
+    capabilities: HashMap<String, ModelCapabilityOverride>,
+    /// 最近一次拉取的可用模型（未开启或尚未拉取成功时为 None）
+    available: Option<AvailableModels>,
+}
+
+static STATE: LazyLock<RwLock<RegistryState>> =
+    LazyLock::new(|| RwLock::new(RegistryState::default()));
+
+/// 模型解析结果
+#[derive(Debug, Clone, PartialEq)]
+pub struct ModelResolution {
+    /// 发送给 Kiro 的模型 ID
+    pub model_id: String,
+    /// 按规则映射的模型不可用而被自动替换时，记录原映射结果（未知模型为请求模型名）
+    pub replaced: Option<String>,
+}
+
+/// 初始化别名表与模型能力覆盖
+pub fn init(
+    aliases: &HashMap<String, String>,
+    capabilities: &HashMap<String, ModelCapabilityOverride>,
+) {
+    let mut state = STATE.write();
+    state.aliases = aliases
+        .iter()
+        .map(|(k, v)| (k.to_lowercase(), v.clone()))
+        .collect();
+    state.capabilities = capabilities.clone();
+}
+
+/// 查询 Kiro 模型的能力：内置能力表叠加 `modelCapabilities` 覆盖，未知模型使用默认能力
+pub fn capabilities(model_id: &str) -> ModelCapabilities {
+    capabilities_with(model_id, &STATE.read().capabilities)
+}
+
+fn capabilities_with(
+    model_id: &str,
+    overrides: &HashMap<String, ModelCapabilityOverride>,
+) -> ModelCapabilities {
+    let builtin = BUILTIN_MODELS
+        .iter()
+        .find(|m| m.model_id == model_id)
+        .map_or(ModelCapabilities::DEFAULT, |m| m.capabilities);
+    match overrides.get(model_id) {
+        Some(o) => builtin.with_override(o),
+        None => builtin,
+    }
+}
+
+/// 对外列出的模型：内置模型（支持 thinking 的附带 `-thinking` 变体）与别名
+///
+/// 已拉取 Kiro 可用模型列表时，只列出对应 Kiro 模型可用的内置模型
+pub fn list_models() -> Vec<ListedModel> {
+    let state = STATE.read();
+    list_models_with(&state)
+}
+
+fn list_models_with(state: &RegistryState) -> Vec<ListedModel> {
+    let mut models = Vec::new();
+    for m in BUILTIN_MODELS {
+        if let Some(available) = &state.available
+            && !available.ids.iter().any(|id| id == m.model_id)
+        {
+            continue;
+        }
+        let caps = capabilities_with(m.model_id, &state.capabilities);
+        models.push(ListedModel {
+            id: m.id.to_string(),
+            display_name: m.display_name.to_string(),
+            created: m.created,
+            max_tokens: caps.max_output_tokens,
+        });
+        if caps.supports_thinking {
+            models.push(ListedModel {
+                id: format!("{}-thinking", m.id),
+                display_name: format!("{} (Thinking)", m.display_name),
+                created: m.created,
+                max_tokens: caps.max_output_tokens,
+            });
+        }
+    }
+
+    let mut aliases: Vec<(&String, &String)> = state.aliases.iter().collect();
+    aliases.sort();
+    for (alias, model_id) in aliases {
+        if models.iter().any(|m| m.id.eq_ignore_ascii_case(alias)) {
+            continue;
+        }
+        let created = BUILTIN_MODELS
+            .iter()
+            .find(|m| m.model_id == model_id)
+            .map_or(0, |m| m.created);
+        models.push(ListedModel {
+            id: alias.clone(),
+            display_name: alias.clone(),
+            created,
+            max_tokens: capabilities_with(model_id, &state.capabilities).max_output_tokens,
+        });
+    }
+    models
+}
+
+/// 解析请求模型（`builtin` 为内置映射规则的结果）
+pub fn resolve(requested: &str, builtin: Option<String>) -> Option<ModelResolution> {
+    let state = STATE.read();
+    resolve_with(requested, builtin, &state.aliases, state.available.as_ref())
+}
+
+fn family(model: &str) -> Option<&'static str> {
+    ["opus", "sonnet", "haiku"]
+        .into_iter()
+        .find(|f| model.contains(f))
+}
+
+fn resolve_with(
+    requested: &str,
+    builtin: Option<String>,
+    aliases: &HashMap<String, String>,
+    available: Option<&AvailableModels>,
+) -> Option<ModelResolution> {
+    let lower = requested.to_lowercase();
+    if let Some(target) = aliases
+        .get(&lower)
+        .or_else(|| aliases.get(lower.trim_end_matches("-thinking")))
+    {
+        return Some(ModelResolution {
+            model_id: target.clone(),
+            replaced: None,
+        });
+    }
+
+    let Some(available) = available else {
+        return builtin.map(|model_id| ModelResolution {
+            model_id,
+            replaced: None,
+        });
+    };
+    if let Some(model_id) = &builtin
+        && available.ids.contains(model_id)
+    {
+        return Some(ModelResolution {
+            model_id: model_id.clone(),
+            replaced: None,
+        });
+    }
+
+    let substitute = family(&lower)
+        .and_then(|f| available.ids.iter().filter(|id| id.contains(f)).max())
+        .or(available.default.as_ref())?;
+    Some(ModelResolution {
+        model_id: substitute.clone(),
+        replaced: Some(builtin.unwrap_or_else(|| requested.to_string())),
+    })
+}
+
+/// 启动可用模型列表的定期拉取任务（间隔为 0 时不启动）
+pub fn spawn_refresh(token_manager: &Arc<MultiTokenManager>, interval_secs: u64) {
+    if interval_secs == 0 {
+        return;
+    }
+    let manager: Weak<MultiTokenManager> = Arc::downgrade(token_manager);
+    tokio::spawn(async move {
+        loop {
+            let Some(token_manager) = manager.upgrade() else {
+                break;
+            };
+            match token_manager.fetch_available_models().await {
+                Ok(response) if !response.models.is_empty() => {
+                    let available = AvailableModels {
+                        ids: response.models.into_iter().map(|m| m.model_id).collect(),
+                        default: response.default_model.map(|m| m.model_id),
+                    };
+                    let mut state = STATE.write();
+                    if state.available.as_ref() != Some(&available) {
+                        tracing::info!("Kiro 可用模型已更新: {}", available.ids.join(", "));
+                        state.available = Some(available);
+                    }
+                }
+                Ok(_) => tracing::warn!("Kiro 返回的可用模型列表为空，保留上次结果"),
+                Err(e) => tracing::warn!("拉取 Kiro 可用模型列表失败: {}", e),
+            }
+            drop(token_manager);
+            tokio::time::sleep(Duration::from_secs(interval_secs)).await;
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn available() -> AvailableModels {
+        AvailableModels {
+            ids: vec![
+                "claude-sonnet-4.5".to_string(),
+                "claude-sonnet-4.6".to_string(),
+                "claude-haiku-4.5".to_string(),
+            ],
+            default: Some("claude-sonnet-4.5".to_string()),
+        }
+    }
+
+    #[test]
+    fn test_alias_takes_precedence() {
+        let aliases =
+            HashMap::from([("claude-opus-4-6".to_string(), "claude-opus-4.5".to_string())]);
+        let resolved = resolve_with(
+            "Claude-Opus-4-6-thinking",
+            Some("claude-opus-4.6".to_string()),
+            &aliases,
+            Some(&available()),
+        )
+        .unwrap();
+        assert_eq!(resolved.model_id, "claude-opus-4.5");
+        assert_eq!(resolved.replaced, None);
+    }
+
+    #[test]
+    fn test_unavailable_model_falls_back() {
+        let aliases = HashMap::new();
+        let available = available();
+
+        let kept = resolve_with(
+            "claude-sonnet-4-5",
+            Some("claude-sonnet-4.5".to_string()),
+            &aliases,
+            Some(&available),
+        )
+        .unwrap();
+        assert_eq!(kept.replaced, None);
+
+        let replaced = resolve_with(
+            "claude-opus-4-6",
+            Some("claude-opus-4.6".to_string()),
+            &aliases,
+            Some(&available),
+        )
+        .unwrap();
+        assert_eq!(replaced.model_id, "claude-sonnet-4.5");
+        assert_eq!(replaced.replaced.as_deref(), Some("claude-opus-4.6"));
+
+        let unknown = resolve_with("gpt-4", None, &aliases, Some(&available)).unwrap();
+        assert_eq!(unknown.model_id, "claude-sonnet-4.5");
+        assert!(resolve_with("gpt-4", None, &aliases, None).is_none());
+    }
+
+    #[test]
+    fn test_capabilities_override() {
+        let overrides = HashMap::from([(
+            "claude-haiku-4.5".to_string(),
+            ModelCapabilityOverride {
+                max_output_tokens: Some(8192),
+                supports_thinking: Some(false),
+                ..Default::default()
+            },
+        )]);
+        let haiku = capabilities_with("claude-haiku-4.5", &overrides);
+        assert_eq!(haiku.max_output_tokens, 8192);
+        assert!(!haiku.supports_thinking);
+        assert_eq!(haiku.context_window, 200_000);
+
+        assert_eq!(
+            capabilities_with("claude-opus-4.6", &overrides).context_window,
+            1_000_000
+        );
+        assert_eq!(
+            capabilities_with("unknown", &overrides),
+            ModelCapabilities::DEFAULT
+        );
+    }
+
+    #[test]
+    fn test_list_models() {
+        let state = RegistryState {
+            aliases: HashMap::from([
+                ("my-model".to_string(), "claude-sonnet-4.5".to_string()),
+                (
+                    "claude-sonnet-4-6".to_string(),
+                    "claude-sonnet-4.5".to_string(),
+                ),
+            ]),
+            capabilities: HashMap::from([(
+                "claude-haiku-4.5".to_string(),
+                ModelCapabilityOverride {
+                    max_output_tokens: Some(8192),
+                    supports_thinking: Some(false),
+                    ..Default::default()
+                },
+            )]),
+            available: Some(available()),
+        };
+        let ids: Vec<String> = list_models_with(&state)
+            .into_iter()
+            .map(|m| format!("{}:{}", m.id, m.max_tokens))
+            .collect();
+        assert_eq!(
+            ids,
+            [
+                "claude-sonnet-4-6:64000",
+                "claude-sonnet-4-6-thinking:64000",
+                "claude-sonnet-4-5-20250929:64000",
+                "claude-sonnet-4-5-20250929-thinking:64000",
+                "claude-haiku-4-5-20251001:8192",
+                "my-model:64000",
+            ]
+        );
+    }
+}
@@ -5,17 +5,28 @@
 //! 支持多凭据故障转移和重试
 //! 支持按凭据级 endpoint 切换不同 Kiro API 端点
 
+use bytes::Bytes;
+use futures::{Stream, StreamExt, stream};
 use reqwest::Client;
+use reqwest::header::{HeaderMap, RETRY_AFTER};
 use std::collections::{HashMap, HashSet};
+use std::fmt;
 use std::sync::Arc;
-use std::time::Duration;
-use tokio::time::sleep;
+use std::time::{Duration, Instant};
+use tokio::time::{sleep, timeout};
+use tracing::Instrument;
 
 use crate::http_client::{ProxyConfig, build_client};
+use crate::kiro::concurrency::{self, InFlightPermit};
 use crate::kiro::endpoint::{KiroEndpoint, RequestContext};
 use crate::kiro::machine_id;
+use crate::kiro::malformed;
 use crate::kiro::model::credentials::KiroCredentials;
-use crate::kiro::token_manager::MultiTokenManager;
+use crate::kiro::model::events::Event;
+use crate::kiro::parser::decoder::EventStreamDecoder;
+use crate::kiro::proxy_health;
+use crate::kiro::request_size;
+use crate::kiro::token_manager::{MultiTokenManager, RouteHints};
 use crate::model::config::TlsBackend;
 use parking_lot::Mutex;
 
@@ -25,6 +36,169 @@ const MAX_RETRIES_PER_CREDENTIAL: usize = 3;
 /// 总重试次数硬上限（避免无限重试）
 const MAX_TOTAL_RETRIES: usize = 9;
 
+/// 流式响应等待首个事件的最长时间
+///
+/// 超时后直接返回响应，不再对首个事件做凭据错误检查
+const STREAM_PEEK_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// 上游 429 限流冷却的上限（秒），避免异常的 Retry-After 长时间锁住凭据
+const MAX_THROTTLE_COOLDOWN_SECS: u64 = 3600;
+
+/// Client 缓存键：(effective proxy config, TLS 后端, 请求指定的 API Region)
+type ClientKey = (Option<ProxyConfig>, TlsBackend, Option<String>);
+
+/// 上游限流错误（429 重试耗尽后返回）
+///
+/// 携带上游 `Retry-After` 给出的等待秒数，供 handler 转发给客户端
+#[derive(Debug)]
+pub struct UpstreamThrottledError {
+    pub message: String,
+    pub retry_after: Option<u64>,
+}
+
+impl fmt::Display for UpstreamThrottledError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+impl std::error::Error for UpstreamThrottledError {}
+
+/// 实际服务请求的凭据 ID（成功时存放在上游响应的 extensions 中）
+#[derive(Debug, Clone, Copy)]
+pub struct ServedCredential(pub u64);
+
+/// 读取响应体为字节流，并让在途并发许可随流一起释放
+///
+/// `bytes_stream()` 会丢弃响应的 extensions，流式读取上游响应时应使用本函数
+pub fn body_stream(mut response: reqwest::Response) -> impl Stream<Item = reqwest::Result<Bytes>> {
+    let permit = response.extensions_mut().remove::<InFlightPermit>();
+    response.bytes_stream().map(move |chunk| {
+        let _keep = &permit;
+        chunk
+    })
+}
+
+/// 流式响应首个事件的检查结果
+enum StreamStart {
+    /// 首个事件正常（或等待超时），响应中包含已预读的数据
+    Ready(reqwest::Response),
+    /// 首个事件为凭据类异常（如 AccessDeniedException）
+    AuthFailed(String),
+    /// 首个事件到达前响应流已中断
+    Broken(String),
+}
+
+/// 是否为凭据类异常/错误代码（上游以 200 响应后在事件流中返回的 401/403）
+fn is_auth_failure(kind: &str, message: &str) -> bool {
+    ["AccessDenied", "Unauthorized", "ExpiredToken", "InvalidToken"]
+        .iter()
+        .any(|k| kind.contains(k))
+        || crate::kiro::endpoint::default_is_bearer_token_invalid(message)
+}
+
+/// 预读流式响应直到首个事件，检查上游是否在事件流中返回了凭据错误
+///
+/// 尚未向客户端发送任何内容，凭据错误和流中断都可以透明地换凭据重试；
+/// 检查通过时把预读的数据与剩余的流重新拼成响应返回
+async fn peek_stream_start(response: reqwest::Response) -> StreamStart {
+    let status = response.status();
+    let headers = response.headers().clone();
+    let mut body = Box::pin(body_stream(response));
+    let mut decoder = EventStreamDecoder::new();
+    let mut buffered: Vec<Bytes> = Vec::new();
+
+    let first_event = timeout(STREAM_PEEK_TIMEOUT, async {
+        loop {
+            match body.next().await {
+                Some(Ok(chunk)) => {
+                    let fed = decoder.feed(&chunk);
+                    buffered.push(chunk);
+                    if fed.is_err() {
+                        return Ok(None);
+                    }
+                    match decoder.decode() {
+                        Ok(Some(frame)) => return Ok(Event::from_frame(frame).ok()),
+                        Ok(None) => continue,
+                        // 解码错误交给下游的流处理记录
+                        Err(_) => return Ok(None),
+                    }
+                }
+                Some(Err(e)) => return Err(format!("读取响应流失败: {}", e)),
+                None => return Err("响应流在首个事件前结束".to_string()),
+            }
+        }
+    })
+    .await;
+
+    match first_event {
+        Ok(Err(e)) => return StreamStart::Broken(e),
+        Ok(Ok(Some(Event::Exception {
+            exception_type: kind,
+            message,
+        })))
+        | Ok(Ok(Some(Event::Error {
+            error_code: kind,
+            error_message: message,
+        }))) if is_auth_failure(&kind, &message) => {
+            return StreamStart::AuthFailed(format!("{}: {}", kind, message));
+        }
+        _ => {}
+    }
+
+    let replay = stream::iter(buffered.into_iter().map(Ok::<_, reqwest::Error>));
+    let mut rebuilt = http::Response::new(reqwest::Body::wrap_stream(replay.chain(body)));
+    *rebuilt.status_mut() = status;
+    *rebuilt.headers_mut() = headers;
+    StreamStart::Ready(rebuilt.into())
+}
+
+/// 解析 `Retry-After` 响应头（秒数或 HTTP 日期），返回距现在的等待秒数
+pub fn parse_retry_after(headers: &HeaderMap) -> Option<u64> {
+    let value = headers.get(RETRY_AFTER)?.to_str().ok()?.trim();
+    if let Ok(secs) = value.parse::<u64>() {
+        return Some(secs);
+    }
+    let at = chrono::DateTime::parse_from_rfc2822(value).ok()?;
+    let secs = (at.timestamp() - chrono::Utc::now().timestamp()).max(0);
+    Some(secs as u64)
+}
+
+/// 从 429 响应体中读取 retry-after 类提示（`retryAfterSeconds` / `retryAfter` / `retry_after`
+/// 数值字段，可位于顶层或 `error` 对象下），返回等待秒数
+fn parse_retry_after_body(body: &str) -> Option<u64> {
+    let json: serde_json::Value = serde_json::from_str(body).ok()?;
+    let error = json.get("error").unwrap_or(&serde_json::Value::Null);
+    [&json, error]
+        .into_iter()
+        .flat_map(|v| ["retryAfterSeconds", "retryAfter", "retry_after"].map(|key| v.get(key)))
+        .flatten()
+        .find_map(|v| {
+            v.as_u64()
+                .or_else(|| v.as_f64().map(|secs| secs.max(0.0).ceil() as u64))
+        })
+}
+
+/// 一次上游调用的重试情况（记录到 `kiro.call_api` span）
+#[derive(Debug, Default)]
+struct RetryTrace {
+    /// 已尝试次数
+    attempts: usize,
+    /// 最后一次尝试使用的凭据
+    credential_id: Option<u64>,
+}
+
+/// 单次 API 调用的请求级选项
+#[derive(Debug, Clone, Copy, Default)]
+pub struct CallOptions<'a> {
+    /// 会话亲和路由的会话键（未配置 `sessionAffinity` 时忽略）
+    pub session_key: Option<&'a str>,
+    /// 转发给上游的用户标识哈希（未配置 `userIdHeader` 时忽略）
+    pub user_hash: Option<&'a str>,
+    /// 覆盖凭据与全局配置的 API Region（来自 `X-Kiro-Region` 请求头）
+    pub api_region: Option<&'a str>,
+}
+
 /// Kiro API Provider
 ///
 /// 核心组件，负责与 Kiro API 通信
@@ -32,13 +206,10 @@ const MAX_TOTAL_RETRIES: usize = 9;
 /// 按凭据 `endpoint` 字段选择 [`KiroEndpoint`] 实现
 pub struct KiroProvider {
     token_manager: Arc<MultiTokenManager>,
-    /// 全局代理配置（用于凭据无自定义代理时的回退）
-    global_proxy: Option<ProxyConfig>,
-    /// Client 缓存：key = effective proxy config, value = reqwest::Client
-    /// 不同代理配置的凭据使用不同的 Client，共享相同代理的凭据复用 Client
-    client_cache: Mutex<HashMap<Option<ProxyConfig>, Client>>,
-    /// TLS 后端配置
-    tls_backend: TlsBackend,
+    /// Client 缓存：key 见 [`ClientKey`]，value = reqwest::Client
+    /// 不同代理配置或客户端画像 TLS 后端的凭据使用不同的 Client，两者相同的凭据复用 Client；
+    /// 通过 `X-Kiro-Region` 指定 Region 的请求按 Region 使用独立的 Client（连接池）
+    client_cache: Mutex<HashMap<ClientKey, Client>>,
     /// 端点实现注册表（key: endpoint 名称）
     endpoints: HashMap<String, Arc<dyn KiroEndpoint>>,
     /// 默认端点名称（凭据未指定 endpoint 时使用）
@@ -50,7 +221,7 @@ impl KiroProvider {
     ///
     /// # Arguments
     /// * `token_manager` - 多凭据 Token 管理器
-    /// * `proxy` - 全局代理配置
+    /// * `proxy` - 全局代理配置（仅用于预热 Client，运行时以 token_manager 中的配置为准）
     /// * `endpoints` - 端点名 → 实现的注册表（至少包含 `default_endpoint` 对应条目）
     /// * `default_endpoint` - 凭据未显式指定 endpoint 时使用的名称
     pub fn with_proxy(
@@ -69,30 +240,56 @@ impl KiroProvider {
         let initial_client = build_client(proxy.as_ref(), 720, tls_backend)
             .expect("创建 HTTP 客户端失败");
         let mut cache = HashMap::new();
-        cache.insert(proxy.clone(), initial_client);
+        cache.insert((proxy.clone(), tls_backend, None), initial_client);
 
         Self {
             token_manager,
-            global_proxy: proxy,
             client_cache: Mutex::new(cache),
-            tls_backend,
             endpoints,
             default_endpoint,
         }
     }
 
-    /// 根据凭据的代理配置获取（或创建并缓存）对应的 reqwest::Client
-    fn client_for(&self, credentials: &KiroCredentials) -> anyhow::Result<Client> {
-        let effective = credentials.effective_proxy(self.global_proxy.as_ref());
+    /// 凭据当前生效的代理（凭据代理不可用时已切换到备用代理）
+    fn proxy_for(&self, credentials: &KiroCredentials) -> Option<ProxyConfig> {
+        // 全局代理以 token_manager 为准（配置热加载时会更新）
+        credentials.effective_proxy(self.token_manager.global_proxy().as_ref())
+    }
+
+    /// 获取（或创建并缓存）代理配置、TLS 后端与请求指定 Region 对应的 reqwest::Client
+    fn client_for(
+        &self,
+        proxy: &Option<ProxyConfig>,
+        tls_backend: TlsBackend,
+        api_region: Option<&str>,
+    ) -> anyhow::Result<Client> {
         let mut cache = self.client_cache.lock();
-        if let Some(client) = cache.get(&effective) {
+        let key = (proxy.clone(), tls_backend, api_region.map(str::to_string));
+        if let Some(client) = cache.get(&key) {
             return Ok(client.clone());
         }
-        let client = build_client(effective.as_ref(), 720, self.tls_backend)?;
-        cache.insert(effective, client.clone());
+        let client = build_client(proxy.as_ref(), 720, tls_backend)?;
+        cache.insert(key, client.clone());
         Ok(client)
     }
 
+    /// 记录经由凭据级代理的发送结果：连接失败时标记代理不可用，下次选择时切换到备用代理
+    fn report_proxy_result(
+        credentials: &KiroCredentials,
+        proxy: &Option<ProxyConfig>,
+        result: &reqwest::Result<reqwest::Response>,
+    ) {
+        // 全局代理没有备用代理，不参与健康记录
+        let (Some(proxy), Some(_)) = (proxy, &credentials.proxy_url) else {
+            return;
+        };
+        match result {
+            Ok(_) => proxy_health::report_success(&proxy.url),
+            Err(e) if e.is_connect() => proxy_health::report_failure(&proxy.url, &e.to_string()),
+            Err(_) => {}
+        }
+    }
+
     /// 根据凭据选择 endpoint 实现
     fn endpoint_for(
         &self,
@@ -110,14 +307,27 @@ impl KiroProvider {
 
     /// 发送非流式 API 请求
     ///
-    /// 支持多凭据故障转移（见 [`Self::call_api_with_retry`]）
-    pub async fn call_api(&self, request_body: &str) -> anyhow::Result<reqwest::Response> {
-        self.call_api_with_retry(request_body, false).await
+    /// 支持多凭据故障转移（见 [`Self::call_api_with_retry`]）；
+    /// `hints` 为模型路由匹配所需的请求特征，`options` 为会话键、用户标识哈希等请求级选项
+    pub async fn call_api(
+        &self,
+        request_body: &str,
+        hints: RouteHints,
+        options: CallOptions<'_>,
+    ) -> anyhow::Result<reqwest::Response> {
+        self.call_api_with_retry(request_body, false, hints, options)
+            .await
     }
 
     /// 发送流式 API 请求
-    pub async fn call_api_stream(&self, request_body: &str) -> anyhow::Result<reqwest::Response> {
-        self.call_api_with_retry(request_body, true).await
+    pub async fn call_api_stream(
+        &self,
+        request_body: &str,
+        hints: RouteHints,
+        options: CallOptions<'_>,
+    ) -> anyhow::Result<reqwest::Response> {
+        self.call_api_with_retry(request_body, true, hints, options)
+            .await
     }
 
     /// 发送 MCP API 请求（WebSearch 等工具调用）
@@ -160,20 +370,25 @@ impl KiroProvider {
                 token: &ctx.token,
                 machine_id: &machine_id,
                 config,
+                api_region: None,
             };
 
             let url = endpoint.mcp_url(&rctx);
             let body = endpoint.transform_mcp_body(request_body, &rctx);
 
+            let proxy = self.proxy_for(&ctx.credentials);
+            let tls_backend = ctx.credentials.effective_client_profile(config).tls_backend;
             let base = self
-                .client_for(&ctx.credentials)?
+                .client_for(&proxy, tls_backend, None)?
                 .post(&url)
                 .body(body)
                 .header("content-type", "application/json")
                 .header("Connection", "close");
             let request = endpoint.decorate_mcp(base, &rctx);
 
-            let response = match request.send().await {
+            let sent = request.send().await;
+            Self::report_proxy_result(&ctx.credentials, &proxy, &sent);
+            let response = match sent {
                 Ok(resp) => resp,
                 Err(e) => {
                     tracing::warn!(
@@ -272,14 +487,46 @@ impl KiroProvider {
 
     /// 内部方法：带重试逻辑的 API 调用
     ///
+    /// 整个调用（含重试）记录为 `kiro.call_api` span，结束时写入尝试次数与最后使用的凭据
+    async fn call_api_with_retry(
+        &self,
+        request_body: &str,
+        is_stream: bool,
+        hints: RouteHints,
+        options: CallOptions<'_>,
+    ) -> anyhow::Result<reqwest::Response> {
+        let span = tracing::info_span!(
+            "kiro.call_api",
+            stream = is_stream,
+            model = tracing::field::Empty,
+            credential_id = tracing::field::Empty,
+            attempts = tracing::field::Empty,
+        );
+        let mut trace = RetryTrace::default();
+        let result = self
+            .call_api_attempts(request_body, is_stream, hints, options, &mut trace)
+            .instrument(span.clone())
+            .await;
+        span.record("attempts", trace.attempts);
+        if let Some(id) = trace.credential_id {
+            span.record("credential_id", id);
+        }
+        result
+    }
+
+    /// 逐次尝试调用上游
+    ///
     /// 重试策略：
     /// - 每个凭据最多重试 MAX_RETRIES_PER_CREDENTIAL 次
     /// - 总重试次数 = min(凭据数量 × 每凭据重试次数, MAX_TOTAL_RETRIES)
     /// - 硬上限 9 次，避免无限重试
-    async fn call_api_with_retry(
+    async fn call_api_attempts(
         &self,
         request_body: &str,
         is_stream: bool,
+        hints: RouteHints,
+        options: CallOptions<'_>,
+        trace: &mut RetryTrace,
     ) -> anyhow::Result<reqwest::Response> {
         let total_credentials = self.token_manager.total_count();
         let max_retries = (total_credentials * MAX_RETRIES_PER_CREDENTIAL).min(MAX_TOTAL_RETRIES);
@@ -289,16 +536,33 @@ impl KiroProvider {
 
         // 尝试从请求体中提取模型信息
         let model = Self::extract_model_from_request(request_body);
+        if let Some(model) = &model {
+            tracing::Span::current().record("model", model.as_str());
+        }
+
+        // 重试复用同一请求体，只在首次发送前记录体积
+        request_size::record(request_body);
 
         for attempt in 0..max_retries {
+            trace.attempts = attempt + 1;
             // 获取调用上下文（绑定 index、credentials、token）
-            let ctx = match self.token_manager.acquire_context(model.as_deref()).await {
+            let ctx = match self
+                .token_manager
+                .acquire_context_for_session(
+                    model.as_deref(),
+                    hints,
+                    options.session_key,
+                    options.api_region,
+                )
+                .await
+            {
                 Ok(c) => c,
                 Err(e) => {
                     last_error = Some(e);
                     continue;
                 }
             };
+            trace.credential_id = Some(ctx.id);
 
             let config = self.token_manager.config();
             let machine_id = machine_id::generate_from_credentials(&ctx.credentials, config);
@@ -317,20 +581,51 @@ impl KiroProvider {
                 token: &ctx.token,
                 machine_id: &machine_id,
                 config,
+                api_region: options.api_region,
             };
 
             let url = endpoint.api_url(&rctx);
-            let body = endpoint.transform_api_body(request_body, &rctx);
+            // 保留实际发送的请求体，供 malformed 诊断使用（Bytes clone 为引用计数）
+            let sent_body = bytes::Bytes::from(endpoint.transform_api_body(request_body, &rctx));
 
+            let proxy = self.proxy_for(&ctx.credentials);
+            let tls_backend = ctx.credentials.effective_client_profile(config).tls_backend;
             let base = self
-                .client_for(&ctx.credentials)?
+                .client_for(&proxy, tls_backend, options.api_region)?
                 .post(&url)
-                .body(body)
+                .body(sent_body.clone())
                 .header("content-type", "application/json")
                 .header("Connection", "close");
             let request = endpoint.decorate_api(base, &rctx);
+            let request = match (&config.user_id_header, options.user_hash) {
+                (Some(header), Some(user_hash)) => request.header(header.as_str(), user_hash),
+                _ => request,
+            };
 
-            let response = match request.send().await {
+            // 并发控制：达到凭据并发上限时排队等待空闲名额
+            let permit = match concurrency::acquire(ctx.id)
+                .instrument(tracing::info_span!("queue", credential_id = ctx.id))
+                .await
+            {
+                Ok(permit) => permit,
+                Err(e) => {
+                    tracing::warn!("{} API 请求未发送: {}", api_type, e);
+                    return Err(e.into());
+                }
+            };
+            let started = Instant::now();
+            let ttfb_span = tracing::info_span!(
+                "upstream_ttfb",
+                credential_id = ctx.id,
+                attempt = attempt + 1,
+                status = tracing::field::Empty
+            );
+            let sent = request.send().instrument(ttfb_span.clone()).await;
+            if let Ok(resp) = &sent {
+                ttfb_span.record("status", resp.status().as_u16());
+            }
+            Self::report_proxy_result(&ctx.credentials, &proxy, &sent);
+            let mut response = match sent {
                 Ok(resp) => resp,
                 Err(e) => {
                     tracing::warn!(
@@ -341,6 +636,10 @@ impl KiroProvider {
                     );
                     // 网络错误通常是上游/链路瞬态问题，不应导致"禁用凭据"或"切换凭据"
                     // （否则一段时间网络抖动会把所有凭据都误禁用，需要重启才能恢复）
+                    // 超时计入熔断器：持续超时的凭据会被暂时摘除
+                    if e.is_timeout() {
+                        self.token_manager.report_upstream_error(ctx.id);
+                    }
                     last_error = Some(e.into());
                     if attempt + 1 < max_retries {
                         sleep(Self::retry_delay(attempt)).await;
@@ -353,11 +652,77 @@ impl KiroProvider {
 
             // 成功响应
             if status.is_success() {
+                if let Some(permit) = permit {
+                    permit.record_success(started.elapsed());
+                    // 许可随响应保存，读取完响应体后才释放名额
+                    response.extensions_mut().insert(permit);
+                }
+                // 流式响应：上游可能先返回 200 再在事件流中报凭据错误，
+                // 在向客户端发送任何内容前预读首个事件，失败时透明地换凭据重试
+                if is_stream {
+                    match peek_stream_start(response).await {
+                        StreamStart::Ready(peeked) => response = peeked,
+                        StreamStart::AuthFailed(message) => {
+                            tracing::warn!(
+                                "流式响应首个事件为凭据错误（尝试 {}/{}）: {}",
+                                attempt + 1,
+                                max_retries,
+                                message
+                            );
+                            if endpoint.is_bearer_token_invalid(&message)
+                                && force_refreshed.insert(ctx.id)
+                            {
+                                tracing::info!(
+                                    "凭据 #{} token 疑似被上游失效，尝试强制刷新",
+                                    ctx.id
+                                );
+                                if self.token_manager.force_refresh_token_for(ctx.id).await.is_ok()
+                                {
+                                    continue;
+                                }
+                            }
+                            let has_available = self.token_manager.report_failure(ctx.id);
+                            if !has_available {
+                                anyhow::bail!(
+                                    "{} API 请求失败（所有凭据已用尽）: {}",
+                                    api_type,
+                                    message
+                                );
+                            }
+                            last_error =
+                                Some(anyhow::anyhow!("{} API 请求失败: {}", api_type, message));
+                            continue;
+                        }
+                        StreamStart::Broken(message) => {
+                            tracing::warn!(
+                                "流式响应在首个事件前中断（尝试 {}/{}）: {}",
+                                attempt + 1,
+                                max_retries,
+                                message
+                            );
+                            self.token_manager.report_upstream_error(ctx.id);
+                            last_error =
+                                Some(anyhow::anyhow!("{} API 请求失败: {}", api_type, message));
+                            if attempt + 1 < max_retries {
+                                sleep(Self::retry_delay(attempt)).await;
+                            }
+                            continue;
+                        }
+                    }
+                }
                 self.token_manager.report_success(ctx.id);
+                response.extensions_mut().insert(ServedCredential(ctx.id));
                 return Ok(response);
             }
+            if status.as_u16() == 429
+                && let Some(permit) = &permit
+            {
+                permit.record_throttled();
+            }
+            drop(permit);
 
-            // 失败响应：读取 body 用于日志/错误信息
+            // 失败响应：读取 body 用于日志/错误信息（先取出 Retry-After，text() 会消费响应）
+            let retry_after = parse_retry_after(response.headers());
             let body = response.text().await.unwrap_or_default();
 
             // 402 Payment Required 且额度用尽：禁用凭据并故障转移
@@ -391,6 +756,10 @@ impl KiroProvider {
 
             // 400 Bad Request - 请求问题，重试/切换凭据无意义
             if status.as_u16() == 400 {
+                if malformed::is_improperly_formed(&body) {
+                    let sent = String::from_utf8_lossy(&sent_body);
+                    return Err(malformed::capture(&sent, status.as_u16(), &body).into());
+                }
                 anyhow::bail!("{} API 请求失败: {} {}", api_type, status, body);
             }
 
@@ -434,6 +803,35 @@ impl KiroProvider {
                 continue;
             }
 
+            // 429 且上游给出了等待时间：该凭据进入限流冷却（不计入失败），换其他凭据重试
+            if status.as_u16() == 429
+                && let Some(secs) = retry_after
+                    .or_else(|| parse_retry_after_body(&body))
+                    .filter(|secs| *secs > 0)
+            {
+                tracing::warn!(
+                    "API 请求被限流（凭据 #{} 冷却 {}s，尝试 {}/{}）: {} {}",
+                    ctx.id,
+                    secs.min(MAX_THROTTLE_COOLDOWN_SECS),
+                    attempt + 1,
+                    max_retries,
+                    status,
+                    body
+                );
+                self.token_manager.report_throttled(
+                    ctx.id,
+                    Duration::from_secs(secs.min(MAX_THROTTLE_COOLDOWN_SECS)),
+                );
+                last_error = Some(
+                    UpstreamThrottledError {
+                        message: format!("{} API 请求失败: {} {}", api_type, status, body),
+                        retry_after: Some(secs),
+                    }
+                    .into(),
+                );
+                continue;
+            }
+
             // 429/408/5xx - 瞬态上游错误：重试但不禁用或切换凭据
             // （避免 429 high traffic / 502 high load 等瞬态错误把所有凭据锁死）
             if matches!(status.as_u16(), 408 | 429) || status.is_server_error() {
@@ -444,12 +842,19 @@ impl KiroProvider {
                     status,
                     body
                 );
-                last_error = Some(anyhow::anyhow!(
-                    "{} API 请求失败: {} {}",
-                    api_type,
-                    status,
-                    body
-                ));
+                if status.as_u16() != 429 {
+                    self.token_manager.report_upstream_error(ctx.id);
+                }
+                let message = format!("{} API 请求失败: {} {}", api_type, status, body);
+                last_error = Some(if status.as_u16() == 429 {
+                    UpstreamThrottledError {
+                        message,
+                        retry_after,
+                    }
+                    .into()
+                } else {
+                    anyhow::anyhow!(message)
+                });
                 if attempt + 1 < max_retries {
                     sleep(Self::retry_delay(attempt)).await;
                 }
@@ -517,3 +922,116 @@ impl KiroProvider {
         Duration::from_millis(backoff.saturating_add(jitter))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::kiro::parser::crc::crc32;
+    use reqwest::header::HeaderValue;
+
+    /// 构造一个 AWS event-stream 帧（仅字符串类型头部）
+    fn encode_frame(headers: &[(&str, &str)], payload: &[u8]) -> Vec<u8> {
+        let mut header_bytes = Vec::new();
+        for (name, value) in headers {
+            header_bytes.push(name.len() as u8);
+            header_bytes.extend_from_slice(name.as_bytes());
+            header_bytes.push(7);
+            header_bytes.extend_from_slice(&(value.len() as u16).to_be_bytes());
+            header_bytes.extend_from_slice(value.as_bytes());
+        }
+        let total = 12 + header_bytes.len() + payload.len() + 4;
+        let mut frame = Vec::with_capacity(total);
+        frame.extend_from_slice(&(total as u32).to_be_bytes());
+        frame.extend_from_slice(&(header_bytes.len() as u32).to_be_bytes());
+        let prelude_crc = crc32(&frame);
+        frame.extend_from_slice(&prelude_crc.to_be_bytes());
+        frame.extend_from_slice(&header_bytes);
+        frame.extend_from_slice(payload);
+        let message_crc = crc32(&frame);
+        frame.extend_from_slice(&message_crc.to_be_bytes());
+        frame
+    }
+
+    fn response_of(body: Vec<u8>) -> reqwest::Response {
+        http::Response::new(reqwest::Body::from(body)).into()
+    }
+
+    #[tokio::test]
+    async fn test_peek_stream_start_detects_auth_exception() {
+        let frame = encode_frame(
+            &[
+                (":message-type", "exception"),
+                (":exception-type", "AccessDeniedException"),
+            ],
+            b"{\"message\":\"not authorized\"}",
+        );
+        match peek_stream_start(response_of(frame)).await {
+            StreamStart::AuthFailed(message) => assert!(message.contains("AccessDenied")),
+            _ => panic!("应识别为凭据错误"),
+        }
+        assert!(matches!(
+            peek_stream_start(response_of(Vec::new())).await,
+            StreamStart::Broken(_)
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_peek_stream_start_replays_consumed_bytes() {
+        let mut body = encode_frame(
+            &[
+                (":message-type", "event"),
+                (":event-type", "assistantResponseEvent"),
+            ],
+            b"{\"content\":\"hello\"}",
+        );
+        body.extend(encode_frame(
+            &[(":message-type", "exception"), (":exception-type", "ThrottlingException")],
+            b"slow down",
+        ));
+        let expected = body.clone();
+
+        let StreamStart::Ready(response) = peek_stream_start(response_of(body)).await else {
+            panic!("正常事件流应直接返回");
+        };
+        assert_eq!(response.bytes().await.unwrap().to_vec(), expected);
+    }
+
+    #[test]
+    fn test_is_auth_failure() {
+        assert!(is_auth_failure("AccessDeniedException", ""));
+        assert!(is_auth_failure(
+            "UnknownError",
+            "The bearer token included in the request is invalid."
+        ));
+        assert!(!is_auth_failure("ThrottlingException", "Too many requests"));
+    }
+
+    #[test]
+    fn test_parse_retry_after() {
+        let mut headers = HeaderMap::new();
+        assert_eq!(parse_retry_after(&headers), None);
+
+        headers.insert(RETRY_AFTER, HeaderValue::from_static("7"));
+        assert_eq!(parse_retry_after(&headers), Some(7));
+
+        // 过去的 HTTP 日期视为无需等待
+        headers.insert(
+            RETRY_AFTER,
+            HeaderValue::from_static("Wed, 21 Oct 2015 07:28:00 GMT"),
+        );
+        assert_eq!(parse_retry_after(&headers), Some(0));
+
+        headers.insert(RETRY_AFTER, HeaderValue::from_static("soon"));
+        assert_eq!(parse_retry_after(&headers), None);
+
+        assert_eq!(
+            parse_retry_after_body(r#"{"retryAfterSeconds": 30}"#),
+            Some(30)
+        );
+        assert_eq!(
+            parse_retry_after_body(r#"{"error": {"retry_after": 1.2}}"#),
+            Some(2)
+        );
+        assert_eq!(parse_retry_after_body("Too many requests"), None);
+    }
+}
@@ -0,0 +1,209 @@
+//! 代理健康状态与故障转移
+//!
+//! 按代理 URL 记录健康状态：经由代理的请求发生连接失败时立即标记为不健康，
+//! [`KiroCredentials::effective_proxy`](crate::kiro::model::credentials::KiroCredentials::effective_proxy)
+//! 据此跳过不健康的凭据代理、依次切换到 `fallbackProxyUrls` 中的备用代理。
+//! 不健康标记在 [`UNHEALTHY_TTL`] 后过期（重新尝试该代理）；开启
+//! `proxyHealthCheckIntervalSecs` 后由后台探测主动确认或恢复。
+
+use std::collections::HashMap;
+use std::sync::LazyLock;
+use std::time::{Duration, Instant};
+
+use chrono::{DateTime, Utc};
+use parking_lot::RwLock;
+use serde::Serialize;
+
+use crate::http_client::{ProxyConfig, build_client};
+use crate::model::config::TlsBackend;
+
+/// 不健康标记的有效期，过期后重新尝试该代理
+pub const UNHEALTHY_TTL: Duration = Duration::from_secs(60);
+
+/// 单次探测超时（秒）
+const PROBE_TIMEOUT_SECS: u64 = 10;
+
+#[derive(Debug, Default)]
+struct ProxyState {
+    unhealthy_until: Option<Instant>,
+    consecutive_failures: u32,
+    last_checked_at: Option<DateTime<Utc>>,
+    last_error: Option<String>,
+}
+
+impl ProxyState {
+    fn is_healthy(&self, now: Instant) -> bool {
+        self.unhealthy_until.is_none_or(|until| now >= until)
+    }
+}
+
+static STATES: LazyLock<RwLock<HashMap<String, ProxyState>>> =
+    LazyLock::new(|| RwLock::new(HashMap::new()));
+
+/// 单个代理的健康状态（用于 Admin API）
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ProxyHealthSnapshot {
+    pub url: String,
+    pub healthy: bool,
+    /// 连续失败次数
+    pub consecutive_failures: u32,
+    /// 最近一次探测或失败的时间（RFC3339 格式）
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub last_checked_at: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub last_error: Option<String>,
+}
+
+/// 凭据级代理状态（用于 Admin API）
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ProxyStatus {
+    /// 当前生效的代理 URL
+    pub active_url: String,
+    /// 主代理与备用代理的健康状态（按切换顺序）
+    pub proxies: Vec<ProxyHealthSnapshot>,
+}
+
+/// 代理当前是否可用（未记录过失败的代理视为可用）
+fn is_healthy_at(url: &str, now: Instant) -> bool {
+    STATES
+        .read()
+        .get(url)
+        .is_none_or(|state| state.is_healthy(now))
+}
+
+/// 从候选代理中选择第一个可用的，全部不可用时使用第一个
+pub fn select(candidates: &[ProxyConfig]) -> Option<&ProxyConfig> {
+    let now = Instant::now();
+    candidates
+        .iter()
+        .find(|proxy| is_healthy_at(&proxy.url, now))
+        .or(candidates.first())
+}
+
+/// 记录经由代理的连接失败，标记为不健康
+pub fn report_failure(url: &str, error: &str) {
+    report_failure_at(url, error, Instant::now());
+}
+
+fn report_failure_at(url: &str, error: &str, now: Instant) {
+    let mut states = STATES.write();
+    let state = states.entry(url.to_string()).or_default();
+    if state.is_healthy(now) {
+        tracing::warn!("代理 {} 不可用，暂时切换到备用代理: {}", url, error);
+    }
+    state.unhealthy_until = Some(now + UNHEALTHY_TTL);
+    state.consecutive_failures += 1;
+    state.last_checked_at = Some(Utc::now());
+    state.last_error = Some(error.to_string());
+}
+
+/// 记录经由代理的请求成功，清除不健康标记
+pub fn report_success(url: &str) {
+    let needs_reset = STATES
+        .read()
+        .get(url)
+        .is_some_and(|state| state.consecutive_failures > 0);
+    if !needs_reset {
+        return;
+    }
+    let mut states = STATES.write();
+    if let Some(state) = states.get_mut(url) {
+        if state.unhealthy_until.is_some() {
+            tracing::info!("代理 {} 已恢复", url);
+        }
+        state.unhealthy_until = None;
+        state.consecutive_failures = 0;
+        state.last_checked_at = Some(Utc::now());
+        state.last_error = None;
+    }
+}
+
+/// 探测代理是否可用：经由代理向 `target` 发送 HEAD 请求，收到任意 HTTP 响应即视为可用
+pub async fn probe(proxy: &ProxyConfig, target: &str, tls_backend: TlsBackend) {
+    let result = match build_client(Some(proxy), PROBE_TIMEOUT_SECS, tls_backend) {
+        Ok(client) => client
+            .head(target)
+            .send()
+            .await
+            .map(|_| ())
+            .map_err(|e| e.to_string()),
+        Err(e) => Err(e.to_string()),
+    };
+    match result {
+        Ok(()) => {
+            report_success(&proxy.url);
+            STATES
+                .write()
+                .entry(proxy.url.clone())
+                .or_default()
+                .last_checked_at = Some(Utc::now());
+        }
+        Err(error) => report_failure(&proxy.url, &error),
+    }
+}
+
+/// 生成候选代理的状态，没有候选代理时返回 None
+pub fn status(candidates: &[ProxyConfig]) -> Option<ProxyStatus> {
+    let active_url = select(candidates)?.url.clone();
+    let now = Instant::now();
+    let states = STATES.read();
+    let proxies = candidates
+        .iter()
+        .map(|proxy| {
+            let state = states.get(&proxy.url);
+            ProxyHealthSnapshot {
+                url: proxy.url.clone(),
+                healthy: state.is_none_or(|s| s.is_healthy(now)),
+                consecutive_failures: state.map_or(0, |s| s.consecutive_failures),
+                last_checked_at: state
+                    .and_then(|s| s.last_checked_at)
+                    .map(|t| t.to_rfc3339()),
+                last_error: state.and_then(|s| s.last_error.clone()),
+            }
+        })
+        .collect();
+    Some(ProxyStatus {
+        active_url,
+        proxies,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_failure_expires_after_ttl() {
+        let url = "http://proxy-health-ttl:3128";
+        let now = Instant::now();
+        assert!(is_healthy_at(url, now));
+
+        report_failure_at(url, "connection refused", now);
+        assert!(!is_healthy_at(url, now + Duration::from_secs(1)));
+        assert!(is_healthy_at(url, now + UNHEALTHY_TTL));
+
+        report_success(url);
+        assert!(is_healthy_at(url, now));
+    }
+
+    #[test]
+    fn test_select_skips_unhealthy() {
+        let primary = ProxyConfig::new("http://proxy-health-primary:3128");
+        let backup = ProxyConfig::new("http://proxy-health-backup:3128");
+        let candidates = vec![primary.clone(), backup.clone()];
+        assert_eq!(select(&candidates), Some(&primary));
+
+        report_failure(&primary.url, "connection refused");
+        assert_eq!(select(&candidates), Some(&backup));
+        let status = status(&candidates).unwrap();
+        assert_eq!(status.active_url, backup.url);
+        assert!(!status.proxies[0].healthy);
+        assert_eq!(status.proxies[0].consecutive_failures, 1);
+
+        // 全部不可用时回到主代理
+        report_failure(&backup.url, "connection refused");
+        assert_eq!(select(&candidates), Some(&primary));
+    }
+}
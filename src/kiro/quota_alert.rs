@@ -0,0 +1,236 @@
+//! 额度使用率告警
+//!
+//! 配置 `quotaAlerts` 后，每次查询凭据使用额度（后台定期检查、Admin 余额查询、添加凭据）
+//! 时计算使用百分比，首次达到某个阈值时向 webhook 发送告警。同一重置周期
+//! （`nextDateReset`）内每个阈值只告警一次，周期变化后重新计算；告警状态持久化到
+//! 凭据文件所在目录，重启后不会重复告警。
+
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::{Arc, OnceLock};
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+use crate::common::atomic_file;
+use crate::http_client::{ProxyConfig, build_client};
+use crate::kiro::model::usage_limits::UsageLimitsResponse;
+use crate::kiro::token_manager::MultiTokenManager;
+use crate::model::config::{QuotaAlertConfig, TlsBackend};
+
+/// webhook 请求超时（秒）
+const WEBHOOK_TIMEOUT_SECS: u64 = 15;
+
+static ALERTER: OnceLock<QuotaAlerter> = OnceLock::new();
+
+/// 单个凭据在当前重置周期内的告警状态
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct AlertState {
+    /// 重置周期（下次重置时间，Unix 秒）
+    period: Option<i64>,
+    /// 本周期已告警的最高阈值
+    alerted: f64,
+}
+
+struct QuotaAlerter {
+    config: QuotaAlertConfig,
+    client: reqwest::Client,
+    states: Mutex<HashMap<u64, AlertState>>,
+    path: Option<PathBuf>,
+}
+
+/// 初始化额度告警（未配置时不做任何事）
+///
+/// 状态文件无法解析时备份后从空状态开始；无法读取时不再写入该文件
+pub fn init(
+    config: Option<&QuotaAlertConfig>,
+    state_path: Option<PathBuf>,
+    proxy: Option<&ProxyConfig>,
+    tls_backend: TlsBackend,
+) -> anyhow::Result<()> {
+    let Some(config) = config else {
+        return Ok(());
+    };
+    if config.thresholds.is_empty() {
+        anyhow::bail!("quotaAlerts.thresholds 不能为空");
+    }
+    let (states, state_path) = match state_path {
+        Some(path) => match atomic_file::load_json(&path) {
+            Ok(states) => (states.unwrap_or_default(), Some(path)),
+            Err(e) => {
+                tracing::error!(
+                    "读取额度告警状态 {} 失败，本次运行不会写入该文件: {}",
+                    path.display(),
+                    e
+                );
+                (HashMap::new(), None)
+            }
+        },
+        None => (HashMap::new(), None),
+    };
+    let client = build_client(proxy, WEBHOOK_TIMEOUT_SECS, tls_backend)?;
+    let _ = ALERTER.set(QuotaAlerter {
+        config: config.clone(),
+        client,
+        states: Mutex::new(states),
+        path: state_path,
+    });
+    Ok(())
+}
+
+/// 记录一次额度查询结果，达到新的阈值时异步发送告警
+pub fn observe(id: u64, email: Option<&str>, usage: &UsageLimitsResponse) {
+    let Some(alerter) = ALERTER.get() else {
+        return;
+    };
+    let limit = usage.usage_limit();
+    if limit <= 0.0 {
+        return;
+    }
+    let current = usage.current_usage();
+    let percent = current / limit * 100.0;
+    let period = usage.next_date_reset.map(|secs| secs as i64);
+
+    let threshold = {
+        let mut states = alerter.states.lock();
+        let state = states.entry(id).or_default();
+        let threshold = crossed(state, &alerter.config.thresholds, percent, period);
+        if threshold.is_some() {
+            alerter.persist(&states);
+        }
+        threshold
+    };
+    let Some(threshold) = threshold else {
+        return;
+    };
+
+    tracing::warn!(
+        "凭据 #{} 额度使用率 {:.1}% 已达到告警阈值 {}%",
+        id,
+        percent,
+        threshold
+    );
+    let payload = json!({
+        "event": "quota_threshold",
+        "credentialId": id,
+        "email": email,
+        "subscriptionTitle": usage.subscription_title(),
+        "threshold": threshold,
+        "usagePercent": (percent * 10.0).round() / 10.0,
+        "currentUsage": current,
+        "usageLimit": limit,
+        "nextResetAt": period
+            .and_then(|secs| DateTime::<Utc>::from_timestamp(secs, 0))
+            .map(|t| t.to_rfc3339()),
+    });
+    let client = alerter.client.clone();
+    let url = alerter.config.webhook_url.clone();
+    tokio::spawn(async move {
+        match client.post(&url).json(&payload).send().await {
+            Ok(resp) if resp.status().is_success() => {}
+            Ok(resp) => tracing::warn!("额度告警 webhook 返回 {}", resp.status()),
+            Err(e) => tracing::warn!("发送额度告警 webhook 失败: {}", e),
+        }
+    });
+}
+
+/// 更新告警状态，返回本次需要告警的阈值（一次跨过多个阈值时只告警最高的）
+fn crossed(
+    state: &mut AlertState,
+    thresholds: &[f64],
+    percent: f64,
+    period: Option<i64>,
+) -> Option<f64> {
+    if state.period != period {
+        *state = AlertState {
+            period,
+            alerted: 0.0,
+        };
+    }
+    let threshold = thresholds
+        .iter()
+        .copied()
+        .filter(|&t| percent >= t && t > state.alerted)
+        .reduce(f64::max)?;
+    state.alerted = threshold;
+    Some(threshold)
+}
+
+impl QuotaAlerter {
+    fn persist(&self, states: &HashMap<u64, AlertState>) {
+        let Some(path) = &self.path else {
+            return;
+        };
+        let result = serde_json::to_string_pretty(states)
+            .map_err(anyhow::Error::from)
+            .and_then(|json| atomic_file::write(path, json.as_bytes()).map_err(Into::into));
+        if let Err(e) = result {
+            tracing::warn!("保存额度告警状态失败: {}", e);
+        }
+    }
+}
+
+/// 启动额度使用率的后台检查（未配置告警或间隔为 0 时不启动）
+///
+/// 依次查询所有启用凭据的使用额度，查询结果经 [`observe`] 触发告警
+pub fn spawn_check(token_manager: &Arc<MultiTokenManager>) {
+    let Some(interval_secs) = ALERTER
+        .get()
+        .map(|a| a.config.check_interval_secs)
+        .filter(|&secs| secs > 0)
+    else {
+        return;
+    };
+    let manager = Arc::downgrade(token_manager);
+    tokio::spawn(async move {
+        loop {
+            tokio::time::sleep(Duration::from_secs(interval_secs)).await;
+            let Some(token_manager) = manager.upgrade() else {
+                break;
+            };
+            let ids: Vec<u64> = token_manager
+                .snapshot()
+                .entries
+                .iter()
+                .filter(|e| !e.disabled)
+                .map(|e| e.id)
+                .collect();
+            for id in ids {
+                if let Err(e) = token_manager.get_usage_limits_for(id).await {
+                    tracing::debug!("额度告警检查：查询凭据 #{} 使用额度失败: {}", id, e);
+                }
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_crossed_fires_once_per_threshold() {
+        let thresholds = [80.0, 95.0];
+        let mut state = AlertState::default();
+        assert_eq!(crossed(&mut state, &thresholds, 50.0, Some(1)), None);
+        assert_eq!(crossed(&mut state, &thresholds, 81.0, Some(1)), Some(80.0));
+        assert_eq!(crossed(&mut state, &thresholds, 90.0, Some(1)), None);
+        assert_eq!(crossed(&mut state, &thresholds, 96.0, Some(1)), Some(95.0));
+        assert_eq!(crossed(&mut state, &thresholds, 100.0, Some(1)), None);
+    }
+
+    #[test]
+    fn test_crossed_resets_with_period() {
+        let thresholds = [80.0, 95.0];
+        let mut state = AlertState::default();
+        // 一次跨过多个阈值只告警最高的
+        assert_eq!(crossed(&mut state, &thresholds, 97.0, Some(1)), Some(95.0));
+        assert_eq!(crossed(&mut state, &thresholds, 98.0, Some(1)), None);
+        // 进入新的重置周期后重新告警
+        assert_eq!(crossed(&mut state, &thresholds, 85.0, Some(2)), Some(80.0));
+    }
+}
@@ -0,0 +1,249 @@
+//! refreshToken 轮换日志
+//!
+//! Kiro 刷新 Token 时可能轮换 refreshToken，旧值随即失效。如果进程在刷新成功之后、
+//! 凭据文件写回之前崩溃，文件中只剩已失效的旧 refreshToken。
+//!
+//! 因此刷新成功后先把新 Token 追加到凭据文件同目录的 `kiro_refresh_journal.jsonl`
+//! 并 fsync，再更新内存和凭据文件；凭据文件写回成功后清空日志。启动时对照日志检查
+//! 凭据文件：文件中仍是轮换前的旧 Token 时用日志中的新 Token 恢复，与日志记录对不上的
+//! 凭据只报告警告。日志中只保存旧 refreshToken 的 SHA-256，不保存旧值本身。
+
+use std::collections::HashMap;
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::kiro::model::credentials::KiroCredentials;
+
+/// 日志文件名（位于凭据文件所在目录）
+pub const JOURNAL_FILE: &str = "kiro_refresh_journal.jsonl";
+
+/// 一次 refreshToken 轮换
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RotationRecord {
+    pub credential_id: u64,
+    /// 轮换前 refreshToken 的 SHA-256（hex）
+    pub previous_hash: String,
+    pub refresh_token: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub access_token: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expires_at: Option<String>,
+    /// 轮换时间（RFC3339 格式）
+    pub rotated_at: String,
+}
+
+impl RotationRecord {
+    /// 由刷新前后的凭据生成记录，refreshToken 未变化时返回 None
+    pub fn new(id: u64, old: &KiroCredentials, new: &KiroCredentials) -> Option<Self> {
+        let previous = old.refresh_token.as_deref()?;
+        let current = new.refresh_token.as_deref()?;
+        if previous == current {
+            return None;
+        }
+        Some(Self {
+            credential_id: id,
+            previous_hash: token_hash(previous),
+            refresh_token: current.to_string(),
+            access_token: new.access_token.clone(),
+            expires_at: new.expires_at.clone(),
+            rotated_at: chrono::Utc::now().to_rfc3339(),
+        })
+    }
+}
+
+/// 启动检查结果
+#[derive(Debug, Default, PartialEq)]
+pub struct ReconcileReport {
+    /// 凭据文件中是轮换前的旧 Token，已按日志恢复
+    pub recovered: Vec<u64>,
+    /// 凭据文件中的 Token 与日志记录都对不上
+    pub stale: Vec<u64>,
+}
+
+fn token_hash(token: &str) -> String {
+    hex::encode(Sha256::digest(token.as_bytes()))
+}
+
+/// 追加一条记录并同步到磁盘
+pub fn append(path: &Path, record: &RotationRecord) -> io::Result<()> {
+    let mut line = serde_json::to_string(record)?;
+    line.push('\n');
+
+    let mut options = OpenOptions::new();
+    options.create(true).append(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    let mut file = options.open(path)?;
+    file.write_all(line.as_bytes())?;
+    file.sync_data()
+}
+
+/// 读取全部记录（文件不存在时为空，损坏的行跳过）
+pub fn load(path: &Path) -> Vec<RotationRecord> {
+    let content = match fs::read_to_string(path) {
+        Ok(content) => content,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Vec::new(),
+        Err(e) => {
+            tracing::warn!("读取刷新日志失败: {:?}: {}", path, e);
+            return Vec::new();
+        }
+    };
+    content
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .filter_map(|line| match serde_json::from_str(line) {
+            Ok(record) => Some(record),
+            Err(e) => {
+                // 崩溃时最后一行可能只写了一半
+                tracing::warn!("跳过无法解析的刷新日志记录: {}", e);
+                None
+            }
+        })
+        .collect()
+}
+
+/// 清空日志（凭据文件写回成功后调用）
+pub fn clear(path: &Path) -> io::Result<()> {
+    match fs::remove_file(path) {
+        Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e),
+        _ => Ok(()),
+    }
+}
+
+/// 对照日志检查并恢复凭据
+///
+/// 按日志顺序处理每个凭据的记录：当前 refreshToken 的哈希等于记录的 `previousHash` 时
+/// 说明轮换后未写回，替换为记录中的新 Token；当前 Token 已等于记录中的新 Token 时说明
+/// 已写回。没有任何一条记录能对上的凭据视为过期。日志中不存在的凭据不受影响。
+pub fn reconcile<'a>(
+    credentials: impl IntoIterator<Item = &'a mut KiroCredentials>,
+    records: &[RotationRecord],
+) -> ReconcileReport {
+    let mut by_id: HashMap<u64, &mut KiroCredentials> = credentials
+        .into_iter()
+        .filter_map(|c| c.id.map(|id| (id, c)))
+        .collect();
+    // (是否已恢复, 是否有记录对上)
+    let mut states: Vec<(u64, bool, bool)> = Vec::new();
+
+    for record in records {
+        let Some(cred) = by_id.get_mut(&record.credential_id) else {
+            continue;
+        };
+        let index = match states.iter().position(|s| s.0 == record.credential_id) {
+            Some(index) => index,
+            None => {
+                states.push((record.credential_id, false, false));
+                states.len() - 1
+            }
+        };
+        let current = cred.refresh_token.as_deref().unwrap_or_default();
+        if token_hash(current) == record.previous_hash {
+            cred.refresh_token = Some(record.refresh_token.clone());
+            cred.access_token = record.access_token.clone();
+            cred.expires_at = record.expires_at.clone();
+            states[index].1 = true;
+            states[index].2 = true;
+        } else if current == record.refresh_token {
+            states[index].2 = true;
+        }
+    }
+
+    let mut report = ReconcileReport::default();
+    for (id, recovered, matched) in states {
+        if recovered {
+            report.recovered.push(id);
+        } else if !matched {
+            report.stale.push(id);
+        }
+    }
+    report
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn creds(id: u64, refresh_token: &str) -> KiroCredentials {
+        KiroCredentials {
+            id: Some(id),
+            refresh_token: Some(refresh_token.to_string()),
+            ..Default::default()
+        }
+    }
+
+    fn rotation(id: u64, from: &str, to: &str) -> RotationRecord {
+        let mut new = creds(id, to);
+        new.access_token = Some(format!("access-{}", to));
+        RotationRecord::new(id, &creds(id, from), &new).unwrap()
+    }
+
+    #[test]
+    fn test_record_requires_rotation() {
+        assert!(RotationRecord::new(1, &creds(1, "a"), &creds(1, "a")).is_none());
+        let record = rotation(1, "a", "b");
+        assert_eq!(record.previous_hash, token_hash("a"));
+        assert_eq!(record.refresh_token, "b");
+    }
+
+    #[test]
+    fn test_reconcile_recovers_chain_and_reports_stale() {
+        let records = [
+            rotation(1, "a", "b"),
+            rotation(2, "x", "y"),
+            rotation(1, "b", "c"),
+            rotation(3, "m", "n"),
+        ];
+        // #1 停留在最早的 Token，#2 已写回，#3 被外部改成了其他 Token，#4 不在日志中
+        let mut credentials = [creds(1, "a"), creds(2, "y"), creds(3, "z"), creds(4, "q")];
+
+        let report = reconcile(credentials.iter_mut(), &records);
+        assert_eq!(
+            report,
+            ReconcileReport {
+                recovered: vec![1],
+                stale: vec![3],
+            }
+        );
+        assert_eq!(credentials[0].refresh_token.as_deref(), Some("c"));
+        assert_eq!(credentials[0].access_token.as_deref(), Some("access-c"));
+        assert_eq!(credentials[1].refresh_token.as_deref(), Some("y"));
+        assert_eq!(credentials[2].refresh_token.as_deref(), Some("z"));
+        assert_eq!(credentials[3].refresh_token.as_deref(), Some("q"));
+    }
+
+    #[test]
+    fn test_append_load_and_clear() {
+        let dir = std::env::temp_dir().join(format!("kiro-journal-{}", uuid::Uuid::new_v4()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join(JOURNAL_FILE);
+
+        append(&path, &rotation(1, "a", "b")).unwrap();
+        append(&path, &rotation(1, "b", "c")).unwrap();
+        // 模拟崩溃时写了一半的行
+        OpenOptions::new()
+            .append(true)
+            .open(&path)
+            .unwrap()
+            .write_all(b"{\"credentialId\":1,\"prev")
+            .unwrap();
+
+        let records = load(&path);
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[1].refresh_token, "c");
+
+        clear(&path).unwrap();
+        assert!(load(&path).is_empty());
+        clear(&path).unwrap();
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,278 @@
+//! 转换后 Kiro 请求体积统计
+//!
+//! 记录每次发往上游的请求体字节数与估算 tokens：
+//! - 累计直方图（固定分桶），反映长期分布
+//! - 最近若干次请求的样本，用于计算 p50/p95/p99
+//!
+//! 配置了告警阈值时，最近样本的 p95 估算 tokens 达到阈值会输出告警日志（限频），
+//! 便于在用户遇到上游长度错误之前调整对话裁剪策略。
+
+use std::collections::VecDeque;
+use std::sync::LazyLock;
+
+use parking_lot::Mutex;
+use serde::Serialize;
+
+/// 计算分位数使用的最近样本数量
+const MAX_SAMPLES: usize = 1000;
+
+/// 触发告警所需的最少样本数（样本过少时分位数没有意义）
+const MIN_ALERT_SAMPLES: usize = 20;
+
+/// 两次告警之间的最小间隔（秒）
+const ALERT_INTERVAL_SECS: i64 = 600;
+
+/// 字节数分桶上界
+const BYTE_BUCKETS: [u64; 9] = [
+    16 << 10,
+    32 << 10,
+    64 << 10,
+    128 << 10,
+    256 << 10,
+    512 << 10,
+    1 << 20,
+    2 << 20,
+    4 << 20,
+];
+
+/// 估算 tokens 分桶上界
+const TOKEN_BUCKETS: [u64; 8] = [
+    4_000, 16_000, 32_000, 64_000, 100_000, 150_000, 200_000, 500_000,
+];
+
+static TRACKER: LazyLock<Mutex<RequestSizeTracker>> =
+    LazyLock::new(|| Mutex::new(RequestSizeTracker::new(0)));
+
+/// 直方图分桶
+#[derive(Debug, Clone, Serialize)]
+pub struct Bucket {
+    /// 分桶上界（含），None 表示 +Inf
+    pub le: Option<u64>,
+    /// 落入该分桶的累计请求数
+    pub count: u64,
+}
+
+/// 单个指标的分布
+#[derive(Debug, Clone, Serialize)]
+pub struct Distribution {
+    pub p50: u64,
+    pub p95: u64,
+    pub p99: u64,
+    pub max: u64,
+    pub buckets: Vec<Bucket>,
+}
+
+/// 请求体积统计快照
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RequestSizeStats {
+    /// 启动以来记录的请求总数
+    pub total: u64,
+    /// 参与分位数计算的最近样本数
+    pub window_samples: usize,
+    /// 告警阈值（估算 tokens，0 表示未启用）
+    pub alert_tokens: u64,
+    /// 请求体字节数分布
+    pub bytes: Distribution,
+    /// 估算 tokens 分布
+    pub tokens: Distribution,
+}
+
+struct Histogram {
+    bounds: &'static [u64],
+    /// 长度为 bounds.len() + 1，最后一项为 +Inf
+    counts: Vec<u64>,
+}
+
+impl Histogram {
+    fn new(bounds: &'static [u64]) -> Self {
+        Self {
+            bounds,
+            counts: vec![0; bounds.len() + 1],
+        }
+    }
+
+    fn observe(&mut self, value: u64) {
+        let index = self
+            .bounds
+            .iter()
+            .position(|&b| value <= b)
+            .unwrap_or(self.bounds.len());
+        self.counts[index] += 1;
+    }
+
+    fn buckets(&self) -> Vec<Bucket> {
+        self.counts
+            .iter()
+            .enumerate()
+            .map(|(i, &count)| Bucket {
+                le: self.bounds.get(i).copied(),
+                count,
+            })
+            .collect()
+    }
+}
+
+/// 已排序样本的分位数（最近秩法）
+fn percentile(sorted: &[u64], p: f64) -> u64 {
+    if sorted.is_empty() {
+        return 0;
+    }
+    let rank = ((p / 100.0) * sorted.len() as f64).ceil() as usize;
+    sorted[rank.clamp(1, sorted.len()) - 1]
+}
+
+struct RequestSizeTracker {
+    total: u64,
+    samples: VecDeque<(u64, u64)>,
+    bytes: Histogram,
+    tokens: Histogram,
+    alert_tokens: u64,
+    last_alert_at: Option<i64>,
+}
+
+impl RequestSizeTracker {
+    fn new(alert_tokens: u64) -> Self {
+        Self {
+            total: 0,
+            samples: VecDeque::with_capacity(MAX_SAMPLES),
+            bytes: Histogram::new(&BYTE_BUCKETS),
+            tokens: Histogram::new(&TOKEN_BUCKETS),
+            alert_tokens,
+            last_alert_at: None,
+        }
+    }
+
+    /// 记录一次请求，需要告警时返回当前 p95 估算 tokens
+    fn record(&mut self, bytes: u64, tokens: u64, now: i64) -> Option<u64> {
+        self.total += 1;
+        self.bytes.observe(bytes);
+        self.tokens.observe(tokens);
+        if self.samples.len() == MAX_SAMPLES {
+            self.samples.pop_front();
+        }
+        self.samples.push_back((bytes, tokens));
+
+        if self.alert_tokens == 0 || self.samples.len() < MIN_ALERT_SAMPLES {
+            return None;
+        }
+        if self
+            .last_alert_at
+            .is_some_and(|at| now - at < ALERT_INTERVAL_SECS)
+        {
+            return None;
+        }
+        let p95 = percentile(&self.sorted(|s| s.1), 95.0);
+        if p95 < self.alert_tokens {
+            return None;
+        }
+        self.last_alert_at = Some(now);
+        Some(p95)
+    }
+
+    fn sorted(&self, field: impl Fn(&(u64, u64)) -> u64) -> Vec<u64> {
+        let mut values: Vec<u64> = self.samples.iter().map(field).collect();
+        values.sort_unstable();
+        values
+    }
+
+    fn distribution(
+        &self,
+        histogram: &Histogram,
+        field: impl Fn(&(u64, u64)) -> u64,
+    ) -> Distribution {
+        let sorted = self.sorted(field);
+        Distribution {
+            p50: percentile(&sorted, 50.0),
+            p95: percentile(&sorted, 95.0),
+            p99: percentile(&sorted, 99.0),
+            max: sorted.last().copied().unwrap_or(0),
+            buckets: histogram.buckets(),
+        }
+    }
+
+    fn stats(&self) -> RequestSizeStats {
+        RequestSizeStats {
+            total: self.total,
+            window_samples: self.samples.len(),
+            alert_tokens: self.alert_tokens,
+            bytes: self.distribution(&self.bytes, |s| s.0),
+            tokens: self.distribution(&self.tokens, |s| s.1),
+        }
+    }
+}
+
+/// 初始化告警阈值（估算 tokens，0 表示不告警）
+pub fn init_alert_threshold(alert_tokens: u64) {
+    TRACKER.lock().alert_tokens = alert_tokens;
+}
+
+/// 记录一次发往上游的请求体
+pub fn record(request_body: &str) {
+    let bytes = request_body.len() as u64;
+    let tokens = crate::token::count_tokens(request_body);
+    let (alert, threshold) = {
+        let mut tracker = TRACKER.lock();
+        let alert = tracker.record(bytes, tokens, chrono::Utc::now().timestamp());
+        (alert, tracker.alert_tokens)
+    };
+    if let Some(p95) = alert {
+        tracing::warn!(
+            "最近请求体积 p95 约 {} tokens，已达到告警阈值 {}，上游可能即将返回长度错误，请考虑缩减对话历史",
+            p95,
+            threshold
+        );
+    }
+}
+
+/// 获取当前统计快照
+pub fn stats() -> RequestSizeStats {
+    TRACKER.lock().stats()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_percentile() {
+        let sorted: Vec<u64> = (1..=100).collect();
+        assert_eq!(percentile(&sorted, 50.0), 50);
+        assert_eq!(percentile(&sorted, 95.0), 95);
+        assert_eq!(percentile(&sorted, 99.0), 99);
+        assert_eq!(percentile(&[], 95.0), 0);
+        assert_eq!(percentile(&[7], 50.0), 7);
+    }
+
+    #[test]
+    fn test_histogram_buckets() {
+        let mut tracker = RequestSizeTracker::new(0);
+        tracker.record(1024, 100, 0);
+        tracker.record(20 << 10, 5_000, 0);
+        tracker.record(10 << 20, 600_000, 0);
+
+        let stats = tracker.stats();
+        assert_eq!(stats.total, 3);
+        assert_eq!(stats.bytes.buckets[0].count, 1);
+        assert_eq!(stats.bytes.buckets[1].count, 1);
+        let overflow = stats.bytes.buckets.last().unwrap();
+        assert_eq!((overflow.le, overflow.count), (None, 1));
+        assert_eq!(stats.tokens.max, 600_000);
+    }
+
+    #[test]
+    fn test_alert_when_p95_reaches_threshold() {
+        let mut tracker = RequestSizeTracker::new(100_000);
+        for _ in 0..(MIN_ALERT_SAMPLES - 1) {
+            assert_eq!(tracker.record(1, 150_000, 0), None);
+        }
+        // 样本数达到下限后告警
+        assert_eq!(tracker.record(1, 150_000, 0), Some(150_000));
+        // 告警限频
+        assert_eq!(tracker.record(1, 150_000, 10), None);
+        assert_eq!(
+            tracker.record(1, 150_000, ALERT_INTERVAL_SECS),
+            Some(150_000)
+        );
+    }
+}
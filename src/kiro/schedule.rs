@@ -0,0 +1,218 @@
+//! 凭据可用时段
+//!
+//! 每条规则形如 `<星期> <开始>-<结束>[ <UTC 偏移>]`：
+//! - 星期：`*`（每天）、`Mon-Fri`、`Sat,Sun`、`Mon,Wed-Fri`（英文三字母缩写，不区分大小写）
+//! - 时间：`HH:MM`，结束时间可为 `24:00`；结束早于开始时表示跨午夜（如 `22:00-07:00`），
+//!   跨过午夜的部分归属开始那天（`Fri 22:00-07:00` 包含周六凌晨）
+//! - UTC 偏移：可选，如 `+08:00`、`-05:00`、`UTC`，未指定时使用服务器本地时区
+//!
+//! 多条规则之间为"或"关系；未配置任何规则表示始终可用。
+
+use chrono::{DateTime, Datelike, FixedOffset, Local, NaiveDateTime, Timelike, Utc};
+
+const WEEKDAYS: [&str; 7] = ["mon", "tue", "wed", "thu", "fri", "sat", "sun"];
+
+/// 单条可用时段
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Window {
+    /// 生效的星期（索引 0 = 周一）
+    days: [bool; 7],
+    /// 开始时间（自午夜起的分钟数）
+    start: u32,
+    /// 结束时间（自午夜起的分钟数，不含）
+    end: u32,
+    /// 时区偏移，None 表示本地时区
+    offset: Option<FixedOffset>,
+}
+
+impl Window {
+    fn parse(rule: &str) -> Result<Self, String> {
+        let parts: Vec<&str> = rule.split_whitespace().collect();
+        let (days, range, offset) = match parts.as_slice() {
+            [days, range] => (*days, *range, None),
+            [days, range, offset] => (*days, *range, Some(parse_offset(offset)?)),
+            _ => return Err(format!("时段格式应为 \"<星期> <开始>-<结束>[ <UTC 偏移>]\": {}", rule)),
+        };
+
+        let (start, end) = range
+            .split_once('-')
+            .ok_or_else(|| format!("时间范围应为 HH:MM-HH:MM: {}", range))?;
+        let start = parse_time(start)?;
+        let end = parse_time(end)?;
+        if start == end {
+            return Err(format!("开始与结束时间不能相同: {}", range));
+        }
+
+        Ok(Self {
+            days: parse_days(days)?,
+            start,
+            end,
+            offset,
+        })
+    }
+
+    fn contains(&self, local: NaiveDateTime) -> bool {
+        let weekday = local.weekday().num_days_from_monday() as usize;
+        let minute = local.hour() * 60 + local.minute();
+        if self.start < self.end {
+            self.days[weekday] && (self.start..self.end).contains(&minute)
+        } else {
+            // 跨午夜：当天的开始之后，或前一天开始、延续到今天的部分
+            (self.days[weekday] && minute >= self.start)
+                || (self.days[(weekday + 6) % 7] && minute < self.end)
+        }
+    }
+}
+
+fn parse_days(spec: &str) -> Result<[bool; 7], String> {
+    let mut days = [false; 7];
+    if spec == "*" {
+        return Ok([true; 7]);
+    }
+    let index = |name: &str| {
+        WEEKDAYS
+            .iter()
+            .position(|d| d.eq_ignore_ascii_case(name))
+            .ok_or_else(|| format!("无法识别的星期: {}", name))
+    };
+    for part in spec.split(',') {
+        match part.split_once('-') {
+            Some((from, to)) => {
+                let (from, to) = (index(from)?, index(to)?);
+                // 支持跨周的范围，如 Sat-Mon
+                let mut day = from;
+                loop {
+                    days[day] = true;
+                    if day == to {
+                        break;
+                    }
+                    day = (day + 1) % 7;
+                }
+            }
+            None => days[index(part)?] = true,
+        }
+    }
+    Ok(days)
+}
+
+fn parse_time(s: &str) -> Result<u32, String> {
+    let (h, m) = s
+        .split_once(':')
+        .ok_or_else(|| format!("时间应为 HH:MM: {}", s))?;
+    let h: u32 = h.parse().map_err(|_| format!("无效的小时: {}", s))?;
+    let m: u32 = m.parse().map_err(|_| format!("无效的分钟: {}", s))?;
+    if m >= 60 || h > 24 || (h == 24 && m != 0) {
+        return Err(format!("时间超出范围: {}", s));
+    }
+    Ok(h * 60 + m)
+}
+
+fn parse_offset(s: &str) -> Result<FixedOffset, String> {
+    if s.eq_ignore_ascii_case("utc") || s.eq_ignore_ascii_case("z") {
+        return Ok(FixedOffset::east_opt(0).expect("零偏移合法"));
+    }
+    let (sign, rest) = match s.split_at_checked(1) {
+        Some(("+", rest)) => (1, rest),
+        Some(("-", rest)) => (-1, rest),
+        _ => return Err(format!("UTC 偏移应为 +HH:MM 或 -HH:MM: {}", s)),
+    };
+    let minutes = parse_time(rest)? as i32;
+    FixedOffset::east_opt(sign * minutes * 60).ok_or_else(|| format!("UTC 偏移超出范围: {}", s))
+}
+
+/// 凭据的可用时段集合
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Schedule {
+    windows: Vec<Window>,
+}
+
+impl Schedule {
+    /// 解析规则列表，任一规则无效即返回错误
+    pub fn parse(rules: &[String]) -> Result<Self, String> {
+        let windows = rules
+            .iter()
+            .map(|r| Window::parse(r.trim()))
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(Self { windows })
+    }
+
+    /// 指定时刻是否处于可用时段内（未配置规则时始终为 true）
+    pub fn is_active(&self, now: DateTime<Utc>) -> bool {
+        if self.windows.is_empty() {
+            return true;
+        }
+        self.windows.iter().any(|w| {
+            let local = match w.offset {
+                Some(offset) => now.with_timezone(&offset).naive_local(),
+                None => now.with_timezone(&Local).naive_local(),
+            };
+            w.contains(local)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(s: &str) -> DateTime<Utc> {
+        DateTime::parse_from_rfc3339(s).unwrap().with_timezone(&Utc)
+    }
+
+    fn schedule(rules: &[&str]) -> Schedule {
+        Schedule::parse(&rules.iter().map(|r| r.to_string()).collect::<Vec<_>>()).unwrap()
+    }
+
+    #[test]
+    fn test_empty_schedule_always_active() {
+        assert!(Schedule::default().is_active(at("2026-01-05T12:00:00Z")));
+    }
+
+    #[test]
+    fn test_weekend_window() {
+        let s = schedule(&["Sat,Sun 00:00-24:00 UTC"]);
+        // 2026-01-03 是周六，2026-01-05 是周一
+        assert!(s.is_active(at("2026-01-03T10:00:00Z")));
+        assert!(s.is_active(at("2026-01-04T23:59:00Z")));
+        assert!(!s.is_active(at("2026-01-05T00:00:00Z")));
+    }
+
+    #[test]
+    fn test_overnight_window_belongs_to_start_day() {
+        let s = schedule(&["Fri 22:00-07:00 +08:00"]);
+        // 周五 22:30（UTC+8）
+        assert!(s.is_active(at("2026-01-02T22:30:00+08:00")));
+        // 周六 06:59（UTC+8），属于周五开始的时段
+        assert!(s.is_active(at("2026-01-03T06:59:00+08:00")));
+        assert!(!s.is_active(at("2026-01-03T07:00:00+08:00")));
+        // 周四晚上不在时段内
+        assert!(!s.is_active(at("2026-01-01T23:00:00+08:00")));
+        // 同一时刻换算到 UTC 表示也应一致
+        assert!(s.is_active(at("2026-01-02T15:00:00Z")));
+    }
+
+    #[test]
+    fn test_day_ranges_and_multiple_rules() {
+        let s = schedule(&["Mon-Wed 09:00-12:00 UTC", "sat-mon 20:00-21:00 UTC"]);
+        assert!(s.is_active(at("2026-01-07T10:00:00Z"))); // 周三
+        assert!(!s.is_active(at("2026-01-08T10:00:00Z"))); // 周四
+        assert!(s.is_active(at("2026-01-04T20:30:00Z"))); // 周日（Sat-Mon 跨周范围）
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        for rule in [
+            "Mon",
+            "Funday 00:00-01:00",
+            "Mon 25:00-26:00",
+            "Mon 10:00-10:00",
+            "Mon 10:00-11:00 +8",
+        ] {
+            assert!(
+                Schedule::parse(&[rule.to_string()]).is_err(),
+                "应拒绝无效规则: {}",
+                rule
+            );
+        }
+    }
+}
@@ -0,0 +1,147 @@
+//! 会话亲和路由
+//!
+//! 将同一会话（session_id 或首条 user 消息哈希）固定到同一凭据，
+//! 使 Kiro 侧的缓存与上下文行为保持一致。绑定在 TTL 内无请求时失效；
+//! 绑定的凭据不可调度（禁用、熔断、不在可用时段等）时由调用方重新选择并覆盖绑定。
+
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+use crate::model::config::SessionAffinityConfig;
+
+#[derive(Debug, Clone, Copy)]
+struct Pin {
+    credential_id: u64,
+    last_used: Instant,
+}
+
+/// 会话 → 凭据绑定表
+#[derive(Debug, Default)]
+pub struct SessionAffinity {
+    ttl: Duration,
+    max_sessions: usize,
+    pins: HashMap<String, Pin>,
+}
+
+impl SessionAffinity {
+    pub fn new(config: SessionAffinityConfig) -> Self {
+        Self {
+            ttl: Duration::from_secs(config.ttl_secs),
+            max_sessions: config.max_sessions.max(1),
+            pins: HashMap::new(),
+        }
+    }
+
+    /// 查询会话绑定的凭据（已过期的绑定会被移除）
+    pub fn get(&mut self, key: &str, now: Instant) -> Option<u64> {
+        let pin = *self.pins.get(key)?;
+        if now.duration_since(pin.last_used) > self.ttl {
+            self.pins.remove(key);
+            return None;
+        }
+        Some(pin.credential_id)
+    }
+
+    /// 绑定（或续期）会话到指定凭据，返回被替换的旧凭据 ID（绑定未变化时为 None）
+    pub fn pin(&mut self, key: &str, credential_id: u64, now: Instant) -> Option<u64> {
+        if !self.pins.contains_key(key) && self.pins.len() >= self.max_sessions {
+            self.evict(now);
+        }
+        let previous = self.pins.insert(
+            key.to_string(),
+            Pin {
+                credential_id,
+                last_used: now,
+            },
+        );
+        previous
+            .map(|p| p.credential_id)
+            .filter(|&id| id != credential_id)
+    }
+
+    /// 移除过期绑定；仍超出上限时淘汰最久未使用的绑定
+    fn evict(&mut self, now: Instant) {
+        let ttl = self.ttl;
+        self.pins
+            .retain(|_, pin| now.duration_since(pin.last_used) <= ttl);
+        if self.pins.len() >= self.max_sessions
+            && let Some(oldest) = self
+                .pins
+                .iter()
+                .min_by_key(|(_, pin)| pin.last_used)
+                .map(|(key, _)| key.clone())
+        {
+            self.pins.remove(&oldest);
+        }
+    }
+
+    /// 移除指向指定凭据的所有绑定（凭据被删除时调用）
+    pub fn unpin_credential(&mut self, credential_id: u64) {
+        self.pins
+            .retain(|_, pin| pin.credential_id != credential_id);
+    }
+
+    /// 各凭据当前绑定的会话数（不含已过期绑定）
+    pub fn counts(&self, now: Instant) -> HashMap<u64, usize> {
+        let mut counts = HashMap::new();
+        for pin in self.pins.values() {
+            if now.duration_since(pin.last_used) <= self.ttl {
+                *counts.entry(pin.credential_id).or_default() += 1;
+            }
+        }
+        counts
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn affinity(max_sessions: usize) -> SessionAffinity {
+        SessionAffinity::new(SessionAffinityConfig {
+            ttl_secs: 60,
+            max_sessions,
+        })
+    }
+
+    #[test]
+    fn test_pin_expires_after_ttl() {
+        let mut affinity = affinity(10);
+        let t0 = Instant::now();
+        assert_eq!(affinity.pin("s1", 1, t0), None);
+        assert_eq!(affinity.get("s1", t0 + Duration::from_secs(30)), Some(1));
+
+        // 续期后从最近一次使用开始计算 TTL
+        affinity.pin("s1", 1, t0 + Duration::from_secs(30));
+        assert_eq!(affinity.get("s1", t0 + Duration::from_secs(80)), Some(1));
+        assert_eq!(affinity.get("s1", t0 + Duration::from_secs(91)), None);
+        assert!(affinity.counts(t0).is_empty());
+    }
+
+    #[test]
+    fn test_repin_returns_previous_credential() {
+        let mut affinity = affinity(10);
+        let now = Instant::now();
+        affinity.pin("s1", 1, now);
+        assert_eq!(affinity.pin("s1", 1, now), None);
+        assert_eq!(affinity.pin("s1", 2, now), Some(1));
+        assert_eq!(affinity.get("s1", now), Some(2));
+
+        affinity.unpin_credential(2);
+        assert_eq!(affinity.get("s1", now), None);
+    }
+
+    #[test]
+    fn test_evicts_least_recently_used_when_full() {
+        let mut affinity = affinity(2);
+        let t0 = Instant::now();
+        affinity.pin("s1", 1, t0);
+        affinity.pin("s2", 1, t0 + Duration::from_secs(1));
+        affinity.pin("s3", 2, t0 + Duration::from_secs(2));
+
+        let now = t0 + Duration::from_secs(2);
+        assert_eq!(affinity.get("s1", now), None);
+        assert_eq!(affinity.get("s2", now), Some(1));
+        assert_eq!(affinity.counts(now).get(&2), Some(&1));
+    }
+}
@@ -5,25 +5,36 @@
 
 use anyhow::bail;
 use chrono::{DateTime, Duration, Utc};
-use parking_lot::Mutex;
+use parking_lot::{Mutex, RwLock};
 use serde::{Deserialize, Serialize};
 use sha2::{Digest, Sha256};
 use tokio::sync::Mutex as TokioMutex;
 
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::fmt;
 use std::path::PathBuf;
+use std::sync::Arc;
 use std::sync::atomic::{AtomicBool, Ordering};
 use std::time::{Duration as StdDuration, Instant};
 
+use crate::common::atomic_file;
 use crate::http_client::{ProxyConfig, build_client};
+use crate::kiro::circuit_breaker::{BreakerSnapshot, CircuitBreaker};
 use crate::kiro::machine_id;
+use crate::kiro::model::available_models::AvailableModelsResponse;
 use crate::kiro::model::credentials::KiroCredentials;
 use crate::kiro::model::token_refresh::{
     IdcRefreshRequest, IdcRefreshResponse, RefreshRequest, RefreshResponse,
 };
 use crate::kiro::model::usage_limits::UsageLimitsResponse;
-use crate::model::config::Config;
+use crate::kiro::provider::UpstreamThrottledError;
+use crate::kiro::proxy_health::{self, ProxyStatus};
+use crate::kiro::quota_alert;
+use crate::kiro::refresh_journal::{self, RotationRecord};
+use crate::kiro::schedule::Schedule;
+use crate::kiro::session_affinity::SessionAffinity;
+use crate::kiro::warm_refresh::{RefreshSource, RefreshState, RefreshStatus};
+use crate::model::config::{Config, ModelRoute};
 
 /// 检查 Token 是否在指定时间内过期
 pub(crate) fn is_token_expiring_within(
@@ -55,7 +66,7 @@ fn sha256_hex(input: &str) -> String {
 }
 
 /// 生成 API Key 脱敏展示(前 4 + ... + 后 4,长度不足或非 ASCII 回退 ***)
-fn mask_api_key(key: &str) -> String {
+pub(crate) fn mask_api_key(key: &str) -> String {
     if key.is_ascii() && key.len() > 16 {
         format!("{}...{}", &key[..4], &key[key.len() - 4..])
     } else {
@@ -103,7 +114,23 @@ impl fmt::Display for RefreshTokenInvalidError {
 
 impl std::error::Error for RefreshTokenInvalidError {}
 
+/// 获取使用额度时上游返回的 HTTP 错误（401/403 表示凭据被拒绝）
+#[derive(Debug)]
+pub(crate) struct UsageLimitsStatusError {
+    pub status: u16,
+    pub message: String,
+}
+
+impl fmt::Display for UsageLimitsStatusError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+impl std::error::Error for UsageLimitsStatusError {}
+
 /// 刷新 Token
+#[tracing::instrument(name = "refresh_token", skip_all, fields(credential_id = credentials.id))]
 pub(crate) async fn refresh_token(
     credentials: &KiroCredentials,
     config: &Config,
@@ -153,9 +180,10 @@ async fn refresh_social_token(
     let refresh_url = format!("https://prod.{}.auth.desktop.kiro.dev/refreshToken", region);
     let refresh_domain = format!("prod.{}.auth.desktop.kiro.dev", region);
     let machine_id = machine_id::generate_from_credentials(credentials, config);
-    let kiro_version = &config.kiro_version;
+    let profile = credentials.effective_client_profile(config);
+    let kiro_version = profile.kiro_version;
 
-    let client = build_client(proxy, 60, config.tls_backend)?;
+    let client = build_client(proxy, 60, profile.tls_backend)?;
     let body = RefreshRequest {
         refresh_token: refresh_token.to_string(),
     };
@@ -242,8 +270,9 @@ async fn refresh_idc_token(
     // 优先级：凭据.auth_region > 凭据.region > config.auth_region > config.region
     let region = credentials.effective_auth_region(config);
     let refresh_url = format!("https://oidc.{}.amazonaws.com/token", region);
-    let os_name = &config.system_version;
-    let node_version = &config.node_version;
+    let profile = credentials.effective_client_profile(config);
+    let os_name = profile.system_version;
+    let node_version = profile.node_version;
 
     let x_amz_user_agent = "aws-sdk-js/3.980.0 KiroIDE";
     let user_agent = format!(
@@ -251,7 +280,7 @@ async fn refresh_idc_token(
         os_name, node_version
     );
 
-    let client = build_client(proxy, 60, config.tls_backend)?;
+    let client = build_client(proxy, 60, profile.tls_backend)?;
     let body = IdcRefreshRequest {
         client_id: client_id.to_string(),
         client_secret: client_secret.to_string(),
@@ -332,9 +361,10 @@ pub(crate) async fn get_usage_limits(
     let region = credentials.effective_api_region(config);
     let host = format!("q.{}.amazonaws.com", region);
     let machine_id = machine_id::generate_from_credentials(credentials, config);
-    let kiro_version = &config.kiro_version;
-    let os_name = &config.system_version;
-    let node_version = &config.node_version;
+    let profile = credentials.effective_client_profile(config);
+    let kiro_version = profile.kiro_version;
+    let os_name = profile.system_version;
+    let node_version = profile.node_version;
 
     // 构建 URL
     let mut url = format!(
@@ -357,7 +387,7 @@ pub(crate) async fn get_usage_limits(
         kiro_version, machine_id
     );
 
-    let client = build_client(proxy, 60, config.tls_backend)?;
+    let client = build_client(proxy, 60, profile.tls_backend)?;
 
     let mut request = client
         .get(&url)
@@ -385,13 +415,73 @@ pub(crate) async fn get_usage_limits(
             500..=599 => "服务器错误，AWS 服务暂时不可用",
             _ => "获取使用额度失败",
         };
-        bail!("{}: {} {}", error_msg, status, body_text);
+        return Err(UsageLimitsStatusError {
+            status: status.as_u16(),
+            message: format!("{}: {} {}", error_msg, status, body_text),
+        }
+        .into());
     }
 
     let data: UsageLimitsResponse = response.json().await?;
     Ok(data)
 }
 
+/// 获取当前凭据可用的模型列表
+pub(crate) async fn list_available_models(
+    credentials: &KiroCredentials,
+    config: &Config,
+    token: &str,
+    proxy: Option<&ProxyConfig>,
+) -> anyhow::Result<AvailableModelsResponse> {
+    tracing::debug!("正在获取可用模型列表...");
+
+    let region = credentials.effective_api_region(config);
+    let host = format!("q.{}.amazonaws.com", region);
+    let machine_id = machine_id::generate_from_credentials(credentials, config);
+
+    let mut url = format!("https://{}/ListAvailableModels?origin=AI_EDITOR", host);
+    if let Some(profile_arn) = &credentials.profile_arn {
+        url.push_str(&format!("&profileArn={}", urlencoding::encode(profile_arn)));
+    }
+
+    let profile = credentials.effective_client_profile(config);
+    let user_agent = format!(
+        "aws-sdk-js/1.0.0 ua/2.1 os/{} lang/js md/nodejs#{} api/codewhispererruntime#1.0.0 m/N,E KiroIDE-{}-{}",
+        profile.system_version, profile.node_version, profile.kiro_version, machine_id
+    );
+    let amz_user_agent = format!(
+        "aws-sdk-js/1.0.0 KiroIDE-{}-{}",
+        profile.kiro_version, machine_id
+    );
+
+    let client = build_client(proxy, 60, profile.tls_backend)?;
+
+    let mut request = client
+        .get(&url)
+        .header("x-amz-user-agent", &amz_user_agent)
+        .header("user-agent", &user_agent)
+        .header("host", &host)
+        .header("amz-sdk-invocation-id", uuid::Uuid::new_v4().to_string())
+        .header("amz-sdk-request", "attempt=1; max=1")
+        .header("Authorization", format!("Bearer {}", token))
+        .header("Connection", "close");
+
+    if credentials.is_api_key_credential() {
+        request = request.header("tokentype", "API_KEY");
+    }
+
+    let response = request.send().await?;
+
+    let status = response.status();
+    if !status.is_success() {
+        let body_text = response.text().await.unwrap_or_default();
+        bail!("获取可用模型列表失败: {} {}", status, body_text);
+    }
+
+    let data: AvailableModelsResponse = response.json().await?;
+    Ok(data)
+}
+
 // ============================================================================
 // 多凭据 Token 管理器
 // ============================================================================
@@ -414,6 +504,97 @@ struct CredentialEntry {
     success_count: u64,
     /// 最后一次 API 调用时间（RFC3339 格式）
     last_used_at: Option<String>,
+    /// 已解析的可用时段（由 credentials.schedule 解析）
+    schedule: Schedule,
+    /// 上游 5xx / 超时熔断器
+    breaker: CircuitBreaker,
+    /// 上游 429 限流冷却的截止时间（冷却期内不参与调度）
+    cooldown_until: Option<DateTime<Utc>>,
+    /// Token 刷新状态（预刷新的抖动与失败退避）
+    refresh: RefreshState,
+}
+
+impl CredentialEntry {
+    /// 指定时刻是否可参与调度（未禁用、处于可用时段内、未熔断且不在限流冷却中）
+    fn is_schedulable(&self, now: DateTime<Utc>) -> bool {
+        !self.disabled
+            && self.schedule.is_active(now)
+            && self.breaker.allows(Instant::now())
+            && !self.is_cooling_down(now)
+    }
+
+    /// 指定时刻是否处于限流冷却中
+    fn is_cooling_down(&self, now: DateTime<Utc>) -> bool {
+        self.cooldown_until.is_some_and(|until| now < until)
+    }
+
+    /// 是否支持指定模型（opus 模型需要付费订阅）
+    fn supports_model(&self, model: Option<&str>) -> bool {
+        let is_opus = model
+            .map(|m| m.to_lowercase().contains("opus"))
+            .unwrap_or(false);
+        !is_opus || self.credentials.supports_opus()
+    }
+
+    /// 能否服务请求指定的 API Region（None 表示请求未指定，不受限制）
+    fn serves_region(&self, api_region: Option<&str>) -> bool {
+        api_region.is_none_or(|region| self.credentials.serves_api_region(region))
+    }
+
+    /// 是否属于指定分组
+    fn in_group(&self, group: &str) -> bool {
+        self.credentials.group.as_deref() == Some(group)
+    }
+}
+
+/// 按「成功次数 / 权重」比较两个凭据的使用程度（交叉相乘，避免浮点误差）
+fn weighted_usage_cmp(a: &CredentialEntry, b: &CredentialEntry) -> std::cmp::Ordering {
+    let a_usage = u128::from(a.success_count) * u128::from(b.credentials.effective_weight());
+    let b_usage = u128::from(b.success_count) * u128::from(a.credentials.effective_weight());
+    a_usage.cmp(&b_usage)
+}
+
+/// 参与模型路由匹配的请求特征（模型之外）
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct RouteHints {
+    /// 请求的消息数
+    pub message_count: Option<usize>,
+    /// 请求的 max_tokens
+    pub max_tokens: Option<i32>,
+}
+
+/// 查找请求命中的第一条路由规则，返回按优先顺序排列的分组
+///
+/// 未提供模型或没有规则命中时返回 None，表示不限制分组；
+/// 规则配置了消息数或 max_tokens 条件而请求未提供对应特征时视为不匹配
+fn route_groups(
+    routes: &[ModelRoute],
+    model: Option<&str>,
+    hints: RouteHints,
+) -> Option<Vec<String>> {
+    let model = model?.to_lowercase();
+    let within = |limit: Option<usize>, value: Option<usize>| {
+        limit.is_none_or(|limit| value.is_some_and(|v| v <= limit))
+    };
+    routes
+        .iter()
+        .find(|route| {
+            let model_matches = route.models.is_empty()
+                || route.models.iter().any(|pattern| {
+                    let pattern = pattern.to_lowercase();
+                    match pattern.strip_suffix('*') {
+                        Some(prefix) => model.starts_with(prefix),
+                        None => model == pattern,
+                    }
+                });
+            model_matches
+                && within(route.max_messages, hints.message_count)
+                && within(
+                    route.max_tokens.map(|t| t.max(0) as usize),
+                    hints.max_tokens.map(|t| t.max(0) as usize),
+                )
+        })
+        .map(|route| route.groups.clone())
 }
 
 /// 禁用原因
@@ -433,6 +614,18 @@ enum DisabledReason {
     InvalidConfig,
 }
 
+impl DisabledReason {
+    /// 是否可能自愈（健康检查会探测此类凭据）
+    fn is_probe_candidate(self) -> bool {
+        matches!(
+            self,
+            DisabledReason::TooManyFailures
+                | DisabledReason::TooManyRefreshFailures
+                | DisabledReason::QuotaExceeded
+        )
+    }
+}
+
 /// 统计数据持久化条目
 #[derive(Serialize, Deserialize)]
 struct StatsEntry {
@@ -479,6 +672,9 @@ pub struct CredentialEntrySnapshot {
     /// 代理 URL（用于前端展示）
     #[serde(skip_serializing_if = "Option::is_none")]
     pub proxy_url: Option<String>,
+    /// 凭据级代理的健康状态与当前生效的代理（未配置凭据级代理时为 None）
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub proxy_status: Option<ProxyStatus>,
     /// Token 刷新连续失败次数
     pub refresh_failure_count: u32,
     /// 禁用原因
@@ -487,6 +683,27 @@ pub struct CredentialEntrySnapshot {
     /// 端点名称（未显式配置时返回 None，由 Admin 层回退到默认值）
     #[serde(skip_serializing_if = "Option::is_none")]
     pub endpoint: Option<String>,
+    /// 可用时段规则（为空表示始终可用）
+    pub schedule: Vec<String>,
+    /// 当前是否处于可用时段内
+    pub in_schedule: bool,
+    /// 凭据分组
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub group: Option<String>,
+    /// balanced 模式下的流量权重
+    pub weight: u32,
+    /// 熔断器状态（未配置熔断时为 None）
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub circuit_breaker: Option<BreakerSnapshot>,
+    /// 限流冷却截止时间（RFC3339 格式，未在冷却中时为 None）
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cooldown_until: Option<String>,
+    /// 绑定到该凭据的会话数（未配置会话亲和时为 None）
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pinned_sessions: Option<usize>,
+    /// 最近一次 Token 刷新的结果（尚未刷新过时为 None）
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub last_refresh: Option<RefreshStatus>,
 }
 
 /// 凭据管理器状态快照
@@ -509,7 +726,8 @@ pub struct ManagerSnapshot {
 /// 故障统计基于 API 调用结果，而非 Token 刷新结果
 pub struct MultiTokenManager {
     config: Config,
-    proxy: Option<ProxyConfig>,
+    /// 全局代理配置（配置热加载时可更新）
+    proxy: RwLock<Option<ProxyConfig>>,
     /// 凭据条目列表
     entries: Mutex<Vec<CredentialEntry>>,
     /// 当前活动凭据 ID
@@ -520,12 +738,18 @@ pub struct MultiTokenManager {
     credentials_path: Option<PathBuf>,
     /// 是否为多凭据格式（数组格式才回写）
     is_multiple_format: bool,
+    /// 凭据文件回写锁，保证刷新日志与凭据文件的写入顺序
+    persist_lock: Mutex<()>,
     /// 负载均衡模式（运行时可修改）
     load_balancing_mode: Mutex<String>,
+    /// 模型 → 凭据分组路由规则（运行时可修改）
+    model_routes: RwLock<Vec<ModelRoute>>,
     /// 最近一次统计持久化时间（用于 debounce）
     last_stats_save_at: Mutex<Option<Instant>>,
     /// 统计数据是否有未落盘更新
     stats_dirty: AtomicBool,
+    /// 会话 → 凭据绑定（未配置会话亲和时为 None）
+    affinity: Option<Mutex<SessionAffinity>>,
 }
 
 /// 每个凭据最大 API 调用失败次数
@@ -599,6 +823,10 @@ impl MultiTokenManager {
                     },
                     success_count: 0,
                     last_used_at: None,
+                    schedule: Schedule::default(),
+                    breaker: CircuitBreaker::new(config_ref.circuit_breaker),
+                    cooldown_until: None,
+                    refresh: RefreshState::new(config_ref.warm_refresh.as_ref()),
                 }
             })
             .collect();
@@ -621,6 +849,16 @@ impl MultiTokenManager {
                 entry.disabled = true;
                 entry.disabled_reason = Some(DisabledReason::InvalidConfig);
             }
+
+            // 解析可用时段，规则无效时禁用凭据，避免在错误的时段被使用
+            match Schedule::parse(&entry.credentials.schedule) {
+                Ok(schedule) => entry.schedule = schedule,
+                Err(e) => {
+                    tracing::warn!("凭据 #{} 的可用时段配置无效，已自动禁用: {}", entry.id, e);
+                    entry.disabled = true;
+                    entry.disabled_reason = Some(DisabledReason::InvalidConfig);
+                }
+            }
         }
 
         // 检测重复 ID
@@ -644,19 +882,29 @@ impl MultiTokenManager {
             .unwrap_or(0);
 
         let load_balancing_mode = config.load_balancing_mode.clone();
+        let model_routes = config.model_routes.clone();
+        let affinity = config
+            .session_affinity
+            .map(|c| Mutex::new(SessionAffinity::new(c)));
         let manager = Self {
             config,
-            proxy,
+            proxy: RwLock::new(proxy),
             entries: Mutex::new(entries),
             current_id: Mutex::new(initial_id),
             refresh_lock: TokioMutex::new(()),
             credentials_path,
             is_multiple_format,
+            persist_lock: Mutex::new(()),
             load_balancing_mode: Mutex::new(load_balancing_mode),
+            model_routes: RwLock::new(model_routes),
             last_stats_save_at: Mutex::new(None),
             stats_dirty: AtomicBool::new(false),
+            affinity,
         };
 
+        // 对照刷新日志检查凭据文件，恢复崩溃前未写回的 refreshToken
+        manager.recover_rotations();
+
         // 如果有新分配的 ID 或新生成的 machineId，立即持久化到配置文件
         if has_new_ids || has_new_machine_ids {
             if let Err(e) = manager.persist_credentials() {
@@ -673,10 +921,22 @@ impl MultiTokenManager {
     }
 
     /// 获取配置的引用
+    ///
+    /// 启动时的配置快照；可热更新的字段（代理、负载均衡模式）以各自的访问器为准
     pub fn config(&self) -> &Config {
         &self.config
     }
 
+    /// 获取当前全局代理配置
+    pub fn global_proxy(&self) -> Option<ProxyConfig> {
+        self.proxy.read().clone()
+    }
+
+    /// 更新全局代理配置（配置热加载）
+    pub fn set_global_proxy(&self, proxy: Option<ProxyConfig>) {
+        *self.proxy.write() = proxy;
+    }
+
     /// 获取凭据总数
     pub fn total_count(&self) -> usize {
         self.entries.lock().len()
@@ -692,31 +952,44 @@ impl MultiTokenManager {
     /// - priority 模式：选择优先级最高（priority 最小）的可用凭据
     /// - balanced 模式：均衡选择可用凭据
     ///
+    /// 请求命中 `modelRoutes` 规则时只在规则指定的分组中选择：按分组顺序，
+    /// 使用第一个有可用凭据的分组
+    ///
     /// # 参数
     /// - `model`: 可选的模型名称，用于过滤支持该模型的凭据（如 opus 模型需要付费订阅）
-    fn select_next_credential(&self, model: Option<&str>) -> Option<(u64, KiroCredentials)> {
+    /// - `groups`: 命中的路由规则指定的分组（None 表示不限制）
+    /// - `api_region`: 请求指定的 API Region，只选择 profileArn 属于该 Region 的凭据
+    fn select_next_credential(
+        &self,
+        model: Option<&str>,
+        groups: Option<&[String]>,
+        api_region: Option<&str>,
+    ) -> Option<(u64, KiroCredentials)> {
         let entries = self.entries.lock();
 
-        // 检查是否是 opus 模型
-        let is_opus = model
-            .map(|m| m.to_lowercase().contains("opus"))
-            .unwrap_or(false);
-
-        // 过滤可用凭据
-        let available: Vec<_> = entries
+        // 过滤可用凭据（未禁用、处于可用时段内、支持请求的模型与 Region）
+        let now = Utc::now();
+        let mut available: Vec<_> = entries
             .iter()
             .filter(|e| {
-                if e.disabled {
-                    return false;
-                }
-                // 如果是 opus 模型，需要检查订阅等级
-                if is_opus && !e.credentials.supports_opus() {
-                    return false;
-                }
-                true
+                e.is_schedulable(now) && e.supports_model(model) && e.serves_region(api_region)
             })
             .collect();
 
+        if let Some(groups) = groups {
+            let group = groups
+                .iter()
+                .find(|g| available.iter().any(|e| e.in_group(g)))?;
+            if groups.first() != Some(group) {
+                tracing::debug!(
+                    "模型 {:?} 的首选分组无可用凭据，溢出到分组 {}",
+                    model,
+                    group
+                );
+            }
+            available.retain(|e| e.in_group(group));
+        }
+
         if available.is_empty() {
             return None;
         }
@@ -726,11 +999,13 @@ impl MultiTokenManager {
 
         match mode {
             "balanced" => {
-                // Least-Used 策略：选择成功次数最少的凭据
+                // 加权 Least-Used 策略：选择「成功次数 / 权重」最小的凭据，
+                // 长期来看各凭据承担的请求数与权重成正比
                 // 平局时按优先级排序（数字越小优先级越高）
-                let entry = available
-                    .iter()
-                    .min_by_key(|e| (e.success_count, e.credentials.priority))?;
+                let entry = available.iter().min_by(|a, b| {
+                    weighted_usage_cmp(a, b)
+                        .then(a.credentials.priority.cmp(&b.credentials.priority))
+                })?;
 
                 Some((entry.id, entry.credentials.clone()))
             }
@@ -753,6 +1028,25 @@ impl MultiTokenManager {
     /// # 参数
     /// - `model`: 可选的模型名称，用于过滤支持该模型的凭据（如 opus 模型需要付费订阅）
     pub async fn acquire_context(&self, model: Option<&str>) -> anyhow::Result<CallContext> {
+        self.acquire_context_for_session(model, RouteHints::default(), None, None)
+            .await
+    }
+
+    /// 获取 API 调用上下文（会话亲和）
+    ///
+    /// 配置 `sessionAffinity` 且提供 `session_key` 时，优先使用会话已绑定的凭据；
+    /// 绑定的凭据不可调度或不支持请求的模型时按常规策略选择，并将会话重新绑定到新凭据。
+    /// `hints` 为模型路由匹配所需的请求特征；`api_region` 为请求通过 `X-Kiro-Region`
+    /// 指定的 API Region，只选择 profileArn 属于该 Region 的凭据，且不改变 priority 模式的当前凭据
+    #[tracing::instrument(name = "acquire_credential", skip_all, fields(model = model))]
+    pub async fn acquire_context_for_session(
+        &self,
+        model: Option<&str>,
+        hints: RouteHints,
+        session_key: Option<&str>,
+        api_region: Option<&str>,
+    ) -> anyhow::Result<CallContext> {
+        let session_key = session_key.filter(|_| self.affinity.is_some());
         let total = self.total_count();
         let max_attempts = (total * MAX_FAILURES_PER_CREDENTIAL as usize).max(1);
         let mut attempt_count = 0;
@@ -768,17 +1062,31 @@ impl MultiTokenManager {
 
             let (id, credentials) = {
                 let is_balanced = self.load_balancing_mode.lock().as_str() == "balanced";
+                // 命中模型路由的请求只在路由分组内选择，不使用也不修改 current_id
+                let groups = route_groups(&self.model_routes.read(), model, hints);
+
+                // 会话已绑定凭据：优先使用（不受负载均衡模式影响，也不修改 current_id）
+                let pinned_hit = session_key.and_then(|key| {
+                    self.pinned_credential(key, model, groups.as_deref(), api_region)
+                });
 
                 // balanced 模式：每次请求都重新均衡选择，不固定 current_id
                 // priority 模式：优先使用 current_id 指向的凭据
-                let current_hit = if is_balanced {
+                let current_hit = if pinned_hit.is_some() {
+                    pinned_hit
+                } else if is_balanced || groups.is_some() {
                     None
                 } else {
                     let entries = self.entries.lock();
                     let current_id = *self.current_id.lock();
                     entries
                         .iter()
-                        .find(|e| e.id == current_id && !e.disabled)
+                        .find(|e| {
+                            e.id == current_id
+                                && e.is_schedulable(Utc::now())
+                                && e.supports_model(model)
+                                && e.serves_region(api_region)
+                        })
                         .map(|e| (e.id, e.credentials.clone()))
                 };
 
@@ -786,7 +1094,8 @@ impl MultiTokenManager {
                     hit
                 } else {
                     // 当前凭据不可用或 balanced 模式，根据负载均衡策略选择
-                    let mut best = self.select_next_credential(model);
+                    let mut best =
+                        self.select_next_credential(model, groups.as_deref(), api_region);
 
                     // 没有可用凭据：如果是"自动禁用导致全灭"，做一次类似重启的自愈
                     if best.is_none() {
@@ -805,21 +1114,98 @@ impl MultiTokenManager {
                                 }
                             }
                             drop(entries);
-                            best = self.select_next_credential(model);
+                            best =
+                                self.select_next_credential(model, groups.as_deref(), api_region);
                         }
                     }
 
                     if let Some((new_id, new_creds)) = best {
-                        // 更新 current_id
-                        let mut current_id = self.current_id.lock();
-                        *current_id = new_id;
+                        if groups.is_none() && api_region.is_none() {
+                            *self.current_id.lock() = new_id;
+                        }
                         (new_id, new_creds)
                     } else {
+                        if let Some(groups) = &groups {
+                            anyhow::bail!(
+                                "模型 {} 路由的凭据分组 {:?} 均无可用凭据",
+                                model.unwrap_or_default(),
+                                groups
+                            );
+                        }
                         let entries = self.entries.lock();
                         // 注意：必须在 bail! 之前计算 available_count，
                         // 因为 available_count() 会尝试获取 entries 锁，
                         // 而此时我们已经持有该锁，会导致死锁
                         let available = entries.iter().filter(|e| !e.disabled).count();
+                        let now = Utc::now();
+                        // 只看支持请求模型与 Region 的凭据：不受支持时，冷却、熔断和可用时段都与本次请求无关
+                        let candidates: Vec<_> = entries
+                            .iter()
+                            .filter(|e| {
+                                !e.disabled
+                                    && e.supports_model(model)
+                                    && e.serves_region(api_region)
+                            })
+                            .collect();
+                        // 限流冷却：以最早结束的冷却时间作为 Retry-After 告知客户端
+                        if let Some(until) = candidates
+                            .iter()
+                            .filter(|e| e.is_cooling_down(now))
+                            .filter_map(|e| e.cooldown_until)
+                            .min()
+                        {
+                            let wait = (until - now).num_milliseconds().max(0) as u64;
+                            return Err(UpstreamThrottledError {
+                                message: format!(
+                                    "所有可用凭据均未就绪（限流冷却中，{}s 后恢复，可用 {}/{}）",
+                                    wait.div_ceil(1000),
+                                    available,
+                                    total
+                                ),
+                                retry_after: Some(wait.div_ceil(1000)),
+                            }
+                            .into());
+                        }
+                        let tripped = candidates
+                            .iter()
+                            .filter(|e| !e.breaker.allows(Instant::now()))
+                            .count();
+                        if tripped > 0 {
+                            anyhow::bail!(
+                                "所有可用凭据均未就绪（{} 个熔断中，可用 {}/{}）",
+                                tripped,
+                                available,
+                                total
+                            );
+                        }
+                        if candidates.iter().any(|e| !e.schedule.is_active(now)) {
+                            anyhow::bail!(
+                                "所有可用凭据均不在可用时段内（{}/{}）",
+                                available,
+                                total
+                            );
+                        }
+                        if let Some(region) = api_region
+                            && !entries
+                                .iter()
+                                .any(|e| !e.disabled && e.serves_region(api_region))
+                        {
+                            anyhow::bail!(
+                                "没有 profileArn 属于 Region {} 的可用凭据（{}/{}）",
+                                region,
+                                available,
+                                total
+                            );
+                        }
+                        if available > 0 {
+                            // 如 opus 请求只剩 FREE 凭据：保持 fallback 可识别的错误信息
+                            anyhow::bail!(
+                                "所有凭据已用尽：没有支持模型 {} 的可用凭据（{}/{}）",
+                                model.unwrap_or_default(),
+                                available,
+                                total
+                            );
+                        }
                         anyhow::bail!("所有凭据均已禁用（{}/{}）", available, total);
                     }
                 }
@@ -828,6 +1214,18 @@ impl MultiTokenManager {
             // 尝试获取/刷新 Token
             match self.try_ensure_token(id, &credentials).await {
                 Ok(ctx) => {
+                    if let Some(entry) = self.entries.lock().iter_mut().find(|e| e.id == id) {
+                        entry.breaker.on_dispatch(Instant::now());
+                    }
+                    if let (Some(key), Some(affinity)) = (session_key, &self.affinity)
+                        && let Some(previous) = affinity.lock().pin(key, id, Instant::now())
+                    {
+                        tracing::info!(
+                            "会话绑定的凭据 #{} 不可用，已重新绑定到凭据 #{}",
+                            previous,
+                            id
+                        );
+                    }
                     return Ok(ctx);
                 }
                 Err(e) => {
@@ -849,6 +1247,30 @@ impl MultiTokenManager {
         }
     }
 
+    /// 查询会话绑定的凭据
+    ///
+    /// 绑定已过期、凭据不可调度、不支持请求的模型与 Region 或不在路由分组内时返回 None
+    fn pinned_credential(
+        &self,
+        key: &str,
+        model: Option<&str>,
+        groups: Option<&[String]>,
+        api_region: Option<&str>,
+    ) -> Option<(u64, KiroCredentials)> {
+        let id = self.affinity.as_ref()?.lock().get(key, Instant::now())?;
+        let entries = self.entries.lock();
+        entries
+            .iter()
+            .find(|e| {
+                e.id == id
+                    && e.is_schedulable(Utc::now())
+                    && e.supports_model(model)
+                    && e.serves_region(api_region)
+                    && groups.is_none_or(|groups| groups.iter().any(|g| e.in_group(g)))
+            })
+            .map(|e| (e.id, e.credentials.clone()))
+    }
+
     /// 选择优先级最高的未禁用凭据作为当前凭据（内部方法）
     ///
     /// 纯粹按优先级选择，不排除当前凭据，用于优先级变更后立即生效
@@ -856,10 +1278,11 @@ impl MultiTokenManager {
         let entries = self.entries.lock();
         let mut current_id = self.current_id.lock();
 
-        // 选择优先级最高的未禁用凭据（不排除当前凭据）
+        // 选择优先级最高的可调度凭据（不排除当前凭据）
+        let now = Utc::now();
         if let Some(best) = entries
             .iter()
-            .filter(|e| !e.disabled)
+            .filter(|e| e.is_schedulable(now))
             .min_by_key(|e| e.credentials.priority)
         {
             if best.id != *current_id {
@@ -918,19 +1341,33 @@ impl MultiTokenManager {
 
             if is_token_expired(&current_creds) || is_token_expiring_soon(&current_creds) {
                 // 确实需要刷新
-                let effective_proxy = current_creds.effective_proxy(self.proxy.as_ref());
-                let new_creds =
-                    refresh_token(&current_creds, &self.config, effective_proxy.as_ref()).await?;
-
-                if is_token_expired(&new_creds) {
-                    anyhow::bail!("刷新后的 Token 仍然无效或已过期");
-                }
+                let effective_proxy = current_creds.effective_proxy(self.global_proxy().as_ref());
+                let refreshed =
+                    refresh_token(&current_creds, &self.config, effective_proxy.as_ref())
+                        .await
+                        .and_then(|new_creds| {
+                            if is_token_expired(&new_creds) {
+                                anyhow::bail!("刷新后的 Token 仍然无效或已过期");
+                            }
+                            Ok(new_creds)
+                        });
+                let new_creds = match refreshed {
+                    Ok(new_creds) => new_creds,
+                    Err(e) => {
+                        self.record_refresh_failure(id, RefreshSource::OnDemand, &e);
+                        return Err(e);
+                    }
+                };
 
                 // 更新凭据
                 {
+                    let _journal = self.journal_rotation(id, &current_creds, &new_creds);
                     let mut entries = self.entries.lock();
                     if let Some(entry) = entries.iter_mut().find(|e| e.id == id) {
                         entry.credentials = new_creds.clone();
+                        entry
+                            .refresh
+                            .record_success(RefreshSource::OnDemand, Utc::now());
                     }
                 }
 
@@ -991,36 +1428,119 @@ impl MultiTokenManager {
             None => return Ok(false),
         };
 
+        // 持有回写锁期间不会有新的刷新日志记录，写回成功后可以安全清空日志
+        let _guard = self.persist_lock.lock();
+
         // 收集所有凭据
-        let credentials: Vec<KiroCredentials> = {
-            let entries = self.entries.lock();
-            entries
-                .iter()
-                .map(|e| {
-                    let mut cred = e.credentials.clone();
-                    cred.canonicalize_auth_method();
-                    // 同步 disabled 状态到凭据对象
-                    cred.disabled = e.disabled;
-                    cred
-                })
-                .collect()
-        };
+        let credentials = self.export_credentials();
 
         // 序列化为 pretty JSON
         let json = serde_json::to_string_pretty(&credentials).context("序列化凭据失败")?;
 
-        // 写入文件（在 Tokio runtime 内使用 block_in_place 避免阻塞 worker）
+        // 原子写入文件（在 Tokio runtime 内使用 block_in_place 避免阻塞 worker）
+        let journal = self.journal_path();
+        let write = || {
+            atomic_file::write(path, json.as_bytes())?;
+            if let Some(Err(e)) = journal.as_deref().map(refresh_journal::clear) {
+                tracing::warn!("清空刷新日志失败: {}", e);
+            }
+            std::io::Result::Ok(())
+        };
         if tokio::runtime::Handle::try_current().is_ok() {
-            tokio::task::block_in_place(|| std::fs::write(path, &json))
-                .with_context(|| format!("回写凭据文件失败: {:?}", path))?;
+            tokio::task::block_in_place(write)
         } else {
-            std::fs::write(path, &json).with_context(|| format!("回写凭据文件失败: {:?}", path))?;
+            write()
         }
+        .with_context(|| format!("回写凭据文件失败: {:?}", path))?;
 
         tracing::debug!("已回写凭据到文件: {:?}", path);
         Ok(true)
     }
 
+    /// 刷新日志路径（仅回写凭据文件时启用）
+    fn journal_path(&self) -> Option<PathBuf> {
+        if !self.is_multiple_format {
+            return None;
+        }
+        self.cache_dir()
+            .map(|d| d.join(refresh_journal::JOURNAL_FILE))
+    }
+
+    /// refreshToken 轮换时先写刷新日志，再更新内存中的凭据
+    ///
+    /// 返回回写锁，调用方应持有它直到内存中的凭据更新完毕，
+    /// 避免并发的回写在凭据更新前清空这条日志。写日志失败只记录警告。
+    fn journal_rotation(
+        &self,
+        id: u64,
+        old: &KiroCredentials,
+        new: &KiroCredentials,
+    ) -> parking_lot::MutexGuard<'_, ()> {
+        let guard = self.persist_lock.lock();
+        if let (Some(path), Some(record)) = (self.journal_path(), RotationRecord::new(id, old, new))
+        {
+            let result = if tokio::runtime::Handle::try_current().is_ok() {
+                tokio::task::block_in_place(|| refresh_journal::append(&path, &record))
+            } else {
+                refresh_journal::append(&path, &record)
+            };
+            if let Err(e) = result {
+                tracing::warn!("凭据 #{} 写入刷新日志失败: {}", id, e);
+            }
+        }
+        guard
+    }
+
+    /// 启动时对照刷新日志检查凭据文件
+    ///
+    /// 文件中仍是轮换前旧 refreshToken 的凭据按日志恢复并写回；
+    /// 与日志对不上的凭据只报告警告，由用户确认 Token 是否仍然有效。
+    fn recover_rotations(&self) {
+        let Some(path) = self.journal_path() else {
+            return;
+        };
+        let records = refresh_journal::load(&path);
+        if records.is_empty() {
+            return;
+        }
+
+        let report = {
+            let mut entries = self.entries.lock();
+            refresh_journal::reconcile(entries.iter_mut().map(|e| &mut e.credentials), &records)
+        };
+        for id in &report.stale {
+            tracing::warn!(
+                "凭据 #{} 的 refreshToken 与刷新日志记录不一致，可能已被轮换失效，请检查凭据文件",
+                id
+            );
+        }
+        if report.recovered.is_empty() {
+            return;
+        }
+        tracing::warn!(
+            "凭据 {:?} 的 refreshToken 在上次轮换后未写回凭据文件，已从刷新日志恢复",
+            report.recovered
+        );
+        if let Err(e) = self.persist_credentials() {
+            tracing::warn!("从刷新日志恢复凭据后持久化失败: {}", e);
+        }
+    }
+
+    /// 导出所有凭据（与回写文件的内容一致，disabled 状态已同步）
+    pub fn export_credentials(&self) -> Vec<KiroCredentials> {
+        let entries = self.entries.lock();
+        entries
+            .iter()
+            .map(|e| {
+                let mut cred = e.credentials.clone();
+                cred.canonicalize_auth_method();
+                // 同步 disabled 状态到凭据对象
+                cred.disabled = e.disabled;
+                cred
+            })
+            .collect()
+    }
+
     /// 获取缓存目录（凭据文件所在目录）
     pub fn cache_dir(&self) -> Option<PathBuf> {
         self.credentials_path
@@ -1131,6 +1651,9 @@ impl MultiTokenManager {
                 entry.failure_count = 0;
                 entry.refresh_failure_count = 0;
                 entry.success_count += 1;
+                if entry.breaker.record_success(Instant::now()) {
+                    tracing::info!("凭据 #{} 探测请求成功，熔断已恢复", id);
+                }
                 entry.last_used_at = Some(Utc::now().to_rfc3339());
                 tracing::debug!(
                     "凭据 #{} API 调用成功（累计 {} 次）",
@@ -1202,6 +1725,71 @@ impl MultiTokenManager {
         result
     }
 
+    /// 报告指定凭据遇到上游 5xx / 超时错误
+    ///
+    /// 不计入连续失败（不会禁用凭据），仅累计到熔断器；熔断后该凭据在冷却期内不参与调度
+    pub fn report_upstream_error(&self, id: u64) {
+        let mut entries = self.entries.lock();
+        if let Some(entry) = entries.iter_mut().find(|e| e.id == id)
+            && entry.breaker.record_failure(Instant::now())
+        {
+            let cooldown = self
+                .config
+                .circuit_breaker
+                .map(|c| c.cooldown_secs)
+                .unwrap_or_default();
+            tracing::warn!("凭据 #{} 上游错误过多，熔断 {}s", id, cooldown);
+        }
+    }
+
+    /// 报告指定凭据被上游限流（429 且给出了 Retry-After）
+    ///
+    /// 不计入连续失败（不会禁用凭据），凭据在 `retry_after` 内不参与调度，
+    /// 冷却结束时由定时任务恢复
+    pub fn report_throttled(self: &Arc<Self>, id: u64, retry_after: StdDuration) {
+        let Ok(wait) = Duration::from_std(retry_after) else {
+            return;
+        };
+        let until = Utc::now() + wait;
+        {
+            let mut entries = self.entries.lock();
+            let Some(entry) = entries.iter_mut().find(|e| e.id == id) else {
+                return;
+            };
+            // 已有更晚结束的冷却时保留原冷却
+            if entry.cooldown_until.is_some_and(|current| current >= until) {
+                return;
+            }
+            entry.cooldown_until = Some(until);
+        }
+        tracing::warn!(
+            "凭据 #{} 被上游限流，冷却 {}s（至 {}）",
+            id,
+            retry_after.as_secs_f64().ceil(),
+            until.to_rfc3339()
+        );
+
+        let manager = Arc::downgrade(self);
+        tokio::spawn(async move {
+            tokio::time::sleep(retry_after).await;
+            if let Some(manager) = manager.upgrade() {
+                manager.end_cooldown(id, until);
+            }
+        });
+    }
+
+    /// 限流冷却到期：清除冷却状态（冷却已被延长时忽略）
+    fn end_cooldown(&self, id: u64, until: DateTime<Utc>) {
+        let mut entries = self.entries.lock();
+        if let Some(entry) = entries
+            .iter_mut()
+            .find(|e| e.id == id && e.cooldown_until == Some(until))
+        {
+            entry.cooldown_until = None;
+            tracing::info!("凭据 #{} 限流冷却结束，恢复调度", id);
+        }
+    }
+
     /// 报告指定凭据额度已用尽
     ///
     /// 用于处理 402 Payment Required 且 reason 为 `MONTHLY_REQUEST_COUNT` 的场景：
@@ -1370,10 +1958,11 @@ impl MultiTokenManager {
         let entries = self.entries.lock();
         let mut current_id = self.current_id.lock();
 
-        // 选择优先级最高的未禁用凭据（排除当前凭据）
+        // 选择优先级最高的可调度凭据（排除当前凭据）
+        let now = Utc::now();
         if let Some(next) = entries
             .iter()
-            .filter(|e| !e.disabled && e.id != *current_id)
+            .filter(|e| e.is_schedulable(now) && e.id != *current_id)
             .min_by_key(|e| e.credentials.priority)
         {
             *current_id = next.id;
@@ -1398,6 +1987,11 @@ impl MultiTokenManager {
         let entries = self.entries.lock();
         let current_id = *self.current_id.lock();
         let available = entries.iter().filter(|e| !e.disabled).count();
+        let now = Utc::now();
+        let pinned = self
+            .affinity
+            .as_ref()
+            .map(|a| a.lock().counts(Instant::now()));
 
         ManagerSnapshot {
             entries: entries
@@ -1444,6 +2038,7 @@ impl MultiTokenManager {
                     last_used_at: e.last_used_at.clone(),
                     has_proxy: e.credentials.proxy_url.is_some(),
                     proxy_url: e.credentials.proxy_url.clone(),
+                    proxy_status: proxy_health::status(&e.credentials.proxy_candidates()),
                     refresh_failure_count: e.refresh_failure_count,
                     disabled_reason: e.disabled_reason.map(|r| match r {
                         DisabledReason::Manual => "Manual",
@@ -1454,6 +2049,19 @@ impl MultiTokenManager {
                         DisabledReason::InvalidConfig => "InvalidConfig",
                     }.to_string()),
                     endpoint: e.credentials.endpoint.clone(),
+                    schedule: e.credentials.schedule.clone(),
+                    in_schedule: e.schedule.is_active(now),
+                    group: e.credentials.group.clone(),
+                    weight: e.credentials.effective_weight(),
+                    circuit_breaker: e.breaker.snapshot(Instant::now()),
+                    cooldown_until: e
+                        .cooldown_until
+                        .filter(|_| e.is_cooling_down(now))
+                        .map(|until| until.to_rfc3339()),
+                    pinned_sessions: pinned
+                        .as_ref()
+                        .map(|p| p.get(&e.id).copied().unwrap_or(0)),
+                    last_refresh: e.refresh.snapshot(now),
                 })
                 .collect(),
             current_id,
@@ -1505,39 +2113,360 @@ impl MultiTokenManager {
         Ok(())
     }
 
-    /// 重置凭据失败计数并重新启用（Admin API）
-    pub fn reset_and_enable(&self, id: u64) -> anyhow::Result<()> {
+    /// 设置凭据可用时段（Admin API）
+    ///
+    /// 规则为空表示始终可用。规则无效时返回错误且不修改现有配置。
+    pub fn set_schedule(&self, id: u64, rules: Vec<String>) -> anyhow::Result<()> {
+        let schedule = Schedule::parse(&rules).map_err(|e| anyhow::anyhow!("可用时段无效: {}", e))?;
         {
             let mut entries = self.entries.lock();
             let entry = entries
                 .iter_mut()
                 .find(|e| e.id == id)
                 .ok_or_else(|| anyhow::anyhow!("凭据不存在: {}", id))?;
-            if entry.disabled_reason == Some(DisabledReason::InvalidConfig) {
-                anyhow::bail!(
-                    "凭据 #{} 因配置无效被禁用，请修正配置后重启服务",
-                    id
-                );
-            }
-            entry.failure_count = 0;
-            entry.refresh_failure_count = 0;
-            entry.disabled = false;
-            entry.disabled_reason = None;
+            entry.credentials.schedule = rules;
+            entry.schedule = schedule;
         }
+        // 当前凭据可能已不在可用时段内，立即重新选择
+        self.select_highest_priority();
         // 持久化更改
         self.persist_credentials()?;
         Ok(())
     }
 
-    /// 获取指定凭据的使用额度（Admin API）
-    pub async fn get_usage_limits_for(&self, id: u64) -> anyhow::Result<UsageLimitsResponse> {
-        let credentials = {
-            let entries = self.entries.lock();
-            entries
-                .iter()
-                .find(|e| e.id == id)
-                .map(|e| e.credentials.clone())
-                .ok_or_else(|| anyhow::anyhow!("凭据不存在: {}", id))?
+    /// 设置凭据分组（Admin API）
+    ///
+    /// 分组为空表示取消分组，返回生效的分组名
+    pub fn set_group(&self, id: u64, group: Option<String>) -> anyhow::Result<Option<String>> {
+        let group = group
+            .map(|g| g.trim().to_string())
+            .filter(|g| !g.is_empty());
+        {
+            let mut entries = self.entries.lock();
+            let entry = entries
+                .iter_mut()
+                .find(|e| e.id == id)
+                .ok_or_else(|| anyhow::anyhow!("凭据不存在: {}", id))?;
+            entry.credentials.group = group.clone();
+        }
+        self.persist_credentials()?;
+        Ok(group)
+    }
+
+    /// 设置凭据在 balanced 模式下的权重（Admin API）
+    pub fn set_weight(&self, id: u64, weight: u32) -> anyhow::Result<()> {
+        if weight == 0 {
+            anyhow::bail!("权重必须大于 0");
+        }
+        {
+            let mut entries = self.entries.lock();
+            let entry = entries
+                .iter_mut()
+                .find(|e| e.id == id)
+                .ok_or_else(|| anyhow::anyhow!("凭据不存在: {}", id))?;
+            // 默认权重不写入凭据文件
+            entry.credentials.weight = (weight != 1).then_some(weight);
+        }
+        self.persist_credentials()?;
+        Ok(())
+    }
+
+    /// 重置凭据失败计数并重新启用（Admin API）
+    pub fn reset_and_enable(&self, id: u64) -> anyhow::Result<()> {
+        {
+            let mut entries = self.entries.lock();
+            let entry = entries
+                .iter_mut()
+                .find(|e| e.id == id)
+                .ok_or_else(|| anyhow::anyhow!("凭据不存在: {}", id))?;
+            if entry.disabled_reason == Some(DisabledReason::InvalidConfig) {
+                anyhow::bail!(
+                    "凭据 #{} 因配置无效被禁用，请修正配置后重启服务",
+                    id
+                );
+            }
+            entry.failure_count = 0;
+            entry.refresh_failure_count = 0;
+            entry.disabled = false;
+            entry.disabled_reason = None;
+        }
+        // 持久化更改
+        self.persist_credentials()?;
+        Ok(())
+    }
+
+    /// 探测自动禁用的凭据，恢复已可用者（健康检查调度器调用）
+    ///
+    /// 仅探测可能自愈的凭据（连续失败、刷新失败、额度用尽），
+    /// 手动禁用、Refresh Token 失效和配置无效的凭据不会被自动启用。
+    /// 探测方式为获取使用额度（必要时刷新 Token）；额度用尽的凭据还需剩余额度大于 0。
+    ///
+    /// # 返回
+    /// 恢复的凭据数量
+    pub async fn probe_disabled_credentials(&self) -> usize {
+        let candidates: Vec<(u64, DisabledReason)> = {
+            let entries = self.entries.lock();
+            entries
+                .iter()
+                .filter(|e| e.disabled)
+                .filter_map(|e| e.disabled_reason.map(|r| (e.id, r)))
+                .filter(|(_, reason)| reason.is_probe_candidate())
+                .collect()
+        };
+        if candidates.is_empty() {
+            return 0;
+        }
+
+        tracing::info!("健康检查：探测 {} 个自动禁用的凭据", candidates.len());
+        let mut recovered = 0;
+        for (id, reason) in candidates {
+            let usage = match self.get_usage_limits_for(id).await {
+                Ok(usage) => usage,
+                Err(e) => {
+                    tracing::debug!("健康检查：凭据 #{} 仍不可用: {}", id, e);
+                    continue;
+                }
+            };
+            if reason == DisabledReason::QuotaExceeded
+                && usage.usage_limit() - usage.current_usage() <= 0.0
+            {
+                tracing::debug!("健康检查：凭据 #{} 额度仍未恢复", id);
+                continue;
+            }
+
+            let enabled = {
+                let mut entries = self.entries.lock();
+                match entries.iter_mut().find(|e| e.id == id) {
+                    // 探测期间可能已被手动修改，仅在禁用原因未变时启用
+                    Some(entry) if entry.disabled && entry.disabled_reason == Some(reason) => {
+                        entry.disabled = false;
+                        entry.disabled_reason = None;
+                        entry.failure_count = 0;
+                        entry.refresh_failure_count = 0;
+                        true
+                    }
+                    _ => false,
+                }
+            };
+            if enabled {
+                tracing::info!(
+                    "健康检查：凭据 #{} 已恢复可用（原禁用原因: {:?}）",
+                    id,
+                    reason
+                );
+                recovered += 1;
+            }
+        }
+
+        if recovered > 0 {
+            self.select_highest_priority();
+            if let Err(e) = self.persist_credentials() {
+                tracing::warn!("健康检查恢复凭据后持久化失败: {}", e);
+            }
+        }
+        recovered
+    }
+
+    /// 启动禁用凭据健康检查调度器
+    ///
+    /// 每隔 `interval` 加上 `[0, jitter]` 的随机抖动执行一次 [`Self::probe_disabled_credentials`]
+    pub fn spawn_health_check(self: &Arc<Self>, interval: StdDuration, jitter: StdDuration) {
+        let manager = Arc::downgrade(self);
+        let jitter_ms = jitter.as_millis() as u64;
+        tokio::spawn(async move {
+            loop {
+                let delay = interval + StdDuration::from_millis(fastrand::u64(0..=jitter_ms));
+                tokio::time::sleep(delay).await;
+                let Some(manager) = manager.upgrade() else {
+                    break;
+                };
+                manager.probe_disabled_credentials().await;
+            }
+        });
+    }
+
+    /// 探测所有凭据级代理（含备用代理）的可用性，同一代理只探测一次
+    pub async fn probe_credential_proxies(&self) {
+        let proxies: Vec<ProxyConfig> = {
+            let entries = self.entries.lock();
+            let mut seen = HashSet::new();
+            entries
+                .iter()
+                .flat_map(|e| e.credentials.proxy_candidates())
+                .filter(|proxy| seen.insert(proxy.url.clone()))
+                .collect()
+        };
+        if proxies.is_empty() {
+            return;
+        }
+        let target = format!(
+            "https://q.{}.amazonaws.com/",
+            self.config.effective_api_region()
+        );
+        for proxy in &proxies {
+            proxy_health::probe(proxy, &target, self.config.tls_backend).await;
+        }
+    }
+
+    /// 启动凭据级代理健康探测调度器
+    pub fn spawn_proxy_health_check(self: &Arc<Self>, interval: StdDuration) {
+        let manager = Arc::downgrade(self);
+        tokio::spawn(async move {
+            loop {
+                tokio::time::sleep(interval).await;
+                let Some(manager) = manager.upgrade() else {
+                    break;
+                };
+                manager.probe_credential_proxies().await;
+            }
+        });
+    }
+
+    /// 记录一次失败的 Token 刷新（用于 Admin API 展示与预刷新退避）
+    fn record_refresh_failure(&self, id: u64, source: RefreshSource, error: &anyhow::Error) {
+        if let Some(entry) = self.entries.lock().iter_mut().find(|e| e.id == id) {
+            entry.refresh.record_failure(
+                source,
+                error.to_string(),
+                self.config.warm_refresh.as_ref(),
+                Utc::now(),
+            );
+        }
+    }
+
+    /// 预刷新即将过期的 OAuth 凭据 Token
+    ///
+    /// 跳过已禁用、API Key 凭据和处于失败退避中的凭据。失败不计入刷新失败次数
+    /// （Token 仍有效，过期前请求路径会再按需刷新），refreshToken 永久失效时立即禁用凭据。
+    ///
+    /// # 返回
+    /// 成功刷新的凭据数量
+    pub async fn warm_refresh_tokens(&self) -> usize {
+        let Some(config) = self.config.warm_refresh else {
+            return 0;
+        };
+        let is_due = |e: &CredentialEntry, now: DateTime<Utc>| {
+            !e.disabled
+                && !e.credentials.is_api_key_credential()
+                && e.credentials
+                    .expires_at
+                    .as_deref()
+                    .and_then(|at| DateTime::parse_from_rfc3339(at).ok())
+                    .is_some_and(|at| e.refresh.is_due(&config, at.with_timezone(&Utc), now))
+        };
+        let due: Vec<u64> = {
+            let entries = self.entries.lock();
+            let now = Utc::now();
+            entries
+                .iter()
+                .filter(|e| is_due(e, now))
+                .map(|e| e.id)
+                .collect()
+        };
+
+        let mut refreshed = 0;
+        for id in due {
+            let _guard = self.refresh_lock.lock().await;
+            // 获取锁后重新检查：等待期间请求路径可能已完成刷新或凭据已被禁用
+            let credentials = {
+                let entries = self.entries.lock();
+                match entries.iter().find(|e| e.id == id) {
+                    Some(e) if is_due(e, Utc::now()) => e.credentials.clone(),
+                    _ => continue,
+                }
+            };
+
+            let effective_proxy = credentials.effective_proxy(self.global_proxy().as_ref());
+            match refresh_token(&credentials, &self.config, effective_proxy.as_ref()).await {
+                Ok(new_creds) => {
+                    {
+                        let _journal = self.journal_rotation(id, &credentials, &new_creds);
+                        let mut entries = self.entries.lock();
+                        if let Some(entry) = entries.iter_mut().find(|e| e.id == id) {
+                            entry.credentials = new_creds;
+                            entry.refresh_failure_count = 0;
+                            entry
+                                .refresh
+                                .record_success(RefreshSource::Warm, Utc::now());
+                        }
+                    }
+                    if let Err(e) = self.persist_credentials() {
+                        tracing::warn!("预刷新 Token 后持久化失败: {}", e);
+                    }
+                    tracing::debug!("凭据 #{} Token 已预刷新", id);
+                    refreshed += 1;
+                }
+                Err(e) => {
+                    self.record_refresh_failure(id, RefreshSource::Warm, &e);
+                    if e.downcast_ref::<RefreshTokenInvalidError>().is_some() {
+                        tracing::warn!("预刷新：凭据 #{} refreshToken 永久失效: {}", id, e);
+                        self.report_refresh_token_invalid(id);
+                    } else {
+                        tracing::warn!("预刷新：凭据 #{} Token 刷新失败: {}", id, e);
+                    }
+                }
+            }
+        }
+        refreshed
+    }
+
+    /// 启动 Token 预刷新调度器（未配置 `warmRefresh` 时不启动）
+    pub fn spawn_warm_refresh(self: &Arc<Self>) {
+        let Some(config) = self.config.warm_refresh else {
+            return;
+        };
+        let manager = Arc::downgrade(self);
+        let interval = StdDuration::from_secs(config.check_interval_secs.max(1));
+        tokio::spawn(async move {
+            loop {
+                let Some(manager) = manager.upgrade() else {
+                    break;
+                };
+                manager.warm_refresh_tokens().await;
+                drop(manager);
+                tokio::time::sleep(interval).await;
+            }
+        });
+    }
+
+    /// 使用当前可用凭据查询 Kiro 可用模型列表
+    pub async fn fetch_available_models(&self) -> anyhow::Result<AvailableModelsResponse> {
+        let ctx = self.acquire_context(None).await?;
+        let effective_proxy = ctx.credentials.effective_proxy(self.global_proxy().as_ref());
+        list_available_models(
+            &ctx.credentials,
+            &self.config,
+            &ctx.token,
+            effective_proxy.as_ref(),
+        )
+        .await
+    }
+
+    /// 检查凭据的 refreshToken 是否完整（API Key 凭据直接通过）
+    pub fn check_refresh_token(&self, id: u64) -> anyhow::Result<()> {
+        let credentials = {
+            let entries = self.entries.lock();
+            entries
+                .iter()
+                .find(|e| e.id == id)
+                .map(|e| e.credentials.clone())
+                .ok_or_else(|| anyhow::anyhow!("凭据不存在: {}", id))?
+        };
+        if credentials.is_api_key_credential() {
+            return Ok(());
+        }
+        validate_refresh_token(&credentials)
+    }
+
+    /// 获取指定凭据的使用额度（Admin API）
+    pub async fn get_usage_limits_for(&self, id: u64) -> anyhow::Result<UsageLimitsResponse> {
+        let credentials = {
+            let entries = self.entries.lock();
+            entries
+                .iter()
+                .find(|e| e.id == id)
+                .map(|e| e.credentials.clone())
+                .ok_or_else(|| anyhow::anyhow!("凭据不存在: {}", id))?
         };
 
         // API Key 凭据直接使用 kiro_api_key，无需刷新
@@ -1563,11 +2492,12 @@ impl MultiTokenManager {
                 };
 
                 if is_token_expired(&current_creds) || is_token_expiring_soon(&current_creds) {
-                    let effective_proxy = current_creds.effective_proxy(self.proxy.as_ref());
+                    let effective_proxy = current_creds.effective_proxy(self.global_proxy().as_ref());
                     let new_creds =
                         refresh_token(&current_creds, &self.config, effective_proxy.as_ref())
                             .await?;
                     {
+                        let _journal = self.journal_rotation(id, &current_creds, &new_creds);
                         let mut entries = self.entries.lock();
                         if let Some(entry) = entries.iter_mut().find(|e| e.id == id) {
                             entry.credentials = new_creds.clone();
@@ -1601,8 +2531,9 @@ impl MultiTokenManager {
                 .ok_or_else(|| anyhow::anyhow!("凭据不存在: {}", id))?
         };
 
-        let effective_proxy = credentials.effective_proxy(self.proxy.as_ref());
+        let effective_proxy = credentials.effective_proxy(self.global_proxy().as_ref());
         let usage_limits = get_usage_limits(&credentials, &self.config, &token, effective_proxy.as_ref()).await?;
+        quota_alert::observe(id, credentials.email.as_deref(), &usage_limits);
 
         // 更新订阅等级到凭据（仅在发生变化时持久化）
         if let Some(subscription_title) = usage_limits.subscription_title() {
@@ -1664,6 +2595,8 @@ impl MultiTokenManager {
         } else {
             validate_refresh_token(&new_cred)?;
         }
+        let schedule = Schedule::parse(&new_cred.schedule)
+            .map_err(|e| anyhow::anyhow!("可用时段无效: {}", e))?;
 
         // 2. 基于哈希检测重复
         if new_cred.is_api_key_credential() {
@@ -1714,18 +2647,11 @@ impl MultiTokenManager {
         let mut validated_cred = if new_cred.is_api_key_credential() {
             new_cred.clone()
         } else {
-            let effective_proxy = new_cred.effective_proxy(self.proxy.as_ref());
+            let effective_proxy = new_cred.effective_proxy(self.global_proxy().as_ref());
             refresh_token(&new_cred, &self.config, effective_proxy.as_ref()).await?
         };
 
-        // 4. 分配新 ID
-        let new_id = {
-            let entries = self.entries.lock();
-            entries.iter().map(|e| e.id).max().unwrap_or(0) + 1
-        };
-
-        // 5. 设置 ID 并保留用户输入的元数据
-        validated_cred.id = Some(new_id);
+        // 4. 保留用户输入的元数据
         validated_cred.priority = new_cred.priority;
         validated_cred.auth_method = new_cred.auth_method.map(|m| {
             if m.eq_ignore_ascii_case("builder-id") || m.eq_ignore_ascii_case("iam") {
@@ -1744,10 +2670,15 @@ impl MultiTokenManager {
         validated_cred.proxy_url = new_cred.proxy_url;
         validated_cred.proxy_username = new_cred.proxy_username;
         validated_cred.proxy_password = new_cred.proxy_password;
+        validated_cred.fallback_proxy_urls = new_cred.fallback_proxy_urls;
         validated_cred.kiro_api_key = new_cred.kiro_api_key;
+        validated_cred.schedule = new_cred.schedule;
 
-        {
+        // 5. 分配新 ID 并插入（同一把锁内完成，避免并发添加时分配到相同 ID）
+        let new_id = {
             let mut entries = self.entries.lock();
+            let new_id = entries.iter().map(|e| e.id).max().unwrap_or(0) + 1;
+            validated_cred.id = Some(new_id);
             entries.push(CredentialEntry {
                 id: new_id,
                 credentials: validated_cred,
@@ -1757,8 +2688,13 @@ impl MultiTokenManager {
                 disabled_reason: None,
                 success_count: 0,
                 last_used_at: None,
+                schedule,
+                breaker: CircuitBreaker::new(self.config.circuit_breaker),
+                cooldown_until: None,
+                refresh: RefreshState::new(self.config.warm_refresh.as_ref()),
             });
-        }
+            new_id
+        };
 
         // 6. 持久化
         self.persist_credentials()?;
@@ -1804,6 +2740,9 @@ impl MultiTokenManager {
 
             // 删除凭据
             entries.retain(|e| e.id != id);
+            if let Some(affinity) = &self.affinity {
+                affinity.lock().unpin_credential(id);
+            }
 
             was_current
         };
@@ -1851,16 +2790,21 @@ impl MultiTokenManager {
         let _guard = self.refresh_lock.lock().await;
 
         // 无条件调用 refresh_token
-        let effective_proxy = credentials.effective_proxy(self.proxy.as_ref());
-        let new_creds =
-            refresh_token(&credentials, &self.config, effective_proxy.as_ref()).await?;
+        let effective_proxy = credentials.effective_proxy(self.global_proxy().as_ref());
+        let new_creds = refresh_token(&credentials, &self.config, effective_proxy.as_ref())
+            .await
+            .inspect_err(|e| self.record_refresh_failure(id, RefreshSource::Forced, e))?;
 
         // 更新 entries 中对应凭据
         {
+            let _journal = self.journal_rotation(id, &credentials, &new_creds);
             let mut entries = self.entries.lock();
             if let Some(entry) = entries.iter_mut().find(|e| e.id == id) {
                 entry.credentials = new_creds;
                 entry.refresh_failure_count = 0;
+                entry
+                    .refresh
+                    .record_success(RefreshSource::Forced, Utc::now());
             }
         }
 
@@ -1899,6 +2843,15 @@ impl MultiTokenManager {
         Ok(())
     }
 
+    /// 应用配置文件中的负载均衡模式（配置热加载，不回写文件）
+    pub fn apply_load_balancing_mode(&self, mode: &str) -> anyhow::Result<()> {
+        if mode != "priority" && mode != "balanced" {
+            anyhow::bail!("无效的负载均衡模式: {}", mode);
+        }
+        *self.load_balancing_mode.lock() = mode.to_string();
+        Ok(())
+    }
+
     /// 设置负载均衡模式（Admin API）
     pub fn set_load_balancing_mode(&self, mode: String) -> anyhow::Result<()> {
         // 验证模式值
@@ -1921,6 +2874,49 @@ impl MultiTokenManager {
         tracing::info!("负载均衡模式已设置为: {}", mode);
         Ok(())
     }
+
+    /// 获取模型路由规则（Admin API）
+    pub fn get_model_routes(&self) -> Vec<ModelRoute> {
+        self.model_routes.read().clone()
+    }
+
+    /// 应用配置文件中的模型路由规则（配置热加载，不回写文件）
+    pub fn apply_model_routes(&self, routes: Vec<ModelRoute>) {
+        *self.model_routes.write() = routes;
+    }
+
+    /// 设置模型路由规则（Admin API）
+    ///
+    /// 规则立即生效并写回 config.json；写回失败时恢复原规则
+    pub fn set_model_routes(&self, routes: Vec<ModelRoute>) -> anyhow::Result<()> {
+        use anyhow::Context;
+
+        if let Some(index) = routes.iter().position(|r| r.groups.is_empty()) {
+            anyhow::bail!("第 {} 条模型路由规则未指定分组", index + 1);
+        }
+
+        let previous = std::mem::replace(&mut *self.model_routes.write(), routes.clone());
+
+        let Some(config_path) = self.config.config_path().map(|p| p.to_path_buf()) else {
+            tracing::warn!("配置文件路径未知，模型路由规则仅在当前进程生效");
+            return Ok(());
+        };
+        let persisted = Config::load(&config_path)
+            .with_context(|| format!("重新加载配置失败: {}", config_path.display()))
+            .and_then(|mut config| {
+                config.model_routes = routes;
+                config
+                    .save()
+                    .with_context(|| format!("持久化模型路由规则失败: {}", config_path.display()))
+            });
+        if let Err(err) = persisted {
+            *self.model_routes.write() = previous;
+            return Err(err);
+        }
+
+        tracing::info!("模型路由规则已更新");
+        Ok(())
+    }
 }
 
 impl Drop for MultiTokenManager {
@@ -2211,6 +3207,56 @@ mod tests {
         assert_eq!(manager.available_count(), 1);
     }
 
+    #[test]
+    fn test_multi_token_manager_skips_credential_outside_schedule() {
+        let config = Config::default();
+        // 仅在三天后那个星期几可用，当前时刻必然不在时段内
+        let other_day = (Utc::now() + Duration::days(3)).format("%a").to_string();
+
+        let night_cred = KiroCredentials {
+            priority: 0,
+            schedule: vec![format!("{} 00:00-24:00 UTC", other_day)],
+            ..Default::default()
+        };
+        let fallback = KiroCredentials {
+            priority: 1,
+            ..Default::default()
+        };
+
+        let manager =
+            MultiTokenManager::new(config, vec![night_cred, fallback], None, None, false).unwrap();
+        // 不在时段内的凭据仍计为可用（未禁用），但不会被选中
+        assert_eq!(manager.available_count(), 2);
+        let (id, _) = manager.select_next_credential(None, None, None).unwrap();
+        assert_eq!(id, 2);
+
+        let snapshot = manager.snapshot();
+        assert!(!snapshot.entries[0].in_schedule);
+        assert!(snapshot.entries[1].in_schedule);
+
+        // 清除时段后恢复按优先级选择
+        manager.set_schedule(1, Vec::new()).unwrap();
+        assert_eq!(
+            manager.select_next_credential(None, None, None).unwrap().0,
+            1
+        );
+    }
+
+    #[test]
+    fn test_multi_token_manager_invalid_schedule() {
+        let config = Config::default();
+        let cred = KiroCredentials {
+            schedule: vec!["Someday 00:00-01:00".to_string()],
+            ..Default::default()
+        };
+
+        let manager = MultiTokenManager::new(config, vec![cred], None, None, false).unwrap();
+        // 启动时规则无效 → 自动禁用
+        assert_eq!(manager.available_count(), 0);
+        // Admin 设置无效规则 → 返回错误
+        assert!(manager.set_schedule(1, vec!["Mon 9-10".to_string()]).is_err());
+    }
+
     #[test]
     fn test_multi_token_manager_report_failure() {
         let config = Config::default();
@@ -2355,6 +3401,288 @@ mod tests {
         assert_eq!(ctx.token, "good-token");
     }
 
+    #[tokio::test]
+    async fn test_multi_token_manager_circuit_breaker_routes_elsewhere() {
+        let mut config = Config::default();
+        config.circuit_breaker = Some(crate::model::config::CircuitBreakerConfig {
+            failure_threshold: 2,
+            window_secs: 60,
+            cooldown_secs: 60,
+        });
+
+        let creds: Vec<KiroCredentials> = (0..2)
+            .map(|priority| KiroCredentials {
+                priority,
+                access_token: Some(format!("token-{}", priority)),
+                expires_at: Some((Utc::now() + Duration::hours(1)).to_rfc3339()),
+                ..Default::default()
+            })
+            .collect();
+        let manager = MultiTokenManager::new(config, creds, None, None, false).unwrap();
+
+        manager.report_upstream_error(1);
+        assert_eq!(manager.acquire_context(None).await.unwrap().id, 1);
+        manager.report_upstream_error(1);
+        assert_eq!(manager.acquire_context(None).await.unwrap().id, 2);
+
+        let snapshot = manager.snapshot();
+        let breaker = snapshot.entries[0].circuit_breaker.as_ref().unwrap();
+        assert_eq!(breaker.state, crate::kiro::circuit_breaker::BreakerState::Open);
+        assert!(!snapshot.entries[0].disabled);
+    }
+
+    #[tokio::test]
+    async fn test_multi_token_manager_throttle_cooldown() {
+        let creds: Vec<KiroCredentials> = (0..2)
+            .map(|priority| KiroCredentials {
+                priority,
+                access_token: Some(format!("token-{}", priority)),
+                expires_at: Some((Utc::now() + Duration::hours(1)).to_rfc3339()),
+                ..Default::default()
+            })
+            .collect();
+        let manager =
+            Arc::new(MultiTokenManager::new(Config::default(), creds, None, None, false).unwrap());
+
+        manager.report_throttled(1, StdDuration::from_millis(200));
+        assert_eq!(manager.acquire_context(None).await.unwrap().id, 2);
+        let snapshot = manager.snapshot();
+        assert!(snapshot.entries[0].cooldown_until.is_some());
+        assert_eq!(snapshot.entries[0].failure_count, 0);
+
+        // 全部冷却中：返回限流错误并给出最早恢复的等待时间
+        manager.report_throttled(2, StdDuration::from_secs(30));
+        let Err(err) = manager.acquire_context(None).await else {
+            panic!("全部冷却中时不应分配凭据");
+        };
+        let throttled = err.downcast_ref::<UpstreamThrottledError>().unwrap();
+        assert_eq!(throttled.retry_after, Some(1));
+
+        tokio::time::sleep(StdDuration::from_millis(300)).await;
+        assert_eq!(manager.acquire_context(None).await.unwrap().id, 1);
+        assert!(manager.snapshot().entries[0].cooldown_until.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_multi_token_manager_model_routes_spill_over_groups() {
+        let mut config = Config::default();
+        config.model_routes = vec![ModelRoute {
+            models: vec!["claude-opus*".to_string()],
+            groups: vec!["opus-a".to_string(), "opus-b".to_string()],
+            ..Default::default()
+        }];
+
+        // #1 未分组且优先级最高，#2 属于 opus-a，#3 属于 opus-b
+        let creds: Vec<KiroCredentials> = [None, Some("opus-a"), Some("opus-b")]
+            .into_iter()
+            .enumerate()
+            .map(|(priority, group)| KiroCredentials {
+                priority: priority as u32,
+                access_token: Some(format!("token-{}", priority)),
+                expires_at: Some((Utc::now() + Duration::hours(1)).to_rfc3339()),
+                group: group.map(str::to_string),
+                ..Default::default()
+            })
+            .collect();
+        let manager = MultiTokenManager::new(config, creds, None, None, false).unwrap();
+        let opus = Some("claude-opus-4.5");
+
+        // 未命中路由的请求不受分组限制
+        assert_eq!(manager.acquire_context(None).await.unwrap().id, 1);
+        assert_eq!(manager.acquire_context(opus).await.unwrap().id, 2);
+        // 路由请求不影响 current_id
+        assert_eq!(manager.snapshot().current_id, 1);
+
+        // 首选分组耗尽后溢出到下一个分组
+        manager.set_disabled(2, true).unwrap();
+        assert_eq!(manager.acquire_context(opus).await.unwrap().id, 3);
+
+        // 所有路由分组耗尽时不回退到未分组凭据
+        manager.set_disabled(3, true).unwrap();
+        let err = match manager.acquire_context(opus).await {
+            Ok(ctx) => panic!("unexpected credential #{}", ctx.id),
+            Err(e) => e.to_string(),
+        };
+        assert!(err.contains("opus-a"), "{}", err);
+        assert_eq!(
+            manager.acquire_context(Some("claude-sonnet-4.5")).await.unwrap().id,
+            1
+        );
+    }
+
+    #[test]
+    fn test_route_groups_matches_first_rule() {
+        let routes = vec![
+            ModelRoute {
+                models: vec!["Claude-Haiku-4.5".to_string()],
+                groups: vec!["haiku-only".to_string()],
+                ..Default::default()
+            },
+            ModelRoute {
+                models: Vec::new(),
+                groups: vec!["default".to_string()],
+                ..Default::default()
+            },
+        ];
+        let hints = RouteHints::default();
+        assert_eq!(
+            route_groups(&routes, Some("claude-haiku-4.5"), hints),
+            Some(vec!["haiku-only".to_string()])
+        );
+        assert_eq!(
+            route_groups(&routes, Some("claude-sonnet-4.5"), hints),
+            Some(vec!["default".to_string()])
+        );
+        assert_eq!(route_groups(&routes, None, hints), None);
+        assert_eq!(route_groups(&[], Some("claude-sonnet-4.5"), hints), None);
+    }
+
+    #[test]
+    fn test_route_groups_matches_lightweight_requests() {
+        let routes = vec![ModelRoute {
+            models: vec!["claude-haiku*".to_string()],
+            max_messages: Some(1),
+            max_tokens: Some(512),
+            groups: vec!["low-priority".to_string()],
+        }];
+        let model = Some("claude-haiku-4.5");
+        let light = RouteHints {
+            message_count: Some(1),
+            max_tokens: Some(512),
+        };
+        assert_eq!(
+            route_groups(&routes, model, light),
+            Some(vec!["low-priority".to_string()])
+        );
+        let long = RouteHints {
+            message_count: Some(3),
+            ..light
+        };
+        assert_eq!(route_groups(&routes, model, long), None);
+        let large = RouteHints {
+            max_tokens: Some(32000),
+            ..light
+        };
+        assert_eq!(route_groups(&routes, model, large), None);
+        // 未提供请求特征时不匹配带条件的规则
+        assert_eq!(route_groups(&routes, model, RouteHints::default()), None);
+    }
+
+    #[tokio::test]
+    async fn test_multi_token_manager_balanced_respects_weights() {
+        let mut config = Config::default();
+        config.load_balancing_mode = "balanced".to_string();
+
+        let creds: Vec<KiroCredentials> = [Some(3), None]
+            .into_iter()
+            .enumerate()
+            .map(|(priority, weight)| KiroCredentials {
+                priority: priority as u32,
+                weight,
+                access_token: Some(format!("token-{}", priority)),
+                expires_at: Some((Utc::now() + Duration::hours(1)).to_rfc3339()),
+                ..Default::default()
+            })
+            .collect();
+        let manager = MultiTokenManager::new(config, creds, None, None, false).unwrap();
+
+        let mut counts = [0; 2];
+        for _ in 0..8 {
+            let id = manager.acquire_context(None).await.unwrap().id;
+            manager.report_success(id);
+            counts[id as usize - 1] += 1;
+        }
+        assert_eq!(counts, [6, 2]);
+
+        manager.set_weight(2, 5).unwrap();
+        assert!(manager.set_weight(2, 0).is_err());
+        assert_eq!(manager.snapshot().entries[1].weight, 5);
+    }
+
+    #[tokio::test]
+    async fn test_multi_token_manager_request_region_matches_profile_arn() {
+        let creds: Vec<KiroCredentials> = ["us-east-1", "eu-central-1"]
+            .into_iter()
+            .enumerate()
+            .map(|(priority, region)| KiroCredentials {
+                priority: priority as u32,
+                profile_arn: Some(format!(
+                    "arn:aws:codewhisperer:{}:699475941385:profile/P{}",
+                    region, priority
+                )),
+                access_token: Some(format!("token-{}", priority)),
+                expires_at: Some((Utc::now() + Duration::hours(1)).to_rfc3339()),
+                ..Default::default()
+            })
+            .collect();
+        let manager = MultiTokenManager::new(Config::default(), creds, None, None, false).unwrap();
+
+        let acquire =
+            |region| manager.acquire_context_for_session(None, RouteHints::default(), None, region);
+        let ctx = acquire(Some("eu-central-1")).await.unwrap();
+        assert_eq!(ctx.id, 2);
+        assert_eq!(ctx.credentials.profile_region(), Some("eu-central-1"));
+        // 指定 Region 的请求不改变 priority 模式的当前凭据
+        assert_eq!(acquire(None).await.unwrap().id, 1);
+        assert_eq!(acquire(Some("us-east-1")).await.unwrap().id, 1);
+
+        let Err(err) = acquire(Some("ap-southeast-1")).await else {
+            panic!("没有 profileArn 属于该 Region 的凭据时应返回错误");
+        };
+        assert!(err.to_string().contains("ap-southeast-1"));
+    }
+
+    #[tokio::test]
+    async fn test_multi_token_manager_session_affinity_repins_when_disabled() {
+        let mut config = Config::default();
+        config.load_balancing_mode = "balanced".to_string();
+        config.session_affinity = Some(crate::model::config::SessionAffinityConfig {
+            ttl_secs: 60,
+            max_sessions: 100,
+        });
+
+        let creds: Vec<KiroCredentials> = (0..2)
+            .map(|priority| KiroCredentials {
+                priority,
+                access_token: Some(format!("token-{}", priority)),
+                expires_at: Some((Utc::now() + Duration::hours(1)).to_rfc3339()),
+                ..Default::default()
+            })
+            .collect();
+        let manager = MultiTokenManager::new(config, creds, None, None, false).unwrap();
+
+        let first = manager
+            .acquire_context_for_session(None, RouteHints::default(), Some("s1"), None)
+            .await
+            .unwrap()
+            .id;
+        // balanced 模式下凭据 #1 成功次数更多，无绑定的请求会选择另一个凭据
+        manager.report_success(first);
+        for _ in 0..3 {
+            let ctx = manager
+                .acquire_context_for_session(None, RouteHints::default(), Some("s1"), None)
+                .await
+                .unwrap();
+            assert_eq!(ctx.id, first);
+        }
+        assert_ne!(manager.acquire_context(None).await.unwrap().id, first);
+        assert_eq!(manager.snapshot().entries[0].pinned_sessions, Some(1));
+
+        manager.set_disabled(first, true).unwrap();
+        let repinned = manager
+            .acquire_context_for_session(None, RouteHints::default(), Some("s1"), None)
+            .await
+            .unwrap()
+            .id;
+        assert_ne!(repinned, first);
+        manager.set_disabled(first, false).unwrap();
+        let ctx = manager
+            .acquire_context_for_session(None, RouteHints::default(), Some("s1"), None)
+            .await
+            .unwrap();
+        assert_eq!(ctx.id, repinned);
+    }
+
     #[test]
     fn test_multi_token_manager_report_refresh_failure() {
         let config = Config::default();
@@ -2444,6 +3772,29 @@ mod tests {
         assert_eq!(manager.available_count(), 0);
     }
 
+    #[test]
+    fn test_disabled_reason_probe_candidates() {
+        assert!(DisabledReason::TooManyFailures.is_probe_candidate());
+        assert!(DisabledReason::TooManyRefreshFailures.is_probe_candidate());
+        assert!(DisabledReason::QuotaExceeded.is_probe_candidate());
+        assert!(!DisabledReason::Manual.is_probe_candidate());
+        assert!(!DisabledReason::InvalidRefreshToken.is_probe_candidate());
+        assert!(!DisabledReason::InvalidConfig.is_probe_candidate());
+    }
+
+    #[tokio::test]
+    async fn test_probe_disabled_credentials_skips_manual_disable() {
+        let config = Config::default();
+        let manager =
+            MultiTokenManager::new(config, vec![KiroCredentials::default()], None, None, false)
+                .unwrap();
+        manager.set_disabled(1, true).unwrap();
+
+        // 手动禁用的凭据不参与探测（不会发起网络请求）
+        assert_eq!(manager.probe_disabled_credentials().await, 0);
+        assert_eq!(manager.available_count(), 0);
+    }
+
     // ============ 凭据级 Region 优先级测试 ============
 
     #[test]
@@ -0,0 +1,269 @@
+//! 凭据验证报告
+//!
+//! 并发查询所有凭据的使用额度（必要时刷新 Token），按结果分类：
+//! - `ok`：可正常使用
+//! - `denied`：上游拒绝（401/403，如账号被封禁或权限不足）
+//! - `invalid`：refreshToken 缺失、被截断或已失效
+//! - `error`：网络或上游临时错误，无法判断
+//!
+//! 开启 `validateCredentialsOnStartup` 后启动时在后台执行一次并输出汇总表；
+//! 最近一次报告可通过 `GET /api/admin/credentials/validation-report` 查看。
+
+use std::sync::Arc;
+use std::time::Instant;
+
+use chrono::Utc;
+use futures::StreamExt;
+use parking_lot::RwLock;
+use serde::Serialize;
+
+use crate::kiro::token_manager::{
+    MultiTokenManager, RefreshTokenInvalidError, UsageLimitsStatusError,
+};
+
+/// 同时验证的凭据数
+const VALIDATION_CONCURRENCY: usize = 8;
+
+static LATEST: RwLock<Option<ValidationReport>> = RwLock::new(None);
+
+/// 单个凭据的验证结论
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ValidationStatus {
+    Ok,
+    Denied,
+    Invalid,
+    Error,
+}
+
+impl ValidationStatus {
+    fn as_str(self) -> &'static str {
+        match self {
+            Self::Ok => "ok",
+            Self::Denied => "denied",
+            Self::Invalid => "invalid",
+            Self::Error => "error",
+        }
+    }
+}
+
+/// 单个凭据的验证结果
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CredentialValidation {
+    pub id: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub email: Option<String>,
+    /// 验证时凭据是否处于禁用状态
+    pub disabled: bool,
+    pub status: ValidationStatus,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub subscription_title: Option<String>,
+    /// 失败原因
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub message: Option<String>,
+}
+
+/// 凭据验证报告
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ValidationReport {
+    /// 验证完成时间（RFC3339 格式）
+    pub validated_at: String,
+    pub duration_ms: u64,
+    pub total: usize,
+    pub ok: usize,
+    pub denied: usize,
+    pub invalid: usize,
+    pub error: usize,
+    pub results: Vec<CredentialValidation>,
+}
+
+impl ValidationReport {
+    fn new(results: Vec<CredentialValidation>, duration_ms: u64) -> Self {
+        let count = |status| results.iter().filter(|r| r.status == status).count();
+        Self {
+            validated_at: Utc::now().to_rfc3339(),
+            duration_ms,
+            total: results.len(),
+            ok: count(ValidationStatus::Ok),
+            denied: count(ValidationStatus::Denied),
+            invalid: count(ValidationStatus::Invalid),
+            error: count(ValidationStatus::Error),
+            results,
+        }
+    }
+
+    /// 渲染为日志中输出的汇总表
+    pub fn table(&self) -> String {
+        let mut lines = vec![format!(
+            "凭据验证报告：共 {}，可用 {}，被拒绝 {}，无效 {}，错误 {}（耗时 {}ms）",
+            self.total, self.ok, self.denied, self.invalid, self.error, self.duration_ms
+        )];
+        lines.push(format!(
+            "  {:<6} {:<8} {:<20} {}",
+            "ID", "STATUS", "SUBSCRIPTION", "EMAIL / MESSAGE"
+        ));
+        for r in &self.results {
+            let id = if r.disabled {
+                format!("#{}*", r.id)
+            } else {
+                format!("#{}", r.id)
+            };
+            let detail = match (&r.email, &r.message) {
+                (Some(email), Some(message)) => format!("{} - {}", email, message),
+                (Some(email), None) => email.clone(),
+                (None, Some(message)) => message.clone(),
+                (None, None) => "-".to_string(),
+            };
+            lines.push(format!(
+                "  {:<6} {:<8} {:<20} {}",
+                id,
+                r.status.as_str(),
+                r.subscription_title.as_deref().unwrap_or("-"),
+                detail
+            ));
+        }
+        lines.join("\n")
+    }
+}
+
+/// 根据查询额度的错误判断验证结论
+fn classify(error: &anyhow::Error) -> ValidationStatus {
+    if error.downcast_ref::<RefreshTokenInvalidError>().is_some() {
+        return ValidationStatus::Invalid;
+    }
+    match error.downcast_ref::<UsageLimitsStatusError>() {
+        Some(e) if matches!(e.status, 401 | 403) => ValidationStatus::Denied,
+        _ => ValidationStatus::Error,
+    }
+}
+
+async fn validate_one(
+    token_manager: &MultiTokenManager,
+    id: u64,
+    email: Option<String>,
+    disabled: bool,
+) -> CredentialValidation {
+    let mut result = CredentialValidation {
+        id,
+        email,
+        disabled,
+        status: ValidationStatus::Ok,
+        subscription_title: None,
+        message: None,
+    };
+    if let Err(e) = token_manager.check_refresh_token(id) {
+        result.status = ValidationStatus::Invalid;
+        result.message = Some(e.to_string());
+        return result;
+    }
+    match token_manager.get_usage_limits_for(id).await {
+        Ok(usage) => {
+            result.subscription_title = usage.subscription_title().map(str::to_string);
+        }
+        Err(e) => {
+            result.status = classify(&e);
+            result.message = Some(e.to_string());
+        }
+    }
+    result
+}
+
+/// 并发验证所有凭据（含已禁用的），保存并返回报告
+pub async fn run(token_manager: &MultiTokenManager) -> ValidationReport {
+    let started = Instant::now();
+    let entries = token_manager.snapshot().entries;
+    let mut results: Vec<CredentialValidation> = futures::stream::iter(entries)
+        .map(|e| validate_one(token_manager, e.id, e.email, e.disabled))
+        .buffer_unordered(VALIDATION_CONCURRENCY)
+        .collect()
+        .await;
+    results.sort_by_key(|r| r.id);
+
+    let report = ValidationReport::new(results, started.elapsed().as_millis() as u64);
+    if report.ok == report.total {
+        tracing::info!("{}", report.table());
+    } else {
+        tracing::warn!("{}", report.table());
+    }
+    *LATEST.write() = Some(report.clone());
+    report
+}
+
+/// 最近一次验证报告
+pub fn latest() -> Option<ValidationReport> {
+    LATEST.read().clone()
+}
+
+/// 启动后在后台执行一次验证（不阻塞服务启动）
+pub fn spawn_startup(token_manager: &Arc<MultiTokenManager>) {
+    let token_manager = Arc::clone(token_manager);
+    tokio::spawn(async move {
+        run(&token_manager).await;
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_classify_errors() {
+        let denied = anyhow::Error::from(UsageLimitsStatusError {
+            status: 403,
+            message: "权限不足".to_string(),
+        });
+        assert_eq!(classify(&denied), ValidationStatus::Denied);
+
+        let throttled = anyhow::Error::from(UsageLimitsStatusError {
+            status: 429,
+            message: "限流".to_string(),
+        });
+        assert_eq!(classify(&throttled), ValidationStatus::Error);
+
+        let invalid = anyhow::Error::from(RefreshTokenInvalidError {
+            message: "invalid_grant".to_string(),
+        });
+        assert_eq!(classify(&invalid), ValidationStatus::Invalid);
+
+        assert_eq!(
+            classify(&anyhow::anyhow!("connection reset")),
+            ValidationStatus::Error
+        );
+    }
+
+    #[test]
+    fn test_report_counts_and_table() {
+        let result = |id, status, disabled| CredentialValidation {
+            id,
+            email: Some(format!("user{}@example.com", id)),
+            disabled,
+            status,
+            subscription_title: None,
+            message: None,
+        };
+        let report = ValidationReport::new(
+            vec![
+                result(1, ValidationStatus::Ok, false),
+                result(2, ValidationStatus::Denied, false),
+                result(3, ValidationStatus::Invalid, true),
+            ],
+            12,
+        );
+        assert_eq!(
+            (
+                report.total,
+                report.ok,
+                report.denied,
+                report.invalid,
+                report.error
+            ),
+            (3, 1, 1, 1, 0)
+        );
+        let table = report.table();
+        assert!(table.contains("#3*"));
+        assert!(table.contains("denied"));
+        assert_eq!(table.lines().count(), 5);
+    }
+}
@@ -0,0 +1,180 @@
+//! Token 预刷新
+//!
+//! 后台定期检查 OAuth 凭据，在 accessToken 过期前 `marginSecs`（再加上每个凭据固定的随机抖动）
+//! 主动刷新，避免请求路径上的同步刷新带来首个请求延迟和并发刷新竞争。
+//! 刷新失败后按检查间隔逐次翻倍退避，Token 仍有效期间不影响凭据调度。
+
+use chrono::{DateTime, Duration, Utc};
+use serde::Serialize;
+
+use crate::model::config::WarmRefreshConfig;
+
+/// 触发刷新的来源
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum RefreshSource {
+    /// 后台预刷新
+    Warm,
+    /// 请求时发现 Token 即将过期
+    OnDemand,
+    /// Admin API 强制刷新
+    Forced,
+}
+
+/// 最近一次刷新的结果（用于 Admin API）
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RefreshStatus {
+    /// 刷新时间（RFC3339 格式）
+    pub at: String,
+    pub source: RefreshSource,
+    pub success: bool,
+    /// 失败原因
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    /// 连续失败次数
+    pub consecutive_failures: u32,
+    /// 退避结束、允许再次预刷新的时间（RFC3339 格式，未在退避中时为 None）
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub retry_at: Option<String>,
+}
+
+/// 单个凭据的刷新状态
+#[derive(Debug, Clone, Default)]
+pub struct RefreshState {
+    /// 在 marginSecs 之外额外提前的时间
+    jitter: Duration,
+    failures: u32,
+    retry_at: Option<DateTime<Utc>>,
+    last: Option<(DateTime<Utc>, RefreshSource, Option<String>)>,
+}
+
+impl RefreshState {
+    /// 创建刷新状态，在 `[0, jitterSecs]` 内为凭据抽取固定的抖动
+    pub fn new(config: Option<&WarmRefreshConfig>) -> Self {
+        let jitter_secs = config.map_or(0, |c| c.jitter_secs);
+        Self {
+            jitter: Duration::seconds(fastrand::u64(0..=jitter_secs) as i64),
+            ..Default::default()
+        }
+    }
+
+    /// 指定时刻是否应预刷新（Token 进入提前量且不在失败退避中）
+    pub fn is_due(
+        &self,
+        config: &WarmRefreshConfig,
+        expires_at: DateTime<Utc>,
+        now: DateTime<Utc>,
+    ) -> bool {
+        let margin = Duration::seconds(config.margin_secs as i64) + self.jitter;
+        expires_at - margin <= now && self.retry_at.is_none_or(|at| now >= at)
+    }
+
+    /// 记录一次成功的刷新，清除退避
+    pub fn record_success(&mut self, source: RefreshSource, now: DateTime<Utc>) {
+        self.failures = 0;
+        self.retry_at = None;
+        self.last = Some((now, source, None));
+    }
+
+    /// 记录一次失败的刷新，退避时间从检查间隔开始逐次翻倍，不超过 maxBackoffSecs
+    pub fn record_failure(
+        &mut self,
+        source: RefreshSource,
+        error: String,
+        config: Option<&WarmRefreshConfig>,
+        now: DateTime<Utc>,
+    ) {
+        self.failures = self.failures.saturating_add(1);
+        self.retry_at = config.map(|c| {
+            let backoff = c
+                .check_interval_secs
+                .saturating_mul(1 << (self.failures - 1).min(16))
+                .min(c.max_backoff_secs);
+            now + Duration::seconds(backoff as i64)
+        });
+        self.last = Some((now, source, Some(error)));
+    }
+
+    /// 最近一次刷新的结果（尚未刷新过时为 None）
+    pub fn snapshot(&self, now: DateTime<Utc>) -> Option<RefreshStatus> {
+        let (at, source, error) = self.last.as_ref()?;
+        Some(RefreshStatus {
+            at: at.to_rfc3339(),
+            source: *source,
+            success: error.is_none(),
+            error: error.clone(),
+            consecutive_failures: self.failures,
+            retry_at: self
+                .retry_at
+                .filter(|at| *at > now)
+                .map(|at| at.to_rfc3339()),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config() -> WarmRefreshConfig {
+        WarmRefreshConfig {
+            margin_secs: 900,
+            jitter_secs: 0,
+            check_interval_secs: 60,
+            max_backoff_secs: 300,
+        }
+    }
+
+    #[test]
+    fn test_is_due_within_margin() {
+        let config = config();
+        let state = RefreshState::new(Some(&config));
+        let now = Utc::now();
+        assert!(!state.is_due(&config, now + Duration::seconds(901), now));
+        assert!(state.is_due(&config, now + Duration::seconds(900), now));
+        assert!(state.is_due(&config, now - Duration::seconds(1), now));
+    }
+
+    #[test]
+    fn test_jitter_refreshes_earlier() {
+        let config = WarmRefreshConfig {
+            jitter_secs: 100,
+            ..config()
+        };
+        let state = RefreshState::new(Some(&config));
+        assert!(state.jitter >= Duration::zero() && state.jitter <= Duration::seconds(100));
+        let now = Utc::now();
+        assert!(state.is_due(&config, now + Duration::seconds(900) + state.jitter, now));
+    }
+
+    #[test]
+    fn test_failure_backoff_doubles_and_caps() {
+        let config = config();
+        let mut state = RefreshState::new(Some(&config));
+        let now = Utc::now();
+        let expires_at = now + Duration::seconds(60);
+
+        let backoffs: Vec<i64> = (0..4)
+            .map(|_| {
+                state.record_failure(RefreshSource::Warm, "boom".into(), Some(&config), now);
+                (state.retry_at.unwrap() - now).num_seconds()
+            })
+            .collect();
+        assert_eq!(backoffs, [60, 120, 240, 300]);
+        assert!(!state.is_due(&config, expires_at, now + Duration::seconds(299)));
+        assert!(state.is_due(&config, expires_at, now + Duration::seconds(300)));
+
+        let status = state.snapshot(now).unwrap();
+        assert!(!status.success);
+        assert_eq!(status.consecutive_failures, 4);
+        assert!(status.retry_at.is_some());
+
+        state.record_success(RefreshSource::OnDemand, now);
+        let status = state.snapshot(now).unwrap();
+        assert!(status.success);
+        assert_eq!(status.source, RefreshSource::OnDemand);
+        assert_eq!(status.consecutive_failures, 0);
+        assert!(state.is_due(&config, expires_at, now));
+    }
+}
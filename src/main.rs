@@ -9,41 +9,171 @@ pub mod token;
 
 use std::collections::HashMap;
 use std::sync::Arc;
+use std::time::Duration;
 
 use clap::Parser;
 use kiro::endpoint::{IdeEndpoint, KiroEndpoint};
 use kiro::model::credentials::{CredentialsConfig, KiroCredentials};
 use kiro::provider::KiroProvider;
 use kiro::token_manager::MultiTokenManager;
-use model::arg::Args;
+use model::arg::{Args, Command, ConfigCommand, CredentialsCommand};
 use model::config::Config;
+use model::config_check::Severity;
+use tower_http::compression::CompressionLayer;
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::util::SubscriberInitExt;
 
-#[tokio::main]
-async fn main() {
+fn main() {
     // 解析命令行参数
     let args = Args::parse();
 
-    // 初始化日志
-    tracing_subscriber::fmt()
-        .with_env_filter(
+    // 工具子命令：执行后直接退出，不启动服务
+    if let Some(Command::Config {
+        action: ConfigCommand::Schema,
+    }) = args.command
+    {
+        let schema = model::config_schema::config_schema();
+        println!(
+            "{}",
+            serde_json::to_string_pretty(&schema).expect("Schema 可序列化")
+        );
+        return;
+    }
+    if let Some(Command::CheckConfig) = args.command {
+        std::process::exit(check_config(&args));
+    }
+    if let Some(Command::Credentials {
+        action: CredentialsCommand::Add,
+    }) = args.command
+    {
+        let config = Config::load_with_profiles(config_file_path(&args), &args.profile)
+            .unwrap_or_else(|e| {
+                eprintln!("加载配置失败: {:#}", e);
+                std::process::exit(1);
+            });
+        let credentials_path = credentials_file_path(&args);
+        std::process::exit(kiro::credential_wizard::run(
+            &config,
+            credentials_path.as_ref(),
+        ));
+    }
+
+    // 后台运行：fork 必须在创建 tokio 运行时（多线程）之前完成
+    if args.daemon
+        && let Err(e) = common::daemon::daemonize()
+    {
+        eprintln!("转入后台运行失败: {}", e);
+        std::process::exit(1);
+    }
+    let pid_file = args
+        .pid_file
+        .clone()
+        .or_else(|| args.daemon.then(|| "kiro-rs.pid".to_string()));
+    if let Some(path) = &pid_file
+        && let Err(e) = common::daemon::write_pid_file(path.as_ref())
+    {
+        eprintln!("写入 PID 文件失败: {}", e);
+        std::process::exit(1);
+    }
+
+    tokio::runtime::Builder::new_multi_thread()
+        .enable_all()
+        .build()
+        .expect("创建 tokio 运行时失败")
+        .block_on(run(args));
+}
+
+fn config_file_path(args: &Args) -> String {
+    args.config
+        .clone()
+        .unwrap_or_else(|| Config::default_config_path().to_string())
+}
+
+fn credentials_file_path(args: &Args) -> String {
+    args.credentials
+        .clone()
+        .unwrap_or_else(|| KiroCredentials::default_credentials_path().to_string())
+}
+
+/// `kiro-rs check-config`：输出检查结果，返回进程退出码
+fn check_config(args: &Args) -> i32 {
+    let issues = model::config_check::check_files(
+        config_file_path(args).as_ref(),
+        credentials_file_path(args).as_ref(),
+    );
+    for issue in &issues {
+        println!("{}", issue);
+    }
+    let errors = issues
+        .iter()
+        .filter(|i| i.severity == Severity::Error)
+        .count();
+    if issues.is_empty() {
+        println!("配置检查通过");
+    } else {
+        println!("发现 {} 个错误，{} 个警告", errors, issues.len() - errors);
+    }
+    if !args.profile.is_empty() {
+        println!("注意: 只检查了基础配置文件，未检查 profile");
+    }
+    i32::from(errors > 0)
+}
+
+async fn run(args: Args) {
+    // 初始化日志（预留 OpenTelemetry layer，加载配置后按需启用）
+    tracing_subscriber::registry()
+        .with(common::telemetry::layer())
+        .with(
             tracing_subscriber::EnvFilter::try_from_default_env()
                 .unwrap_or_else(|_| tracing_subscriber::EnvFilter::new("info")),
         )
+        .with(tracing_subscriber::fmt::layer().with_writer(common::log_buffer::TeeMakeWriter))
         .init();
 
+    // 检查配置文件：默认只输出告警，--strict-config 时存在错误则拒绝启动
+    let issues = model::config_check::check_files(
+        config_file_path(&args).as_ref(),
+        credentials_file_path(&args).as_ref(),
+    );
+    for issue in &issues {
+        match issue.severity {
+            Severity::Error if args.strict_config => tracing::error!("{}", issue),
+            _ => tracing::warn!("{}", issue),
+        }
+    }
+    if args.strict_config && issues.iter().any(|i| i.severity == Severity::Error) {
+        tracing::error!("配置检查未通过（--strict-config），拒绝启动");
+        std::process::exit(1);
+    }
+
     // 加载配置
-    let config_path = args
-        .config
-        .unwrap_or_else(|| Config::default_config_path().to_string());
-    let config = Config::load(&config_path).unwrap_or_else(|e| {
-        tracing::error!("加载配置失败: {}", e);
+    let config_path = config_file_path(&args);
+    let config = Config::load_with_profiles(&config_path, &args.profile).unwrap_or_else(|e| {
+        tracing::error!("加载配置失败: {:#}", e);
         std::process::exit(1);
     });
+    if let Some(log_file) = &config.log_file {
+        if let Err(e) = common::log_file::init(log_file) {
+            tracing::error!("打开日志文件 {} 失败: {}", log_file.path, e);
+            std::process::exit(1);
+        }
+        tracing::info!("日志同时写入文件: {}", log_file.path);
+    } else if args.daemon {
+        tracing::warn!("后台运行但未配置 logFile，日志将被丢弃");
+    }
+    if let Some(otlp) = &config.otlp {
+        if let Err(e) = common::telemetry::init(otlp) {
+            tracing::error!("初始化 OTLP 链路追踪失败: {}", e);
+            std::process::exit(1);
+        }
+        tracing::info!("链路追踪导出到: {}", otlp.endpoint);
+    }
+    if !args.profile.is_empty() {
+        tracing::info!("已应用配置 profile: {}", args.profile.join(" -> "));
+    }
 
     // 加载凭证（支持单对象或数组格式）
-    let credentials_path = args
-        .credentials
-        .unwrap_or_else(|| KiroCredentials::default_credentials_path().to_string());
+    let credentials_path = credentials_file_path(&args);
     let credentials_config = CredentialsConfig::load(&credentials_path).unwrap_or_else(|e| {
         tracing::error!("加载凭证失败: {}", e);
         std::process::exit(1);
@@ -83,14 +213,11 @@ async fn main() {
         std::process::exit(1);
     });
 
+    // 设置上游连接的 IP 协议族（须在构建任何 HTTP Client 之前）
+    http_client::init_ip_family(config.upstream_ip_family);
+
     // 构建代理配置
-    let proxy_config = config.proxy_url.as_ref().map(|url| {
-        let mut proxy = http_client::ProxyConfig::new(url);
-        if let (Some(username), Some(password)) = (&config.proxy_username, &config.proxy_password) {
-            proxy = proxy.with_auth(username, password);
-        }
-        proxy
-    });
+    let proxy_config = http_client::ProxyConfig::from_config(&config);
 
     if proxy_config.is_some() {
         tracing::info!("已配置 HTTP 代理: {}", config.proxy_url.as_ref().unwrap());
@@ -141,6 +268,51 @@ async fn main() {
         std::process::exit(1);
     });
     let token_manager = Arc::new(token_manager);
+    if config.health_check_interval_secs > 0 {
+        token_manager.spawn_health_check(
+            Duration::from_secs(config.health_check_interval_secs),
+            Duration::from_secs(config.health_check_jitter_secs),
+        );
+        tracing::info!(
+            "已启用禁用凭据健康检查（间隔 {}s，抖动 {}s）",
+            config.health_check_interval_secs,
+            config.health_check_jitter_secs
+        );
+    }
+    if config.proxy_health_check_interval_secs > 0 {
+        token_manager
+            .spawn_proxy_health_check(Duration::from_secs(config.proxy_health_check_interval_secs));
+        tracing::info!(
+            "已启用凭据级代理健康探测（间隔 {}s）",
+            config.proxy_health_check_interval_secs
+        );
+    }
+    if let Some(warm) = &config.warm_refresh {
+        token_manager.spawn_warm_refresh();
+        tracing::info!(
+            "已启用 Token 预刷新（过期前 {}s，抖动 {}s，每 {}s 检查）",
+            warm.margin_secs,
+            warm.jitter_secs,
+            warm.check_interval_secs
+        );
+    }
+    if config.validate_credentials_on_startup {
+        tracing::info!("正在后台验证所有凭据...");
+        kiro::validation::spawn_startup(&token_manager);
+    }
+    if config.config_reload_interval_secs > 0 {
+        common::config_reload::spawn(
+            config_path.clone().into(),
+            args.profile.clone(),
+            config.clone(),
+            &token_manager,
+        );
+        tracing::info!(
+            "已启用配置热加载（每 {}s 检查 {}）",
+            config.config_reload_interval_secs,
+            config_path
+        );
+    }
     let kiro_provider = KiroProvider::with_proxy(
         token_manager.clone(),
         proxy_config.clone(),
@@ -153,15 +325,136 @@ async fn main() {
         api_url: config.count_tokens_api_url.clone(),
         api_key: config.count_tokens_api_key.clone(),
         auth_type: config.count_tokens_auth_type.clone(),
-        proxy: proxy_config,
+        proxy: proxy_config.clone(),
         tls_backend: config.tls_backend,
     });
+    if let Err(e) = anthropic::search_provider::init(
+        config.web_search.as_ref(),
+        proxy_config.as_ref(),
+        config.tls_backend,
+    ) {
+        tracing::error!("初始化 WebSearch 后端失败: {}", e);
+        std::process::exit(1);
+    }
+    if let Err(e) = anthropic::embeddings::init(
+        config.embeddings.as_ref(),
+        proxy_config.as_ref(),
+        config.tls_backend,
+    ) {
+        tracing::error!("初始化 Embeddings 上游失败: {}", e);
+        std::process::exit(1);
+    }
+    if let Err(e) = anthropic::shadow::init(config.shadow.as_ref(), config.tls_backend) {
+        tracing::error!("初始化影子流量失败: {}", e);
+        std::process::exit(1);
+    }
+    if let Some(shadow) = &config.shadow {
+        tracing::info!(
+            "已启用影子流量: {}（复制 {}% 的非流式请求）",
+            shadow.url,
+            shadow.sample_ratio * 100.0
+        );
+    }
+    if let Some(web_search) = &config.web_search {
+        tracing::info!("WebSearch 使用本地后端: {:?}", web_search.provider);
+    }
+    if let Err(e) = kiro::quota_alert::init(
+        config.quota_alerts.as_ref(),
+        token_manager
+            .cache_dir()
+            .map(|d| d.join("kiro_quota_alerts.json")),
+        proxy_config.as_ref(),
+        config.tls_backend,
+    ) {
+        tracing::error!("初始化额度告警失败: {}", e);
+        std::process::exit(1);
+    }
+    kiro::quota_alert::spawn_check(&token_manager);
+    anthropic::session_memory::init(
+        config.session_memory.as_ref(),
+        token_manager
+            .cache_dir()
+            .map(|d| d.join("kiro_session_memory.json")),
+    );
+    if config.session_memory.is_some() {
+        tracing::info!("已启用会话记忆");
+    }
+    anthropic::usage_ledger::init(
+        config.usage_ledger.as_ref(),
+        token_manager
+            .cache_dir()
+            .map(|d| d.join("kiro_usage_ledger.json")),
+    );
+    if let Some(ledger) = &config.usage_ledger {
+        tracing::info!("已启用 token 用量账本（保留 {} 天）", ledger.retention_days);
+    }
+    if let Err(e) = anthropic::files::init(
+        config.files.as_ref(),
+        token_manager.cache_dir().map(|d| d.join("kiro_files")),
+    ) {
+        tracing::error!("初始化 Files API 存储失败: {}", e);
+        std::process::exit(1);
+    }
+    if let Some(files) = &config.files {
+        tracing::info!("已启用 Files API（单文件上限 {}MB）", files.max_file_mb);
+    }
+    if let Some(alerts) = &config.quota_alerts {
+        tracing::info!("已启用额度使用率告警（阈值 {:?}%）", alerts.thresholds);
+    }
+    kiro::request_size::init_alert_threshold(config.request_size_alert_tokens);
+    anthropic::system_prompt::init(config.system_prompts.clone());
+    anthropic::session_cost::init_prices(config.model_prices.clone());
+    anthropic::image_compress::init(config.image_compression.as_ref());
+    if let Err(e) = anthropic::output_filter::init(&config.output_redactions) {
+        tracing::error!("加载输出脱敏规则失败: {:#}", e);
+        std::process::exit(1);
+    }
+    anthropic::init_tool_history_repair(config.repair_tool_history);
+    kiro::model::events::unknown_events::init(config.map_unknown_text_events);
+    kiro::model_registry::init(&config.model_aliases, &config.model_capabilities);
+    kiro::model_registry::spawn_refresh(&token_manager, config.model_registry_refresh_secs);
+    if config.model_registry_refresh_secs > 0 {
+        tracing::info!(
+            "已启用 Kiro 可用模型列表同步（每 {}s）",
+            config.model_registry_refresh_secs
+        );
+    }
+    kiro::concurrency::init(&config);
+    if config.max_in_flight_per_credential > 0 {
+        tracing::info!(
+            "已启用按凭据的并发上限: {}（排队上限 {}，超时 {}s）",
+            config.max_in_flight_per_credential,
+            config.concurrency_queue_size,
+            config.concurrency_queue_timeout_secs
+        );
+    }
+    if let Some(adaptive) = &config.adaptive_concurrency {
+        tracing::info!(
+            "已启用按凭据的自适应并发控制（初始 {}，范围 {}-{}）",
+            adaptive.initial_limit,
+            adaptive.min_limit,
+            adaptive.max_limit
+        );
+    }
+    anthropic::admission::init(config.admission.as_ref());
+    if let Some(admission) = &config.admission {
+        tracing::info!(
+            "已启用全局准入控制: 在途上限 {}（排队上限 {}，超时 {}s）",
+            admission.max_in_flight,
+            admission.queue_size,
+            admission.queue_timeout_secs
+        );
+    }
+    anthropic::replay::init(config.debug_capture_frames);
+    if config.debug_capture_frames {
+        tracing::warn!("已开启上游事件流录制（debugCaptureFrames），录制内容包含完整响应，仅用于调试");
+    }
 
     // 构建 Anthropic API 路由（profile_arn 由 provider 层根据实际凭据动态注入）
     let anthropic_app = anthropic::create_router_with_provider(
         &api_key,
         Some(kiro_provider),
-        config.extract_thinking,
+        &config,
     );
 
     // 构建 Admin API 路由（如果配置了非空的 admin_api_key）
@@ -183,37 +476,125 @@ async fn main() {
             let admin_app = admin::create_admin_router(admin_state);
 
             // 创建 Admin UI 路由
-            let admin_ui_app = admin_ui::create_admin_ui_router();
+            let admin_ui_path = match admin_ui::normalize_base_path(&config.admin_ui_path) {
+                Ok(path) => path,
+                Err(e) => {
+                    tracing::error!("{}", e);
+                    std::process::exit(1);
+                }
+            };
+            let admin_ui_app = admin_ui::create_admin_ui_router(&admin_ui_path);
 
             tracing::info!("Admin API 已启用");
-            tracing::info!("Admin UI 已启用: /admin");
+            tracing::info!("Admin UI 已启用: {}", admin_ui_path);
             anthropic_app
                 .nest("/api/admin", admin_app)
-                .nest("/admin", admin_ui_app)
+                .nest(&admin_ui_path, admin_ui_app)
         }
     } else {
         anthropic_app
     };
 
+    if !config.fault_injection.is_empty() {
+        tracing::warn!(
+            "已启用故障注入（{} 条规则），匹配的请求会按概率失败，仅用于测试环境",
+            config.fault_injection.len()
+        );
+    }
+
+    // 响应压缩：默认谓词已排除 SSE（text/event-stream）、图片与过小的响应
+    let app = if config.compress_responses {
+        tracing::info!("已启用响应压缩（zstd / gzip）");
+        app.layer(CompressionLayer::new())
+    } else {
+        app
+    };
+
     // 启动服务器
-    let addr = format!("{}:{}", config.host, config.port);
+    let addr = if config.host.contains(':') {
+        format!("[{}]:{}", config.host, config.port)
+    } else {
+        format!("{}:{}", config.host, config.port)
+    };
     tracing::info!("启动 Anthropic API 端点: {}", addr);
     tracing::info!("API Key: {}***", &api_key[..(api_key.len() / 2)]);
     tracing::info!("可用 API:");
     tracing::info!("  GET  /v1/models");
     tracing::info!("  POST /v1/messages");
     tracing::info!("  POST /v1/messages/count_tokens");
+    tracing::info!("  POST /v1/messages/batches");
+    tracing::info!("  GET  /v1/sessions/:session_id/cost");
+    tracing::info!("  GET  /v1/sessions/:session_id/usage");
+    tracing::info!("  GET  /v1/sessions/:session_id/memory");
+    tracing::info!("  PUT  /v1/sessions/:session_id/memory/:key");
+    if config.files.is_some() {
+        tracing::info!("  POST /v1/files");
+        tracing::info!("  GET  /v1/files/:file_id");
+    }
+    if config.embeddings.is_some() {
+        tracing::info!("  POST /v1/embeddings");
+    }
     if admin_key_valid {
         tracing::info!("Admin API:");
         tracing::info!("  GET  /api/admin/credentials");
+        tracing::info!("  POST /api/admin/credentials/import");
+        tracing::info!("  POST /api/admin/credentials/export");
+        tracing::info!("  POST /api/admin/credentials/import-bundle");
         tracing::info!("  POST /api/admin/credentials/:index/disabled");
         tracing::info!("  POST /api/admin/credentials/:index/priority");
+        tracing::info!("  POST /api/admin/credentials/:index/weight");
+        tracing::info!("  POST /api/admin/credentials/:index/schedule");
+        tracing::info!("  POST /api/admin/credentials/:index/group");
         tracing::info!("  POST /api/admin/credentials/:index/reset");
         tracing::info!("  GET  /api/admin/credentials/:index/balance");
+        tracing::info!("  GET  /api/admin/config/model-routes");
+        tracing::info!("  PUT  /api/admin/config/model-routes");
+        tracing::info!("  GET  /api/admin/config/system-prompts");
+        tracing::info!("  PUT  /api/admin/config/system-prompts");
+        tracing::info!("  GET  /api/admin/config/schema");
+        tracing::info!("  GET  /api/admin/sessions/usage");
+        tracing::info!("  GET  /api/admin/sessions/memory");
+        tracing::info!("  PUT  /api/admin/sessions/:session_id/memory");
+        tracing::info!("  POST /api/admin/share-links");
+        tracing::info!("  GET  /api/admin/support-bundle");
+        tracing::info!("  GET  /api/admin/malformed-requests");
+        tracing::info!("  GET  /api/admin/request-sizes");
+        tracing::info!("  GET  /api/admin/shadow");
+        tracing::info!("  GET  /api/admin/concurrency");
+        tracing::info!("  GET  /api/admin/admission");
+        tracing::info!("  GET  /api/admin/usage");
+        tracing::info!("  GET  /api/admin/usage/export");
+        tracing::info!("  GET  /api/admin/model-prices");
+        tracing::info!("  GET  /api/admin/upstream-fields");
+        tracing::info!("  GET  /api/admin/upstream-events");
+        tracing::info!("  GET  /api/admin/debug/frames");
+        tracing::info!("  GET  /api/admin/debug/frames/:id");
+        tracing::info!("  POST /api/admin/debug/replay");
+        tracing::info!("  GET  /api/admin/share/credentials?token=...");
         tracing::info!("Admin UI:");
         tracing::info!("  GET  /admin");
     }
 
-    let listener = tokio::net::TcpListener::bind(&addr).await.unwrap();
-    axum::serve(listener, app).await.unwrap();
+    let listener = common::net::bind_listener(&config.host, config.port, config.dual_stack)
+        .await
+        .unwrap_or_else(|e| {
+            tracing::error!("监听 {} 失败: {}", addr, e);
+            std::process::exit(1);
+        });
+
+    // 上游连通性自检（后台执行，不阻塞启动；配置了全局代理时直连结果无参考意义）
+    if config.proxy_url.is_none() {
+        tokio::spawn(common::net::connectivity_self_test(
+            format!("q.{}.amazonaws.com", config.effective_api_region()),
+            443,
+        ));
+    }
+
+    // 附带对端地址，供按 IP 限流使用
+    axum::serve(
+        listener,
+        app.into_make_service_with_connect_info::<std::net::SocketAddr>(),
+    )
+    .await
+    .unwrap();
 }
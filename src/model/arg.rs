@@ -1,4 +1,4 @@
-use clap::Parser;
+use clap::{Parser, Subcommand};
 
 /// Anthropic <-> Kiro API 客户端
 #[derive(Parser, Debug)]
@@ -11,4 +11,53 @@ pub struct Args {
     /// 凭证文件路径
     #[arg(long)]
     pub credentials: Option<String>,
+
+    /// 叠加的配置 profile（config.d/<profile>.json），可逗号分隔或多次指定，按顺序覆盖
+    #[arg(long, value_delimiter = ',')]
+    pub profile: Vec<String>,
+
+    /// 后台运行（仅 Unix），日志请通过 config.json 的 logFile 写入文件
+    #[arg(long)]
+    pub daemon: bool,
+
+    /// PID 文件路径（--daemon 时默认为 kiro-rs.pid）
+    #[arg(long)]
+    pub pid_file: Option<String>,
+
+    /// 严格检查配置：config.json 或 credentials.json 存在错误（如未知字段、类型不符）时拒绝启动
+    #[arg(long)]
+    pub strict_config: bool,
+
+    /// 子命令（未指定时启动服务）
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum Command {
+    /// 配置相关工具
+    Config {
+        #[command(subcommand)]
+        action: ConfigCommand,
+    },
+    /// 检查 config.json 与 credentials.json（未知字段、类型不符、互斥或缺失的选项组合），
+    /// 存在错误时以状态码 1 退出
+    CheckConfig,
+    /// 凭据管理工具
+    Credentials {
+        #[command(subcommand)]
+        action: CredentialsCommand,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+pub enum ConfigCommand {
+    /// 输出 config.json 的 JSON Schema
+    Schema,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum CredentialsCommand {
+    /// 交互式添加凭据：选择认证方式、粘贴 refreshToken，可选验证后写入凭据文件（写入前备份）
+    Add,
 }
@@ -4,7 +4,7 @@ use std::collections::HashMap;
 use std::fs;
 use std::path::{Path, PathBuf};
 
-#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Hash)]
 #[serde(rename_all = "kebab-case")]
 pub enum TlsBackend {
     Rustls,
@@ -17,6 +17,849 @@ impl Default for TlsBackend {
     }
 }
 
+/// 上游连接使用的 IP 协议族
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum IpFamily {
+    /// 自动：同时尝试 IPv4/IPv6（happy eyeballs），先连上者胜出
+    #[default]
+    Auto,
+    /// 仅 IPv4
+    Ipv4,
+    /// 仅 IPv6
+    Ipv6,
+}
+
+/// SSE 保活 ping 的格式
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum SsePingStyle {
+    /// Anthropic 风格的 `event: ping` 事件
+    #[default]
+    Event,
+    /// SSE 注释行 `: ping`（客户端忽略，只用于保持连接活跃）
+    Comment,
+}
+
+/// 滚动窗口 token 配额规则
+///
+/// 统计最近 `window_secs` 秒内每个 API Key 的 token 用量，任一上限超出即拒绝新请求
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct TokenQuota {
+    /// 窗口长度（秒），如 18000 表示 5 小时
+    pub window_secs: u64,
+
+    /// 窗口内输入 tokens 上限
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_input_tokens: Option<u64>,
+
+    /// 窗口内输出 tokens 上限
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_output_tokens: Option<u64>,
+}
+
+/// 日志文件配置
+///
+/// 日志同时写入 stdout 与该文件，按天和/或按大小轮转
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct LogFileConfig {
+    /// 日志文件路径，轮转后的文件为 `<path>.<YYYYmmdd-HHMMSS>`
+    pub path: String,
+
+    /// 单个文件大小上限（MB），0 表示不按大小轮转
+    #[serde(default = "default_log_max_size_mb")]
+    pub max_size_mb: u64,
+
+    /// 是否按天轮转（跨日后首次写入时轮转）
+    #[serde(default = "default_log_daily")]
+    pub daily: bool,
+
+    /// 保留的已轮转文件数
+    #[serde(default = "default_log_max_files")]
+    pub max_files: usize,
+}
+
+fn default_log_max_size_mb() -> u64 {
+    100
+}
+
+fn default_log_daily() -> bool {
+    true
+}
+
+fn default_log_max_files() -> usize {
+    7
+}
+
+/// OTLP 链路追踪导出配置
+///
+/// 请求处理、请求转换、上游调用与 Token 刷新的 span 通过 OTLP/HTTP（protobuf）
+/// 批量导出到 Jaeger / Tempo 等后端
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct OtlpConfig {
+    /// OTLP/HTTP traces 接收地址（如 `http://localhost:4318/v1/traces`）
+    pub endpoint: String,
+
+    /// 上报的服务名
+    #[serde(default = "default_otlp_service_name")]
+    pub service_name: String,
+
+    /// 附加到导出请求的请求头（如鉴权）
+    #[serde(default)]
+    #[serde(skip_serializing_if = "HashMap::is_empty")]
+    pub headers: HashMap<String, String>,
+
+    /// 采样比例（0~1），按 trace 采样
+    #[serde(default = "default_otlp_sample_ratio")]
+    pub sample_ratio: f64,
+}
+
+fn default_otlp_service_name() -> String {
+    "kiro-rs".to_string()
+}
+
+fn default_otlp_sample_ratio() -> f64 {
+    1.0
+}
+
+/// 模型路由规则
+///
+/// 请求匹配时只在指定分组的凭据中选择，按 `groups` 顺序优先使用前面的分组，
+/// 前一个分组没有可用凭据时溢出到下一个分组；所有配置的条件都满足才算匹配
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct ModelRoute {
+    /// 匹配的模型（Kiro 模型 ID，不区分大小写，支持 `*` 后缀通配），为空时匹配所有模型
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub models: Vec<String>,
+
+    /// 仅匹配消息数不超过该值的请求（如 Claude Code 标题生成等单条消息的轻量请求）
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_messages: Option<usize>,
+
+    /// 仅匹配 max_tokens 不超过该值的请求
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_tokens: Option<i32>,
+
+    /// 按优先顺序排列的凭据分组
+    #[serde(default)]
+    pub groups: Vec<String>,
+}
+
+/// 模型价格规则
+///
+/// 用于估算会话费用与用量账本费用，按顺序匹配，第一条匹配的规则生效；未匹配时使用内置的 Anthropic 标价
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct ModelPriceRule {
+    /// 匹配的模型（不区分大小写，支持 `*` 后缀通配）
+    pub models: Vec<String>,
+
+    /// 每百万输入 tokens 的价格（美元）
+    #[serde(rename = "inputPerMTok")]
+    pub input_per_mtok: f64,
+
+    /// 每百万输出 tokens 的价格（美元）
+    #[serde(rename = "outputPerMTok")]
+    pub output_per_mtok: f64,
+
+    /// 每百万缓存读取 tokens 的价格（美元），未配置时为输入价格的 10%
+    #[serde(default, rename = "cacheReadPerMTok")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cache_read_per_mtok: Option<f64>,
+}
+
+/// 请求改写规则
+///
+/// 在请求转换前按配置顺序依次评估，所有匹配的规则都会生效；
+/// 匹配条件均未配置时匹配所有请求
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct RequestTransform {
+    /// 匹配的请求模型（不区分大小写，支持 `*` 后缀通配），为空时匹配所有模型
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub models: Vec<String>,
+
+    /// 匹配的 User-Agent 子串（不区分大小写）
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub user_agent: Option<String>,
+
+    /// 插入到 system 提示开头的文本
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub prepend_system: Option<String>,
+
+    /// 按名称移除的工具
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub drop_tools: Vec<String>,
+
+    /// 改写后的模型名
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub rewrite_model: Option<String>,
+
+    /// max_tokens 上限
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_tokens: Option<i32>,
+}
+
+/// 按模型注入的 system 提示
+///
+/// 用于抵消 Kiro 侧注入的代理行为（如拒绝非编码任务）；所有匹配的规则都会生效，
+/// 前缀按规则顺序插入到 system 开头，后缀按规则顺序追加到末尾
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct SystemPromptRule {
+    /// 匹配的请求模型（不区分大小写，支持 `*` 后缀通配），为空时匹配所有模型
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub models: Vec<String>,
+
+    /// 匹配的 User-Agent 子串（不区分大小写），用于区分客户端
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub user_agent: Option<String>,
+
+    /// 插入到 system 提示开头的文本
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub prefix: Option<String>,
+
+    /// 追加到 system 提示末尾的文本
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub suffix: Option<String>,
+}
+
+/// 输出脱敏规则
+///
+/// 命中的文本在发送给客户端前被替换（仅作用于 text 内容块）
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct OutputRedaction {
+    /// 要屏蔽的字符串（`regex` 为 true 时为正则表达式）
+    pub pattern: String,
+
+    /// 是否按正则表达式匹配
+    #[serde(default)]
+    pub regex: bool,
+
+    /// 替换文本
+    #[serde(default = "default_redaction_replacement")]
+    pub replacement: String,
+}
+
+fn default_redaction_replacement() -> String {
+    "[REDACTED]".to_string()
+}
+
+/// 本地 WebSearch 后端类型
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum WebSearchProvider {
+    /// 自建 SearXNG 实例（需开启 JSON 输出格式）
+    Searxng,
+    /// Brave Search API
+    Brave,
+    /// Bing Web Search API
+    Bing,
+}
+
+/// 本地 WebSearch 配置
+///
+/// 配置后 web_search 工具请求由代理直接调用搜索后端，不再经过 Kiro MCP
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct WebSearchConfig {
+    pub provider: WebSearchProvider,
+
+    /// 搜索 API 地址（SearXNG 必填；Brave / Bing 未配置时使用官方地址）
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub url: Option<String>,
+
+    /// 搜索 API 密钥（Brave / Bing 必填）
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub api_key: Option<String>,
+
+    /// 单次搜索返回的最大结果数
+    #[serde(default = "default_web_search_max_results")]
+    pub max_results: usize,
+}
+
+fn default_web_search_max_results() -> usize {
+    10
+}
+
+/// Embeddings 转发配置
+///
+/// `POST /v1/embeddings` 转发到外部 OpenAI 兼容接口
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct EmbeddingsConfig {
+    /// 上游完整地址，如 `https://api.openai.com/v1/embeddings`
+    pub url: String,
+
+    /// 上游 API 密钥（以 Bearer 方式发送）
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub api_key: Option<String>,
+
+    /// 固定使用的模型，配置后覆盖请求中的 model
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub model: Option<String>,
+
+    /// 上游请求超时（秒）
+    #[serde(default = "default_embeddings_timeout_secs")]
+    pub timeout_secs: u64,
+}
+
+fn default_embeddings_timeout_secs() -> u64 {
+    60
+}
+
+/// 影子流量配置
+///
+/// 按比例把非流式 Messages 请求复制一份发往另一个 kiro.rs 实例，比较两边的响应并记录差异
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct ShadowConfig {
+    /// 影子实例的地址，如 `http://127.0.0.1:8991`（请求发往 `<url>/v1/messages`）
+    pub url: String,
+
+    /// 影子实例的 API 密钥（以 x-api-key 发送）
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub api_key: Option<String>,
+
+    /// 复制的请求比例（0~1）
+    #[serde(default = "default_shadow_sample_ratio")]
+    pub sample_ratio: f64,
+
+    /// 是否比较文本内容（模型输出本身不确定，默认只比较结构）
+    #[serde(default)]
+    pub compare_text: bool,
+
+    /// 影子请求超时（秒）
+    #[serde(default = "default_shadow_timeout_secs")]
+    pub timeout_secs: u64,
+
+    /// 最多保留的差异记录数
+    #[serde(default = "default_shadow_max_records")]
+    pub max_records: usize,
+}
+
+fn default_shadow_sample_ratio() -> f64 {
+    0.01
+}
+
+fn default_shadow_timeout_secs() -> u64 {
+    300
+}
+
+fn default_shadow_max_records() -> usize {
+    100
+}
+
+/// 图片重新编码的格式
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ImageOutputFormat {
+    /// 有损 JPEG（透明区域以白色填充）
+    #[default]
+    Jpeg,
+    /// 无损 WebP（保留透明通道，适合文字较多的截图）
+    Webp,
+}
+
+/// 图片压缩配置
+///
+/// 请求转换时对超出分辨率或大小上限的 base64 图片缩小并重新编码
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct ImageCompressionConfig {
+    /// 最长边的像素上限，超出时等比缩小
+    #[serde(default = "default_image_max_dimension")]
+    pub max_dimension: u32,
+
+    /// 单张图片的大小上限（KB，按解码后的字节数计），超出时重新编码
+    #[serde(default = "default_image_max_kb")]
+    pub max_kb: usize,
+
+    /// 重新编码的格式
+    #[serde(default)]
+    pub format: ImageOutputFormat,
+
+    /// JPEG 质量（1~100）
+    #[serde(default = "default_image_jpeg_quality")]
+    pub jpeg_quality: u8,
+}
+
+impl Default for ImageCompressionConfig {
+    fn default() -> Self {
+        Self {
+            max_dimension: default_image_max_dimension(),
+            max_kb: default_image_max_kb(),
+            format: ImageOutputFormat::default(),
+            jpeg_quality: default_image_jpeg_quality(),
+        }
+    }
+}
+
+fn default_image_max_dimension() -> u32 {
+    1568
+}
+
+fn default_image_max_kb() -> usize {
+    1024
+}
+
+fn default_image_jpeg_quality() -> u8 {
+    85
+}
+
+/// 自适应并发控制配置（AIMD）
+///
+/// 按凭据维护并发上限：请求成功且延迟正常时缓慢增加，
+/// 遇到上游 429 时减半，延迟明显升高时小幅下调
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct AdaptiveConcurrencyConfig {
+    /// 初始并发上限
+    #[serde(default = "default_adaptive_initial_limit")]
+    pub initial_limit: u32,
+
+    /// 并发上限的下界
+    #[serde(default = "default_adaptive_min_limit")]
+    pub min_limit: u32,
+
+    /// 并发上限的上界
+    #[serde(default = "default_adaptive_max_limit")]
+    pub max_limit: u32,
+
+    /// 延迟容忍倍数：首字节延迟超过基线的该倍数时视为拥塞
+    #[serde(default = "default_adaptive_latency_tolerance")]
+    pub latency_tolerance: f64,
+}
+
+fn default_adaptive_initial_limit() -> u32 {
+    4
+}
+
+fn default_adaptive_min_limit() -> u32 {
+    1
+}
+
+fn default_adaptive_max_limit() -> u32 {
+    16
+}
+
+fn default_adaptive_latency_tolerance() -> f64 {
+    2.0
+}
+
+/// 全局准入控制配置
+///
+/// 限制所有凭据合计的在途 Messages 请求数，超出时按优先级排队，
+/// 队列溢出或等待超时返回 503
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct AdmissionConfig {
+    /// 全局最大在途请求数
+    #[serde(default = "default_admission_max_in_flight")]
+    pub max_in_flight: usize,
+
+    /// 最多排队等待的请求数，0 表示不排队直接拒绝
+    #[serde(default = "default_admission_queue_size")]
+    pub queue_size: usize,
+
+    /// 排队等待的超时时间（秒）
+    #[serde(default = "default_admission_queue_timeout_secs")]
+    pub queue_timeout_secs: u64,
+
+    /// 拒绝请求时 `retry-after` 响应头的秒数
+    #[serde(default = "default_admission_retry_after_secs")]
+    pub retry_after_secs: u64,
+
+    /// 视为后台请求（最低优先级）的模型名关键字（不区分大小写的子串匹配）
+    #[serde(default = "default_admission_background_models")]
+    pub background_models: Vec<String>,
+}
+
+fn default_admission_max_in_flight() -> usize {
+    32
+}
+
+fn default_admission_queue_size() -> usize {
+    128
+}
+
+fn default_admission_queue_timeout_secs() -> u64 {
+    30
+}
+
+fn default_admission_retry_after_secs() -> u64 {
+    5
+}
+
+fn default_admission_background_models() -> Vec<String> {
+    vec!["haiku".to_string()]
+}
+
+/// 按凭据的熔断配置
+///
+/// 窗口内上游 5xx / 超时次数达到阈值时熔断该凭据，冷却期内不再调度；
+/// 冷却结束后放行一个探测请求，成功则恢复，失败则重新熔断
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct CircuitBreakerConfig {
+    /// 触发熔断的错误次数
+    #[serde(default = "default_breaker_failure_threshold")]
+    pub failure_threshold: u32,
+
+    /// 统计错误次数的滑动窗口（秒）
+    #[serde(default = "default_breaker_window_secs")]
+    pub window_secs: u64,
+
+    /// 熔断冷却时间（秒）
+    #[serde(default = "default_breaker_cooldown_secs")]
+    pub cooldown_secs: u64,
+}
+
+fn default_breaker_failure_threshold() -> u32 {
+    5
+}
+
+fn default_breaker_window_secs() -> u64 {
+    60
+}
+
+fn default_breaker_cooldown_secs() -> u64 {
+    30
+}
+
+/// Token 预刷新配置
+///
+/// 后台定期检查 OAuth 凭据，在 accessToken 过期前主动刷新，避免请求路径上同步刷新带来的延迟
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct WarmRefreshConfig {
+    /// 距过期多久开始刷新（秒）
+    #[serde(default = "default_warm_refresh_margin_secs")]
+    pub margin_secs: u64,
+
+    /// 每个凭据额外提前的随机抖动上限（秒），错开同时签发的 Token 的刷新时间
+    #[serde(default = "default_warm_refresh_jitter_secs")]
+    pub jitter_secs: u64,
+
+    /// 检查间隔（秒）
+    #[serde(default = "default_warm_refresh_check_interval_secs")]
+    pub check_interval_secs: u64,
+
+    /// 刷新失败后的最大退避时间（秒），退避从检查间隔开始逐次翻倍
+    #[serde(default = "default_warm_refresh_max_backoff_secs")]
+    pub max_backoff_secs: u64,
+}
+
+fn default_warm_refresh_margin_secs() -> u64 {
+    900
+}
+
+fn default_warm_refresh_jitter_secs() -> u64 {
+    300
+}
+
+fn default_warm_refresh_check_interval_secs() -> u64 {
+    60
+}
+
+fn default_warm_refresh_max_backoff_secs() -> u64 {
+    600
+}
+
+/// Files API 配置
+///
+/// 上传的文件保存在本地目录，Messages 请求中的 `file_id` 引用在转换前内联为文件内容
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct FilesConfig {
+    /// 文件保存目录，未配置时使用凭据文件所在目录下的 `kiro_files`
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub dir: Option<String>,
+
+    /// 单个文件的大小上限（MB）
+    #[serde(default = "default_files_max_file_mb")]
+    pub max_file_mb: u64,
+
+    /// 所有文件的总大小上限（MB）
+    #[serde(default = "default_files_max_total_mb")]
+    pub max_total_mb: u64,
+}
+
+fn default_files_max_file_mb() -> u64 {
+    32
+}
+
+fn default_files_max_total_mb() -> u64 {
+    1024
+}
+
+/// Token 用量账本配置
+///
+/// 按 (API Key, 凭据, 模型, 日期) 累计请求数与 token 用量，持久化到凭据文件所在目录
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct UsageLedgerConfig {
+    /// 账本保留的天数，更早的记录在保存时清理
+    #[serde(default = "default_usage_retention_days")]
+    pub retention_days: u32,
+}
+
+fn default_usage_retention_days() -> u32 {
+    400
+}
+
+/// 非流式请求的响应缓存配置
+///
+/// 完全相同的非流式请求（模型、system、messages、工具等一致）在 TTL 内直接返回缓存的响应
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct ResponseCacheConfig {
+    /// 缓存有效期（秒）
+    #[serde(default = "default_response_cache_ttl_secs")]
+    pub ttl_secs: u64,
+
+    /// 最多缓存的响应数，超出时淘汰最早写入的响应
+    #[serde(default = "default_response_cache_max_entries")]
+    pub max_entries: usize,
+}
+
+fn default_response_cache_ttl_secs() -> u64 {
+    300
+}
+
+fn default_response_cache_max_entries() -> usize {
+    1000
+}
+
+/// 额度使用率告警配置
+///
+/// 凭据的额度使用百分比首次达到某个阈值时向 webhook 发送告警，同一重置周期内每个阈值只告警一次
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct QuotaAlertConfig {
+    /// 告警 webhook 地址（POST JSON）
+    pub webhook_url: String,
+
+    /// 告警阈值（使用百分比）
+    #[serde(default = "default_quota_alert_thresholds")]
+    pub thresholds: Vec<f64>,
+
+    /// 后台检查间隔（秒），0 表示只在查询余额、添加凭据时检查
+    #[serde(default = "default_quota_alert_check_interval_secs")]
+    pub check_interval_secs: u64,
+}
+
+fn default_quota_alert_thresholds() -> Vec<f64> {
+    vec![80.0, 95.0]
+}
+
+fn default_quota_alert_check_interval_secs() -> u64 {
+    900
+}
+
+/// 会话亲和路由配置
+///
+/// 同一会话（metadata.user_id 中的 session_id，缺省时为首条 user 消息哈希）
+/// 固定使用同一凭据，绑定的凭据不可用时自动重新绑定
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct SessionAffinityConfig {
+    /// 会话无请求多久后解除绑定（秒）
+    #[serde(default = "default_affinity_ttl_secs")]
+    pub ttl_secs: u64,
+
+    /// 最多保留的绑定数，超出时淘汰最久未使用的绑定
+    #[serde(default = "default_affinity_max_sessions")]
+    pub max_sessions: usize,
+}
+
+fn default_affinity_ttl_secs() -> u64 {
+    1800
+}
+
+fn default_affinity_max_sessions() -> usize {
+    10_000
+}
+
+/// 会话记忆配置
+///
+/// 按会话保存键值笔记，每次请求时以 system 块注入，笔记持久化到凭据文件所在目录
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct SessionMemoryConfig {
+    /// 每个会话最多保存的笔记数
+    #[serde(default = "default_memory_max_notes")]
+    pub max_notes: usize,
+
+    /// 最多保存记忆的会话数，超出时淘汰最久未更新的会话
+    #[serde(default = "default_memory_max_sessions")]
+    pub max_sessions: usize,
+}
+
+fn default_memory_max_notes() -> usize {
+    32
+}
+
+fn default_memory_max_sessions() -> usize {
+    1000
+}
+
+/// 公共 API 限流配置（令牌桶）
+///
+/// 对 `/v1` 与 `/cc/v1` 路由按 API Key 和客户端 IP 分别限流，超出时返回 429
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct RateLimitConfig {
+    /// 每个 API Key 每分钟允许的请求数，0 表示不限制
+    #[serde(default)]
+    pub per_key_rpm: u32,
+
+    /// 每个客户端 IP 每分钟允许的请求数，0 表示不限制
+    #[serde(default)]
+    pub per_ip_rpm: u32,
+
+    /// 突发容量（令牌桶大小），未配置时等于每分钟请求数
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub burst: Option<u32>,
+
+    /// 是否从 `X-Forwarded-For` 读取客户端 IP（仅在部署于可信反向代理之后时开启）
+    #[serde(default)]
+    pub trust_forwarded_for: bool,
+}
+
+/// 注入的故障类型
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub enum FaultKind {
+    /// 401 authentication_error
+    Unauthorized,
+    /// 429 rate_limit_error
+    RateLimited,
+    /// 500 api_error
+    ServerError,
+    /// 400 模型不可用（与上游返回 INVALID_MODEL_ID 时一致）
+    InvalidModel,
+    /// 流式响应的每个事件前等待 `delayMs`
+    SlowStream,
+    /// 流式响应发送 `afterEvents` 个事件后断开
+    TruncatedStream,
+}
+
+/// 故障注入规则
+///
+/// 用于客户端集成测试：按概率让匹配的请求返回模拟的上游错误，或让流式响应变慢、中途断开。
+/// 错误类故障在调用上游前直接返回，不占用凭据，也不影响凭据的健康状态
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct FaultRule {
+    /// 匹配的路径前缀（如 `/v1/messages`），为空时匹配所有 `/v1` 与 `/cc/v1` 路由
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub paths: Vec<String>,
+
+    /// 故障类型
+    pub fault: FaultKind,
+
+    /// 注入概率（0~1）
+    #[serde(default = "default_fault_probability")]
+    pub probability: f64,
+
+    /// slowStream：每个事件前的等待时间（毫秒）
+    #[serde(default = "default_fault_delay_ms")]
+    pub delay_ms: u64,
+
+    /// truncatedStream：断开前发送的事件数
+    #[serde(default = "default_fault_after_events")]
+    pub after_events: usize,
+}
+
+fn default_fault_probability() -> f64 {
+    1.0
+}
+
+fn default_fault_delay_ms() -> u64 {
+    1000
+}
+
+fn default_fault_after_events() -> usize {
+    3
+}
+
+/// 模型能力覆盖（键为 Kiro 模型 ID），未配置的字段沿用内置能力表
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ModelCapabilityOverride {
+    /// 上下文窗口（tokens）
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub context_window: Option<u32>,
+
+    /// 最大输出 tokens，请求的 max_tokens 超出时被截断到该值
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_output_tokens: Option<u32>,
+
+    /// 是否支持 thinking
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub supports_thinking: Option<bool>,
+
+    /// 是否支持图片输入
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub supports_images: Option<bool>,
+}
+
+/// HTTP 客户端画像（User-Agent 中的版本信息与 TLS 后端）
+///
+/// 未配置的字段回退到全局 `kiroVersion` / `systemVersion` / `nodeVersion` / `tlsBackend`
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ClientProfile {
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub kiro_version: Option<String>,
+
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub system_version: Option<String>,
+
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub node_version: Option<String>,
+
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tls_backend: Option<TlsBackend>,
+}
+
 /// KNA 应用配置
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
@@ -27,6 +870,16 @@ pub struct Config {
     #[serde(default = "default_port")]
     pub port: u16,
 
+    /// 双栈监听（仅在 host 为 IPv6 地址时生效，如 "::"）
+    ///
+    /// 开启后同一个 IPv6 socket 同时接受 IPv4 连接（关闭 IPV6_V6ONLY）
+    #[serde(default)]
+    pub dual_stack: bool,
+
+    /// 上游连接使用的 IP 协议族（"auto" / "ipv4" / "ipv6"，默认 "auto"）
+    #[serde(default)]
+    pub upstream_ip_family: IpFamily,
+
     #[serde(default = "default_region")]
     pub region: String,
 
@@ -40,6 +893,11 @@ pub struct Config {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub api_region: Option<String>,
 
+    /// 允许客户端通过 `X-Kiro-Region` 请求头指定的 API Region（为空时不允许覆盖）
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub allowed_api_regions: Vec<String>,
+
     #[serde(default = "default_kiro_version")]
     pub kiro_version: String,
 
@@ -58,6 +916,14 @@ pub struct Config {
     #[serde(default = "default_tls_backend")]
     pub tls_backend: TlsBackend,
 
+    /// 客户端画像池（可选）
+    ///
+    /// 未设置 `clientProfile` 的凭据按 id 轮流分配其中一个画像，使同一台机器上的
+    /// 多个凭据呈现不同的 User-Agent 与 TLS 指纹
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub client_profiles: Vec<ClientProfile>,
+
     /// 外部 count_tokens API 地址（可选）
     #[serde(default)]
     pub count_tokens_api_url: Option<String>,
@@ -87,6 +953,10 @@ pub struct Config {
     #[serde(default)]
     pub admin_api_key: Option<String>,
 
+    /// Admin UI 访问路径（默认 `/admin`），不能与 `/v1`、`/cc`、`/api` 等 API 路由冲突
+    #[serde(default = "default_admin_ui_path")]
+    pub admin_ui_path: String,
+
     /// 负载均衡模式（"priority" 或 "balanced"）
     #[serde(default = "default_load_balancing_mode")]
     pub load_balancing_mode: String,
@@ -98,6 +968,19 @@ pub struct Config {
     #[serde(default = "default_extract_thinking")]
     pub extract_thinking: bool,
 
+    /// 是否修复历史中的 tool_use/tool_result 配对（默认 false）
+    ///
+    /// 启用后为缺少 tool_result 的孤立 tool_use 补充占位结果（而不是移除该 tool_use），
+    /// 并合并重复的 tool_use_id，避免 Kiro 拒绝整段历史。
+    #[serde(default)]
+    pub repair_tool_history: bool,
+
+    /// 是否将带文本字段的未识别上游事件按助手文本输出（默认 false）
+    ///
+    /// 上游新增事件类型时，payload 顶层带 `content` / `text` 字符串的事件不再被丢弃
+    #[serde(default)]
+    pub map_unknown_text_events: bool,
+
     /// 默认端点名称（凭据未显式指定 endpoint 时使用，默认 "ide"）
     #[serde(default = "default_endpoint")]
     pub default_endpoint: String,
@@ -109,6 +992,238 @@ pub struct Config {
     #[serde(default)]
     pub endpoints: HashMap<String, serde_json::Value>,
 
+    /// 模型 fallback 规则
+    ///
+    /// 键为客户端请求的模型名，值为按顺序尝试的备用模型。
+    /// 请求模型因模型无效或额度用尽失败时，依次改用备用模型重试。
+    /// 客户端可通过 `x-kiro-model-fallback` 请求头按请求覆盖。
+    #[serde(default)]
+    #[serde(skip_serializing_if = "HashMap::is_empty")]
+    pub model_fallbacks: HashMap<String, Vec<String>>,
+
+    /// 模型别名
+    ///
+    /// 键为客户端请求的模型名（不区分大小写），值为 Kiro 模型 ID，优先于内置映射
+    #[serde(default)]
+    #[serde(skip_serializing_if = "HashMap::is_empty")]
+    pub model_aliases: HashMap<String, String>,
+
+    /// 模型能力覆盖（键为 Kiro 模型 ID），用于截断 max_tokens、拒绝不支持的功能与生成模型列表
+    #[serde(default)]
+    #[serde(skip_serializing_if = "HashMap::is_empty")]
+    pub model_capabilities: HashMap<String, ModelCapabilityOverride>,
+
+    /// 请求改写规则（在请求转换前按顺序评估）
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub request_transforms: Vec<RequestTransform>,
+
+    /// 按模型注入的 system 提示前缀/后缀（可通过 Admin API 在运行时修改）
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub system_prompts: Vec<SystemPromptRule>,
+
+    /// 输出脱敏规则（违禁字符串/正则），命中的文本替换后再发送给客户端
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub output_redactions: Vec<OutputRedaction>,
+
+    /// 按模型路由到凭据分组的规则（按顺序匹配，第一条匹配的规则生效）
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub model_routes: Vec<ModelRoute>,
+
+    /// 模型价格表（按顺序匹配，覆盖内置的 Anthropic 标价），用于费用估算
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub model_prices: Vec<ModelPriceRule>,
+
+    /// 从 Kiro 拉取可用模型列表的间隔（秒），0 表示关闭
+    ///
+    /// 开启后映射结果不在可用列表中的请求自动改用同系列的可用模型
+    #[serde(default)]
+    pub model_registry_refresh_secs: u64,
+
+    /// 滚动窗口 token 配额（按 API Key 统计，可配置多个窗口）
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub token_quotas: Vec<TokenQuota>,
+
+    /// 禁用凭据健康检查间隔（秒），0 表示关闭
+    ///
+    /// 定期探测因连续失败、刷新失败或额度用尽而被自动禁用的凭据，恢复可用者
+    #[serde(default)]
+    pub health_check_interval_secs: u64,
+
+    /// 健康检查间隔的随机抖动上限（秒），避免多实例同时探测
+    #[serde(default = "default_health_check_jitter_secs")]
+    pub health_check_jitter_secs: u64,
+
+    /// 凭据级代理健康探测间隔（秒），0 表示关闭
+    ///
+    /// 定期经由各凭据的主代理与备用代理探测上游，及时切换或恢复代理
+    #[serde(default)]
+    pub proxy_health_check_interval_secs: u64,
+
+    /// 启动时是否在后台并发验证所有凭据并输出汇总表
+    #[serde(default)]
+    pub validate_credentials_on_startup: bool,
+
+    /// 请求体积告警阈值（估算 tokens），0 表示关闭
+    ///
+    /// 最近请求的 p95 估算 tokens 达到该值时输出告警日志
+    #[serde(default)]
+    pub request_size_alert_tokens: u64,
+
+    /// 是否录制上游原始事件流（调试用，保存在内存中供 Admin API 回放）
+    #[serde(default)]
+    pub debug_capture_frames: bool,
+
+    /// Message Batches API 的执行并发数（所有批次共享）
+    #[serde(default = "default_batch_concurrency")]
+    pub batch_concurrency: usize,
+
+    /// 流式响应等待上游期间发送 ping 保活的间隔（秒），0 表示不发送
+    #[serde(default = "default_sse_ping_interval_secs")]
+    pub sse_ping_interval_secs: u64,
+
+    /// ping 保活的格式（"event" / "comment"，默认 "event"）
+    #[serde(default)]
+    pub sse_ping_style: SsePingStyle,
+
+    /// 单个请求与上游交互的总时限（秒，含等待响应与读取完整响应流），0 表示不限制
+    ///
+    /// 客户端可通过 `x-kiro-request-timeout` 请求头按请求覆盖
+    #[serde(default)]
+    pub request_timeout_secs: u64,
+
+    /// Anthropic API 请求体大小上限（MB），超出时返回 413 `request_too_large`
+    #[serde(default = "default_max_request_body_mb")]
+    pub max_request_body_mb: usize,
+
+    /// 图片压缩（未配置时图片原样转发）
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub image_compression: Option<ImageCompressionConfig>,
+
+    /// 本地 WebSearch 后端（未配置时 web_search 工具请求转发到 Kiro MCP）
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub web_search: Option<WebSearchConfig>,
+
+    /// Embeddings 转发上游（未配置时 /v1/embeddings 返回 404）
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub embeddings: Option<EmbeddingsConfig>,
+
+    /// 影子流量（未配置时不复制请求）
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub shadow: Option<ShadowConfig>,
+
+    /// 按凭据的自适应并发控制（未配置时不调整并发上限）
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub adaptive_concurrency: Option<AdaptiveConcurrencyConfig>,
+
+    /// 按凭据的熔断配置（未配置时不熔断）
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub circuit_breaker: Option<CircuitBreakerConfig>,
+
+    /// Token 预刷新（未配置时只在请求时按需刷新）
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub warm_refresh: Option<WarmRefreshConfig>,
+
+    /// 日志文件配置（未配置时只输出到 stdout）
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub log_file: Option<LogFileConfig>,
+
+    /// OTLP 链路追踪导出（未配置时不导出）
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub otlp: Option<OtlpConfig>,
+
+    /// 会话亲和路由配置（未配置时不绑定）
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub session_affinity: Option<SessionAffinityConfig>,
+
+    /// 会话记忆配置（未配置时不保存也不注入）
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub session_memory: Option<SessionMemoryConfig>,
+
+    /// Files API 配置（未配置时 /v1/files 返回 404）
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub files: Option<FilesConfig>,
+
+    /// Token 用量账本（未配置时不记录）
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub usage_ledger: Option<UsageLedgerConfig>,
+
+    /// 把 metadata.user_id 的哈希转发给 Kiro 时使用的请求头（未配置时不转发）
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub user_id_header: Option<String>,
+
+    /// 非流式请求的响应缓存（未配置时不缓存）
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub response_cache: Option<ResponseCacheConfig>,
+
+    /// 合并完全相同的并发非流式请求：后到的请求等待进行中的请求并复用其响应（默认关闭）
+    #[serde(default)]
+    pub coalesce_requests: bool,
+
+    /// 按 Accept-Encoding 以 zstd/gzip 压缩非流式响应（SSE 事件流不压缩，默认关闭）
+    #[serde(default)]
+    pub compress_responses: bool,
+
+    /// 额度使用率告警（未配置时不告警）
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub quota_alerts: Option<QuotaAlertConfig>,
+
+    /// 公共 API 限流配置（未配置时不限流）
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub rate_limit: Option<RateLimitConfig>,
+
+    /// 故障注入规则（仅用于测试，按顺序评估，第一条命中的规则生效）
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub fault_injection: Vec<FaultRule>,
+
+    /// 全局准入控制（未配置时不限制总在途请求数）
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub admission: Option<AdmissionConfig>,
+
+    /// 每个凭据的最大在途请求数，0 表示不限制
+    ///
+    /// 与自适应上限同时配置时取较小者
+    #[serde(default)]
+    pub max_in_flight_per_credential: usize,
+
+    /// 凭据并发已满时每个凭据最多排队等待的请求数，0 表示不排队直接拒绝
+    #[serde(default = "default_concurrency_queue_size")]
+    pub concurrency_queue_size: usize,
+
+    /// 排队等待并发名额的超时时间（秒）
+    #[serde(default = "default_concurrency_queue_timeout_secs")]
+    pub concurrency_queue_timeout_secs: u64,
+
+    /// 配置文件热加载检查间隔（秒），0 表示关闭
+    ///
+    /// 开启后定期检查 config.json 的修改时间，变化时重新加载并应用可热更新的字段
+    #[serde(default)]
+    pub config_reload_interval_secs: u64,
+
     /// 配置文件路径（运行时元数据，不写入 JSON）
     #[serde(skip)]
     config_path: Option<PathBuf>,
@@ -143,6 +1258,10 @@ fn default_count_tokens_auth_type() -> String {
     "x-api-key".to_string()
 }
 
+fn default_admin_ui_path() -> String {
+    "/admin".to_string()
+}
+
 fn default_tls_backend() -> TlsBackend {
     TlsBackend::Rustls
 }
@@ -155,6 +1274,30 @@ fn default_extract_thinking() -> bool {
     true
 }
 
+fn default_health_check_jitter_secs() -> u64 {
+    60
+}
+
+fn default_batch_concurrency() -> usize {
+    4
+}
+
+fn default_sse_ping_interval_secs() -> u64 {
+    25
+}
+
+fn default_max_request_body_mb() -> usize {
+    50
+}
+
+fn default_concurrency_queue_size() -> usize {
+    64
+}
+
+fn default_concurrency_queue_timeout_secs() -> u64 {
+    60
+}
+
 fn default_endpoint() -> String {
     crate::kiro::endpoint::ide::IDE_ENDPOINT_NAME.to_string()
 }
@@ -164,15 +1307,19 @@ impl Default for Config {
         Self {
             host: default_host(),
             port: default_port(),
+            dual_stack: false,
+            upstream_ip_family: IpFamily::default(),
             region: default_region(),
             auth_region: None,
             api_region: None,
+            allowed_api_regions: Vec::new(),
             kiro_version: default_kiro_version(),
             machine_id: None,
             api_key: None,
             system_version: default_system_version(),
             node_version: default_node_version(),
             tls_backend: default_tls_backend(),
+            client_profiles: Vec::new(),
             count_tokens_api_url: None,
             count_tokens_api_key: None,
             count_tokens_auth_type: default_count_tokens_auth_type(),
@@ -180,10 +1327,59 @@ impl Default for Config {
             proxy_username: None,
             proxy_password: None,
             admin_api_key: None,
+            admin_ui_path: default_admin_ui_path(),
             load_balancing_mode: default_load_balancing_mode(),
             extract_thinking: default_extract_thinking(),
+            repair_tool_history: false,
+            map_unknown_text_events: false,
             default_endpoint: default_endpoint(),
             endpoints: HashMap::new(),
+            model_fallbacks: HashMap::new(),
+            model_aliases: HashMap::new(),
+            model_capabilities: HashMap::new(),
+            request_transforms: Vec::new(),
+            system_prompts: Vec::new(),
+            output_redactions: Vec::new(),
+            model_routes: Vec::new(),
+            model_prices: Vec::new(),
+            model_registry_refresh_secs: 0,
+            token_quotas: Vec::new(),
+            health_check_interval_secs: 0,
+            health_check_jitter_secs: default_health_check_jitter_secs(),
+            proxy_health_check_interval_secs: 0,
+            validate_credentials_on_startup: false,
+            request_size_alert_tokens: 0,
+            debug_capture_frames: false,
+            batch_concurrency: default_batch_concurrency(),
+            sse_ping_interval_secs: default_sse_ping_interval_secs(),
+            sse_ping_style: SsePingStyle::default(),
+            request_timeout_secs: 0,
+            max_request_body_mb: default_max_request_body_mb(),
+            image_compression: None,
+            web_search: None,
+            embeddings: None,
+            shadow: None,
+            adaptive_concurrency: None,
+            circuit_breaker: None,
+            warm_refresh: None,
+            log_file: None,
+            otlp: None,
+            session_affinity: None,
+            session_memory: None,
+            files: None,
+            usage_ledger: None,
+            user_id_header: None,
+            response_cache: None,
+            coalesce_requests: false,
+            compress_responses: false,
+            quota_alerts: None,
+            rate_limit: None,
+            fault_injection: Vec::new(),
+            admission: None,
+            max_in_flight_per_credential: 0,
+            concurrency_queue_size: default_concurrency_queue_size(),
+            concurrency_queue_timeout_secs: default_concurrency_queue_timeout_secs(),
+            config_reload_interval_secs: 0,
             config_path: None,
         }
     }
@@ -223,6 +1419,51 @@ impl Config {
         Ok(config)
     }
 
+    /// 加载配置并按顺序叠加 profile
+    ///
+    /// profile 文件位于配置文件同级的 `config.d/<profile>.json`，
+    /// 以 `path` 为基础按 `profiles` 顺序逐个深度合并（后者覆盖前者）：
+    /// - 对象按键递归合并
+    /// - 其他值（含数组）整体替换
+    /// - 值为 `null` 时删除该键（恢复默认值）
+    ///
+    /// 指定的 profile 文件不存在时报错，避免拼写错误被静默忽略。
+    /// `save()` 仍写回基础配置文件，不会把 profile 覆盖项固化进去。
+    pub fn load_with_profiles<P: AsRef<Path>>(
+        path: P,
+        profiles: &[String],
+    ) -> anyhow::Result<Self> {
+        if profiles.is_empty() {
+            return Self::load(path);
+        }
+
+        let path = path.as_ref();
+        let mut merged = if path.exists() {
+            let content = fs::read_to_string(path)?;
+            serde_json::from_str::<serde_json::Value>(&content)
+                .with_context(|| format!("解析配置文件失败: {}", path.display()))?
+        } else {
+            serde_json::Value::Object(Default::default())
+        };
+
+        let profile_dir = path
+            .parent()
+            .unwrap_or_else(|| Path::new(""))
+            .join("config.d");
+        for profile in profiles {
+            let profile_path = profile_dir.join(format!("{}.json", profile));
+            let content = fs::read_to_string(&profile_path)
+                .with_context(|| format!("读取 profile 失败: {}", profile_path.display()))?;
+            let overlay: serde_json::Value = serde_json::from_str(&content)
+                .with_context(|| format!("解析 profile 失败: {}", profile_path.display()))?;
+            merge_json(&mut merged, overlay);
+        }
+
+        let mut config: Config = serde_json::from_value(merged).context("解析合并后的配置失败")?;
+        config.config_path = Some(path.to_path_buf());
+        Ok(config)
+    }
+
     /// 获取配置文件路径（如果有）
     pub fn config_path(&self) -> Option<&Path> {
         self.config_path.as_deref()
@@ -240,3 +1481,85 @@ impl Config {
         Ok(())
     }
 }
+
+/// 将 `overlay` 深度合并到 `base`
+fn merge_json(base: &mut serde_json::Value, overlay: serde_json::Value) {
+    match (base, overlay) {
+        (serde_json::Value::Object(base), serde_json::Value::Object(overlay)) => {
+            for (key, value) in overlay {
+                if value.is_null() {
+                    base.remove(&key);
+                } else if let Some(existing) = base.get_mut(&key) {
+                    merge_json(existing, value);
+                } else {
+                    base.insert(key, value);
+                }
+            }
+        }
+        (base, overlay) => *base = overlay,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_merge_json_overrides_and_nests() {
+        let mut base = json!({
+            "port": 8080,
+            "endpoints": {"ide": {"a": 1, "b": 2}},
+            "modelFallbacks": {"x": ["a", "b"]}
+        });
+        merge_json(
+            &mut base,
+            json!({
+                "port": 9000,
+                "endpoints": {"ide": {"b": 3}},
+                "modelFallbacks": {"x": ["c"]}
+            }),
+        );
+        assert_eq!(
+            base,
+            json!({
+                "port": 9000,
+                "endpoints": {"ide": {"a": 1, "b": 3}},
+                "modelFallbacks": {"x": ["c"]}
+            })
+        );
+    }
+
+    #[test]
+    fn test_merge_json_null_removes_key() {
+        let mut base = json!({"proxyUrl": "http://127.0.0.1:7890", "port": 1});
+        merge_json(&mut base, json!({"proxyUrl": null}));
+        assert_eq!(base, json!({"port": 1}));
+    }
+
+    #[test]
+    fn test_load_with_profiles_applies_in_order() {
+        let dir =
+            std::env::temp_dir().join(format!("kiro-rs-profile-test-{}", uuid::Uuid::new_v4()));
+        fs::create_dir_all(dir.join("config.d")).unwrap();
+        let base = dir.join("config.json");
+        fs::write(&base, r#"{"port": 8080, "loadBalancingMode": "priority"}"#).unwrap();
+        fs::write(dir.join("config.d/prod.json"), r#"{"port": 9000}"#).unwrap();
+        fs::write(
+            dir.join("config.d/debug.json"),
+            r#"{"port": 9001, "extractThinking": false}"#,
+        )
+        .unwrap();
+
+        let config =
+            Config::load_with_profiles(&base, &["prod".to_string(), "debug".to_string()]).unwrap();
+        assert_eq!(config.port, 9001);
+        assert!(!config.extract_thinking);
+        assert_eq!(config.load_balancing_mode, "priority");
+        assert_eq!(config.config_path(), Some(base.as_path()));
+
+        assert!(Config::load_with_profiles(&base, &["missing".to_string()]).is_err());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
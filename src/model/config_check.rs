@@ -0,0 +1,790 @@
+//! 配置文件检查
+//!
+//! 加载配置时 serde 会静默忽略未知字段并为缺失字段填入默认值，拼错的键名或放错层级的
+//! 选项不会有任何提示。本模块按 [`config_schema`](super::config_schema) 中的 Schema
+//! 检查 config.json 与 credentials.json，报告：
+//! - 未知字段（附带最接近的已知字段名）、类型不符、取值越界或不在枚举内
+//! - 互斥的选项（如 API Key 凭据同时配置 refreshToken）
+//! - 缺失的必需组合（如 IdC 凭据缺少 clientId / clientSecret）
+//!
+//! 每条问题都带有所在文件与行号。`kiro-rs check-config` 输出检查结果，
+//! 启动服务时同样会检查，`--strict-config` 下存在错误时拒绝启动。
+
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::path::Path;
+
+use regex::Regex;
+use serde_json::Value;
+
+use super::config::Config;
+use super::config_schema::{config_schema, credential_schema};
+use crate::kiro::machine_id::normalize_machine_id;
+use crate::kiro::schedule::Schedule;
+
+/// 问题级别
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    /// 配置不会按预期生效
+    Error,
+    /// 可能是配置错误，但不影响启动
+    Warning,
+}
+
+/// 配置文件中的一个问题
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Issue {
+    pub severity: Severity,
+    pub file: String,
+    /// 所在行号（从 1 开始）
+    pub line: Option<usize>,
+    /// 字段的 JSON Pointer（如 `/rateLimit/perKeyRpm`），为空表示整个文件
+    pub path: String,
+    pub message: String,
+}
+
+impl fmt::Display for Issue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let level = match self.severity {
+            Severity::Error => "错误",
+            Severity::Warning => "警告",
+        };
+        write!(f, "{}", self.file)?;
+        if let Some(line) = self.line {
+            write!(f, ":{}", line)?;
+        }
+        write!(f, ": {}: ", level)?;
+        if !self.path.is_empty() {
+            write!(f, "{}: ", self.path)?;
+        }
+        write!(f, "{}", self.message)
+    }
+}
+
+/// 检查配置文件与凭据文件（文件不存在时按默认值检查）
+pub fn check_files(config_path: &Path, credentials_path: &Path) -> Vec<Issue> {
+    let mut issues = match std::fs::read_to_string(config_path) {
+        Ok(text) => check_config(&config_path.display().to_string(), &text),
+        Err(_) => check_config(&config_path.display().to_string(), "{}"),
+    };
+    if let Ok(text) = std::fs::read_to_string(credentials_path) {
+        issues.extend(check_credentials(
+            &credentials_path.display().to_string(),
+            &text,
+        ));
+    }
+    issues
+}
+
+/// 检查 config.json 的内容
+pub fn check_config(file: &str, text: &str) -> Vec<Issue> {
+    let (mut checker, root) = match Checker::parse(file, text) {
+        Ok(parsed) => parsed,
+        Err(issue) => return vec![issue],
+    };
+    checker.validate(&config_schema(), &root, "");
+    let schema_ok = checker.issues.iter().all(|i| i.severity != Severity::Error);
+    if schema_ok && let Err(e) = serde_json::from_str::<Config>(text) {
+        checker.issues.push(Issue {
+            severity: Severity::Error,
+            file: file.to_string(),
+            line: Some(e.line()),
+            path: String::new(),
+            message: e.to_string(),
+        });
+    }
+    checker.check_config_rules(&root);
+    checker.finish()
+}
+
+/// 检查 credentials.json 的内容（单个凭据或凭据数组，空文件视为没有凭据）
+pub fn check_credentials(file: &str, text: &str) -> Vec<Issue> {
+    if text.trim().is_empty() {
+        return Vec::new();
+    }
+    let (mut checker, root) = match Checker::parse(file, text) {
+        Ok(parsed) => parsed,
+        Err(issue) => return vec![issue],
+    };
+    let schema = credential_schema();
+    match &root {
+        Value::Array(items) => {
+            let mut ids = HashSet::new();
+            for (i, item) in items.iter().enumerate() {
+                let path = format!("/{}", i);
+                checker.validate(&schema, item, &path);
+                checker.check_credential_rules(item, &path);
+                if let Some(id) = item.get("id").and_then(Value::as_u64)
+                    && !ids.insert(id)
+                {
+                    checker.error(&format!("{}/id", path), format!("凭据 id {} 重复", id));
+                }
+            }
+        }
+        Value::Object(_) => {
+            checker.validate(&schema, &root, "");
+            checker.check_credential_rules(&root, "");
+        }
+        _ => checker.error("", "凭据文件必须是凭据对象或凭据数组".to_string()),
+    }
+    checker.finish()
+}
+
+struct Checker {
+    file: String,
+    /// JSON Pointer → 行号
+    lines: HashMap<String, usize>,
+    issues: Vec<Issue>,
+}
+
+impl Checker {
+    /// 解析 JSON 文本，返回检查器与解析出的值
+    fn parse(file: &str, text: &str) -> Result<(Self, Value), Issue> {
+        let root: Value = serde_json::from_str(text).map_err(|e| Issue {
+            severity: Severity::Error,
+            file: file.to_string(),
+            line: Some(e.line()),
+            path: String::new(),
+            message: format!("JSON 解析失败: {}", e),
+        })?;
+        let checker = Self {
+            file: file.to_string(),
+            lines: line_index(text),
+            issues: Vec::new(),
+        };
+        Ok((checker, root))
+    }
+
+    fn push(&mut self, severity: Severity, path: &str, message: String) {
+        // 缺失的字段没有行号，使用最近的上级字段所在行
+        let mut pointer = path;
+        let line = loop {
+            if let Some(line) = self.lines.get(pointer) {
+                break Some(*line);
+            }
+            match pointer.rfind('/') {
+                Some(pos) => pointer = &pointer[..pos],
+                None => break None,
+            }
+        };
+        self.issues.push(Issue {
+            severity,
+            file: self.file.clone(),
+            line,
+            path: path.to_string(),
+            message,
+        });
+    }
+
+    /// 按行号排序（同一行保持发现顺序）
+    fn finish(mut self) -> Vec<Issue> {
+        self.issues.sort_by_key(|issue| issue.line);
+        self.issues
+    }
+
+    fn error(&mut self, path: &str, message: String) {
+        self.push(Severity::Error, path, message);
+    }
+
+    fn warn(&mut self, path: &str, message: String) {
+        self.push(Severity::Warning, path, message);
+    }
+
+    /// 按 Schema 检查值（支持本项目 Schema 用到的关键字）
+    fn validate(&mut self, schema: &Value, value: &Value, path: &str) {
+        if let Some(expected) = schema.get("type") {
+            let types: Vec<&str> = match expected {
+                Value::String(t) => vec![t.as_str()],
+                Value::Array(ts) => ts.iter().filter_map(Value::as_str).collect(),
+                _ => Vec::new(),
+            };
+            if !types.is_empty() && !types.iter().any(|t| matches_type(t, value)) {
+                self.error(
+                    path,
+                    format!(
+                        "类型错误：期望 {}，实际为 {}",
+                        types.join(" 或 "),
+                        type_name(value)
+                    ),
+                );
+                return;
+            }
+        }
+
+        if let Some(allowed) = schema.get("enum").and_then(Value::as_array)
+            && !allowed.contains(value)
+        {
+            let allowed: Vec<String> = allowed.iter().map(Value::to_string).collect();
+            self.error(
+                path,
+                format!("取值 {} 无效，可选值: {}", value, allowed.join(", ")),
+            );
+        }
+
+        if let Some(n) = value.as_f64() {
+            if let Some(min) = schema.get("minimum").and_then(Value::as_f64)
+                && n < min
+            {
+                self.error(path, format!("取值 {} 小于最小值 {}", value, min));
+            }
+            if let Some(min) = schema.get("exclusiveMinimum").and_then(Value::as_f64)
+                && n <= min
+            {
+                self.error(path, format!("取值 {} 必须大于 {}", value, min));
+            }
+            if let Some(max) = schema.get("maximum").and_then(Value::as_f64)
+                && n > max
+            {
+                self.error(path, format!("取值 {} 大于最大值 {}", value, max));
+            }
+        }
+
+        if let (Some(pattern), Some(s)) = (
+            schema.get("pattern").and_then(Value::as_str),
+            value.as_str(),
+        ) && Regex::new(pattern).is_ok_and(|re| !re.is_match(s))
+        {
+            self.error(
+                path,
+                format!("取值 \"{}\" 格式无效（应匹配 {}）", s, pattern),
+            );
+        }
+
+        match value {
+            Value::Object(object) => {
+                let properties = schema.get("properties").and_then(Value::as_object);
+                for (key, child) in object {
+                    let child_path = format!("{}/{}", path, escape_pointer(key));
+                    match properties.and_then(|p| p.get(key)) {
+                        Some(child_schema) => self.validate(child_schema, child, &child_path),
+                        None => match schema.get("additionalProperties") {
+                            Some(Value::Bool(false)) => {
+                                let hint = properties
+                                    .and_then(|p| closest_key(key, p.keys()))
+                                    .map(|k| format!("，是否为 \"{}\"？", k))
+                                    .unwrap_or_default();
+                                self.error(
+                                    &child_path,
+                                    format!("未知字段 \"{}\"，将被忽略{}", key, hint),
+                                );
+                            }
+                            Some(extra @ Value::Object(_)) => {
+                                self.validate(extra, child, &child_path)
+                            }
+                            _ => {}
+                        },
+                    }
+                }
+                for key in schema
+                    .get("required")
+                    .and_then(Value::as_array)
+                    .into_iter()
+                    .flatten()
+                    .filter_map(Value::as_str)
+                {
+                    if !object.contains_key(key) {
+                        self.error(path, format!("缺少必需字段 \"{}\"", key));
+                    }
+                }
+            }
+            Value::Array(items) => {
+                if let Some(item_schema) = schema.get("items") {
+                    for (i, item) in items.iter().enumerate() {
+                        self.validate(item_schema, item, &format!("{}/{}", path, i));
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// config.json 中 Schema 无法表达的组合规则
+    fn check_config_rules(&mut self, root: &Value) {
+        let has = |key: &str| root.get(key).is_some_and(|v| !v.is_null());
+
+        if root
+            .get("apiKey")
+            .and_then(Value::as_str)
+            .is_none_or(str::is_empty)
+        {
+            self.error("/apiKey", "未设置 apiKey，服务无法启动".to_string());
+        }
+        if has("proxyUsername") != has("proxyPassword") {
+            self.error(
+                "/proxyUsername",
+                "proxyUsername 与 proxyPassword 必须同时配置".to_string(),
+            );
+        }
+        if (has("proxyUsername") || has("proxyPassword")) && !has("proxyUrl") {
+            self.warn(
+                "/proxyUsername",
+                "配置了代理认证但未配置 proxyUrl，代理认证不会生效".to_string(),
+            );
+        }
+        if has("countTokensApiKey") && !has("countTokensApiUrl") {
+            self.warn(
+                "/countTokensApiKey",
+                "配置了 countTokensApiKey 但未配置 countTokensApiUrl，将不会使用外部 count_tokens API"
+                    .to_string(),
+            );
+        }
+        if root.get("dualStack").and_then(Value::as_bool) == Some(true)
+            && !root
+                .get("host")
+                .and_then(Value::as_str)
+                .is_some_and(|h| h.contains(':'))
+        {
+            self.warn(
+                "/dualStack",
+                "dualStack 仅在 host 为 IPv6 地址（如 \"::\"）时生效".to_string(),
+            );
+        }
+        if let Some(header) = root.get("userIdHeader").and_then(Value::as_str)
+            && axum::http::HeaderName::from_bytes(header.as_bytes()).is_err()
+        {
+            self.error(
+                "/userIdHeader",
+                format!("\"{}\" 不是有效的请求头名称", header),
+            );
+        }
+        if let Some(path) = root.get("adminUiPath").and_then(Value::as_str)
+            && let Err(e) = crate::admin_ui::normalize_base_path(path)
+        {
+            self.error("/adminUiPath", e.to_string());
+        }
+        // 请求路径在过期前 10 分钟内就会同步刷新，预刷新提前量不超过该值时没有效果
+        if let Some(margin) = root
+            .pointer("/warmRefresh/marginSecs")
+            .and_then(Value::as_u64)
+            && margin <= 600
+        {
+            self.warn(
+                "/warmRefresh/marginSecs",
+                "marginSecs 不大于 600 时 Token 会先被请求按需刷新，预刷新不会生效".to_string(),
+            );
+        }
+        if root
+            .get("faultInjection")
+            .and_then(Value::as_array)
+            .is_some_and(|rules| !rules.is_empty())
+        {
+            self.warn(
+                "/faultInjection",
+                "故障注入已开启，匹配的请求会按概率失败，仅用于测试环境".to_string(),
+            );
+        }
+    }
+
+    /// 单个凭据的组合规则
+    fn check_credential_rules(&mut self, cred: &Value, path: &str) {
+        if !cred.is_object() {
+            return;
+        }
+        let get = |key: &str| {
+            cred.get(key)
+                .and_then(Value::as_str)
+                .filter(|v| !v.is_empty())
+        };
+        let at = |key: &str| format!("{}/{}", path, key);
+
+        let auth_method = get("authMethod").map(str::to_ascii_lowercase);
+        let kind = match auth_method.as_deref() {
+            None | Some("social") => "social",
+            Some("idc" | "builder-id" | "iam") => "idc",
+            Some("api_key" | "apikey") => "api_key",
+            Some(other) => {
+                self.error(
+                    &at("authMethod"),
+                    format!(
+                        "未知的 authMethod \"{}\"，可选值: social, idc, builder-id, iam, api_key",
+                        other
+                    ),
+                );
+                return;
+            }
+        };
+        // 未显式指定 authMethod 时，同时带 clientId / clientSecret 的凭据按 IdC 刷新
+        let kind = if auth_method.is_none() && get("kiroApiKey").is_some() {
+            "api_key"
+        } else if auth_method.is_none()
+            && get("clientId").is_some()
+            && get("clientSecret").is_some()
+        {
+            "idc"
+        } else {
+            kind
+        };
+
+        match kind {
+            "api_key" => {
+                if get("kiroApiKey").is_none() {
+                    self.error(
+                        path,
+                        "authMethod 为 api_key 的凭据缺少 kiroApiKey".to_string(),
+                    );
+                }
+                if get("refreshToken").is_some() {
+                    self.warn(
+                        &at("refreshToken"),
+                        "API Key 凭据与 refreshToken 互斥，refreshToken 将被忽略".to_string(),
+                    );
+                }
+            }
+            _ => {
+                if get("kiroApiKey").is_some() {
+                    self.error(
+                        &at("kiroApiKey"),
+                        format!(
+                            "kiroApiKey 与 authMethod \"{}\" 互斥（API Key 凭据应使用 api_key 或省略 authMethod）",
+                            auth_method.as_deref().unwrap_or(kind)
+                        ),
+                    );
+                }
+                if get("refreshToken").is_none() {
+                    self.error(path, "缺少 refreshToken".to_string());
+                }
+            }
+        }
+        if kind == "idc" {
+            for key in ["clientId", "clientSecret"] {
+                if get(key).is_none() {
+                    self.error(path, format!("IdC 凭据缺少 {}", key));
+                }
+            }
+        } else if kind == "social" && (get("clientId").is_some() != get("clientSecret").is_some()) {
+            self.error(
+                path,
+                "clientId 与 clientSecret 必须同时配置（IdC 凭据）".to_string(),
+            );
+        }
+
+        if let Some(machine_id) = get("machineId")
+            && normalize_machine_id(machine_id).is_none()
+        {
+            self.error(
+                &at("machineId"),
+                "machineId 应为 64 位十六进制字符串或 UUID".to_string(),
+            );
+        }
+        if let Some(rules) = cred.get("schedule").and_then(Value::as_array) {
+            let rules: Vec<String> = rules
+                .iter()
+                .filter_map(Value::as_str)
+                .map(str::to_string)
+                .collect();
+            if let Err(e) = Schedule::parse(&rules) {
+                self.error(&at("schedule"), format!("可用时段无效: {}", e));
+            }
+        }
+        if (get("proxyUsername").is_some() || get("proxyPassword").is_some())
+            && get("proxyUrl").is_none()
+            && cred
+                .get("fallbackProxyUrls")
+                .and_then(Value::as_array)
+                .is_none_or(|urls| urls.is_empty())
+        {
+            self.warn(
+                &at("proxyUsername"),
+                "配置了代理认证但未配置 proxyUrl，将使用全局代理设置".to_string(),
+            );
+        }
+    }
+}
+
+fn matches_type(expected: &str, value: &Value) -> bool {
+    match expected {
+        "null" => value.is_null(),
+        "boolean" => value.is_boolean(),
+        "integer" => value.is_i64() || value.is_u64(),
+        "number" => value.is_number(),
+        "string" => value.is_string(),
+        "array" => value.is_array(),
+        "object" => value.is_object(),
+        _ => true,
+    }
+}
+
+fn type_name(value: &Value) -> &'static str {
+    match value {
+        Value::Null => "null",
+        Value::Bool(_) => "boolean",
+        Value::Number(n) if n.is_f64() => "number",
+        Value::Number(_) => "integer",
+        Value::String(_) => "string",
+        Value::Array(_) => "array",
+        Value::Object(_) => "object",
+    }
+}
+
+fn escape_pointer(key: &str) -> String {
+    key.replace('~', "~0").replace('/', "~1")
+}
+
+/// 找出与未知字段最接近的已知字段（忽略大小写、下划线与连字符，编辑距离不超过 2）
+fn closest_key<'a>(key: &str, known: impl Iterator<Item = &'a String>) -> Option<&'a str> {
+    let normalize = |s: &str| -> Vec<char> {
+        s.chars()
+            .filter(|c| *c != '_' && *c != '-')
+            .flat_map(char::to_lowercase)
+            .collect()
+    };
+    let target = normalize(key);
+    known
+        .map(|k| (edit_distance(&target, &normalize(k)), k))
+        .filter(|(distance, _)| *distance <= 2)
+        .min_by_key(|(distance, _)| *distance)
+        .map(|(_, k)| k.as_str())
+}
+
+fn edit_distance(a: &[char], b: &[char]) -> usize {
+    let mut prev: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.iter().enumerate() {
+        let mut current = vec![i + 1];
+        for (j, cb) in b.iter().enumerate() {
+            let cost = usize::from(ca != cb);
+            current.push((prev[j] + cost).min(prev[j + 1] + 1).min(current[j] + 1));
+        }
+        prev = current;
+    }
+    prev[b.len()]
+}
+
+/// 记录合法 JSON 文本中每个值所在的行号（对象成员取键所在行）
+fn line_index(text: &str) -> HashMap<String, usize> {
+    let mut scanner = Scanner {
+        bytes: text.as_bytes(),
+        pos: 0,
+        line: 1,
+        lines: HashMap::new(),
+    };
+    scanner.value(String::new());
+    scanner.lines
+}
+
+struct Scanner<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+    line: usize,
+    lines: HashMap<String, usize>,
+}
+
+impl Scanner<'_> {
+    fn peek(&self) -> Option<u8> {
+        self.bytes.get(self.pos).copied()
+    }
+
+    fn skip_whitespace(&mut self) {
+        while let Some(b) = self.peek() {
+            match b {
+                b'\n' => self.line += 1,
+                b' ' | b'\t' | b'\r' => {}
+                _ => break,
+            }
+            self.pos += 1;
+        }
+    }
+
+    fn value(&mut self, path: String) {
+        self.skip_whitespace();
+        self.lines.entry(path.clone()).or_insert(self.line);
+        match self.peek() {
+            Some(b'{') => self.object(&path),
+            Some(b'[') => self.array(&path),
+            Some(b'"') => {
+                self.string();
+            }
+            _ => {
+                while let Some(b) = self.peek() {
+                    if matches!(b, b',' | b']' | b'}') || b.is_ascii_whitespace() {
+                        break;
+                    }
+                    self.pos += 1;
+                }
+            }
+        }
+    }
+
+    fn object(&mut self, path: &str) {
+        self.pos += 1;
+        loop {
+            self.skip_whitespace();
+            match self.peek() {
+                Some(b'"') => {}
+                Some(b'}') | None => {
+                    self.pos += 1;
+                    return;
+                }
+                Some(_) => {
+                    self.pos += 1;
+                    continue;
+                }
+            }
+            let line = self.line;
+            let key = self.string();
+            let child = format!("{}/{}", path, escape_pointer(&key));
+            self.lines.insert(child.clone(), line);
+            self.skip_whitespace();
+            if self.peek() == Some(b':') {
+                self.pos += 1;
+            }
+            self.value(child);
+            self.skip_whitespace();
+            if self.peek() == Some(b',') {
+                self.pos += 1;
+            }
+        }
+    }
+
+    fn array(&mut self, path: &str) {
+        self.pos += 1;
+        let mut index = 0;
+        loop {
+            self.skip_whitespace();
+            match self.peek() {
+                Some(b']') | None => {
+                    self.pos += 1;
+                    return;
+                }
+                _ => {}
+            }
+            self.value(format!("{}/{}", path, index));
+            index += 1;
+            self.skip_whitespace();
+            if self.peek() == Some(b',') {
+                self.pos += 1;
+            }
+        }
+    }
+
+    /// 读取一个字符串字面量，返回解码后的内容
+    fn string(&mut self) -> String {
+        let start = self.pos;
+        self.pos += 1;
+        while let Some(b) = self.peek() {
+            self.pos += 1;
+            match b {
+                b'\\' => self.pos += 1,
+                b'"' => break,
+                _ => {}
+            }
+        }
+        let literal = &self.bytes[start..self.pos.min(self.bytes.len())];
+        serde_json::from_slice(literal).unwrap_or_default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn messages(issues: &[Issue]) -> Vec<String> {
+        issues.iter().map(|i| i.to_string()).collect()
+    }
+
+    #[test]
+    fn test_default_config_passes_schema() {
+        let mut config = Config::default();
+        config.api_key = Some("sk-test".to_string());
+        let text = serde_json::to_string_pretty(&config).unwrap();
+        assert_eq!(
+            messages(&check_config("config.json", &text)),
+            Vec::<String>::new()
+        );
+    }
+
+    #[test]
+    fn test_config_unknown_keys_and_types() {
+        let text = r#"{
+  "apiKey": "sk-test",
+  "prot": 8080,
+  "rateLimit": {
+    "perKeyRpm": "60",
+    "burts": 10
+  },
+  "loadBalancingMode": "random"
+}"#;
+        let issues = check_config("config.json", text);
+        assert_eq!(
+            messages(&issues),
+            [
+                "config.json:3: 错误: /prot: 未知字段 \"prot\"，将被忽略，是否为 \"port\"？",
+                "config.json:5: 错误: /rateLimit/perKeyRpm: 类型错误：期望 integer，实际为 string",
+                "config.json:6: 错误: /rateLimit/burts: 未知字段 \"burts\"，将被忽略，是否为 \"burst\"？",
+                "config.json:8: 错误: /loadBalancingMode: 取值 \"random\" 无效，可选值: \"priority\", \"balanced\"",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_config_combination_rules() {
+        let issues = check_config(
+            "config.json",
+            r#"{"proxyUsername": "u", "dualStack": true, "userIdHeader": "x user", "adminUiPath": "/api/ui", "warmRefresh": {"marginSecs": 300}, "faultInjection": [{"fault": "serverError"}]}"#,
+        );
+        let summary: Vec<(Severity, &str)> = issues
+            .iter()
+            .map(|i| (i.severity, i.path.as_str()))
+            .collect();
+        assert_eq!(
+            summary,
+            [
+                (Severity::Error, "/apiKey"),
+                (Severity::Error, "/proxyUsername"),
+                (Severity::Warning, "/proxyUsername"),
+                (Severity::Warning, "/dualStack"),
+                (Severity::Error, "/userIdHeader"),
+                (Severity::Error, "/adminUiPath"),
+                (Severity::Warning, "/warmRefresh/marginSecs"),
+                (Severity::Warning, "/faultInjection"),
+            ]
+        );
+        assert_eq!(issues[0].line, Some(1));
+
+        let issues = check_config("config.json", "{\n  \"apiKey\": \"k\",\n}");
+        assert_eq!(issues.len(), 1);
+        assert_eq!(issues[0].line, Some(3));
+        assert!(issues[0].message.starts_with("JSON 解析失败"));
+    }
+
+    #[test]
+    fn test_credentials_rules() {
+        let text = r#"[
+  {"id": 1, "refreshToken": "r", "authMethod": "idc", "clientId": "c"},
+  {"id": 1, "kiroApiKey": "ksk_x", "refreshToken": "r"},
+  {"refreshToken": "r", "authMethod": "social", "kiroApiKey": "ksk_y", "weight": 0},
+  {"refreshToken": "r", "schedule": ["Someday 00:00-01:00"], "machineId": "xyz"},
+  {"refreshToken": "r", "clientId": "c", "clientSecret": "s", "prioirty": 1}
+]"#;
+        let issues = check_credentials("credentials.json", text);
+        let summary: Vec<(Severity, Option<usize>, &str)> = issues
+            .iter()
+            .map(|i| (i.severity, i.line, i.path.as_str()))
+            .collect();
+        assert_eq!(
+            summary,
+            [
+                (Severity::Error, Some(2), "/0"),
+                (Severity::Warning, Some(3), "/1/refreshToken"),
+                (Severity::Error, Some(3), "/1/id"),
+                (Severity::Error, Some(4), "/2/weight"),
+                (Severity::Error, Some(4), "/2/kiroApiKey"),
+                (Severity::Error, Some(5), "/3/machineId"),
+                (Severity::Error, Some(5), "/3/schedule"),
+                (Severity::Error, Some(6), "/4/prioirty"),
+            ]
+        );
+        assert_eq!(issues[0].message, "IdC 凭据缺少 clientSecret");
+
+        assert!(check_credentials("credentials.json", "  ").is_empty());
+        assert!(check_credentials("credentials.json", r#"{"refreshToken": "r"}"#).is_empty());
+    }
+
+    #[test]
+    fn test_line_index() {
+        let lines = line_index("{\n  \"a/b\": [\n    1,\n    {\"c\": \"x\\\"y\"}\n  ]\n}");
+        assert_eq!(lines.get(""), Some(&1));
+        assert_eq!(lines.get("/a~1b"), Some(&2));
+        assert_eq!(lines.get("/a~1b/0"), Some(&3));
+        assert_eq!(lines.get("/a~1b/1/c"), Some(&4));
+    }
+}
@@ -2,3 +2,5 @@
 
 pub mod arg;
 pub mod config;
+pub mod config_check;
+pub mod config_schema;
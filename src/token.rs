@@ -2,10 +2,14 @@
 //!
 //! 提供文本 token 数量计算功能。
 //!
-//! # 计算规则
-//! - 非西文字符：每个计 4.5 个字符单位
-//! - 西文字符：每个计 1 个字符单位
-//! - 4 个字符单位 = 1 token（四舍五入）
+//! 未配置远程 count_tokens API 时使用本地估算：按 BPE 分词器的预分词规则
+//! 把文本切分为单词、数字、标点与空白片段，再按各类片段的典型合并长度估算 token 数，
+//! 对代码（大量标点、缩进与驼峰标识符）的估算比按字符数换算准确得多。
+//! 请求级估算还计入思考内容、工具调用与结果、工具定义（含上游注入的工具系统提示）
+//! 以及按尺寸换算的图片 tokens。
+
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD as BASE64;
 
 use crate::anthropic::types::{
     CountTokensRequest, CountTokensResponse, Message, SystemMessage, Tool,
@@ -72,34 +76,74 @@ fn is_non_western_char(c: char) -> bool {
 /// 计算文本的 token 数量
 ///
 /// # 计算规则
-/// - 非西文字符：每个计 4.5 个字符单位
-/// - 西文字符：每个计 1 个字符单位
-/// - 4 个字符单位 = 1 token（四舍五入）
-/// ```
+/// - 非西文字符（中日韩等）：每个字符计 1 token
+/// - 单词：按驼峰边界拆分，每段 8 个字母以内计 1 token，更长的每 8 个字母计 1 token
+/// - 数字：每 3 位计 1 token
+/// - 标点符号：每 2 个连续符号计 1 token
+/// - 连续换行计 1 token；连续空格/制表符（缩进）每 16 个计 1 token，
+///   单词或标点前的一个空格与其合并、不单独计数
 pub fn count_tokens(text: &str) -> u64 {
-    // println!("text: {}", text);
-
-    let char_units: f64 = text
-        .chars()
-        .map(|c| if is_non_western_char(c) { 4.0 } else { 1.0 })
-        .sum();
-
-    let tokens = char_units / 4.0;
+    let chars: Vec<char> = text.chars().collect();
+    let mut tokens = 0u64;
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        let start = i;
+        if is_non_western_char(c) {
+            tokens += 1;
+            i += 1;
+        } else if c.is_alphabetic() {
+            let mut segment = 0;
+            while i < chars.len() && chars[i].is_alphabetic() && !is_non_western_char(chars[i]) {
+                // 驼峰边界（小写后接大写）处切分
+                if segment > 0 && chars[i - 1].is_lowercase() && chars[i].is_uppercase() {
+                    tokens += word_tokens(segment);
+                    segment = 0;
+                }
+                segment += 1;
+                i += 1;
+            }
+            tokens += word_tokens(segment);
+        } else if c.is_ascii_digit() {
+            while i < chars.len() && chars[i].is_ascii_digit() {
+                i += 1;
+            }
+            tokens += (i - start).div_ceil(3) as u64;
+        } else if c == '\n' || c == '\r' {
+            while i < chars.len() && (chars[i] == '\n' || chars[i] == '\r') {
+                i += 1;
+            }
+            tokens += 1;
+        } else if c.is_whitespace() {
+            while i < chars.len() && chars[i].is_whitespace() && chars[i] != '\n' && chars[i] != '\r'
+            {
+                i += 1;
+            }
+            let mut len = i - start;
+            // 单词或标点前的一个空格与其合并
+            if i < chars.len() && (chars[i].is_alphabetic() || is_symbol(chars[i])) {
+                len -= 1;
+            }
+            tokens += len.div_ceil(16) as u64;
+        } else {
+            i += 1;
+            while i < chars.len() && is_symbol(chars[i]) {
+                i += 1;
+            }
+            tokens += (i - start).div_ceil(2) as u64;
+        }
+    }
+    tokens
+}
 
-    let acc_token = if tokens < 100.0 {
-        tokens * 1.5
-    } else if tokens < 200.0 {
-        tokens * 1.3
-    } else if tokens < 300.0 {
-        tokens * 1.25
-    } else if tokens < 800.0 {
-        tokens * 1.2
-    } else {
-        tokens * 1.0
-    } as u64;
+/// 单词片段的 token 数
+fn word_tokens(len: usize) -> u64 {
+    len.div_ceil(8) as u64
+}
 
-    // println!("tokens: {}, acc_tokens: {}", tokens, acc_token);
-    acc_token
+/// 标点符号等不属于单词、数字、空白的西文字符
+fn is_symbol(c: char) -> bool {
+    !c.is_alphanumeric() && !c.is_whitespace() && !is_non_western_char(c)
 }
 
 /// 估算请求的输入 tokens
@@ -183,6 +227,24 @@ async fn call_remote_count_tokens(
     Ok(result.input_tokens as u64)
 }
 
+/// 每条消息的角色标记等固定开销
+const MESSAGE_OVERHEAD_TOKENS: u64 = 3;
+
+/// 请求携带工具时上游注入的工具使用系统提示
+const TOOL_SYSTEM_PROMPT_TOKENS: u64 = 346;
+
+/// 每个 tool_use / tool_result 块的结构开销
+const TOOL_BLOCK_OVERHEAD_TOKENS: u64 = 8;
+
+/// 图片缩放后的最大边长（像素），超出时按比例缩小
+const IMAGE_MAX_EDGE: u64 = 1568;
+
+/// 图片缩放后的最大像素数（约 1.15 百万像素）
+const IMAGE_MAX_PIXELS: u64 = 1_150_000;
+
+/// 每个图片 token 对应的像素数
+const PIXELS_PER_IMAGE_TOKEN: u64 = 750;
+
 /// 本地计算请求的输入 tokens
 fn count_all_tokens_local(
     system: Option<Vec<SystemMessage>>,
@@ -198,32 +260,158 @@ fn count_all_tokens_local(
         }
     }
 
-    // 用户消息
+    // 对话消息（文本、思考、工具调用/结果、图片）
     for msg in &messages {
-        if let serde_json::Value::String(s) = &msg.content {
-            total += count_tokens(s);
-        } else if let serde_json::Value::Array(arr) = &msg.content {
-            for item in arr {
-                if let Some(text) = item.get("text").and_then(|v| v.as_str()) {
-                    total += count_tokens(text);
-                }
-            }
-        }
+        total += MESSAGE_OVERHEAD_TOKENS + count_content_tokens(&msg.content);
     }
 
     // 工具定义
-    if let Some(ref tools) = tools {
+    if let Some(ref tools) = tools
+        && !tools.is_empty()
+    {
+        total += TOOL_SYSTEM_PROMPT_TOKENS;
         for tool in tools {
             total += count_tokens(&tool.name);
             total += count_tokens(&tool.description);
-            let input_schema_json = serde_json::to_string(&tool.input_schema).unwrap_or_default();
-            total += count_tokens(&input_schema_json);
+            if !tool.input_schema.is_empty() {
+                let input_schema_json =
+                    serde_json::to_string(&tool.input_schema).unwrap_or_default();
+                total += count_tokens(&input_schema_json);
+            }
         }
     }
 
     total.max(1)
 }
 
+/// 计算消息内容（字符串或内容块数组）的 tokens
+fn count_content_tokens(content: &serde_json::Value) -> u64 {
+    match content {
+        serde_json::Value::String(s) => count_tokens(s),
+        serde_json::Value::Array(blocks) => blocks.iter().map(count_block_tokens).sum(),
+        _ => 0,
+    }
+}
+
+/// 计算单个内容块的 tokens
+fn count_block_tokens(block: &serde_json::Value) -> u64 {
+    let str_field = |key: &str| block.get(key).and_then(|v| v.as_str()).unwrap_or_default();
+    match str_field("type") {
+        "thinking" => count_tokens(str_field("thinking")),
+        "tool_use" => {
+            let input = block
+                .get("input")
+                .map(|v| serde_json::to_string(v).unwrap_or_default())
+                .unwrap_or_default();
+            TOOL_BLOCK_OVERHEAD_TOKENS + count_tokens(str_field("name")) + count_tokens(&input)
+        }
+        "tool_result" => {
+            TOOL_BLOCK_OVERHEAD_TOKENS
+                + block
+                    .get("content")
+                    .map(count_content_tokens)
+                    .unwrap_or_default()
+        }
+        "image" => block.get("source").map(image_tokens).unwrap_or_default(),
+        "document" => block
+            .get("source")
+            .filter(|s| s.get("type").and_then(|v| v.as_str()) == Some("text"))
+            .and_then(|s| s.get("data"))
+            .and_then(|v| v.as_str())
+            .map(count_tokens)
+            .unwrap_or_default(),
+        _ => count_tokens(str_field("text")),
+    }
+}
+
+/// 估算图片的 tokens
+///
+/// 与上游一致：长边超过 1568 像素或总像素超过约 1.15M 时先等比缩小，
+/// 再按每 750 像素 1 token 计算。尺寸从图片头部解析，无法解析时按数据大小估算
+fn image_tokens(source: &serde_json::Value) -> u64 {
+    let data = source
+        .get("data")
+        .and_then(|v| v.as_str())
+        .and_then(|d| BASE64.decode(d).ok());
+    let Some(data) = data else {
+        // URL 图片等无法获取内容：按缩放后的最大尺寸估算
+        return IMAGE_MAX_PIXELS / PIXELS_PER_IMAGE_TOKEN;
+    };
+    let (width, height) = match image_dimensions(&data) {
+        Some((w, h)) => (u64::from(w), u64::from(h)),
+        None => {
+            // 压缩后的图片平均约每像素 0.5 字节
+            let pixels = (data.len() as u64 * 2).min(IMAGE_MAX_PIXELS);
+            return pixels.div_ceil(PIXELS_PER_IMAGE_TOKEN).max(1);
+        }
+    };
+
+    let mut scale = 1.0_f64;
+    let long_edge = width.max(height);
+    if long_edge > IMAGE_MAX_EDGE {
+        scale = IMAGE_MAX_EDGE as f64 / long_edge as f64;
+    }
+    let pixels = (width * height) as f64 * scale * scale;
+    if pixels > IMAGE_MAX_PIXELS as f64 {
+        scale *= (IMAGE_MAX_PIXELS as f64 / pixels).sqrt();
+    }
+    let pixels = (width * height) as f64 * scale * scale;
+    ((pixels / PIXELS_PER_IMAGE_TOKEN as f64).ceil() as u64).max(1)
+}
+
+/// 从 PNG / GIF / WebP / JPEG 头部解析图片宽高
+fn image_dimensions(data: &[u8]) -> Option<(u32, u32)> {
+    let be16 = |i: usize| {
+        data.get(i..i + 2)
+            .map(|b| u32::from(u16::from_be_bytes([b[0], b[1]])))
+    };
+    let le16 = |i: usize| {
+        data.get(i..i + 2)
+            .map(|b| u32::from(u16::from_le_bytes([b[0], b[1]])))
+    };
+    let le24 = |i: usize| Some(le16(i)? | u32::from(*data.get(i + 2)?) << 16);
+
+    if data.starts_with(b"\x89PNG\r\n\x1a\n") {
+        let width = u32::from_be_bytes(data.get(16..20)?.try_into().ok()?);
+        let height = u32::from_be_bytes(data.get(20..24)?.try_into().ok()?);
+        return Some((width, height));
+    }
+    if data.starts_with(b"GIF8") {
+        return Some((le16(6)?, le16(8)?));
+    }
+    if data.starts_with(b"RIFF") && data.get(8..12) == Some(b"WEBP") {
+        return match data.get(12..16)? {
+            b"VP8X" => Some((le24(24)? + 1, le24(27)? + 1)),
+            b"VP8L" => {
+                let bits = u32::from_le_bytes(data.get(21..25)?.try_into().ok()?);
+                Some(((bits & 0x3FFF) + 1, ((bits >> 14) & 0x3FFF) + 1))
+            }
+            b"VP8 " => Some((le16(26)? & 0x3FFF, le16(28)? & 0x3FFF)),
+            _ => None,
+        };
+    }
+    if data.starts_with(&[0xFF, 0xD8]) {
+        // 依次跳过各段，直到 SOF 段（含图片尺寸）
+        let mut i = 2;
+        while i + 3 < data.len() {
+            if data[i] != 0xFF {
+                i += 1;
+                continue;
+            }
+            let marker = data[i + 1];
+            match marker {
+                0xFF => i += 1,
+                0xD0..=0xD9 | 0x01 => i += 2,
+                0xC0..=0xCF if !matches!(marker, 0xC4 | 0xC8 | 0xCC) => {
+                    return Some((be16(i + 7)?, be16(i + 5)?));
+                }
+                _ => i += 2 + be16(i + 2)? as usize,
+            }
+        }
+    }
+    None
+}
+
 /// 估算输出 tokens
 pub(crate) fn estimate_output_tokens(content: &[serde_json::Value]) -> i32 {
     let mut total = 0;
@@ -243,3 +431,94 @@ pub(crate) fn estimate_output_tokens(content: &[serde_json::Value]) -> i32 {
 
     total.max(1)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_count_tokens_pre_tokenization() {
+        assert_eq!(count_tokens(""), 0);
+        assert_eq!(count_tokens("Hello world"), 2);
+        assert_eq!(count_tokens("getUserName"), 3);
+        assert_eq!(count_tokens("1234567"), 3);
+        assert_eq!(count_tokens("你好世界"), 4);
+        // 代码中的标点与缩进不再按 4 字符/token 低估
+        assert_eq!(count_tokens("fn f() {\n    x += 1;\n}"), 13);
+    }
+
+    fn png_base64(width: u32, height: u32) -> String {
+        let mut data = b"\x89PNG\r\n\x1a\n\0\0\0\x0dIHDR".to_vec();
+        data.extend_from_slice(&width.to_be_bytes());
+        data.extend_from_slice(&height.to_be_bytes());
+        BASE64.encode(data)
+    }
+
+    #[test]
+    fn test_image_dimensions() {
+        let png = BASE64.decode(png_base64(640, 480)).unwrap();
+        assert_eq!(image_dimensions(&png), Some((640, 480)));
+        assert_eq!(
+            image_dimensions(b"GIF89a\x20\x03\x58\x02"),
+            Some((800, 600))
+        );
+
+        let mut jpeg = vec![0xFF, 0xD8, 0xFF, 0xE0, 0x00, 0x04, 0x00, 0x00];
+        jpeg.extend_from_slice(&[0xFF, 0xC0, 0x00, 0x11, 0x08, 0x01, 0xE0, 0x02, 0x80]);
+        assert_eq!(image_dimensions(&jpeg), Some((640, 480)));
+        assert_eq!(image_dimensions(b"not an image"), None);
+    }
+
+    #[test]
+    fn test_image_tokens_scale_down_large_images() {
+        let source = |w, h| serde_json::json!({"type": "base64", "data": png_base64(w, h)});
+        // 1000x1000 = 1M 像素，未超限：1M / 750
+        assert_eq!(image_tokens(&source(1000, 1000)), 1334);
+        // 4000x3000 先缩到长边 1568，再缩到约 1.15M 像素
+        let tokens = image_tokens(&source(4000, 3000));
+        assert!((1530..=1534).contains(&tokens), "{}", tokens);
+    }
+
+    #[test]
+    fn test_count_all_tokens_local_includes_tools_and_blocks() {
+        let text_only = count_all_tokens_local(
+            None,
+            vec![Message {
+                role: "user".to_string(),
+                content: serde_json::json!("hello"),
+            }],
+            None,
+        );
+        let with_blocks = count_all_tokens_local(
+            None,
+            vec![
+                Message {
+                    role: "user".to_string(),
+                    content: serde_json::json!("hello"),
+                },
+                Message {
+                    role: "assistant".to_string(),
+                    content: serde_json::json!([
+                        {"type": "tool_use", "id": "t1", "name": "read_file", "input": {"path": "/a.rs"}}
+                    ]),
+                },
+                Message {
+                    role: "user".to_string(),
+                    content: serde_json::json!([
+                        {"type": "tool_result", "tool_use_id": "t1", "content": [{"type": "text", "text": "fn main() {}"}]}
+                    ]),
+                },
+            ],
+            Some(vec![Tool {
+                tool_type: None,
+                name: "read_file".to_string(),
+                description: "Read a file".to_string(),
+                input_schema: Default::default(),
+                max_uses: None,
+            }]),
+        );
+        assert!(
+            with_blocks > text_only + TOOL_SYSTEM_PROMPT_TOKENS + 2 * TOOL_BLOCK_OVERHEAD_TOKENS
+        );
+    }
+}